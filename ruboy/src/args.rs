@@ -1,6 +1,7 @@
 use std::path::PathBuf;
 
 use clap::{Parser, ValueEnum};
+use ruboy_lib::logging::Subsystem;
 
 #[derive(Parser, Debug, Clone)]
 #[command(author, about, version)]
@@ -14,6 +15,56 @@ pub(crate) struct CLIArgs {
     #[cfg(debug_assertions)]
     #[arg(value_enum, short, long, default_value_t = LogLevel::Info)]
     pub verbosity: LogLevel,
+
+    /// Run the built-in consistency checks and exit, instead of starting
+    /// the emulator
+    #[arg(long)]
+    pub self_test: bool,
+
+    /// The file the settings are read from and saved to. Defaults to
+    /// config.toml in the user's config directory
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Plays over the network, as if a link cable connects this game to
+    /// the one of another Ruboy. Waits for the other side to connect to
+    /// this address, e.g. 0.0.0.0:5738
+    #[arg(long, value_name = "ADDR", conflicts_with = "link_connect")]
+    pub link_listen: Option<String>,
+
+    /// Plays over the network with another Ruboy, that listens on this
+    /// address
+    #[arg(long, value_name = "ADDR")]
+    pub link_connect: Option<String>,
+
+    /// Sends the link cable traffic over UDP instead of TCP. Both sides
+    /// then bind the address given with --link-bind, and send to the one
+    /// given with --link-connect
+    #[arg(long, value_name = "ADDR", requires = "link_connect")]
+    pub link_bind: Option<String>,
+
+    /// Doesn't wait for the other side on every transfer, but assumes it
+    /// answers the same as last time. Hides network latency, but not every
+    /// game copes with it
+    #[arg(long)]
+    pub link_speculative: bool,
+
+    /// Limits the log messages of a part of the emulator, e.g. cpu=off to
+    /// see everything but the CPU. Can be given multiple times. The parts
+    /// are cpu, ppu, apu, memory, serial and emulator
+    #[arg(long, value_name = "PART=LEVEL", value_parser = parse_subsystem_level)]
+    pub log_filter: Vec<(Subsystem, log::LevelFilter)>,
+}
+
+fn parse_subsystem_level(arg: &str) -> Result<(Subsystem, log::LevelFilter), String> {
+    let (subsystem, level) = arg.split_once('=').ok_or("Expected PART=LEVEL")?;
+
+    let subsystem = subsystem.parse().map_err(|e| format!("{}", e))?;
+    let level = level
+        .parse()
+        .map_err(|_| format!("Unknown log level {}", level))?;
+
+    Ok((subsystem, level))
 }
 
 #[derive(Debug, Clone, ValueEnum)]
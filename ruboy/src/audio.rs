@@ -0,0 +1,202 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{
+    BuildStreamError, DefaultStreamConfigError, Device, FromSample, PlayStreamError, SampleFormat,
+    SizedSample, Stream, StreamConfig,
+};
+use thiserror::Error;
+
+/// How far ahead of the audio device the emulator tries to stay, in seconds
+const TARGET_LATENCY: f64 = 0.05;
+
+/// The most the emulator output rate is nudged away from the device rate.
+/// Small enough that the change in pitch is inaudible.
+const MAX_RATE_DELTA: f64 = 0.005;
+
+#[derive(Debug, Error)]
+pub enum AudioErr {
+    #[error("No audio output device available")]
+    NoDevice,
+
+    #[error("Could not query output config: {0}")]
+    Config(#[from] DefaultStreamConfigError),
+
+    #[error("Unsupported sample format: {0}")]
+    UnsupportedFormat(SampleFormat),
+
+    #[error("Could not open output stream: {0}")]
+    Build(#[from] BuildStreamError),
+
+    #[error("Could not start output stream: {0}")]
+    Play(#[from] PlayStreamError),
+}
+
+/// State shared between the emulator and the audio callback
+#[derive(Debug)]
+struct Shared {
+    buffer: VecDeque<[f32; 2]>,
+    volume: f32,
+}
+
+/// Plays the samples produced by the emulator on the default output device.
+///
+/// Samples are handed to the audio callback through a ring buffer, filled
+/// through an [AudioSink].
+pub struct AudioOutput {
+    _stream: Stream,
+    sink: AudioSink,
+}
+
+/// The emulator side of an [AudioOutput]. To keep the ring buffer from
+/// running dry or overflowing, the rate the emulator produces samples at is
+/// continuously adjusted around the device rate based on how full the
+/// buffer is (dynamic rate control).
+#[derive(Debug, Clone)]
+pub struct AudioSink {
+    shared: Arc<Mutex<Shared>>,
+    sample_rate: u32,
+    target: usize,
+}
+
+impl AudioOutput {
+    pub fn new(volume: f32) -> Result<Self, AudioErr> {
+        let host = cpal::default_host();
+        let device = host.default_output_device().ok_or(AudioErr::NoDevice)?;
+
+        let supported = device.default_output_config()?;
+        let format = supported.sample_format();
+        let config: StreamConfig = supported.into();
+
+        let sample_rate = config.sample_rate.0;
+        let target = (sample_rate as f64 * TARGET_LATENCY) as usize;
+
+        let shared = Arc::new(Mutex::new(Shared {
+            buffer: VecDeque::with_capacity(target * 4),
+            volume,
+        }));
+
+        let stream = match format {
+            SampleFormat::F32 => build_stream::<f32>(&device, &config, shared.clone()),
+            SampleFormat::I16 => build_stream::<i16>(&device, &config, shared.clone()),
+            SampleFormat::U16 => build_stream::<u16>(&device, &config, shared.clone()),
+            other => return Err(AudioErr::UnsupportedFormat(other)),
+        }?;
+
+        stream.play()?;
+
+        log::info!(
+            "Opened audio output at {}Hz, {} channel(s)",
+            sample_rate,
+            config.channels
+        );
+
+        Ok(Self {
+            _stream: stream,
+            sink: AudioSink {
+                shared,
+                sample_rate,
+                target,
+            },
+        })
+    }
+
+    pub fn sink(&self) -> AudioSink {
+        self.sink.clone()
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        if let Ok(mut shared) = self.sink.shared.lock() {
+            shared.volume = volume;
+        }
+    }
+}
+
+impl AudioSink {
+    pub const fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Queues samples for playback. If the emulator gets too far ahead, the
+    /// oldest samples are dropped.
+    pub fn push(&self, samples: impl Iterator<Item = [f32; 2]>) {
+        let Ok(mut shared) = self.shared.lock() else {
+            return;
+        };
+
+        shared.buffer.extend(samples);
+
+        let max = self.target * 4;
+
+        if shared.buffer.len() > max {
+            let excess = shared.buffer.len() - max;
+            shared.buffer.drain(..excess);
+        }
+    }
+
+    /// The amount of samples needed to fill the buffer up to the target
+    /// latency
+    pub fn missing(&self) -> usize {
+        match self.shared.lock() {
+            Ok(shared) => self.target.saturating_sub(shared.buffer.len()),
+            Err(_) => 0,
+        }
+    }
+
+    /// The rate the emulator should currently produce samples at. Slightly
+    /// above the device rate when the buffer is below the target latency,
+    /// and slightly below it when it is above.
+    pub fn adjusted_rate(&self) -> u32 {
+        let buffered = match self.shared.lock() {
+            Ok(shared) => shared.buffer.len(),
+            Err(_) => self.target,
+        };
+
+        let fill = (buffered as f64 / (2 * self.target) as f64).min(1.0);
+        let ratio = 1.0 + MAX_RATE_DELTA * (1.0 - 2.0 * fill);
+
+        (self.sample_rate as f64 * ratio).round() as u32
+    }
+}
+
+fn build_stream<T>(
+    device: &Device,
+    config: &StreamConfig,
+    shared: Arc<Mutex<Shared>>,
+) -> Result<Stream, BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    let channels = config.channels as usize;
+
+    device.build_output_stream(
+        config,
+        move |data: &mut [T], _| {
+            let Ok(mut shared) = shared.lock() else {
+                data.fill(T::EQUILIBRIUM);
+                return;
+            };
+
+            let volume = shared.volume;
+
+            for frame in data.chunks_mut(channels) {
+                // Play silence when the emulator can't keep up
+                let [left, right] = shared.buffer.pop_front().unwrap_or([0.0; 2]);
+
+                for (channel, out) in frame.iter_mut().enumerate() {
+                    let sample = match (channels, channel) {
+                        (1, _) => (left + right) / 2.0,
+                        (_, 0) => left,
+                        (_, 1) => right,
+                        _ => 0.0,
+                    };
+
+                    *out = T::from_sample(sample * volume);
+                }
+            }
+        },
+        |err| log::error!("Audio stream error: {}", err),
+        None,
+    )
+}
@@ -0,0 +1,92 @@
+//! Writing frames to PNG files, either as single screenshots or by dumping
+//! every Nth frame, e.g. to compare runs against each other
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use eframe::egui::ColorImage;
+use ruboy_lib::{Frame, FRAME_X, FRAME_Y};
+
+/// Writes 8-bit RGBA pixels, row by row, to a PNG file
+pub fn write_png(
+    path: impl AsRef<Path>,
+    width: usize,
+    height: usize,
+    rgba: &[u8],
+) -> Result<(), png::EncodingError> {
+    let file = BufWriter::new(File::create(path)?);
+
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.write_header()?.write_image_data(rgba)
+}
+
+/// Writes the image to a PNG file at the given path
+pub fn export_png(img: &ColorImage, path: impl AsRef<Path>) -> Result<(), png::EncodingError> {
+    let data: Vec<u8> = img.pixels.iter().flat_map(|pix| pix.to_array()).collect();
+
+    write_png(path, img.width(), img.height(), &data)
+}
+
+/// Where screenshots taken with the hotkey go
+pub fn screenshot_dir() -> PathBuf {
+    dirs::picture_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("ruboy")
+}
+
+/// The first `<prefix>-NNNN.png` in the directory that doesn't exist yet
+pub fn next_free_path(dir: &Path, prefix: &str) -> PathBuf {
+    (1..)
+        .map(|num| dir.join(format!("{}-{:04}.png", prefix, num)))
+        .find(|path| !path.exists())
+        .expect("Ran out of screenshot names")
+}
+
+/// Writes every Nth frame sent to the screen to a directory
+#[derive(Debug)]
+pub struct FrameDump {
+    dir: PathBuf,
+    every: u32,
+
+    /// Frames seen since the dump started, including skipped ones
+    seen: u64,
+}
+
+/// Shared between the UI, which starts and stops the dump, and the video
+/// output on the emulation thread, which feeds it
+pub type SharedFrameDump = Arc<Mutex<Option<FrameDump>>>;
+
+impl FrameDump {
+    /// Creates the directory if needed. An `every` of 0 is treated as 1.
+    pub fn new(dir: PathBuf, every: u32) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+
+        Ok(Self {
+            dir,
+            every: every.max(1),
+            seen: 0,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn frame(&mut self, frame: &Frame) -> Result<(), png::EncodingError> {
+        let num = self.seen;
+        self.seen += 1;
+
+        if !num.is_multiple_of(u64::from(self.every)) {
+            return Ok(());
+        }
+
+        let path = self.dir.join(format!("frame-{:08}.png", num));
+
+        write_png(path, FRAME_X, FRAME_Y, &frame.to_rgba_bytes())
+    }
+}
@@ -0,0 +1,154 @@
+use eframe::egui::{Color32, ColorImage};
+use ruboy_lib::color::DmgPalette;
+use ruboy_lib::debug::{DebugImage, TileMapArea};
+
+use crate::video::rgb_to_color32;
+use crate::AppRuboy;
+
+const BACKGROUND: Color32 = Color32::from_gray(32);
+
+/// A single view that can be placed in the composite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    Game,
+    Tilemap(TileMapArea),
+    Oam,
+}
+
+impl View {
+    pub const ALL: [View; 4] = [
+        View::Game,
+        View::Tilemap(TileMapArea::Low),
+        View::Tilemap(TileMapArea::High),
+        View::Oam,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            View::Game => "Game",
+            View::Tilemap(TileMapArea::Low) => "Tilemap (0x9800)",
+            View::Tilemap(TileMapArea::High) => "Tilemap (0x9C00)",
+            View::Oam => "OAM",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arrangement {
+    /// Views next to each other, aligned at the top
+    Horizontal,
+
+    /// Views below each other, aligned on the left
+    Vertical,
+}
+
+/// Places the game frame and debug views side by side into a single image
+#[derive(Debug, Clone)]
+pub struct Compositor {
+    pub enabled: bool,
+    pub views: Vec<View>,
+    pub arrangement: Arrangement,
+
+    /// Space between views, in pixels
+    pub spacing: usize,
+}
+
+impl Default for Compositor {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            views: vec![View::Game, View::Tilemap(TileMapArea::Low), View::Oam],
+            arrangement: Arrangement::Horizontal,
+            spacing: 8,
+        }
+    }
+}
+
+pub fn convert_debug_image(img: &DebugImage, palette: &DmgPalette) -> ColorImage {
+    ColorImage {
+        size: [img.width(), img.height()],
+        pixels: img
+            .get_raw()
+            .iter()
+            .map(|shade| rgb_to_color32(palette.color(*shade)))
+            .collect(),
+    }
+}
+
+fn blit(target: &mut ColorImage, src: &ColorImage, x_base: usize, y_base: usize) {
+    for (y, row) in src.pixels.chunks(src.width()).enumerate() {
+        let start = ((y_base + y) * target.width()) + x_base;
+
+        target.pixels[start..start + row.len()].copy_from_slice(row);
+    }
+}
+
+impl Compositor {
+    pub fn toggle_view(&mut self, view: View, shown: bool) {
+        let pos = self.views.iter().position(|v| *v == view);
+
+        match (pos, shown) {
+            (None, true) => self.views.push(view),
+            (Some(idx), false) => {
+                self.views.remove(idx);
+            }
+            _ => {}
+        }
+    }
+
+    fn render_view(view: View, game: &ColorImage, ruboy: &AppRuboy) -> ColorImage {
+        let rendered = match view {
+            View::Game => return game.clone(),
+            View::Tilemap(area) => ruboy.render_tilemap(area),
+            View::Oam => ruboy.render_oam(),
+        };
+
+        match rendered {
+            Ok(img) => convert_debug_image(&img, ruboy.palette()),
+            Err(e) => {
+                log::warn!("Could not render {} view: {}", view.name(), e);
+                ColorImage::new([0, 0], BACKGROUND)
+            }
+        }
+    }
+
+    /// Renders all selected views and places them according to the layout
+    pub fn compose(&self, game: &ColorImage, ruboy: &AppRuboy) -> ColorImage {
+        let images: Vec<ColorImage> = self
+            .views
+            .iter()
+            .map(|view| Self::render_view(*view, game, ruboy))
+            .collect();
+
+        let total_spacing = self.spacing * images.len().saturating_sub(1);
+
+        let size = match self.arrangement {
+            Arrangement::Horizontal => [
+                images.iter().map(|img| img.width()).sum::<usize>() + total_spacing,
+                images.iter().map(|img| img.height()).max().unwrap_or(0),
+            ],
+            Arrangement::Vertical => [
+                images.iter().map(|img| img.width()).max().unwrap_or(0),
+                images.iter().map(|img| img.height()).sum::<usize>() + total_spacing,
+            ],
+        };
+
+        let mut composite = ColorImage::new(size, BACKGROUND);
+        let mut offset = 0;
+
+        for img in &images {
+            match self.arrangement {
+                Arrangement::Horizontal => {
+                    blit(&mut composite, img, offset, 0);
+                    offset += img.width() + self.spacing;
+                }
+                Arrangement::Vertical => {
+                    blit(&mut composite, img, 0, offset);
+                    offset += img.height() + self.spacing;
+                }
+            }
+        }
+
+        composite
+    }
+}
@@ -0,0 +1,476 @@
+//! User settings that are kept between runs, stored as TOML in the user's
+//! config directory
+
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::string::FromUtf8Error;
+
+use eframe::egui::Vec2;
+use ruboy_lib::color::{DmgPalette, Rgb};
+use ruboy_lib::storage::{FsStorage, StorageProvider};
+use ruboy_lib::{Frameskip, FRAME_X, FRAME_Y};
+use thiserror::Error;
+use toml::{Table, Value};
+
+use crate::display::ScaleMode;
+use crate::filter::VideoFilter;
+use crate::input::config::InputConfig;
+
+/// How many ROMs are remembered in the "Open recent" menu
+pub const MAX_RECENT_ROMS: usize = 10;
+
+pub const SCALE_RANGE: RangeInclusive<u32> = 1..=8;
+
+/// The gamma corrections offered, see [Config::gamma]
+pub const GAMMA_RANGE: RangeInclusive<f32> = 0.5..=2.0;
+
+/// How much of the previous frame can show through, see [Config::ghosting]
+pub const GHOSTING_RANGE: RangeInclusive<f32> = 0.0..=0.9;
+
+/// The frameskip settings offered in the menu
+pub const FRAMESKIP_CHOICES: [Frameskip; 5] = [
+    Frameskip::Off,
+    Frameskip::Fixed(1),
+    Frameskip::Fixed(2),
+    Frameskip::Fixed(3),
+    Frameskip::Auto { max: 4 },
+];
+
+/// Room for the menu bar above the Game Boy screen
+const MENU_HEIGHT: f32 = 40.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// The most recently loaded ROM first
+    pub recent_roms: Vec<PathBuf>,
+
+    /// The palette before gamma correction
+    pub palette: DmgPalette,
+
+    /// The gamma correction applied to the palette, in [GAMMA_RANGE]
+    pub gamma: f32,
+
+    /// The size of the window, in multiples of the Game Boy screen
+    pub window_scale: u32,
+
+    /// How the Game Boy screen is fitted into the window
+    pub scale_mode: ScaleMode,
+
+    /// Keep the aspect ratio of the Game Boy screen when stretching
+    pub keep_aspect: bool,
+
+    /// Applied to the Game Boy screen before it is scaled to the window
+    pub filter: VideoFilter,
+
+    /// How much of the previous frame shows through each frame, like the
+    /// slow pixels of a DMG LCD. 0 turns it off.
+    pub ghosting: f32,
+
+    /// Which frames are not drawn, to keep up on slow machines
+    pub frameskip: Frameskip,
+
+    pub volume: f32,
+
+    /// If true, emulation is clocked by the audio device instead of the
+    /// system clock
+    pub audio_pacing: bool,
+
+    pub input: InputConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            recent_roms: Vec::new(),
+            palette: DmgPalette::default(),
+            gamma: 1.0,
+            window_scale: 3,
+            scale_mode: ScaleMode::Stretch,
+            keep_aspect: true,
+            filter: VideoFilter::None,
+            ghosting: 0.0,
+            frameskip: Frameskip::Off,
+            volume: 1.0,
+            audio_pacing: false,
+            input: InputConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigErr {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Config file is not valid UTF-8: {0}")]
+    Encoding(#[from] FromUtf8Error),
+
+    #[error("Invalid config file: {0}")]
+    Parse(#[from] toml::de::Error),
+
+    #[error("Could not serialize config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+}
+
+impl Config {
+    /// Where the config is stored if no other path is given
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("ruboy").join("config.toml"))
+    }
+
+    /// Reads the config from a file, see [Config::load_from]
+    pub fn load(path: &Path) -> Result<Self, ConfigErr> {
+        let (storage, key) = config_location(path);
+
+        Self::load_from(&storage, &key)
+    }
+
+    /// Reads the config stored under `key`. If nothing is stored there, the
+    /// default config is returned. Settings that are missing or can't be
+    /// parsed keep their default.
+    pub fn load_from<S: StorageProvider<Err = io::Error>>(
+        storage: &S,
+        key: &str,
+    ) -> Result<Self, ConfigErr> {
+        let Some(data) = storage.load(key)? else {
+            return Ok(Self::default());
+        };
+
+        let table: Table = String::from_utf8(data)?.parse()?;
+        let mut config = Self {
+            input: InputConfig::read(&table),
+            ..Self::default()
+        };
+
+        if let Some(roms) = table.get("recent_roms").and_then(Value::as_array) {
+            config.recent_roms = roms
+                .iter()
+                .filter_map(Value::as_str)
+                .map(PathBuf::from)
+                .take(MAX_RECENT_ROMS)
+                .collect();
+        }
+
+        if let Some(scale) = table.get("window_scale").and_then(Value::as_integer) {
+            config.window_scale = u32::try_from(scale)
+                .unwrap_or(0)
+                .clamp(*SCALE_RANGE.start(), *SCALE_RANGE.end());
+        }
+
+        if let Some(mode) = table.get("scale_mode").and_then(Value::as_str) {
+            match ScaleMode::from_name(mode) {
+                Some(mode) => config.scale_mode = mode,
+                None => log::warn!("Ignoring invalid scale mode in config: {}", mode),
+            }
+        }
+
+        if let Some(keep_aspect) = table.get("keep_aspect").and_then(Value::as_bool) {
+            config.keep_aspect = keep_aspect;
+        }
+
+        if let Some(filter) = table.get("filter").and_then(Value::as_str) {
+            match VideoFilter::from_name(filter) {
+                Some(filter) => config.filter = filter,
+                None => log::warn!("Ignoring invalid filter in config: {}", filter),
+            }
+        }
+
+        if let Some(ghosting) = read_float(&table, "ghosting") {
+            config.ghosting = ghosting.clamp(*GHOSTING_RANGE.start(), *GHOSTING_RANGE.end());
+        }
+
+        if let Some(frameskip) = table.get("frameskip").and_then(Value::as_str) {
+            match FRAMESKIP_CHOICES
+                .into_iter()
+                .find(|choice| frameskip_name(*choice) == frameskip)
+            {
+                Some(choice) => config.frameskip = choice,
+                None => log::warn!("Ignoring invalid frameskip in config: {}", frameskip),
+            }
+        }
+
+        if let Some(volume) = read_float(&table, "volume") {
+            config.volume = volume.clamp(0.0, 1.0);
+        }
+
+        if let Some(pacing) = table.get("audio_pacing").and_then(Value::as_bool) {
+            config.audio_pacing = pacing;
+        }
+
+        if let Some(Value::Table(palette)) = table.get("palette") {
+            if let Some(gamma) = read_float(palette, "gamma") {
+                config.gamma = gamma.clamp(*GAMMA_RANGE.start(), *GAMMA_RANGE.end());
+            }
+
+            if let Some(shades) = palette.get("shades").and_then(Value::as_array) {
+                match shades
+                    .iter()
+                    .map(|shade| shade.as_str().and_then(parse_rgb))
+                    .collect::<Option<Vec<_>>>()
+                    .and_then(|shades| <[Rgb; 4]>::try_from(shades).ok())
+                {
+                    Some(shades) => config.palette = DmgPalette::new(shades),
+                    None => log::warn!("Ignoring invalid palette in config: {}", palette),
+                }
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Writes the config to a file, see [Config::save_to]
+    pub fn save(&self, path: &Path) -> Result<(), ConfigErr> {
+        let (mut storage, key) = config_location(path);
+
+        self.save_to(&mut storage, &key)
+    }
+
+    /// Stores the config under `key`, to be read back with [Config::load_from]
+    pub fn save_to<S: StorageProvider<Err = io::Error>>(
+        &self,
+        storage: &mut S,
+        key: &str,
+    ) -> Result<(), ConfigErr> {
+        let mut table = Table::new();
+
+        table.insert(
+            "recent_roms".to_string(),
+            Value::Array(
+                self.recent_roms
+                    .iter()
+                    .filter_map(|path| match path.to_str() {
+                        Some(path) => Some(Value::String(path.to_string())),
+                        None => {
+                            // TOML strings can't hold it, and a lossy copy would not open
+                            log::warn!("Not remembering ROM with a non UTF-8 path: {:?}", path);
+                            None
+                        }
+                    })
+                    .collect(),
+            ),
+        );
+        table.insert(
+            "window_scale".to_string(),
+            Value::Integer(self.window_scale.into()),
+        );
+        table.insert(
+            "scale_mode".to_string(),
+            Value::String(self.scale_mode.name().to_string()),
+        );
+        table.insert("keep_aspect".to_string(), Value::Boolean(self.keep_aspect));
+        table.insert(
+            "filter".to_string(),
+            Value::String(self.filter.name().to_string()),
+        );
+        table.insert("ghosting".to_string(), Value::Float(self.ghosting.into()));
+        table.insert(
+            "frameskip".to_string(),
+            Value::String(frameskip_name(self.frameskip)),
+        );
+        table.insert("volume".to_string(), Value::Float(self.volume.into()));
+        table.insert(
+            "audio_pacing".to_string(),
+            Value::Boolean(self.audio_pacing),
+        );
+
+        let mut palette = Table::new();
+        palette.insert(
+            "shades".to_string(),
+            Value::Array(
+                self.palette
+                    .shades
+                    .iter()
+                    .map(|rgb| Value::String(format!("#{:02x}{:02x}{:02x}", rgb.r, rgb.g, rgb.b)))
+                    .collect(),
+            ),
+        );
+        palette.insert("gamma".to_string(), Value::Float(self.gamma.into()));
+        table.insert("palette".to_string(), Value::Table(palette));
+
+        self.input.write(&mut table);
+
+        storage.store(key, toml::to_string(&table)?.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Moves the ROM to the front of the recently loaded ROMs
+    pub fn add_recent_rom(&mut self, rom: PathBuf) {
+        self.recent_roms.retain(|recent| *recent != rom);
+        self.recent_roms.insert(0, rom);
+        self.recent_roms.truncate(MAX_RECENT_ROMS);
+    }
+
+    /// The palette sent to the emulator, with gamma correction applied
+    pub fn corrected_palette(&self) -> DmgPalette {
+        self.palette.gamma_corrected(self.gamma)
+    }
+
+    /// The inner size of the window for the configured scale
+    pub fn window_size(&self) -> Vec2 {
+        let scale = self.window_scale as f32;
+
+        Vec2::new(FRAME_X as f32 * scale, FRAME_Y as f32 * scale + MENU_HEIGHT)
+    }
+}
+
+/// The config file is stored by its name, in the directory it is in
+fn config_location(path: &Path) -> (FsStorage, String) {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "config.toml".to_string());
+
+    (FsStorage::new(dir), name)
+}
+
+/// Reads a float, also accepting integers as TOML doesn't parse `1` as a
+/// float
+fn read_float(table: &Table, key: &str) -> Option<f32> {
+    match table.get(key)? {
+        Value::Float(val) => Some(*val as f32),
+        Value::Integer(val) => Some(*val as f32),
+        _ => None,
+    }
+}
+
+/// The name a frameskip setting is stored under
+fn frameskip_name(frameskip: Frameskip) -> String {
+    match frameskip {
+        Frameskip::Off => "off".to_string(),
+        Frameskip::Fixed(skip) => skip.to_string(),
+        Frameskip::Auto { .. } => "auto".to_string(),
+    }
+}
+
+/// Parses a `#rrggbb` color
+fn parse_rgb(text: &str) -> Option<Rgb> {
+    let hex = text.strip_prefix('#')?;
+
+    if hex.len() != 6 {
+        return None;
+    }
+
+    let channel = |idx: usize| u8::from_str_radix(hex.get(idx..idx + 2)?, 16).ok();
+
+    Some(Rgb::new(channel(0)?, channel(2)?, channel(4)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_storage(name: &str) -> FsStorage {
+        FsStorage::new(std::env::temp_dir().join(format!(
+            "ruboy-config-{}-{}",
+            name,
+            std::process::id()
+        )))
+    }
+
+    #[test]
+    fn round_trips() {
+        let mut storage = temp_storage("round-trip");
+
+        let config = Config {
+            recent_roms: vec![PathBuf::from("/roms/tetris.gb"), PathBuf::from("zelda.gb")],
+            palette: DmgPalette::new([
+                Rgb::new(0xFF, 0xEE, 0xDD),
+                Rgb::new(0xAA, 0x99, 0x88),
+                Rgb::new(0x55, 0x44, 0x33),
+                Rgb::new(0x00, 0x11, 0x22),
+            ]),
+            gamma: 1.5,
+            window_scale: 5,
+            scale_mode: ScaleMode::Integer,
+            keep_aspect: false,
+            filter: VideoFilter::Scanlines,
+            ghosting: 0.5,
+            frameskip: Frameskip::Fixed(2),
+            volume: 0.25,
+            audio_pacing: true,
+            input: InputConfig::default(),
+        };
+
+        config.save_to(&mut storage, "config.toml").unwrap();
+
+        assert_eq!(config, Config::load_from(&storage, "config.toml").unwrap());
+        assert_eq!(
+            Config::default(),
+            Config::load_from(&storage, "missing.toml").unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_values_keep_defaults_or_are_clamped() {
+        let mut storage = temp_storage("invalid");
+
+        storage
+            .store(
+                "config.toml",
+                br##"
+                recent_roms = ["game.gb", 3]
+                window_scale = 100
+                scale_mode = "zoom"
+                filter = "blur"
+                ghosting = 5.0
+                frameskip = "sometimes"
+                volume = -1
+                audio_pacing = "yes"
+
+                [palette]
+                gamma = 50
+                shades = ["#ffffff", "#000000"]
+                "##,
+            )
+            .unwrap();
+
+        let config = Config::load_from(&storage, "config.toml").unwrap();
+        let default = Config::default();
+
+        assert_eq!(vec![PathBuf::from("game.gb")], config.recent_roms);
+        assert_eq!(*SCALE_RANGE.end(), config.window_scale);
+        assert_eq!(default.scale_mode, config.scale_mode);
+        assert_eq!(default.filter, config.filter);
+        assert_eq!(*GHOSTING_RANGE.end(), config.ghosting);
+        assert_eq!(default.frameskip, config.frameskip);
+        assert_eq!(0.0, config.volume);
+        assert_eq!(default.audio_pacing, config.audio_pacing);
+        assert_eq!(*GAMMA_RANGE.end(), config.gamma);
+        assert_eq!(default.palette, config.palette);
+
+        storage.store("broken.toml", b"window_scale = ").unwrap();
+        assert!(matches!(
+            Config::load_from(&storage, "broken.toml"),
+            Err(ConfigErr::Parse(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn non_utf8_rom_paths_are_not_stored() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut storage = temp_storage("non-utf8");
+
+        let config = Config {
+            recent_roms: vec![
+                PathBuf::from(OsStr::from_bytes(b"/roms/\xFF.gb")),
+                PathBuf::from("/roms/tetris.gb"),
+            ],
+            ..Config::default()
+        };
+
+        config.save_to(&mut storage, "config.toml").unwrap();
+
+        assert_eq!(
+            vec![PathBuf::from("/roms/tetris.gb")],
+            Config::load_from(&storage, "config.toml")
+                .unwrap()
+                .recent_roms
+        );
+    }
+}
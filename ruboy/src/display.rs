@@ -0,0 +1,67 @@
+//! How the Game Boy screen is fitted into the window
+
+use std::fmt::Display;
+
+use eframe::egui::{Context, Rect, Vec2, ViewportCommand};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleMode {
+    /// Only scale by whole multiples, so every Game Boy pixel is the same
+    /// size. The rest of the window is letterboxed.
+    Integer,
+
+    /// Fill as much of the window as possible
+    Stretch,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 2] = [ScaleMode::Integer, ScaleMode::Stretch];
+
+    /// The name used in the config file
+    pub const fn name(self) -> &'static str {
+        match self {
+            ScaleMode::Integer => "integer",
+            ScaleMode::Stretch => "stretch",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+}
+
+impl Display for ScaleMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ScaleMode::Integer => "Integer scaling",
+            ScaleMode::Stretch => "Stretch to fit",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Where an image of `size` is drawn inside `available`. It is always
+/// centered.
+pub fn frame_rect(mode: ScaleMode, keep_aspect: bool, size: Vec2, available: Rect) -> Rect {
+    let scale = available.size() / size;
+    let fit = scale.min_elem();
+
+    let drawn = match mode {
+        ScaleMode::Integer if fit >= 1.0 => size * fit.floor(),
+        // Shrink instead if the window is smaller than the image
+        ScaleMode::Integer => size * fit,
+        ScaleMode::Stretch if keep_aspect => size * fit,
+        ScaleMode::Stretch => available.size(),
+    };
+
+    Rect::from_center_size(available.center(), drawn)
+}
+
+pub fn is_fullscreen(ctx: &Context) -> bool {
+    ctx.input(|input| input.viewport().fullscreen.unwrap_or(false))
+}
+
+pub fn toggle_fullscreen(ctx: &Context) {
+    ctx.send_viewport_cmd(ViewportCommand::Fullscreen(!is_fullscreen(ctx)));
+}
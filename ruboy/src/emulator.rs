@@ -0,0 +1,1073 @@
+//! Runs the emulator on a worker thread, so slow frames don't stutter the UI
+//! and a blocked UI (e.g. while dragging the window) doesn't pause the game
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, TryIter};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ruboy_lib::color::DmgPalette;
+use ruboy_lib::emucore::{self, CoreAllocator, CoreRom, CoreStartErr, EmulatorCore};
+use ruboy_lib::link;
+use ruboy_lib::model::BootRom;
+use ruboy_lib::netlink::NetLink;
+use ruboy_lib::printer::{PrintHandler, Printer, Printout, PRINTOUT_X};
+use ruboy_lib::rewind::Rewinder;
+use ruboy_lib::rom::capabilities::Capability;
+use ruboy_lib::rom::meta::{CartridgeMapper, RomMeta};
+use ruboy_lib::storage::FsStorage;
+#[cfg(feature = "audio")]
+use ruboy_lib::AudioChannel;
+use ruboy_lib::{ErrorPolicy, Frameskip, RumbleHandler, CLOCK_SPEED_HZ_F64, DESIRED_FRAMERATE};
+
+#[cfg(feature = "audio")]
+use crate::audio::AudioSink;
+use crate::capture::{self, FrameDump, SharedFrameDump};
+use crate::input::SharedInputs;
+use crate::recording::{Recorder, RecordingHook, SharedRecorder};
+#[cfg(feature = "scripting")]
+use crate::scripting::{ScriptRunner, SharedScriptPath};
+use crate::video::{FrameHandoff, VideoOutput};
+use crate::AppRuboy;
+
+/// The most emulated time a single step may catch up on. Anything beyond
+/// that (e.g. after the host was suspended) is skipped instead of being
+/// run as fast as possible.
+const MAX_STEP: f64 = 0.1;
+
+/// The range of speeds selectable in the frontend, as a multiple of real time
+pub const SPEED_RANGE: RangeInclusive<f64> = 0.25..=4.0;
+
+/// Frames between two rewind snapshots
+const REWIND_INTERVAL: u64 = 2;
+
+/// Rewind snapshots kept, enough for about 20 seconds
+const REWIND_CAPACITY: usize = 600;
+
+/// Where the emulator sends its audio, and which channels are muted
+#[cfg(feature = "audio")]
+#[derive(Debug, Clone, Default)]
+pub struct AudioSettings {
+    pub sink: Option<AudioSink>,
+    pub muted: [bool; AudioChannel::ALL.len()],
+
+    /// If true, emulation is clocked by the audio device: every step runs
+    /// until the samples it is about to play are produced, instead of for
+    /// the time that passed
+    pub paced: bool,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    /// Replaces the running game with the given ROM
+    LoadRom(PathBuf),
+
+    SetPaused(bool),
+
+    /// Runs emulation at a multiple of real time
+    SetSpeed(f64),
+
+    /// Runs emulation as fast as possible, ignoring the speed. Audio is
+    /// dropped while enabled.
+    SetTurbo(bool),
+
+    /// Steps back through the recent history instead of running, one
+    /// snapshot per frame
+    SetRewinding(bool),
+
+    /// Changes the colors the game is shown in
+    SetPalette(DmgPalette),
+
+    /// Skips drawing frames, to keep up on slow machines
+    SetFrameskip(Frameskip),
+
+    /// Connects or disconnects the Game Boy Printer, which saves everything
+    /// it prints as PNGs in the screenshot directory
+    SetPrinter(bool),
+
+    /// Opens a second game, connected to the running one by a link cable,
+    /// or closes it if None. Both run in lockstep, and the second game
+    /// draws to its own window. The printer is unplugged while linked.
+    SetLinkPartner(Option<PathBuf>),
+
+    /// Plugs the link cable into a connection to another Ruboy over the
+    /// network, or unplugs it if None. Takes the place of the printer, but
+    /// not of a second game opened with [Command::SetLinkPartner].
+    SetNetLink(Option<NetLink>),
+
+    /// Runs the script at the path, replacing the running one. The script
+    /// restarts along with the game.
+    #[cfg(feature = "scripting")]
+    LoadScript(PathBuf),
+
+    #[cfg(feature = "scripting")]
+    StopScript,
+
+    /// Restarts the current ROM from scratch
+    Reset,
+
+    /// Writes the save data of the current ROM to disk
+    Save,
+
+    #[cfg(feature = "audio")]
+    Audio(AudioSettings),
+
+    Quit,
+}
+
+/// Sent by the emulation thread to tell the UI about changes it didn't ask
+/// for, or can't see the outcome of otherwise
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// A ROM was loaded and started
+    RomLoaded {
+        path: PathBuf,
+        title: String,
+        capabilities: Vec<Capability>,
+    },
+
+    /// A ROM could not be loaded. The previous game, if any, keeps running.
+    RomLoadFailed { path: PathBuf, error: String },
+
+    /// A ROM could not be loaded because its mapper isn't emulated yet.
+    /// The previous game, if any, keeps running.
+    UnsupportedCartridge {
+        path: PathBuf,
+        mapper: CartridgeMapper,
+
+        /// The header, to show the user what else is in the cartridge. None
+        /// if it could not be read again.
+        meta: Option<RomMeta>,
+    },
+
+    /// Something the game printed could not be saved
+    PrintFailed { error: String },
+
+    /// The network link could not be set up, or failed and was unplugged
+    NetLinkFailed { error: String },
+
+    /// Emulation failed and was paused. The error says where and why.
+    EmulationFailed { error: String },
+
+    /// A script could not be loaded, or failed while running, and was
+    /// stopped
+    #[cfg(feature = "scripting")]
+    ScriptFailed { path: PathBuf, error: String },
+}
+
+/// Handle to the emulation thread.
+///
+/// The emulator itself is shared behind a mutex, so that the debug windows
+/// can still inspect and change it. The thread only holds the lock while
+/// stepping, and stepping waits while the UI holds it.
+pub struct Emulator {
+    ruboy: Arc<Mutex<Option<AppRuboy>>>,
+    paused: Arc<AtomicBool>,
+    linked: Arc<AtomicBool>,
+    #[cfg(feature = "gamepad")]
+    rumble: Arc<AtomicBool>,
+    frame_dump: SharedFrameDump,
+    recorder: SharedRecorder,
+    #[cfg(feature = "scripting")]
+    script: SharedScriptPath,
+    speed: f64,
+    turbo: bool,
+    rewinding: bool,
+    printer: bool,
+    commands: Sender<Command>,
+    events: Receiver<Event>,
+    event_sender: Sender<Event>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Emulator {
+    /// Starts the emulation thread. The game drawn to `handoff` and played
+    /// with `inputs` is the main one, the other pair is for the second
+    /// game on the link cable.
+    pub fn spawn(
+        handoff: Arc<FrameHandoff>,
+        inputs: SharedInputs,
+        partner_handoff: Arc<FrameHandoff>,
+        partner_inputs: SharedInputs,
+    ) -> Self {
+        let ruboy = Arc::new(Mutex::new(None));
+        let paused = Arc::new(AtomicBool::new(false));
+        let linked = Arc::new(AtomicBool::new(false));
+        let rumble = Arc::new(AtomicBool::new(false));
+        let frame_dump = SharedFrameDump::default();
+        let recorder = SharedRecorder::default();
+        #[cfg(feature = "scripting")]
+        let script = SharedScriptPath::default();
+        let (commands, receiver) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+
+        let worker = Worker {
+            ruboy: ruboy.clone(),
+            paused: paused.clone(),
+            linked: linked.clone(),
+            rumble: rumble.clone(),
+            frame_dump: frame_dump.clone(),
+            recorder: recorder.clone(),
+            commands: receiver,
+            #[cfg(feature = "scripting")]
+            scripts: ScriptRunner::new(script.clone(), event_sender.clone()),
+            events: event_sender.clone(),
+            handoff,
+            inputs,
+            rom: None,
+            partner: None,
+            partner_handoff,
+            partner_inputs,
+            link_cycles: 0.0,
+            net_link: None,
+            speed: 1.0,
+            turbo: false,
+            rewinding: false,
+            rewinder: Rewinder::new(REWIND_INTERVAL, REWIND_CAPACITY),
+            palette: DmgPalette::default(),
+            frameskip: Frameskip::Off,
+            printer: false,
+            #[cfg(feature = "audio")]
+            audio: AudioSettings::default(),
+        };
+
+        let handle = thread::Builder::new()
+            .name("Ruboy emulation".to_string())
+            .spawn(move || worker.run())
+            .expect("Could not start emulation thread");
+
+        Self {
+            ruboy,
+            paused,
+            linked,
+            #[cfg(feature = "gamepad")]
+            rumble,
+            frame_dump,
+            recorder,
+            #[cfg(feature = "scripting")]
+            script,
+            speed: 1.0,
+            turbo: false,
+            rewinding: false,
+            printer: false,
+            commands,
+            events,
+            event_sender,
+            worker: Some(handle),
+        }
+    }
+
+    pub fn send(&self, command: Command) {
+        if self.commands.send(command).is_err() {
+            log::error!("Emulation thread is no longer running");
+        }
+    }
+
+    /// The events sent since the last call
+    pub fn events(&self) -> TryIter<'_, Event> {
+        self.events.try_iter()
+    }
+
+    /// Locks the emulator for inspection. Emulation waits until the guard
+    /// is dropped, so don't hold on to it.
+    pub fn lock(&self) -> MutexGuard<'_, Option<AppRuboy>> {
+        self.ruboy.lock().unwrap()
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    /// Whether the rumble motor of the cartridge is running
+    #[cfg(feature = "gamepad")]
+    pub fn rumbling(&self) -> bool {
+        !self.paused() && self.rumble.load(Ordering::Relaxed)
+    }
+
+    pub fn set_speed(&mut self, speed: f64) {
+        let speed = speed.clamp(*SPEED_RANGE.start(), *SPEED_RANGE.end());
+
+        if speed != self.speed {
+            self.speed = speed;
+            self.send(Command::SetSpeed(speed));
+        }
+    }
+
+    pub const fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn set_turbo(&mut self, turbo: bool) {
+        if turbo != self.turbo {
+            self.turbo = turbo;
+            self.send(Command::SetTurbo(turbo));
+        }
+    }
+
+    pub const fn turbo(&self) -> bool {
+        self.turbo
+    }
+
+    pub fn set_printer(&mut self, connected: bool) {
+        if connected != self.printer {
+            self.printer = connected;
+            self.send(Command::SetPrinter(connected));
+        }
+    }
+
+    pub const fn printer(&self) -> bool {
+        self.printer
+    }
+
+    /// Connects the link cable to another Ruboy over the network in the
+    /// background, since that may wait for the other side. The game is
+    /// plugged in once the connection is up.
+    pub fn connect_net_link(&self, connect: impl FnOnce() -> io::Result<NetLink> + Send + 'static) {
+        let commands = self.commands.clone();
+        let events = self.event_sender.clone();
+
+        let spawned = thread::Builder::new()
+            .name("Ruboy network link".to_string())
+            .spawn(move || match connect() {
+                Ok(link) => {
+                    log::info!("Network link connected");
+                    let _ = commands.send(Command::SetNetLink(Some(link)));
+                }
+                Err(e) => {
+                    log::error!("Could not set up network link: {}", e);
+                    let _ = events.send(Event::NetLinkFailed {
+                        error: e.to_string(),
+                    });
+                }
+            });
+
+        if let Err(e) = spawned {
+            log::error!("Could not start network link thread: {}", e);
+        }
+    }
+
+    /// Whether a second game is running on the other end of the link cable
+    pub fn linked(&self) -> bool {
+        self.linked.load(Ordering::Relaxed)
+    }
+
+    /// The script that is running, if any
+    #[cfg(feature = "scripting")]
+    pub fn script(&self) -> Option<PathBuf> {
+        self.script.lock().unwrap().clone()
+    }
+
+    pub fn set_rewinding(&mut self, rewinding: bool) {
+        if rewinding != self.rewinding {
+            self.rewinding = rewinding;
+            self.send(Command::SetRewinding(rewinding));
+        }
+    }
+
+    pub const fn rewinding(&self) -> bool {
+        self.rewinding
+    }
+
+    /// Starts writing frames to disk, or stops if `dump` is None
+    pub fn set_frame_dump(&self, dump: Option<FrameDump>) {
+        *self.frame_dump.lock().unwrap() = dump;
+    }
+
+    /// Starts recording every frame drawn from now on. Replaces a recording
+    /// already running without finishing it, so stop that one first.
+    pub fn start_recording(&self, recorder: Recorder) {
+        *self.recorder.lock().unwrap() = Some(recorder);
+    }
+
+    /// Stops recording, handing back the recorder so it can be finished
+    pub fn stop_recording(&self) -> Option<Recorder> {
+        self.recorder.lock().unwrap().take()
+    }
+
+    /// Where the recording goes and how many frames it has, if recording
+    pub fn recording(&self) -> Option<(PathBuf, u64)> {
+        self.recorder
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|rec| (rec.path().to_path_buf(), rec.frames()))
+    }
+
+    /// Where frames are dumped to, if they are
+    pub fn frame_dump_dir(&self) -> Option<PathBuf> {
+        self.frame_dump
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|dump| dump.dir().to_path_buf())
+    }
+}
+
+impl Drop for Emulator {
+    fn drop(&mut self) {
+        self.send(Command::Quit);
+
+        if let Some(worker) = self.worker.take() {
+            if worker.join().is_err() {
+                log::error!("Emulation thread panicked");
+            }
+        }
+
+        if let Some(Err(e)) = self.stop_recording().map(Recorder::finish) {
+            log::error!("Could not finish recording: {}", e);
+        }
+    }
+}
+
+struct Worker {
+    ruboy: Arc<Mutex<Option<AppRuboy>>>,
+    paused: Arc<AtomicBool>,
+    linked: Arc<AtomicBool>,
+    rumble: Arc<AtomicBool>,
+    frame_dump: SharedFrameDump,
+    recorder: SharedRecorder,
+    commands: Receiver<Command>,
+    events: Sender<Event>,
+    handoff: Arc<FrameHandoff>,
+    inputs: SharedInputs,
+    rom: Option<PathBuf>,
+
+    /// The second game on the link cable, and its ROM
+    partner: Option<(AppRuboy, PathBuf)>,
+    partner_handoff: Arc<FrameHandoff>,
+    partner_inputs: SharedInputs,
+
+    /// Fractional cycles carried over between linked steps
+    link_cycles: f64,
+
+    /// The other end of the link cable, on another machine
+    net_link: Option<NetLink>,
+
+    speed: f64,
+    turbo: bool,
+    rewinding: bool,
+    rewinder: Rewinder,
+    palette: DmgPalette,
+    frameskip: Frameskip,
+    printer: bool,
+
+    #[cfg(feature = "scripting")]
+    scripts: ScriptRunner,
+
+    #[cfg(feature = "audio")]
+    audio: AudioSettings,
+}
+
+impl Worker {
+    fn run(mut self) {
+        let frame_time = Duration::from_secs_f64(1.0 / DESIRED_FRAMERATE);
+
+        let mut prev_step = Instant::now();
+        let mut next_step = prev_step;
+
+        loop {
+            // Handle commands while waiting for the next frame
+            let timeout = next_step.saturating_duration_since(Instant::now());
+
+            match self.commands.recv_timeout(timeout) {
+                Ok(Command::Quit) | Err(RecvTimeoutError::Disconnected) => {
+                    self.save();
+                    return;
+                }
+                Ok(command) => {
+                    self.handle(command);
+                    continue;
+                }
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            let now = Instant::now();
+            let dt = now.duration_since(prev_step).as_secs_f64();
+
+            prev_step = now;
+            next_step = now + frame_time;
+
+            if self.paused.load(Ordering::Relaxed) {
+                continue;
+            }
+
+            if self.rewinding {
+                self.rewind();
+            } else if self.turbo {
+                self.run_turbo(frame_time, next_step);
+            } else {
+                self.step(dt.min(MAX_STEP));
+            }
+        }
+    }
+
+    fn handle(&mut self, command: Command) {
+        match command {
+            Command::LoadRom(path) => self.load(path),
+            Command::SetPaused(paused) => self.set_paused(paused),
+            Command::SetSpeed(speed) => {
+                self.speed = speed;
+
+                if let Some(ruboy) = self.ruboy.lock().unwrap().as_mut() {
+                    ruboy.set_speed(speed);
+                }
+            }
+            Command::SetTurbo(turbo) => self.turbo = turbo,
+            Command::SetRewinding(rewinding) => self.rewinding = rewinding,
+            Command::SetPalette(palette) => {
+                self.palette = palette;
+
+                if let Some((partner, _)) = &mut self.partner {
+                    partner.set_palette(palette);
+                }
+
+                if let Some(ruboy) = self.ruboy.lock().unwrap().as_mut() {
+                    ruboy.set_palette(palette);
+
+                    // Show the change right away, even while paused
+                    if let Err(e) = ruboy.present_frame() {
+                        log::error!("Could not redraw frame: {}", e);
+                    }
+                }
+            }
+            Command::SetFrameskip(frameskip) => {
+                self.frameskip = frameskip;
+
+                if let Some((partner, _)) = &mut self.partner {
+                    partner.set_frameskip(frameskip);
+                }
+
+                if let Some(ruboy) = self.ruboy.lock().unwrap().as_mut() {
+                    ruboy.set_frameskip(frameskip);
+                }
+            }
+            Command::SetPrinter(connected) => {
+                self.printer = connected;
+
+                if let Some(ruboy) = self.ruboy.lock().unwrap().as_mut() {
+                    self.connect_link_port(ruboy);
+                }
+            }
+            Command::SetLinkPartner(path) => self.link(path),
+            Command::SetNetLink(link) => {
+                self.net_link = link;
+
+                if let Some(ruboy) = self.ruboy.lock().unwrap().as_mut() {
+                    self.connect_link_port(ruboy);
+                }
+            }
+            #[cfg(feature = "scripting")]
+            Command::LoadScript(path) => {
+                let mut guard = self.ruboy.lock().unwrap();
+                self.scripts.set(path, guard.as_mut());
+            }
+            #[cfg(feature = "scripting")]
+            Command::StopScript => self.scripts.stop(),
+            Command::Reset => self.reset(),
+            Command::Save => self.save(),
+            #[cfg(feature = "audio")]
+            Command::Audio(settings) => {
+                self.audio = settings;
+
+                if let Some(ruboy) = self.ruboy.lock().unwrap().as_mut() {
+                    self.apply_audio(ruboy);
+                }
+            }
+            Command::Quit => unreachable!("Quit is handled by the run loop"),
+        }
+    }
+
+    fn open(path: &Path, output: VideoOutput, inputs: SharedInputs) -> Result<AppRuboy, OpenErr> {
+        emucore::boxed_send(
+            CoreRom::Buffer(read_rom(path)?),
+            CoreAllocator::Inline,
+            output,
+            inputs,
+            BootRom::BuiltIn,
+        )
+        .map_err(OpenErr::from)
+    }
+
+    /// Opens the ROM, telling the UI if that fails
+    fn open_or_report(
+        &self,
+        path: PathBuf,
+        output: VideoOutput,
+        inputs: SharedInputs,
+    ) -> Option<(AppRuboy, PathBuf)> {
+        match Self::open(&path, output, inputs) {
+            Ok(ruboy) => Some((ruboy, path)),
+            Err(e) => {
+                self.report(path, e);
+                None
+            }
+        }
+    }
+
+    /// Tells the UI why the ROM could not be loaded
+    fn report(&self, path: PathBuf, err: OpenErr) {
+        match err {
+            OpenErr::Unsupported(mapper) => {
+                log::error!(
+                    "Could not load {}: the {} mapper is not supported",
+                    path.display(),
+                    mapper
+                );
+
+                let meta = read_header(&path);
+                let _ = self
+                    .events
+                    .send(Event::UnsupportedCartridge { path, mapper, meta });
+            }
+            OpenErr::Failed(e) => {
+                log::error!("Could not load {}: {}", path.display(), e);
+
+                let _ = self.events.send(Event::RomLoadFailed {
+                    path,
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+
+    /// Starts the game, replacing the one running. The debugger of the
+    /// game being replaced stays attached.
+    fn load(&mut self, path: PathBuf) {
+        // Don't lose the progress in the game being replaced
+        self.save();
+
+        let output = VideoOutput::new(self.handoff.clone(), self.frame_dump.clone());
+
+        let Some((mut ruboy, _)) = self.open_or_report(path.clone(), output, self.inputs.clone())
+        else {
+            return;
+        };
+
+        ruboy.set_speed(self.speed);
+        ruboy.set_palette(self.palette);
+        ruboy.set_frameskip(self.frameskip);
+        ruboy.set_paused(self.paused.load(Ordering::Relaxed));
+        ruboy.set_error_policy(ErrorPolicy::Pause);
+        ruboy.attach_frame_observer(Box::new(RecordingHook(self.recorder.clone())));
+        ruboy.attach_rumble_handler(Box::new(RumbleHook(self.rumble.clone())));
+        self.connect_link_port(&mut ruboy);
+
+        #[cfg(feature = "audio")]
+        self.apply_audio(&mut ruboy);
+
+        let mut guard = self.ruboy.lock().unwrap();
+
+        if let Some(mut old) = guard.take() {
+            carry_over_debugging(&mut *old, &mut *ruboy);
+        }
+
+        let ruboy = guard.insert(ruboy);
+
+        log::info!("Loaded {}", path.display());
+
+        let (storage, name) = save_location(&path);
+
+        if let Err(e) = ruboy.as_mut().load_persistent(&storage, &name) {
+            log::error!("Could not load save data for {}: {}", path.display(), e);
+        }
+
+        self.rumble.store(false, Ordering::Relaxed);
+
+        #[cfg(feature = "scripting")]
+        self.scripts.start(ruboy);
+
+        let loaded = Event::RomLoaded {
+            path: path.clone(),
+            title: ruboy.rom_meta().title().to_string(),
+            capabilities: ruboy.rom_meta().capabilities(),
+        };
+
+        self.rewinder.clear();
+        self.rom = Some(path);
+
+        // Nobody to tell if the UI is already gone
+        let _ = self.events.send(loaded);
+    }
+
+    /// Restarts the game, keeping its save data and everything attached
+    fn reset(&mut self) {
+        let mut guard = self.ruboy.lock().unwrap();
+
+        let Some(ruboy) = guard.as_mut() else {
+            return;
+        };
+
+        if let Err(e) = ruboy.reset() {
+            log::error!("Could not reset, pausing: {}", e);
+            self.paused.store(true, Ordering::Relaxed);
+            ruboy.set_paused(true);
+            return;
+        }
+
+        // Show the blank screen right away, even while paused
+        if let Err(e) = ruboy.present_frame() {
+            log::error!("Could not redraw frame: {}", e);
+        }
+    }
+
+    /// Pauses or resumes both the loop and the emulator itself
+    fn set_paused(&mut self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+
+        if let Some(ruboy) = self.ruboy.lock().unwrap().as_mut() {
+            ruboy.set_paused(paused);
+        }
+    }
+
+    /// Starts the second game on the link cable, replacing the one running,
+    /// or stops it if `path` is None
+    fn link(&mut self, path: Option<PathBuf>) {
+        self.save_partner();
+        self.partner = None;
+
+        if let Some(path) = path {
+            let output = VideoOutput::new(self.partner_handoff.clone(), SharedFrameDump::default());
+
+            if let Some((mut partner, path)) =
+                self.open_or_report(path, output, self.partner_inputs.clone())
+            {
+                let (storage, name) = partner_save_location(&path);
+
+                if let Err(e) = partner.as_mut().load_persistent(&storage, &name) {
+                    log::error!("Could not load save data for {}: {}", path.display(), e);
+                }
+
+                partner.set_palette(self.palette);
+                partner.set_frameskip(self.frameskip);
+
+                log::info!("Linked {}", path.display());
+                self.partner = Some((partner, path));
+            }
+        }
+
+        self.link_cycles = 0.0;
+        self.linked.store(self.partner.is_some(), Ordering::Relaxed);
+
+        if let Some(ruboy) = self.ruboy.lock().unwrap().as_mut() {
+            self.connect_link_port(ruboy);
+        }
+    }
+
+    /// Plugs whatever should be on the link port in: the cable to a second
+    /// game if one is running, else the network link, else the printer if
+    /// enabled
+    fn connect_link_port(&self, ruboy: &mut AppRuboy) {
+        if self.partner.is_some() {
+            // Stepped together, without a device in between
+            ruboy.detach_link_device();
+        } else if let Some(net) = &self.net_link {
+            ruboy.attach_link_device(Box::new(net.device()));
+        } else if self.printer {
+            ruboy.attach_link_device(Box::new(Printer::new(PrintToPng(self.events.clone()))));
+        } else {
+            ruboy.detach_link_device();
+        }
+    }
+
+    fn save(&mut self) {
+        self.save_partner();
+
+        let Some(rom) = &self.rom else {
+            return;
+        };
+
+        let mut guard = self.ruboy.lock().unwrap();
+
+        let Some(ruboy) = guard.as_mut() else {
+            return;
+        };
+
+        let (mut storage, name) = save_location(rom);
+
+        match ruboy.as_mut().save_persistent(&mut storage, &name) {
+            Ok(()) => log::info!("Saved data for {}", rom.display()),
+            Err(e) => log::error!("Could not save data for {}: {}", rom.display(), e),
+        }
+    }
+
+    fn save_partner(&mut self) {
+        let Some((partner, rom)) = &mut self.partner else {
+            return;
+        };
+
+        let (mut storage, name) = partner_save_location(rom);
+
+        match partner.as_mut().save_persistent(&mut storage, &name) {
+            Ok(()) => log::info!("Saved data for {}", rom.display()),
+            Err(e) => log::error!("Could not save data for {}: {}", rom.display(), e),
+        }
+    }
+
+    /// Runs frames back to back until `deadline`, releasing the emulator
+    /// between frames so the UI can still get to it
+    fn run_turbo(&mut self, frame_time: Duration, deadline: Instant) {
+        while Instant::now() < deadline && !self.paused.load(Ordering::Relaxed) {
+            self.step(frame_time.as_secs_f64());
+        }
+    }
+
+    fn step(&mut self, dt: f64) {
+        let mut guard = self.ruboy.lock().unwrap();
+
+        let Some(ruboy) = guard.as_mut() else {
+            return;
+        };
+
+        #[cfg(feature = "scripting")]
+        let frame = ruboy.frame_count();
+
+        let paced = self.paced_samples();
+
+        let result = match &mut self.partner {
+            Some((partner, _)) => {
+                let cycles = dt * self.speed * CLOCK_SPEED_HZ_F64 + self.link_cycles;
+                self.link_cycles = cycles.fract();
+
+                link::run_linked(ruboy.as_mut(), partner.as_mut(), cycles as u64)
+                    .map(drop)
+                    .map_err(|e| e.to_string())
+            }
+            None => match paced {
+                Some(samples) => ruboy.step_until_audio(samples),
+                None => ruboy.step(dt),
+            }
+            .map(drop)
+            .map_err(|e| e.to_string()),
+        };
+
+        if let Err(e) = result {
+            log::error!("Emulation failed, pausing: {}", e);
+            self.paused.store(true, Ordering::Relaxed);
+            ruboy.set_paused(true);
+        }
+
+        // The emulator already paused itself and logged it
+        if let Some(fault) = ruboy.take_fault() {
+            self.paused.store(true, Ordering::Relaxed);
+
+            let _ = self.events.send(Event::EmulationFailed {
+                error: fault.to_string(),
+            });
+        }
+
+        if let Some(Err(e)) = self.net_link.as_ref().map(|net| net.poll(ruboy.as_mut())) {
+            log::error!("Network link failed, unplugging it: {}", e);
+
+            let _ = self.events.send(Event::NetLinkFailed {
+                error: e.to_string(),
+            });
+
+            self.net_link = None;
+            self.connect_link_port(ruboy);
+        }
+
+        #[cfg(feature = "scripting")]
+        if ruboy.frame_count() != frame {
+            self.scripts.frame(ruboy);
+        }
+
+        self.rewinder.capture(ruboy.as_ref());
+
+        #[cfg(feature = "audio")]
+        if let Some(sink) = &self.audio.sink {
+            if self.turbo {
+                // Far more audio than can be played back, so skip it
+                ruboy.drain_audio().for_each(drop);
+            } else {
+                let samples: Vec<_> = ruboy.drain_audio().collect();
+                self.record_audio(&samples, sink.sample_rate());
+
+                sink.push(samples.into_iter());
+
+                // When paced, the device rate can't drift from the emulator
+                if !self.audio.paced {
+                    ruboy.set_audio_sample_rate(sink.adjusted_rate());
+                }
+            }
+        }
+    }
+
+    /// With audio pacing, the amount of samples the next step should
+    /// produce. Never more than [MAX_STEP] worth, like stepping by time.
+    #[cfg(feature = "audio")]
+    fn paced_samples(&self) -> Option<usize> {
+        let sink = self
+            .audio
+            .sink
+            .as_ref()
+            .filter(|_| self.audio.paced && !self.turbo)?;
+
+        let max = (sink.sample_rate() as f64 * MAX_STEP) as usize;
+
+        Some(sink.missing().min(max))
+    }
+
+    #[cfg(not(feature = "audio"))]
+    fn paced_samples(&self) -> Option<usize> {
+        None
+    }
+
+    #[cfg(feature = "audio")]
+    fn record_audio(&self, samples: &[[f32; 2]], sample_rate: u32) {
+        let mut recorder = self.recorder.lock().unwrap();
+
+        if let Some(Err(e)) = recorder.as_mut().map(|rec| rec.audio(samples, sample_rate)) {
+            log::error!("Stopped recording: {}", e);
+            *recorder = None;
+        }
+    }
+
+    /// Goes back a single snapshot, and shows the frame it was taken at
+    fn rewind(&mut self) {
+        let mut guard = self.ruboy.lock().unwrap();
+
+        let Some(ruboy) = guard.as_mut() else {
+            return;
+        };
+
+        match self.rewinder.rewind(ruboy.as_mut()) {
+            Ok(true) => {
+                if let Err(e) = ruboy.present_frame() {
+                    log::error!("Could not show rewound frame: {}", e);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => log::error!("Could not rewind: {}", e),
+        }
+    }
+
+    #[cfg(feature = "audio")]
+    fn apply_audio(&self, ruboy: &mut AppRuboy) {
+        match &self.audio.sink {
+            Some(sink) => ruboy.enable_audio(sink.sample_rate()),
+            None => ruboy.disable_audio(),
+        }
+
+        for (channel, muted) in AudioChannel::ALL.into_iter().zip(self.audio.muted) {
+            ruboy.set_audio_channel_muted(channel, muted);
+        }
+    }
+}
+
+/// Why a ROM could not be opened
+enum OpenErr {
+    /// Ruboy doesn't emulate the mapper of the cartridge yet
+    Unsupported(CartridgeMapper),
+
+    Failed(String),
+}
+
+impl From<CoreStartErr> for OpenErr {
+    fn from(value: CoreStartErr) -> Self {
+        match value.unsupported_mapper() {
+            Some(mapper) => OpenErr::Unsupported(mapper),
+            None => OpenErr::Failed(format!("Could not initialize Ruboy: {}", value)),
+        }
+    }
+}
+
+fn read_rom(path: &Path) -> Result<Vec<u8>, OpenErr> {
+    fs::read(path).map_err(|e| OpenErr::Failed(format!("Could not open file: {}", e)))
+}
+
+/// Moves the debugger and what it records over to the game replacing the
+/// one it was attached to
+fn carry_over_debugging(
+    old: &mut dyn EmulatorCore<VideoOutput>,
+    new: &mut dyn EmulatorCore<VideoOutput>,
+) {
+    if let Some(debugger) = old.detach_debugger() {
+        new.attach_debugger(debugger);
+    }
+
+    if let Some(trace) = old.trace() {
+        new.enable_trace(trace.capacity());
+    }
+
+    if old.call_stack().is_some() {
+        new.enable_call_stack();
+    }
+}
+
+/// Reads just the header of a ROM
+fn read_header(path: &Path) -> Option<RomMeta> {
+    let mut rom = [0; RomMeta::OFFSET_HEADER_START + RomMeta::HEADER_LENGTH];
+    File::open(path).ok()?.read_exact(&mut rom).ok()?;
+
+    RomMeta::parse(&rom[RomMeta::OFFSET_HEADER_START..]).ok()
+}
+
+/// Tells the UI thread the state of the rumble motor, which it forwards to
+/// the gamepads
+struct RumbleHook(Arc<AtomicBool>);
+
+impl RumbleHandler for RumbleHook {
+    fn rumble(&mut self, on: bool) {
+        self.0.store(on, Ordering::Relaxed);
+    }
+}
+
+/// Saves printouts next to the screenshots, telling the UI if that fails
+struct PrintToPng(Sender<Event>);
+
+impl PrintToPng {
+    fn save(printout: &Printout) -> Result<PathBuf, png::EncodingError> {
+        let dir = capture::screenshot_dir();
+        fs::create_dir_all(&dir)?;
+
+        let path = capture::next_free_path(&dir, "printout");
+        let rgba = printout.to_rgba_bytes(&DmgPalette::GRAYSCALE);
+        capture::write_png(&path, PRINTOUT_X, printout.height(), &rgba)?;
+
+        Ok(path)
+    }
+}
+
+impl PrintHandler for PrintToPng {
+    fn print(&mut self, printout: Printout) {
+        // Games print empty images to feed paper
+        if printout.height() == 0 {
+            return;
+        }
+
+        match Self::save(&printout) {
+            Ok(path) => log::info!("Saved printout to {}", path.display()),
+            Err(e) => {
+                log::error!("Could not save printout: {}", e);
+
+                let _ = self.0.send(Event::PrintFailed {
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+}
+
+/// Save data is stored next to the ROM, named after it
+fn save_location(rom: &Path) -> (FsStorage, String) {
+    let dir = rom.parent().unwrap_or(Path::new("."));
+    let name = rom
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "save".to_string());
+
+    (FsStorage::new(dir), name)
+}
+
+/// The second game saves separately, so linking a game to itself doesn't
+/// have both copies write the same file
+fn partner_save_location(rom: &Path) -> (FsStorage, String) {
+    let (storage, name) = save_location(rom);
+
+    (storage, format!("{}-player2", name))
+}
@@ -0,0 +1,318 @@
+//! Filters applied to the Game Boy screen before it is shown. They work on
+//! the small frame and return a larger image, which is then scaled to the
+//! window without smoothing like the plain frame.
+//!
+//! They run on the CPU rather than as shaders: the frame is tiny, and the
+//! result goes through the same egui texture as the plain frame, so every
+//! renderer eframe supports shows it.
+
+use std::fmt::Display;
+
+use eframe::egui::{Color32, ColorImage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VideoFilter {
+    /// Every Game Boy pixel is a plain square
+    #[default]
+    None,
+
+    /// Splits every pixel into red, green and blue stripes with a dark gap
+    /// below, like a close look at an LCD
+    LcdGrid,
+
+    /// Dark lines between the rows, like a CRT television
+    Scanlines,
+
+    /// Rounds off diagonal edges, doubling the resolution
+    Scale2x,
+
+    /// [VideoFilter::Scale2x] applied twice
+    Scale4x,
+}
+
+impl VideoFilter {
+    pub const ALL: [VideoFilter; 5] = [
+        VideoFilter::None,
+        VideoFilter::LcdGrid,
+        VideoFilter::Scanlines,
+        VideoFilter::Scale2x,
+        VideoFilter::Scale4x,
+    ];
+
+    /// The name used in the config file
+    pub const fn name(self) -> &'static str {
+        match self {
+            VideoFilter::None => "none",
+            VideoFilter::LcdGrid => "lcd_grid",
+            VideoFilter::Scanlines => "scanlines",
+            VideoFilter::Scale2x => "scale2x",
+            VideoFilter::Scale4x => "scale4x",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|filter| filter.name() == name)
+    }
+
+    /// How many times larger the filtered image is than the frame
+    pub const fn factor(self) -> usize {
+        match self {
+            VideoFilter::None => 1,
+            VideoFilter::LcdGrid | VideoFilter::Scanlines => 3,
+            VideoFilter::Scale2x => 2,
+            VideoFilter::Scale4x => 4,
+        }
+    }
+
+    pub fn apply(self, image: ColorImage) -> ColorImage {
+        match self {
+            VideoFilter::None => image,
+            VideoFilter::LcdGrid => lcd_grid(&image),
+            VideoFilter::Scanlines => scanlines(&image),
+            VideoFilter::Scale2x => scale2x(&image),
+            VideoFilter::Scale4x => scale2x(&scale2x(&image)),
+        }
+    }
+}
+
+impl Display for VideoFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            VideoFilter::None => "None",
+            VideoFilter::LcdGrid => "LCD grid",
+            VideoFilter::Scanlines => "Scanlines",
+            VideoFilter::Scale2x => "Scale2x",
+            VideoFilter::Scale4x => "Scale4x",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+/// Multiplies the channels of the color by the given factors
+fn dim(color: Color32, [r, g, b]: [f32; 3]) -> Color32 {
+    let scale = |channel: u8, factor: f32| (f32::from(channel) * factor) as u8;
+
+    Color32::from_rgb(
+        scale(color.r(), r),
+        scale(color.g(), g),
+        scale(color.b(), b),
+    )
+}
+
+/// Replaces every pixel by a `factor` by `factor` block, colored by `block`
+/// from the pixel and the position inside the block
+fn expand(
+    image: &ColorImage,
+    factor: usize,
+    block: impl Fn(Color32, usize, usize) -> Color32,
+) -> ColorImage {
+    let [width, height] = image.size;
+    let mut pixels = Vec::with_capacity(width * height * factor * factor);
+
+    for row in image.pixels.chunks(width) {
+        for y in 0..factor {
+            for &pixel in row {
+                pixels.extend((0..factor).map(|x| block(pixel, x, y)));
+            }
+        }
+    }
+
+    ColorImage {
+        size: [width * factor, height * factor],
+        pixels,
+    }
+}
+
+fn lcd_grid(image: &ColorImage) -> ColorImage {
+    const STRIPES: [[f32; 3]; 3] = [[1.0, 0.7, 0.7], [0.7, 1.0, 0.7], [0.7, 0.7, 1.0]];
+
+    expand(image, 3, |pixel, x, y| {
+        let striped = dim(pixel, STRIPES[x]);
+
+        if y == 2 {
+            dim(striped, [0.6; 3])
+        } else {
+            striped
+        }
+    })
+}
+
+fn scanlines(image: &ColorImage) -> ColorImage {
+    expand(image, 3, |pixel, _, y| match y {
+        0 => dim(pixel, [0.85; 3]),
+        1 => pixel,
+        _ => dim(pixel, [0.4; 3]),
+    })
+}
+
+/// The Scale2x algorithm, also known as AdvMAME2x: every pixel becomes 4,
+/// each taking the color of two matching neighbours it sits between
+fn scale2x(image: &ColorImage) -> ColorImage {
+    let [width, height] = image.size;
+    let at = |x: usize, y: usize| image.pixels[y * width + x];
+
+    let mut pixels = vec![Color32::BLACK; width * height * 4];
+    let out_width = width * 2;
+
+    for y in 0..height {
+        for x in 0..width {
+            let p = at(x, y);
+            let up = at(x, y.saturating_sub(1));
+            let down = at(x, (y + 1).min(height - 1));
+            let left = at(x.saturating_sub(1), y);
+            let right = at((x + 1).min(width - 1), y);
+
+            let (top_left, top_right, bottom_left, bottom_right) = if up != down && left != right {
+                (
+                    if left == up { left } else { p },
+                    if up == right { right } else { p },
+                    if left == down { left } else { p },
+                    if down == right { right } else { p },
+                )
+            } else {
+                (p, p, p, p)
+            };
+
+            let top = (y * 2) * out_width + x * 2;
+            let bottom = top + out_width;
+
+            pixels[top] = top_left;
+            pixels[top + 1] = top_right;
+            pixels[bottom] = bottom_left;
+            pixels[bottom + 1] = bottom_right;
+        }
+    }
+
+    ColorImage {
+        size: [out_width, height * 2],
+        pixels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds an image from rows of `#` (black) and `.` (white)
+    fn image(rows: &[&str]) -> ColorImage {
+        let pixels = rows
+            .iter()
+            .flat_map(|row| row.chars())
+            .map(|pixel| match pixel {
+                '#' => Color32::BLACK,
+                _ => Color32::WHITE,
+            })
+            .collect();
+
+        ColorImage {
+            size: [rows[0].len(), rows.len()],
+            pixels,
+        }
+    }
+
+    fn single(color: Color32) -> ColorImage {
+        ColorImage {
+            size: [1, 1],
+            pixels: vec![color],
+        }
+    }
+
+    #[test]
+    fn filtered_size_matches_factor() {
+        for filter in VideoFilter::ALL {
+            let filtered = filter.apply(image(&["#..", "...", ".#."]));
+
+            assert_eq!([3 * filter.factor(); 2], filtered.size, "{}", filter);
+            assert_eq!(
+                9 * filter.factor().pow(2),
+                filtered.pixels.len(),
+                "{}",
+                filter
+            );
+        }
+    }
+
+    #[test]
+    fn none_keeps_the_frame() {
+        let frame = image(&["#.", ".#"]);
+
+        assert_eq!(frame, VideoFilter::None.apply(frame.clone()));
+    }
+
+    #[test]
+    fn lcd_grid_stripes_pixels() {
+        let filtered = VideoFilter::LcdGrid.apply(single(Color32::WHITE));
+
+        let red = Color32::from_rgb(255, 178, 178);
+        let green = Color32::from_rgb(178, 255, 178);
+        let blue = Color32::from_rgb(178, 178, 255);
+        let gap = |color: Color32| dim(color, [0.6; 3]);
+
+        assert_eq!(
+            vec![
+                red,
+                green,
+                blue,
+                red,
+                green,
+                blue,
+                gap(red),
+                gap(green),
+                gap(blue)
+            ],
+            filtered.pixels
+        );
+        assert_eq!(Color32::from_rgb(153, 106, 106), gap(red));
+    }
+
+    #[test]
+    fn scanlines_darken_rows() {
+        let filtered = VideoFilter::Scanlines.apply(single(Color32::from_rgb(100, 200, 40)));
+
+        let top = Color32::from_rgb(85, 170, 34);
+        let middle = Color32::from_rgb(100, 200, 40);
+        let bottom = Color32::from_rgb(40, 80, 16);
+
+        assert_eq!(
+            vec![top, top, top, middle, middle, middle, bottom, bottom, bottom],
+            filtered.pixels
+        );
+    }
+
+    #[test]
+    fn scale2x_rounds_diagonals() {
+        let filtered = VideoFilter::Scale2x.apply(image(&[".#.", "#..", "..."]));
+
+        assert_eq!(
+            image(&[
+                "..##..", //
+                ".###..", "###...", "##....", "......", "......",
+            ]),
+            filtered
+        );
+    }
+
+    #[test]
+    fn scale2x_keeps_straight_edges() {
+        let frame = image(&["###", "###", "..."]);
+
+        assert_eq!(
+            image(&[
+                "######", //
+                "######", "######", "######", "......", "......",
+            ]),
+            VideoFilter::Scale2x.apply(frame)
+        );
+    }
+
+    #[test]
+    fn scale4x_is_scale2x_twice() {
+        let frame = image(&[".#.", "#..", "..."]);
+
+        assert_eq!(
+            VideoFilter::Scale2x.apply(VideoFilter::Scale2x.apply(frame.clone())),
+            VideoFilter::Scale4x.apply(frame)
+        );
+    }
+}
@@ -0,0 +1,198 @@
+//! Which keys and gamepad buttons control which Game Boy buttons
+
+use std::fmt::Display;
+
+use eframe::egui::Key;
+use toml::{Table, Value};
+
+use super::GbButton;
+
+/// A gamepad button, named after its position on the gamepad. Kept separate
+/// from gilrs, so bindings are kept in builds without gamepad support.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl PadButton {
+    pub const ALL: [PadButton; 17] = [
+        PadButton::South,
+        PadButton::East,
+        PadButton::North,
+        PadButton::West,
+        PadButton::LeftTrigger,
+        PadButton::LeftTrigger2,
+        PadButton::RightTrigger,
+        PadButton::RightTrigger2,
+        PadButton::Select,
+        PadButton::Start,
+        PadButton::Mode,
+        PadButton::LeftThumb,
+        PadButton::RightThumb,
+        PadButton::DPadUp,
+        PadButton::DPadDown,
+        PadButton::DPadLeft,
+        PadButton::DPadRight,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            PadButton::South => "South",
+            PadButton::East => "East",
+            PadButton::North => "North",
+            PadButton::West => "West",
+            PadButton::LeftTrigger => "LeftTrigger",
+            PadButton::LeftTrigger2 => "LeftTrigger2",
+            PadButton::RightTrigger => "RightTrigger",
+            PadButton::RightTrigger2 => "RightTrigger2",
+            PadButton::Select => "Select",
+            PadButton::Start => "Start",
+            PadButton::Mode => "Mode",
+            PadButton::LeftThumb => "LeftThumb",
+            PadButton::RightThumb => "RightThumb",
+            PadButton::DPadUp => "DPadUp",
+            PadButton::DPadDown => "DPadDown",
+            PadButton::DPadLeft => "DPadLeft",
+            PadButton::DPadRight => "DPadRight",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|button| button.name() == name)
+    }
+}
+
+impl Display for PadButton {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// What is bound to each Game Boy button
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bindings<T>([T; GbButton::ALL.len()]);
+
+impl<T: Copy> Bindings<T> {
+    pub const fn get(&self, button: GbButton) -> T {
+        self.0[button as usize]
+    }
+
+    pub fn set(&mut self, button: GbButton, bound: T) {
+        self.0[button as usize] = bound;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InputConfig {
+    pub keys: Bindings<Key>,
+    pub pad: Bindings<PadButton>,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        // In the order of GbButton::ALL
+        Self {
+            keys: Bindings([
+                Key::ArrowUp,
+                Key::ArrowDown,
+                Key::ArrowLeft,
+                Key::ArrowRight,
+                Key::A,
+                Key::B,
+                Key::Enter,
+                Key::Backspace,
+            ]),
+            pad: Bindings([
+                PadButton::DPadUp,
+                PadButton::DPadDown,
+                PadButton::DPadLeft,
+                PadButton::DPadRight,
+                PadButton::East,
+                PadButton::South,
+                PadButton::Start,
+                PadButton::Select,
+            ]),
+        }
+    }
+}
+
+impl InputConfig {
+    /// Reads the `[keyboard]` and `[gamepad]` sections of the config file.
+    /// Bindings that are missing or can't be parsed keep their default.
+    pub fn read(table: &Table) -> Self {
+        let mut config = Self::default();
+
+        read_bindings(table, "keyboard", &mut config.keys, Key::from_name);
+        read_bindings(table, "gamepad", &mut config.pad, PadButton::from_name);
+
+        config
+    }
+
+    pub fn write(&self, table: &mut Table) {
+        table.insert(
+            "keyboard".to_string(),
+            write_bindings(&self.keys, Key::name),
+        );
+        table.insert(
+            "gamepad".to_string(),
+            write_bindings(&self.pad, PadButton::name),
+        );
+    }
+}
+
+fn read_bindings<T: Copy>(
+    table: &Table,
+    section: &str,
+    bindings: &mut Bindings<T>,
+    parse: impl Fn(&str) -> Option<T>,
+) {
+    let Some(Value::Table(entries)) = table.get(section) else {
+        return;
+    };
+
+    for button in GbButton::ALL {
+        let Some(entry) = entries.get(button.name()) else {
+            continue;
+        };
+
+        match entry.as_str().and_then(&parse) {
+            Some(bound) => bindings.set(button, bound),
+            None => log::warn!(
+                "Ignoring invalid {} binding for {}: {}",
+                section,
+                button,
+                entry
+            ),
+        }
+    }
+}
+
+fn write_bindings<T: Copy>(bindings: &Bindings<T>, name: impl Fn(T) -> &'static str) -> Value {
+    let entries = GbButton::ALL
+        .into_iter()
+        .map(|button| {
+            (
+                button.name().to_string(),
+                Value::String(name(bindings.get(button)).to_string()),
+            )
+        })
+        .collect();
+
+    Value::Table(entries)
+}
@@ -0,0 +1,158 @@
+//! Gamepad input through gilrs
+
+use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Replay, Ticks};
+use gilrs::{Axis, Button, EventType, Gamepad, GamepadId, Gilrs};
+
+use super::config::PadButton;
+
+/// How far the left stick has to be pushed to count as a D-pad press
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// Strength of the force feedback while the cartridge rumbles
+const RUMBLE_MAGNITUDE: u16 = u16::MAX / 2;
+
+pub struct Gamepads {
+    gilrs: Gilrs,
+
+    /// The last button pressed on any gamepad, until taken
+    last_pressed: Option<PadButton>,
+
+    /// Plays while the cartridge rumbles
+    rumble: Option<Effect>,
+}
+
+impl Gamepads {
+    /// Returns None if gamepads are not available on this system
+    pub fn new() -> Option<Self> {
+        match Gilrs::new() {
+            Ok(gilrs) => Some(Self {
+                gilrs,
+                last_pressed: None,
+                rumble: None,
+            }),
+            Err(e) => {
+                log::warn!("Gamepads are not available: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Processes the pending gamepad events. Call once per frame.
+    pub fn update(&mut self) {
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::ButtonPressed(button, _) => {
+                    if let Some(pressed) = from_gilrs(button) {
+                        self.last_pressed = Some(pressed);
+                    }
+                }
+                EventType::Connected => log::info!("Gamepad {} connected", event.id),
+                EventType::Disconnected => log::info!("Gamepad {} disconnected", event.id),
+                _ => {}
+            }
+        }
+    }
+
+    /// The last button pressed since the previous call, used for rebinding
+    pub fn take_last_pressed(&mut self) -> Option<PadButton> {
+        self.last_pressed.take()
+    }
+
+    /// Starts or stops the force feedback of every gamepad that has it
+    pub fn set_rumble(&mut self, on: bool) {
+        if on == self.rumble.is_some() {
+            return;
+        }
+
+        match self.rumble.take() {
+            Some(effect) => {
+                if let Err(e) = effect.stop() {
+                    log::debug!("Could not stop rumble: {}", e);
+                }
+            }
+            None => self.rumble = self.start_rumble(),
+        }
+    }
+
+    fn start_rumble(&mut self) -> Option<Effect> {
+        let pads: Vec<GamepadId> = self
+            .gilrs
+            .gamepads()
+            .filter(|(_, pad)| pad.is_ff_supported())
+            .map(|(id, _)| id)
+            .collect();
+
+        if pads.is_empty() {
+            return None;
+        }
+
+        // Repeats until stopped
+        let effect = EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind: BaseEffectType::Strong {
+                    magnitude: RUMBLE_MAGNITUDE,
+                },
+                scheduling: Replay {
+                    play_for: Ticks::from_ms(100),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .gamepads(&pads)
+            .finish(&mut self.gilrs)
+            .and_then(|effect| effect.play().map(|_| effect));
+
+        effect
+            .inspect_err(|e| log::debug!("Could not start rumble: {}", e))
+            .ok()
+    }
+
+    /// Whether the button is held on any connected gamepad. The left stick
+    /// also works as the D-pad.
+    pub fn is_pressed(&self, button: PadButton) -> bool {
+        self.gilrs
+            .gamepads()
+            .any(|(_, pad)| pad.is_pressed(to_gilrs(button)) || stick_pressed(&pad, button))
+    }
+}
+
+fn stick_pressed(pad: &Gamepad, button: PadButton) -> bool {
+    let x = pad.value(Axis::LeftStickX);
+    let y = pad.value(Axis::LeftStickY);
+
+    match button {
+        PadButton::DPadUp => y > STICK_THRESHOLD,
+        PadButton::DPadDown => y < -STICK_THRESHOLD,
+        PadButton::DPadLeft => x < -STICK_THRESHOLD,
+        PadButton::DPadRight => x > STICK_THRESHOLD,
+        _ => false,
+    }
+}
+
+const fn to_gilrs(button: PadButton) -> Button {
+    match button {
+        PadButton::South => Button::South,
+        PadButton::East => Button::East,
+        PadButton::North => Button::North,
+        PadButton::West => Button::West,
+        PadButton::LeftTrigger => Button::LeftTrigger,
+        PadButton::LeftTrigger2 => Button::LeftTrigger2,
+        PadButton::RightTrigger => Button::RightTrigger,
+        PadButton::RightTrigger2 => Button::RightTrigger2,
+        PadButton::Select => Button::Select,
+        PadButton::Start => Button::Start,
+        PadButton::Mode => Button::Mode,
+        PadButton::LeftThumb => Button::LeftThumb,
+        PadButton::RightThumb => Button::RightThumb,
+        PadButton::DPadUp => Button::DPadUp,
+        PadButton::DPadDown => Button::DPadDown,
+        PadButton::DPadLeft => Button::DPadLeft,
+        PadButton::DPadRight => Button::DPadRight,
+    }
+}
+
+fn from_gilrs(button: Button) -> Option<PadButton> {
+    PadButton::ALL
+        .into_iter()
+        .find(|pad_button| to_gilrs(*pad_button) == button)
+}
@@ -1,8 +1,69 @@
-use core::cell::RefCell;
-use std::rc::Rc;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
 
 use ruboy_lib::{GbInputs, InputHandler};
 
+pub mod config;
+#[cfg(feature = "gamepad")]
+pub mod gamepad;
+
+/// A button on the Game Boy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GbButton {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+    Start,
+    Select,
+}
+
+impl GbButton {
+    pub const ALL: [GbButton; 8] = [
+        GbButton::Up,
+        GbButton::Down,
+        GbButton::Left,
+        GbButton::Right,
+        GbButton::A,
+        GbButton::B,
+        GbButton::Start,
+        GbButton::Select,
+    ];
+
+    /// The name used in the config file
+    pub const fn name(self) -> &'static str {
+        match self {
+            GbButton::Up => "up",
+            GbButton::Down => "down",
+            GbButton::Left => "left",
+            GbButton::Right => "right",
+            GbButton::A => "a",
+            GbButton::B => "b",
+            GbButton::Start => "start",
+            GbButton::Select => "select",
+        }
+    }
+}
+
+impl Display for GbButton {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            GbButton::Up => "Up",
+            GbButton::Down => "Down",
+            GbButton::Left => "Left",
+            GbButton::Right => "Right",
+            GbButton::A => "A",
+            GbButton::B => "B",
+            GbButton::Start => "Start",
+            GbButton::Select => "Select",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Inputs {
     pub up: bool,
@@ -26,26 +87,45 @@ impl Inputs {
         self.a = false;
         self.b = false;
     }
+
+    pub fn set(&mut self, button: GbButton, pressed: bool) {
+        let state = match button {
+            GbButton::Up => &mut self.up,
+            GbButton::Down => &mut self.down,
+            GbButton::Left => &mut self.left,
+            GbButton::Right => &mut self.right,
+            GbButton::A => &mut self.a,
+            GbButton::B => &mut self.b,
+            GbButton::Start => &mut self.start,
+            GbButton::Select => &mut self.select,
+        };
+
+        *state = pressed;
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct SharedInputs {
-    pub inputs: Rc<RefCell<Inputs>>,
+    pub inputs: Arc<Mutex<Inputs>>,
+
+    /// What the emulator got on the last poll
+    polled: Option<GbInputs>,
 }
 
 impl SharedInputs {
     pub fn new() -> Self {
         Self {
-            inputs: Rc::new(RefCell::new(Inputs::default())),
+            inputs: Arc::new(Mutex::new(Inputs::default())),
+            polled: None,
         }
     }
 }
 
 impl InputHandler for SharedInputs {
-    fn get_new_inputs(&mut self) -> ruboy_lib::GbInputs {
-        let inputs_borrowed = self.inputs.borrow();
+    fn poll_inputs(&mut self) -> Option<GbInputs> {
+        let inputs_borrowed = self.inputs.lock().unwrap();
 
-        GbInputs {
+        let inputs = GbInputs {
             up: inputs_borrowed.up,
             start: inputs_borrowed.start,
             select: inputs_borrowed.select,
@@ -54,6 +134,13 @@ impl InputHandler for SharedInputs {
             down: inputs_borrowed.down,
             left: inputs_borrowed.left,
             right: inputs_borrowed.right,
+        };
+
+        if self.polled == Some(inputs) {
+            return None;
         }
+
+        self.polled = Some(inputs);
+        Some(inputs)
     }
 }
@@ -1,50 +1,146 @@
-use std::fs::File;
-use std::io::BufReader;
-use std::ops::Deref;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
-use std::time::Instant;
+use std::process::ExitCode;
 
 use clap::Parser;
+use compositor::Compositor;
+use config::Config;
 use eframe::egui::Key;
 use eframe::egui::{
-    self, load::SizedTexture, CentralPanel, ColorImage, Image, TextureHandle, TextureOptions,
+    self, load::SizedTexture, Align2, CentralPanel, Color32, ColorImage, FontId, Id, Image,
+    LayerId, Order, Sense, TextureHandle, TextureOptions, ViewportBuilder, ViewportId,
 };
 use eframe::NativeOptions;
-use input::SharedInputs;
-use menu::{draw_menu, MenuData};
-use ruboy_lib::{InlineAllocator, Ruboy};
-use video::VideoOutput;
+use emulator::{Command, Emulator, Event};
+use filter::VideoFilter;
+use input::{GbButton, SharedInputs};
+use menu::{draw_menu, draw_windows, MenuData};
+use notifications::Notifications;
+use ruboy_lib::emucore::EmulatorCore;
+use ruboy_lib::netlink::{LinkSync, NetLink, TcpTransport, UdpTransport};
+use video::{FrameHandoff, FrameReader, VideoOutput};
 
 use crate::args::CLIArgs;
 
 mod args;
+#[cfg(feature = "audio")]
+mod audio;
+mod capture;
+mod compositor;
+mod config;
+mod display;
+mod emulator;
+mod filter;
 mod input;
 mod menu;
+mod notifications;
+mod recording;
+#[cfg(feature = "scripting")]
+mod scripting;
 mod video;
 
+type AppRuboy = Box<dyn EmulatorCore<VideoOutput> + Send>;
+
 struct RuboyApp {
     pub cli_args: CLIArgs,
-    pub rom: Option<PathBuf>,
-    pub prev_frame_time: Instant,
-    pub ruboy: Option<Ruboy<InlineAllocator, BufReader<File>, VideoOutput, SharedInputs>>,
+    pub emulator: Emulator,
+    pub frames: FrameReader,
     pub frametex: Option<TextureHandle>,
+    /// The filter the frame texture was made with
+    pub filtered: VideoFilter,
+    pub compositor: Compositor,
+    pub composite: Option<ColorImage>,
+    pub composite_tex: Option<TextureHandle>,
     pub input_handler: SharedInputs,
-    pub video_handler: VideoOutput,
+    /// The second game on the link cable, shown in its own window
+    pub partner_frames: FrameReader,
+    pub partner_tex: Option<TextureHandle>,
+    pub partner_inputs: SharedInputs,
+    pub config: Config,
+    /// Where the config is saved, if there is a config directory
+    pub config_path: Option<PathBuf>,
+    /// The config changed since it was last saved
+    pub config_dirty: bool,
+    #[cfg(feature = "gamepad")]
+    pub gamepads: Option<input::gamepad::Gamepads>,
     pub menu_data: MenuData,
+    pub notifications: Notifications,
+    #[cfg(feature = "audio")]
+    pub audio: Option<audio::AudioOutput>,
 }
 
 impl RuboyApp {
-    pub fn new(args: CLIArgs) -> Self {
-        Self {
+    pub fn new(
+        args: CLIArgs,
+        config_path: Option<PathBuf>,
+        config: Result<Config, config::ConfigErr>,
+    ) -> Self {
+        let handoff = FrameHandoff::new();
+        let input_handler = SharedInputs::new();
+        let partner_handoff = FrameHandoff::new();
+        let partner_inputs = SharedInputs::new();
+        let mut notifications = Notifications::default();
+
+        let config = config.unwrap_or_else(|e| {
+            notifications.push_warning(format!("Could not load settings: {}", e));
+            Config::default()
+        });
+
+        #[cfg(feature = "audio")]
+        let audio = audio::AudioOutput::new(config.volume)
+            .inspect_err(|e| log::warn!("Could not start audio: {}", e))
+            .ok();
+
+        let app = Self {
             cli_args: args,
-            rom: None,
-            prev_frame_time: Instant::now(),
-            ruboy: None,
+            emulator: Emulator::spawn(
+                handoff.clone(),
+                input_handler.clone(),
+                partner_handoff.clone(),
+                partner_inputs.clone(),
+            ),
+            frames: FrameReader::new(handoff),
             frametex: None,
-            input_handler: SharedInputs::new(),
-            video_handler: VideoOutput::new(),
+            filtered: VideoFilter::None,
+            compositor: Compositor::default(),
+            composite: None,
+            composite_tex: None,
+            input_handler,
+            partner_frames: FrameReader::new(partner_handoff),
+            partner_tex: None,
+            partner_inputs,
+            config,
+            config_path,
+            config_dirty: false,
+            #[cfg(feature = "gamepad")]
+            gamepads: input::gamepad::Gamepads::new(),
             menu_data: MenuData::default(),
+            notifications,
+            #[cfg(feature = "audio")]
+            audio,
+        };
+
+        app.emulator
+            .send(Command::SetPalette(app.config.corrected_palette()));
+        app.emulator
+            .send(Command::SetFrameskip(app.config.frameskip));
+        app.apply_ghosting();
+
+        #[cfg(feature = "audio")]
+        menu::apply_audio_settings(&app);
+
+        if let Some(connect) = net_link(&app.cli_args) {
+            app.emulator.connect_net_link(connect);
         }
+
+        app
+    }
+
+    /// Hands the ghosting setting to the video of both games
+    pub fn apply_ghosting(&self) {
+        self.frames.set_ghosting(self.config.ghosting);
+        self.partner_frames.set_ghosting(self.config.ghosting);
     }
 
     const fn get_gb_tex_options() -> TextureOptions {
@@ -56,134 +152,448 @@ impl RuboyApp {
         }
     }
 
-    fn init_ruboy(&mut self, romfile: impl AsRef<Path>) {
-        debug_assert!(self.ruboy.is_none());
-
-        let romfile = File::open(romfile).expect("Could not open file at provided path");
-
-        let reader = BufReader::new(romfile);
-
-        let ruboy = Ruboy::<InlineAllocator, _, _, _>::new(
-            reader,
-            self.video_handler.clone(),
-            self.input_handler.clone(),
-        )
-        .expect("Could not initialize Ruboy");
-
-        self.ruboy = Some(ruboy);
-        self.prev_frame_time = Instant::now();
-    }
-
     fn init_gbtexture(&mut self, ctx: &egui::Context) {
         debug_assert!(self.frametex.is_none());
 
         self.frametex = Some(ctx.load_texture(
             "Ruboy Display",
-            ColorImage::from(self.video_handler.framebuf.borrow().deref()),
+            self.filtered_frame(),
             Self::get_gb_tex_options(),
         ));
+        self.filtered = self.config.filter;
     }
 
-    fn try_initialize(&mut self, ctx: &egui::Context) {
-        if self.ruboy.is_none() {
-            if let Some(rom) = self.rom.clone() {
-                self.init_ruboy(rom);
-            }
-        }
+    /// The current frame, with the chosen filter applied
+    fn filtered_frame(&self) -> ColorImage {
+        self.config
+            .filter
+            .apply(ColorImage::from(self.frames.current()))
+    }
 
+    fn try_initialize(&mut self, ctx: &egui::Context) {
         if self.frametex.is_none() {
             self.init_gbtexture(ctx);
         }
     }
 
     fn update_texture_from_framedata(&mut self) {
-        if !(*self.video_handler.dirty.borrow()) {
+        // Filter the frame again if the filter changed, even while paused
+        if !self.frames.update() && self.filtered == self.config.filter {
             return;
         }
 
-        self.frametex.as_mut().unwrap().set(
-            ColorImage::from(self.video_handler.framebuf.borrow().deref()),
-            Self::get_gb_tex_options(),
-        );
+        let image = self.filtered_frame();
+        self.filtered = self.config.filter;
 
-        *self.video_handler.dirty.borrow_mut() = false;
+        self.frametex
+            .as_mut()
+            .unwrap()
+            .set(image, Self::get_gb_tex_options());
     }
 
-    fn show_gameboy_frame(&mut self, ui: &mut egui::Ui) {
-        ui.centered_and_justified(|ui| {
-            if self.frametex.is_some() {
-                let sized_tex = SizedTexture::from_handle(&self.frametex.clone().unwrap());
-
-                let image = Image::new(sized_tex)
-                    .maintain_aspect_ratio(true)
-                    .shrink_to_fit();
-                ui.add(image);
+    fn update_composite(&mut self, ctx: &egui::Context) {
+        let guard = self.emulator.lock();
+
+        let ruboy = match guard.as_ref() {
+            Some(ruboy) if self.compositor.enabled => ruboy,
+            _ => {
+                self.composite = None;
+                return;
             }
-        });
+        };
+
+        let game = ColorImage::from(self.frames.current());
+        let composite = self.compositor.compose(&game, ruboy);
+        drop(guard);
+
+        match &mut self.composite_tex {
+            Some(tex) => tex.set(composite.clone(), Self::get_gb_tex_options()),
+            None => {
+                self.composite_tex = Some(ctx.load_texture(
+                    "Ruboy Composite",
+                    composite.clone(),
+                    Self::get_gb_tex_options(),
+                ))
+            }
+        }
+
+        self.composite = Some(composite);
+    }
+
+    fn show_gameboy_frame(&mut self, ui: &mut egui::Ui) {
+        let (tex, factor) = if self.composite.is_some() {
+            (&self.composite_tex, 1)
+        } else {
+            (&self.frametex, self.filtered.factor())
+        };
+
+        self.paint_texture(ui, tex.as_ref(), factor);
+    }
+
+    /// Fits the texture into the available space. Filtered frames are
+    /// `factor` times larger than the frame, and are fitted as if they were
+    /// not.
+    fn paint_texture(&self, ui: &mut egui::Ui, tex: Option<&TextureHandle>, factor: usize) {
+        let available = ui.available_rect_before_wrap();
+        ui.allocate_rect(available, Sense::hover());
+
+        let Some(tex) = tex else {
+            return;
+        };
+
+        // Letterboxing
+        ui.painter().rect_filled(available, 0.0, Color32::BLACK);
+
+        let rect = display::frame_rect(
+            self.config.scale_mode,
+            self.config.keep_aspect,
+            tex.size_vec2() / factor as f32,
+            available,
+        );
+
+        Image::new(SizedTexture::from_handle(tex)).paint_at(ui, rect);
     }
 
-    fn update_keyboard_input(&mut self, ctx: &egui::Context) {
+    fn update_input(&mut self, ctx: &egui::Context) {
+        #[cfg(feature = "gamepad")]
+        if let Some(pads) = self.gamepads.as_mut() {
+            pads.update();
+            pads.set_rumble(self.emulator.rumbling());
+        }
+
         ctx.input(|input| {
-            if !input.focused {
-                self.input_handler.inputs.borrow_mut().set_to_none();
+            let mut inputs = self.input_handler.inputs.lock().unwrap();
+
+            if !input.focused || self.menu_data.capturing_input() {
+                inputs.set_to_none();
                 return;
             }
 
-            let keys_down = &input.keys_down;
-            let mut inputs = self.input_handler.inputs.borrow_mut();
-
-            inputs.left = keys_down.contains(&Key::ArrowLeft);
-            inputs.right = keys_down.contains(&Key::ArrowRight);
-            inputs.up = keys_down.contains(&Key::ArrowUp);
-            inputs.down = keys_down.contains(&Key::ArrowDown);
-            inputs.a = keys_down.contains(&Key::A);
-            inputs.b = keys_down.contains(&Key::B);
-            inputs.start = keys_down.contains(&Key::Enter);
-            inputs.select = keys_down.contains(&Key::Backspace);
+            for button in GbButton::ALL {
+                let key_down = input
+                    .keys_down
+                    .contains(&self.config.input.keys.get(button));
+
+                #[cfg(feature = "gamepad")]
+                let pad_down = self
+                    .gamepads
+                    .as_ref()
+                    .is_some_and(|pads| pads.is_pressed(self.config.input.pad.get(button)));
+
+                #[cfg(not(feature = "gamepad"))]
+                let pad_down = false;
+
+                inputs.set(button, key_down || pad_down);
+            }
+        });
+    }
+
+    /// Shows the second game on the link cable in a window of its own. It
+    /// is played with the keyboard while that window has focus.
+    fn show_partner_window(&mut self, ctx: &egui::Context) {
+        if !self.emulator.linked() {
+            self.partner_tex = None;
+            return;
+        }
+
+        let viewport = ViewportBuilder::default()
+            .with_title("Ruboy - Player 2")
+            .with_inner_size(self.config.window_size());
+
+        ctx.show_viewport_immediate(ViewportId::from_hash_of("partner"), viewport, |ctx, _| {
+            ctx.input(|input| {
+                let mut inputs = self.partner_inputs.inputs.lock().unwrap();
+
+                if !input.focused || self.menu_data.capturing_input() {
+                    inputs.set_to_none();
+                    return;
+                }
+
+                for button in GbButton::ALL {
+                    inputs.set(
+                        button,
+                        input
+                            .keys_down
+                            .contains(&self.config.input.keys.get(button)),
+                    );
+                }
+            });
+
+            let changed = self.partner_frames.update();
+            let image = || {
+                self.config
+                    .filter
+                    .apply(ColorImage::from(self.partner_frames.current()))
+            };
+
+            match &mut self.partner_tex {
+                Some(tex) if changed => tex.set(image(), Self::get_gb_tex_options()),
+                Some(_) => {}
+                None => {
+                    self.partner_tex = Some(ctx.load_texture(
+                        "Ruboy Player 2",
+                        image(),
+                        Self::get_gb_tex_options(),
+                    ))
+                }
+            }
+
+            CentralPanel::default().show(ctx, |ui| {
+                self.paint_texture(ui, self.partner_tex.as_ref(), self.config.filter.factor());
+            });
+
+            if ctx.input(|input| input.viewport().close_requested()) {
+                self.emulator.send(Command::SetLinkPartner(None));
+            }
         });
     }
 
-    fn step_emulator(&mut self, ctx: &egui::Context) {
-        self.update_keyboard_input(ctx);
+    fn handle_emulator_events(&mut self) {
+        for event in self.emulator.events() {
+            match &event {
+                Event::RomLoaded { path, .. } => {
+                    self.config.add_recent_rom(path.clone());
+                    self.config_dirty = true;
+                }
+                Event::RomLoadFailed { .. }
+                | Event::UnsupportedCartridge { .. }
+                | Event::PrintFailed { .. }
+                | Event::NetLinkFailed { .. }
+                | Event::EmulationFailed { .. } => {}
+                #[cfg(feature = "scripting")]
+                Event::ScriptFailed { .. } => {}
+            }
 
-        let cur_time = Instant::now();
+            self.notifications.handle(event);
+        }
+    }
 
-        let dt = cur_time.duration_since(self.prev_frame_time).as_secs_f64();
-        let _cycles_ran = self.ruboy.as_mut().unwrap().step(dt).unwrap();
+    /// Loads a ROM dropped onto the window. Only the first file is used if
+    /// several are dropped at once.
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped = ctx.input(|input| {
+            input
+                .raw
+                .dropped_files
+                .iter()
+                .find_map(|file| file.path.clone())
+        });
 
-        self.prev_frame_time = cur_time;
+        if let Some(path) = dropped {
+            self.emulator.send(Command::LoadRom(path));
+        }
+    }
 
-        self.update_texture_from_framedata();
+    fn draw_drop_overlay(ctx: &egui::Context) {
+        if ctx.input(|input| input.raw.hovered_files.is_empty()) {
+            return;
+        }
+
+        let painter = ctx.layer_painter(LayerId::new(Order::Foreground, Id::new("drop_overlay")));
+        let screen = ctx.screen_rect();
+
+        painter.rect_filled(screen, 0.0, Color32::from_black_alpha(192));
+        painter.text(
+            screen.center(),
+            Align2::CENTER_CENTER,
+            "Drop a ROM to load it",
+            FontId::proportional(24.0),
+            Color32::WHITE,
+        );
+    }
+
+    /// Writes the config if it changed, but not while the mouse is held, so
+    /// dragging a slider doesn't write the file every frame
+    fn save_config(&mut self, ctx: &egui::Context) {
+        if !self.config_dirty || ctx.input(|input| input.pointer.any_down()) {
+            return;
+        }
+
+        self.config_dirty = false;
+
+        let Some(path) = &self.config_path else {
+            return;
+        };
+
+        if let Err(e) = self.config.save(path) {
+            self.notifications
+                .push_warning(format!("Could not save settings: {}", e));
+        }
+    }
+
+    fn update_window_hotkeys(&mut self, ctx: &egui::Context) {
+        let (fullscreen, screenshot) =
+            ctx.input(|input| (input.key_pressed(Key::F11), input.key_pressed(Key::F12)));
+
+        if fullscreen {
+            display::toggle_fullscreen(ctx);
+        }
+
+        if screenshot {
+            self.quick_screenshot();
+        }
+    }
+
+    /// What is shown in the window, including the debug views if enabled
+    pub fn screenshot_image(&self) -> ColorImage {
+        self.composite
+            .clone()
+            .unwrap_or_else(|| ColorImage::from(self.frames.current()))
+    }
+
+    fn save_screenshot(&self, dir: &Path) -> Result<PathBuf, png::EncodingError> {
+        fs::create_dir_all(dir)?;
+
+        let path = capture::next_free_path(dir, "ruboy");
+        capture::export_png(&self.screenshot_image(), &path)?;
+
+        Ok(path)
+    }
+
+    /// Saves a screenshot to the screenshot directory, without asking where
+    pub fn quick_screenshot(&mut self) {
+        let result = self.save_screenshot(&capture::screenshot_dir());
+
+        match result {
+            Ok(path) => log::info!("Saved screenshot to {}", path.display()),
+            Err(e) => self
+                .notifications
+                .push_warning(format!("Could not save screenshot: {}", e)),
+        }
+    }
+
+    fn update_control_hotkeys(&mut self, ctx: &egui::Context) {
+        let (pause, reset) =
+            ctx.input(|input| (input.key_pressed(Key::P), input.key_pressed(Key::F5)));
+
+        if pause {
+            self.emulator
+                .send(Command::SetPaused(!self.emulator.paused()));
+        }
+
+        if reset {
+            self.emulator.send(Command::Reset);
+        }
+    }
+
+    fn update_speed_hotkeys(&mut self, ctx: &egui::Context) {
+        let (turbo_pressed, turbo_released, slower, faster, rewind) = ctx.input(|input| {
+            (
+                input.key_pressed(Key::Tab),
+                input.key_released(Key::Tab),
+                input.key_pressed(Key::Minus),
+                input.key_pressed(Key::Equals),
+                input.key_down(Key::R),
+            )
+        });
+
+        self.emulator.set_rewinding(rewind);
+
+        if turbo_pressed {
+            self.emulator.set_turbo(true);
+        } else if turbo_released {
+            self.emulator.set_turbo(false);
+        }
+
+        if slower {
+            self.emulator.set_speed(self.emulator.speed() / 2.0);
+        }
+
+        if faster {
+            self.emulator.set_speed(self.emulator.speed() * 2.0);
+        }
     }
 }
 
 impl eframe::App for RuboyApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         self.try_initialize(ctx);
-
-        if self.ruboy.is_some() {
-            self.step_emulator(ctx);
-        }
+        self.handle_emulator_events();
+        self.handle_dropped_files(ctx);
+        self.update_input(ctx);
+        self.update_control_hotkeys(ctx);
+        self.update_speed_hotkeys(ctx);
+        self.update_window_hotkeys(ctx);
+        self.update_texture_from_framedata();
+        self.update_composite(ctx);
 
         // Actual UI code now
         CentralPanel::default().show(ctx, |ui| {
             draw_menu(self, ui);
             ui.separator();
 
-            if self.ruboy.is_none() {
-                ui.label("No ROM selected. Select a ROM with 'ROM -> Open'");
+            self.notifications.draw(ui);
+
+            if self.emulator.lock().is_none() {
+                ui.label("No ROM selected. Select a ROM with 'ROM -> Open', or drop one here");
             }
 
             self.show_gameboy_frame(ui);
         });
 
+        draw_windows(self, ctx);
+        self.show_partner_window(ctx);
+        self.notifications.draw_dialogs(ctx);
+        Self::draw_drop_overlay(ctx);
+        self.save_config(ctx);
+
         ctx.request_repaint();
     }
 }
 
-fn main() {
+/// Sets up the network link asked for on the command line, if any
+fn net_link(args: &CLIArgs) -> Option<impl FnOnce() -> io::Result<NetLink> + Send + 'static> {
+    let sync = if args.link_speculative {
+        LinkSync::Speculative
+    } else {
+        LinkSync::PerTransfer
+    };
+
+    let listen = args.link_listen.clone();
+    let remote = args.link_connect.clone();
+    let local = args.link_bind.clone();
+
+    if listen.is_none() && remote.is_none() {
+        return None;
+    }
+
+    Some(move || {
+        let link = match (listen, remote, local) {
+            (Some(addr), _, _) => NetLink::new(TcpTransport::listen(addr)?, sync),
+            (None, Some(remote), Some(local)) => {
+                NetLink::new(UdpTransport::new(local, remote)?, sync)
+            }
+            (None, Some(addr), None) => NetLink::new(TcpTransport::connect(addr)?, sync),
+            (None, None, _) => unreachable!("Checked above"),
+        };
+
+        Ok(link)
+    })
+}
+
+/// Runs the built-in consistency checks and reports the results
+fn self_test() -> ExitCode {
+    let results = ruboy_lib::selftest::run_all();
+
+    for result in &results {
+        println!("{}", result);
+    }
+
+    if results.iter().all(|result| result.passed()) {
+        println!("All checks passed");
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn main() -> ExitCode {
     let args = CLIArgs::parse();
 
+    if args.self_test {
+        return self_test();
+    }
+
     let logconfig = simplelog::ConfigBuilder::new()
         .set_thread_mode(simplelog::ThreadLogMode::Both)
         .set_time_format_rfc3339()
@@ -199,16 +609,32 @@ fn main() {
     )
     .expect("Could not initialize logger");
 
+    for &(subsystem, level) in &args.log_filter {
+        ruboy_lib::logging::set_level(subsystem, level);
+    }
+
     log::info!("Starting Ruboy Emulator Frontend");
 
+    let config_path = args.config.clone().or_else(Config::default_path);
+    let config = config_path
+        .as_deref()
+        .map_or(Ok(Config::default()), Config::load);
+
+    let window_size = config
+        .as_ref()
+        .map_or_else(|_| Config::default().window_size(), Config::window_size);
+
     let options = NativeOptions {
+        viewport: ViewportBuilder::default().with_inner_size(window_size),
         ..Default::default()
     };
 
     eframe::run_native(
         "Ruboy",
         options,
-        Box::new(|_| Ok(Box::new(RuboyApp::new(args)))),
+        Box::new(|_| Ok(Box::new(RuboyApp::new(args, config_path, config)))),
     )
     .expect("Could not initialize window");
+
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,183 @@
+use eframe::egui::{self, Color32, Context, Pos2, Sense, Shape, Stroke, Ui, Vec2};
+use ruboy_lib::debug::ChannelState;
+use ruboy_lib::AudioChannel;
+
+use crate::{AppRuboy, RuboyApp};
+
+/// Samples shown in the scope, about 20ms at common output rates
+const SCOPE_LEN: usize = 1024;
+const SCOPE_SIZE: Vec2 = Vec2::new(512.0, 128.0);
+
+const LEFT_COLOR: Color32 = Color32::from_rgb(80, 160, 230);
+const RIGHT_COLOR: Color32 = Color32::from_rgb(230, 120, 80);
+
+#[derive(Debug, Default)]
+pub struct ApuMenuData {
+    window_open: bool,
+
+    /// Whether the scope was enabled for the shown instance, so it can be
+    /// disabled again when the window closes
+    scope_enabled: bool,
+}
+
+pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
+    if ui
+        .checkbox(&mut app.menu_data.apu.window_open, "Show APU viewer")
+        .clicked()
+    {
+        ui.close_menu();
+    }
+}
+
+pub fn draw_window(app: &mut RuboyApp, ctx: &Context) {
+    let data = &mut app.menu_data.apu;
+
+    if !data.window_open {
+        if data.scope_enabled {
+            if let Some(ruboy) = app.emulator.lock().as_mut() {
+                ruboy.disable_audio_scope();
+            }
+
+            data.scope_enabled = false;
+        }
+
+        return;
+    }
+
+    let mut open = true;
+
+    egui::Window::new("APU viewer")
+        .open(&mut open)
+        .default_width(560.0)
+        .show(ctx, |ui| {
+            let mut guard = app.emulator.lock();
+
+            let Some(ruboy) = guard.as_mut() else {
+                ui.label("No ROM loaded");
+                return;
+            };
+
+            // Loading another ROM starts a new instance without the scope
+            if ruboy.audio_scope().is_none() {
+                ruboy.enable_audio_scope(SCOPE_LEN);
+                data.scope_enabled = true;
+            }
+
+            draw_channels(ruboy, ui);
+
+            ui.separator();
+
+            draw_scope(ruboy, ui);
+        });
+
+    data.window_open = open;
+}
+
+fn draw_channels(ruboy: &AppRuboy, ui: &mut Ui) {
+    egui::Grid::new("apu_channels")
+        .striped(true)
+        .show(ui, |ui| {
+            for header in [
+                "Channel", "On", "DAC", "Freq", "Hz", "Duty", "Volume", "Envelope", "Length",
+                "Out", "L/R",
+            ] {
+                ui.strong(header);
+            }
+            ui.end_row();
+
+            for channel in AudioChannel::ALL {
+                let state = ruboy.audio_channel(channel);
+
+                ui.label(channel.to_string());
+                draw_state(&state, ui);
+                ui.end_row();
+            }
+        });
+}
+
+fn draw_state(state: &ChannelState, ui: &mut Ui) {
+    let yes_no = |on: bool| if on { "Yes" } else { "No" };
+
+    ui.monospace(yes_no(state.enabled));
+    ui.monospace(yes_no(state.dac_enabled));
+    ui.monospace(format!("{:03X}", state.freq));
+    ui.monospace(format!("{:8.1}", state.hz));
+
+    match state.duty {
+        Some(duty) => ui.monospace(format!("{:4.1}%", duty * 100.0)),
+        None => ui.monospace("-"),
+    };
+
+    ui.monospace(format!("{:2}", state.volume));
+
+    match state.envelope {
+        Some(env) if env.period == 0 => ui.monospace(format!("{:2} stopped", env.initial)),
+        Some(env) => ui.monospace(format!(
+            "{:2} {} every {}",
+            env.initial,
+            if env.increase { "up" } else { "down" },
+            env.period
+        )),
+        None => ui.monospace("-"),
+    };
+
+    match state.length {
+        Some(length) => ui.monospace(format!("{:3}", length)),
+        None => ui.monospace("Off"),
+    };
+
+    ui.monospace(format!("{:2}", state.output));
+
+    let panning = match (state.left, state.right) {
+        (true, true) => "L R",
+        (true, false) => "L",
+        (false, true) => "R",
+        (false, false) => "-",
+    };
+
+    if state.muted {
+        ui.monospace(format!("{} (muted)", panning));
+    } else {
+        ui.monospace(panning);
+    }
+}
+
+/// Draws the last mixed samples, the left and right side on top of each
+/// other
+fn draw_scope(ruboy: &AppRuboy, ui: &mut Ui) {
+    if ruboy.audio_sample_rate().is_none() {
+        ui.label("Enable audio to see the output");
+        return;
+    }
+
+    let samples: Vec<[f32; 2]> = ruboy.audio_scope().into_iter().flatten().collect();
+
+    let (rect, _) = ui.allocate_exact_size(SCOPE_SIZE, Sense::hover());
+    let painter = ui.painter_at(rect);
+
+    painter.rect_filled(rect, 0.0, Color32::BLACK);
+    painter.hline(
+        rect.x_range(),
+        rect.center().y,
+        Stroke::new(1.0, Color32::DARK_GRAY),
+    );
+
+    if samples.len() < 2 {
+        return;
+    }
+
+    for (side, color) in [LEFT_COLOR, RIGHT_COLOR].into_iter().enumerate() {
+        let points = samples
+            .iter()
+            .enumerate()
+            .map(|(i, sample)| {
+                let x = rect.left() + rect.width() * i as f32 / (samples.len() - 1) as f32;
+                let y = rect.center().y - sample[side].clamp(-1.0, 1.0) * rect.height() / 2.0;
+
+                Pos2::new(x, y)
+            })
+            .collect();
+
+        painter.add(Shape::line(points, Stroke::new(1.0, color)));
+    }
+}
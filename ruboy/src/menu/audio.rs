@@ -1,8 +1,93 @@
+#[cfg(feature = "audio")]
+use eframe::egui::Slider;
 use eframe::egui::Ui;
+#[cfg(feature = "audio")]
+use ruboy_lib::AudioChannel;
 
+#[cfg(feature = "audio")]
+use crate::audio::AudioOutput;
+#[cfg(feature = "audio")]
+use crate::emulator::{AudioSettings, Command};
 use crate::RuboyApp;
 
+#[cfg(feature = "audio")]
+#[derive(Debug, Default)]
+pub struct AudioMenuData {
+    muted: [bool; AudioChannel::ALL.len()],
+}
+
+#[cfg(not(feature = "audio"))]
 #[derive(Debug, Default)]
 pub struct AudioMenuData {}
 
-pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {}
+#[cfg(feature = "audio")]
+pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
+    let mut enabled = app.audio.is_some();
+
+    if ui.checkbox(&mut enabled, "Enable audio").changed() {
+        app.audio = if enabled {
+            AudioOutput::new(app.config.volume)
+                .inspect_err(|e| log::error!("Could not start audio: {}", e))
+                .ok()
+        } else {
+            None
+        };
+
+        apply_audio_settings(app);
+    }
+
+    let data = &mut app.menu_data.audio;
+    let mut mutes_changed = false;
+    let mut pacing_changed = false;
+
+    ui.add_enabled_ui(app.audio.is_some(), |ui| {
+        if ui
+            .add(Slider::new(&mut app.config.volume, 0.0..=1.0).text("Volume"))
+            .changed()
+        {
+            app.config_dirty = true;
+
+            if let Some(audio) = &app.audio {
+                audio.set_volume(app.config.volume);
+            }
+        }
+
+        if ui
+            .checkbox(&mut app.config.audio_pacing, "Sync to audio")
+            .on_hover_text(
+                "Runs the game as fast as the audio plays, instead of by the clock. \
+                 Avoids crackles, but may tear the picture.",
+            )
+            .changed()
+        {
+            app.config_dirty = true;
+            pacing_changed = true;
+        }
+
+        ui.separator();
+
+        for (channel, muted) in AudioChannel::ALL.into_iter().zip(data.muted.iter_mut()) {
+            mutes_changed |= ui.checkbox(muted, format!("Mute {}", channel)).changed();
+        }
+    });
+
+    if mutes_changed || pacing_changed {
+        apply_audio_settings(app);
+    }
+}
+
+#[cfg(not(feature = "audio"))]
+pub fn draw_menu(_app: &mut RuboyApp, ui: &mut Ui) {
+    ui.label("Built without audio support");
+}
+
+/// Sends the current output, if any, and the channel mutes to the
+/// emulation thread
+#[cfg(feature = "audio")]
+pub fn apply_audio_settings(app: &RuboyApp) {
+    app.emulator.send(Command::Audio(AudioSettings {
+        sink: app.audio.as_ref().map(|audio| audio.sink()),
+        muted: app.menu_data.audio.muted,
+        paced: app.config.audio_pacing,
+    }));
+}
@@ -0,0 +1,176 @@
+use eframe::egui::{self, Context, Event, Grid, Key, Ui};
+
+use crate::input::config::InputConfig;
+use crate::input::GbButton;
+use crate::RuboyApp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Device {
+    Keyboard,
+    Gamepad,
+}
+
+#[derive(Debug, Default)]
+pub struct ControlsMenuData {
+    window_open: bool,
+
+    /// The binding waiting for the next key or gamepad button press
+    rebinding: Option<(GbButton, Device)>,
+}
+
+impl ControlsMenuData {
+    /// Whether key presses should go to the rebinding instead of the game
+    pub fn capturing_input(&self) -> bool {
+        self.rebinding.is_some()
+    }
+}
+
+pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
+    if ui
+        .checkbox(&mut app.menu_data.controls.window_open, "Show controls")
+        .clicked()
+    {
+        ui.close_menu();
+    }
+}
+
+/// The first key pressed this frame
+fn pressed_key(ctx: &Context) -> Option<Key> {
+    ctx.input(|input| {
+        input.events.iter().find_map(|event| match event {
+            Event::Key {
+                key,
+                pressed: true,
+                repeat: false,
+                ..
+            } => Some(*key),
+            _ => None,
+        })
+    })
+}
+
+/// Finishes a pending rebinding if the user pressed something. Returns
+/// whether the config changed.
+fn capture_binding(app: &mut RuboyApp, ctx: &Context) -> bool {
+    let Some((button, device)) = app.menu_data.controls.rebinding else {
+        return false;
+    };
+
+    let key = pressed_key(ctx);
+
+    if key == Some(Key::Escape) {
+        app.menu_data.controls.rebinding = None;
+        return false;
+    }
+
+    match device {
+        Device::Keyboard => {
+            let Some(key) = key else {
+                return false;
+            };
+
+            app.config.input.keys.set(button, key);
+        }
+        Device::Gamepad => {
+            #[cfg(feature = "gamepad")]
+            let pressed = app
+                .gamepads
+                .as_mut()
+                .and_then(|pads| pads.take_last_pressed());
+
+            #[cfg(not(feature = "gamepad"))]
+            let pressed = None;
+
+            let Some(pad_button) = pressed else {
+                return false;
+            };
+
+            app.config.input.pad.set(button, pad_button);
+        }
+    }
+
+    app.menu_data.controls.rebinding = None;
+    true
+}
+
+fn binding_button(app: &mut RuboyApp, ui: &mut Ui, button: GbButton, device: Device) {
+    let waiting = app.menu_data.controls.rebinding == Some((button, device));
+
+    let text = if waiting {
+        "Press a button...".to_string()
+    } else {
+        match device {
+            Device::Keyboard => app.config.input.keys.get(button).name().to_string(),
+            Device::Gamepad => app.config.input.pad.get(button).to_string(),
+        }
+    };
+
+    let enabled = match device {
+        Device::Keyboard => true,
+        #[cfg(feature = "gamepad")]
+        Device::Gamepad => app.gamepads.is_some(),
+        #[cfg(not(feature = "gamepad"))]
+        Device::Gamepad => false,
+    };
+
+    let response = ui
+        .add_enabled(enabled, egui::Button::new(text).selected(waiting))
+        .on_disabled_hover_text("Gamepads are not available");
+
+    if response.clicked() {
+        #[cfg(feature = "gamepad")]
+        if let Some(pads) = app.gamepads.as_mut() {
+            // Don't bind a button pressed before the rebinding started
+            pads.take_last_pressed();
+        }
+
+        app.menu_data.controls.rebinding = Some((button, device));
+    }
+}
+
+pub fn draw_window(app: &mut RuboyApp, ctx: &Context) {
+    let mut open = app.menu_data.controls.window_open;
+
+    if !open {
+        app.menu_data.controls.rebinding = None;
+        return;
+    }
+
+    let mut changed = capture_binding(app, ctx);
+
+    egui::Window::new("Controls")
+        .open(&mut open)
+        .show(ctx, |ui| {
+            Grid::new("controls_grid").striped(true).show(ui, |ui| {
+                ui.strong("Button");
+                ui.strong("Keyboard");
+                ui.strong("Gamepad");
+                ui.end_row();
+
+                for button in GbButton::ALL {
+                    ui.label(button.to_string());
+                    binding_button(app, ui, button, Device::Keyboard);
+                    binding_button(app, ui, button, Device::Gamepad);
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+
+            if app.menu_data.controls.capturing_input() {
+                ui.label("Press Escape to cancel");
+            }
+
+            if ui.button("Reset to defaults").clicked() {
+                app.config.input = InputConfig::default();
+                app.menu_data.controls.rebinding = None;
+                changed = true;
+            }
+        });
+
+    if changed {
+        app.config_dirty = true;
+    }
+
+    app.menu_data.controls.window_open = open;
+}
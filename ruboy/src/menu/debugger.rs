@@ -1,8 +1,539 @@
-use eframe::egui::Ui;
+use eframe::egui::{self, Color32, Context, RichText, ScrollArea, TextEdit, Ui};
+use rfd::FileDialog;
+use ruboy_lib::debug::{CallKind, Debugger, Expr, Profiler};
+use ruboy_lib::isa::decoder::{decode, DecoderReadable};
+use ruboy_lib::isa::display::{DisplayableInstruction, FormatOpts, HardwareRegisters};
+use ruboy_lib::isa::Instruction;
 
-use crate::RuboyApp;
+use super::parse_addr;
+use crate::{AppRuboy, RuboyApp};
+
+/// Amount of previously executed instructions shown above PC
+const HISTORY_LEN: usize = 8;
+
+/// Amount of instructions shown from PC onwards
+const LOOKAHEAD_LEN: usize = 24;
+
+/// Amount of the most expensive instructions shown while profiling
+const HOTTEST_LEN: usize = 8;
+
+const PC_COLOR: Color32 = Color32::from_rgb(255, 210, 80);
+const BREAKPOINT_COLOR: Color32 = Color32::from_rgb(230, 80, 80);
 
 #[derive(Debug, Default)]
-pub struct DebuggerMenuData {}
+pub struct DebuggerMenuData {
+    window_open: bool,
+    new_breakpoint: String,
+    new_watch: String,
+}
+
+/// Reads memory for the disassembler, without side effects
+struct PeekReader<'a>(&'a AppRuboy);
+
+impl DecoderReadable for PeekReader<'_> {
+    type Err = ();
+
+    fn read_at(&self, addr: u16) -> Result<u8, Self::Err> {
+        self.0.peek(addr).map_err(|_| ())
+    }
+}
+
+fn format_instr(instr: Instruction) -> String {
+    DisplayableInstruction::from(instr)
+        .with_format(&FormatOpts::rgdbs().with_symbols(HardwareRegisters))
+}
+
+pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
+    if ui
+        .checkbox(&mut app.menu_data.debugger.window_open, "Show debugger")
+        .clicked()
+    {
+        ui.close_menu();
+    }
+}
+
+pub fn draw_window(app: &mut RuboyApp, ctx: &Context) {
+    let mut open = app.menu_data.debugger.window_open;
+    let mut save_profile = None;
+
+    egui::Window::new("Debugger")
+        .open(&mut open)
+        .default_width(640.0)
+        .show(ctx, |ui| {
+            let data = &mut app.menu_data.debugger;
+
+            let mut guard = app.emulator.lock();
+
+            let Some(ruboy) = guard.as_mut() else {
+                ui.label("No ROM loaded");
+                return;
+            };
+
+            if ruboy.debugger().is_none() {
+                if ui.button("Attach debugger").clicked() {
+                    ruboy.attach_debugger(Debugger::new());
+                    ruboy.enable_trace(HISTORY_LEN);
+                    ruboy.enable_call_stack();
+                }
+
+                return;
+            }
+
+            draw_controls(ruboy, ui);
+            ui.separator();
+
+            ui.columns(2, |cols| {
+                draw_disassembly(ruboy, &mut cols[0]);
+
+                draw_registers(ruboy, &mut cols[1]);
+                cols[1].separator();
+                draw_breakpoints(ruboy, data, &mut cols[1]);
+                cols[1].separator();
+                draw_watches(ruboy, data, &mut cols[1]);
+                cols[1].separator();
+                draw_call_stack(ruboy, &mut cols[1]);
+                cols[1].separator();
+                save_profile = draw_profiler(ruboy, &mut cols[1]);
+            });
+        });
+
+    app.menu_data.debugger.window_open = open;
+
+    // Asked for after letting go of the emulator, so it keeps running while
+    // the dialog is open
+    if let Some(folded) = save_profile {
+        if let Some(path) = FileDialog::new()
+            .set_title("Save profile")
+            .add_filter("Folded stacks", &["folded", "txt"])
+            .set_file_name("ruboy.folded")
+            .save_file()
+        {
+            match std::fs::write(&path, folded) {
+                Ok(()) => log::info!("Saved profile to {}", path.display()),
+                Err(e) => log::error!("Could not save profile to {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+fn draw_controls(ruboy: &mut AppRuboy, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        let paused = ruboy.debugger().is_some_and(|d| d.paused());
+
+        if paused {
+            if ui.button("Run").clicked() {
+                ruboy.debugger_mut().unwrap().resume();
+            }
+        } else if ui.button("Pause").clicked() {
+            ruboy.debugger_mut().unwrap().pause();
+        }
+
+        if ui.button("Step").clicked() {
+            if let Err(e) = ruboy.step_instruction() {
+                log::error!("Error while stepping: {}", e);
+            }
+        }
+
+        if ui.button("Step frame").clicked() {
+            if let Err(e) = ruboy.step_frame() {
+                log::error!("Error while stepping: {}", e);
+            }
+        }
+
+        if ui.button("Detach").clicked() {
+            ruboy.detach_debugger();
+            ruboy.disable_trace();
+            ruboy.disable_call_stack();
+            return;
+        }
+
+        match ruboy.debugger().and_then(|d| d.stop_reason()) {
+            Some(reason) => ui.label(reason.to_string()),
+            None => ui.label("Running"),
+        };
+    });
+}
+
+fn draw_disassembly(ruboy: &mut AppRuboy, ui: &mut Ui) {
+    ui.heading("Disassembly");
+
+    let pc = ruboy.registers().pc;
+
+    let history: Vec<(u16, Instruction)> = ruboy
+        .trace()
+        .map(|trace| {
+            trace
+                .entries()
+                .map(|e| (e.pc, e.instruction))
+                .filter(|(addr, _)| *addr != pc)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut lookahead: Vec<(u16, Instruction)> = Vec::with_capacity(LOOKAHEAD_LEN);
+    let reader = PeekReader(ruboy);
+    let mut addr = pc;
+
+    for _ in 0..LOOKAHEAD_LEN {
+        let Ok(instr) = decode(&reader, addr) else {
+            break;
+        };
+
+        lookahead.push((addr, instr));
+        addr = addr.wrapping_add(instr.len().max(1) as u16);
+    }
+
+    let mut toggle = None;
+
+    ScrollArea::vertical()
+        .id_salt("disassembly")
+        .max_height(400.0)
+        .show(ui, |ui| {
+            for (addr, instr) in history.iter().chain(lookahead.iter()) {
+                let bank = ruboy.bank_at(*addr);
+                let has_bp = ruboy.debugger().is_some_and(|d| {
+                    d.breakpoints().any(|bp| bp == *addr)
+                        || d.banked_breakpoints()
+                            .any(|bp| Some(bp.0) == bank && bp.1 == *addr)
+                });
+
+                let marker = if *addr == pc { ">" } else { " " };
+                let bp = if has_bp { "●" } else { " " };
+
+                let mut text = RichText::new(format!(
+                    "{}{} {:04X}  {}",
+                    bp,
+                    marker,
+                    addr,
+                    format_instr(*instr)
+                ))
+                .monospace();
+
+                if *addr == pc {
+                    text = text.color(PC_COLOR);
+                } else if has_bp {
+                    text = text.color(BREAKPOINT_COLOR);
+                }
+
+                if ui
+                    .selectable_label(false, text)
+                    .on_hover_text("Click to toggle breakpoint")
+                    .clicked()
+                {
+                    toggle = Some((*addr, bank, has_bp));
+                }
+            }
+        });
+
+    if let (Some((addr, bank, has_bp)), Some(debugger)) = (toggle, ruboy.debugger_mut()) {
+        if has_bp {
+            debugger.remove_breakpoint(addr);
+
+            if let Some(bank) = bank {
+                debugger.remove_banked_breakpoint(bank, addr);
+            }
+        } else {
+            debugger.add_breakpoint(addr);
+        }
+    }
+}
+
+fn draw_registers(ruboy: &AppRuboy, ui: &mut Ui) {
+    ui.heading("Registers");
+
+    let regs = ruboy.registers();
+
+    egui::Grid::new("registers").striped(true).show(ui, |ui| {
+        let pairs = [
+            ("AF", regs.a, regs.f),
+            ("BC", regs.b, regs.c),
+            ("DE", regs.d, regs.e),
+            ("HL", regs.h, regs.l),
+        ];
+
+        for (name, high, low) in pairs {
+            ui.monospace(name);
+            ui.monospace(format!("{:02X}{:02X}", high, low));
+            ui.end_row();
+        }
+
+        ui.monospace("SP");
+        ui.monospace(format!("{:04X}", regs.sp));
+        ui.end_row();
+
+        ui.monospace("PC");
+        ui.monospace(format!("{:04X}", regs.pc));
+        ui.end_row();
+
+        ui.monospace("Bank");
+        ui.monospace(format!("{:02X}", ruboy.current_bank()));
+        ui.end_row();
+
+        ui.monospace("IME");
+        ui.monospace(if regs.ime { "1" } else { "0" });
+        ui.end_row();
+
+        let flag = |mask: u8, name: &'static str| if regs.f & mask != 0 { name } else { "-" };
+
+        ui.monospace("Flags");
+        ui.monospace(format!(
+            "{}{}{}{}",
+            flag(0b10000000, "Z"),
+            flag(0b1000000, "N"),
+            flag(0b100000, "H"),
+            flag(0b10000, "C")
+        ));
+        ui.end_row();
+    });
+}
+
+fn draw_breakpoints(ruboy: &mut AppRuboy, data: &mut DebuggerMenuData, ui: &mut Ui) {
+    ui.heading("Breakpoints");
+
+    let Some(debugger) = ruboy.debugger_mut() else {
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.add(
+            TextEdit::singleline(&mut data.new_breakpoint)
+                .hint_text("[Bank:]Address [if condition]")
+                .desired_width(200.0),
+        );
+
+        if ui.button("Add").clicked() {
+            match parse_breakpoint(&data.new_breakpoint) {
+                Ok(NewBreakpoint::Plain(addr)) => debugger.add_breakpoint(addr),
+                Ok(NewBreakpoint::Banked(bank, addr)) => debugger.add_banked_breakpoint(bank, addr),
+                Ok(NewBreakpoint::Conditional(addr, condition)) => {
+                    debugger.add_conditional_breakpoint(addr, condition)
+                }
+                Err(e) => {
+                    log::warn!("Invalid breakpoint \"{}\": {}", data.new_breakpoint, e);
+                    return;
+                }
+            }
+
+            data.new_breakpoint.clear();
+        }
+    });
+
+    let mut to_remove = None;
+
+    for addr in debugger.breakpoints() {
+        ui.horizontal(|ui| {
+            ui.monospace(format!("{:04X}", addr));
+
+            if ui.small_button("Remove").clicked() {
+                to_remove = Some(Removal::Plain(addr));
+            }
+        });
+    }
+
+    for (bank, addr) in debugger.banked_breakpoints() {
+        ui.horizontal(|ui| {
+            ui.monospace(format!("{:02X}:{:04X}", bank, addr));
+
+            if ui.small_button("Remove").clicked() {
+                to_remove = Some(Removal::Banked(bank, addr));
+            }
+        });
+    }
+
+    for (index, bp) in debugger.conditional_breakpoints().iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.monospace(format!("{:04X} if {}", bp.addr, bp.condition));
+
+            if ui.small_button("Remove").clicked() {
+                to_remove = Some(Removal::Conditional(index));
+            }
+        });
+    }
+
+    match to_remove {
+        Some(Removal::Plain(addr)) => {
+            debugger.remove_breakpoint(addr);
+        }
+        Some(Removal::Banked(bank, addr)) => {
+            debugger.remove_banked_breakpoint(bank, addr);
+        }
+        Some(Removal::Conditional(index)) => {
+            debugger.remove_conditional_breakpoint(index);
+        }
+        None => (),
+    }
+}
+
+/// A breakpoint as entered in the debugger window
+enum NewBreakpoint {
+    Plain(u16),
+    Banked(u16, u16),
+    Conditional(u16, Expr),
+}
+
+/// A breakpoint the user wants gone
+enum Removal {
+    Plain(u16),
+    Banked(u16, u16),
+    Conditional(usize),
+}
+
+/// Parses a breakpoint address, optionally prefixed with a hexadecimal ROM
+/// bank and a colon, like `02:4100`, or followed by a condition, like
+/// `4100 if a == 0x3C`
+fn parse_breakpoint(text: &str) -> Result<NewBreakpoint, String> {
+    let (location, condition) = match text.split_once(" if ") {
+        Some((location, condition)) => (location, Some(condition)),
+        None => (text, None),
+    };
+
+    let (bank, addr) = match location.split_once(':') {
+        Some((bank, addr)) => {
+            let bank = u16::from_str_radix(bank.trim(), 16)
+                .map_err(|_| format!("invalid bank {}", bank.trim()))?;
+
+            (Some(bank), addr)
+        }
+        None => (None, location),
+    };
+
+    let addr = parse_addr(addr).ok_or_else(|| format!("invalid address {}", addr.trim()))?;
+
+    match (bank, condition) {
+        (None, None) => Ok(NewBreakpoint::Plain(addr)),
+        (Some(bank), None) => Ok(NewBreakpoint::Banked(bank, addr)),
+        (None, Some(condition)) => Expr::parse(condition)
+            .map(|condition| NewBreakpoint::Conditional(addr, condition))
+            .map_err(|e| e.to_string()),
+        (Some(_), Some(_)) => Err("breakpoints can't have both a bank and a condition".to_string()),
+    }
+}
+
+fn draw_watches(ruboy: &mut AppRuboy, data: &mut DebuggerMenuData, ui: &mut Ui) {
+    ui.heading("Watch");
+
+    // Evaluated on copies, so the ones in the debugger still see every
+    // instruction for `changed`
+    let values: Vec<(String, u16)> = ruboy
+        .debugger()
+        .map(|d| d.watch_expressions())
+        .unwrap_or_default()
+        .iter()
+        .map(|expr| (expr.to_string(), expr.clone().eval(&**ruboy)))
+        .collect();
+
+    let Some(debugger) = ruboy.debugger_mut() else {
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.add(
+            TextEdit::singleline(&mut data.new_watch)
+                .hint_text("Expression")
+                .desired_width(200.0),
+        );
+
+        if ui
+            .button("Add")
+            .on_hover_text("Stops as soon as the expression is not 0")
+            .clicked()
+        {
+            match Expr::parse(&data.new_watch) {
+                Ok(expr) => {
+                    debugger.add_watch_expression(expr);
+                    data.new_watch.clear();
+                }
+                Err(e) => log::warn!("Invalid watch expression \"{}\": {}", data.new_watch, e),
+            }
+        }
+    });
+
+    let mut to_remove = None;
+
+    for (index, (expr, value)) in values.iter().enumerate() {
+        ui.horizontal(|ui| {
+            ui.monospace(format!("{} = {:04X}", expr, value));
+
+            if ui.small_button("Remove").clicked() {
+                to_remove = Some(index);
+            }
+        });
+    }
+
+    if let Some(index) = to_remove {
+        debugger.remove_watch_expression(index);
+    }
+}
+
+fn draw_call_stack(ruboy: &AppRuboy, ui: &mut Ui) {
+    ui.heading("Call stack");
+
+    let Some(calls) = ruboy.call_stack() else {
+        return;
+    };
+
+    if calls.depth() == 0 {
+        ui.label("No calls since attaching");
+        return;
+    }
+
+    ScrollArea::vertical()
+        .id_salt("call_stack")
+        .max_height(150.0)
+        .show(ui, |ui| {
+            for frame in calls.frames().iter().rev() {
+                let kind = match frame.kind {
+                    CallKind::Call => "CALL",
+                    CallKind::Rst => "RST",
+                    CallKind::Interrupt => "INT",
+                };
+
+                ui.monospace(format!(
+                    "{:<4} {}  ret {:04X}",
+                    kind, frame.target, frame.return_addr
+                ));
+            }
+        });
+}
+
+/// Returns the profile in the folded stack format when asked to save it
+fn draw_profiler(ruboy: &mut AppRuboy, ui: &mut Ui) -> Option<String> {
+    ui.heading("Profiler");
+
+    let mut save = None;
+
+    ui.horizontal(|ui| match ruboy.profiler() {
+        Some(profiler) => {
+            if ui.button("Save...").clicked() {
+                let mut folded = String::new();
+                profiler
+                    .write_folded(&mut folded)
+                    .expect("Writing to a String does not fail");
+                save = Some(folded);
+            }
+
+            if ui.button("Stop").clicked() {
+                ruboy.disable_profiler();
+            }
+        }
+        None => {
+            if ui.button("Start").clicked() {
+                ruboy.enable_profiler(Profiler::DEFAULT_INTERVAL);
+            }
+        }
+    });
+
+    if let Some(profiler) = ruboy.profiler() {
+        let total = profiler.total_cycles().max(1);
+
+        for (location, cycles) in profiler.by_address().into_iter().take(HOTTEST_LEN) {
+            ui.monospace(format!(
+                "{}  {:5.1}%",
+                location,
+                cycles as f64 * 100.0 / total as f64
+            ));
+        }
+    }
 
-pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {}
+    save
+}
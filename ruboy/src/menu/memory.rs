@@ -0,0 +1,211 @@
+use eframe::egui::{self, Context, RichText, ScrollArea, TextEdit, Ui};
+
+use super::parse_addr;
+use crate::{AppRuboy, RuboyApp};
+
+const ROW_LEN: u16 = 16;
+const NUM_ROWS: usize = 0x10000 / ROW_LEN as usize;
+
+#[derive(Debug)]
+pub struct MemoryMenuData {
+    window_open: bool,
+    goto: String,
+    scroll_to: Option<u16>,
+
+    /// Re-read memory every frame. When disabled, `snapshot` is shown instead
+    live: bool,
+    snapshot: Vec<Option<Vec<u8>>>,
+
+    selected: Option<u16>,
+    edit: String,
+}
+
+impl Default for MemoryMenuData {
+    fn default() -> Self {
+        Self {
+            window_open: false,
+            goto: "C000".to_string(),
+            scroll_to: None,
+            live: true,
+            snapshot: Vec::new(),
+            selected: None,
+            edit: String::new(),
+        }
+    }
+}
+
+/// Reads a single row, or None if any address in it could not be read
+fn read_row(ruboy: &AppRuboy, base: u16) -> Option<Vec<u8>> {
+    ruboy.read_memory(base..=base + (ROW_LEN - 1)).ok()
+}
+
+fn take_snapshot(ruboy: &AppRuboy) -> Vec<Option<Vec<u8>>> {
+    (0..NUM_ROWS)
+        .map(|row| read_row(ruboy, row as u16 * ROW_LEN))
+        .collect()
+}
+
+fn to_ascii(byte: u8) -> char {
+    if byte.is_ascii_graphic() || byte == b' ' {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+/// Parses whitespace separated hexadecimal bytes
+fn parse_bytes(text: &str) -> Option<Vec<u8>> {
+    text.split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect()
+}
+
+pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
+    if ui
+        .checkbox(&mut app.menu_data.memory.window_open, "Show memory editor")
+        .clicked()
+    {
+        ui.close_menu();
+    }
+}
+
+pub fn draw_window(app: &mut RuboyApp, ctx: &Context) {
+    let mut open = app.menu_data.memory.window_open;
+
+    egui::Window::new("Memory")
+        .open(&mut open)
+        .default_width(720.0)
+        .show(ctx, |ui| {
+            let data = &mut app.menu_data.memory;
+
+            let mut guard = app.emulator.lock();
+
+            let Some(ruboy) = guard.as_mut() else {
+                ui.label("No ROM loaded");
+                return;
+            };
+
+            draw_controls(ruboy, data, ui);
+            ui.separator();
+            draw_rows(ruboy, data, ui);
+            ui.separator();
+            draw_editor(ruboy, data, ui);
+        });
+
+    app.menu_data.memory.window_open = open;
+}
+
+fn draw_controls(ruboy: &AppRuboy, data: &mut MemoryMenuData, ui: &mut Ui) {
+    ui.horizontal(|ui| {
+        ui.add(TextEdit::singleline(&mut data.goto).desired_width(80.0));
+
+        if ui.button("Go to").clicked() {
+            match parse_addr(&data.goto) {
+                Some(addr) => data.scroll_to = Some(addr),
+                None => log::warn!("Invalid address: {}", data.goto),
+            }
+        }
+
+        ui.separator();
+
+        if ui.checkbox(&mut data.live, "Live refresh").changed() && !data.live {
+            data.snapshot = take_snapshot(ruboy);
+        }
+
+        if !data.live && ui.button("Refresh").clicked() {
+            data.snapshot = take_snapshot(ruboy);
+        }
+    });
+}
+
+fn draw_rows(ruboy: &AppRuboy, data: &mut MemoryMenuData, ui: &mut Ui) {
+    let row_height = ui.spacing().interact_size.y;
+
+    let mut scroll = ScrollArea::vertical()
+        .max_height(360.0)
+        .auto_shrink([false, true]);
+
+    if let Some(addr) = data.scroll_to.take() {
+        let row = (addr / ROW_LEN) as f32;
+        let spacing = ui.spacing().item_spacing.y;
+
+        scroll = scroll.vertical_scroll_offset(row * (row_height + spacing));
+    }
+
+    scroll.show_rows(ui, row_height, NUM_ROWS, |ui, rows| {
+        for row in rows {
+            let base = row as u16 * ROW_LEN;
+
+            let bytes = if data.live {
+                read_row(ruboy, base)
+            } else {
+                data.snapshot.get(row).cloned().flatten()
+            };
+
+            ui.horizontal(|ui| {
+                ui.monospace(format!("{:04X}", base));
+
+                for offset in 0..ROW_LEN {
+                    let addr = base + offset;
+                    let byte = bytes.as_ref().map(|b| b[offset as usize]);
+
+                    let text = match byte {
+                        Some(byte) => format!("{:02X}", byte),
+                        None => "??".to_string(),
+                    };
+
+                    let selected = data.selected == Some(addr);
+
+                    if ui
+                        .selectable_label(selected, RichText::new(text).monospace())
+                        .clicked()
+                    {
+                        data.selected = Some(addr);
+                        data.edit = byte.map(|b| format!("{:02X}", b)).unwrap_or_default();
+                    }
+                }
+
+                let ascii: String = match &bytes {
+                    Some(bytes) => bytes.iter().copied().map(to_ascii).collect(),
+                    None => " ".repeat(ROW_LEN as usize),
+                };
+
+                ui.monospace(ascii);
+                ui.weak(ruboy.memory_region(base).to_string());
+            });
+        }
+    });
+}
+
+fn draw_editor(ruboy: &mut AppRuboy, data: &mut MemoryMenuData, ui: &mut Ui) {
+    let Some(addr) = data.selected else {
+        ui.label("Click a byte to edit it");
+        return;
+    };
+
+    ui.horizontal(|ui| {
+        ui.monospace(format!("{:04X}", addr));
+        ui.weak(ruboy.memory_region(addr).to_string());
+
+        ui.add(
+            TextEdit::singleline(&mut data.edit)
+                .hint_text("Bytes, e.g. 3E 01")
+                .desired_width(160.0),
+        );
+
+        if ui.button("Write").clicked() {
+            let Some(bytes) = parse_bytes(&data.edit) else {
+                log::warn!("Invalid bytes: {}", data.edit);
+                return;
+            };
+
+            if let Err(e) = ruboy.write_memory(addr, &bytes) {
+                log::error!("Could not write memory: {}", e);
+            }
+
+            if !data.live {
+                data.snapshot = take_snapshot(ruboy);
+            }
+        }
+    });
+}
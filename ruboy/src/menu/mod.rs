@@ -1,16 +1,28 @@
+use apu::ApuMenuData;
+#[cfg(feature = "audio")]
+pub use audio::apply_audio_settings;
 use audio::AudioMenuData;
+use controls::ControlsMenuData;
 use debugger::DebuggerMenuData;
-use eframe::egui::{self, Ui};
+use eframe::egui::{self, Context, Ui};
+use memory::MemoryMenuData;
+use record::RecordMenuData;
 use rom::RomMenuData;
 use save::SaveMenuData;
+use vram::VramMenuData;
 use window::WindowMenuData;
 
 use crate::RuboyApp;
 
+mod apu;
 mod audio;
+mod controls;
 mod debugger;
+mod memory;
+mod record;
 mod rom;
 mod save;
+mod vram;
 mod window;
 
 #[derive(Debug, Default)]
@@ -19,7 +31,20 @@ pub struct MenuData {
     save: SaveMenuData,
     window: WindowMenuData,
     debugger: DebuggerMenuData,
+    memory: MemoryMenuData,
+    vram: VramMenuData,
+    apu: ApuMenuData,
     audio: AudioMenuData,
+    controls: ControlsMenuData,
+    record: RecordMenuData,
+}
+
+impl MenuData {
+    /// Whether an open window wants key presses for itself, so the game
+    /// should not get them
+    pub fn capturing_input(&self) -> bool {
+        self.controls.capturing_input()
+    }
 }
 
 pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
@@ -36,12 +61,43 @@ pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
             audio::draw_menu(app, ui);
         });
 
+        ui.menu_button("Input", |ui| {
+            controls::draw_menu(app, ui);
+        });
+
         ui.menu_button("Window", |ui| {
             window::draw_menu(app, ui);
         });
 
+        ui.menu_button("Record", |ui| {
+            record::draw_menu(app, ui);
+        });
+
         ui.menu_button("Debugger", |ui| {
             debugger::draw_menu(app, ui);
+            memory::draw_menu(app, ui);
+            vram::draw_menu(app, ui);
+            apu::draw_menu(app, ui);
         });
     });
 }
+
+/// Draws the windows that can be opened from the menu
+pub fn draw_windows(app: &mut RuboyApp, ctx: &Context) {
+    controls::draw_window(app, ctx);
+    debugger::draw_window(app, ctx);
+    memory::draw_window(app, ctx);
+    vram::draw_window(app, ctx);
+    apu::draw_window(app, ctx);
+}
+
+/// Parses a hexadecimal address, optionally prefixed with `0x` or `$`
+fn parse_addr(text: &str) -> Option<u16> {
+    let trimmed = text.trim();
+    let hex = trimmed
+        .strip_prefix("0x")
+        .or_else(|| trimmed.strip_prefix('$'))
+        .unwrap_or(trimmed);
+
+    u16::from_str_radix(hex, 16).ok()
+}
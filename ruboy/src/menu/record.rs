@@ -0,0 +1,84 @@
+use std::path::PathBuf;
+
+use eframe::egui::Ui;
+use rfd::FileDialog;
+
+use crate::recording::{RecordFormat, Recorder};
+use crate::RuboyApp;
+
+#[derive(Debug)]
+pub struct RecordMenuData {
+    format: RecordFormat,
+}
+
+impl Default for RecordMenuData {
+    fn default() -> Self {
+        Self {
+            format: RecordFormat::Gif,
+        }
+    }
+}
+
+fn pick_output(format: RecordFormat) -> Option<PathBuf> {
+    let dialog = FileDialog::new().set_title("Record to");
+
+    match format.extension() {
+        Some(ext) => dialog
+            .add_filter(format.to_string(), &[ext])
+            .set_file_name(format!("ruboy.{}", ext))
+            .save_file(),
+        None => dialog.pick_folder(),
+    }
+}
+
+fn stop_recording(app: &mut RuboyApp) {
+    let Some(recorder) = app.emulator.stop_recording() else {
+        return;
+    };
+
+    let path = recorder.path().to_path_buf();
+
+    match recorder.finish() {
+        Ok(()) => log::info!("Recorded to {}", path.display()),
+        Err(e) => app
+            .notifications
+            .push_warning(format!("Could not finish recording: {}", e)),
+    }
+}
+
+pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
+    if let Some((path, frames)) = app.emulator.recording() {
+        ui.label(format!("Recording to {}", path.display()));
+        ui.label(format!("{} frames", frames));
+
+        if ui.button("Stop recording").clicked() {
+            ui.close_menu();
+            stop_recording(app);
+        }
+
+        return;
+    }
+
+    for format in RecordFormat::ALL {
+        ui.radio_value(&mut app.menu_data.record.format, format, format.to_string());
+    }
+
+    ui.separator();
+
+    if ui.button("Start recording...").clicked() {
+        ui.close_menu();
+
+        let format = app.menu_data.record.format;
+
+        let Some(path) = pick_output(format) else {
+            return;
+        };
+
+        match Recorder::start(format, path) {
+            Ok(recorder) => app.emulator.start_recording(recorder),
+            Err(e) => app
+                .notifications
+                .push_warning(format!("Could not start recording: {}", e)),
+        }
+    }
+}
@@ -1,17 +1,200 @@
-use eframe::egui::Ui;
+use eframe::egui::{Button, Checkbox, Slider, Ui};
 use rfd::FileDialog;
 
+use ruboy_lib::Frameskip;
+
+use crate::capture;
+use crate::config::FRAMESKIP_CHOICES;
+use crate::emulator::{Command, SPEED_RANGE};
 use crate::RuboyApp;
 
 #[derive(Debug, Default)]
 pub struct RomMenuData {}
 
+fn draw_recent_roms(app: &mut RuboyApp, ui: &mut Ui) {
+    let mut picked = None;
+
+    for path in &app.config.recent_roms {
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        );
+
+        let exists = path.exists();
+        let hover = if exists {
+            path.display().to_string()
+        } else {
+            format!("{} (not found)", path.display())
+        };
+
+        if ui
+            .add_enabled(exists, Button::new(name))
+            .on_hover_text(&hover)
+            .on_disabled_hover_text(&hover)
+            .clicked()
+        {
+            picked = Some(path.clone());
+        }
+    }
+
+    ui.separator();
+
+    if ui.button("Clear").clicked() {
+        app.config.recent_roms.clear();
+        app.config_dirty = true;
+        ui.close_menu();
+    }
+
+    if let Some(path) = picked {
+        app.emulator.send(Command::LoadRom(path));
+        ui.close_menu();
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn draw_script(app: &mut RuboyApp, ui: &mut Ui) {
+    match app.emulator.script() {
+        Some(path) => {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+            if ui
+                .button(format!("Stop script {}", name))
+                .on_hover_text(path.display().to_string())
+                .clicked()
+            {
+                app.emulator.send(Command::StopScript);
+                ui.close_menu();
+            }
+        }
+        None => {
+            if ui.button("Run script...").clicked() {
+                if let Some(path) = FileDialog::new()
+                    .set_title("Pick a script")
+                    .add_filter("Rhai script", &["rhai"])
+                    .pick_file()
+                {
+                    app.emulator.send(Command::LoadScript(path));
+                    ui.close_menu();
+                }
+            }
+        }
+    }
+}
+
 pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
     if ui.button("Open...").clicked() {
         if let Some(path) = FileDialog::new().set_title("Pick a ROM").pick_file() {
-            app.rom = Some(path);
-            app.ruboy = None;
+            app.emulator.send(Command::LoadRom(path));
             ui.close_menu();
         }
     }
+
+    ui.add_enabled_ui(!app.config.recent_roms.is_empty(), |ui| {
+        ui.menu_button("Open recent", |ui| {
+            draw_recent_roms(app, ui);
+        });
+    });
+
+    ui.separator();
+
+    let mut paused = app.emulator.paused();
+
+    if ui.checkbox(&mut paused, "Paused (P)").changed() {
+        app.emulator.send(Command::SetPaused(paused));
+    }
+
+    if ui.button("Reset (F5)").clicked() {
+        app.emulator.send(Command::Reset);
+        ui.close_menu();
+    }
+
+    let mut printer = app.emulator.printer();
+    let hover = format!(
+        "Printouts are saved to {}",
+        capture::screenshot_dir().display()
+    );
+
+    if ui
+        .add_enabled(
+            !app.emulator.linked(),
+            Checkbox::new(&mut printer, "Game Boy Printer"),
+        )
+        .on_hover_text(hover)
+        .on_disabled_hover_text("The link cable is in use")
+        .changed()
+    {
+        app.emulator.set_printer(printer);
+    }
+
+    if app.emulator.linked() {
+        if ui.button("Unplug link cable").clicked() {
+            app.emulator.send(Command::SetLinkPartner(None));
+            ui.close_menu();
+        }
+    } else if ui
+        .add_enabled(
+            app.emulator.lock().is_some(),
+            Button::new("Link second game..."),
+        )
+        .on_hover_text("Opens a second game in its own window, connected by a link cable")
+        .clicked()
+    {
+        if let Some(path) = FileDialog::new()
+            .set_title("Pick a ROM for player 2")
+            .pick_file()
+        {
+            app.emulator.send(Command::SetLinkPartner(Some(path)));
+            ui.close_menu();
+        }
+    }
+
+    #[cfg(feature = "scripting")]
+    draw_script(app, ui);
+
+    ui.separator();
+
+    let mut speed = app.emulator.speed();
+
+    let slider = Slider::new(&mut speed, SPEED_RANGE)
+        .logarithmic(true)
+        .suffix("x")
+        .text("Speed (-/=)");
+
+    if ui.add(slider).changed() {
+        app.emulator.set_speed(speed);
+    }
+
+    ui.menu_button("Frameskip", |ui| {
+        for frameskip in FRAMESKIP_CHOICES {
+            let label = match frameskip {
+                Frameskip::Off => "Off".to_string(),
+                Frameskip::Fixed(skip) => format!("Skip {}", skip),
+                Frameskip::Auto { .. } => "Auto".to_string(),
+            };
+
+            if ui
+                .radio_value(&mut app.config.frameskip, frameskip, label)
+                .changed()
+            {
+                app.config_dirty = true;
+                app.emulator.send(Command::SetFrameskip(frameskip));
+            }
+        }
+    })
+    .response
+    .on_hover_text(
+        "Skips drawing frames when the computer can't keep up. Auto only skips when needed.",
+    );
+
+    let mut turbo = app.emulator.turbo();
+
+    if ui.checkbox(&mut turbo, "Turbo (hold Tab)").changed() {
+        app.emulator.set_turbo(turbo);
+    }
+
+    if app.emulator.rewinding() {
+        ui.label("Rewinding...");
+    } else {
+        ui.label("Hold R to rewind");
+    }
 }
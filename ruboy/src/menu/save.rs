@@ -1,8 +1,19 @@
-use eframe::egui::Ui;
+use eframe::egui::{Button, Ui};
 
+use crate::emulator::Command;
 use crate::RuboyApp;
 
 #[derive(Debug, Default)]
 pub struct SaveMenuData {}
 
-pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {}
+pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
+    let loaded = app.emulator.lock().is_some();
+
+    if ui
+        .add_enabled(loaded, Button::new("Write save data"))
+        .clicked()
+    {
+        app.emulator.send(Command::Save);
+        ui.close_menu();
+    }
+}
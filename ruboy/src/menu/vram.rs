@@ -0,0 +1,226 @@
+use eframe::egui::{
+    self, load::SizedTexture, Color32, ColorImage, Context, Image, Rect, ScrollArea, Stroke,
+    TextureHandle, Ui, Vec2,
+};
+use ruboy_lib::color::DmgPalette;
+use ruboy_lib::debug::{DebugImage, TileMapArea};
+
+use crate::compositor::convert_debug_image;
+use crate::{AppRuboy, RuboyApp};
+
+const SCALE: f32 = 2.0;
+
+const SCREEN_SIZE: Vec2 = Vec2::new(160.0, 144.0);
+const TILEMAP_SIZE: f32 = 256.0;
+
+const VIEWPORT_COLOR: Color32 = Color32::from_rgb(230, 80, 80);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum Tab {
+    #[default]
+    Tiles,
+    Tilemap(TileMapArea),
+    Oam,
+}
+
+#[derive(Default)]
+pub struct VramMenuData {
+    window_open: bool,
+    tab: Tab,
+    tex: Option<TextureHandle>,
+}
+
+impl std::fmt::Debug for VramMenuData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VramMenuData")
+            .field("window_open", &self.window_open)
+            .field("tab", &self.tab)
+            .finish_non_exhaustive()
+    }
+}
+
+pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
+    if ui
+        .checkbox(&mut app.menu_data.vram.window_open, "Show VRAM viewer")
+        .clicked()
+    {
+        ui.close_menu();
+    }
+}
+
+pub fn draw_window(app: &mut RuboyApp, ctx: &Context) {
+    let mut open = app.menu_data.vram.window_open;
+
+    egui::Window::new("VRAM viewer")
+        .open(&mut open)
+        .default_width(560.0)
+        .show(ctx, |ui| {
+            let data = &mut app.menu_data.vram;
+
+            let guard = app.emulator.lock();
+
+            let Some(ruboy) = guard.as_ref() else {
+                ui.label("No ROM loaded");
+                return;
+            };
+
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut data.tab, Tab::Tiles, "Tiles");
+                ui.selectable_value(
+                    &mut data.tab,
+                    Tab::Tilemap(TileMapArea::Low),
+                    "Tilemap (0x9800)",
+                );
+                ui.selectable_value(
+                    &mut data.tab,
+                    Tab::Tilemap(TileMapArea::High),
+                    "Tilemap (0x9C00)",
+                );
+                ui.selectable_value(&mut data.tab, Tab::Oam, "OAM");
+            });
+
+            ui.separator();
+
+            let rendered = match data.tab {
+                Tab::Tiles => ruboy.render_tiles(),
+                Tab::Tilemap(area) => ruboy.render_tilemap(area),
+                Tab::Oam => ruboy.render_oam(),
+            };
+
+            let img = match rendered {
+                Ok(img) => img,
+                Err(e) => {
+                    ui.label(format!("Could not read VRAM: {}", e));
+                    return;
+                }
+            };
+
+            let rect = show_image(&mut data.tex, &img, ruboy.palette(), ui);
+
+            match data.tab {
+                Tab::Tiles => draw_tiles_info(ui),
+                Tab::Tilemap(area) => draw_tilemap_info(ruboy, area, rect, ui),
+                Tab::Oam => draw_oam_list(ruboy, ui),
+            }
+        });
+
+    app.menu_data.vram.window_open = open;
+}
+
+/// Uploads the image and shows it scaled up. Returns where it was drawn
+fn show_image(
+    tex: &mut Option<TextureHandle>,
+    img: &DebugImage,
+    palette: &DmgPalette,
+    ui: &mut Ui,
+) -> Rect {
+    let color_img: ColorImage = convert_debug_image(img, palette);
+
+    let tex = match tex {
+        Some(tex) => {
+            tex.set(color_img, RuboyApp::get_gb_tex_options());
+            tex
+        }
+        None => tex.insert(ui.ctx().load_texture(
+            "Ruboy VRAM",
+            color_img,
+            RuboyApp::get_gb_tex_options(),
+        )),
+    };
+
+    let size = Vec2::new(img.width() as f32, img.height() as f32) * SCALE;
+
+    ui.add(Image::new(SizedTexture::new(tex.id(), size))).rect
+}
+
+fn draw_tiles_info(ui: &mut Ui) {
+    ui.label("0x8000 - 0x97FF, 16 tiles per row. Block 0 (0x8000) is at the top");
+}
+
+fn draw_tilemap_info(ruboy: &AppRuboy, area: TileMapArea, rect: Rect, ui: &mut Ui) {
+    let layout = ruboy.vram_layout();
+
+    let mut uses = Vec::new();
+
+    if layout.bg_tilemap == area {
+        uses.push("background");
+    }
+
+    if layout.window_enabled && layout.window_tilemap == area {
+        uses.push("window");
+    }
+
+    ui.label(format!(
+        "Used for: {}. Tile data at {}",
+        if uses.is_empty() {
+            "nothing".to_string()
+        } else {
+            uses.join(", ")
+        },
+        if layout.unsigned_tile_addressing {
+            "0x8000 (unsigned)"
+        } else {
+            "0x9000 (signed)"
+        }
+    ));
+
+    if layout.bg_tilemap != area {
+        return;
+    }
+
+    ui.label(format!("SCX: {}, SCY: {}", layout.scx, layout.scy));
+
+    // The viewport wraps around the edges of the map, so draw it at every
+    // offset it can overlap the map from
+    let painter = ui.painter_at(rect);
+    let scroll = Vec2::new(layout.scx as f32, layout.scy as f32);
+
+    for wrap_x in [0.0, -TILEMAP_SIZE] {
+        for wrap_y in [0.0, -TILEMAP_SIZE] {
+            let min = rect.min + (scroll + Vec2::new(wrap_x, wrap_y)) * SCALE;
+
+            painter.rect_stroke(
+                Rect::from_min_size(min, SCREEN_SIZE * SCALE),
+                0.0,
+                Stroke::new(1.0, VIEWPORT_COLOR),
+            );
+        }
+    }
+}
+
+fn draw_oam_list(ruboy: &AppRuboy, ui: &mut Ui) {
+    let entries = match ruboy.oam_entries() {
+        Ok(entries) => entries,
+        Err(e) => {
+            ui.label(format!("Could not read OAM: {}", e));
+            return;
+        }
+    };
+
+    let tall = ruboy.vram_layout().tall_objects;
+    ui.label(if tall { "8x16 objects" } else { "8x8 objects" });
+
+    ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+        egui::Grid::new("oam").striped(true).show(ui, |ui| {
+            for header in ["#", "X", "Y", "Tile", "Palette", "Flip", "Prio"] {
+                ui.strong(header);
+            }
+            ui.end_row();
+
+            for entry in entries {
+                ui.monospace(entry.index.to_string());
+                ui.monospace(format!("{:3}", entry.x));
+                ui.monospace(format!("{:3}", entry.y));
+                ui.monospace(format!("{:02X}", entry.tile));
+                ui.monospace(format!("OBP{}", entry.palette));
+                ui.monospace(format!(
+                    "{}{}",
+                    if entry.x_flip { "X" } else { "-" },
+                    if entry.y_flip { "Y" } else { "-" }
+                ));
+                ui.monospace(if entry.behind_bg { "BG" } else { "OBJ" });
+                ui.end_row();
+            }
+        });
+    });
+}
@@ -1,8 +1,230 @@
-use eframe::egui::Ui;
+use eframe::egui::{DragValue, Slider, Ui, ViewportCommand};
+use rfd::FileDialog;
+use ruboy_lib::color::{DmgPalette, Rgb};
 
+use crate::capture::{self, FrameDump};
+use crate::compositor::{Arrangement, View};
+use crate::config::{GAMMA_RANGE, GHOSTING_RANGE, SCALE_RANGE};
+use crate::display::{self, ScaleMode};
+use crate::emulator::Command;
+use crate::filter::VideoFilter;
 use crate::RuboyApp;
 
-#[derive(Debug, Default)]
-pub struct WindowMenuData {}
+#[derive(Debug)]
+pub struct WindowMenuData {
+    /// Only every Nth frame is written when dumping frames
+    dump_every: u32,
+}
 
-pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {}
+impl Default for WindowMenuData {
+    fn default() -> Self {
+        Self { dump_every: 1 }
+    }
+}
+
+fn draw_palette_picker(app: &mut RuboyApp, ui: &mut Ui) {
+    let data = &mut app.config;
+    let mut changed = false;
+
+    ui.label("Palette");
+
+    for (name, preset) in DmgPalette::PRESETS {
+        if ui.radio(data.palette == preset, name).clicked() {
+            data.palette = preset;
+            changed = true;
+        }
+    }
+
+    ui.horizontal(|ui| {
+        for shade in &mut data.palette.shades {
+            let mut rgb = [shade.r, shade.g, shade.b];
+
+            if ui.color_edit_button_srgb(&mut rgb).changed() {
+                *shade = Rgb::new(rgb[0], rgb[1], rgb[2]);
+                changed = true;
+            }
+        }
+    });
+
+    changed |= ui
+        .add(Slider::new(&mut data.gamma, GAMMA_RANGE).text("Gamma"))
+        .changed();
+
+    if changed {
+        app.config_dirty = true;
+        app.emulator
+            .send(Command::SetPalette(app.config.corrected_palette()));
+    }
+}
+
+fn draw_scale_picker(app: &mut RuboyApp, ui: &mut Ui) {
+    let slider = Slider::new(&mut app.config.window_scale, SCALE_RANGE)
+        .suffix("x")
+        .text("Window scale");
+
+    if ui.add(slider).changed() {
+        app.config_dirty = true;
+        ui.ctx()
+            .send_viewport_cmd(ViewportCommand::InnerSize(app.config.window_size()));
+    }
+}
+
+fn draw_display_options(app: &mut RuboyApp, ui: &mut Ui) {
+    let mut fullscreen = display::is_fullscreen(ui.ctx());
+
+    if ui.checkbox(&mut fullscreen, "Fullscreen (F11)").changed() {
+        ui.ctx()
+            .send_viewport_cmd(ViewportCommand::Fullscreen(fullscreen));
+    }
+
+    for mode in ScaleMode::ALL {
+        if ui
+            .radio_value(&mut app.config.scale_mode, mode, mode.to_string())
+            .changed()
+        {
+            app.config_dirty = true;
+        }
+    }
+
+    ui.add_enabled_ui(app.config.scale_mode == ScaleMode::Stretch, |ui| {
+        if ui
+            .checkbox(&mut app.config.keep_aspect, "Keep aspect ratio")
+            .changed()
+        {
+            app.config_dirty = true;
+        }
+    });
+
+    ui.menu_button("Filter", |ui| {
+        for filter in VideoFilter::ALL {
+            if ui
+                .radio_value(&mut app.config.filter, filter, filter.to_string())
+                .changed()
+            {
+                app.config_dirty = true;
+            }
+        }
+    });
+
+    let ghosting = Slider::new(&mut app.config.ghosting, GHOSTING_RANGE)
+        .text("LCD ghosting")
+        .custom_formatter(|val, _| {
+            if val == 0.0 {
+                "Off".to_string()
+            } else {
+                format!("{:.0}%", val * 100.0)
+            }
+        });
+
+    if ui
+        .add(ghosting)
+        .on_hover_text("Blends every frame with the previous ones, like a DMG screen. Some games rely on it for transparency.")
+        .changed()
+    {
+        app.config_dirty = true;
+        app.apply_ghosting();
+    }
+}
+
+pub fn draw_menu(app: &mut RuboyApp, ui: &mut Ui) {
+    draw_display_options(app, ui);
+
+    ui.separator();
+
+    draw_scale_picker(app, ui);
+
+    ui.separator();
+
+    draw_palette_picker(app, ui);
+
+    ui.separator();
+
+    ui.checkbox(&mut app.compositor.enabled, "Debug views");
+
+    ui.add_enabled_ui(app.compositor.enabled, |ui| {
+        ui.separator();
+
+        for view in View::ALL {
+            let mut shown = app.compositor.views.contains(&view);
+
+            if ui.checkbox(&mut shown, view.name()).changed() {
+                app.compositor.toggle_view(view, shown);
+            }
+        }
+
+        ui.separator();
+
+        ui.radio_value(
+            &mut app.compositor.arrangement,
+            Arrangement::Horizontal,
+            "Side by side",
+        );
+        ui.radio_value(
+            &mut app.compositor.arrangement,
+            Arrangement::Vertical,
+            "Stacked",
+        );
+    });
+
+    ui.separator();
+
+    draw_capture_options(app, ui);
+}
+
+fn draw_capture_options(app: &mut RuboyApp, ui: &mut Ui) {
+    if ui.button("Quick screenshot (F12)").clicked() {
+        ui.close_menu();
+        app.quick_screenshot();
+    }
+
+    if ui.button("Export screenshot...").clicked() {
+        ui.close_menu();
+
+        if let Some(path) = FileDialog::new()
+            .set_title("Save screenshot")
+            .add_filter("PNG image", &["png"])
+            .set_file_name("ruboy.png")
+            .save_file()
+        {
+            if let Err(e) = capture::export_png(&app.screenshot_image(), &path) {
+                log::error!("Could not export screenshot to {}: {}", path.display(), e);
+            } else {
+                log::info!("Exported screenshot to {}", path.display());
+            }
+        }
+    }
+
+    ui.separator();
+
+    if let Some(dir) = app.emulator.frame_dump_dir() {
+        ui.label(format!("Dumping frames to {}", dir.display()));
+
+        if ui.button("Stop dumping frames").clicked() {
+            app.emulator.set_frame_dump(None);
+        }
+
+        return;
+    }
+
+    ui.horizontal(|ui| {
+        ui.label("Dump every");
+        ui.add(DragValue::new(&mut app.menu_data.window.dump_every).range(1..=600));
+        ui.label("frames");
+    });
+
+    if ui.button("Dump frames...").clicked() {
+        ui.close_menu();
+
+        if let Some(dir) = FileDialog::new()
+            .set_title("Pick a folder for the frames")
+            .pick_folder()
+        {
+            match FrameDump::new(dir, app.menu_data.window.dump_every) {
+                Ok(dump) => app.emulator.set_frame_dump(Some(dump)),
+                Err(e) => app
+                    .notifications
+                    .push_warning(format!("Could not dump frames: {}", e)),
+            }
+        }
+    }
+}
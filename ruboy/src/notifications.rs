@@ -0,0 +1,189 @@
+//! Messages about the running game, shown above it until dismissed
+
+use std::path::PathBuf;
+
+use eframe::egui::{self, Align2, Color32, Context, Grid, RichText, Ui};
+use ruboy_lib::rom::capabilities::Capability;
+use ruboy_lib::rom::meta::{CartridgeMapper, RomMeta};
+
+use crate::emulator::Event;
+
+#[derive(Debug, Default)]
+pub struct Notifications {
+    warnings: Vec<String>,
+
+    /// The features of the loaded cartridge
+    capabilities: Vec<Capability>,
+
+    /// A cartridge that could not be loaded, shown in a dialog until closed
+    unsupported: Option<Unsupported>,
+}
+
+#[derive(Debug)]
+struct Unsupported {
+    path: PathBuf,
+    mapper: CartridgeMapper,
+    meta: Option<RomMeta>,
+}
+
+impl Notifications {
+    pub fn handle(&mut self, event: Event) {
+        match event {
+            Event::RomLoaded {
+                path,
+                title,
+                capabilities,
+            } => {
+                self.warnings.clear();
+
+                let name = if title.is_empty() {
+                    path.display().to_string()
+                } else {
+                    title
+                };
+
+                for cap in &capabilities {
+                    if let Some(limitation) = cap.limitation() {
+                        self.push_warning(format!("{} uses {}: {}", name, cap, limitation));
+                    }
+                }
+
+                self.capabilities = capabilities;
+            }
+            Event::RomLoadFailed { path, error } => {
+                // Already logged by the emulation thread
+                self.warnings
+                    .push(format!("Could not load {}: {}", path.display(), error));
+            }
+            Event::UnsupportedCartridge { path, mapper, meta } => {
+                self.unsupported = Some(Unsupported { path, mapper, meta });
+            }
+            Event::PrintFailed { error } => {
+                self.warnings
+                    .push(format!("Could not save printout: {}", error));
+            }
+            Event::NetLinkFailed { error } => {
+                // Already logged by the emulation thread
+                self.warnings.push(format!("Network link: {}", error));
+            }
+            Event::EmulationFailed { error } => {
+                // Already logged by the emulator
+                self.warnings.push(error);
+            }
+            #[cfg(feature = "scripting")]
+            Event::ScriptFailed { path, error } => {
+                // Already logged by the emulation thread
+                self.warnings
+                    .push(format!("Script {} stopped: {}", path.display(), error));
+            }
+        }
+    }
+
+    pub fn push_warning(&mut self, text: String) {
+        log::warn!("{}", text);
+        self.warnings.push(text);
+    }
+
+    /// Draws the dialog about an unsupported cartridge, if there is one
+    pub fn draw_dialogs(&mut self, ctx: &Context) {
+        let Some(unsupported) = &self.unsupported else {
+            return;
+        };
+
+        let mut close = false;
+
+        egui::Window::new("Unsupported cartridge")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                let name = match &unsupported.meta {
+                    Some(meta) if !meta.title().is_empty() => meta.title().to_string(),
+                    _ => unsupported.path.display().to_string(),
+                };
+
+                ui.label(format!(
+                    "{} needs the {} mapper, which Ruboy can't emulate yet.",
+                    name, unsupported.mapper
+                ));
+
+                if let Some(meta) = &unsupported.meta {
+                    ui.separator();
+                    draw_hardware(ui, meta);
+                }
+
+                ui.separator();
+                close = ui.button("OK").clicked();
+            });
+
+        if close {
+            self.unsupported = None;
+        }
+    }
+
+    pub fn draw(&mut self, ui: &mut Ui) {
+        if !self.capabilities.is_empty() {
+            ui.horizontal(|ui| {
+                ui.label("Cartridge:");
+
+                for cap in &self.capabilities {
+                    let text = RichText::new(cap.to_string());
+
+                    if cap.supported() {
+                        ui.label(text.strong());
+                    } else {
+                        ui.label(text.weak().strikethrough())
+                            .on_hover_text(cap.limitation().unwrap_or_default());
+                    }
+                }
+            });
+        }
+
+        self.warnings.retain(|warning| {
+            ui.horizontal(|ui| {
+                ui.label(RichText::new(warning).color(Color32::YELLOW));
+                !ui.small_button("Dismiss").clicked()
+            })
+            .inner
+        });
+    }
+}
+
+/// Lists what is in the cartridge, according to its header
+fn draw_hardware(ui: &mut Ui, meta: &RomMeta) {
+    let hardware = meta.cartridge_hardware();
+    let features: Vec<String> = meta
+        .capabilities()
+        .iter()
+        .map(Capability::to_string)
+        .collect();
+
+    Grid::new("cartridge_hardware")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Mapper:");
+            ui.label(
+                hardware
+                    .mapper()
+                    .map_or_else(|| "None".to_string(), |mapper| mapper.to_string()),
+            );
+            ui.end_row();
+
+            ui.label("ROM:");
+            ui.label(meta.rom_size().to_string());
+            ui.end_row();
+
+            ui.label("RAM:");
+            match meta.ram_size().in_bytes() {
+                0 => ui.label("None"),
+                _ => ui.label(meta.ram_size().to_string()),
+            };
+            ui.end_row();
+
+            if !features.is_empty() {
+                ui.label("Features:");
+                ui.label(features.join(", "));
+                ui.end_row();
+            }
+        });
+}
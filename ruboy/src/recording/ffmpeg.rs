@@ -0,0 +1,57 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+
+use ruboy_lib::{Frame, DESIRED_FRAMERATE, FRAME_X, FRAME_Y};
+
+use super::{FrameEncoder, RecordErr};
+
+/// Pipes raw frames into ffmpeg, which encodes them with the lossless FFV1
+/// codec
+pub struct Ffv1Encoder {
+    ffmpeg: Child,
+    stdin: Option<ChildStdin>,
+}
+
+impl Ffv1Encoder {
+    pub fn new(path: &Path) -> Result<Self, RecordErr> {
+        let mut ffmpeg = Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error"])
+            .args(["-f", "rawvideo", "-pixel_format", "rgba"])
+            .args(["-video_size", &format!("{}x{}", FRAME_X, FRAME_Y)])
+            .args(["-framerate", &DESIRED_FRAMERATE.to_string()])
+            .args(["-i", "-", "-c:v", "ffv1"])
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .map_err(RecordErr::FfmpegStart)?;
+
+        let stdin = ffmpeg.stdin.take();
+
+        Ok(Self { ffmpeg, stdin })
+    }
+}
+
+impl FrameEncoder for Ffv1Encoder {
+    fn frame(&mut self, frame: &Frame) -> Result<(), RecordErr> {
+        if let Some(stdin) = &mut self.stdin {
+            stdin.write_all(&frame.to_rgba_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), RecordErr> {
+        // Closing stdin tells ffmpeg the video ended
+        drop(self.stdin.take());
+
+        let status = self.ffmpeg.wait()?;
+
+        if !status.success() {
+            return Err(RecordErr::Ffmpeg(status));
+        }
+
+        Ok(())
+    }
+}
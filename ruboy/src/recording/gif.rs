@@ -0,0 +1,82 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use gif::{Encoder, Repeat};
+use ruboy_lib::{Frame, DESIRED_FRAMERATE, FRAME_X, FRAME_Y};
+
+use super::{FrameEncoder, RecordErr};
+
+/// GIF delays are in hundredths of a second, and most viewers slow down
+/// anything faster than 50 frames per second, so only every Nth frame is
+/// kept
+const FRAME_STEP: u64 = 2;
+
+pub struct GifEncoder {
+    encoder: Option<Encoder<BufWriter<File>>>,
+    frames: u64,
+
+    /// Time of the last written frame, in hundredths of a second. Tracked
+    /// exactly, so the rounded delays add up to the right length.
+    written_at: u64,
+}
+
+impl GifEncoder {
+    pub fn new(path: &Path) -> Result<Self, RecordErr> {
+        let file = BufWriter::new(File::create(path)?);
+
+        let mut encoder = Encoder::new(file, FRAME_X as u16, FRAME_Y as u16, &[])?;
+        encoder.set_repeat(Repeat::Infinite)?;
+
+        Ok(Self {
+            encoder: Some(encoder),
+            frames: 0,
+            written_at: 0,
+        })
+    }
+}
+
+impl FrameEncoder for GifEncoder {
+    fn frame(&mut self, frame: &Frame) -> Result<(), RecordErr> {
+        let num = self.frames;
+        self.frames += 1;
+
+        if !num.is_multiple_of(FRAME_STEP) {
+            return Ok(());
+        }
+
+        let Some(encoder) = &mut self.encoder else {
+            return Ok(());
+        };
+
+        // The delay is how long the frame is shown, so until the next
+        // written frame
+        let shown_until = ((num + FRAME_STEP) as f64 * 100.0 / DESIRED_FRAMERATE).round() as u64;
+
+        let pixels: Vec<u8> = frame.get_raw().iter().map(|shade| *shade as u8).collect();
+        let palette: Vec<u8> = frame
+            .palette()
+            .shades
+            .iter()
+            .flat_map(|rgb| [rgb.r, rgb.g, rgb.b])
+            .collect();
+
+        let mut gif_frame =
+            gif::Frame::from_palette_pixels(FRAME_X as u16, FRAME_Y as u16, pixels, palette, None);
+        gif_frame.delay = (shown_until - self.written_at) as u16;
+        self.written_at = shown_until;
+
+        encoder.write_frame(&gif_frame)?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), RecordErr> {
+        if let Some(encoder) = self.encoder.take() {
+            // Writes the trailer
+            encoder.into_inner()?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ruboy_lib::{Frame, FRAME_X, FRAME_Y};
+
+use super::{FrameEncoder, RecordErr};
+use crate::capture;
+
+pub struct ImageSequence {
+    dir: PathBuf,
+    frames: u64,
+}
+
+impl ImageSequence {
+    pub fn new(dir: &Path) -> Result<Self, RecordErr> {
+        fs::create_dir_all(dir)?;
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            frames: 0,
+        })
+    }
+}
+
+impl FrameEncoder for ImageSequence {
+    fn frame(&mut self, frame: &Frame) -> Result<(), RecordErr> {
+        let path = self.dir.join(format!("frame-{:08}.png", self.frames));
+        self.frames += 1;
+
+        capture::write_png(path, FRAME_X, FRAME_Y, &frame.to_rgba_bytes())?;
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), RecordErr> {
+        Ok(())
+    }
+}
@@ -0,0 +1,192 @@
+//! Recording gameplay to video files.
+//!
+//! Frames come from a [FrameObserver] attached to the emulator, so every
+//! drawn frame is recorded exactly once, no matter how fast the UI
+//! redraws. The video format is up to the [FrameEncoder]. Audio is written
+//! next to the video as a WAV file, as not every format can hold audio.
+
+use std::fmt::Display;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use ruboy_lib::{Frame, FrameObserver};
+use thiserror::Error;
+
+use self::ffmpeg::Ffv1Encoder;
+use self::gif::GifEncoder;
+use self::images::ImageSequence;
+#[cfg(feature = "audio")]
+use self::wav::WavWriter;
+
+mod ffmpeg;
+mod gif;
+mod images;
+#[cfg(feature = "audio")]
+mod wav;
+
+#[derive(Debug, Error)]
+pub enum RecordErr {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+
+    #[error("Could not encode PNG: {0}")]
+    Png(#[from] png::EncodingError),
+
+    #[error("Could not encode GIF: {0}")]
+    Gif(#[from] ::gif::EncodingError),
+
+    #[error("Could not start ffmpeg, is it installed? {0}")]
+    FfmpegStart(io::Error),
+
+    #[error("ffmpeg failed with {0}")]
+    Ffmpeg(std::process::ExitStatus),
+}
+
+/// Turns frames into a video
+pub trait FrameEncoder: Send {
+    fn frame(&mut self, frame: &Frame) -> Result<(), RecordErr>;
+
+    /// Writes out everything still buffered. Called once, after the last
+    /// frame.
+    fn finish(&mut self) -> Result<(), RecordErr>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// A PNG file per frame, in a directory
+    ImageSequence,
+
+    /// An animated GIF, at half the framerate
+    Gif,
+
+    /// Lossless FFV1 video in a Matroska file. Needs ffmpeg to be installed.
+    Ffv1,
+}
+
+impl RecordFormat {
+    pub const ALL: [RecordFormat; 3] = [
+        RecordFormat::ImageSequence,
+        RecordFormat::Gif,
+        RecordFormat::Ffv1,
+    ];
+
+    /// The file extension of the output, or None if the output is a
+    /// directory
+    pub const fn extension(self) -> Option<&'static str> {
+        match self {
+            RecordFormat::ImageSequence => None,
+            RecordFormat::Gif => Some("gif"),
+            RecordFormat::Ffv1 => Some("mkv"),
+        }
+    }
+
+    fn encoder(self, path: &Path) -> Result<Box<dyn FrameEncoder>, RecordErr> {
+        Ok(match self {
+            RecordFormat::ImageSequence => Box::new(ImageSequence::new(path)?),
+            RecordFormat::Gif => Box::new(GifEncoder::new(path)?),
+            RecordFormat::Ffv1 => Box::new(Ffv1Encoder::new(path)?),
+        })
+    }
+}
+
+impl Display for RecordFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RecordFormat::ImageSequence => "PNG sequence",
+            RecordFormat::Gif => "GIF",
+            RecordFormat::Ffv1 => "FFV1 (ffmpeg)",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+pub struct Recorder {
+    encoder: Box<dyn FrameEncoder>,
+    path: PathBuf,
+    frames: u64,
+
+    /// Created when the first samples arrive, as the sample rate is not
+    /// known before
+    #[cfg(feature = "audio")]
+    audio: Option<WavWriter>,
+}
+
+/// Shared between the UI, which starts and stops recording, and the
+/// emulation thread, which feeds the recorder
+pub type SharedRecorder = Arc<Mutex<Option<Recorder>>>;
+
+impl Recorder {
+    pub fn start(format: RecordFormat, path: PathBuf) -> Result<Self, RecordErr> {
+        Ok(Self {
+            encoder: format.encoder(&path)?,
+            path,
+            frames: 0,
+            #[cfg(feature = "audio")]
+            audio: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The amount of frames recorded so far
+    pub const fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn frame(&mut self, frame: &Frame) -> Result<(), RecordErr> {
+        self.encoder.frame(frame)?;
+        self.frames += 1;
+
+        Ok(())
+    }
+
+    #[cfg(feature = "audio")]
+    pub fn audio(&mut self, samples: &[[f32; 2]], sample_rate: u32) -> Result<(), RecordErr> {
+        let wav = match &mut self.audio {
+            Some(wav) => wav,
+            None => {
+                let path = if self.path.is_dir() {
+                    self.path.join("audio.wav")
+                } else {
+                    self.path.with_extension("wav")
+                };
+
+                self.audio.insert(WavWriter::new(&path, sample_rate)?)
+            }
+        };
+
+        wav.write(samples)?;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<(), RecordErr> {
+        self.encoder.finish()?;
+
+        #[cfg(feature = "audio")]
+        if let Some(wav) = self.audio.take() {
+            wav.finish()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Feeds the frames of the emulator it is attached to to the shared
+/// recorder, if recording
+pub struct RecordingHook(pub SharedRecorder);
+
+impl FrameObserver for RecordingHook {
+    fn frame(&mut self, frame: &Frame, _number: u64, _cycle: u64) {
+        let mut recorder = self.0.lock().unwrap();
+
+        if let Some(Err(e)) = recorder.as_mut().map(|rec| rec.frame(frame)) {
+            log::error!("Stopped recording: {}", e);
+            *recorder = None;
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+const CHANNELS: u16 = 2;
+const BITS_PER_SAMPLE: u16 = 16;
+
+/// Bytes before the sample data
+const HEADER_LEN: u32 = 44;
+
+/// Writes 16-bit stereo PCM. The sizes in the header are filled in by
+/// [WavWriter::finish].
+pub struct WavWriter {
+    file: BufWriter<File>,
+    data_len: u32,
+}
+
+impl WavWriter {
+    pub fn new(path: &Path, sample_rate: u32) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVE")?;
+
+        file.write_all(b"fmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&CHANNELS.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * u32::from(block_align)).to_le_bytes())?;
+        file.write_all(&block_align.to_le_bytes())?;
+        file.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self { file, data_len: 0 })
+    }
+
+    pub fn write(&mut self, samples: &[[f32; 2]]) -> io::Result<()> {
+        for sample in samples.iter().flatten() {
+            let pcm = (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16;
+            self.file.write_all(&pcm.to_le_bytes())?;
+        }
+
+        self.data_len += (samples.len() * usize::from(CHANNELS * BITS_PER_SAMPLE / 8)) as u32;
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file
+            .write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+
+        self.file.seek(SeekFrom::Start(u64::from(HEADER_LEN) - 4))?;
+        self.file.write_all(&self.data_len.to_le_bytes())?;
+
+        self.file.flush()
+    }
+}
@@ -0,0 +1,94 @@
+//! Runs a user script next to the game, see [ruboy_lib::script]
+
+use std::fs;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use ruboy_lib::script::Script;
+
+use crate::emulator::Event;
+use crate::AppRuboy;
+
+/// The script that is running, shared with the UI
+pub type SharedScriptPath = Arc<Mutex<Option<PathBuf>>>;
+
+/// Owned by the emulation thread. The script restarts along with the game,
+/// and is stopped when it fails.
+pub struct ScriptRunner {
+    script: Option<Script>,
+    path: SharedScriptPath,
+    events: Sender<Event>,
+}
+
+impl ScriptRunner {
+    pub fn new(path: SharedScriptPath, events: Sender<Event>) -> Self {
+        Self {
+            script: None,
+            path,
+            events,
+        }
+    }
+
+    /// Runs the script at `path` from now on, replacing the running one
+    pub fn set(&mut self, path: PathBuf, ruboy: Option<&mut AppRuboy>) {
+        *self.path.lock().unwrap() = Some(path);
+
+        match ruboy {
+            Some(ruboy) => self.start(ruboy),
+            None => self.script = None,
+        }
+    }
+
+    pub fn stop(&mut self) {
+        if self.path.lock().unwrap().take().is_some() {
+            log::info!("Stopped script");
+        }
+
+        self.script = None;
+    }
+
+    /// (Re)starts the script for the game that was just loaded, if one is
+    /// set
+    pub fn start(&mut self, ruboy: &mut AppRuboy) {
+        let Some(path) = self.path.lock().unwrap().clone() else {
+            self.script = None;
+            return;
+        };
+
+        let script = fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| Script::load(&source, ruboy.as_mut()).map_err(|e| e.to_string()));
+
+        match script {
+            Ok(script) => {
+                log::info!("Running script {}", path.display());
+                self.script = Some(script);
+            }
+            Err(e) => self.fail(path, e),
+        }
+    }
+
+    /// Gives the script its frame callback. When more than one frame passed
+    /// since the last call, it still only runs once.
+    pub fn frame(&mut self, ruboy: &mut AppRuboy) {
+        let Some(script) = &mut self.script else {
+            return;
+        };
+
+        if let Err(e) = script.frame(ruboy.as_mut()) {
+            let path = self.path.lock().unwrap().clone().unwrap_or_default();
+            self.fail(path, e.to_string());
+        }
+    }
+
+    fn fail(&mut self, path: PathBuf, error: String) {
+        log::error!("Script {} failed: {}", path.display(), error);
+
+        self.script = None;
+        *self.path.lock().unwrap() = None;
+
+        // Nobody to tell if the UI is already gone
+        let _ = self.events.send(Event::ScriptFailed { path, error });
+    }
+}
@@ -1,23 +1,97 @@
-use core::cell::RefCell;
 use core::{array, fmt::Display};
 use std::error::Error;
-use std::rc::Rc;
+use std::mem;
+use std::sync::{Arc, Mutex};
 
 use eframe::egui::{Color32, ColorImage};
-use ruboy_lib::{Frame, GBGraphicsDrawer, GbMonoColor, FRAME_X, FRAME_Y};
+use ruboy_lib::color::Rgb;
+use ruboy_lib::{Frame, GBGraphicsDrawer, FRAME_X, FRAME_Y};
 
-#[derive(Debug, Clone)]
+use crate::capture::SharedFrameDump;
+
+/// The middle buffer of the triple buffer between the emulation thread and
+/// the UI. Both sides own one buffer of their own, and only lock this one
+/// to swap buffers with it, so neither side ever waits for the other to
+/// finish drawing or reading a frame.
+#[derive(Debug)]
+pub struct FrameHandoff {
+    middle: Mutex<(Box<FrameData>, bool)>,
+
+    /// How much of the previous frame shows through, see
+    /// [crate::config::Config::ghosting]
+    ghosting: Mutex<f32>,
+}
+
+impl FrameHandoff {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            middle: Mutex::new((Box::default(), true)),
+            ghosting: Mutex::new(0.0),
+        })
+    }
+}
+
+/// The writing side of a [FrameHandoff], given to the emulator
+#[derive(Debug)]
 pub struct VideoOutput {
-    pub framebuf: Rc<RefCell<FrameData>>,
-    pub dirty: Rc<RefCell<bool>>,
+    back: Box<FrameData>,
+
+    /// The last frame handed off, which ghosts into the next one
+    shown: Box<FrameData>,
+
+    handoff: Arc<FrameHandoff>,
+    dump: SharedFrameDump,
 }
 
 impl VideoOutput {
-    pub fn new() -> Self {
+    pub fn new(handoff: Arc<FrameHandoff>, dump: SharedFrameDump) -> Self {
         Self {
-            framebuf: Rc::new(RefCell::new(FrameData::default())),
-            dirty: Rc::new(RefCell::new(true)),
+            back: Box::default(),
+            shown: Box::default(),
+            handoff,
+            dump,
+        }
+    }
+}
+
+/// The reading side of a [FrameHandoff], used by the UI
+#[derive(Debug)]
+pub struct FrameReader {
+    front: Box<FrameData>,
+    handoff: Arc<FrameHandoff>,
+}
+
+impl FrameReader {
+    pub fn new(handoff: Arc<FrameHandoff>) -> Self {
+        Self {
+            front: Box::default(),
+            handoff,
+        }
+    }
+
+    /// Takes the newest finished frame, if one was finished since the last
+    /// call. Returns whether the current frame changed.
+    pub fn update(&mut self) -> bool {
+        let mut middle = self.handoff.middle.lock().unwrap();
+
+        if !middle.1 {
+            return false;
         }
+
+        mem::swap(&mut self.front, &mut middle.0);
+        middle.1 = false;
+
+        true
+    }
+
+    pub fn current(&self) -> &FrameData {
+        &self.front
+    }
+
+    /// Blends every new frame with the previous one, keeping `persistence`
+    /// of the previous color. 0 turns blending off.
+    pub fn set_ghosting(&self, persistence: f32) {
+        *self.handoff.ghosting.lock().unwrap() = persistence;
     }
 }
 
@@ -31,31 +105,61 @@ impl Display for VideoOutputErr {
 }
 impl Error for VideoOutputErr {}
 
-pub const WHITE: Color32 = Color32::from_rgb(123, 130, 15);
-pub const LIGHT_GRAY: Color32 = Color32::from_rgb(90, 121, 66);
-pub const DARK_GRAY: Color32 = Color32::from_rgb(57, 89, 74);
-pub const BLACK: Color32 = Color32::from_rgb(41, 65, 57);
+pub const fn rgb_to_color32(rgb: Rgb) -> Color32 {
+    Color32::from_rgb(rgb.r, rgb.g, rgb.b)
+}
+
+/// Mixes `persistence` of the previous color into the new one. Rounds
+/// towards the new color, so fades always finish.
+fn ghost(prev: Color32, new: Color32, persistence: f32) -> Color32 {
+    let mix = |prev: u8, new: u8| {
+        let mixed = f32::from(new) + (f32::from(prev) - f32::from(new)) * persistence;
+
+        if prev > new {
+            mixed.floor() as u8
+        } else {
+            mixed.ceil() as u8
+        }
+    };
+
+    Color32::from_rgb(
+        mix(prev.r(), new.r()),
+        mix(prev.g(), new.g()),
+        mix(prev.b(), new.b()),
+    )
+}
 
 impl GBGraphicsDrawer for VideoOutput {
     type Err = VideoOutputErr;
 
     fn output(&mut self, frame: &Frame) -> std::result::Result<(), Self::Err> {
-        let converted_frame: Vec<Color32> = frame
-            .get_raw()
-            .iter()
-            .map(|color| match color {
-                GbMonoColor::White => WHITE,
-                GbMonoColor::LightGray => LIGHT_GRAY,
-                GbMonoColor::DarkGray => DARK_GRAY,
-                GbMonoColor::Black => BLACK,
-            })
-            .collect();
-
-        for (i, pix) in self.framebuf.borrow_mut().buf.iter_mut().enumerate() {
-            *pix = converted_frame[i];
+        let ghosting = *self.handoff.ghosting.lock().unwrap();
+
+        if ghosting > 0.0 {
+            let pixels = self.back.buf.iter_mut().zip(&self.shown.buf);
+
+            for ((pix, prev), rgb) in pixels.zip(frame.rgb_pixels()) {
+                *pix = ghost(*prev, rgb_to_color32(rgb), ghosting);
+            }
+        } else {
+            for (pix, rgb) in self.back.buf.iter_mut().zip(frame.rgb_pixels()) {
+                *pix = rgb_to_color32(rgb);
+            }
         }
 
-        *self.dirty.borrow_mut() = true;
+        self.shown.buf = self.back.buf;
+
+        let mut middle = self.handoff.middle.lock().unwrap();
+        mem::swap(&mut self.back, &mut middle.0);
+        middle.1 = true;
+        drop(middle);
+
+        let mut dump = self.dump.lock().unwrap();
+
+        if let Some(Err(e)) = dump.as_mut().map(|dump| dump.frame(frame)) {
+            log::error!("Stopped dumping frames: {}", e);
+            *dump = None;
+        }
 
         Ok(())
     }
@@ -91,7 +195,7 @@ impl From<&FrameData> for ColorImage {
 
 impl Default for FrameData {
     fn default() -> Self {
-        let mut default_buf = [WHITE; FRAME_X * FRAME_Y];
+        let mut default_buf = [Color32::WHITE; FRAME_X * FRAME_Y];
 
         let mut cur_color = 0;
         for (y, row) in default_buf.chunks_mut(FRAME_X).enumerate() {
@@ -0,0 +1,339 @@
+use ruboy_lib::isa::{
+    ArithSrc, Bit, Condition, IncDecTarget, Instruction, Ld16Dst, Ld16Src, Ld8Dst, Ld8Src, MemLoc,
+    PrefArithTarget, Reg16, Reg8, RsVec,
+};
+
+use super::operand::{Expr, MemOperand, Operand, Scope};
+use super::AsmErrorKind;
+
+type BuildResult = Result<Instruction, AsmErrorKind>;
+
+fn bad_operands(mnemonic: &str) -> AsmErrorKind {
+    AsmErrorKind::InvalidOperands(mnemonic.to_string())
+}
+
+fn condition(op: &Operand) -> Option<Condition> {
+    match op {
+        Operand::Cond(cond) => Some(*cond),
+        Operand::Reg8(Reg8::C) => Some(Condition::Carry),
+        _ => None,
+    }
+}
+
+fn arith_src(op: &Operand, scope: &Scope) -> Result<Option<ArithSrc>, AsmErrorKind> {
+    Ok(match op {
+        Operand::Reg8(reg) => Some(ArithSrc::Reg(*reg)),
+        Operand::Mem(MemOperand::Reg(Reg16::HL)) => Some(ArithSrc::Mem(MemLoc::Reg(Reg16::HL))),
+        Operand::Imm(expr) => Some(ArithSrc::Imm(expr.eval_u8(scope)?)),
+        _ => None,
+    })
+}
+
+fn pref_target(op: &Operand) -> Option<PrefArithTarget> {
+    match op {
+        Operand::Reg8(reg) => Some(PrefArithTarget::Reg(*reg)),
+        Operand::Mem(MemOperand::Reg(Reg16::HL)) => Some(PrefArithTarget::MemHL),
+        _ => None,
+    }
+}
+
+fn bit(expr: &Expr, scope: &Scope) -> Result<Bit, AsmErrorKind> {
+    Ok(match expr.eval(scope)? {
+        0 => Bit::B0,
+        1 => Bit::B1,
+        2 => Bit::B2,
+        3 => Bit::B3,
+        4 => Bit::B4,
+        5 => Bit::B5,
+        6 => Bit::B6,
+        7 => Bit::B7,
+        other => return scope.out_of_range(other).map(|()| Bit::B0),
+    })
+}
+
+fn rst_vec(expr: &Expr, scope: &Scope) -> Result<RsVec, AsmErrorKind> {
+    Ok(match expr.eval(scope)? {
+        0x00 => RsVec::Rst0,
+        0x08 => RsVec::Rst1,
+        0x10 => RsVec::Rst2,
+        0x18 => RsVec::Rst3,
+        0x20 => RsVec::Rst4,
+        0x28 => RsVec::Rst5,
+        0x30 => RsVec::Rst6,
+        0x38 => RsVec::Rst7,
+        other => return scope.out_of_range(other).map(|()| RsVec::Rst0),
+    })
+}
+
+/// The offset of a relative jump to the target in `expr`
+fn jr_offset(expr: &Expr, scope: &Scope) -> Result<i8, AsmErrorKind> {
+    let target = expr.eval_u16(scope)? as i64;
+    let offset = target - (scope.here as i64 + 2);
+
+    if scope.allow_unknown {
+        return Ok(0);
+    }
+
+    i8::try_from(offset).map_err(|_| AsmErrorKind::JumpTooFar(offset))
+}
+
+/// The address in a `ldh` operand, which must be in high memory
+fn high_addr(mem: &MemOperand, scope: &Scope) -> Result<Option<u8>, AsmErrorKind> {
+    let addr = match mem {
+        MemOperand::High(expr) => return Ok(Some(expr.eval_u8(scope)?)),
+        MemOperand::Addr(expr) => expr.eval(scope)?,
+        _ => return Ok(None),
+    };
+
+    match addr {
+        0xFF00..=0xFFFF => Ok(Some(addr as u8)),
+        0x00..=0xFF => Ok(Some(addr as u8)),
+        _ => scope.out_of_range(addr),
+    }
+}
+
+fn mem_loc(mem: &MemOperand, scope: &Scope) -> Result<Option<MemLoc>, AsmErrorKind> {
+    Ok(match mem {
+        MemOperand::Reg(reg) => Some(MemLoc::Reg(*reg)),
+        MemOperand::HighC => Some(MemLoc::HighMemReg(Reg8::C)),
+        MemOperand::High(expr) => Some(MemLoc::HighMemImm(expr.eval_u8(scope)?)),
+        MemOperand::Addr(expr) => Some(MemLoc::Imm(expr.eval_u16(scope)?)),
+        MemOperand::HlInc | MemOperand::HlDec => None,
+    })
+}
+
+fn load(ops: &[Operand], scope: &Scope) -> BuildResult {
+    use Operand as O;
+
+    let bad = || bad_operands("ld");
+
+    Ok(match ops {
+        [O::Reg8(Reg8::A), O::HlInc | O::Mem(MemOperand::HlInc)] => Instruction::LoadHLItoA,
+        [O::Reg8(Reg8::A), O::HlDec | O::Mem(MemOperand::HlDec)] => Instruction::LoadHLDtoA,
+        [O::HlInc | O::Mem(MemOperand::HlInc), O::Reg8(Reg8::A)] => Instruction::LoadAtoHLI,
+        [O::HlDec | O::Mem(MemOperand::HlDec), O::Reg8(Reg8::A)] => Instruction::LoadAtoHLD,
+
+        [O::Reg16(Reg16::HL), O::SpOffset(expr)] => Instruction::LoadSPi8toHL(expr.eval_i8(scope)?),
+        [O::Reg16(dst), O::Imm(expr)] => {
+            Instruction::Load16(Ld16Dst::Reg(*dst), Ld16Src::Imm(expr.eval_u16(scope)?))
+        }
+        [O::Reg16(dst), O::Reg16(src)] => {
+            Instruction::Load16(Ld16Dst::Reg(*dst), Ld16Src::Reg(*src))
+        }
+        [O::Mem(MemOperand::Addr(expr)), O::Reg16(src)] => Instruction::Load16(
+            Ld16Dst::Mem(MemLoc::Imm(expr.eval_u16(scope)?)),
+            Ld16Src::Reg(*src),
+        ),
+
+        [dst, src] => {
+            let dst = match dst {
+                O::Reg8(reg) => Ld8Dst::Reg(*reg),
+                O::Mem(mem) => Ld8Dst::Mem(mem_loc(mem, scope)?.ok_or_else(bad)?),
+                _ => return Err(bad()),
+            };
+
+            let src = match src {
+                O::Reg8(reg) => Ld8Src::Reg(*reg),
+                O::Mem(mem) => Ld8Src::Mem(mem_loc(mem, scope)?.ok_or_else(bad)?),
+                O::Imm(expr) => Ld8Src::Imm(expr.eval_u8(scope)?),
+                _ => return Err(bad()),
+            };
+
+            Instruction::Load8(dst, src)
+        }
+
+        _ => return Err(bad()),
+    })
+}
+
+fn load_high(ops: &[Operand], scope: &Scope) -> BuildResult {
+    let bad = || bad_operands("ldh");
+
+    Ok(match ops {
+        [Operand::Reg8(Reg8::A), Operand::Mem(MemOperand::HighC)] => Instruction::Load8(
+            Ld8Dst::Reg(Reg8::A),
+            Ld8Src::Mem(MemLoc::HighMemReg(Reg8::C)),
+        ),
+        [Operand::Mem(MemOperand::HighC), Operand::Reg8(Reg8::A)] => Instruction::Load8(
+            Ld8Dst::Mem(MemLoc::HighMemReg(Reg8::C)),
+            Ld8Src::Reg(Reg8::A),
+        ),
+        [Operand::Reg8(Reg8::A), Operand::Mem(mem)] => Instruction::Load8(
+            Ld8Dst::Reg(Reg8::A),
+            Ld8Src::Mem(MemLoc::HighMemImm(high_addr(mem, scope)?.ok_or_else(bad)?)),
+        ),
+        [Operand::Mem(mem), Operand::Reg8(Reg8::A)] => Instruction::Load8(
+            Ld8Dst::Mem(MemLoc::HighMemImm(high_addr(mem, scope)?.ok_or_else(bad)?)),
+            Ld8Src::Reg(Reg8::A),
+        ),
+        _ => return Err(bad()),
+    })
+}
+
+/// Builds the instruction for a mnemonic (in lowercase) and its operands
+pub fn build(mnemonic: &str, ops: &[Operand], scope: &Scope) -> BuildResult {
+    let bad = || bad_operands(mnemonic);
+
+    // The 8 bit arithmetic instructions can leave out the A operand
+    let arith_ops = match ops {
+        [Operand::Reg8(Reg8::A), src] => Some(src),
+        [src] => Some(src),
+        _ => None,
+    };
+
+    let arith = |instr: fn(ArithSrc) -> Instruction| -> BuildResult {
+        let src = arith_ops.ok_or_else(bad)?;
+        Ok(instr(arith_src(src, scope)?.ok_or_else(bad)?))
+    };
+
+    let prefixed = |instr: fn(PrefArithTarget) -> Instruction| -> BuildResult {
+        match ops {
+            [target] => Ok(instr(pref_target(target).ok_or_else(bad)?)),
+            _ => Err(bad()),
+        }
+    };
+
+    let bit_op = |instr: fn(Bit, PrefArithTarget) -> Instruction| -> BuildResult {
+        match ops {
+            [Operand::Imm(expr), target] => Ok(instr(
+                bit(expr, scope)?,
+                pref_target(target).ok_or_else(bad)?,
+            )),
+            _ => Err(bad()),
+        }
+    };
+
+    let no_operands = |instr: Instruction| -> BuildResult {
+        match ops {
+            [] => Ok(instr),
+            _ => Err(bad()),
+        }
+    };
+
+    match mnemonic {
+        "nop" => no_operands(Instruction::Nop),
+        "halt" => no_operands(Instruction::Halt),
+        "ei" => no_operands(Instruction::EI),
+        "di" => no_operands(Instruction::DI),
+        "daa" => no_operands(Instruction::DecimalAdjust),
+        "cpl" => no_operands(Instruction::ComplementAccumulator),
+        "scf" => no_operands(Instruction::SetCarryFlag),
+        "ccf" => no_operands(Instruction::ComplementCarry),
+        "rlca" => no_operands(Instruction::RotLeftCircularA),
+        "rrca" => no_operands(Instruction::RotRightCircularA),
+        "rla" => no_operands(Instruction::RotLeftA),
+        "rra" => no_operands(Instruction::RotRightA),
+        "reti" => no_operands(Instruction::Reti),
+        "stop" => match ops {
+            [] => Ok(Instruction::Stop(0)),
+            [Operand::Imm(expr)] => Ok(Instruction::Stop(expr.eval_u8(scope)?)),
+            _ => Err(bad()),
+        },
+
+        "add" => match ops {
+            [Operand::Reg16(Reg16::HL), Operand::Reg16(src)] => Ok(Instruction::AddHL(*src)),
+            [Operand::Reg16(Reg16::SP), Operand::Imm(expr)] => {
+                Ok(Instruction::AddSP(expr.eval_i8(scope)?))
+            }
+            _ => arith(Instruction::Add),
+        },
+        "adc" => arith(Instruction::AddCarry),
+        "sub" => arith(Instruction::Sub),
+        "sbc" => arith(Instruction::SubCarry),
+        "and" => arith(Instruction::And),
+        "xor" => arith(Instruction::Xor),
+        "or" => arith(Instruction::Or),
+        "cp" | "cmp" => arith(Instruction::Cmp),
+
+        "inc" | "dec" => {
+            let target = match ops {
+                [Operand::Reg8(reg)] => IncDecTarget::Reg8(*reg),
+                [Operand::Reg16(reg)] => IncDecTarget::Reg16(*reg),
+                [Operand::Mem(MemOperand::Reg(Reg16::HL))] => IncDecTarget::MemHL,
+                _ => return Err(bad()),
+            };
+
+            Ok(match mnemonic {
+                "inc" => Instruction::Inc(target),
+                _ => Instruction::Dec(target),
+            })
+        }
+
+        "rlc" => prefixed(Instruction::RotLeftCircular),
+        "rrc" => prefixed(Instruction::RotRightCircular),
+        "rl" => prefixed(Instruction::RotLeft),
+        "rr" => prefixed(Instruction::RotRight),
+        "sla" => prefixed(Instruction::ShiftLeftArith),
+        "sra" => prefixed(Instruction::ShiftRightArith),
+        "swap" => prefixed(Instruction::Swap),
+        "srl" => prefixed(Instruction::ShiftRightLogic),
+        "bit" => bit_op(Instruction::Bit),
+        "res" => bit_op(Instruction::Res),
+        "set" => bit_op(Instruction::Set),
+
+        "ld" => load(ops, scope),
+        "ldh" => load_high(ops, scope),
+        "ldi" => match ops {
+            [Operand::Reg8(Reg8::A), Operand::Mem(MemOperand::Reg(Reg16::HL))] => {
+                Ok(Instruction::LoadHLItoA)
+            }
+            [Operand::Mem(MemOperand::Reg(Reg16::HL)), Operand::Reg8(Reg8::A)] => {
+                Ok(Instruction::LoadAtoHLI)
+            }
+            _ => Err(bad()),
+        },
+        "ldd" => match ops {
+            [Operand::Reg8(Reg8::A), Operand::Mem(MemOperand::Reg(Reg16::HL))] => {
+                Ok(Instruction::LoadHLDtoA)
+            }
+            [Operand::Mem(MemOperand::Reg(Reg16::HL)), Operand::Reg8(Reg8::A)] => {
+                Ok(Instruction::LoadAtoHLD)
+            }
+            _ => Err(bad()),
+        },
+
+        "jp" => match ops {
+            [Operand::Reg16(Reg16::HL)] | [Operand::Mem(MemOperand::Reg(Reg16::HL))] => {
+                Ok(Instruction::JumpHL)
+            }
+            [Operand::Imm(expr)] => Ok(Instruction::Jump(expr.eval_u16(scope)?)),
+            [cond, Operand::Imm(expr)] => Ok(Instruction::JumpIf(
+                expr.eval_u16(scope)?,
+                condition(cond).ok_or_else(bad)?,
+            )),
+            _ => Err(bad()),
+        },
+        "jr" => match ops {
+            [Operand::Imm(expr)] => Ok(Instruction::JumpRel(jr_offset(expr, scope)?)),
+            [cond, Operand::Imm(expr)] => Ok(Instruction::JumpRelIf(
+                jr_offset(expr, scope)?,
+                condition(cond).ok_or_else(bad)?,
+            )),
+            _ => Err(bad()),
+        },
+        "call" => match ops {
+            [Operand::Imm(expr)] => Ok(Instruction::Call(expr.eval_u16(scope)?)),
+            [cond, Operand::Imm(expr)] => Ok(Instruction::CallIf(
+                expr.eval_u16(scope)?,
+                condition(cond).ok_or_else(bad)?,
+            )),
+            _ => Err(bad()),
+        },
+        "ret" => match ops {
+            [] => Ok(Instruction::Ret),
+            [cond] => Ok(Instruction::RetIf(condition(cond).ok_or_else(bad)?)),
+            _ => Err(bad()),
+        },
+        "push" | "pop" => match ops {
+            [Operand::Reg16(reg)] if mnemonic == "push" => Ok(Instruction::Push(*reg)),
+            [Operand::Reg16(reg)] => Ok(Instruction::Pop(*reg)),
+            _ => Err(bad()),
+        },
+        "rst" => match ops {
+            [Operand::Imm(expr)] => Ok(Instruction::Rst(rst_vec(expr, scope)?)),
+            _ => Err(bad()),
+        },
+
+        _ => Err(AsmErrorKind::UnknownMnemonic(mnemonic.to_string())),
+    }
+}
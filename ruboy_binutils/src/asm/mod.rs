@@ -0,0 +1,523 @@
+//! An assembler for a subset of the rgbds syntax, enough to write test ROMs
+//! and demos without external tooling. It accepts everything the `rgdbs`
+//! disassembly format prints, and assembles to a flat image starting at
+//! address zero.
+//!
+//! Each line holds an optional `label:`, followed by an optional instruction
+//! or directive and an optional `; comment`. The directives are `.org expr`,
+//! which moves the output address, `.db`, which emits bytes and `"strings"`,
+//! and `.ds count, fill`, which emits `count` times the `fill` byte, or
+//! zeroes without one. Expressions add and subtract numbers (`$FF`, `0xFF`, `%101`,
+//! `0b101` or decimal), labels and `@`, the current address. Like in rgbds,
+//! `jr` takes the target address, not the offset. The disassembly prints it
+//! relative to `@`, like `jr @ - $7`. The I/O registers are predefined with
+//! their `hardware.inc` names, like `rLCDC`.
+
+use std::collections::HashMap;
+
+use ruboy_lib::isa::encoder::EncodeError;
+use thiserror::Error;
+
+use operand::{Expr, Operand, Scope};
+
+mod instr;
+mod operand;
+
+#[derive(Debug, Error)]
+#[error("Line {line}: {kind}")]
+pub struct AsmError {
+    /// The line the error is on, starting at 1
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Debug, Error)]
+pub enum AsmErrorKind {
+    #[error("Unknown mnemonic or directive: {0}")]
+    UnknownMnemonic(String),
+
+    #[error("Invalid operands for {0}")]
+    InvalidOperands(String),
+
+    #[error("Invalid expression: {0}")]
+    InvalidExpr(String),
+
+    #[error("Invalid number: {0}")]
+    InvalidNumber(String),
+
+    #[error("Invalid label: {0}")]
+    InvalidLabel(String),
+
+    #[error("Unknown label: {0}")]
+    UnknownLabel(String),
+
+    #[error("Label defined twice: {0}")]
+    DuplicateLabel(String),
+
+    #[error("Value out of range: {0}")]
+    OutOfRange(i64),
+
+    #[error("Relative jump too far: {0} bytes")]
+    JumpTooFar(i64),
+
+    #[error("Unterminated string")]
+    UnterminatedString,
+
+    #[error("Output past the end of the address space")]
+    AddressOverflow,
+
+    #[error("Overwrites already assembled output at ${0:04X}")]
+    Overlap(u16),
+
+    #[error("No encoding: {0}")]
+    Encode(#[from] EncodeError),
+}
+
+/// The result of assembling a listing
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+    /// The output, starting at address zero. Gaps are filled with zeroes.
+    pub image: Vec<u8>,
+
+    /// The address of every label
+    pub labels: HashMap<String, u16>,
+}
+
+#[derive(Debug)]
+enum DbItem {
+    Expr(Expr),
+    Str(String),
+}
+
+#[derive(Debug)]
+enum StmtKind {
+    Instr { mnemonic: String, ops: Vec<Operand> },
+    Org(Expr),
+    Db(Vec<DbItem>),
+    Ds { count: Expr, fill: Option<Expr> },
+}
+
+#[derive(Debug)]
+struct Stmt {
+    line: usize,
+    label: Option<String>,
+    kind: Option<StmtKind>,
+}
+
+/// Assembles a listing into a flat image
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    let stmts = source
+        .lines()
+        .enumerate()
+        .map(|(idx, text)| {
+            parse_line(idx + 1, text).map_err(|kind| AsmError {
+                line: idx + 1,
+                kind,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The first pass only finds out where every label ends up. Instructions
+    // have the same size whatever their operand values, so labels that are
+    // not known yet can stand in as zero.
+    let mut labels = HashMap::new();
+    run_pass(&stmts, &mut labels, None)?;
+
+    let mut image = Vec::new();
+    run_pass(&stmts, &mut labels, Some(&mut image))?;
+
+    Ok(Assembly { image, labels })
+}
+
+/// Runs through every statement, defining labels when there is no output
+/// yet, and emitting into the output otherwise
+fn run_pass(
+    stmts: &[Stmt],
+    labels: &mut HashMap<String, u16>,
+    mut output: Option<&mut Vec<u8>>,
+) -> Result<(), AsmError> {
+    let first_pass = output.is_none();
+
+    // Kept wider than an address, so running off the end can be detected
+    let mut addr: u32 = 0;
+    let mut written: Vec<bool> = Vec::new();
+
+    for stmt in stmts {
+        let err = |kind| AsmError {
+            line: stmt.line,
+            kind,
+        };
+
+        if let Some(label) = &stmt.label {
+            let here = u16::try_from(addr).map_err(|_| err(AsmErrorKind::AddressOverflow))?;
+
+            if first_pass && labels.insert(label.clone(), here).is_some() {
+                return Err(err(AsmErrorKind::DuplicateLabel(label.clone())));
+            }
+        }
+
+        let Some(kind) = &stmt.kind else {
+            continue;
+        };
+
+        let here = u16::try_from(addr).map_err(|_| err(AsmErrorKind::AddressOverflow))?;
+
+        let scope = Scope {
+            labels,
+            here,
+            allow_unknown: first_pass,
+        };
+
+        let bytes = match kind {
+            StmtKind::Org(expr) => {
+                // Labels after the .org are not known in the first pass
+                // yet, so they can't move it
+                let org_scope = Scope {
+                    allow_unknown: false,
+                    ..scope
+                };
+
+                addr = expr.eval_u16(&org_scope).map_err(err)? as u32;
+                continue;
+            }
+            StmtKind::Ds { count, fill } => {
+                // Moves the labels after it, just like .org
+                let count_scope = Scope {
+                    allow_unknown: false,
+                    ..scope
+                };
+
+                let count = count.eval_u16(&count_scope).map_err(err)?;
+                let fill = match fill {
+                    Some(fill) => fill.eval_u8(&scope).map_err(err)?,
+                    None => 0,
+                };
+
+                vec![fill; count as usize]
+            }
+            StmtKind::Db(items) => {
+                let mut bytes = Vec::new();
+
+                for item in items {
+                    match item {
+                        DbItem::Expr(expr) => bytes.push(expr.eval_u8(&scope).map_err(err)?),
+                        DbItem::Str(s) => bytes.extend_from_slice(s.as_bytes()),
+                    }
+                }
+
+                bytes
+            }
+            StmtKind::Instr { mnemonic, ops } => instr::build(mnemonic, ops, &scope)
+                .and_then(|instr| Ok(instr.encode()?))
+                .map_err(err)?
+                .to_vec(),
+        };
+
+        let end = addr + bytes.len() as u32;
+
+        if end > 0x10000 {
+            return Err(err(AsmErrorKind::AddressOverflow));
+        }
+
+        if let Some(output) = output.as_deref_mut() {
+            let range = addr as usize..end as usize;
+
+            if output.len() < range.end {
+                output.resize(range.end, 0);
+                written.resize(range.end, false);
+            }
+
+            if let Some(pos) = written[range.clone()].iter().position(|w| *w) {
+                return Err(err(AsmErrorKind::Overlap((addr as usize + pos) as u16)));
+            }
+
+            output[range.clone()].copy_from_slice(&bytes);
+            written[range].fill(true);
+        }
+
+        addr = end;
+    }
+
+    Ok(())
+}
+
+/// Cuts off the comment of a line, leaving semicolons in strings alone
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+
+    for (idx, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &text[..idx],
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// Splits operands on the commas that are not in brackets or strings
+fn split_operands(text: &str) -> Result<Vec<&str>, AsmErrorKind> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut start = 0;
+
+    for (idx, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '[' | '(' if !in_string => depth += 1,
+            ']' | ')' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                parts.push(text[start..idx].trim());
+                start = idx + 1;
+            }
+            _ => {}
+        }
+    }
+
+    if in_string {
+        return Err(AsmErrorKind::UnterminatedString);
+    }
+
+    parts.push(text[start..].trim());
+
+    if parts.iter().any(|part| part.is_empty()) {
+        return Err(AsmErrorKind::InvalidExpr(text.trim().to_string()));
+    }
+
+    Ok(parts)
+}
+
+fn parse_line(line: usize, text: &str) -> Result<Stmt, AsmErrorKind> {
+    let mut rest = strip_comment(text).trim();
+    let mut label = None;
+
+    // Both `label:` and the exported `label::` define a label. Strings are the
+    // only other place a colon can show up.
+    if let Some(colon) = rest.find(':') {
+        if !rest[..colon].contains('"') {
+            let name = rest[..colon].trim();
+
+            if !operand::is_label(name) {
+                return Err(AsmErrorKind::InvalidLabel(name.to_string()));
+            }
+
+            label = Some(name.to_string());
+            rest = rest[colon + 1..].trim_start_matches(':').trim();
+        }
+    }
+
+    let kind = if rest.is_empty() {
+        None
+    } else {
+        let (mnemonic, operands) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let mnemonic = mnemonic.to_lowercase();
+        let operands = operands.trim();
+
+        let parts = if operands.is_empty() {
+            Vec::new()
+        } else {
+            split_operands(operands)?
+        };
+
+        Some(match mnemonic.as_str() {
+            ".org" => match parts.as_slice() {
+                [expr] => StmtKind::Org(Expr::parse(expr)?),
+                _ => return Err(AsmErrorKind::InvalidOperands(mnemonic)),
+            },
+            ".ds" => match parts.as_slice() {
+                [count] => StmtKind::Ds {
+                    count: Expr::parse(count)?,
+                    fill: None,
+                },
+                [count, fill] => StmtKind::Ds {
+                    count: Expr::parse(count)?,
+                    fill: Some(Expr::parse(fill)?),
+                },
+                _ => return Err(AsmErrorKind::InvalidOperands(mnemonic)),
+            },
+            ".db" => {
+                if parts.is_empty() {
+                    return Err(AsmErrorKind::InvalidOperands(mnemonic));
+                }
+
+                StmtKind::Db(
+                    parts
+                        .into_iter()
+                        .map(|part| match part.strip_prefix('"') {
+                            Some(s) => s
+                                .strip_suffix('"')
+                                .map(|s| DbItem::Str(s.to_string()))
+                                .ok_or(AsmErrorKind::UnterminatedString),
+                            None => Expr::parse(part).map(DbItem::Expr),
+                        })
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            _ => StmtKind::Instr {
+                ops: parts
+                    .into_iter()
+                    .map(Operand::parse)
+                    .collect::<Result<_, _>>()?,
+                mnemonic,
+            },
+        })
+    };
+
+    Ok(Stmt { line, label, kind })
+}
+
+#[cfg(test)]
+mod tests {
+    use ruboy_lib::isa::decoder::decode;
+    use ruboy_lib::isa::display::{DisplayableInstruction, FormatOpts, HardwareRegisters};
+    use ruboy_lib::isa::{opcode_table, PREFIX};
+
+    use super::*;
+
+    fn image(source: &str) -> Vec<u8> {
+        assemble(source).unwrap().image
+    }
+
+    fn error(source: &str) -> AsmError {
+        assemble(source).unwrap_err()
+    }
+
+    #[test]
+    fn resolves_labels_both_ways() {
+        let asm = assemble(
+            "
+            start:
+                ld a, 5         ; counter
+            loop:
+                dec a
+                jr nz, loop
+                jp done
+                nop
+            done::
+                call start
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                0x3E, 0x05, // ld a, 5
+                0x3D, // dec a
+                0x20, 0xFD, // jr nz, loop
+                0xC3, 0x09, 0x00, // jp done
+                0x00, // nop
+                0xCD, 0x00, 0x00, // call start
+            ],
+            asm.image
+        );
+        assert_eq!(Some(&0x02), asm.labels.get("loop"));
+        assert_eq!(Some(&0x09), asm.labels.get("done"));
+    }
+
+    #[test]
+    fn directives_and_expressions() {
+        assert_eq!(
+            vec![0x00, 0x00, 0x01, b'a', b';', 0xFF, 0x12, 0x05],
+            image(".org 2\n.db 1, \"a;\", -1\n.db $10 + %10, 0b11 + 2")
+        );
+
+        assert_eq!(
+            vec![0xAA, 0xAA, 0xAA, 0x00, 0x00, 0xC3, 0x05, 0x00],
+            image(".ds 3, $AA\n.ds 2\nend: jp end")
+        );
+
+        // `@` is the address of the instruction itself
+        assert_eq!(vec![0x00, 0xC3, 0x01, 0x00], image("nop\njp @"));
+        assert_eq!(vec![0xF0, 0x40], image("ldh a, [rLCDC]"));
+        assert_eq!(vec![0xF8, 0xFB], image("ld hl, sp + -5"));
+    }
+
+    #[test]
+    fn relative_jumps() {
+        assert_eq!(vec![0x18, 0xFE], image("jr @"));
+        assert_eq!(vec![0x18, 0x00], image("jr @ + 2"));
+        assert_eq!(vec![0x00, 0x38, 0xFD], image("nop\njr c, @ - 1"));
+        assert_eq!(vec![0x18, 0x7F], image("jr @ + $81"));
+
+        assert!(matches!(
+            error("jr @ + $82").kind,
+            AsmErrorKind::JumpTooFar(128)
+        ));
+    }
+
+    #[test]
+    fn reports_the_failing_line() {
+        let err = error("nop\n\nfoo a");
+        assert_eq!(3, err.line);
+        assert!(matches!(err.kind, AsmErrorKind::UnknownMnemonic(m) if m == "foo"));
+
+        assert!(matches!(
+            error("a:\na:").kind,
+            AsmErrorKind::DuplicateLabel(label) if label == "a"
+        ));
+        assert!(matches!(
+            error("jp nowhere").kind,
+            AsmErrorKind::UnknownLabel(_)
+        ));
+        assert!(matches!(
+            error("ld a, $100").kind,
+            AsmErrorKind::OutOfRange(0x100)
+        ));
+        assert!(matches!(
+            error("push a").kind,
+            AsmErrorKind::InvalidOperands(_)
+        ));
+        assert!(matches!(
+            error("ld [hl], [hl]").kind,
+            AsmErrorKind::Encode(_)
+        ));
+        assert!(matches!(
+            error(".db \"abc").kind,
+            AsmErrorKind::UnterminatedString
+        ));
+        assert!(matches!(
+            error("nop\nnop\n.org 1\nnop").kind,
+            AsmErrorKind::Overlap(1)
+        ));
+        assert!(matches!(
+            error(".org $FFFF\nld a, 0").kind,
+            AsmErrorKind::AddressOverflow
+        ));
+    }
+
+    /// Every opcode, displayed the way the disassembler does, assembles back
+    /// to the same bytes
+    #[test]
+    fn display_round_trips() {
+        let formats = [
+            FormatOpts::rgdbs(),
+            FormatOpts::rgdbs().with_symbols(HardwareRegisters),
+        ];
+
+        for info in opcode_table() {
+            // Small and large values, negative offsets and a high RAM
+            // register with a name
+            for [lo, hi] in [[0x12, 0x34], [0xF7, 0xFF], [0x40, 0xFF]] {
+                let bytes = if info.prefixed {
+                    vec![PREFIX, info.opcode]
+                } else {
+                    [info.opcode, lo, hi][..info.len as usize].to_vec()
+                };
+
+                let instr = decode(&bytes.as_slice(), 0).unwrap();
+
+                for fmt in &formats {
+                    let text = DisplayableInstruction::from(instr).with_format(fmt);
+
+                    // Away from zero, so relative jumps can go backwards
+                    let asm = assemble(&format!(".org $1000\n{}", text))
+                        .unwrap_or_else(|e| panic!("{}: {}", text, e));
+
+                    assert_eq!(bytes, asm.image[0x1000..], "{}", text);
+                }
+            }
+        }
+    }
+}
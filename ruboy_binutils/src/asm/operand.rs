@@ -0,0 +1,310 @@
+use std::collections::HashMap;
+
+use ruboy_lib::isa::{display::HardwareRegisters, Condition, Reg16, Reg8};
+
+use super::AsmErrorKind;
+
+/// A value, made of numbers and labels added or subtracted from each other
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    terms: Vec<(bool, Term)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Term {
+    Num(i64),
+    Label(String),
+
+    /// `@`, the address of the current instruction
+    Here,
+}
+
+/// What expressions are evaluated against
+pub struct Scope<'a> {
+    pub labels: &'a HashMap<String, u16>,
+
+    /// The address of the current instruction or directive
+    pub here: u16,
+
+    /// In the first pass, labels defined further down are not known yet, and
+    /// evaluate to zero
+    pub allow_unknown: bool,
+}
+
+impl Scope<'_> {
+    /// Fails on a value that does not fit its operand. In the first pass
+    /// values can still be off because of unknown labels, and the second pass
+    /// checks them again, so it settles for a placeholder there.
+    pub fn out_of_range<T: Default>(&self, val: i64) -> Result<T, AsmErrorKind> {
+        if self.allow_unknown {
+            Ok(T::default())
+        } else {
+            Err(AsmErrorKind::OutOfRange(val))
+        }
+    }
+}
+
+impl Expr {
+    pub fn parse(s: &str) -> Result<Self, AsmErrorKind> {
+        let mut terms = Vec::new();
+        let mut rest = s;
+        let mut negative = false;
+
+        loop {
+            // Unary signs, like in `sp + -5`
+            loop {
+                rest = rest.trim_start();
+
+                if let Some(stripped) = rest.strip_prefix('-') {
+                    negative = !negative;
+                    rest = stripped;
+                } else if let Some(stripped) = rest.strip_prefix('+') {
+                    rest = stripped;
+                } else {
+                    break;
+                }
+            }
+
+            let end = rest.find(['+', '-']).unwrap_or(rest.len());
+            let term = rest[..end].trim();
+
+            if term.is_empty() {
+                return Err(AsmErrorKind::InvalidExpr(s.to_string()));
+            }
+
+            terms.push((negative, Term::parse(term)?));
+
+            if end == rest.len() {
+                break;
+            }
+
+            negative = rest[end..].starts_with('-');
+            rest = &rest[end + 1..];
+        }
+
+        Ok(Self { terms })
+    }
+
+    pub fn eval(&self, scope: &Scope) -> Result<i64, AsmErrorKind> {
+        let mut total: i64 = 0;
+
+        for (negative, term) in &self.terms {
+            let val = match term {
+                Term::Num(num) => *num,
+                Term::Here => scope.here as i64,
+                Term::Label(label) => match scope
+                    .labels
+                    .get(label)
+                    .copied()
+                    .or_else(|| HardwareRegisters::addr(label))
+                {
+                    Some(addr) => addr as i64,
+                    None if scope.allow_unknown => 0,
+                    None => return Err(AsmErrorKind::UnknownLabel(label.clone())),
+                },
+            };
+
+            total = if *negative { total - val } else { total + val };
+        }
+
+        Ok(total)
+    }
+
+    /// An 8 bit value, either signed or unsigned
+    pub fn eval_u8(&self, scope: &Scope) -> Result<u8, AsmErrorKind> {
+        let val = self.eval(scope)?;
+
+        if !(-0x80..=0xFF).contains(&val) {
+            return scope.out_of_range(val);
+        }
+
+        Ok(val as u8)
+    }
+
+    pub fn eval_i8(&self, scope: &Scope) -> Result<i8, AsmErrorKind> {
+        let val = self.eval(scope)?;
+
+        match i8::try_from(val) {
+            Ok(val) => Ok(val),
+            Err(_) => scope.out_of_range(val),
+        }
+    }
+
+    /// A 16 bit value, either signed or unsigned
+    pub fn eval_u16(&self, scope: &Scope) -> Result<u16, AsmErrorKind> {
+        let val = self.eval(scope)?;
+
+        if !(-0x8000..=0xFFFF).contains(&val) {
+            return scope.out_of_range(val);
+        }
+
+        Ok(val as u16)
+    }
+}
+
+impl Term {
+    fn parse(s: &str) -> Result<Self, AsmErrorKind> {
+        let invalid = || AsmErrorKind::InvalidNumber(s.to_string());
+
+        let (digits, radix) = if let Some(hex) = s.strip_prefix('$') {
+            (hex, 16)
+        } else if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            (hex, 16)
+        } else if let Some(bin) = s.strip_prefix('%') {
+            (bin, 2)
+        } else if let Some(bin) = s.strip_prefix("0b").or_else(|| s.strip_prefix("0B")) {
+            (bin, 2)
+        } else if s == "@" {
+            return Ok(Term::Here);
+        } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+            (s, 10)
+        } else if is_label(s) {
+            return Ok(Term::Label(s.to_string()));
+        } else {
+            return Err(invalid());
+        };
+
+        i64::from_str_radix(digits, radix)
+            .map(Term::Num)
+            .map_err(|_| invalid())
+    }
+}
+
+/// Labels start with a letter, `_` or `.`, followed by letters, digits, `_`
+/// and `.`
+pub fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// An operand in memory brackets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MemOperand {
+    /// `[bc]`, `[de]` or `[hl]`
+    Reg(Reg16),
+
+    /// `[hli]` or `[hl+]`
+    HlInc,
+
+    /// `[hld]` or `[hl-]`
+    HlDec,
+
+    /// `[c]` or `[$FF00 + c]`
+    HighC,
+
+    /// `[$FF00 + n8]`
+    High(Expr),
+
+    /// `[n16]`
+    Addr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operand {
+    Reg8(Reg8),
+    Reg16(Reg16),
+
+    /// `nz`, `z` and `nc`. The carry condition parses as [Reg8::C].
+    Cond(Condition),
+
+    /// `hli`/`hl+` and `hld`/`hl-` without brackets, as the disassembler
+    /// shows them
+    HlInc,
+    HlDec,
+
+    /// `sp + e8`
+    SpOffset(Expr),
+
+    Mem(MemOperand),
+    Imm(Expr),
+}
+
+impl Operand {
+    pub fn parse(s: &str) -> Result<Self, AsmErrorKind> {
+        let s = s.trim();
+
+        let bracketed = s
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .or_else(|| s.strip_prefix('(').and_then(|s| s.strip_suffix(')')));
+
+        if let Some(inner) = bracketed {
+            return Ok(Operand::Mem(MemOperand::parse(inner)?));
+        }
+
+        let compact: String = s
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+
+        Ok(match compact.as_str() {
+            "a" => Operand::Reg8(Reg8::A),
+            "b" => Operand::Reg8(Reg8::B),
+            "c" => Operand::Reg8(Reg8::C),
+            "d" => Operand::Reg8(Reg8::D),
+            "e" => Operand::Reg8(Reg8::E),
+            "h" => Operand::Reg8(Reg8::H),
+            "l" => Operand::Reg8(Reg8::L),
+            "af" => Operand::Reg16(Reg16::AF),
+            "bc" => Operand::Reg16(Reg16::BC),
+            "de" => Operand::Reg16(Reg16::DE),
+            "hl" => Operand::Reg16(Reg16::HL),
+            "sp" => Operand::Reg16(Reg16::SP),
+            "nz" => Operand::Cond(Condition::NotZero),
+            "z" => Operand::Cond(Condition::Zero),
+            "nc" => Operand::Cond(Condition::NotCarry),
+            "hli" | "hl+" => Operand::HlInc,
+            "hld" | "hl-" => Operand::HlDec,
+            _ => match sp_offset(s) {
+                Some(offset) => Operand::SpOffset(Expr::parse(offset)?),
+                None => Operand::Imm(Expr::parse(s)?),
+            },
+        })
+    }
+}
+
+/// The offset in `sp + e8`, including its sign
+fn sp_offset(s: &str) -> Option<&str> {
+    let (sp, offset) = s.split_at_checked(2)?;
+    let offset = offset.trim_start();
+
+    (sp.eq_ignore_ascii_case("sp") && offset.starts_with(['+', '-'])).then_some(offset)
+}
+
+impl MemOperand {
+    fn parse(s: &str) -> Result<Self, AsmErrorKind> {
+        let compact: String = s
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+
+        Ok(match compact.as_str() {
+            "bc" => MemOperand::Reg(Reg16::BC),
+            "de" => MemOperand::Reg(Reg16::DE),
+            "hl" => MemOperand::Reg(Reg16::HL),
+            "hli" | "hl+" => MemOperand::HlInc,
+            "hld" | "hl-" => MemOperand::HlDec,
+            "c" | "$ff00+c" | "0xff00+c" => MemOperand::HighC,
+            _ => {
+                let offset = compact
+                    .strip_prefix("$ff00+")
+                    .or_else(|| compact.strip_prefix("0xff00+"));
+
+                match offset {
+                    // Keep the case of labels
+                    Some(_) => {
+                        let plus = s.find('+').expect("Prefix contains a plus");
+                        MemOperand::High(Expr::parse(&s[plus + 1..])?)
+                    }
+                    None => MemOperand::Addr(Expr::parse(s)?),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use ruboy_binutils::{asm::assemble, cli::asm, header::fix_header};
+
+fn main() -> Result<()> {
+    let args = asm::CLIArgs::parse();
+
+    let source = std::fs::read_to_string(&args.file).context("Failed to read file")?;
+
+    let assembly = assemble(&source).with_context(|| format!("{}", args.file.display()))?;
+    let mut image = assembly.image;
+
+    if let Some(size) = args.pad_to {
+        if image.len() < size {
+            image.resize(size, 0);
+        }
+    }
+
+    if args.fix_header {
+        fix_header(&mut image, true)?;
+    }
+
+    let output = args
+        .output
+        .unwrap_or_else(|| args.file.with_extension("gb"));
+
+    std::fs::write(&output, &image).context("Failed to write output")?;
+
+    println!(
+        "Assembled {} bytes and {} labels into {}",
+        image.len(),
+        assembly.labels.len(),
+        output.display()
+    );
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use std::{fs::File, io::BufReader, time::Instant};
+
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use ruboy_binutils::{
+    cli::bench,
+    headless::{NullInput, NullOutput},
+    output::{Field, Report},
+    ListOutput,
+};
+use ruboy_lib::{model::RuboyConfig, InlineAllocator, RenderMode, Ruboy, DESIRED_FRAMERATE};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct BenchReport {
+    frames: u64,
+    seconds: f64,
+    frames_per_second: f64,
+
+    /// How many times faster than a real Gameboy
+    speed: f64,
+}
+
+impl Report for BenchReport {
+    type Row = Field;
+
+    fn text(&self) -> String {
+        let mut output = ListOutput::new();
+
+        output.add_single("Frames", self.frames);
+        output.add_single("Time", format!("{:.3} s", self.seconds));
+        output.add_single(
+            "Frames per second",
+            format!("{:.1}", self.frames_per_second),
+        );
+        output.add_single("Speed", format!("{:.2}x real time", self.speed));
+
+        output.to_string()
+    }
+
+    fn rows(&self) -> Vec<Field> {
+        vec![
+            Field::new("frames", self.frames),
+            Field::new("seconds", self.seconds),
+            Field::new("frames_per_second", self.frames_per_second),
+            Field::new("speed", self.speed),
+        ]
+    }
+}
+
+fn main() -> Result<()> {
+    let args = bench::CLIArgs::parse();
+
+    let file = File::open(&args.file).context("Failed to open file")?;
+
+    let config = RuboyConfig {
+        render_mode: if args.scanline {
+            RenderMode::Scanline
+        } else {
+            RenderMode::Fifo
+        },
+        ..args.config
+    };
+
+    let mut ruboy = Ruboy::<InlineAllocator, _, _, _>::with_config(
+        BufReader::new(file),
+        NullOutput,
+        NullInput,
+        config,
+    )
+    .map_err(|e| anyhow!("Could not initialize emulator: {}", e))?;
+
+    for frame in 0..args.warmup {
+        ruboy
+            .step_frame()
+            .map_err(|e| anyhow!("Emulation failed in warmup frame {}: {}", frame, e))?;
+    }
+
+    let start = Instant::now();
+
+    for frame in 0..args.frames {
+        ruboy
+            .step_frame()
+            .map_err(|e| anyhow!("Emulation failed in frame {}: {}", frame, e))?;
+    }
+
+    let seconds = start.elapsed().as_secs_f64();
+    let frames_per_second = args.frames as f64 / seconds;
+
+    let report = BenchReport {
+        frames: args.frames,
+        seconds,
+        frames_per_second,
+        speed: frames_per_second / DESIRED_FRAMERATE,
+    };
+
+    args.output_format.print(&report)?;
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use std::{fs::File, io::BufReader, path::Path, process::ExitCode};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Parser;
+use ruboy_binutils::{
+    cli::bisect::{self, Compare},
+    headless::NullOutput,
+    ListOutput,
+};
+use ruboy_lib::{
+    model::RuboyConfig,
+    movie::{Movie, ReplayInputHandler},
+    FrameEvent, InlineAllocator, Ruboy,
+};
+
+type HeadlessRuboy = Ruboy<InlineAllocator, BufReader<File>, NullOutput, ReplayInputHandler>;
+
+fn start(file: &Path, config: RuboyConfig, movie: Option<&Movie>) -> Result<HeadlessRuboy> {
+    let file = File::open(file).context("Failed to open file")?;
+
+    let mut ruboy = Ruboy::with_config(
+        BufReader::new(file),
+        NullOutput,
+        ReplayInputHandler::default(),
+        config,
+    )
+    .map_err(|e| anyhow!("Could not initialize emulator: {}", e))?;
+
+    if let Some(movie) = movie {
+        movie
+            .restore_start(&mut ruboy)
+            .map_err(|e| anyhow!("Could not restore the start of the movie: {}", e))?;
+
+        *ruboy.input_mut() = ReplayInputHandler::new(movie);
+    }
+
+    Ok(ruboy)
+}
+
+fn load_movie(path: &Path) -> Result<Movie> {
+    let bytes = std::fs::read(path).context("Failed to read movie")?;
+
+    Movie::from_bytes(&bytes).map_err(|e| anyhow!("Failed to load movie: {}", e))
+}
+
+/// Runs a single frame, and returns the hash to compare. [None] if the LCD
+/// was off during the whole frame
+fn run_frame(ruboy: &mut HeadlessRuboy, compare: Compare) -> Result<Option<u64>> {
+    let frame_hash = match ruboy.step_frame().map_err(|e| anyhow!("{}", e))? {
+        FrameEvent::Completed(frame) => Some(frame.digest()),
+        FrameEvent::LcdOff => None,
+        FrameEvent::Stopped(reason) => bail!("Unexpected stop: {:?}", reason),
+    };
+
+    Ok(match compare {
+        Compare::Frame => frame_hash,
+        Compare::State => Some(ruboy.state_digest()),
+    })
+}
+
+fn format_hash(hash: Option<u64>) -> String {
+    match hash {
+        Some(hash) => format!("{:016x}", hash),
+        None => "LCD off".to_string(),
+    }
+}
+
+fn display_divergence(frame: u64, runs: [(&RuboyConfig, &HeadlessRuboy, Option<u64>); 2]) {
+    println!("Configurations diverge at frame {}", frame);
+
+    let mut output = ListOutput::new();
+
+    for ((config, ruboy, hash), name) in runs.into_iter().zip(["A", "B"]) {
+        let regs = ruboy.registers();
+
+        output.add_multiple(
+            name,
+            vec![
+                format!("{:?}", config),
+                format!("Hash: {}", format_hash(hash)),
+                format!(
+                    "AF={:02X}{:02X} BC={:02X}{:02X} DE={:02X}{:02X} HL={:02X}{:02X} SP={:04X} PC={:04X}",
+                    regs.a, regs.f, regs.b, regs.c, regs.d, regs.e, regs.h, regs.l, regs.sp, regs.pc
+                ),
+            ],
+        );
+    }
+
+    print!("{}", output);
+}
+
+fn main() -> Result<ExitCode> {
+    let args = bisect::CLIArgs::parse();
+
+    let movie = args.movie.as_deref().map(load_movie).transpose()?;
+
+    let mut ruboy_a =
+        start(&args.file, args.a, movie.as_ref()).context("Configuration A failed to start")?;
+    let mut ruboy_b =
+        start(&args.file, args.b, movie.as_ref()).context("Configuration B failed to start")?;
+
+    for frame in 0..args.frames {
+        let hash_a = run_frame(&mut ruboy_a, args.compare)
+            .with_context(|| format!("Configuration A failed in frame {}", frame))?;
+
+        let hash_b = run_frame(&mut ruboy_b, args.compare)
+            .with_context(|| format!("Configuration B failed in frame {}", frame))?;
+
+        if hash_a != hash_b {
+            display_divergence(
+                frame,
+                [(&args.a, &ruboy_a, hash_a), (&args.b, &ruboy_b, hash_b)],
+            );
+
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    println!("No divergence in {} frames", args.frames);
+
+    Ok(ExitCode::SUCCESS)
+}
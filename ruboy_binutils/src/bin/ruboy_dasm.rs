@@ -1,77 +1,28 @@
-use std::{
-    cell::{Cell, RefCell},
-    collections::HashMap,
-    fs::File,
-    io::{self, BufReader, Read, Seek},
-};
+use std::fmt::Write;
 
 use anyhow::{Context, Result};
 use clap::Parser;
 use ruboy_binutils::{
     cli::dasm::{self, CLIArgs},
-    ListOutput,
+    dasm::{bank_addr, Disassembly, BANK_SIZE},
+    output::Report,
 };
 use ruboy_lib::isa::{
-    decoder::{decode, DecoderReadable},
-    display::{DisplayableInstruction, FormatOpts, ImmediateFormat},
+    display::{
+        DisplayableInstruction, FormatOpts, HardwareRegisters, ImmediateFormat, SymbolResolver,
+    },
     Instruction,
 };
+use serde::Serialize;
 
-// TODO: Pull into main lib and properly implement Read and Seek traits
-struct SmartReader<R: Read + Seek + ?Sized> {
-    pos: Cell<usize>,
-    reader: RefCell<BufReader<R>>,
-}
-
-impl<R: Read + Seek> SmartReader<R> {
-    pub fn new(read: R) -> Self {
-        let mut bufreader = BufReader::new(read);
-        let init_pos = bufreader.stream_position().unwrap();
-
-        Self {
-            pos: Cell::new(init_pos as usize),
-            reader: RefCell::new(bufreader),
-        }
-    }
-}
-
-impl<R: Read + Seek + ?Sized> DecoderReadable for SmartReader<R> {
-    type Err = io::Error;
-    fn read_at(&self, idx: usize) -> Result<u8, Self::Err> {
-        let mut reader = self.reader.borrow_mut();
-        let cur_pos = self.pos.get();
-        let offset = idx.wrapping_sub(cur_pos) as isize;
-
-        reader.seek_relative(offset as i64)?;
-        let mut buf: [u8; 1] = [0; 1];
+/// The column comments start at
+const COMMENT_COLUMN: usize = 32;
 
-        reader.read_exact(&mut buf)?;
-        self.pos.replace(idx + 1);
-
-        Ok(buf[0])
-    }
-}
-
-fn display_output(print_addr: bool, instructions: &HashMap<usize, String>) {
-    let mut sorted: Vec<(usize, _)> = instructions
-        .iter()
-        .map(|(&addr, instr)| (addr, instr))
-        .collect();
-
-    sorted.sort_by(|x, y| usize::cmp(&x.0, &y.0));
-
-    let mut output = ListOutput::new();
-
-    for (addr, instr) in sorted {
-        if print_addr {
-            output.add_single(format!("0x{:x}", addr), instr);
-        } else {
-            output.add_single("", instr);
-        }
-    }
+/// The most bytes in a single `.db` line
+const DATA_PER_LINE: usize = 8;
 
-    println!("{}", output);
-}
+/// Runs of at least this many equal bytes become a single `.ds`
+const MIN_FILL_RUN: usize = 16;
 
 fn to_format_opts(args: &CLIArgs) -> FormatOpts {
     let mut opts = FormatOpts::rgdbs();
@@ -96,45 +47,252 @@ fn to_format_opts(args: &CLIArgs) -> FormatOpts {
         opts.operand_order = op_order.into();
     }
 
+    if !args.no_symbols {
+        opts = opts.with_symbols(HardwareRegisters);
+    }
+
     opts
 }
 
-fn format_instruction(instr: Instruction, opts: &FormatOpts) -> String {
+/// Formats an instruction, naming its jump or call target after the label
+/// there, if it has one
+fn format_instruction(
+    instr: Instruction,
+    opts: &FormatOpts,
+    target: Option<(u16, String)>,
+) -> String {
     let displayable = DisplayableInstruction::from(instr);
 
-    displayable.with_format(opts)
+    match target {
+        Some((target_addr, name)) if opts.symbols.is_some() => {
+            let opts = opts.clone().with_symbols(move |addr| {
+                if addr == target_addr {
+                    Some(name.clone())
+                } else {
+                    HardwareRegisters.resolve(addr)
+                }
+            });
+
+            displayable.with_format(&opts)
+        }
+        _ => displayable.with_format(opts),
+    }
 }
 
-fn main() -> Result<()> {
-    let args = dasm::CLIArgs::parse();
-    let format_opts = to_format_opts(&args);
-    let filepath = args.file.clone();
-    let file = File::open(filepath).context("Failed to open file")?;
+/// Whether the instruction shows its target as an address, instead of an
+/// offset or a vector
+fn absolute_target(instr: Instruction) -> bool {
+    matches!(
+        instr,
+        Instruction::Jump(_)
+            | Instruction::JumpIf(..)
+            | Instruction::Call(_)
+            | Instruction::CallIf(..)
+    )
+}
 
-    let reader = SmartReader::new(file);
+/// Writes a line of the listing, followed by the comments, if any
+fn write_line(out: &mut String, line: String, comments: &[String]) {
+    if comments.is_empty() {
+        out.push_str(&line);
+    } else {
+        // Keep a space before the comment when the line is too long
+        let _ = write!(
+            out,
+            "{:width$} ; {}",
+            line,
+            comments.join(", "),
+            width = COMMENT_COLUMN - 1
+        );
+    }
 
-    let mut instructions: HashMap<usize, Instruction> = HashMap::new();
+    out.push('\n');
+}
+
+/// The location of an offset, as shown in the listing
+fn location(offset: usize) -> String {
+    let (bank, addr) = bank_addr(offset);
 
-    let mut cur_addr: usize = 0x0;
+    format!("{:03X}:{:04X}", bank, addr)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
 
-    while let Ok(instr) = decode(&reader, cur_addr as u16) {
-        let existing = instructions.insert(cur_addr, instr);
+/// An instruction or some data in the listing
+#[derive(Debug, Clone, Serialize)]
+struct Line {
+    offset: usize,
+    bank: usize,
+    addr: u16,
 
-        assert!(existing.is_none());
+    /// The label placed before the line
+    label: Option<String>,
 
-        if let Instruction::IllegalInstruction(_) = instr {
-            cur_addr += 1;
-        } else {
-            cur_addr += instr.len() as usize;
+    /// The instruction or data directive
+    text: String,
+    len: usize,
+
+    /// Left out for runs of equal bytes
+    bytes: Option<String>,
+
+    /// The label of the jump or call target
+    target: Option<String>,
+
+    /// Whether the text already names the target
+    #[serde(skip)]
+    target_in_text: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Listing {
+    /// Jump targets in an unknown bank
+    unresolved: Vec<u16>,
+
+    lines: Vec<Line>,
+
+    #[serde(skip)]
+    print_addr: bool,
+}
+
+/// The data from `offset` up to the next code, label or bank, as the text to
+/// print and the amount of bytes in it, and whether it is a run of one byte
+fn data_line(rom: &[u8], dasm: &Disassembly, offset: usize) -> (String, usize, bool) {
+    let end = (offset + 1..rom.len())
+        .find(|next| {
+            next % BANK_SIZE == 0 || dasm.code.contains_key(next) || dasm.labels.contains_key(next)
+        })
+        .unwrap_or(rom.len());
+
+    let data = &rom[offset..end];
+    let run = data.iter().take_while(|b| **b == data[0]).count();
+
+    if run >= MIN_FILL_RUN {
+        return (format!(".ds {}, ${:02X}", run, data[0]), run, true);
+    }
+
+    // Leave a run that follows for its own line
+    let len = (1..data.len().min(DATA_PER_LINE))
+        .find(|start| {
+            data[*start..]
+                .iter()
+                .take_while(|b| **b == data[*start])
+                .count()
+                >= MIN_FILL_RUN
+        })
+        .unwrap_or(data.len().min(DATA_PER_LINE));
+
+    let bytes: Vec<String> = data[..len].iter().map(|b| format!("${:02X}", b)).collect();
+
+    (format!(".db {}", bytes.join(", ")), len, false)
+}
+
+impl Listing {
+    fn new(rom: &[u8], dasm: &Disassembly, opts: &FormatOpts, print_addr: bool) -> Self {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+
+        while offset < rom.len() {
+            let instr = dasm.code.get(&offset).copied();
+
+            let target = dasm
+                .targets
+                .get(&offset)
+                .and_then(|target| Some((bank_addr(*target).1, dasm.labels.get(target)?.name())));
+
+            let target_in_text =
+                target.is_some() && opts.symbols.is_some() && instr.is_some_and(absolute_target);
+
+            let (text, len, fill) = match instr {
+                Some(instr) => (
+                    format_instruction(instr, opts, target.clone()),
+                    instr.len() as usize,
+                    false,
+                ),
+                None => data_line(rom, dasm, offset),
+            };
+
+            let (bank, addr) = bank_addr(offset);
+
+            lines.push(Line {
+                offset,
+                bank,
+                addr,
+                label: dasm.labels.get(&offset).map(|label| label.name()),
+                text,
+                len,
+                bytes: (!fill).then(|| hex(&rom[offset..offset + len])),
+                target: target.map(|(_, name)| name),
+                target_in_text,
+            });
+
+            offset += len;
+        }
+
+        Self {
+            unresolved: dasm.unresolved.clone(),
+            lines,
+            print_addr,
         }
     }
+}
+
+impl Report for Listing {
+    type Row = Line;
+
+    fn text(&self) -> String {
+        let mut out = String::new();
 
-    let instructions_formatted: HashMap<_, _> = instructions
-        .into_iter()
-        .map(|(addr, instr)| (addr, format_instruction(instr, &format_opts)))
-        .collect();
+        for addr in &self.unresolved {
+            let _ = writeln!(out, "; Jump to ${:04X} in an unknown bank", addr);
+        }
+
+        for line in &self.lines {
+            if line.offset % BANK_SIZE == 0 {
+                if line.offset != 0 {
+                    out.push('\n');
+                }
+
+                let _ = writeln!(out, "; ROM bank {}", line.bank);
+            }
+
+            if let Some(label) = &line.label {
+                let _ = writeln!(out, "\n{}:", label);
+            }
+
+            let mut comments = Vec::new();
+
+            if self.print_addr {
+                comments.push(location(line.offset));
+            }
+
+            if let Some(target) = line.target.as_ref().filter(|_| !line.target_in_text) {
+                comments.push(format!("-> {}", target));
+            }
+
+            write_line(&mut out, format!("    {}", line.text), &comments);
+        }
+
+        out
+    }
+
+    fn rows(&self) -> Vec<Line> {
+        self.lines.clone()
+    }
+}
+
+fn main() -> Result<()> {
+    let args = dasm::CLIArgs::parse();
+    let format_opts = to_format_opts(&args);
 
-    display_output(!args.no_print_label, &instructions_formatted);
+    let rom = std::fs::read(&args.file).context("Failed to read file")?;
+    let dasm = Disassembly::trace(&rom, &args.entry);
 
-    Ok(())
+    args.output_format.print(&Listing::new(
+        &rom,
+        &dasm,
+        &format_opts,
+        !args.no_print_label,
+    ))
 }
@@ -0,0 +1,85 @@
+use std::process::ExitCode;
+
+use anyhow::{Context, Result};
+use clap::Parser;
+use colored::*;
+use ruboy_binutils::{
+    cli::fixrom,
+    header::{check_logo, fix_header, HeaderDiff},
+    ListOutput,
+};
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn add_diff(output: &mut ListOutput, diff: &HeaderDiff, fixed: bool) {
+    let status = if fixed {
+        "fixed".green()
+    } else {
+        "not fixed".red()
+    };
+
+    if diff.found.len() <= 2 {
+        output.add_single(
+            diff.field,
+            format!(
+                "0x{} -> 0x{} ({})",
+                hex(&diff.found),
+                hex(&diff.expected),
+                status
+            ),
+        );
+    } else {
+        let bytes: Vec<String> = diff
+            .bytes()
+            .map(|(offset, found, expected)| {
+                format!("0x{:04x}: 0x{:02x} -> 0x{:02x}", offset, found, expected)
+            })
+            .collect();
+
+        output.add_multiple(format!("{} ({})", diff.field, status), bytes);
+    }
+}
+
+fn main() -> Result<ExitCode> {
+    let args = fixrom::CLIArgs::parse();
+
+    let mut rom = std::fs::read(&args.file).context("Failed to open file")?;
+
+    let bad_logo = match args.fix_logo {
+        true => None,
+        false => check_logo(&rom)?,
+    };
+
+    let diffs = fix_header(&mut rom, args.fix_logo)?;
+
+    let mut output = ListOutput::new();
+
+    for diff in &diffs {
+        add_diff(&mut output, diff, !args.dry_run);
+    }
+
+    if let Some(logo) = &bad_logo {
+        add_diff(&mut output, logo, false);
+    }
+
+    if diffs.is_empty() && bad_logo.is_none() {
+        println!("Nothing to fix");
+    } else {
+        print!("{}", output);
+    }
+
+    if !args.dry_run && !diffs.is_empty() {
+        let path = args.output.as_ref().unwrap_or(&args.file);
+        std::fs::write(path, &rom).context("Failed to write ROM")?;
+    }
+
+    let failed = bad_logo.is_some() || (args.dry_run && !diffs.is_empty());
+
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
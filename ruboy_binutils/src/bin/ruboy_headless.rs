@@ -0,0 +1,274 @@
+use std::{fs::File, io::BufReader, path::Path, process::ExitCode};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Parser;
+use ruboy_binutils::{
+    cli::headless,
+    headless::{LastFrame, NullOutput, SerialCapture},
+    output::{Field, Report},
+    ListOutput,
+};
+use ruboy_lib::{
+    movie::{Movie, ReplayInputHandler},
+    FrameEvent, InlineAllocator, Ruboy,
+};
+use serde::Serialize;
+
+type HeadlessRuboy = Ruboy<InlineAllocator, BufReader<File>, NullOutput, ReplayInputHandler>;
+
+/// How often the serial output is checked when running for a number of
+/// cycles. About a frame.
+const CHECK_INTERVAL_CYCLES: u64 = 70224;
+
+#[derive(Debug, Serialize)]
+struct CpuState {
+    a: u8,
+    f: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    h: u8,
+    l: u8,
+    sp: u16,
+    pc: u16,
+    ime: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct RunReport {
+    passed: bool,
+
+    /// Why the run failed
+    reason: Option<String>,
+
+    frames: u64,
+    frame_hash: Option<String>,
+    state_hash: String,
+    serial: String,
+    cpu: CpuState,
+}
+
+impl Report for RunReport {
+    type Row = Field;
+
+    fn text(&self) -> String {
+        let mut output = ListOutput::new();
+
+        output.add_single("Result", if self.passed { "PASS" } else { "FAIL" });
+
+        if let Some(reason) = &self.reason {
+            output.add_single("Reason", reason);
+        }
+
+        output.add_single("Frames", self.frames);
+        output.add_single(
+            "Frame hash",
+            self.frame_hash.as_deref().unwrap_or("No frame drawn"),
+        );
+        output.add_single("State hash", &self.state_hash);
+
+        let cpu = &self.cpu;
+        output.add_single(
+            "CPU",
+            format!(
+                "AF={:02X}{:02X} BC={:02X}{:02X} DE={:02X}{:02X} HL={:02X}{:02X} SP={:04X} PC={:04X} IME={}",
+                cpu.a, cpu.f, cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.sp, cpu.pc, cpu.ime
+            ),
+        );
+
+        output.add_multiple("Serial output", self.serial.lines().collect());
+
+        output.to_string()
+    }
+
+    fn rows(&self) -> Vec<Field> {
+        let cpu = &self.cpu;
+
+        vec![
+            Field::new("passed", self.passed),
+            Field::new("reason", self.reason.as_deref().unwrap_or("")),
+            Field::new("frames", self.frames),
+            Field::new("frame_hash", self.frame_hash.as_deref().unwrap_or("")),
+            Field::new("state_hash", &self.state_hash),
+            Field::new("serial", &self.serial),
+            Field::new("a", cpu.a),
+            Field::new("f", cpu.f),
+            Field::new("b", cpu.b),
+            Field::new("c", cpu.c),
+            Field::new("d", cpu.d),
+            Field::new("e", cpu.e),
+            Field::new("h", cpu.h),
+            Field::new("l", cpu.l),
+            Field::new("sp", cpu.sp),
+            Field::new("pc", cpu.pc),
+            Field::new("ime", cpu.ime),
+        ]
+    }
+}
+
+fn start(args: &headless::CLIArgs) -> Result<HeadlessRuboy> {
+    let file = File::open(&args.file).context("Failed to open file")?;
+
+    let mut ruboy = Ruboy::with_config(
+        BufReader::new(file),
+        NullOutput,
+        ReplayInputHandler::default(),
+        args.config,
+    )
+    .map_err(|e| anyhow!("Could not initialize emulator: {}", e))?;
+
+    if let Some(path) = &args.movie {
+        let movie = load_movie(path)?;
+
+        movie
+            .restore_start(&mut ruboy)
+            .map_err(|e| anyhow!("Could not restore the start of the movie: {}", e))?;
+
+        *ruboy.input_mut() = ReplayInputHandler::new(&movie);
+    }
+
+    Ok(ruboy)
+}
+
+fn load_movie(path: &Path) -> Result<Movie> {
+    let bytes = std::fs::read(path).context("Failed to read movie")?;
+
+    Movie::from_bytes(&bytes).map_err(|e| anyhow!("Failed to load movie: {}", e))
+}
+
+/// Checks the serial output for the pass and fail texts. Returns the
+/// verdict once either was printed.
+fn check_serial(args: &headless::CLIArgs, serial: &str) -> Option<Result<(), String>> {
+    if let Some(text) = &args.fail_serial {
+        if serial.contains(text.as_str()) {
+            return Some(Err(format!("Serial output contains \"{}\"", text)));
+        }
+    }
+
+    match &args.pass_serial {
+        Some(text) if serial.contains(text.as_str()) => Some(Ok(())),
+        _ => None,
+    }
+}
+
+/// Runs the ROM until the frame or cycle limit, or until the serial output
+/// decides the result. Returns that result, if any.
+fn run(
+    ruboy: &mut HeadlessRuboy,
+    args: &headless::CLIArgs,
+    serial: &SerialCapture,
+) -> Result<Option<Result<(), String>>> {
+    let mut frames = 0;
+    let mut cycles_left = args.cycles;
+
+    loop {
+        match cycles_left {
+            Some(0) => return Ok(None),
+            Some(left) => {
+                let cycles = left.min(CHECK_INTERVAL_CYCLES);
+
+                ruboy
+                    .run_cycles(cycles)
+                    .map_err(|e| anyhow!("Emulation failed: {}", e))?;
+
+                cycles_left = Some(left - cycles);
+            }
+            None if frames == args.frames => return Ok(None),
+            None => {
+                let event = ruboy
+                    .step_frame()
+                    .map_err(|e| anyhow!("Emulation failed in frame {}: {}", frames, e))?;
+
+                if let FrameEvent::Stopped(reason) = event {
+                    bail!("Unexpected stop: {:?}", reason);
+                }
+
+                frames += 1;
+            }
+        }
+
+        if let Some(verdict) = check_serial(args, &serial.text()) {
+            return Ok(Some(verdict));
+        }
+    }
+}
+
+fn judge(
+    args: &headless::CLIArgs,
+    ran: Result<Option<Result<(), String>>>,
+    frame_hash: Option<u64>,
+) -> Result<(), String> {
+    match ran {
+        Err(e) => return Err(format!("{:#}", e)),
+        Ok(Some(verdict)) => verdict?,
+        Ok(None) => {
+            if let Some(text) = &args.pass_serial {
+                return Err(format!("Serial output never contained \"{}\"", text));
+            }
+        }
+    }
+
+    match args.expect_hash {
+        Some(expected) if frame_hash != Some(expected) => Err(format!(
+            "Expected frame hash {:016x}, got {}",
+            expected,
+            format_hash(frame_hash)
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn format_hash(hash: Option<u64>) -> String {
+    match hash {
+        Some(hash) => format!("{:016x}", hash),
+        None => "none".to_string(),
+    }
+}
+
+fn main() -> Result<ExitCode> {
+    let args = headless::CLIArgs::parse();
+
+    let mut ruboy = start(&args)?;
+
+    let serial = SerialCapture::default();
+    let last_frame = LastFrame::default();
+
+    ruboy.attach_snooper(serial.clone());
+    ruboy.attach_frame_observer(last_frame.clone());
+
+    let ran = run(&mut ruboy, &args, &serial);
+    let verdict = judge(&args, ran, last_frame.digest());
+
+    let regs = ruboy.registers();
+
+    let report = RunReport {
+        passed: verdict.is_ok(),
+        reason: verdict.err(),
+        frames: ruboy.frame_count(),
+        frame_hash: last_frame.digest().map(|hash| format!("{:016x}", hash)),
+        state_hash: format!("{:016x}", ruboy.state_digest()),
+        serial: serial.text(),
+        cpu: CpuState {
+            a: regs.a,
+            f: regs.f,
+            b: regs.b,
+            c: regs.c,
+            d: regs.d,
+            e: regs.e,
+            h: regs.h,
+            l: regs.l,
+            sp: regs.sp,
+            pc: regs.pc,
+            ime: regs.ime,
+        },
+    };
+
+    args.output_format.print(&report)?;
+
+    Ok(if report.passed {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
@@ -0,0 +1,117 @@
+use std::{fs::File, io::BufReader};
+
+use anyhow::{anyhow, Context, Result};
+use clap::Parser;
+use ruboy_binutils::{
+    cli::opstats,
+    headless::{NullInput, NullOutput},
+    output::Report,
+    ListOutput,
+};
+use ruboy_lib::{
+    debug::{Opcode, OpcodeStats},
+    isa::{
+        decoder::decode,
+        display::{DisplayableInstruction, FormatOpts},
+    },
+    InlineAllocator, Ruboy,
+};
+use serde::Serialize;
+
+/// Formats the instruction for an opcode. Immediate operands are shown as
+/// zero, as they differ per execution
+fn format_opcode(opcode: Opcode) -> String {
+    let mut bytes = opcode.bytes();
+    bytes.resize(3, 0);
+
+    match decode(&bytes.as_slice(), 0) {
+        Ok(instr) => DisplayableInstruction::from(instr).with_format(&FormatOpts::rgdbs()),
+        Err(_) => "???".to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OpcodeCount {
+    opcode: String,
+    instruction: String,
+    count: u64,
+    percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct StatsReport {
+    total: u64,
+    opcodes: Vec<OpcodeCount>,
+}
+
+impl StatsReport {
+    fn new(stats: &OpcodeStats, top: Option<usize>) -> Self {
+        let total = stats.total();
+        let sorted = stats.sorted();
+        let shown = top.unwrap_or(sorted.len());
+
+        let opcodes = sorted
+            .into_iter()
+            .take(shown)
+            .map(|(opcode, count)| OpcodeCount {
+                opcode: opcode.to_string(),
+                instruction: format_opcode(opcode),
+                count,
+                percent: (count as f64 / total as f64) * 100.0,
+            })
+            .collect();
+
+        Self { total, opcodes }
+    }
+}
+
+impl Report for StatsReport {
+    type Row = OpcodeCount;
+
+    fn text(&self) -> String {
+        let mut output = ListOutput::new();
+
+        for op in &self.opcodes {
+            output.add_single(
+                &op.opcode,
+                format!(
+                    "{:<20} {:>12} ({:.2}%)",
+                    op.instruction, op.count, op.percent
+                ),
+            );
+        }
+
+        format!("{}\nTotal instructions executed: {}\n", output, self.total)
+    }
+
+    fn rows(&self) -> Vec<OpcodeCount> {
+        self.opcodes.clone()
+    }
+}
+
+fn main() -> Result<()> {
+    let args = opstats::CLIArgs::parse();
+
+    let file = File::open(&args.file).context("Failed to open file")?;
+
+    let mut ruboy =
+        Ruboy::<InlineAllocator, _, _, _>::new(BufReader::new(file), NullOutput, NullInput)
+            .map_err(|e| anyhow!("Could not initialize emulator: {}", e))?;
+
+    ruboy.enable_opcode_stats();
+
+    for frame in 0..args.frames {
+        ruboy
+            .step_frame()
+            .map_err(|e| anyhow!("Emulation failed in frame {}: {}", frame, e))?;
+    }
+
+    let stats = ruboy
+        .opcode_stats()
+        .context("Opcode statistics were not collected")?;
+
+    args.output_format
+        .print(&StatsReport::new(stats, args.top))?;
+
+    Ok(())
+}
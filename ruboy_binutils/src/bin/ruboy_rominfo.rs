@@ -0,0 +1,214 @@
+use std::process::ExitCode;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Parser;
+use colored::*;
+use ruboy_binutils::{
+    cli::rominfo,
+    header::fix_header,
+    output::{Field, Report},
+    ListOutput,
+};
+use ruboy_lib::{quirks, rom::meta::RomMeta};
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct Checksum<T> {
+    stored: T,
+    computed: T,
+    valid: bool,
+}
+
+impl<T: PartialEq + Copy> Checksum<T> {
+    fn new(stored: T, computed: T) -> Self {
+        Self {
+            stored,
+            computed,
+            valid: stored == computed,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct RomInfo {
+    title: String,
+    manufacturer: String,
+    cgb_support: String,
+    licensee: String,
+    sgb_support: bool,
+    mapper: Option<String>,
+    hardware: Vec<String>,
+    rom_size: String,
+    ram_size: String,
+    file_size: usize,
+    destination: String,
+    game_version: u8,
+    header_checksum: Checksum<u8>,
+    global_checksum: Checksum<u16>,
+    header_hash: String,
+    quirks: Vec<String>,
+}
+
+impl RomInfo {
+    fn new(meta: &RomMeta, rom: &[u8]) -> Self {
+        let hw = meta.cartridge_hardware();
+
+        let hardware = [
+            (hw.has_ram(), "RAM"),
+            (hw.has_battery(), "Battery"),
+            (hw.has_timer(), "Timer"),
+            (hw.has_rumble(), "Rumble"),
+            (hw.has_sensor(), "Sensor"),
+            (hw.has_camera(), "Camera"),
+        ]
+        .into_iter()
+        .filter(|(present, _)| *present)
+        .map(|(_, name)| name.to_string())
+        .collect();
+
+        Self {
+            title: meta.title().to_string(),
+            manufacturer: meta.manufacturer().to_string(),
+            cgb_support: meta.cgb_support().to_string(),
+            licensee: meta.licensee().to_string(),
+            sgb_support: meta.sgb_support(),
+            mapper: hw.mapper().map(|mapper| mapper.to_string()),
+            hardware,
+            rom_size: meta.rom_size().to_string(),
+            ram_size: meta.ram_size().to_string(),
+            file_size: rom.len(),
+            destination: meta.destination().to_string(),
+            game_version: meta.game_version(),
+            header_checksum: Checksum::new(
+                meta.header_checksum(),
+                RomMeta::compute_header_checksum(header_bytes(rom)),
+            ),
+            global_checksum: Checksum::new(
+                meta.global_checksum(),
+                RomMeta::compute_global_checksum(rom),
+            ),
+            header_hash: format!("0x{:016x}", meta.header_hash()),
+            quirks: quirks::for_rom(meta)
+                .iter()
+                .map(|quirk| quirk.to_string())
+                .collect(),
+        }
+    }
+
+    fn checksums_valid(&self) -> bool {
+        self.header_checksum.valid && self.global_checksum.valid
+    }
+}
+
+impl Report for RomInfo {
+    type Row = Field;
+
+    fn text(&self) -> String {
+        let mut output = ListOutput::new();
+        output.add_single("Title", &self.title);
+        output.add_single("Manufacturer", &self.manufacturer);
+        output.add_single("CGB Support", &self.cgb_support);
+        output.add_single("Licensee", &self.licensee);
+        output.add_single("SGB Support", self.sgb_support);
+
+        let mut hw_strs: Vec<String> = Vec::new();
+
+        if let Some(mapper) = &self.mapper {
+            hw_strs.push(format!("Mapper: {}", mapper));
+        }
+
+        hw_strs.extend(self.hardware.iter().cloned());
+
+        output.add_multiple("Cartridge hardware", hw_strs);
+
+        output.add_single("ROM size", &self.rom_size);
+        output.add_single("RAM size", &self.ram_size);
+        output.add_single("File size", format!("{} bytes", self.file_size));
+        output.add_single("Intended destination", &self.destination);
+        output.add_single("Game version number", self.game_version);
+        output.add_single("Header checksum", format_checksum(&self.header_checksum));
+        output.add_single("Global checksum", format_checksum(&self.global_checksum));
+        output.add_single("Header hash", &self.header_hash);
+
+        if !self.quirks.is_empty() {
+            output.add_single("Quirks", self.quirks.join(", "));
+        }
+
+        format!("{}\n", output)
+    }
+
+    fn rows(&self) -> Vec<Field> {
+        vec![
+            Field::new("title", &self.title),
+            Field::new("manufacturer", &self.manufacturer),
+            Field::new("cgb_support", &self.cgb_support),
+            Field::new("licensee", &self.licensee),
+            Field::new("sgb_support", self.sgb_support),
+            Field::new("mapper", self.mapper.as_deref().unwrap_or("")),
+            Field::new("hardware", self.hardware.join(" ")),
+            Field::new("rom_size", &self.rom_size),
+            Field::new("ram_size", &self.ram_size),
+            Field::new("file_size", self.file_size),
+            Field::new("destination", &self.destination),
+            Field::new("game_version", self.game_version),
+            Field::new("header_checksum", self.header_checksum.stored),
+            Field::new("header_checksum_valid", self.header_checksum.valid),
+            Field::new("global_checksum", self.global_checksum.stored),
+            Field::new("global_checksum_valid", self.global_checksum.valid),
+            Field::new("header_hash", &self.header_hash),
+            Field::new("quirks", self.quirks.join(" ")),
+        ]
+    }
+}
+
+fn format_checksum<T: std::fmt::LowerHex>(checksum: &Checksum<T>) -> String {
+    if checksum.valid {
+        format!("0x{:x} ({})", checksum.stored, "valid".green())
+    } else {
+        format!(
+            "0x{:x} ({}, should be 0x{:x})",
+            checksum.stored,
+            "invalid".red(),
+            checksum.computed
+        )
+    }
+}
+
+fn header_bytes(rom: &[u8]) -> &[u8] {
+    &rom[RomMeta::OFFSET_HEADER_START..RomMeta::OFFSET_HEADER_END]
+}
+
+fn parse_meta(rom: &[u8]) -> Result<RomMeta> {
+    if rom.len() < RomMeta::OFFSET_HEADER_END {
+        bail!(
+            "File of {} bytes is too small to contain a header",
+            rom.len()
+        );
+    }
+
+    RomMeta::parse(header_bytes(rom)).map_err(|e| anyhow!("Could not parse header: {}", e))
+}
+
+fn main() -> Result<ExitCode> {
+    let args = rominfo::CLIArgs::parse();
+
+    let mut rom = std::fs::read(&args.file).context("Failed to open file")?;
+    let mut meta = parse_meta(&rom)?;
+
+    if args.fix && !RomInfo::new(&meta, &rom).checksums_valid() {
+        fix_header(&mut rom, false)?;
+        std::fs::write(&args.file, &rom).context("Failed to write fixed checksums")?;
+
+        meta = parse_meta(&rom)?;
+    }
+
+    let info = RomInfo::new(&meta, &rom);
+
+    args.output_format.print(&info)?;
+
+    Ok(if args.verify && !info.checksums_valid() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
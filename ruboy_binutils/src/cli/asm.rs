@@ -0,0 +1,23 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[command(author, about, version)]
+pub struct CLIArgs {
+    pub file: PathBuf,
+
+    /// Where to write the image. Defaults to the input file with a ".gb"
+    /// extension
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Pad the image with zeroes to at least this many bytes, e.g. 32768
+    /// for the smallest cartridge
+    #[arg(short, long)]
+    pub pad_to: Option<usize>,
+
+    /// Fill in the Nintendo logo and the header and global checksums
+    #[arg(short, long)]
+    pub fix_header: bool,
+}
@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use ruboy_lib::model::RuboyConfig;
+
+use super::{parse_config, CONFIG_HELP};
+use crate::output::OutputFormat;
+
+#[derive(Parser, Debug)]
+#[command(author, about, version)]
+pub struct CLIArgs {
+    pub file: PathBuf,
+
+    /// The amount of frames to time
+    #[arg(short, long, default_value_t = 3600)]
+    pub frames: u64,
+
+    /// The amount of frames to run before timing starts, to get past the
+    /// boot ROM and title screens
+    #[arg(short, long, default_value_t = 60)]
+    pub warmup: u64,
+
+    /// Draw whole lines at once instead of pixel by pixel
+    #[arg(long)]
+    pub scanline: bool,
+
+    /// The emulator configuration, see --help for the format
+    #[arg(short, long, value_parser = parse_config, default_value = "", long_help = CONFIG_HELP)]
+    pub config: RuboyConfig,
+
+    #[arg(value_enum, long, default_value_t)]
+    pub output_format: OutputFormat,
+}
@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use ruboy_lib::model::RuboyConfig;
+
+use super::{parse_config, CONFIG_HELP};
+
+#[derive(Parser, Debug)]
+#[command(author, about, version)]
+pub struct CLIArgs {
+    pub file: PathBuf,
+
+    /// The first configuration, see --help for the format
+    #[arg(short, long, value_parser = parse_config, default_value = "", long_help = CONFIG_HELP)]
+    pub a: RuboyConfig,
+
+    /// The second configuration, see --help for the format
+    #[arg(short, long, value_parser = parse_config, default_value = "", long_help = CONFIG_HELP)]
+    pub b: RuboyConfig,
+
+    /// Replay the inputs of a movie file in both configurations, starting
+    /// from its save state
+    #[arg(short, long)]
+    pub movie: Option<PathBuf>,
+
+    /// The maximum amount of frames to run both configurations for
+    #[arg(short, long, default_value_t = 3600)]
+    pub frames: u64,
+
+    /// What to compare after every frame
+    #[arg(value_enum, short, long, default_value_t = Compare::Frame)]
+    pub compare: Compare,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Compare {
+    /// The drawn frame. Use this when the configurations are expected to
+    /// differ internally, e.g. different models
+    Frame,
+
+    /// The complete emulator state
+    State,
+}
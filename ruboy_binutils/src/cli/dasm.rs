@@ -3,6 +3,8 @@ use std::path::PathBuf;
 use clap::{Args, Parser, ValueEnum};
 use ruboy_lib::isa::display::{Case, ImmediateFormat, OperandOrder};
 
+use crate::output::OutputFormat;
+
 #[derive(Parser, Debug)]
 #[command(author, about, version)]
 pub struct CLIArgs {
@@ -27,8 +29,31 @@ pub struct CLIArgs {
     #[command(flatten)]
     pub immediate_format: ParsableImmediateFormat,
 
+    /// Do not print the bank and address of every line
     #[arg(long, default_value_t = false)]
     pub no_print_label: bool,
+
+    /// Print addresses as numbers, instead of as labels and the names of
+    /// the I/O registers
+    #[arg(long, default_value_t = false)]
+    pub no_symbols: bool,
+
+    /// More addresses to follow code from, for code that is only reached
+    /// through jump tables (e.g. "0x1234" or "$1234")
+    #[arg(short, long, value_parser = parse_addr)]
+    pub entry: Vec<u16>,
+
+    #[arg(value_enum, long, default_value_t)]
+    pub output_format: OutputFormat,
+}
+
+fn parse_addr(val: &str) -> Result<u16, String> {
+    let hex = val
+        .strip_prefix('$')
+        .or_else(|| val.strip_prefix("0x"))
+        .unwrap_or(val);
+
+    u16::from_str_radix(hex, 16).map_err(|e| format!("Invalid address {}: {}", val, e))
 }
 
 #[derive(Debug, Clone, Copy, ValueEnum)]
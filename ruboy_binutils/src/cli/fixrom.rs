@@ -0,0 +1,21 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[command(author, about, version)]
+pub struct CLIArgs {
+    pub file: PathBuf,
+
+    /// Where to write the fixed ROM. Defaults to overwriting the input
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Also replace an invalid logo with the Nintendo logo
+    #[arg(short, long)]
+    pub fix_logo: bool,
+
+    /// Only report what is wrong, and exit with a failure if anything is
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+}
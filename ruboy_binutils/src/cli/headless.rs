@@ -0,0 +1,49 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use ruboy_lib::model::RuboyConfig;
+
+use super::{parse_config, CONFIG_HELP};
+use crate::output::OutputFormat;
+
+#[derive(Parser, Debug)]
+#[command(author, about, version)]
+pub struct CLIArgs {
+    pub file: PathBuf,
+
+    /// The amount of frames to run the ROM for
+    #[arg(short, long, default_value_t = 600, conflicts_with = "cycles")]
+    pub frames: u64,
+
+    /// Run for this amount of T-cycles instead of a number of frames
+    #[arg(long)]
+    pub cycles: Option<u64>,
+
+    /// The emulator configuration, see --help for the format
+    #[arg(short, long, value_parser = parse_config, default_value = "", long_help = CONFIG_HELP)]
+    pub config: RuboyConfig,
+
+    /// Replay the inputs of a movie file, starting from its save state
+    #[arg(short, long)]
+    pub movie: Option<PathBuf>,
+
+    /// Pass, and stop early, once the serial output contains this text
+    #[arg(long)]
+    pub pass_serial: Option<String>,
+
+    /// Fail, and stop early, once the serial output contains this text
+    #[arg(long)]
+    pub fail_serial: Option<String>,
+
+    /// Fail unless the hash of the last drawn frame is this (in hex)
+    #[arg(long, value_parser = parse_hash)]
+    pub expect_hash: Option<u64>,
+
+    #[arg(value_enum, long, default_value_t)]
+    pub output_format: OutputFormat,
+}
+
+fn parse_hash(val: &str) -> Result<u64, String> {
+    u64::from_str_radix(val.trim_start_matches("0x"), 16)
+        .map_err(|e| format!("Invalid hash {}: {}", val, e))
+}
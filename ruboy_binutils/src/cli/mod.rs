@@ -1,7 +1,17 @@
 use clap::ValueEnum;
+use std::num::NonZeroU16;
 
+use ruboy_lib::model::{GbModel, InputPolling, RamInit, RuboyConfig};
+use ruboy_lib::RenderMode;
+
+pub mod asm;
+pub mod bench;
+pub mod bisect;
 pub mod dasm;
-pub mod romdump;
+pub mod fixrom;
+pub mod headless;
+pub mod opstats;
+pub mod rominfo;
 
 #[derive(Debug, Clone, ValueEnum)]
 pub enum LogLevel {
@@ -41,3 +51,137 @@ impl From<LogLevel> for log::LevelFilter {
         }
     }
 }
+
+fn parse_model(val: &str) -> Result<GbModel, String> {
+    GbModel::ALL
+        .into_iter()
+        .find(|model| model.to_string().eq_ignore_ascii_case(val))
+        .ok_or_else(|| format!("Unknown model: {}", val))
+}
+
+fn parse_ram_init(val: &str) -> Result<RamInit, String> {
+    let (kind, arg) = match val.split_once(':') {
+        Some((kind, arg)) => (kind, Some(arg)),
+        None => (val, None),
+    };
+
+    match (kind, arg) {
+        ("zero", None) => Ok(RamInit::Zeroed),
+        ("fill", Some(byte)) => u8::from_str_radix(byte, 16)
+            .map(RamInit::Fill)
+            .map_err(|e| format!("Invalid fill byte {}: {}", byte, e)),
+        ("pattern", Some(seed)) => seed
+            .parse()
+            .map(|seed| RamInit::Pattern { seed })
+            .map_err(|e| format!("Invalid seed {}: {}", seed, e)),
+        _ => Err(format!("Invalid RAM initialization: {}", val)),
+    }
+}
+
+fn parse_on_off(val: &str) -> Result<bool, String> {
+    match val {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("Expected \"on\" or \"off\", got {}", val)),
+    }
+}
+
+fn parse_render_mode(val: &str) -> Result<RenderMode, String> {
+    match val {
+        "fifo" => Ok(RenderMode::Fifo),
+        "scanline" => Ok(RenderMode::Scanline),
+        _ => Err(format!("Expected \"fifo\" or \"scanline\", got {}", val)),
+    }
+}
+
+fn parse_polling(val: &str) -> Result<InputPolling, String> {
+    match val {
+        "vblank" => Ok(InputPolling::VBlank),
+        cycles => cycles
+            .parse::<NonZeroU16>()
+            .map(InputPolling::Interval)
+            .map_err(|e| format!("Invalid polling interval {}: {}", cycles, e)),
+    }
+}
+
+fn parse_deterministic(val: &str) -> Result<Option<u64>, String> {
+    match val {
+        "off" => Ok(None),
+        seed => seed
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("Invalid seed {}: {}", seed, e)),
+    }
+}
+
+/// The format [parse_config] accepts, shown in the help of every argument
+/// taking a configuration
+pub const CONFIG_HELP: &str = "Comma separated key=value pairs, e.g. \"model=SGB,ram=zero\". Keys not given keep their default.
+
+  model          The Gameboy model, e.g. DMG or SGB
+  ram            What RAM starts as: \"zero\", \"fill:<hex byte>\" or \"pattern:<seed>\"
+  blocking       Block VRAM and OAM accesses like the hardware: \"on\" or \"off\"
+  render         How the PPU draws: \"fifo\" or \"scanline\"
+  polling        When inputs are read: \"vblank\" or every \"<T-cycles>\"
+  deterministic  Derive RAM and the cartridge clock from \"<seed>\", or \"off\"";
+
+/// Parses an emulator configuration given in the [CONFIG_HELP] format
+pub fn parse_config(spec: &str) -> Result<RuboyConfig, String> {
+    let mut config = RuboyConfig::default();
+
+    for pair in spec.split(',').filter(|pair| !pair.is_empty()) {
+        let (key, val) = pair
+            .split_once('=')
+            .ok_or_else(|| format!("Expected key=value, got {}", pair))?;
+
+        match key {
+            "model" => config.model = parse_model(val)?,
+            "ram" => config.ram_init = parse_ram_init(val)?,
+            "blocking" => config.access_blocking = parse_on_off(val)?,
+            "render" => config.render_mode = parse_render_mode(val)?,
+            "polling" => config.input_polling = parse_polling(val)?,
+            "deterministic" => config.deterministic = parse_deterministic(val)?,
+            _ => return Err(format!("Unknown configuration key: {}", key)),
+        }
+    }
+
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_config_key() {
+        let config = parse_config(
+            "model=sgb,ram=fill:a5,blocking=off,render=scanline,polling=456,deterministic=7",
+        )
+        .unwrap();
+
+        assert_eq!(GbModel::Sgb, config.model);
+        assert_eq!(RamInit::Fill(0xA5), config.ram_init);
+        assert!(!config.access_blocking);
+        assert_eq!(RenderMode::Scanline, config.render_mode);
+        assert_eq!(
+            InputPolling::Interval(NonZeroU16::new(456).unwrap()),
+            config.input_polling
+        );
+        assert_eq!(Some(7), config.deterministic);
+
+        assert_eq!(RuboyConfig::default(), parse_config("").unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_configs() {
+        for spec in [
+            "model",
+            "speed=2",
+            "render=lines",
+            "polling=0",
+            "deterministic=on",
+        ] {
+            assert!(parse_config(spec).is_err(), "{} was accepted", spec);
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::output::OutputFormat;
+
+#[derive(Parser, Debug)]
+#[command(author, about, version)]
+pub struct CLIArgs {
+    pub file: PathBuf,
+
+    /// The amount of frames to run the ROM for
+    #[arg(short, long, default_value_t = 600)]
+    pub frames: u64,
+
+    /// Only show the most executed opcodes
+    #[arg(short, long)]
+    pub top: Option<usize>,
+
+    #[arg(value_enum, long, default_value_t)]
+    pub output_format: OutputFormat,
+}
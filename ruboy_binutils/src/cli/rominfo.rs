@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::output::OutputFormat;
+
+#[derive(Parser, Debug)]
+#[command(author, about, version)]
+pub struct CLIArgs {
+    pub file: PathBuf,
+
+    #[arg(value_enum, long, default_value_t)]
+    pub output_format: OutputFormat,
+
+    /// Exit with a failure when a checksum is invalid
+    #[arg(short, long)]
+    pub verify: bool,
+
+    /// Write the correct checksums into the file, if they are invalid
+    #[arg(short, long)]
+    pub fix: bool,
+}
@@ -0,0 +1,467 @@
+//! A disassembler that follows the flow of the code, instead of decoding
+//! everything in a ROM as instructions. It starts at the entry point and the
+//! RST and interrupt vectors, and follows every jump and call it finds. All
+//! bytes it never reaches are data.
+//!
+//! Addresses 0x4000 to 0x7FFF can map to any ROM bank. The disassembler
+//! assumes bank 1 is mapped until it sees the code switching banks, which it
+//! recognizes as a write of a known value to the bank register of the common
+//! mappers (0x2000 to 0x3FFF).
+
+use std::collections::BTreeMap;
+
+use ruboy_lib::isa::{
+    decoder::{decode, DecodeError, DecoderReadable},
+    IncDecTarget, Instruction, Ld16Dst, Ld16Src, Ld8Dst, Ld8Src, MemLoc, Reg16, Reg8,
+};
+
+/// The size of a ROM bank, and of the address range it is mapped to
+pub const BANK_SIZE: usize = 0x4000;
+
+/// Where the CPU starts running code by itself
+pub const ENTRY_POINTS: [(u16, &str); 14] = [
+    (0x0000, "Rst_00"),
+    (0x0008, "Rst_08"),
+    (0x0010, "Rst_10"),
+    (0x0018, "Rst_18"),
+    (0x0020, "Rst_20"),
+    (0x0028, "Rst_28"),
+    (0x0030, "Rst_30"),
+    (0x0038, "Rst_38"),
+    (0x0040, "VBlankInterrupt"),
+    (0x0048, "LCDCInterrupt"),
+    (0x0050, "TimerInterrupt"),
+    (0x0058, "SerialInterrupt"),
+    (0x0060, "JoypadInterrupt"),
+    (0x0100, "Entry"),
+];
+
+/// Writes to this range select the ROM bank on most mappers
+const BANK_SELECT: core::ops::RangeInclusive<u16> = 0x2000..=0x3FFF;
+
+/// Why a location has a label. Later variants win over earlier ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    Jump,
+    Call,
+    Entry(&'static str),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label {
+    pub kind: LabelKind,
+    pub bank: usize,
+    pub addr: u16,
+}
+
+impl Label {
+    pub fn name(&self) -> String {
+        match self.kind {
+            LabelKind::Entry(name) => name.to_string(),
+            LabelKind::Call => format!("Call_{:03X}_{:04X}", self.bank, self.addr),
+            LabelKind::Jump => format!("Jump_{:03X}_{:04X}", self.bank, self.addr),
+        }
+    }
+}
+
+/// The bank and CPU address of an offset in the ROM
+pub fn bank_addr(offset: usize) -> (usize, u16) {
+    let bank = offset / BANK_SIZE;
+
+    if bank == 0 {
+        (0, offset as u16)
+    } else {
+        (bank, (BANK_SIZE + offset % BANK_SIZE) as u16)
+    }
+}
+
+/// The ROM as the CPU sees it, with a bank mapped to 0x4000 to 0x7FFF, if
+/// that is known
+struct BankView<'a> {
+    rom: &'a [u8],
+    mapped: Option<usize>,
+}
+
+impl BankView<'_> {
+    fn offset(&self, addr: u16) -> Option<usize> {
+        let offset = match addr as usize {
+            addr @ 0..BANK_SIZE => addr,
+            addr @ BANK_SIZE..0x8000 => self.mapped? * BANK_SIZE + addr - BANK_SIZE,
+            _ => return None,
+        };
+
+        (offset < self.rom.len()).then_some(offset)
+    }
+}
+
+impl DecoderReadable for BankView<'_> {
+    type Err = DecodeError;
+
+    fn read_at(&self, addr: u16) -> Result<u8, Self::Err> {
+        self.offset(addr)
+            .map(|offset| self.rom[offset])
+            .ok_or(DecodeError::NotEnoughBytes(addr))
+    }
+}
+
+/// What a ROM decodes to
+#[derive(Debug, Default)]
+pub struct Disassembly {
+    /// Every reached instruction, by ROM offset
+    pub code: BTreeMap<usize, Instruction>,
+
+    /// Labels for jump and call targets, by ROM offset
+    pub labels: BTreeMap<usize, Label>,
+
+    /// Where each jump, call and RST in the ROM goes, by ROM offset
+    pub targets: BTreeMap<usize, usize>,
+
+    /// Targets in the switchable bank area, jumped to while the mapped bank
+    /// was unknown
+    pub unresolved: Vec<u16>,
+}
+
+impl Disassembly {
+    /// Follows the code from the entry points, and from `extra_entries`
+    pub fn trace(rom: &[u8], extra_entries: &[u16]) -> Self {
+        let mut dasm = Disassembly::default();
+
+        let mut covered = vec![false; rom.len()];
+        let mut pending = Vec::new();
+
+        let view = BankView {
+            rom,
+            mapped: Some(1),
+        };
+
+        let entries = ENTRY_POINTS
+            .iter()
+            .map(|(addr, name)| (*addr, LabelKind::Entry(name)))
+            .chain(extra_entries.iter().map(|addr| (*addr, LabelKind::Call)));
+
+        for (addr, kind) in entries {
+            if let Some(offset) = view.offset(addr) {
+                dasm.add_label(offset, kind);
+                pending.push((addr, view.mapped));
+            }
+        }
+
+        while let Some((addr, mapped)) = pending.pop() {
+            dasm.follow(rom, addr, mapped, &mut covered, &mut pending);
+        }
+
+        dasm.unresolved.sort_unstable();
+        dasm.unresolved.dedup();
+
+        dasm
+    }
+
+    fn add_label(&mut self, offset: usize, kind: LabelKind) {
+        let (bank, addr) = bank_addr(offset);
+        let label = Label { kind, bank, addr };
+
+        self.labels
+            .entry(offset)
+            .and_modify(|existing| {
+                if kind > existing.kind {
+                    *existing = label;
+                }
+            })
+            .or_insert(label);
+    }
+
+    /// Decodes from `addr` until the code stops falling through, queueing
+    /// every target on the way
+    fn follow(
+        &mut self,
+        rom: &[u8],
+        mut addr: u16,
+        mapped: Option<usize>,
+        covered: &mut [bool],
+        pending: &mut Vec<(u16, Option<usize>)>,
+    ) {
+        let mut view = BankView { rom, mapped };
+        let mut tracker = BankTracker::default();
+
+        // Stops at code that was decoded before, and at the end of the ROM
+        while let Some(offset) = view.offset(addr).filter(|offset| !covered[*offset]) {
+            let Ok(instr) = decode(&view, addr) else {
+                return;
+            };
+
+            if matches!(instr, Instruction::IllegalInstruction(_)) {
+                return;
+            }
+
+            let len = instr.len() as usize;
+
+            // Decoding from the middle of another instruction
+            if covered[offset..offset + len].iter().any(|c| *c) {
+                return;
+            }
+
+            covered[offset..offset + len].fill(true);
+            self.code.insert(offset, instr);
+
+            if let Some(switched) = tracker.observe(instr) {
+                view.mapped = switched.filter(|bank| bank * BANK_SIZE < rom.len());
+            }
+
+            let next = addr.wrapping_add(len as u16);
+
+            let (target, kind) = match instr {
+                Instruction::Jump(target) | Instruction::JumpIf(target, _) => {
+                    (Some(target), LabelKind::Jump)
+                }
+                Instruction::JumpRel(rel) | Instruction::JumpRelIf(rel, _) => {
+                    (Some(next.wrapping_add_signed(rel as i16)), LabelKind::Jump)
+                }
+                Instruction::Call(target) | Instruction::CallIf(target, _) => {
+                    (Some(target), LabelKind::Call)
+                }
+                Instruction::Rst(vec) => (Some(vec as u16), LabelKind::Call),
+                _ => (None, LabelKind::Jump),
+            };
+
+            if let Some(target) = target {
+                match view.offset(target) {
+                    Some(target_offset) => {
+                        self.add_label(target_offset, kind);
+                        self.targets.insert(offset, target_offset);
+                        pending.push((target, view.mapped));
+                    }
+                    None if view.mapped.is_none() && (0x4000..0x8000).contains(&target) => {
+                        self.unresolved.push(target)
+                    }
+                    // Code in RAM, or past the end of the ROM
+                    None => {}
+                }
+            }
+
+            if !falls_through(instr) {
+                return;
+            }
+
+            addr = next;
+        }
+    }
+}
+
+/// Whether execution can continue with the next instruction
+fn falls_through(instr: Instruction) -> bool {
+    !matches!(
+        instr,
+        Instruction::Jump(_)
+            | Instruction::JumpRel(_)
+            | Instruction::JumpHL
+            | Instruction::Ret
+            | Instruction::Reti
+    )
+}
+
+/// Follows the values of A and HL along a trace, to recognize bank switches
+#[derive(Debug, Default)]
+struct BankTracker {
+    a: Option<u8>,
+    hl: Option<u16>,
+}
+
+impl BankTracker {
+    /// Returns the newly selected bank if the instruction switches banks,
+    /// which is unknown when the written value is
+    fn observe(&mut self, instr: Instruction) -> Option<Option<usize>> {
+        let selected = match instr {
+            Instruction::Load8(Ld8Dst::Mem(MemLoc::Imm(addr)), Ld8Src::Reg(Reg8::A))
+                if BANK_SELECT.contains(&addr) =>
+            {
+                self.a
+            }
+            Instruction::Load8(Ld8Dst::Mem(MemLoc::Reg(Reg16::HL)), src)
+                if self.hl.is_some_and(|hl| BANK_SELECT.contains(&hl)) =>
+            {
+                match src {
+                    Ld8Src::Imm(val) => Some(val),
+                    Ld8Src::Reg(Reg8::A) => self.a,
+                    _ => None,
+                }
+            }
+            _ => {
+                self.a = match instr {
+                    Instruction::Load8(Ld8Dst::Reg(Reg8::A), Ld8Src::Imm(val)) => Some(val),
+                    _ if keeps_a(instr) => self.a,
+                    _ => None,
+                };
+
+                self.hl = match instr {
+                    Instruction::Load16(Ld16Dst::Reg(Reg16::HL), Ld16Src::Imm(val)) => Some(val),
+                    _ if keeps_hl(instr) => self.hl,
+                    _ => None,
+                };
+
+                return None;
+            }
+        };
+
+        // Most mappers select bank 1 when asked for bank 0
+        Some(selected.map(|bank| bank.max(1) as usize))
+    }
+}
+
+/// Whether A is certainly unchanged after the instruction. Calls may change
+/// anything.
+fn keeps_a(instr: Instruction) -> bool {
+    match instr {
+        Instruction::Load8(Ld8Dst::Reg(dst), _) => dst != Reg8::A,
+        Instruction::Inc(target) | Instruction::Dec(target) => {
+            target != IncDecTarget::Reg8(Reg8::A)
+        }
+        Instruction::Load8(Ld8Dst::Mem(_), _)
+        | Instruction::Load16(..)
+        | Instruction::LoadAtoHLI
+        | Instruction::LoadAtoHLD
+        | Instruction::AddHL(_)
+        | Instruction::Cmp(_)
+        | Instruction::Bit(..)
+        | Instruction::Push(_)
+        | Instruction::Nop
+        | Instruction::DI
+        | Instruction::EI
+        | Instruction::JumpIf(..)
+        | Instruction::JumpRelIf(..) => true,
+        _ => false,
+    }
+}
+
+/// Whether HL is certainly unchanged after the instruction
+fn keeps_hl(instr: Instruction) -> bool {
+    match instr {
+        Instruction::Load8(Ld8Dst::Reg(dst), _) => !matches!(dst, Reg8::H | Reg8::L),
+        Instruction::Load16(Ld16Dst::Reg(dst), _) => dst != Reg16::HL,
+        Instruction::Inc(target) | Instruction::Dec(target) => !matches!(
+            target,
+            IncDecTarget::Reg8(Reg8::H | Reg8::L) | IncDecTarget::Reg16(Reg16::HL)
+        ),
+        Instruction::Load8(Ld8Dst::Mem(_), _)
+        | Instruction::Load16(Ld16Dst::Mem(_), _)
+        | Instruction::Add(_)
+        | Instruction::AddCarry(_)
+        | Instruction::Sub(_)
+        | Instruction::SubCarry(_)
+        | Instruction::And(_)
+        | Instruction::Or(_)
+        | Instruction::Xor(_)
+        | Instruction::Cmp(_)
+        | Instruction::Bit(..)
+        | Instruction::Push(_)
+        | Instruction::Nop
+        | Instruction::DI
+        | Instruction::EI
+        | Instruction::JumpIf(..)
+        | Instruction::JumpRelIf(..) => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Three banks of NOPs, with a few routines
+    fn test_rom() -> Vec<u8> {
+        let mut rom = vec![0x00; 3 * BANK_SIZE];
+
+        let mut put = |offset: usize, bytes: &[u8]| {
+            rom[offset..offset + bytes.len()].copy_from_slice(bytes);
+        };
+
+        // Entry: jp $0150, over the header
+        put(0x100, &[0xC3, 0x50, 0x01]);
+        put(0x104, &[0xAB; 0x4C]);
+
+        // ld a, 2; ld [$2000], a; call $4000; jr @; data
+        put(
+            0x150,
+            &[
+                0x3E, 0x02, 0xEA, 0x00, 0x20, 0xCD, 0x00, 0x40, 0x18, 0xFE, 0x12, 0x34,
+            ],
+        );
+
+        // Bank 1 is mapped until the switch, and its $4000 is never called
+        put(BANK_SIZE, &[0xC9]);
+
+        // Bank 2: ret
+        put(2 * BANK_SIZE, &[0xC9]);
+
+        // Extra entry: ld a, [hl]; ld [$2000], a; jp $4000
+        put(0x160, &[0x7E, 0xEA, 0x00, 0x20, 0xC3, 0x00, 0x40]);
+
+        rom
+    }
+
+    #[test]
+    fn separates_code_and_data() {
+        let dasm = Disassembly::trace(&test_rom(), &[]);
+
+        for offset in [0x000, 0x0FF, 0x100, 0x150, 0x152, 0x155, 0x158] {
+            assert!(dasm.code.contains_key(&offset), "${:04X}", offset);
+        }
+
+        // The header, the bytes after the endless loop, and the instruction
+        // bytes after the opcodes
+        for offset in [0x104, 0x14F, 0x15A, 0x15B, 0x101, 0x153] {
+            assert!(!dasm.code.contains_key(&offset), "${:04X}", offset);
+        }
+
+        assert_eq!(Some(&Instruction::JumpRel(-2)), dasm.code.get(&0x158));
+    }
+
+    #[test]
+    fn labels_targets() {
+        let dasm = Disassembly::trace(&test_rom(), &[]);
+
+        let name = |offset| dasm.labels.get(&offset).map(Label::name);
+
+        assert_eq!(Some("Entry".to_string()), name(0x100));
+        assert_eq!(Some("Rst_00".to_string()), name(0x000));
+        assert_eq!(Some("Jump_000_0150".to_string()), name(0x150));
+        assert_eq!(Some("Jump_000_0158".to_string()), name(0x158));
+        assert_eq!(None, name(0x152));
+
+        assert_eq!(Some(&0x150), dasm.targets.get(&0x100));
+        assert_eq!(Some(&0x158), dasm.targets.get(&0x158));
+    }
+
+    #[test]
+    fn follows_bank_switches() {
+        let dasm = Disassembly::trace(&test_rom(), &[]);
+
+        // The call after selecting bank 2 goes there, not to bank 1
+        assert_eq!(Some(&(2 * BANK_SIZE)), dasm.targets.get(&0x155));
+        assert_eq!(
+            Some("Call_002_4000".to_string()),
+            dasm.labels.get(&(2 * BANK_SIZE)).map(Label::name)
+        );
+        assert!(dasm.code.contains_key(&(2 * BANK_SIZE)));
+        assert!(!dasm.code.contains_key(&BANK_SIZE));
+        assert!(dasm.unresolved.is_empty());
+    }
+
+    #[test]
+    fn unknown_bank_is_unresolved() {
+        let dasm = Disassembly::trace(&test_rom(), &[0x160]);
+
+        assert_eq!(
+            Some("Call_000_0160".to_string()),
+            dasm.labels.get(&0x160).map(Label::name)
+        );
+        assert_eq!(vec![0x4000], dasm.unresolved);
+        assert!(!dasm.targets.contains_key(&0x164));
+    }
+
+    #[test]
+    fn bank_addresses() {
+        assert_eq!((0, 0x3FFF), bank_addr(0x3FFF));
+        assert_eq!((1, 0x4000), bank_addr(0x4000));
+        assert_eq!((2, 0x7FFF), bank_addr(0xBFFF));
+    }
+}
@@ -0,0 +1,121 @@
+//! Repairing the parts of a ROM header that have to match the rest of the
+//! ROM
+
+use ruboy_lib::rom::meta::RomMeta;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("ROM of {0} bytes is too small to contain a header")]
+pub struct TooShort(pub usize);
+
+/// A part of the header that did not contain what it should
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderDiff {
+    pub field: &'static str,
+
+    /// Where the field starts in the ROM
+    pub offset: usize,
+
+    pub found: Vec<u8>,
+    pub expected: Vec<u8>,
+}
+
+impl HeaderDiff {
+    /// The offsets of the bytes that differ, with what was found and
+    /// expected there
+    pub fn bytes(&self) -> impl Iterator<Item = (usize, u8, u8)> + '_ {
+        self.found
+            .iter()
+            .zip(&self.expected)
+            .enumerate()
+            .filter(|(_, (found, expected))| found != expected)
+            .map(|(idx, (found, expected))| (self.offset + idx, *found, *expected))
+    }
+}
+
+/// Overwrites `range` of the ROM with `expected`, recording a diff when that
+/// changes anything
+fn patch(
+    rom: &mut [u8],
+    diffs: &mut Vec<HeaderDiff>,
+    field: &'static str,
+    offset: usize,
+    expected: &[u8],
+) {
+    let range = offset..offset + expected.len();
+
+    if rom[range.clone()] != *expected {
+        diffs.push(HeaderDiff {
+            field,
+            offset,
+            found: rom[range.clone()].to_vec(),
+            expected: expected.to_vec(),
+        });
+
+        rom[range].copy_from_slice(expected);
+    }
+}
+
+/// Writes the correct header checksum and global checksum into the ROM, and
+/// the Nintendo logo if `fix_logo` is set. Returns what changed.
+pub fn fix_header(rom: &mut [u8], fix_logo: bool) -> Result<Vec<HeaderDiff>, TooShort> {
+    if rom.len() < RomMeta::OFFSET_HEADER_END {
+        return Err(TooShort(rom.len()));
+    }
+
+    let mut diffs = Vec::new();
+
+    if fix_logo {
+        patch(
+            rom,
+            &mut diffs,
+            "Logo",
+            RomMeta::OFFSET_LOGO,
+            &RomMeta::LOGO,
+        );
+    }
+
+    // Each checksum covers the fields fixed before it
+    let header_checksum = RomMeta::compute_header_checksum(
+        &rom[RomMeta::OFFSET_HEADER_START..RomMeta::OFFSET_HEADER_END],
+    );
+
+    patch(
+        rom,
+        &mut diffs,
+        "Header checksum",
+        RomMeta::OFFSET_HEADER_CHECKSUM,
+        &[header_checksum],
+    );
+
+    let global_checksum = RomMeta::compute_global_checksum(rom);
+
+    patch(
+        rom,
+        &mut diffs,
+        "Global checksum",
+        RomMeta::OFFSET_GLOBAL_CHECKSUM,
+        &global_checksum.to_be_bytes(),
+    );
+
+    Ok(diffs)
+}
+
+/// Compares the logo in the ROM with the Nintendo logo
+pub fn check_logo(rom: &[u8]) -> Result<Option<HeaderDiff>, TooShort> {
+    let mut copy = rom
+        .get(..RomMeta::OFFSET_HEADER_END)
+        .ok_or(TooShort(rom.len()))?
+        .to_vec();
+
+    let mut diffs = Vec::new();
+    patch(
+        &mut copy,
+        &mut diffs,
+        "Logo",
+        RomMeta::OFFSET_LOGO,
+        &RomMeta::LOGO,
+    );
+
+    Ok(diffs.pop())
+}
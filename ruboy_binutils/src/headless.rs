@@ -0,0 +1,89 @@
+//! Drivers for running the emulator without a window
+
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+
+use ruboy_lib::{BusSnooper, Frame, FrameObserver, GBGraphicsDrawer, GbInputs, InputHandler};
+
+/// SB, the serial transfer data register
+const SERIAL_DATA: u16 = 0xFF01;
+
+/// SC, the serial transfer control register
+const SERIAL_CONTROL: u16 = 0xFF02;
+
+const SERIAL_TRANSFER_ENABLE: u8 = 0b10000000;
+
+/// Discards all frames
+#[derive(Debug, Default)]
+pub struct NullOutput;
+
+impl GBGraphicsDrawer for NullOutput {
+    type Err = Infallible;
+
+    fn output(&mut self, _frame: &Frame) -> Result<(), Self::Err> {
+        Ok(())
+    }
+}
+
+/// Never presses any buttons
+#[derive(Debug, Default)]
+pub struct NullInput;
+
+impl InputHandler for NullInput {
+    fn poll_inputs(&mut self) -> Option<GbInputs> {
+        None
+    }
+}
+
+/// Collects every byte the game sends over the serial port. Test ROMs, like
+/// the Blargg ones, print their results this way. Clones share the output,
+/// so keep one after attaching another to the emulator.
+#[derive(Debug, Clone, Default)]
+pub struct SerialCapture {
+    output: Arc<Mutex<Vec<u8>>>,
+
+    /// The last value written to SB
+    data: u8,
+}
+
+impl SerialCapture {
+    pub fn output(&self) -> Vec<u8> {
+        self.output.lock().unwrap().clone()
+    }
+
+    /// The output so far, with anything that is not valid UTF-8 replaced
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.output()).into_owned()
+    }
+}
+
+impl BusSnooper for SerialCapture {
+    fn write(&mut self, addr: u16, value: u8) -> Option<u8> {
+        match addr {
+            SERIAL_DATA => self.data = value,
+            SERIAL_CONTROL if value & SERIAL_TRANSFER_ENABLE != 0 => {
+                self.output.lock().unwrap().push(self.data)
+            }
+            _ => (),
+        }
+
+        Some(value)
+    }
+}
+
+/// Remembers the hash of the last drawn frame. Clones share it.
+#[derive(Debug, Clone, Default)]
+pub struct LastFrame(Arc<Mutex<Option<u64>>>);
+
+impl LastFrame {
+    /// The [Frame::digest] of the last frame, or None if no frame was drawn
+    pub fn digest(&self) -> Option<u64> {
+        *self.0.lock().unwrap()
+    }
+}
+
+impl FrameObserver for LastFrame {
+    fn frame(&mut self, frame: &Frame, _number: u64, _cycle: u64) {
+        *self.0.lock().unwrap() = Some(frame.digest());
+    }
+}
@@ -1,7 +1,12 @@
 use std::fmt::Display;
 use unicode_width::UnicodeWidthStr;
 
+pub mod asm;
 pub mod cli;
+pub mod dasm;
+pub mod header;
+pub mod headless;
+pub mod output;
 
 #[derive(Default)]
 pub struct ListOutput {
@@ -0,0 +1,69 @@
+//! Output for other tools. Every report can also be printed as JSON, or as
+//! CSV records.
+
+use std::fmt::Display;
+
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Formatted for people
+    #[default]
+    Text,
+
+    Json,
+
+    /// One record per line, with a header line
+    Csv,
+}
+
+/// The result of a command, printable in every [OutputFormat]. Serializing
+/// it gives the JSON output.
+pub trait Report: Serialize {
+    /// A CSV record. Its fields have to be plain values.
+    type Row: Serialize;
+
+    /// The text output
+    fn text(&self) -> String;
+
+    /// The CSV output
+    fn rows(&self) -> Vec<Self::Row>;
+}
+
+/// A CSV record for reports that are a list of values
+#[derive(Debug, Serialize)]
+pub struct Field {
+    pub field: &'static str,
+    pub value: String,
+}
+
+impl Field {
+    pub fn new(field: &'static str, value: impl Display) -> Self {
+        Self {
+            field,
+            value: value.to_string(),
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn print<R: Report>(self, report: &R) -> Result<()> {
+        match self {
+            OutputFormat::Text => print!("{}", report.text()),
+            OutputFormat::Json => println!("{}", serde_json::to_string_pretty(report)?),
+            OutputFormat::Csv => {
+                let mut writer = csv::Writer::from_writer(std::io::stdout().lock());
+
+                for row in report.rows() {
+                    writer.serialize(row)?;
+                }
+
+                writer.flush()?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+//! Benchmarks of the paths every emulated cycle goes through. Each one runs
+//! a small generated ROM, so no game is needed. Run them with
+//! `cargo bench -p ruboy_lib`, and see `ruboy_bench` in ruboy_binutils for
+//! timing a whole game.
+
+use std::convert::Infallible;
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use ruboy_lib::isa::decoder::decode;
+use ruboy_lib::model::GbModel;
+use ruboy_lib::rom::InMemoryRom;
+use ruboy_lib::{
+    Frame, GBGraphicsDrawer, GbInputs, InlineAllocator, InputHandler, RenderMode, Ruboy,
+};
+
+/// The amount of T-cycles in a frame
+const FRAME_CYCLES: u64 = 70224;
+
+/// Where the generated code starts, right after the header
+const CODE_START: usize = 0x150;
+
+#[derive(Debug)]
+struct NullOutput;
+
+impl GBGraphicsDrawer for NullOutput {
+    type Err = Infallible;
+
+    fn output(&mut self, _frame: &Frame) -> Result<(), Self::Err> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+struct NullInput;
+
+impl InputHandler for NullInput {
+    fn poll_inputs(&mut self) -> Option<GbInputs> {
+        None
+    }
+}
+
+type BenchRuboy = Ruboy<InlineAllocator, InMemoryRom, NullOutput, NullInput>;
+
+/// Turns the LCD off, so only the CPU and memory are measured
+const LCD_OFF: [u8; 3] = [
+    0xAF, // XOR A
+    0xE0, 0x40, // LDH (LCDC), A
+];
+
+/// A ROM that runs `setup` once, and then `body` over and over. The body is
+/// repeated a few times between jumps, so the jump barely counts.
+fn looping_rom(cartridge_type: u8, setup: &[u8], body: &[u8]) -> InMemoryRom {
+    let mut rom = vec![0u8; 0x8000];
+
+    rom[0x100..0x103].copy_from_slice(&[0xC3, CODE_START as u8, (CODE_START >> 8) as u8]);
+    rom[0x147] = cartridge_type;
+
+    let loop_start = CODE_START + setup.len();
+    let mut code = setup.to_vec();
+
+    for _ in 0..32 {
+        code.extend_from_slice(body);
+    }
+
+    code.extend_from_slice(&[0xC3, loop_start as u8, (loop_start >> 8) as u8]);
+    rom[CODE_START..CODE_START + code.len()].copy_from_slice(&code);
+
+    InMemoryRom::from(rom)
+}
+
+fn start(rom: InMemoryRom) -> BenchRuboy {
+    Ruboy::with_model(rom, NullOutput, NullInput, GbModel::Dmg).unwrap()
+}
+
+/// Runs a frame worth of cycles per iteration
+fn bench_cycles(c: &mut Criterion, name: &str, rom: InMemoryRom) {
+    let mut ruboy = start(rom);
+
+    c.bench_function(name, |b| {
+        b.iter(|| ruboy.run_cycles(black_box(FRAME_CYCLES)).unwrap())
+    });
+}
+
+fn cpu(c: &mut Criterion) {
+    let alu = [
+        0x3C, // INC A
+        0x80, // ADD A, B
+        0x47, // LD B, A
+        0xA9, // XOR C
+        0x4F, // LD C, A
+        0x05, // DEC B
+        0x00, // NOP
+    ];
+
+    bench_cycles(c, "cpu/straight_line", looping_rom(0x00, &LCD_OFF, &alu));
+
+    let prefixed = [
+        0xCB, 0x10, // RL B
+        0xCB, 0x7F, // BIT 7, A
+        0xCB, 0xD9, // SET 3, C
+        0xCB, 0x37, // SWAP A
+        0xCB, 0x3A, // SRL D
+        0xCB, 0x91, // RES 2, C
+    ];
+
+    bench_cycles(c, "cpu/cb_prefixed", looping_rom(0x00, &LCD_OFF, &prefixed));
+}
+
+fn decoder(c: &mut Criterion) {
+    let prefixed: Vec<u8> = (0..=255).flat_map(|op| [0xCB, op]).collect();
+
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(256));
+
+    group.bench_function("cb_prefixed", |b| {
+        b.iter(|| {
+            for pc in (0..prefixed.len()).step_by(2) {
+                black_box(decode(&prefixed.as_slice(), black_box(pc as u16)).unwrap());
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn ppu(c: &mut Criterion) {
+    // Spins in place, leaving the CPU nearly idle while the PPU draws
+    let spin = looping_rom(0x00, &[], &[0x18, 0xFE]);
+
+    for (name, mode) in [
+        ("ppu/frame_fifo", RenderMode::Fifo),
+        ("ppu/frame_scanline", RenderMode::Scanline),
+    ] {
+        let mut ruboy = start(spin.clone());
+        ruboy.set_render_mode(mode);
+
+        c.bench_function(name, |b| {
+            b.iter(|| ruboy.run_cycles(black_box(FRAME_CYCLES)).unwrap())
+        });
+    }
+}
+
+fn memory(c: &mut Criterion) {
+    let wram_setup = [LCD_OFF.as_slice(), &[0x21, 0x00, 0xC0]].concat(); // LD HL, 0xC000
+
+    let wram = [
+        0x7E, // LD A, (HL)
+        0x77, // LD (HL), A
+        0x2C, // INC L
+    ];
+
+    bench_cycles(c, "memory/wram", looping_rom(0x00, &wram_setup, &wram));
+
+    let high = [
+        0xF0, 0x80, // LDH A, (0xFF80)
+        0xE0, 0x81, // LDH (0xFF81), A
+        0xF0, 0x44, // LDH A, (LY)
+        0xF0, 0x00, // LDH A, (P1)
+        0xE0, 0x47, // LDH (BGP), A
+    ];
+
+    bench_cycles(c, "memory/hram_io", looping_rom(0x00, &LCD_OFF, &high));
+
+    let cartridge = [
+        0x3E, 0x02, // LD A, 2
+        0xEA, 0x00, 0x20, // LD (0x2000), A
+        0xFA, 0x00, 0x40, // LD A, (0x4000)
+        0xFA, 0x00, 0x10, // LD A, (0x1000)
+    ];
+
+    // MBC1, so the writes switch banks
+    bench_cycles(
+        c,
+        "memory/cartridge",
+        looping_rom(0x01, &LCD_OFF, &cartridge),
+    );
+}
+
+criterion_group!(benches, cpu, decoder, ppu, memory);
+criterion_main!(benches);
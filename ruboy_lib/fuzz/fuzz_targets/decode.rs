@@ -0,0 +1,35 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use ruboy_lib::isa::{
+    decoder::decode,
+    display::{DisplayableInstruction, FormatOpts},
+    Instruction,
+};
+
+// The first two bytes are the PC, the rest is memory starting at 0x0000
+fuzz_target!(|data: &[u8]| {
+    let Some((pc, mem)) = data.split_first_chunk::<2>() else {
+        return;
+    };
+
+    let pc = u16::from_le_bytes(*pc);
+
+    let Ok(instr) = decode(&mem, pc) else {
+        return;
+    };
+
+    // Re-encoding gives the bytes that were decoded, also when they wrapped
+    // around the end of the address space
+    let bytes = instr.encode().unwrap();
+
+    for (offset, byte) in bytes.iter().enumerate() {
+        assert_eq!(mem[pc.wrapping_add(offset as u16) as usize], *byte);
+    }
+
+    if !matches!(instr, Instruction::IllegalInstruction(_)) {
+        assert_eq!(instr.len() as usize, bytes.len());
+    }
+
+    DisplayableInstruction::from(instr).with_format(&FormatOpts::default());
+});
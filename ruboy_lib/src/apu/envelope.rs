@@ -0,0 +1,144 @@
+use crate::debug::EnvelopeState;
+use crate::model::StateDigest;
+use crate::savestate::state_data;
+
+/// Length counter. When enabled, it silences the channel once it has been
+/// clocked `max` times after being loaded with zero.
+#[derive(Debug, Clone, Copy)]
+pub struct Length {
+    max: u16,
+    counter: u16,
+    pub enabled: bool,
+}
+
+state_data!(Length { counter, enabled });
+
+impl Length {
+    pub const fn new(max: u16) -> Self {
+        Self {
+            max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    /// Loads the counter from the length bits of NRx1
+    pub fn load(&mut self, val: u8) {
+        self.counter = self.max - (val as u16 % self.max);
+    }
+
+    pub fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    /// Clocks the counter at 256Hz. Returns `true` if the channel must be
+    /// disabled
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+
+        self.counter -= 1;
+        self.counter == 0
+    }
+
+    /// The ticks left, if enabled
+    pub const fn remaining(&self) -> Option<u16> {
+        if self.enabled {
+            Some(self.counter)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_u16(self.counter);
+        digest.write_bool(self.enabled);
+    }
+}
+
+/// Volume envelope (NRx2), shared by the square and noise channels
+#[derive(Debug, Clone, Copy)]
+pub struct Envelope {
+    initial: u8,
+    increase: bool,
+    period: u8,
+
+    pub volume: u8,
+    timer: u8,
+}
+
+state_data!(Envelope {
+    initial,
+    increase,
+    period,
+    volume,
+    timer,
+});
+
+impl Envelope {
+    pub const fn new() -> Self {
+        Self {
+            initial: 0,
+            increase: false,
+            period: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.initial = val >> 4;
+        self.increase = (val & 0b1000) != 0;
+        self.period = val & 0b111;
+    }
+
+    pub const fn read(&self) -> u8 {
+        (self.initial << 4) | ((self.increase as u8) << 3) | self.period
+    }
+
+    pub const fn state(&self) -> EnvelopeState {
+        EnvelopeState {
+            initial: self.initial,
+            increase: self.increase,
+            period: self.period,
+        }
+    }
+
+    /// The DAC is off when the upper five bits of NRx2 are all zero
+    pub const fn dac_enabled(&self) -> bool {
+        (self.read() & 0xF8) != 0
+    }
+
+    pub fn trigger(&mut self) {
+        self.volume = self.initial;
+        self.timer = self.period;
+    }
+
+    /// Clocks the envelope at 64Hz
+    pub fn clock(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+
+        self.timer = self.timer.saturating_sub(1);
+
+        if self.timer == 0 {
+            self.timer = self.period;
+
+            if self.increase && self.volume < 15 {
+                self.volume += 1;
+            } else if !self.increase && self.volume > 0 {
+                self.volume -= 1;
+            }
+        }
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_u8(self.read());
+        digest.write_u8(self.volume);
+        digest.write_u8(self.timer);
+    }
+}
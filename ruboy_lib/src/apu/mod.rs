@@ -0,0 +1,632 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::fmt::Display;
+#[cfg(not(feature = "std"))]
+use num::traits::Float;
+
+use noise::Noise;
+use square::Square;
+use wave::Wave;
+
+use crate::{
+    debug::ChannelState,
+    logging::{debug, trace, Subsystem},
+    model::StateDigest,
+    savestate::state_data,
+    CLOCK_SPEED_HZ,
+};
+
+mod envelope;
+mod noise;
+mod square;
+mod wave;
+
+/// T-cycles between two steps of the frame sequencer (512Hz)
+const SEQUENCER_CYCLES: u16 = 8192;
+
+/// Per-T-cycle charge factor of the high-pass filter (the capacitor on
+/// the output of the real hardware) that removes the DC offset of the DACs
+const HIGH_PASS_CHARGE: f64 = 0.999958;
+
+const NR52_POWER: u8 = 0x80;
+
+/// One of the four sound channels of the APU
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AudioChannel {
+    Square1,
+    Square2,
+    Wave,
+    Noise,
+}
+
+impl AudioChannel {
+    pub const ALL: [Self; 4] = [Self::Square1, Self::Square2, Self::Wave, Self::Noise];
+
+    const fn index(self) -> usize {
+        match self {
+            Self::Square1 => 0,
+            Self::Square2 => 1,
+            Self::Wave => 2,
+            Self::Noise => 3,
+        }
+    }
+}
+
+impl Display for AudioChannel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Square1 => write!(f, "Square 1"),
+            Self::Square2 => write!(f, "Square 2"),
+            Self::Wave => write!(f, "Wave"),
+            Self::Noise => write!(f, "Noise"),
+        }
+    }
+}
+
+/// Resamples the output of the APU to the rate of the host. Produced
+/// samples are buffered until the frontend drains them.
+///
+/// When emulation runs faster or slower than real time, proportionally
+/// fewer or more samples are produced per emulated second, so the host
+/// still receives `rate` samples per real second.
+#[derive(Debug, Clone)]
+struct SampleOutput {
+    rate: u32,
+    speed: f64,
+
+    /// Samples produced per emulated second
+    step: u64,
+    acc: u64,
+    charge: f32,
+    capacitor: [f32; 2],
+    samples: Vec<[f32; 2]>,
+}
+
+impl SampleOutput {
+    fn new(rate: u32, speed: f64) -> Self {
+        let mut output = Self {
+            rate,
+            speed,
+            step: 0,
+            acc: 0,
+            charge: 0.0,
+            capacitor: [0.0; 2],
+            samples: Vec::with_capacity(rate as usize / 10),
+        };
+
+        output.set_rate(rate);
+        output
+    }
+
+    fn set_rate(&mut self, rate: u32) {
+        assert_ne!(0, rate, "Sample rate must be non-zero");
+
+        self.rate = rate;
+        self.update_step();
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+        self.update_step();
+    }
+
+    fn update_step(&mut self) {
+        self.step = ((self.rate as f64 / self.speed).round() as u64).max(1);
+        self.charge = HIGH_PASS_CHARGE.powf(CLOCK_SPEED_HZ as f64 / self.step as f64) as f32;
+    }
+
+    /// Returns `true` when a new sample is due
+    fn cycle(&mut self) -> bool {
+        self.acc += self.step;
+
+        if self.acc >= CLOCK_SPEED_HZ as u64 {
+            self.acc -= CLOCK_SPEED_HZ as u64;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn push(&mut self, sample: [f32; 2]) {
+        // Nobody is draining the samples. Keep at most a second of audio
+        // around instead of growing forever
+        if self.samples.len() >= self.rate as usize {
+            let half = self.samples.len() / 2;
+            self.samples.drain(..half);
+        }
+
+        let mut filtered = [0.0; 2];
+
+        for (side, input) in sample.into_iter().enumerate() {
+            filtered[side] = input - self.capacitor[side];
+            self.capacitor[side] = input - filtered[side] * self.charge;
+        }
+
+        self.samples.push(filtered);
+    }
+}
+
+/// The audio processing unit (0xFF10 - 0xFF3F).
+///
+/// The frame sequencer runs on its own 512Hz counter instead of being
+/// derived from DIV.
+#[derive(Debug, Clone)]
+pub struct Apu {
+    powered: bool,
+
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+
+    /// NR50
+    master_volume: u8,
+
+    /// NR51
+    panning: u8,
+
+    sequencer_cycles: u16,
+    sequencer_step: u8,
+
+    muted: [bool; 4],
+    speed: f64,
+    output: Option<SampleOutput>,
+
+    /// The last mixed samples and how many to keep, while the scope is
+    /// enabled
+    scope: Option<(VecDeque<[f32; 2]>, usize)>,
+}
+
+// Muting, the speed and the output are host settings, not emulator state
+state_data!(Apu {
+    powered,
+    square1,
+    square2,
+    wave,
+    noise,
+    master_volume,
+    panning,
+    sequencer_cycles,
+    sequencer_step,
+});
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub const fn new() -> Self {
+        Self {
+            powered: false,
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            master_volume: 0,
+            panning: 0,
+            sequencer_cycles: 0,
+            sequencer_step: 0,
+            muted: [false; 4],
+            speed: 1.0,
+            output: None,
+            scope: None,
+        }
+    }
+
+    /// Loads the state the boot ROM leaves NR50 - NR52 in
+    pub fn apply_boot_state(&mut self, volume: u8, panning: u8, enable: u8) {
+        self.set_power((enable & NR52_POWER) != 0);
+        self.master_volume = volume;
+        self.panning = panning;
+    }
+
+    fn set_power(&mut self, on: bool) {
+        if self.powered && !on {
+            // Powering off clears every register, except for wave RAM
+            let ram = self.wave.ram;
+
+            self.square1 = Square::new(true);
+            self.square2 = Square::new(false);
+            self.wave = Wave::new();
+            self.wave.ram = ram;
+            self.noise = Noise::new();
+            self.master_volume = 0;
+            self.panning = 0;
+        } else if !self.powered && on {
+            self.sequencer_cycles = 0;
+            self.sequencer_step = 0;
+        }
+
+        self.powered = on;
+    }
+
+    pub fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0xFF30..=0xFF3F => self.wave.ram[(addr - 0xFF30) as usize] = val,
+            0xFF26 => self.set_power((val & NR52_POWER) != 0),
+            _ if !self.powered => {
+                trace!(
+                    Subsystem::Apu,
+                    "Ignoring write to 0x{:x} while the APU is off",
+                    addr
+                );
+            }
+            0xFF10..=0xFF14 => self.square1.write(addr - 0xFF10, val),
+            0xFF15..=0xFF19 => self.square2.write(addr - 0xFF15, val),
+            0xFF1A..=0xFF1E => self.wave.write(addr - 0xFF1A, val),
+            0xFF20..=0xFF23 => self.noise.write(addr - 0xFF20, val),
+            0xFF24 => self.master_volume = val,
+            0xFF25 => self.panning = val,
+            _ => debug!(Subsystem::Apu, "Write to unused APU register 0x{:x}", addr),
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0xFF10..=0xFF14 => self.square1.read(addr - 0xFF10),
+            0xFF15..=0xFF19 => self.square2.read(addr - 0xFF15),
+            0xFF1A..=0xFF1E => self.wave.read(addr - 0xFF1A),
+            0xFF20..=0xFF23 => self.noise.read(addr - 0xFF20),
+            0xFF24 => self.master_volume,
+            0xFF25 => self.panning,
+            0xFF26 => self.status(),
+            0xFF30..=0xFF3F => self.wave.ram[(addr - 0xFF30) as usize],
+            _ => 0xFF,
+        }
+    }
+
+    /// NR52
+    fn status(&self) -> u8 {
+        ((self.powered as u8) << 7)
+            | 0x70
+            | ((self.noise.enabled as u8) << 3)
+            | ((self.wave.enabled as u8) << 2)
+            | ((self.square2.enabled as u8) << 1)
+            | (self.square1.enabled as u8)
+    }
+
+    /// Returns `true` when a sample was produced
+    pub fn cycle(&mut self) -> bool {
+        if self.powered {
+            self.square1.cycle();
+            self.square2.cycle();
+            self.wave.cycle();
+            self.noise.cycle();
+
+            self.sequencer_cycles += 1;
+
+            if self.sequencer_cycles == SEQUENCER_CYCLES {
+                self.sequencer_cycles = 0;
+                self.step_sequencer();
+            }
+        }
+
+        let due = match self.output.as_mut() {
+            Some(output) => output.cycle(),
+            None => false,
+        };
+
+        if due {
+            let sample = self.mix();
+
+            if let Some(output) = self.output.as_mut() {
+                output.push(sample);
+            }
+
+            if let Some((scope, len)) = self.scope.as_mut() {
+                if scope.len() == *len {
+                    scope.pop_front();
+                }
+
+                scope.push_back(sample);
+            }
+        }
+
+        due
+    }
+
+    fn step_sequencer(&mut self) {
+        // Length at 256Hz, sweep at 128Hz and envelopes at 64Hz
+        if (self.sequencer_step & 1) == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+
+        if self.sequencer_step == 2 || self.sequencer_step == 6 {
+            self.square1.clock_sweep();
+        }
+
+        if self.sequencer_step == 7 {
+            self.square1.envelope.clock();
+            self.square2.envelope.clock();
+            self.noise.envelope.clock();
+        }
+
+        self.sequencer_step = (self.sequencer_step + 1) % 8;
+    }
+
+    /// Converts the digital output of a channel to the -1.0 - 1.0 range
+    fn dac(output: u8, enabled: bool) -> f32 {
+        if enabled {
+            (output as f32 / 7.5) - 1.0
+        } else {
+            0.0
+        }
+    }
+
+    /// Mixes the channels into a stereo sample
+    fn mix(&self) -> [f32; 2] {
+        if !self.powered {
+            return [0.0; 2];
+        }
+
+        let channels = [
+            Self::dac(self.square1.output(), self.square1.dac_enabled()),
+            Self::dac(self.square2.output(), self.square2.dac_enabled()),
+            Self::dac(self.wave.output(), self.wave.dac_enabled()),
+            Self::dac(self.noise.output(), self.noise.dac_enabled()),
+        ];
+
+        let mut left = 0.0;
+        let mut right = 0.0;
+
+        for (i, analog) in channels.into_iter().enumerate() {
+            if self.muted[i] {
+                continue;
+            }
+
+            if (self.panning & (1 << (i + 4))) != 0 {
+                left += analog;
+            }
+
+            if (self.panning & (1 << i)) != 0 {
+                right += analog;
+            }
+        }
+
+        let left_volume = (((self.master_volume >> 4) & 0b111) + 1) as f32 / 8.0;
+        let right_volume = ((self.master_volume & 0b111) + 1) as f32 / 8.0;
+
+        [left * left_volume / 4.0, right * right_volume / 4.0]
+    }
+
+    /// Starts producing samples at the given rate
+    pub fn enable_output(&mut self, rate: u32) {
+        self.output = Some(SampleOutput::new(rate, self.speed));
+    }
+
+    pub fn disable_output(&mut self) {
+        self.output = None;
+    }
+
+    /// Changes the output rate, without dropping buffered samples
+    pub fn set_sample_rate(&mut self, rate: u32) {
+        match self.output.as_mut() {
+            Some(output) => output.set_rate(rate),
+            None => self.enable_output(rate),
+        }
+    }
+
+    /// Tells the resampler how fast emulation runs compared to real time
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed;
+
+        if let Some(output) = self.output.as_mut() {
+            output.set_speed(speed);
+        }
+    }
+
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.output.as_ref().map(|output| output.rate)
+    }
+
+    pub fn drain_samples(&mut self) -> impl Iterator<Item = [f32; 2]> + '_ {
+        self.output
+            .as_mut()
+            .map(|output| output.samples.drain(..))
+            .into_iter()
+            .flatten()
+    }
+
+    pub fn channel_state(&self, channel: AudioChannel) -> ChannelState {
+        let mut state = match channel {
+            AudioChannel::Square1 => self.square1.state(),
+            AudioChannel::Square2 => self.square2.state(),
+            AudioChannel::Wave => self.wave.state(),
+            AudioChannel::Noise => self.noise.state(),
+        };
+
+        let i = channel.index();
+
+        state.left = (self.panning & (1 << (i + 4))) != 0;
+        state.right = (self.panning & (1 << i)) != 0;
+        state.muted = self.muted[i];
+
+        state
+    }
+
+    /// Starts keeping the last `len` mixed samples, see [Apu::scope]
+    pub fn enable_scope(&mut self, len: usize) {
+        self.scope = Some((VecDeque::with_capacity(len), len));
+    }
+
+    pub fn disable_scope(&mut self) {
+        self.scope = None;
+    }
+
+    /// The last mixed samples, oldest first, before the high-pass filter.
+    /// Samples are only mixed while there is an output.
+    pub fn scope(&self) -> Option<impl Iterator<Item = [f32; 2]> + '_> {
+        self.scope.as_ref().map(|(scope, _)| scope.iter().copied())
+    }
+
+    pub fn set_muted(&mut self, channel: AudioChannel, muted: bool) {
+        self.muted[channel.index()] = muted;
+    }
+
+    pub const fn muted(&self, channel: AudioChannel) -> bool {
+        self.muted[channel.index()]
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_bool(self.powered);
+        self.square1.digest_into(digest);
+        self.square2.digest_into(digest);
+        self.wave.digest_into(digest);
+        self.noise.digest_into(digest);
+        digest.write_u8(self.master_volume);
+        digest.write_u8(self.panning);
+        digest.write_u16(self.sequencer_cycles);
+        digest.write_u8(self.sequencer_step);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::EnvelopeState;
+
+    fn powered() -> Apu {
+        let mut apu = Apu::new();
+        apu.write(0xFF26, NR52_POWER);
+        apu.write(0xFF24, 0x77);
+        apu.write(0xFF25, 0xFF);
+        apu
+    }
+
+    fn run(apu: &mut Apu, cycles: usize) {
+        for _ in 0..cycles {
+            apu.cycle();
+        }
+    }
+
+    #[test]
+    fn trigger_enables_channel() {
+        let mut apu = powered();
+
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x80);
+        assert_eq!(0xF1, apu.read(0xFF26));
+
+        // Turning the DAC off disables the channel again
+        apu.write(0xFF12, 0x00);
+        assert_eq!(0xF0, apu.read(0xFF26));
+    }
+
+    #[test]
+    fn length_counter_disables_channel() {
+        let mut apu = powered();
+
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF16, 0x3E); // Two length clocks left
+        apu.write(0xFF19, 0xC0);
+        assert_eq!(0xF2, apu.read(0xFF26));
+
+        // Lengths are clocked on the even steps, starting at the first
+        run(&mut apu, SEQUENCER_CYCLES as usize);
+        assert_eq!(0xF2, apu.read(0xFF26));
+
+        run(&mut apu, 2 * SEQUENCER_CYCLES as usize);
+        assert_eq!(0xF0, apu.read(0xFF26));
+    }
+
+    #[test]
+    fn power_off_clears_registers_but_not_wave_ram() {
+        let mut apu = powered();
+
+        apu.write(0xFF30, 0x12);
+        apu.write(0xFF11, 0x80);
+        apu.write(0xFF26, 0x00);
+
+        assert_eq!(0x00, apu.read(0xFF24));
+        assert_eq!(0x3F, apu.read(0xFF11));
+        assert_eq!(0x12, apu.read(0xFF30));
+
+        // Writes are ignored while off
+        apu.write(0xFF24, 0x77);
+        assert_eq!(0x00, apu.read(0xFF24));
+    }
+
+    #[test]
+    fn produces_samples_at_rate() {
+        let mut apu = powered();
+        apu.enable_output(1000);
+
+        run(&mut apu, CLOCK_SPEED_HZ / 4);
+        assert_eq!(250, apu.drain_samples().count());
+        assert_eq!(0, apu.drain_samples().count());
+    }
+
+    #[test]
+    fn resamples_for_speed() {
+        let mut apu = powered();
+        apu.enable_output(1000);
+        apu.set_speed(2.0);
+
+        run(&mut apu, CLOCK_SPEED_HZ / 4);
+        assert_eq!(125, apu.drain_samples().count());
+    }
+
+    #[test]
+    fn muted_channel_is_silent() {
+        let mut apu = powered();
+        apu.enable_output(48000);
+
+        apu.write(0xFF12, 0xF0);
+        apu.write(0xFF14, 0x80);
+        apu.set_muted(AudioChannel::Square1, true);
+
+        run(&mut apu, CLOCK_SPEED_HZ / 10);
+        assert!(apu.drain_samples().all(|s| s == [0.0; 2]));
+    }
+
+    #[test]
+    fn channel_state_shows_registers() {
+        let mut apu = powered();
+        apu.write(0xFF25, 0x20); // Only left
+
+        apu.write(0xFF16, 0x80); // 50% duty
+        apu.write(0xFF17, 0xA3); // Volume 10, decreasing every 3 ticks
+        apu.write(0xFF18, 0xD6);
+        apu.write(0xFF19, 0xC6); // Trigger with length, frequency 0x6D6
+
+        let state = apu.channel_state(AudioChannel::Square2);
+        assert!(state.enabled && state.dac_enabled);
+        assert_eq!(0x6D6, state.freq);
+        assert_eq!(440, state.hz.round() as u32);
+        assert_eq!(Some(0.5), state.duty);
+        assert_eq!(10, state.volume);
+        assert_eq!(
+            Some(EnvelopeState {
+                initial: 10,
+                increase: false,
+                period: 3
+            }),
+            state.envelope
+        );
+        assert_eq!(Some(64), state.length);
+        assert!(state.left && !state.right && !state.muted);
+
+        let wave = apu.channel_state(AudioChannel::Wave);
+        assert!(!wave.enabled);
+        assert_eq!(None, wave.envelope);
+    }
+
+    #[test]
+    fn scope_keeps_last_samples() {
+        let mut apu = powered();
+        apu.enable_output(48000);
+        assert!(apu.scope().is_none());
+
+        apu.enable_scope(16);
+        run(&mut apu, CLOCK_SPEED_HZ / 100);
+
+        // Without taking them from the output
+        assert_eq!(16, apu.scope().unwrap().count());
+        assert!(apu.drain_samples().count() > 400);
+    }
+}
@@ -0,0 +1,159 @@
+use crate::debug::ChannelState;
+use crate::model::StateDigest;
+use crate::savestate::state_data;
+use crate::CLOCK_SPEED_HZ;
+
+use super::envelope::{Envelope, Length};
+
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+/// The noise channel (channel 4), driven by a linear feedback shift register
+#[derive(Debug, Clone, Copy)]
+pub struct Noise {
+    pub enabled: bool,
+    clock_shift: u8,
+    short_mode: bool,
+    divisor_code: u8,
+    timer: u32,
+    lfsr: u16,
+
+    pub length: Length,
+    pub envelope: Envelope,
+}
+
+state_data!(Noise {
+    enabled,
+    clock_shift,
+    short_mode,
+    divisor_code,
+    timer,
+    lfsr,
+    length,
+    envelope,
+});
+
+impl Noise {
+    pub const fn new() -> Self {
+        Self {
+            enabled: false,
+            clock_shift: 0,
+            short_mode: false,
+            divisor_code: 0,
+            timer: 0,
+            lfsr: 0x7FFF,
+            length: Length::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    const fn period(&self) -> u32 {
+        DIVISORS[self.divisor_code as usize] << self.clock_shift
+    }
+
+    /// Writes NR41 - NR44, where `reg` is the offset from NR41
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => self.length.load(val & 0x3F),
+            1 => {
+                self.envelope.write(val);
+
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            2 => {
+                self.clock_shift = val >> 4;
+                self.short_mode = (val & 0b1000) != 0;
+                self.divisor_code = val & 0b111;
+            }
+            3 => {
+                self.length.enabled = (val & 0x40) != 0;
+
+                if (val & 0x80) != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!("Invalid noise channel register {}", reg),
+        }
+    }
+
+    pub const fn read(&self, reg: u16) -> u8 {
+        match reg {
+            1 => self.envelope.read(),
+            2 => (self.clock_shift << 4) | ((self.short_mode as u8) << 3) | self.divisor_code,
+            3 => 0xBF | ((self.length.enabled as u8) << 6),
+            _ => 0xFF,
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.timer = self.period();
+        self.envelope.trigger();
+        self.lfsr = 0x7FFF;
+    }
+
+    pub fn cycle(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period();
+
+            let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 1;
+            self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+
+            if self.short_mode {
+                self.lfsr = (self.lfsr & !(1 << 6)) | (feedback << 6);
+            }
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// The current digital output, 0 - 15
+    pub const fn output(&self) -> u8 {
+        if self.enabled && (self.lfsr & 1) == 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+
+    pub const fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Everything but the panning and muting, which are up to the APU
+    pub fn state(&self) -> ChannelState {
+        ChannelState {
+            enabled: self.enabled,
+            dac_enabled: self.dac_enabled(),
+            freq: 0,
+            hz: CLOCK_SPEED_HZ as f32 / self.period() as f32,
+            duty: None,
+            volume: self.envelope.volume,
+            envelope: Some(self.envelope.state()),
+            length: self.length.remaining(),
+            output: self.output(),
+            left: false,
+            right: false,
+            muted: false,
+        }
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_bool(self.enabled);
+        digest.write_u8(self.read(2));
+        digest.write_u64(self.timer as u64);
+        digest.write_u16(self.lfsr);
+        self.length.digest_into(digest);
+        self.envelope.digest_into(digest);
+    }
+}
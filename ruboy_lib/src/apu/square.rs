@@ -0,0 +1,288 @@
+use crate::debug::ChannelState;
+use crate::model::StateDigest;
+use crate::savestate::state_data;
+use crate::CLOCK_SPEED_HZ;
+
+use super::envelope::{Envelope, Length};
+
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+/// Frequency sweep (NR10). Only present on channel 1
+#[derive(Debug, Clone, Copy, Default)]
+struct Sweep {
+    period: u8,
+    negate: bool,
+    shift: u8,
+
+    timer: u8,
+    shadow: u16,
+    enabled: bool,
+}
+
+state_data!(Sweep {
+    period,
+    negate,
+    shift,
+    timer,
+    shadow,
+    enabled,
+});
+
+impl Sweep {
+    fn write(&mut self, val: u8) {
+        self.period = (val >> 4) & 0b111;
+        self.negate = (val & 0b1000) != 0;
+        self.shift = val & 0b111;
+    }
+
+    const fn read(&self) -> u8 {
+        0x80 | (self.period << 4) | ((self.negate as u8) << 3) | self.shift
+    }
+
+    fn reload_timer(&mut self) {
+        // A period of zero is treated as eight
+        self.timer = if self.period == 0 { 8 } else { self.period };
+    }
+
+    /// Calculates the next frequency. Returns [None] on overflow
+    const fn next_freq(&self) -> Option<u16> {
+        let delta = self.shadow >> self.shift;
+
+        let freq = if self.negate {
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        };
+
+        if freq > 2047 {
+            None
+        } else {
+            Some(freq)
+        }
+    }
+}
+
+/// Square wave channel, used for both channel 1 (with sweep) and channel 2
+#[derive(Debug, Clone, Copy)]
+pub struct Square {
+    sweep: Option<Sweep>,
+
+    pub enabled: bool,
+    duty: u8,
+    duty_pos: u8,
+    freq: u16,
+    timer: u16,
+
+    pub length: Length,
+    pub envelope: Envelope,
+}
+
+state_data!(Square {
+    sweep,
+    enabled,
+    duty,
+    duty_pos,
+    freq,
+    timer,
+    length,
+    envelope,
+});
+
+impl Square {
+    pub const fn new(with_sweep: bool) -> Self {
+        Self {
+            sweep: if with_sweep {
+                Some(Sweep {
+                    period: 0,
+                    negate: false,
+                    shift: 0,
+                    timer: 0,
+                    shadow: 0,
+                    enabled: false,
+                })
+            } else {
+                None
+            },
+            enabled: false,
+            duty: 0,
+            duty_pos: 0,
+            freq: 0,
+            timer: 0,
+            length: Length::new(64),
+            envelope: Envelope::new(),
+        }
+    }
+
+    const fn period(&self) -> u16 {
+        (2048 - self.freq) * 4
+    }
+
+    /// Writes NRx0 - NRx4, where `reg` is the offset from NRx0
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                if let Some(sweep) = self.sweep.as_mut() {
+                    sweep.write(val);
+                }
+            }
+            1 => {
+                self.duty = val >> 6;
+                self.length.load(val & 0x3F);
+            }
+            2 => {
+                self.envelope.write(val);
+
+                if !self.envelope.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.freq = (self.freq & 0x700) | val as u16,
+            4 => {
+                self.freq = (self.freq & 0xFF) | (((val & 0b111) as u16) << 8);
+                self.length.enabled = (val & 0x40) != 0;
+
+                if (val & 0x80) != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!("Invalid square channel register {}", reg),
+        }
+    }
+
+    pub const fn read(&self, reg: u16) -> u8 {
+        match reg {
+            0 => match &self.sweep {
+                Some(sweep) => sweep.read(),
+                None => 0xFF,
+            },
+            1 => (self.duty << 6) | 0x3F,
+            2 => self.envelope.read(),
+            3 => 0xFF,
+            4 => 0xBF | ((self.length.enabled as u8) << 6),
+            _ => 0xFF,
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.envelope.dac_enabled();
+        self.length.trigger();
+        self.timer = self.period();
+        self.envelope.trigger();
+
+        if let Some(sweep) = self.sweep.as_mut() {
+            sweep.shadow = self.freq;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period != 0 || sweep.shift != 0;
+
+            if sweep.shift != 0 && sweep.next_freq().is_none() {
+                self.enabled = false;
+            }
+        }
+    }
+
+    pub fn cycle(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.duty_pos = (self.duty_pos + 1) % 8;
+        }
+    }
+
+    /// Clocks the sweep at 128Hz
+    pub fn clock_sweep(&mut self) {
+        let Some(sweep) = self.sweep.as_mut() else {
+            return;
+        };
+
+        sweep.timer = sweep.timer.saturating_sub(1);
+
+        if sweep.timer != 0 {
+            return;
+        }
+
+        sweep.reload_timer();
+
+        if !sweep.enabled || sweep.period == 0 {
+            return;
+        }
+
+        let Some(freq) = sweep.next_freq() else {
+            self.enabled = false;
+            return;
+        };
+
+        if sweep.shift != 0 {
+            sweep.shadow = freq;
+            self.freq = freq;
+
+            // The new frequency is immediately checked for overflow
+            // again, but not written back
+            if sweep.next_freq().is_none() {
+                self.enabled = false;
+            }
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// The current digital output, 0 - 15
+    pub const fn output(&self) -> u8 {
+        if self.enabled && DUTY_PATTERNS[self.duty as usize][self.duty_pos as usize] != 0 {
+            self.envelope.volume
+        } else {
+            0
+        }
+    }
+
+    pub const fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    /// Everything but the panning and muting, which are up to the APU
+    pub fn state(&self) -> ChannelState {
+        ChannelState {
+            enabled: self.enabled,
+            dac_enabled: self.dac_enabled(),
+            freq: self.freq,
+            // A period steps through one of the eight duty steps
+            hz: CLOCK_SPEED_HZ as f32 / (self.period() as f32 * 8.0),
+            duty: Some([0.125, 0.25, 0.5, 0.75][self.duty as usize]),
+            volume: self.envelope.volume,
+            envelope: Some(self.envelope.state()),
+            length: self.length.remaining(),
+            output: self.output(),
+            left: false,
+            right: false,
+            muted: false,
+        }
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        if let Some(sweep) = &self.sweep {
+            digest.write_u8(sweep.read());
+            digest.write_u8(sweep.timer);
+            digest.write_u16(sweep.shadow);
+            digest.write_bool(sweep.enabled);
+        }
+
+        digest.write_bool(self.enabled);
+        digest.write_u8(self.duty);
+        digest.write_u8(self.duty_pos);
+        digest.write_u16(self.freq);
+        digest.write_u16(self.timer);
+        self.length.digest_into(digest);
+        self.envelope.digest_into(digest);
+    }
+}
@@ -0,0 +1,169 @@
+use crate::debug::ChannelState;
+use crate::model::StateDigest;
+use crate::savestate::state_data;
+use crate::CLOCK_SPEED_HZ;
+
+use super::envelope::Length;
+
+/// The wave channel (channel 3), which plays back the 32 4-bit samples
+/// stored in wave RAM
+#[derive(Debug, Clone, Copy)]
+pub struct Wave {
+    dac: bool,
+    pub enabled: bool,
+    volume_code: u8,
+    freq: u16,
+    timer: u16,
+    position: u8,
+
+    pub length: Length,
+
+    /// 0xFF30 - 0xFF3F
+    pub ram: [u8; 16],
+}
+
+state_data!(Wave {
+    dac,
+    enabled,
+    volume_code,
+    freq,
+    timer,
+    position,
+    length,
+    ram,
+});
+
+impl Wave {
+    pub const fn new() -> Self {
+        Self {
+            dac: false,
+            enabled: false,
+            volume_code: 0,
+            freq: 0,
+            timer: 0,
+            position: 0,
+            length: Length::new(256),
+            ram: [0; 16],
+        }
+    }
+
+    const fn period(&self) -> u16 {
+        (2048 - self.freq) * 2
+    }
+
+    /// Writes NR30 - NR34, where `reg` is the offset from NR30
+    pub fn write(&mut self, reg: u16, val: u8) {
+        match reg {
+            0 => {
+                self.dac = (val & 0x80) != 0;
+
+                if !self.dac {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(val),
+            2 => self.volume_code = (val >> 5) & 0b11,
+            3 => self.freq = (self.freq & 0x700) | val as u16,
+            4 => {
+                self.freq = (self.freq & 0xFF) | (((val & 0b111) as u16) << 8);
+                self.length.enabled = (val & 0x40) != 0;
+
+                if (val & 0x80) != 0 {
+                    self.trigger();
+                }
+            }
+            _ => unreachable!("Invalid wave channel register {}", reg),
+        }
+    }
+
+    pub const fn read(&self, reg: u16) -> u8 {
+        match reg {
+            0 => 0x7F | ((self.dac as u8) << 7),
+            2 => 0x9F | (self.volume_code << 5),
+            4 => 0xBF | ((self.length.enabled as u8) << 6),
+            _ => 0xFF,
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    pub fn cycle(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+        }
+
+        if self.timer == 0 {
+            self.timer = self.period();
+            self.position = (self.position + 1) % 32;
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// The current digital output, 0 - 15
+    pub const fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        let byte = self.ram[(self.position / 2) as usize];
+
+        // The high nibble is played first
+        let sample = if (self.position & 1) == 0 {
+            byte >> 4
+        } else {
+            byte & 0x0F
+        };
+
+        match self.volume_code {
+            0 => 0,
+            code => sample >> (code - 1),
+        }
+    }
+
+    pub const fn dac_enabled(&self) -> bool {
+        self.dac
+    }
+
+    /// Everything but the panning and muting, which are up to the APU
+    pub fn state(&self) -> ChannelState {
+        ChannelState {
+            enabled: self.enabled,
+            dac_enabled: self.dac,
+            freq: self.freq,
+            // A period steps through one of the 32 samples
+            hz: CLOCK_SPEED_HZ as f32 / (self.period() as f32 * 32.0),
+            duty: None,
+            volume: match self.volume_code {
+                0 => 0,
+                code => 15 >> (code - 1),
+            },
+            envelope: None,
+            length: self.length.remaining(),
+            output: self.output(),
+            left: false,
+            right: false,
+            muted: false,
+        }
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_bool(self.dac);
+        digest.write_bool(self.enabled);
+        digest.write_u8(self.volume_code);
+        digest.write_u16(self.freq);
+        digest.write_u16(self.timer);
+        digest.write_u8(self.position);
+        self.length.digest_into(digest);
+        digest.write_bytes(&self.ram);
+    }
+}
@@ -14,15 +14,23 @@ macro_rules! path_sep {
     };
 }
 
+// DIGEST is the FNV-1a hash of the selected image, used by the self-test
+// to catch a corrupted or wrong image being compiled in
 cfg_if! {
     if #[cfg(feature = "boot_dmg0")] {
         pub const IMAGE_NAME: &str = "DMG0";
         pub const IMAGE: &[u8] = include_bytes!(concat!("..", path_sep!(), "boot", path_sep!(), "dmg0.bin"));
+        pub const DIGEST: u64 = 0xBAA7F0CF4313F1C0;
     } else if #[cfg(feature = "boot_dmg")] {
         pub const IMAGE_NAME: &str = "DMG";
         pub const IMAGE: &[u8] = include_bytes!(concat!("..", path_sep!(), "boot", path_sep!(), "dmg.bin"));
+        pub const DIGEST: u64 = 0x7446127DDFDAAFA5;
     } else if #[cfg(feature = "boot_mgb")] {
         pub const IMAGE_NAME: &str = "MGB";
         pub const IMAGE: &[u8] = include_bytes!(concat!("..", path_sep!(), "boot", path_sep!(), "mgb.bin"));
+        pub const DIGEST: u64 = 0x0C6EE87A40B8915B;
+    } else {
+        // Never mapped, as emulation then starts from a boot state preset
+        pub const IMAGE: &[u8] = &[];
     }
 }
@@ -0,0 +1,426 @@
+//! Step by step construction of a [Ruboy], for when the plain constructors
+//! do not offer enough options.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::marker::PhantomData;
+
+use crate::color::DmgPalette;
+use crate::debug::Debugger;
+use crate::memcontroller::MemControllerInitErr;
+use crate::model::{BootRom, GbModel, InputPolling, RamInit, RuboyConfig};
+use crate::rom::controller::RomControllerInitErr;
+use crate::rom::rtc::Rtc;
+use crate::rom::BankCaching;
+use crate::storage::StorageProvider;
+use crate::{
+    rtc_key, sav_key, BusSnooper, FrameObserver, GBAllocator, GBGraphicsDrawer, InputHandler,
+    LoadPersistentErr, RenderMode, RomReader, Ruboy, RuboyStartErr,
+};
+
+/// Collects the options for a new emulator instance. Create one with
+/// [Ruboy::builder], and finish with [RuboyBuilder::build].
+///
+/// ```ignore
+/// let ruboy = Ruboy::<InlineAllocator, _, _, _>::builder(rom, output, input)
+///     .model(GbModel::Mgb)
+///     .palette(DmgPalette::GRAYSCALE)
+///     .audio(48000)
+///     .build()?;
+/// ```
+pub struct RuboyBuilder<A, R, V, I>
+where
+    A: GBAllocator,
+    R: RomReader,
+    V: GBGraphicsDrawer,
+    I: InputHandler,
+{
+    rom: R,
+    output: V,
+    input: I,
+
+    boot_rom: BootRom,
+    config: RuboyConfig,
+
+    /// Whether [RuboyBuilder::ram_init] was called, which [RuboyBuilder::model]
+    /// then leaves alone
+    ram_init_chosen: bool,
+
+    palette: Option<DmgPalette>,
+    audio_sample_rate: Option<u32>,
+    rtc: Option<Rtc>,
+    cartridge_ram: Option<Vec<u8>>,
+
+    debugger: Option<Debugger>,
+    trace_capacity: Option<usize>,
+    snooper: Option<Box<dyn BusSnooper>>,
+    frame_observer: Option<Box<dyn FrameObserver>>,
+
+    allocator: PhantomData<A>,
+}
+
+impl<A: GBAllocator, R: RomReader, V: GBGraphicsDrawer, I: InputHandler> RuboyBuilder<A, R, V, I> {
+    pub fn new(rom: R, output: V, input: I) -> Self {
+        Self {
+            rom,
+            output,
+            input,
+            boot_rom: BootRom::default(),
+            config: RuboyConfig::default(),
+            ram_init_chosen: false,
+            palette: None,
+            audio_sample_rate: None,
+            rtc: None,
+            cartridge_ram: None,
+            debugger: None,
+            trace_capacity: None,
+            snooper: None,
+            frame_observer: None,
+            allocator: PhantomData,
+        }
+    }
+
+    /// Skips the boot ROM, starting in the state the given model is in
+    /// after its boot ROM finishes: with the registers games check to detect
+    /// the model, and RAM filled with the [RamInit::Pattern] of the model.
+    /// A RAM init chosen with [RuboyBuilder::ram_init] takes precedence.
+    pub fn model(mut self, model: GbModel) -> Self {
+        if !self.ram_init_chosen {
+            self.config.ram_init = RamInit::Pattern { seed: 0 };
+        }
+
+        self.boot_rom(BootRom::Skip(model))
+    }
+
+    /// What runs before the cartridge takes over. Defaults to
+    /// [BootRom::BuiltIn].
+    pub fn boot_rom(mut self, boot_rom: BootRom) -> Self {
+        if let BootRom::Skip(model) = boot_rom {
+            self.config.model = model;
+        }
+
+        self.boot_rom = boot_rom;
+        self
+    }
+
+    /// What work RAM and high RAM contain at power on
+    pub fn ram_init(mut self, ram_init: RamInit) -> Self {
+        self.config.ram_init = ram_init;
+        self.ram_init_chosen = true;
+        self
+    }
+
+    /// Makes runs reproducible, see [RuboyConfig::deterministic]
+    pub fn deterministic(mut self, seed: u64) -> Self {
+        self.config.deterministic = Some(seed);
+        self
+    }
+
+    /// See [RuboyConfig::access_blocking]
+    pub fn access_blocking(mut self, enabled: bool) -> Self {
+        self.config.access_blocking = enabled;
+        self
+    }
+
+    /// When the [InputHandler] is asked for new inputs, see
+    /// [Ruboy::set_input_polling]
+    pub fn input_polling(mut self, polling: InputPolling) -> Self {
+        self.config.input_polling = polling;
+        self
+    }
+
+    /// How the screen is drawn, see [Ruboy::set_render_mode]
+    pub fn render_mode(mut self, mode: RenderMode) -> Self {
+        self.config.render_mode = mode;
+        self
+    }
+
+    /// Which ROM banks are kept in memory, see [BankCaching]
+    pub fn bank_caching(mut self, caching: BankCaching) -> Self {
+        self.config.bank_caching = caching;
+        self
+    }
+
+    /// The colors frames are shown in, see [Ruboy::set_palette]
+    pub fn palette(mut self, palette: DmgPalette) -> Self {
+        self.palette = Some(palette);
+        self
+    }
+
+    /// Produces audio at the given sample rate, see [Ruboy::enable_audio]
+    pub fn audio(mut self, sample_rate: u32) -> Self {
+        self.audio_sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Starts with a previously saved cartridge clock, see [Ruboy::load_rtc]
+    pub fn rtc(mut self, rtc: Rtc) -> Self {
+        self.rtc = Some(rtc);
+        self
+    }
+
+    /// Starts with previously saved cartridge RAM, see
+    /// [Ruboy::load_cartridge_ram]. RAM of the wrong size is ignored.
+    pub fn cartridge_ram(mut self, ram: Vec<u8>) -> Self {
+        self.cartridge_ram = Some(ram);
+        self
+    }
+
+    /// Loads the data stored by [Ruboy::save_persistent] under `name`
+    pub fn load_persistent<S: StorageProvider>(
+        mut self,
+        storage: &S,
+        name: &str,
+    ) -> Result<Self, LoadPersistentErr<S::Err>> {
+        self.cartridge_ram = storage
+            .load(&sav_key(name))
+            .map_err(LoadPersistentErr::Storage)?;
+
+        let saved = storage
+            .load(&rtc_key(name))
+            .map_err(LoadPersistentErr::Storage)?;
+
+        if let Some(bytes) = saved {
+            self.rtc = Some(Rtc::from_save_bytes(&bytes)?);
+        }
+
+        Ok(self)
+    }
+
+    /// See [Ruboy::attach_debugger]
+    pub fn debugger(mut self, debugger: Debugger) -> Self {
+        self.debugger = Some(debugger);
+        self
+    }
+
+    /// Records the last `capacity` executed instructions, see
+    /// [Ruboy::enable_trace]
+    pub fn trace(mut self, capacity: usize) -> Self {
+        self.trace_capacity = Some(capacity);
+        self
+    }
+
+    /// See [Ruboy::attach_snooper]. This is also how serial output is
+    /// captured, by watching writes to the serial registers.
+    pub fn snooper(mut self, snooper: impl BusSnooper + 'static) -> Self {
+        self.snooper = Some(Box::new(snooper));
+        self
+    }
+
+    /// See [Ruboy::attach_frame_observer]
+    pub fn frame_observer(mut self, observer: impl FrameObserver + 'static) -> Self {
+        self.frame_observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Creates the emulator with the chosen options
+    pub fn build(self) -> Result<Ruboy<A, R, V, I>, RuboyStartErr<R>> {
+        let boot_rom = match self.boot_rom {
+            BootRom::BuiltIn if !cfg!(feature = "boot_img_enabled") => {
+                BootRom::Skip(self.config.model)
+            }
+            boot_rom => boot_rom,
+        };
+
+        let mut ruboy = match boot_rom {
+            BootRom::Skip(_) => Ruboy::with_config(self.rom, self.output, self.input, self.config)?,
+            boot_rom => {
+                let mut ruboy = Ruboy::with_boot_rom(self.rom, self.output, self.input, boot_rom)?;
+
+                match self.config.deterministic {
+                    Some(seed) => ruboy.mem.make_deterministic(seed, self.config.model),
+                    None => ruboy.mem.init_ram(self.config.ram_init, self.config.model),
+                }
+
+                if !self.config.access_blocking {
+                    ruboy.mem.disable_access_blocking();
+                }
+
+                ruboy.config = self.config;
+                ruboy.set_input_polling(self.config.input_polling);
+                ruboy.set_render_mode(self.config.render_mode);
+
+                ruboy
+                    .set_bank_caching(self.config.bank_caching)
+                    .map_err(|e| MemControllerInitErr::Rom(RomControllerInitErr::Read(e)))?;
+
+                ruboy
+            }
+        };
+
+        if let Some(palette) = self.palette {
+            ruboy.set_palette(palette);
+        }
+
+        if let Some(sample_rate) = self.audio_sample_rate {
+            ruboy.enable_audio(sample_rate);
+        }
+
+        if let Some(rtc) = self.rtc {
+            ruboy.load_rtc(rtc);
+        }
+
+        if let Some(ram) = self.cartridge_ram {
+            if !ruboy.load_cartridge_ram(&ram) {
+                log::warn!(
+                    "Ignoring saved cartridge RAM of {} bytes, the cartridge has {}",
+                    ram.len(),
+                    ruboy.cartridge_ram().len()
+                );
+            }
+        }
+
+        if let Some(debugger) = self.debugger {
+            ruboy.attach_debugger(debugger);
+        }
+
+        if let Some(capacity) = self.trace_capacity {
+            ruboy.enable_trace(capacity);
+        }
+
+        if let Some(snooper) = self.snooper {
+            ruboy.mem.snooper = Some(RefCell::new(snooper));
+        }
+
+        ruboy.frame_observer = self.frame_observer;
+
+        Ok(ruboy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::testutils::{blank_rom, NullInput, NullOutput, TestRuboy};
+    use crate::InlineAllocator;
+
+    #[test]
+    fn applies_options() {
+        let config = RuboyConfig {
+            model: GbModel::Mgb,
+            ram_init: RamInit::Fill(0xAA),
+            ..Default::default()
+        };
+
+        let plain: TestRuboy =
+            Ruboy::with_config(blank_rom(0x3C), NullOutput, NullInput, config).unwrap();
+
+        let built: TestRuboy = Ruboy::builder(blank_rom(0x3C), NullOutput, NullInput)
+            .model(GbModel::Mgb)
+            .ram_init(RamInit::Fill(0xAA))
+            .palette(DmgPalette::GRAYSCALE)
+            .audio(48000)
+            .trace(4)
+            .build()
+            .unwrap();
+
+        assert_eq!(plain.state_digest(), built.state_digest());
+        assert_eq!(&DmgPalette::GRAYSCALE, built.palette());
+        assert_eq!(Some(48000), built.audio_sample_rate());
+        assert!(built.trace().is_some());
+    }
+
+    #[test]
+    fn model_sets_registers_and_ram() {
+        let build = |model| -> TestRuboy {
+            Ruboy::builder(blank_rom(0x3C), NullOutput, NullInput)
+                .model(model)
+                .build()
+                .unwrap()
+        };
+
+        let expected = [
+            (GbModel::Dmg0, [0x0100, 0xFF13, 0x00C1, 0x8403]),
+            (GbModel::Dmg, [0x01B0, 0x0013, 0x00D8, 0x014D]),
+            (GbModel::Mgb, [0xFFB0, 0x0013, 0x00D8, 0x014D]),
+            (GbModel::Sgb, [0x0100, 0x0014, 0x0000, 0xC060]),
+        ];
+
+        for (model, [af, bc, de, hl]) in expected {
+            let regs = build(model).registers();
+            let pair = |hi: u8, lo: u8| u16::from_be_bytes([hi, lo]);
+
+            assert_eq!(
+                [af, bc, de, hl],
+                [
+                    pair(regs.a, regs.f),
+                    pair(regs.b, regs.c),
+                    pair(regs.d, regs.e),
+                    pair(regs.h, regs.l)
+                ],
+                "{:?}",
+                model
+            );
+        }
+
+        let dmg = build(GbModel::Dmg);
+        let sgb = build(GbModel::Sgb);
+        assert_ne!(dmg.registers(), sgb.registers());
+        assert_ne!(dmg.state_digest(), sgb.state_digest());
+
+        // Not the zeroed RAM of a plain start
+        let plain: TestRuboy = Ruboy::with_config(
+            blank_rom(0x3C),
+            NullOutput,
+            NullInput,
+            RuboyConfig {
+                model: GbModel::Dmg,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        assert!(plain.wram().iter().all(|&b| b == 0));
+        assert_ne!(plain.wram(), dmg.wram());
+        assert_ne!(plain.hram(), dmg.hram());
+    }
+
+    #[test]
+    fn ram_init_overrides_model_pattern() {
+        let built: TestRuboy = Ruboy::builder(blank_rom(0x3C), NullOutput, NullInput)
+            .ram_init(RamInit::Fill(0xAA))
+            .model(GbModel::Mgb)
+            .build()
+            .unwrap();
+
+        assert!(built.wram().iter().all(|&b| b == 0xAA));
+        assert!(built.hram().iter().all(|&b| b == 0xAA));
+    }
+
+    #[test]
+    fn loads_persistent_data() {
+        let mut rom = blank_rom(0x00).into_inner();
+        rom[0x147] = 0x0F;
+
+        let mut storage = MemoryStorage::new();
+        let builder = || {
+            RuboyBuilder::<InlineAllocator, _, _, _>::new(
+                Cursor::new(rom.clone()),
+                NullOutput,
+                NullInput,
+            )
+        };
+
+        storage.store("game.rtc", &[0x00; 3]).unwrap();
+        assert!(matches!(
+            builder().load_persistent(&storage, "game"),
+            Err(LoadPersistentErr::Rtc(_))
+        ));
+
+        storage
+            .store("game.rtc", &Rtc::default().to_save_bytes())
+            .unwrap();
+
+        let mut built = builder()
+            .load_persistent(&storage, "game")
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert!(built.save_rtc().is_some());
+    }
+}
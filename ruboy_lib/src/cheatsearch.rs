@@ -0,0 +1,165 @@
+//! Finding where a game keeps a value in work RAM.
+//!
+//! A [CheatSearch] starts with every work RAM address as a candidate, and
+//! remembers the value each one had. Every [CheatSearch::filter] drops the
+//! candidates that don't match, compared with a known value or with the value
+//! at the previous search. After a few rounds of e.g. "lost a life, so the
+//! value decreased by one", only the address of the lives counter is left.
+
+use alloc::vec::Vec;
+
+use crate::memcontroller::WORKRAM_START;
+use crate::{GBAllocator, GBGraphicsDrawer, InputHandler, RomReader, Ruboy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+}
+
+impl Compare {
+    fn matches(self, value: u8, other: u8) -> bool {
+        match self {
+            Compare::Equal => value == other,
+            Compare::NotEqual => value != other,
+            Compare::Greater => value > other,
+            Compare::Less => value < other,
+        }
+    }
+}
+
+/// Which candidates a search keeps
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filter {
+    /// The current value compared with the given one
+    Value(Compare, u8),
+
+    /// The current value compared with the one at the previous search, e.g.
+    /// `Previous(Compare::Greater)` for values that increased
+    Previous(Compare),
+
+    /// The value changed by exactly this much since the previous search
+    ChangedBy(i16),
+}
+
+impl Filter {
+    fn matches(self, value: u8, previous: u8) -> bool {
+        match self {
+            Filter::Value(cmp, other) => cmp.matches(value, other),
+            Filter::Previous(cmp) => cmp.matches(value, previous),
+            Filter::ChangedBy(diff) => value as i16 - previous as i16 == diff,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CheatSearch {
+    /// Work RAM at the previous search
+    snapshot: Vec<u8>,
+
+    /// Offsets into work RAM that matched every search so far, in order
+    candidates: Vec<u16>,
+}
+
+impl CheatSearch {
+    /// Starts a search with every work RAM address as a candidate
+    pub fn new<A, R, V, I>(ruboy: &Ruboy<A, R, V, I>) -> Self
+    where
+        A: GBAllocator,
+        R: RomReader,
+        V: GBGraphicsDrawer,
+        I: InputHandler,
+    {
+        let snapshot = ruboy.wram().to_vec();
+        let candidates = (0..snapshot.len() as u16).collect();
+
+        Self {
+            snapshot,
+            candidates,
+        }
+    }
+
+    /// Keeps only the candidates matching `filter`, and remembers the
+    /// current values for the next search
+    pub fn filter<A, R, V, I>(&mut self, ruboy: &Ruboy<A, R, V, I>, filter: Filter)
+    where
+        A: GBAllocator,
+        R: RomReader,
+        V: GBGraphicsDrawer,
+        I: InputHandler,
+    {
+        let wram = ruboy.wram();
+
+        self.candidates.retain(|offset| {
+            let offset = *offset as usize;
+            filter.matches(wram[offset], self.snapshot[offset])
+        });
+
+        self.snapshot.copy_from_slice(wram);
+    }
+
+    /// The amount of addresses left
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// The addresses left, with their values at the previous search
+    pub fn candidates(&self) -> impl Iterator<Item = (u16, u8)> + '_ {
+        self.candidates
+            .iter()
+            .map(|offset| (WORKRAM_START + offset, self.snapshot[*offset as usize]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::GbModel;
+    use crate::testutils::{blank_rom, NullInput, NullOutput, TestRuboy};
+
+    fn new_ruboy() -> TestRuboy {
+        Ruboy::with_model(blank_rom(0x3C), NullOutput, NullInput, GbModel::Dmg).unwrap()
+    }
+
+    #[test]
+    fn finds_changing_value() {
+        let mut ruboy = new_ruboy();
+        ruboy.write_memory(0xC123, &[3]).unwrap();
+
+        let mut search = CheatSearch::new(&ruboy);
+        search.filter(&ruboy, Filter::Value(Compare::Equal, 3));
+        assert!(search.candidates().any(|(addr, _)| addr == 0xC123));
+
+        ruboy.write_memory(0xC123, &[2]).unwrap();
+        search.filter(&ruboy, Filter::ChangedBy(-1));
+
+        assert_eq!(vec![(0xC123, 2)], search.candidates().collect::<Vec<_>>());
+
+        ruboy.write_memory(0xC123, &[5]).unwrap();
+        search.filter(&ruboy, Filter::Previous(Compare::Less));
+
+        assert!(search.is_empty());
+    }
+
+    #[test]
+    fn starts_with_all_of_work_ram() {
+        let ruboy = new_ruboy();
+        let mut search = CheatSearch::new(&ruboy);
+
+        assert_eq!(0x2000, search.len());
+
+        search.filter(&ruboy, Filter::Previous(Compare::Equal));
+
+        assert_eq!(0x2000, search.len());
+        assert_eq!(
+            Some(0xDFFF),
+            search.candidates().last().map(|(addr, _)| addr)
+        );
+    }
+}
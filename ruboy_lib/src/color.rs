@@ -0,0 +1,109 @@
+//! Mapping the four shades of the DMG display to RGB colors.
+//!
+//! The emulator itself only knows shades, see [GbMonoColor]. The palette is
+//! only applied when a frame is shown, so changing it never affects
+//! emulation.
+
+#[cfg(not(feature = "std"))]
+use num::traits::Float;
+
+use crate::GbMonoColor;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmgPalette {
+    /// The color of every shade, from white to black. Indexed by
+    /// [GbMonoColor].
+    pub shades: [Rgb; 4],
+}
+
+impl DmgPalette {
+    /// The yellow-green of the original DMG screen
+    pub const CLASSIC: Self = Self::new([
+        Rgb::new(123, 130, 15),
+        Rgb::new(90, 121, 66),
+        Rgb::new(57, 89, 74),
+        Rgb::new(41, 65, 57),
+    ]);
+
+    /// The gray-green of the Game Boy Pocket screen
+    pub const POCKET: Self = Self::new([
+        Rgb::new(196, 207, 161),
+        Rgb::new(139, 149, 109),
+        Rgb::new(77, 83, 60),
+        Rgb::new(31, 31, 31),
+    ]);
+
+    pub const GRAYSCALE: Self = Self::new([
+        Rgb::new(255, 255, 255),
+        Rgb::new(170, 170, 170),
+        Rgb::new(85, 85, 85),
+        Rgb::new(0, 0, 0),
+    ]);
+
+    /// The built-in palettes and their names
+    pub const PRESETS: [(&'static str, DmgPalette); 3] = [
+        ("Classic", Self::CLASSIC),
+        ("Pocket", Self::POCKET),
+        ("Grayscale", Self::GRAYSCALE),
+    ];
+
+    pub const fn new(shades: [Rgb; 4]) -> Self {
+        Self { shades }
+    }
+
+    pub const fn color(&self, shade: GbMonoColor) -> Rgb {
+        self.shades[shade as usize]
+    }
+
+    /// Applies gamma correction to every shade. A gamma above 1 darkens the
+    /// midtones, below 1 brightens them.
+    pub fn gamma_corrected(&self, gamma: f32) -> Self {
+        let correct = |channel: u8| {
+            let linear = channel as f32 / 255.0;
+            (linear.powf(gamma) * 255.0).round() as u8
+        };
+
+        Self::new(
+            self.shades
+                .map(|rgb| Rgb::new(correct(rgb.r), correct(rgb.g), correct(rgb.b))),
+        )
+    }
+}
+
+impl Default for DmgPalette {
+    fn default() -> Self {
+        Self::CLASSIC
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_keeps_extremes() {
+        let corrected = DmgPalette::GRAYSCALE.gamma_corrected(2.2);
+
+        assert_eq!(Rgb::new(255, 255, 255), corrected.color(GbMonoColor::White));
+        assert_eq!(Rgb::new(0, 0, 0), corrected.color(GbMonoColor::Black));
+        assert!(corrected.color(GbMonoColor::LightGray).r < 170);
+
+        assert_eq!(
+            DmgPalette::CLASSIC,
+            DmgPalette::CLASSIC.gamma_corrected(1.0)
+        );
+    }
+}
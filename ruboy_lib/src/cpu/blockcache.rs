@@ -0,0 +1,319 @@
+//! Cache of decoded basic blocks.
+//!
+//! Decoding is the most expensive part of running an instruction, so instead
+//! of decoding at every fetch, a whole basic block (up to and including the
+//! next control flow instruction) is decoded at once on a miss, and reused
+//! afterwards. Instructions are still executed one by one, so the CPU stays
+//! cycle-interleaved with the PPU and timers.
+//!
+//! Blocks are only built in ROM, work RAM and high RAM. Decoded ROM code is
+//! kept per bank, in a table indexed by the offset in the bank, so switching
+//! banks only changes which table is looked in. The contents of a bank never
+//! change, so those tables stay valid until the cartridge is swapped. Code in
+//! RAM is tracked per block, and writes into a cached block (self-modifying
+//! code) drop that block.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use crate::isa::Instruction;
+
+/// Longest block that is decoded at once
+pub const MAX_BLOCK_LEN: usize = 64;
+
+const BANK_SIZE: usize = 0x4000;
+const BOOT_ROM_END: u32 = 0x100;
+
+/// Work RAM, followed by high RAM
+const RAM_TABLE_LEN: usize = 0x2000 + 0x7F;
+
+/// Granularity at which RAM is tracked for holding cached code, so most
+/// writes can skip looking through the blocks
+const PAGE_SIZE: usize = 64;
+const PAGES: usize = RAM_TABLE_LEN.div_ceil(PAGE_SIZE);
+
+/// The memory code is decoded from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodeSource {
+    BootRom,
+    RomBank(usize),
+    Ram,
+}
+
+impl CodeSource {
+    /// The end of the memory containing `addr` mapped as this source, past
+    /// which a block cannot continue
+    pub fn end(self, addr: u16) -> u32 {
+        match self {
+            CodeSource::BootRom => BOOT_ROM_END,
+            CodeSource::RomBank(_) => (addr as u32 & 0xC000) + BANK_SIZE as u32,
+            CodeSource::Ram if addr < 0xE000 => 0xE000,
+            CodeSource::Ram => 0xFFFF,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Block {
+    /// In [ram_index] space
+    range: Range<usize>,
+    pcs: Vec<u16>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BlockCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+}
+
+type Table = Box<[Option<Instruction>]>;
+
+fn table(len: usize) -> Table {
+    vec![None; len].into_boxed_slice()
+}
+
+/// Where work RAM (including its echo) or high RAM address `addr` is in the
+/// RAM table
+fn ram_index(addr: u16) -> Option<usize> {
+    match addr {
+        0xC000..=0xDFFF => Some((addr - 0xC000) as usize),
+        0xE000..=0xFDFF => Some((addr - 0xE000) as usize),
+        0xFF80..=0xFFFE => Some(0x2000 + (addr - 0xFF80) as usize),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockCache {
+    boot: Table,
+
+    /// Indexed by bank number, allocated once code in that bank runs
+    banks: Vec<Option<Table>>,
+
+    ram: Table,
+    ram_blocks: Vec<Block>,
+
+    /// The amount of blocks in each RAM page
+    ram_pages: [u16; PAGES],
+
+    stats: BlockCacheStats,
+}
+
+impl Default for BlockCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BlockCache {
+    pub fn new() -> Self {
+        Self {
+            boot: table(BOOT_ROM_END as usize),
+            banks: Vec::new(),
+            ram: table(RAM_TABLE_LEN),
+            ram_blocks: Vec::new(),
+            ram_pages: [0; PAGES],
+            stats: BlockCacheStats::default(),
+        }
+    }
+
+    pub const fn stats(&self) -> BlockCacheStats {
+        self.stats
+    }
+
+    #[inline]
+    pub fn get(&mut self, source: CodeSource, pc: u16) -> Option<Instruction> {
+        let found = match source {
+            CodeSource::BootRom => self.boot.get(pc as usize).copied().flatten(),
+            CodeSource::RomBank(bank) => match self.banks.get(bank) {
+                Some(Some(table)) => table[pc as usize % BANK_SIZE],
+                _ => None,
+            },
+            CodeSource::Ram => ram_index(pc).and_then(|i| self.ram[i]),
+        };
+
+        match found {
+            Some(_) => self.stats.hits += 1,
+            None => self.stats.misses += 1,
+        }
+
+        found
+    }
+
+    /// Adds a decoded block. The instructions must be contiguous, starting at
+    /// the start of the block, and all come from `source`
+    pub fn insert(&mut self, source: CodeSource, instrs: &[(u16, Instruction)]) {
+        match source {
+            CodeSource::BootRom => {
+                for &(pc, instr) in instrs {
+                    self.boot[pc as usize] = Some(instr);
+                }
+            }
+            CodeSource::RomBank(bank) => {
+                if self.banks.len() <= bank {
+                    self.banks.resize(bank + 1, None);
+                }
+
+                let table = self.banks[bank].get_or_insert_with(|| table(BANK_SIZE));
+
+                for &(pc, instr) in instrs {
+                    table[pc as usize % BANK_SIZE] = Some(instr);
+                }
+            }
+            CodeSource::Ram => self.insert_ram(instrs),
+        }
+    }
+
+    fn insert_ram(&mut self, instrs: &[(u16, Instruction)]) {
+        let Some((&(start, _), &(last, last_instr))) = instrs.first().zip(instrs.last()) else {
+            return;
+        };
+
+        let (Some(start), Some(last)) = (ram_index(start), ram_index(last)) else {
+            return;
+        };
+
+        for &(pc, instr) in instrs {
+            if let Some(i) = ram_index(pc) {
+                self.ram[i] = Some(instr);
+            }
+        }
+
+        let range = start..(last + last_instr.len() as usize);
+
+        for page in &mut self.ram_pages[start / PAGE_SIZE..=(range.end - 1) / PAGE_SIZE] {
+            *page += 1;
+        }
+
+        self.ram_blocks.push(Block {
+            range,
+            pcs: instrs.iter().map(|(pc, _)| *pc).collect(),
+        });
+    }
+
+    fn drop_ram_blocks(&mut self, mut overlaps: impl FnMut(&Range<usize>) -> bool) {
+        let ram = &mut self.ram;
+        let pages = &mut self.ram_pages;
+        let stats = &mut self.stats;
+
+        self.ram_blocks.retain(|block| {
+            if !overlaps(&block.range) {
+                return true;
+            }
+
+            stats.invalidations += 1;
+
+            for i in block.pcs.iter().filter_map(|&pc| ram_index(pc)) {
+                ram[i] = None;
+            }
+
+            for page in
+                &mut pages[block.range.start / PAGE_SIZE..=(block.range.end - 1) / PAGE_SIZE]
+            {
+                *page -= 1;
+            }
+
+            false
+        });
+    }
+
+    /// Drops every block in RAM, for when all of RAM may have changed at once
+    pub fn clear_ram(&mut self) {
+        self.drop_ram_blocks(|_| true);
+    }
+
+    /// Drops every block, for when the cartridge or boot ROM is swapped
+    pub fn clear(&mut self) {
+        self.clear_ram();
+
+        self.stats.invalidations += self.banks.iter().flatten().count() as u64;
+
+        self.banks.clear();
+        self.boot.fill(None);
+    }
+
+    /// Must be called for every write to memory
+    #[inline]
+    pub fn on_write(&mut self, addr: u16) {
+        let Some(index) = ram_index(addr) else {
+            return;
+        };
+
+        if self.ram_pages[index / PAGE_SIZE] == 0 {
+            return;
+        }
+
+        self.drop_ram_blocks(|range| range.contains(&index));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block_at(start: u16) -> Vec<(u16, Instruction)> {
+        vec![
+            (start, Instruction::Nop),
+            (start + 1, Instruction::Nop),
+            (start + 2, Instruction::Jump(start)),
+        ]
+    }
+
+    #[test]
+    fn write_into_block_drops_it() {
+        let mut cache = BlockCache::new();
+        cache.insert(CodeSource::Ram, &block_at(0xC000));
+        cache.insert(CodeSource::Ram, &block_at(0xC100));
+
+        assert!(cache.get(CodeSource::Ram, 0xC001).is_some());
+
+        // Right after the block
+        cache.on_write(0xC005);
+        assert!(cache.get(CodeSource::Ram, 0xC001).is_some());
+
+        // Last byte of the jump
+        cache.on_write(0xC004);
+        assert!(cache.get(CodeSource::Ram, 0xC001).is_none());
+        assert!(cache.get(CodeSource::Ram, 0xC101).is_some());
+    }
+
+    #[test]
+    fn echo_ram_write_drops_block() {
+        let mut cache = BlockCache::new();
+        cache.insert(CodeSource::Ram, &block_at(0xC000));
+
+        cache.on_write(0xE002);
+
+        assert!(cache.get(CodeSource::Ram, 0xC000).is_none());
+        assert_eq!(1, cache.stats().invalidations);
+    }
+
+    #[test]
+    fn banks_are_cached_separately() {
+        let mut cache = BlockCache::new();
+        cache.insert(CodeSource::RomBank(1), &block_at(0x4000));
+
+        // A bank switch is just a write to ROM, and leaves the bank alone
+        cache.on_write(0x2000);
+
+        assert!(cache.get(CodeSource::RomBank(1), 0x4000).is_some());
+        assert!(cache.get(CodeSource::RomBank(2), 0x4000).is_none());
+        assert!(cache.get(CodeSource::BootRom, 0x0000).is_none());
+        assert_eq!(0, cache.stats().invalidations);
+
+        // The same bank mapped in the low slot, as MBC1 can
+        assert!(cache.get(CodeSource::RomBank(1), 0x0000).is_some());
+    }
+
+    #[test]
+    fn block_ends() {
+        assert_eq!(0x4000, CodeSource::RomBank(0).end(0x0150));
+        assert_eq!(0x8000, CodeSource::RomBank(5).end(0x4000));
+        assert_eq!(0x100, CodeSource::BootRom.end(0x0000));
+        assert_eq!(0xE000, CodeSource::Ram.end(0xD000));
+        assert_eq!(0xFFFF, CodeSource::Ram.end(0xFF80));
+    }
+}
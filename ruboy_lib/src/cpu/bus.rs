@@ -0,0 +1,108 @@
+use crate::isa::decoder::{self, DecoderReadable};
+use crate::isa::Instruction;
+use crate::memcontroller::{MemController, MemControllerDecoderErr, ReadError, WriteError};
+use crate::{GBAllocator, RomReader};
+
+/// The memory the CPU runs against. This is the [MemController] when
+/// emulating, the CPU tests use plain RAM instead.
+pub(crate) trait CpuBus: DecoderReadable<Err = MemControllerDecoderErr> + Sized {
+    fn read8(&self, addr: u16) -> Result<u8, ReadError>;
+    fn write8(&mut self, addr: u16, value: u8) -> Result<(), WriteError>;
+
+    fn read16(&self, addr: u16) -> Result<u16, ReadError> {
+        Ok(u16::from_le_bytes([
+            self.read8(addr)?,
+            self.read8(addr.wrapping_add(1))?,
+        ]))
+    }
+
+    fn write16(&mut self, addr: u16, value: u16) -> Result<(), WriteError> {
+        let bytes = value.to_le_bytes();
+
+        self.write8(addr, bytes[0])?;
+        self.write8(addr.wrapping_add(1), bytes[1])
+    }
+
+    /// Reads without anything attached to the bus noticing, like the
+    /// debugger's watchpoints
+    fn read8_direct(&self, addr: u16) -> Result<u8, ReadError> {
+        self.read8(addr)
+    }
+
+    /// The instruction at `pc`
+    fn decode(&mut self, pc: u16) -> Result<Instruction, MemControllerDecoderErr> {
+        decoder::decode(self, pc)
+    }
+
+    /// The ROM bank mapped at `addr`, if it is in the cartridge
+    fn rom_bank(&self, _addr: u16) -> Option<usize> {
+        None
+    }
+
+    /// The lower nibble of P1: the joypad lines, low for pressed buttons
+    /// of the selected groups
+    fn joypad_lines(&self) -> u8 {
+        0x0F
+    }
+
+    /// Called when STOP runs, which resets DIV
+    fn stop(&mut self) {}
+
+    /// The IE register
+    fn interrupts_enabled(&self) -> u8;
+
+    /// The IF register
+    fn interrupts_requested(&self) -> u8;
+    fn set_interrupts_requested(&mut self, requested: u8);
+}
+
+impl<A: GBAllocator, R: RomReader> CpuBus for MemController<A, R> {
+    fn read8(&self, addr: u16) -> Result<u8, ReadError> {
+        MemController::read8(self, addr)
+    }
+
+    fn write8(&mut self, addr: u16, value: u8) -> Result<(), WriteError> {
+        MemController::write8(self, addr, value)
+    }
+
+    fn read16(&self, addr: u16) -> Result<u16, ReadError> {
+        MemController::read16(self, addr)
+    }
+
+    fn write16(&mut self, addr: u16, value: u16) -> Result<(), WriteError> {
+        MemController::write16(self, addr, value)
+    }
+
+    fn read8_direct(&self, addr: u16) -> Result<u8, ReadError> {
+        MemController::read8_direct(self, addr)
+    }
+
+    #[cfg(feature = "cached_interpreter")]
+    fn decode(&mut self, pc: u16) -> Result<Instruction, MemControllerDecoderErr> {
+        self.decode_cached(pc)
+    }
+
+    fn rom_bank(&self, addr: u16) -> Option<usize> {
+        MemController::rom_bank(self, addr)
+    }
+
+    fn joypad_lines(&self) -> u8 {
+        self.io_registers.joypad.read() & 0x0F
+    }
+
+    fn stop(&mut self) {
+        self.io_registers.timer.write_div();
+    }
+
+    fn interrupts_enabled(&self) -> u8 {
+        self.interrupts_enabled.into()
+    }
+
+    fn interrupts_requested(&self) -> u8 {
+        self.io_registers.interrupts_requested.into()
+    }
+
+    fn set_interrupts_requested(&mut self, requested: u8) {
+        self.io_registers.interrupts_requested = requested.into();
+    }
+}
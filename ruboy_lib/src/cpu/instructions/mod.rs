@@ -1,32 +1,40 @@
-use crate::memcontroller::MemController;
-use crate::{GBAllocator, RomReader};
-
 use super::nums::{GbBits, GbHalfCarry};
-use super::{Cpu, CpuErr, IncDecTarget, Instruction, Ld16Dst, Ld16Src, Ld8Dst, Ld8Src};
-
-macro_rules! instr_todo {
-    ($instr:expr) => {
-        todo!("{}", $instr)
-    };
-}
+use super::{Cpu, CpuBus, CpuErr, IncDecTarget, Instruction, Ld16Dst, Ld16Src, Ld8Dst, Ld8Src};
 
 impl Cpu {
     /// Runs the given CPU instruction
     pub fn execute_instruction(
         &mut self,
-        mem: &mut MemController<impl GBAllocator, impl RomReader>,
+        mem: &mut impl CpuBus,
         instr: Instruction,
     ) -> Result<bool, CpuErr> {
         let jumped = match instr {
             Instruction::Nop => false,
-            Instruction::Stop(_) => instr_todo!(instr),
-            Instruction::Halt => instr_todo!(instr),
+            Instruction::Stop(_) => {
+                // With a button already held, the CPU has nothing to wait for
+                self.stopped = mem.joypad_lines() == 0x0F;
+                mem.stop();
+
+                false
+            }
+            Instruction::Halt => {
+                let pending = self.pending_interrupts(mem) != 0;
+
+                if !self.interrupts_master && pending {
+                    self.halt_bug = true;
+                } else {
+                    self.halted = !pending;
+                }
+
+                false
+            }
             Instruction::EI => {
                 self.ei_queued = true;
                 false
             }
             Instruction::DI => {
                 self.interrupts_master = false;
+                self.ei_queued = false;
                 false
             }
             Instruction::Add(src) => {
@@ -73,7 +81,12 @@ impl Cpu {
 
                 false
             }
-            Instruction::AddSP(_) => instr_todo!(instr),
+            Instruction::AddSP(offset) => {
+                let res = self.sp_plus_offset(offset);
+                self.registers.set_sp(res);
+
+                false
+            }
             Instruction::Sub(src) => {
                 let base = self.registers.a();
                 let val = self.get_arith_src(mem, src)?;
@@ -87,7 +100,24 @@ impl Cpu {
 
                 false
             }
-            Instruction::SubCarry(_) => instr_todo!(instr),
+            Instruction::SubCarry(src) => {
+                let base = self.registers.a();
+                let val = self.get_arith_src(mem, src)?;
+                let cur_carry = if self.registers.carry_flag() { 1 } else { 0 };
+
+                let res = base.wrapping_sub(val).wrapping_sub(cur_carry);
+
+                self.registers.set_flags(
+                    res == 0,
+                    true,
+                    (base & 0xF) < (val & 0xF) + cur_carry,
+                    (base as u16) < (val as u16) + (cur_carry as u16),
+                );
+
+                self.registers.set_a(res);
+
+                false
+            }
             Instruction::And(src) => {
                 let val = self.get_arith_src(mem, src)?;
 
@@ -194,7 +224,17 @@ impl Cpu {
                 };
                 false
             }
-            Instruction::RotLeftCircular(_) => instr_todo!(instr),
+            Instruction::RotLeftCircular(tgt) => {
+                let pre = self.get_prefarith_tgt(mem, tgt)?;
+                let result = pre.rotate_left(1);
+
+                self.registers
+                    .set_flags(result == 0, false, false, pre.msb_set());
+
+                self.set_prefarith_tgt(mem, tgt, result)?;
+
+                false
+            }
             Instruction::RotRightCircular(tgt) => {
                 let pre = self.get_prefarith_tgt(mem, tgt)?;
 
@@ -239,7 +279,19 @@ impl Cpu {
 
                 false
             }
-            Instruction::ShiftRightArith(_) => instr_todo!(instr),
+            Instruction::ShiftRightArith(tgt) => {
+                let init_val = self.get_prefarith_tgt(mem, tgt)?;
+
+                // Bit 7 stays, keeping the sign
+                let shifted = init_val.wrapping_shr(1).set_msb(init_val.msb_set());
+
+                self.registers
+                    .set_flags(shifted == 0, false, false, init_val.lsb_set());
+
+                self.set_prefarith_tgt(mem, tgt, shifted)?;
+
+                false
+            }
             Instruction::Swap(tgt) => {
                 let val = self.get_prefarith_tgt(mem, tgt)?;
                 let val_lower = val & 0xF;
@@ -360,7 +412,12 @@ impl Cpu {
 
                 false
             }
-            Instruction::LoadSPi8toHL(_) => instr_todo!(instr),
+            Instruction::LoadSPi8toHL(offset) => {
+                let res = self.sp_plus_offset(offset);
+                self.registers.set_hl(res);
+
+                false
+            }
             Instruction::Jump(addr) => {
                 self.registers.set_pc(addr);
                 true
@@ -416,11 +473,10 @@ impl Cpu {
                 true
             }
             Instruction::Reti => {
-                // TODO: Not entirely sure if the order and timings
-                // of enabling/disabling interrupts is correct.
+                // Unlike EI, RETI enables interrupts without delay
                 let ret_addr = self.do_pop16(mem)?;
                 self.registers.set_pc(ret_addr);
-                self.interrupts_master = true; // This is definitely not correct
+                self.interrupts_master = true;
 
                 true
             }
@@ -482,8 +538,18 @@ impl Cpu {
                 self.registers.set_half_carry_flag(true);
                 false
             }
-            Instruction::SetCarryFlag => instr_todo!(instr),
-            Instruction::ComplementCarry => instr_todo!(instr),
+            Instruction::SetCarryFlag => {
+                self.registers.set_subtract_flag(false);
+                self.registers.set_half_carry_flag(false);
+                self.registers.set_carry_flag(true);
+                false
+            }
+            Instruction::ComplementCarry => {
+                self.registers.set_subtract_flag(false);
+                self.registers.set_half_carry_flag(false);
+                self.registers.set_carry_flag(!self.registers.carry_flag());
+                false
+            }
             Instruction::Rst(rsvec) => {
                 let curr_addr = self.registers.pc();
                 let return_addr = curr_addr + (instr.len() as u16);
@@ -522,7 +588,18 @@ impl Cpu {
 
                 false
             }
-            Instruction::RotRightA => instr_todo!(instr),
+            Instruction::RotRightA => {
+                let cur_val = self.registers.a();
+                let shifted = cur_val.wrapping_shr(1);
+                let result = shifted.set_msb(self.registers.carry_flag());
+
+                self.registers
+                    .set_flags(false, false, false, cur_val.lsb_set());
+
+                self.registers.set_a(result);
+
+                false
+            }
             Instruction::IllegalInstruction(illegal) => {
                 return Err(CpuErr::Illegal(illegal));
             }
@@ -530,4 +607,21 @@ impl Cpu {
 
         Ok(jumped)
     }
+
+    /// SP plus a signed offset, for ADD SP,e and LD HL,SP+e. The flags are
+    /// those of adding the offset to the low byte of SP as unsigned bytes.
+    fn sp_plus_offset(&mut self, offset: i8) -> u16 {
+        let sp = self.registers.sp();
+        let low = sp as u8;
+        let val = offset as u8;
+
+        self.registers.set_flags(
+            false,
+            false,
+            low.halfcarry_add(val),
+            low.overflowing_add(val).1,
+        );
+
+        sp.wrapping_add_signed(offset.into())
+    }
 }
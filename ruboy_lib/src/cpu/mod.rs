@@ -1,32 +1,80 @@
+#[cfg(feature = "cached_interpreter")]
+pub mod blockcache;
+mod bus;
 mod instructions;
 mod nums;
 mod registers;
-mod timer;
+#[cfg(test)]
+mod singlestep;
+pub mod trace;
 
-use core::num::Wrapping;
+use alloc::boxed::Box;
+#[cfg(feature = "std")]
+use std::io::Write;
 
 use thiserror::Error;
 
+pub(crate) use bus::CpuBus;
 use registers::Registers;
+use trace::{TraceEntry, Tracer};
 
 use crate::{
-    extern_traits::{GBAllocator, RomReader},
-    isa::*,
-    memcontroller::{
-        interrupts::Interrupts, MemController, MemControllerDecoderErr, ReadError, WriteError,
+    debug::{
+        CallFrame, CallKind, CallStack, CodeLocation, CpuRegisters, Opcode, OpcodeStats, Profiler,
     },
+    isa::{decoder::DecoderReadable, *},
+    logging::{debug, trace, Subsystem},
+    memcontroller::{MemControllerDecoderErr, ReadError, WriteError},
+    model::{BootRegisters, StateDigest},
+    savestate::state_data,
 };
 
 pub struct Cpu {
-    timer_cycles: Wrapping<usize>,
     cycles_remaining: u8,
     interrupts_master: bool,
     /// Whether the interrupts master flag should be re-enabled after the next instruction
     ei_queued: bool,
 
+    /// Set by HALT, until an interrupt is pending
+    halted: bool,
+
+    /// Set by STOP, until a button is pressed
+    stopped: bool,
+
+    /// Set when HALT runs with interrupts disabled while one is already
+    /// pending. The CPU then does not halt, but fails to increment PC after
+    /// the next opcode fetch, so the byte after HALT is read twice.
+    halt_bug: bool,
+
     registers: Registers,
+
+    /// Records executed instructions, if enabled
+    pub tracer: Option<Tracer>,
+
+    /// Receives a gameboy-doctor log line for every executed instruction, if enabled
+    #[cfg(feature = "std")]
+    pub doctor_log: Option<Box<dyn Write + Send>>,
+
+    /// Counts executed opcodes, if enabled
+    pub opcode_stats: Option<Box<OpcodeStats>>,
+
+    /// Follows calls and returns, if enabled
+    pub call_stack: Option<Box<CallStack>>,
+
+    /// Samples where cycles are spent, if enabled
+    pub profiler: Option<Box<Profiler>>,
 }
 
+state_data!(Cpu {
+    cycles_remaining,
+    interrupts_master,
+    ei_queued,
+    halted,
+    stopped,
+    halt_bug,
+    registers,
+});
+
 #[derive(Debug, Error)]
 pub enum CpuErr {
     #[error("Error during instruction decoding")]
@@ -40,16 +88,121 @@ pub enum CpuErr {
 
     #[error("Could not read from memory")]
     MemReadError(#[from] ReadError),
+
+    #[cfg(feature = "std")]
+    #[error("Could not write to the gameboy-doctor log")]
+    DoctorLog(#[source] std::io::Error),
 }
 
 impl Cpu {
     pub fn new() -> Self {
         Cpu {
-            timer_cycles: Wrapping(0),
             cycles_remaining: 0,
             interrupts_master: false,
             ei_queued: false,
+            halted: false,
+            stopped: false,
+            halt_bug: false,
             registers: Registers::new(),
+            tracer: None,
+            #[cfg(feature = "std")]
+            doctor_log: None,
+            opcode_stats: None,
+            call_stack: None,
+            profiler: None,
+        }
+    }
+
+    /// Puts the CPU back in its power on state. Enabled debug tools like
+    /// the tracer are kept.
+    pub fn reset(&mut self) {
+        self.cycles_remaining = 0;
+        self.interrupts_master = false;
+        self.ei_queued = false;
+        self.halted = false;
+        self.stopped = false;
+        self.halt_bug = false;
+        self.registers = Registers::new();
+    }
+
+    /// Overwrites the register file with the given preset, as if the boot
+    /// ROM had just finished
+    pub fn apply_boot_registers(&mut self, regs: &BootRegisters) {
+        self.registers.set_a(regs.a);
+        self.registers.set_f(regs.f);
+        self.registers.set_b(regs.b);
+        self.registers.set_c(regs.c);
+        self.registers.set_d(regs.d);
+        self.registers.set_e(regs.e);
+        self.registers.set_h(regs.h);
+        self.registers.set_l(regs.l);
+        self.registers.set_sp(regs.sp);
+        self.registers.set_pc(regs.pc);
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_u16(self.registers.af());
+        digest.write_u16(self.registers.bc());
+        digest.write_u16(self.registers.de());
+        digest.write_u16(self.registers.hl());
+        digest.write_u16(self.registers.sp());
+        digest.write_u16(self.registers.pc());
+        digest.write_bool(self.interrupts_master);
+        digest.write_bool(self.ei_queued);
+        digest.write_bool(self.halted);
+        digest.write_bool(self.stopped);
+        digest.write_bool(self.halt_bug);
+        digest.write_u8(self.cycles_remaining);
+    }
+
+    /// True while STOP waits for a button press
+    pub const fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// True if the next cycle starts a new instruction
+    pub const fn at_instruction_boundary(&self) -> bool {
+        self.cycles_remaining == 0
+    }
+
+    pub const fn pc(&self) -> u16 {
+        self.registers.pc()
+    }
+
+    pub const fn registers_snapshot(&self) -> CpuRegisters {
+        CpuRegisters {
+            a: self.registers.a(),
+            f: self.registers.f(),
+            b: self.registers.b(),
+            c: self.registers.c(),
+            d: self.registers.d(),
+            e: self.registers.e(),
+            h: self.registers.h(),
+            l: self.registers.l(),
+            sp: self.registers.sp(),
+            pc: self.registers.pc(),
+            ime: self.interrupts_master,
+        }
+    }
+
+    fn trace_entry(&self, mem: &impl CpuBus, instr: Instruction) -> TraceEntry {
+        let pc = self.registers.pc();
+        let pcmem =
+            core::array::from_fn(|i| mem.read8_direct(pc.wrapping_add(i as u16)).unwrap_or(0xFF));
+
+        TraceEntry {
+            a: self.registers.a(),
+            f: self.registers.f(),
+            b: self.registers.b(),
+            c: self.registers.c(),
+            d: self.registers.d(),
+            e: self.registers.e(),
+            h: self.registers.h(),
+            l: self.registers.l(),
+            sp: self.registers.sp(),
+            pc,
+            pcmem,
+            instruction: instr,
         }
     }
 
@@ -126,51 +279,36 @@ impl Cpu {
         self.registers.set_pc(jump_addr);
     }
 
-    #[expect(unused, reason = "Matching other imlpementations")]
-    fn do_push8(
-        &mut self,
-        mem: &mut MemController<impl GBAllocator, impl RomReader>,
-        val: u8,
-    ) -> Result<(), WriteError> {
-        self.registers.set_sp(self.registers.sp() - 1);
+    fn do_push8(&mut self, mem: &mut impl CpuBus, val: u8) -> Result<(), WriteError> {
+        self.registers.set_sp(self.registers.sp().wrapping_sub(1));
         mem.write8(self.registers.sp(), val)
     }
 
     #[expect(unused, reason = "Matching other imlpementations")]
-    fn do_pop8(
-        &mut self,
-        mem: &mut MemController<impl GBAllocator, impl RomReader>,
-    ) -> Result<u8, ReadError> {
+    fn do_pop8(&mut self, mem: &mut impl CpuBus) -> Result<u8, ReadError> {
         let val = mem.read8(self.registers.sp());
 
-        self.registers.set_sp(self.registers.sp() + 1);
+        self.registers.set_sp(self.registers.sp().wrapping_add(1));
 
         val
     }
 
-    fn do_push16(
-        &mut self,
-        mem: &mut MemController<impl GBAllocator, impl RomReader>,
-        val: u16,
-    ) -> Result<(), WriteError> {
-        self.registers.set_sp(self.registers.sp() - 2);
+    fn do_push16(&mut self, mem: &mut impl CpuBus, val: u16) -> Result<(), WriteError> {
+        self.registers.set_sp(self.registers.sp().wrapping_sub(2));
         mem.write16(self.registers.sp(), val)
     }
 
-    fn do_pop16(
-        &mut self,
-        mem: &mut MemController<impl GBAllocator, impl RomReader>,
-    ) -> Result<u16, ReadError> {
+    fn do_pop16(&mut self, mem: &mut impl CpuBus) -> Result<u16, ReadError> {
         let val = mem.read16(self.registers.sp());
 
-        self.registers.set_sp(self.registers.sp() + 2);
+        self.registers.set_sp(self.registers.sp().wrapping_add(2));
 
         val
     }
 
     fn do_call(
         &mut self,
-        mem: &mut MemController<impl GBAllocator, impl RomReader>,
+        mem: &mut impl CpuBus,
         return_addr: u16,
         call_addr: u16,
     ) -> Result<(), WriteError> {
@@ -179,11 +317,7 @@ impl Cpu {
         Ok(())
     }
 
-    fn get_arith_src(
-        &self,
-        mem: &MemController<impl GBAllocator, impl RomReader>,
-        src: ArithSrc,
-    ) -> Result<u8, ReadError> {
+    fn get_arith_src(&self, mem: &impl CpuBus, src: ArithSrc) -> Result<u8, ReadError> {
         match src {
             ArithSrc::Reg(reg) => Ok(self.get_reg8_value(reg)),
             ArithSrc::Imm(imm) => Ok(imm),
@@ -191,11 +325,7 @@ impl Cpu {
         }
     }
 
-    fn get_prefarith_tgt(
-        &self,
-        mem: &MemController<impl GBAllocator, impl RomReader>,
-        tgt: PrefArithTarget,
-    ) -> Result<u8, ReadError> {
+    fn get_prefarith_tgt(&self, mem: &impl CpuBus, tgt: PrefArithTarget) -> Result<u8, ReadError> {
         match tgt {
             PrefArithTarget::Reg(reg) => Ok(self.get_reg8_value(reg)),
             PrefArithTarget::MemHL => mem.read8(self.registers.hl()),
@@ -204,7 +334,7 @@ impl Cpu {
 
     fn set_prefarith_tgt(
         &mut self,
-        mem: &mut MemController<impl GBAllocator, impl RomReader>,
+        mem: &mut impl CpuBus,
         tgt: PrefArithTarget,
         val: u8,
     ) -> Result<(), WriteError> {
@@ -217,32 +347,89 @@ impl Cpu {
         }
     }
 
-    fn handle_timers(&mut self, mem: &mut MemController<impl GBAllocator, impl RomReader>) {
-        if self.timer_cycles.0 % 256 == 0 {
-            mem.io_registers.timer_div += 1;
+    fn code_location(mem: &impl CpuBus, addr: u16) -> CodeLocation {
+        CodeLocation {
+            bank: mem.rom_bank(addr).map(|bank| bank as u16),
+            addr,
         }
+    }
 
-        if let Some(tac_frequency) = timer::get_tac_modulo(mem.io_registers.timer_control) {
-            if self.timer_cycles.0 % tac_frequency == 0 {
-                let (incremented, overflown) = mem.io_registers.timer_counter.overflowing_add(1);
-
-                if overflown {
-                    mem.io_registers.timer_counter = mem.io_registers.timer_modulo;
-                    mem.io_registers.interrupts_requested.set_timer(true);
-                } else {
-                    mem.io_registers.timer_counter = incremented;
-                }
+    /// Updates the call stack after executing `instr` from `pc`
+    fn track_call(&mut self, mem: &impl CpuBus, pc: u16, instr: Instruction, jumped: bool) {
+        let Some(calls) = &mut self.call_stack else {
+            return;
+        };
+
+        let kind = match instr {
+            Instruction::Call(_) | Instruction::CallIf(..) if jumped => CallKind::Call,
+            Instruction::Rst(_) => CallKind::Rst,
+            Instruction::Ret | Instruction::Reti | Instruction::RetIf(_) if jumped => {
+                calls.ret(self.registers.sp().wrapping_sub(2));
+                return;
             }
+            _ => return,
+        };
+
+        calls.push(CallFrame {
+            kind,
+            target: Self::code_location(mem, self.registers.pc()),
+            return_addr: pc.wrapping_add(instr.len() as u16),
+            sp: self.registers.sp(),
+        });
+    }
+
+    /// The interrupts that are both requested and enabled, one bit each
+    fn pending_interrupts(&self, mem: &impl CpuBus) -> u8 {
+        // The upper 3 bits do not correspond to an interrupt
+        mem.interrupts_enabled() & mem.interrupts_requested() & 0x1F
+    }
+
+    /// Services the highest priority pending interrupt. This takes 5
+    /// M-cycles: two idle cycles, two for pushing PC, and one for the jump
+    /// to the handler.
+    fn dispatch_interrupt(&mut self, mem: &mut impl CpuBus) -> Result<(), CpuErr> {
+        self.interrupts_master = false;
+
+        let pc = self.registers.pc();
+
+        // The interrupt to service is only picked after pushing the upper
+        // byte of PC. That write hits IE when SP points just past it, which
+        // can change the interrupt, or cancel it and jump to 0x0000 instead.
+        self.do_push8(mem, (pc >> 8) as u8)?;
+        let pending = self.pending_interrupts(mem);
+        self.do_push8(mem, pc as u8)?;
+
+        let handler_addr = if pending == 0 {
+            0x0000
+        } else {
+            let bit = pending.trailing_zeros();
+            let requested = mem.interrupts_requested() & !(1 << bit);
+
+            debug!(Subsystem::Cpu, "Handling interrupt! 0b{:b}", pending);
+
+            mem.set_interrupts_requested(requested);
+            0x40 + 8 * bit as u16
+        };
+
+        self.registers.set_pc(handler_addr);
+        self.cycles_remaining = 20 - 1;
+
+        if let Some(calls) = &mut self.call_stack {
+            calls.push(CallFrame {
+                kind: CallKind::Interrupt,
+                target: Self::code_location(mem, handler_addr),
+                return_addr: pc,
+                sp: self.registers.sp(),
+            });
         }
 
-        self.timer_cycles += 1;
+        Ok(())
     }
 
-    pub fn run_cycle(
-        &mut self,
-        mem: &mut MemController<impl GBAllocator, impl RomReader>,
-    ) -> Result<(), CpuErr> {
-        self.handle_timers(mem);
+    pub fn run_cycle(&mut self, mem: &mut impl CpuBus) -> Result<(), CpuErr> {
+        if let Some(profiler) = &mut self.profiler {
+            profiler.tick();
+        }
 
         if self.cycles_remaining != 0 {
             // Still executing, continue later
@@ -250,16 +437,107 @@ impl Cpu {
             return Ok(());
         }
 
-        let instr = decoder::decode(mem, self.registers.pc())?;
+        if self.stopped {
+            // The clock is stopped, only pulling a joypad line low starts
+            // it again
+            self.stopped = mem.joypad_lines() == 0x0F;
+            return Ok(());
+        }
+
+        let pending = self.pending_interrupts(mem);
+
+        if self.halted {
+            // Waking up takes an M-cycle, after which the interrupt is
+            // serviced if IME is set, or execution simply continues
+            self.halted = pending == 0;
+            self.cycles_remaining = 4 - 1;
+            return Ok(());
+        }
+
+        // Interrupts are serviced instead of fetching the next instruction
+        if self.interrupts_master && pending != 0 {
+            return self.dispatch_interrupt(mem);
+        }
+
+        let halt_bug = core::mem::take(&mut self.halt_bug);
+
+        let instr = if halt_bug {
+            decoder::decode(
+                &HaltBugReader(mem, self.registers.pc()),
+                self.registers.pc(),
+            )?
+        } else {
+            mem.decode(self.registers.pc())?
+        };
+
+        // Everything the instruction causes is traced within its span
+        #[cfg(feature = "tracing")]
+        let _span = crate::logging::enabled(Subsystem::Cpu, log::Level::Trace).then(|| {
+            let pc = self.registers.pc();
+            tracing::trace_span!(target: Subsystem::Cpu.target(), "instruction", pc).entered()
+        });
+
+        trace!(
+            Subsystem::Cpu,
+            "Running 0x{:x}: {}",
+            self.registers.pc(),
+            instr
+        );
+
+        #[cfg(feature = "std")]
+        let wants_entry = self.tracer.is_some() || self.doctor_log.is_some();
+
+        #[cfg(not(feature = "std"))]
+        let wants_entry = self.tracer.is_some();
+
+        if wants_entry {
+            let entry = self.trace_entry(mem, instr);
+
+            #[cfg(feature = "std")]
+            if let Some(log) = &mut self.doctor_log {
+                writeln!(log, "{}", entry).map_err(CpuErr::DoctorLog)?;
+            }
+
+            if let Some(tracer) = &mut self.tracer {
+                tracer.record(entry);
+            }
+        }
+
+        if let Some(stats) = &mut self.opcode_stats {
+            let pc = self.registers.pc();
+
+            let opcode = match mem.read8_direct(pc)? {
+                0xCB => Opcode::Prefixed(mem.read8_direct(pc.wrapping_add(1))?),
+                op => Opcode::Base(op),
+            };
+
+            stats.record(opcode);
+        }
+
+        let pc = self.registers.pc();
 
-        log::trace!("Running 0x{:x}: {}", self.registers.pc(), instr);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(Self::code_location(mem, pc), self.call_stack.as_deref());
+        }
+
+        if halt_bug {
+            // The instruction was fetched without incrementing PC, so
+            // everything relative to PC ends up one byte short
+            self.registers.set_pc(self.registers.pc().wrapping_sub(1));
+        }
 
         let should_enable_interrupts = self.ei_queued;
 
         // Actually run the instruction here
         let jumped = self.execute_instruction(mem, instr)?;
 
-        if should_enable_interrupts {
+        if self.call_stack.is_some() {
+            self.track_call(mem, pc, instr, jumped);
+        }
+
+        // EI takes effect after the instruction following it, unless that
+        // instruction was a DI
+        if should_enable_interrupts && self.ei_queued {
             self.ei_queued = false;
             self.interrupts_master = true;
         }
@@ -268,50 +546,10 @@ impl Cpu {
         if !jumped {
             let instr_len = instr.len() as u16;
 
-            self.registers.set_pc(self.registers.pc() + instr_len);
+            self.registers
+                .set_pc(self.registers.pc().wrapping_add(instr_len));
         }
 
-        // Handle any interrupts.
-        if self.interrupts_master {
-            let enabled = mem.interrupts_enabled;
-            let requested = mem.io_registers.interrupts_requested;
-            let to_service: Interrupts = (u8::from(enabled) & u8::from(requested)).into();
-
-            // We have an interrupt! Disable any following interrupts
-            // and go to the handler. We check for zero
-            // with the lower 5 bits, because the upper 3 are unused
-            // and thus do not actually correspond to an interrupt
-            if u8::from(to_service) & 0b00011111 != 0 {
-                log::debug!("Handling interrupt! 0b{:b}", u8::from(to_service));
-                self.interrupts_master = false;
-
-                let handler_addr: u16 = if to_service.vblank() {
-                    mem.io_registers.interrupts_requested.set_vblank(false);
-                    0x40
-                } else if to_service.lcd() {
-                    mem.io_registers.interrupts_requested.set_lcd(false);
-                    0x48
-                } else if to_service.timer() {
-                    mem.io_registers.interrupts_requested.set_timer(false);
-                    0x50
-                } else if to_service.serial() {
-                    mem.io_registers.interrupts_requested.set_serial(false);
-                    0x58
-                } else if to_service.joypad() {
-                    mem.io_registers.interrupts_requested.set_joypad(false);
-                    0x60
-                } else {
-                    unreachable!("Not actually an interrupt");
-                };
-
-                // Return addr is just the current PC now, since we were interrupted before executing it
-                self.do_call(mem, self.registers.pc(), handler_addr)?;
-                self.cycles_remaining = 20; // Entire interrupt routine takes 20 cycles to complete
-                return Ok(());
-            }
-        }
-
-        // No interrupt was handled. Just continue execution as usual
         match instr.cycles() {
             TCycles::Static(cycles) => self.cycles_remaining = cycles - 1,
             TCycles::Branching { taken, non_taken } => {
@@ -324,3 +562,183 @@ impl Cpu {
         Ok(())
     }
 }
+
+/// Reads memory as the decoder sees it right after the HALT bug: the byte
+/// at PC appears twice, shifting everything after it by one
+struct HaltBugReader<'a, M>(&'a M, u16);
+
+impl<M: DecoderReadable> DecoderReadable for HaltBugReader<'_, M> {
+    type Err = M::Err;
+
+    fn read_at(&self, addr: u16) -> Result<u8, Self::Err> {
+        if addr == self.1 {
+            self.0.read_at(addr)
+        } else {
+            self.0.read_at(addr.wrapping_sub(1))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::model::GbModel;
+    use crate::testutils::{blank_rom, NullInput, NullOutput, TestRuboy};
+    use crate::{GbInputs, Ruboy, RunUntil};
+
+    /// Runs `program` from `addr`, with the given interrupts enabled and
+    /// requested. Everything else in the ROM is NOPs.
+    fn start_at(addr: u16, program: &[u8], enabled: u8, requested: u8) -> TestRuboy {
+        let mut rom = blank_rom(0x00).into_inner();
+        let addr = addr as usize;
+
+        // JP addr
+        rom[0x100..0x103].copy_from_slice(&[0xC3, addr as u8, (addr >> 8) as u8]);
+        rom[addr..addr + program.len()].copy_from_slice(program);
+
+        let mut ruboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+
+        ruboy.run_until_pc(addr as u16, 100).unwrap();
+        ruboy.write_memory(0xFFFF, &[enabled]).unwrap();
+        ruboy.write_memory(0xFF0F, &[requested]).unwrap();
+        ruboy
+    }
+
+    fn start(program: &[u8], enabled: u8, requested: u8) -> TestRuboy {
+        start_at(0x150, program, enabled, requested)
+    }
+
+    #[test]
+    fn interrupt_dispatch_takes_five_m_cycles() {
+        // EI, NOP
+        let mut ruboy = start(&[0xFB, 0x00], 0x01, 0x01);
+        let sp = ruboy.registers().sp;
+
+        // 4 cycles each for EI and the NOP, then 20 for the dispatch
+        assert_eq!(
+            RunUntil::Reached(28),
+            ruboy.run_until_pc(0x40, 100).unwrap()
+        );
+
+        assert_eq!(sp - 2, ruboy.registers().sp);
+        assert_eq!(
+            vec![0x52, 0x01],
+            ruboy.read_memory(sp - 2..=sp - 1).unwrap()
+        );
+        assert_eq!(0x00, ruboy.peek(0xFF0F).unwrap() & 0x1F);
+        assert!(!ruboy.registers().ime);
+    }
+
+    #[test]
+    fn di_right_after_ei_cancels_it() {
+        // EI, DI
+        let mut ruboy = start(&[0xFB, 0xF3], 0x01, 0x01);
+
+        assert_eq!(RunUntil::Limit, ruboy.run_until_pc(0x40, 100).unwrap());
+        assert!(!ruboy.registers().ime);
+    }
+
+    #[test]
+    fn halt_wakes_without_ime() {
+        // HALT, INC A
+        let mut ruboy = start(&[0x76, 0x3C], 0x04, 0x00);
+        let a = ruboy.registers().a;
+
+        // The timer overflows after 16 increments, every 16 cycles
+        ruboy.write_memory(0xFF05, &[0xF0]).unwrap();
+        ruboy.write_memory(0xFF07, &[0x05]).unwrap();
+
+        // Woken by the timer, without jumping to its handler
+        match ruboy.run_until_pc(0x152, 1000).unwrap() {
+            RunUntil::Reached(cycles) => assert!(cycles > 200, "Woke up after {}", cycles),
+            other => panic!("Never woke up: {:?}", other),
+        }
+
+        assert_eq!(a.wrapping_add(1), ruboy.registers().a);
+        assert_eq!(0x04, ruboy.peek(0xFF0F).unwrap() & 0x04);
+    }
+
+    #[test]
+    fn halt_bug_reads_next_byte_twice() {
+        // HALT, INC A, with an interrupt already pending
+        let mut ruboy = start(&[0x76, 0x3C], 0x01, 0x01);
+        let a = ruboy.registers().a;
+
+        ruboy.run_until_pc(0x152, 100).unwrap();
+        assert_eq!(a.wrapping_add(2), ruboy.registers().a);
+
+        // HALT, LD A, 0x14, which runs as LD A, 0x3E and INC D
+        let mut ruboy = start(&[0x76, 0x3E, 0x14], 0x01, 0x01);
+        let d = ruboy.registers().d;
+
+        ruboy.run_until_pc(0x153, 100).unwrap();
+        assert_eq!(0x3E, ruboy.registers().a);
+        assert_eq!(d.wrapping_add(1), ruboy.registers().d);
+    }
+
+    #[test]
+    fn stop_waits_for_a_button() {
+        // LD A, 0x10 (select the buttons), LDH [P1], A, STOP, INC B
+        let mut ruboy = start(&[0x3E, 0x10, 0xE0, 0x00, 0x10, 0x00, 0x04], 0x00, 0x00);
+        let b = ruboy.registers().b;
+
+        assert_eq!(RunUntil::Limit, ruboy.run_until_pc(0x157, 10_000).unwrap());
+        assert_eq!(0x156, ruboy.registers().pc);
+        assert_eq!(0, ruboy.peek(0xFF04).unwrap());
+
+        ruboy.set_inputs(GbInputs {
+            start: true,
+            ..GbInputs::default()
+        });
+
+        assert!(matches!(
+            ruboy.run_until_pc(0x157, 100).unwrap(),
+            RunUntil::Reached(_)
+        ));
+        assert_eq!(b.wrapping_add(1), ruboy.registers().b);
+    }
+
+    #[test]
+    fn pushing_into_ie_changes_the_interrupt() {
+        // LD SP, 0x0000, EI, NOP. Pushing PC writes its upper byte to IE.
+        let program = [0x31, 0x00, 0x00, 0xFB, 0x00];
+
+        // 0x02 only enables STAT, which is not requested: cancelled
+        let mut ruboy = start_at(0x0200, &program, 0x01, 0x01);
+        assert!(matches!(
+            ruboy.run_until_pc(0x0000, 100).unwrap(),
+            RunUntil::Reached(_)
+        ));
+        assert_eq!(0x01, ruboy.peek(0xFF0F).unwrap() & 0x1F);
+
+        // 0x04 only enables the timer, which is serviced instead
+        let mut ruboy = start_at(0x0400, &program, 0x01, 0x05);
+        assert!(matches!(
+            ruboy.run_until_pc(0x50, 100).unwrap(),
+            RunUntil::Reached(_)
+        ));
+        assert_eq!(0x01, ruboy.peek(0xFF0F).unwrap() & 0x1F);
+    }
+
+    /// Runs the interrupt and HALT tests of the Mooneye test suite, in the
+    /// directory set by `RUBOY_MOONEYE_ACCEPTANCE` (`acceptance` of the
+    /// suite's builds)
+    #[test]
+    #[ignore = "needs the Mooneye test ROMs"]
+    fn mooneye_interrupts_and_halt() {
+        const ROMS: [&str; 6] = [
+            "ie_push.gb",
+            "intr_timing.gb",
+            "halt_ime0_ei.gb",
+            "halt_ime0_nointr_timing.gb",
+            "halt_ime1_timing.gb",
+            "halt_ime1_timing2-GS.gb",
+        ];
+
+        crate::testutils::assert_mooneye_passes("RUBOY_MOONEYE_ACCEPTANCE", |name| {
+            ROMS.contains(&name)
+        });
+    }
+}
@@ -1,3 +1,5 @@
+use crate::savestate::state_data;
+
 #[derive(Default)]
 pub(crate) struct Registers {
     a: u8,
@@ -12,6 +14,19 @@ pub(crate) struct Registers {
     pc: u16,
 }
 
+state_data!(Registers {
+    a,
+    b,
+    c,
+    d,
+    e,
+    f,
+    h,
+    l,
+    sp,
+    pc
+});
+
 /// Basic register operations
 impl Registers {
     pub fn new() -> Registers {
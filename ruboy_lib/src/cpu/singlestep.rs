@@ -0,0 +1,336 @@
+//! Runs the sm83 single step tests: JSON files with for every opcode
+//! thousands of vectors, each the state before and after running a single
+//! instruction, and the M-cycles it took. The CPU runs them against 64KiB of
+//! plain RAM, so they check the instructions alone.
+//!
+//! The vectors start with the opcode already fetched, the SM83 fetches the
+//! next opcode during the last M-cycle of an instruction. The opcode is at
+//! PC - 1, which is where the CPU here starts, and the final PC is one past
+//! where it ends.
+
+use std::any::Any;
+use std::boxed::Box;
+use std::format;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+
+use super::{Cpu, CpuBus, CpuErr};
+use crate::isa::decoder::DecoderReadable;
+use crate::memcontroller::{MemControllerDecoderErr, ReadError, WriteError};
+
+/// Plain RAM over the whole address space, without any interrupts
+struct FlatRam(Box<[u8; 0x10000]>);
+
+impl DecoderReadable for FlatRam {
+    type Err = MemControllerDecoderErr;
+
+    fn read_at(&self, addr: u16) -> Result<u8, Self::Err> {
+        Ok(self.0[addr as usize])
+    }
+}
+
+impl CpuBus for FlatRam {
+    fn read8(&self, addr: u16) -> Result<u8, ReadError> {
+        Ok(self.0[addr as usize])
+    }
+
+    fn write8(&mut self, addr: u16, value: u8) -> Result<(), WriteError> {
+        self.0[addr as usize] = value;
+        Ok(())
+    }
+
+    fn interrupts_enabled(&self) -> u8 {
+        0
+    }
+
+    fn interrupts_requested(&self) -> u8 {
+        0
+    }
+
+    fn set_interrupts_requested(&mut self, _requested: u8) {}
+}
+
+#[derive(Debug, Deserialize)]
+struct Vector {
+    name: String,
+    initial: State,
+
+    #[serde(rename = "final")]
+    end: State,
+
+    /// The bus activity of every M-cycle. Only the amount is checked, as
+    /// the CPU does all accesses of an instruction at once.
+    cycles: Vec<IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+struct State {
+    pc: u16,
+    sp: u16,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    ime: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+impl State {
+    fn apply(&self, cpu: &mut Cpu, ram: &mut FlatRam) {
+        let regs = &mut cpu.registers;
+
+        regs.set_pc(self.pc.wrapping_sub(1));
+        regs.set_sp(self.sp);
+        regs.set_a(self.a);
+        regs.set_b(self.b);
+        regs.set_c(self.c);
+        regs.set_d(self.d);
+        regs.set_e(self.e);
+        regs.set_f(self.f);
+        regs.set_h(self.h);
+        regs.set_l(self.l);
+        cpu.interrupts_master = self.ime != 0;
+
+        for &(addr, value) in &self.ram {
+            ram.0[addr as usize] = value;
+        }
+    }
+
+    /// The state of the CPU and RAM, at the addresses of `self.ram`
+    fn observe(&self, cpu: &Cpu, ram: &FlatRam) -> State {
+        let regs = &cpu.registers;
+
+        State {
+            pc: regs.pc().wrapping_add(1),
+            sp: regs.sp(),
+            a: regs.a(),
+            b: regs.b(),
+            c: regs.c(),
+            d: regs.d(),
+            e: regs.e(),
+            f: regs.f(),
+            h: regs.h(),
+            l: regs.l(),
+            ime: cpu.interrupts_master.into(),
+            ram: self
+                .ram
+                .iter()
+                .map(|&(addr, _)| (addr, ram.0[addr as usize]))
+                .collect(),
+        }
+    }
+}
+
+/// Runs a single vector, describing how it failed if it did
+fn run(vector: &Vector) -> Result<(), String> {
+    run_with(vector, |cpu, ram| cpu.run_cycle(ram))
+}
+
+/// Runs a single vector through `step`. Panics count as failures too, so
+/// they do not end the whole run.
+fn run_with(
+    vector: &Vector,
+    step: impl FnOnce(&mut Cpu, &mut FlatRam) -> Result<(), CpuErr>,
+) -> Result<(), String> {
+    let mut cpu = Cpu::new();
+    let mut ram = FlatRam(Box::new([0; 0x10000]));
+
+    vector.initial.apply(&mut cpu, &mut ram);
+
+    panic::catch_unwind(AssertUnwindSafe(|| step(&mut cpu, &mut ram)))
+        .map_err(|panic| format!("{}: panicked: {}", vector.name, panic_message(&*panic)))?
+        .map_err(|e| format!("{}: {}", vector.name, e))?;
+
+    let end = vector.end.observe(&cpu, &ram);
+
+    if end != vector.end {
+        return Err(format!(
+            "{}: expected {:?}, got {:?}",
+            vector.name, vector.end, end
+        ));
+    }
+
+    let m_cycles = (cpu.cycles_remaining as usize + 1) / 4;
+
+    if m_cycles != vector.cycles.len() {
+        return Err(format!(
+            "{}: took {} M-cycles instead of {}",
+            vector.name,
+            m_cycles,
+            vector.cycles.len()
+        ));
+    }
+
+    Ok(())
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    match panic.downcast_ref::<&str>() {
+        Some(msg) => msg,
+        None => panic
+            .downcast_ref::<String>()
+            .map_or("unknown panic", String::as_str),
+    }
+}
+
+/// Runs every vector in `json`, returning the failures
+fn run_all(json: &str) -> Vec<String> {
+    let vectors: Vec<Vector> = serde_json::from_str(json).unwrap();
+
+    vectors.iter().filter_map(|v| run(v).err()).collect()
+}
+
+/// A few vectors in the format of the test files, so the harness itself
+/// always runs
+const SAMPLE: &str = r#"[
+    {
+        "name": "00 nop",
+        "initial": {"pc": 49153, "sp": 65534, "a": 1, "b": 2, "c": 3, "d": 4, "e": 5, "f": 176, "h": 6, "l": 7, "ime": 0, "ram": [[49152, 0]]},
+        "final": {"pc": 49154, "sp": 65534, "a": 1, "b": 2, "c": 3, "d": 4, "e": 5, "f": 176, "h": 6, "l": 7, "ime": 0, "ram": [[49152, 0]]},
+        "cycles": [[49153, 0, "r-m"]]
+    },
+    {
+        "name": "80 add a,b",
+        "initial": {"pc": 257, "sp": 65534, "a": 58, "b": 198, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 128]]},
+        "final": {"pc": 258, "sp": 65534, "a": 0, "b": 198, "c": 0, "d": 0, "e": 0, "f": 176, "h": 0, "l": 0, "ime": 0, "ram": [[256, 128]]},
+        "cycles": [[257, 0, "r-m"]]
+    },
+    {
+        "name": "34 inc (hl)",
+        "initial": {"pc": 769, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 16, "h": 193, "l": 35, "ime": 1, "ram": [[768, 52], [49443, 255]]},
+        "final": {"pc": 770, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 176, "h": 193, "l": 35, "ime": 1, "ram": [[768, 52], [49443, 0]]},
+        "cycles": [[49443, 255, "r-m"], [49443, 0, "-wm"], [770, 0, "r-m"]]
+    },
+    {
+        "name": "c4 call nz,a16",
+        "initial": {"pc": 513, "sp": 53248, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[512, 196], [513, 52], [514, 18], [53246, 0], [53247, 0]]},
+        "final": {"pc": 4661, "sp": 53246, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[512, 196], [513, 52], [514, 18], [53246, 3], [53247, 2]]},
+        "cycles": [[513, 52, "r-m"], [514, 18, "r-m"], null, [53247, 2, "-wm"], [53246, 3, "-wm"], [4660, 0, "r-m"]]
+    },
+    {
+        "name": "cb 37 swap a",
+        "initial": {"pc": 16385, "sp": 65534, "a": 241, "b": 0, "c": 0, "d": 0, "e": 0, "f": 240, "h": 0, "l": 0, "ime": 0, "ram": [[16384, 203], [16385, 55]]},
+        "final": {"pc": 16387, "sp": 65534, "a": 31, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[16384, 203], [16385, 55]]},
+        "cycles": [[16385, 55, "r-m"], [16386, 0, "r-m"]]
+    },
+    {
+        "name": "37 scf",
+        "initial": {"pc": 1537, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 224, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 55]]},
+        "final": {"pc": 1538, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 144, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 55]]},
+        "cycles": [null]
+    },
+    {
+        "name": "3f ccf",
+        "initial": {"pc": 1537, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 112, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 63]]},
+        "final": {"pc": 1538, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 63]]},
+        "cycles": [null]
+    },
+    {
+        "name": "1f rra",
+        "initial": {"pc": 1537, "sp": 65534, "a": 1, "b": 0, "c": 0, "d": 0, "e": 0, "f": 128, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 31]]},
+        "final": {"pc": 1538, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 16, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 31]]},
+        "cycles": [null]
+    },
+    {
+        "name": "9a sbc a,d",
+        "initial": {"pc": 1537, "sp": 65534, "a": 16, "b": 0, "c": 0, "d": 15, "e": 0, "f": 16, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 154]]},
+        "final": {"pc": 1538, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 15, "e": 0, "f": 224, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 154]]},
+        "cycles": [null]
+    },
+    {
+        "name": "de sbc a,n8",
+        "initial": {"pc": 1537, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 222], [1537, 1]]},
+        "final": {"pc": 1539, "sp": 65534, "a": 255, "b": 0, "c": 0, "d": 0, "e": 0, "f": 112, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 222], [1537, 1]]},
+        "cycles": [null, null]
+    },
+    {
+        "name": "e8 add sp,e8",
+        "initial": {"pc": 1537, "sp": 65528, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 128, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 232], [1537, 8]]},
+        "final": {"pc": 1539, "sp": 0, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 48, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 232], [1537, 8]]},
+        "cycles": [null, null, null, null]
+    },
+    {
+        "name": "f8 ld hl,sp+e8",
+        "initial": {"pc": 1537, "sp": 49152, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 240, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 248], [1537, 255]]},
+        "final": {"pc": 1539, "sp": 49152, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 191, "l": 255, "ime": 0, "ram": [[1536, 248], [1537, 255]]},
+        "cycles": [null, null, null]
+    },
+    {
+        "name": "cb 00 rlc b",
+        "initial": {"pc": 1537, "sp": 65534, "a": 0, "b": 128, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 203], [1537, 0]]},
+        "final": {"pc": 1539, "sp": 65534, "a": 0, "b": 1, "c": 0, "d": 0, "e": 0, "f": 16, "h": 0, "l": 0, "ime": 0, "ram": [[1536, 203], [1537, 0]]},
+        "cycles": [null, null]
+    },
+    {
+        "name": "cb 2e sra (hl)",
+        "initial": {"pc": 1537, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 193, "l": 35, "ime": 0, "ram": [[1536, 203], [1537, 46], [49443, 129]]},
+        "final": {"pc": 1539, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 16, "h": 193, "l": 35, "ime": 0, "ram": [[1536, 203], [1537, 46], [49443, 192]]},
+        "cycles": [null, null, null, null]
+    },
+    {
+        "name": "10 stop",
+        "initial": {"pc": 257, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 16], [257, 0]]},
+        "final": {"pc": 259, "sp": 65534, "a": 0, "b": 0, "c": 0, "d": 0, "e": 0, "f": 0, "h": 0, "l": 0, "ime": 0, "ram": [[256, 16], [257, 0]]},
+        "cycles": [null]
+    }
+]"#;
+
+#[test]
+fn sample_vectors() {
+    assert_eq!(Vec::<String>::new(), run_all(SAMPLE));
+}
+
+#[test]
+fn catches_wrong_results() {
+    let broken = SAMPLE.replacen(r#""a": 0, "b": 198"#, r#""a": 1, "b": 198"#, 1);
+
+    assert_eq!(1, run_all(&broken).len());
+}
+
+#[test]
+fn reports_panics() {
+    let vectors: Vec<Vector> = serde_json::from_str(SAMPLE).unwrap();
+
+    let failure = run_with(&vectors[0], |_, _| panic!("broken")).unwrap_err();
+
+    assert_eq!("00 nop: panicked: broken", failure);
+}
+
+#[test]
+#[ignore = "needs the sm83 single step tests"]
+fn sm83_single_step() {
+    let dir = std::env::var("RUBOY_SM83_TESTS").expect("RUBOY_SM83_TESTS not set");
+
+    let mut files: Vec<_> = std::fs::read_dir(Path::new(&dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+
+    files.sort();
+    assert!(!files.is_empty(), "No test files in {}", dir);
+
+    let mut failures = Vec::new();
+
+    for path in &files {
+        let json = std::fs::read_to_string(path).unwrap();
+        failures.extend(run_all(&json).into_iter().take(3));
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} failures, of {} files:\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,294 @@
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::vec::Vec;
+use core::fmt::Display;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+use crate::isa::Instruction;
+
+/// CPU state right before an instruction was executed
+#[derive(Debug, Clone, Copy)]
+pub struct TraceEntry {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+
+    /// The four bytes of memory starting at PC
+    pub pcmem: [u8; 4],
+
+    /// The instruction that was executed
+    pub instruction: Instruction,
+}
+
+impl TraceEntry {
+    #[inline]
+    pub const fn zero_flag(&self) -> bool {
+        self.f & 0b10000000 != 0
+    }
+
+    #[inline]
+    pub const fn subtract_flag(&self) -> bool {
+        self.f & 0b1000000 != 0
+    }
+
+    #[inline]
+    pub const fn half_carry_flag(&self) -> bool {
+        self.f & 0b100000 != 0
+    }
+
+    #[inline]
+    pub const fn carry_flag(&self) -> bool {
+        self.f & 0b10000 != 0
+    }
+
+    /// The registers and flags that changed between this entry and `after`,
+    /// usually the entry of the next instruction
+    pub const fn diff<'a>(&'a self, after: &'a TraceEntry) -> TraceDiff<'a> {
+        TraceDiff {
+            before: self,
+            after,
+        }
+    }
+
+    fn registers(&self) -> [(&'static str, u16, usize); 8] {
+        [
+            ("A", self.a as u16, 2),
+            ("B", self.b as u16, 2),
+            ("C", self.c as u16, 2),
+            ("D", self.d as u16, 2),
+            ("E", self.e as u16, 2),
+            ("H", self.h as u16, 2),
+            ("L", self.l as u16, 2),
+            ("SP", self.sp, 4),
+        ]
+    }
+
+    fn flags(&self) -> [(&'static str, bool); 4] {
+        [
+            ("Z", self.zero_flag()),
+            ("N", self.subtract_flag()),
+            ("H", self.half_carry_flag()),
+            ("C", self.carry_flag()),
+        ]
+    }
+}
+
+/// Only the registers and flags that changed between two trace entries,
+/// formatted like `A:3E->00 Z:0->1`. PC is left out, as it changes with
+/// nearly every instruction. See [TraceEntry::diff]
+#[derive(Debug, Clone, Copy)]
+pub struct TraceDiff<'a> {
+    before: &'a TraceEntry,
+    after: &'a TraceEntry,
+}
+
+impl TraceDiff<'_> {
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.before.registers() == self.after.registers()
+            && self.before.flags() == self.after.flags()
+    }
+}
+
+impl Display for TraceDiff<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut changes = Vec::new();
+
+        for ((name, old, width), (_, new, _)) in self
+            .before
+            .registers()
+            .into_iter()
+            .zip(self.after.registers())
+        {
+            if old != new {
+                changes.push(format!("{}:{:0w$X}->{:0w$X}", name, old, new, w = width));
+            }
+        }
+
+        for ((name, old), (_, new)) in self.before.flags().into_iter().zip(self.after.flags()) {
+            if old != new {
+                changes.push(format!("{}:{}->{}", name, old as u8, new as u8));
+            }
+        }
+
+        write!(f, "{}", changes.join(" "))
+    }
+}
+
+/// Formats the entry in the log format used by
+/// [gameboy-doctor](https://github.com/robert/gameboy-doctor)
+impl Display for TraceEntry {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            self.a,
+            self.f,
+            self.b,
+            self.c,
+            self.d,
+            self.e,
+            self.h,
+            self.l,
+            self.sp,
+            self.pc,
+            self.pcmem[0],
+            self.pcmem[1],
+            self.pcmem[2],
+            self.pcmem[3]
+        )
+    }
+}
+
+/// Ring buffer containing the last N executed instructions
+#[derive(Debug, Clone)]
+pub struct Tracer {
+    capacity: usize,
+    entries: VecDeque<TraceEntry>,
+}
+
+impl Tracer {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn record(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+
+        self.entries.push_back(entry);
+    }
+
+    /// Iterates over the recorded entries, oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &TraceEntry> {
+        self.entries.iter()
+    }
+
+    /// Writes all recorded entries, oldest first, in the
+    /// gameboy-doctor log format. One line per instruction.
+    #[cfg(feature = "std")]
+    pub fn dump_doctor(&self, out: &mut impl Write) -> std::io::Result<()> {
+        for entry in self.entries() {
+            writeln!(out, "{}", entry)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes all recorded entries, oldest first, with only the registers
+    /// and flags each instruction changed. The changes of the newest
+    /// instruction are not known yet, so it is written without them.
+    #[cfg(feature = "std")]
+    pub fn dump_diff(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let mut entries = self.entries().peekable();
+
+        while let Some(entry) = entries.next() {
+            let instruction = entry.instruction.to_string();
+
+            match entries.peek() {
+                Some(next) => writeln!(
+                    out,
+                    "{:04X}  {:<20} {}",
+                    entry.pc,
+                    instruction,
+                    entry.diff(next)
+                )?,
+                None => writeln!(out, "{:04X}  {}", entry.pc, instruction)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(pc: u16) -> TraceEntry {
+        TraceEntry {
+            a: 0x01,
+            f: 0xB0,
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+            sp: 0xFFFE,
+            pc,
+            pcmem: [0x00, 0xC3, 0x13, 0x02],
+            instruction: Instruction::Nop,
+        }
+    }
+
+    #[test]
+    fn doctor_format() {
+        assert_eq!(
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,C3,13,02",
+            entry(0x100).to_string()
+        );
+    }
+
+    #[test]
+    fn diff_shows_only_changes() {
+        let before = entry(0x100);
+        let mut after = entry(0x101);
+
+        assert!(before.diff(&after).is_empty());
+        assert_eq!("", before.diff(&after).to_string());
+
+        after.a = 0x00;
+        after.sp = 0xFFFC;
+        after.f = 0x30;
+
+        assert_eq!(
+            "A:01->00 SP:FFFE->FFFC Z:1->0",
+            before.diff(&after).to_string()
+        );
+    }
+
+    #[test]
+    fn ring_buffer_drops_oldest() {
+        let mut tracer = Tracer::new(3);
+
+        for pc in 0..5 {
+            tracer.record(entry(pc));
+        }
+
+        let pcs: Vec<u16> = tracer.entries().map(|e| e.pc).collect();
+        assert_eq!(vec![2, 3, 4], pcs);
+    }
+}
@@ -0,0 +1,425 @@
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::fmt::Display;
+use core::ops::RangeInclusive;
+
+use super::expr::{Expr, ExprContext};
+
+/// Kind of memory access a watchpoint triggers on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    const fn matches(self, access: Access) -> bool {
+        matches!(
+            (self, access),
+            (Access::ReadWrite, _) | (Access::Read, Access::Read) | (Access::Write, Access::Write)
+        )
+    }
+}
+
+/// Stops execution when the CPU accesses the given range of memory.
+/// Accesses by the PPU and DMA are not watched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: RangeInclusive<u16>,
+    pub access: Access,
+}
+
+impl Watchpoint {
+    pub const fn new(range: RangeInclusive<u16>, access: Access) -> Self {
+        Self { range, access }
+    }
+
+    pub const fn at(addr: u16, access: Access) -> Self {
+        Self::new(addr..=addr, access)
+    }
+}
+
+/// Stops execution at an address, but only when the condition is true
+#[derive(Debug, Clone)]
+pub struct ConditionalBreakpoint {
+    pub addr: u16,
+    pub condition: Expr,
+}
+
+/// Why execution was paused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Paused by the user, through [Debugger::pause]
+    Paused,
+
+    /// About to execute the instruction at the given address
+    Breakpoint(u16),
+
+    /// About to execute the instruction at the given address, with the given
+    /// ROM bank mapped there
+    BankedBreakpoint { bank: u16, addr: u16 },
+
+    /// A watched address was accessed. For reads, `value` is the value read,
+    /// for writes the value written
+    Watchpoint {
+        addr: u16,
+        access: Access,
+        value: u8,
+    },
+
+    /// The watch expression at the given index of
+    /// [Debugger::watch_expressions] was true before executing the
+    /// instruction at `pc`
+    WatchExpression { index: usize, pc: u16 },
+
+    /// A single instruction was executed
+    Step,
+
+    /// A single frame was executed
+    Frame,
+
+    /// Emulation failed at the given address, see [crate::Ruboy::take_fault]
+    Fault(u16),
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StopReason::Paused => write!(f, "Paused"),
+            StopReason::Breakpoint(addr) => write!(f, "Breakpoint at 0x{:04X}", addr),
+            StopReason::BankedBreakpoint { bank, addr } => {
+                write!(f, "Breakpoint at {:02X}:{:04X}", bank, addr)
+            }
+            StopReason::Watchpoint {
+                addr,
+                access,
+                value,
+            } => write!(
+                f,
+                "Watchpoint: {:?} of 0x{:02X} at 0x{:04X}",
+                access, value, addr
+            ),
+            StopReason::WatchExpression { index, pc } => {
+                write!(f, "Watch expression {} hit at 0x{:04X}", index, pc)
+            }
+            StopReason::Step => write!(f, "Stepped one instruction"),
+            StopReason::Frame => write!(f, "Stepped one frame"),
+            StopReason::Fault(addr) => write!(f, "Emulation failed at 0x{:04X}", addr),
+        }
+    }
+}
+
+/// Snapshot of the CPU registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuRegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+}
+
+/// Breakpoints, watchpoints and run state. Attach it to an emulator with
+/// [crate::Ruboy::attach_debugger], after which [crate::Ruboy::step] stops
+/// as soon as a breakpoint or watchpoint is hit, and does nothing while
+/// paused.
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    breakpoints: BTreeSet<u16>,
+
+    /// Breakpoints that only hit in one ROM bank, as (bank, address)
+    banked_breakpoints: BTreeSet<(u16, u16)>,
+
+    conditional_breakpoints: Vec<ConditionalBreakpoint>,
+
+    /// Checked before every instruction, stopping when one is true
+    watch_expressions: Vec<Expr>,
+    watchpoints: Vec<Watchpoint>,
+    stop: Option<StopReason>,
+
+    /// Set when stopping on a breakpoint, so that resuming does not
+    /// immediately hit the same breakpoint again
+    resume_from: Option<u16>,
+
+    watch_hit: Cell<Option<StopReason>>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    /// Adds a breakpoint that only hits while the given ROM bank is mapped at
+    /// the address, see [crate::Ruboy::bank_at]. Breakpoints added with
+    /// [Debugger::add_breakpoint] hit in every bank.
+    pub fn add_banked_breakpoint(&mut self, bank: u16, addr: u16) {
+        self.banked_breakpoints.insert((bank, addr));
+    }
+
+    pub fn remove_banked_breakpoint(&mut self, bank: u16, addr: u16) -> bool {
+        self.banked_breakpoints.remove(&(bank, addr))
+    }
+
+    /// Adds a breakpoint that only hits when the condition is true. The
+    /// condition is only evaluated when the breakpoint is reached, so a
+    /// `changed` in it compares with the previous time it was reached.
+    pub fn add_conditional_breakpoint(&mut self, addr: u16, condition: Expr) {
+        self.conditional_breakpoints
+            .push(ConditionalBreakpoint { addr, condition });
+    }
+
+    /// Removes the conditional breakpoint at the given index of
+    /// [Debugger::conditional_breakpoints]
+    pub fn remove_conditional_breakpoint(&mut self, index: usize) -> Option<ConditionalBreakpoint> {
+        (index < self.conditional_breakpoints.len())
+            .then(|| self.conditional_breakpoints.remove(index))
+    }
+
+    pub fn conditional_breakpoints(&self) -> &[ConditionalBreakpoint] {
+        &self.conditional_breakpoints
+    }
+
+    /// Removes all breakpoints, banked, conditional or neither
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.banked_breakpoints.clear();
+        self.conditional_breakpoints.clear();
+    }
+
+    /// Adds an expression that is evaluated before every instruction,
+    /// stopping execution as soon as it is true, e.g. `a == 0x3C` or
+    /// `changed([hl])`
+    pub fn add_watch_expression(&mut self, expr: Expr) {
+        self.watch_expressions.push(expr);
+    }
+
+    /// Removes the watch expression at the given index of
+    /// [Debugger::watch_expressions]
+    pub fn remove_watch_expression(&mut self, index: usize) -> Option<Expr> {
+        (index < self.watch_expressions.len()).then(|| self.watch_expressions.remove(index))
+    }
+
+    pub fn clear_watch_expressions(&mut self) {
+        self.watch_expressions.clear();
+    }
+
+    pub fn watch_expressions(&self) -> &[Expr] {
+        &self.watch_expressions
+    }
+
+    /// All breakpoints that hit in every bank, in ascending order
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// All banked breakpoints as (bank, address), in ascending order
+    pub fn banked_breakpoints(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.banked_breakpoints.iter().copied()
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub const fn paused(&self) -> bool {
+        self.stop.is_some()
+    }
+
+    /// Why execution is paused, if it is
+    pub const fn stop_reason(&self) -> Option<StopReason> {
+        self.stop
+    }
+
+    pub fn pause(&mut self) {
+        if self.stop.is_none() {
+            self.stop = Some(StopReason::Paused);
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.stop = None;
+    }
+
+    pub(crate) fn stop(&mut self, reason: StopReason) {
+        log::info!("Debugger stopped: {}", reason);
+
+        self.resume_from = match reason {
+            StopReason::Breakpoint(addr)
+            | StopReason::BankedBreakpoint { addr, .. }
+            | StopReason::WatchExpression { pc: addr, .. } => Some(addr),
+            _ => None,
+        };
+
+        self.stop = Some(reason);
+    }
+
+    /// Whether there are any conditions to check with
+    /// [Debugger::check_conditions]. Checking them is slow, so this keeps
+    /// the emulator fast when there are none.
+    #[inline]
+    pub(crate) fn armed(&self) -> bool {
+        !self.conditional_breakpoints.is_empty() || !self.watch_expressions.is_empty()
+    }
+
+    /// Evaluates the conditional breakpoints at `pc` and all watch
+    /// expressions. Every watch expression is evaluated, even after one is
+    /// true, so each `changed` sees every instruction.
+    pub(crate) fn check_conditions(&self, pc: u16, ctx: &impl ExprContext) -> Option<StopReason> {
+        let mut hit = None;
+
+        for bp in self
+            .conditional_breakpoints
+            .iter()
+            .filter(|bp| bp.addr == pc)
+        {
+            if bp.condition.is_true(ctx) {
+                hit = Some(StopReason::Breakpoint(pc));
+            }
+        }
+
+        for (index, expr) in self.watch_expressions.iter().enumerate() {
+            if expr.is_true(ctx) && hit.is_none() {
+                hit = Some(StopReason::WatchExpression { index, pc });
+            }
+        }
+
+        hit
+    }
+
+    /// Checks for a breakpoint at the instruction about to be executed.
+    /// `bank` is the ROM bank mapped at `pc`, if it is in the cartridge ROM,
+    /// and `condition` what [Debugger::check_conditions] found.
+    pub(crate) fn check_breakpoint(
+        &mut self,
+        pc: u16,
+        bank: Option<u16>,
+        condition: Option<StopReason>,
+    ) -> Option<StopReason> {
+        if self.resume_from.take() == Some(pc) {
+            return None;
+        }
+
+        if self.breakpoints.contains(&pc) {
+            return Some(StopReason::Breakpoint(pc));
+        }
+
+        bank.filter(|bank| self.banked_breakpoints.contains(&(*bank, pc)))
+            .map(|bank| StopReason::BankedBreakpoint { bank, addr: pc })
+            .or(condition)
+    }
+
+    /// Records a hit if the access matches a watchpoint. Called from the
+    /// (immutable) CPU read path, hence the interior mutability
+    #[inline]
+    pub(crate) fn check_access(&self, addr: u16, access: Access, value: u8) {
+        if self.watchpoints.is_empty() {
+            return;
+        }
+
+        let hit = self
+            .watchpoints
+            .iter()
+            .any(|w| w.access.matches(access) && w.range.contains(&addr));
+
+        if hit && self.watch_hit.get().is_none() {
+            self.watch_hit.set(Some(StopReason::Watchpoint {
+                addr,
+                access,
+                value,
+            }));
+        }
+    }
+
+    pub(crate) fn take_watch_hit(&self) -> Option<StopReason> {
+        self.watch_hit.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchpoint_access_kinds() {
+        let mut dbg = Debugger::new();
+        dbg.add_watchpoint(Watchpoint::new(0xC000..=0xC0FF, Access::Write));
+
+        dbg.check_access(0xC010, Access::Read, 0x12);
+        assert_eq!(None, dbg.take_watch_hit());
+
+        dbg.check_access(0xC100, Access::Write, 0x12);
+        assert_eq!(None, dbg.take_watch_hit());
+
+        dbg.check_access(0xC010, Access::Write, 0x34);
+        assert_eq!(
+            Some(StopReason::Watchpoint {
+                addr: 0xC010,
+                access: Access::Write,
+                value: 0x34
+            }),
+            dbg.take_watch_hit()
+        );
+    }
+
+    #[test]
+    fn resuming_skips_breakpoint_once() {
+        let mut dbg = Debugger::new();
+        dbg.add_breakpoint(0x150);
+
+        let hit = dbg.check_breakpoint(0x150, None, None).unwrap();
+        dbg.stop(hit);
+        dbg.resume();
+
+        assert_eq!(None, dbg.check_breakpoint(0x150, None, None));
+        assert_eq!(Some(hit), dbg.check_breakpoint(0x150, None, None));
+    }
+
+    #[test]
+    fn banked_breakpoint_only_hits_in_its_bank() {
+        let mut dbg = Debugger::new();
+        dbg.add_banked_breakpoint(3, 0x4100);
+
+        assert_eq!(None, dbg.check_breakpoint(0x4100, Some(2), None));
+        assert_eq!(None, dbg.check_breakpoint(0x4100, None, None));
+        assert_eq!(
+            Some(StopReason::BankedBreakpoint {
+                bank: 3,
+                addr: 0x4100
+            }),
+            dbg.check_breakpoint(0x4100, Some(3), None)
+        );
+
+        dbg.clear_breakpoints();
+        assert_eq!(None, dbg.banked_breakpoints().next());
+    }
+}
@@ -0,0 +1,580 @@
+//! Small expressions over registers and memory, for conditional breakpoints
+//! and watch expressions.
+//!
+//! Expressions are made of numbers (`60`, `0x3C` or `$3C`), registers (`a`,
+//! `hl`, `sp`, `pc`, ...), memory bytes (`[hl]`, `[0xC000 + b]`) and the
+//! operators of C, with the same precedence: `|| && | ^ & == != < <= > >= +
+//! -` and the unary `! - ~`. All values are 16 bits and wrap around;
+//! comparisons and logic give 1 for true and 0 for false. `changed(x)` is
+//! true when `x` differs from what it was the previous time the expression
+//! was evaluated, e.g. `changed([hl])`.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::fmt::Display;
+use core::str::FromStr;
+
+use thiserror::Error;
+
+use super::CpuRegisters;
+use crate::emucore::EmulatorCore;
+use crate::{GBAllocator, GBGraphicsDrawer, InputHandler, RomReader, Ruboy};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    AF,
+    BC,
+    DE,
+    HL,
+    SP,
+    PC,
+}
+
+impl Register {
+    fn from_name(name: &str) -> Option<Self> {
+        let reg = match name.to_ascii_lowercase().as_str() {
+            "a" => Register::A,
+            "f" => Register::F,
+            "b" => Register::B,
+            "c" => Register::C,
+            "d" => Register::D,
+            "e" => Register::E,
+            "h" => Register::H,
+            "l" => Register::L,
+            "af" => Register::AF,
+            "bc" => Register::BC,
+            "de" => Register::DE,
+            "hl" => Register::HL,
+            "sp" => Register::SP,
+            "pc" => Register::PC,
+            _ => return None,
+        };
+
+        Some(reg)
+    }
+
+    fn read(self, regs: &CpuRegisters) -> u16 {
+        let pair = |high: u8, low: u8| u16::from_be_bytes([high, low]);
+
+        match self {
+            Register::A => regs.a as u16,
+            Register::F => regs.f as u16,
+            Register::B => regs.b as u16,
+            Register::C => regs.c as u16,
+            Register::D => regs.d as u16,
+            Register::E => regs.e as u16,
+            Register::H => regs.h as u16,
+            Register::L => regs.l as u16,
+            Register::AF => pair(regs.a, regs.f),
+            Register::BC => pair(regs.b, regs.c),
+            Register::DE => pair(regs.d, regs.e),
+            Register::HL => pair(regs.h, regs.l),
+            Register::SP => regs.sp,
+            Register::PC => regs.pc,
+        }
+    }
+}
+
+/// What expressions are evaluated against
+pub trait ExprContext {
+    fn register(&self, reg: Register) -> u16;
+
+    /// Reads memory without side effects
+    fn read(&self, addr: u16) -> u8;
+}
+
+impl<A, R, V, I> ExprContext for Ruboy<A, R, V, I>
+where
+    A: GBAllocator,
+    R: RomReader,
+    V: GBGraphicsDrawer,
+    I: InputHandler,
+{
+    fn register(&self, reg: Register) -> u16 {
+        reg.read(&self.registers())
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        // Nothing drives the bus where reading fails
+        self.peek(addr).unwrap_or(0xFF)
+    }
+}
+
+impl<V: GBGraphicsDrawer> ExprContext for dyn EmulatorCore<V> + '_ {
+    fn register(&self, reg: Register) -> u16 {
+        reg.read(&self.registers())
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        self.peek(addr).unwrap_or(0xFF)
+    }
+}
+
+impl<V: GBGraphicsDrawer> ExprContext for dyn EmulatorCore<V> + Send + '_ {
+    fn register(&self, reg: Register) -> u16 {
+        (self as &dyn EmulatorCore<V>).register(reg)
+    }
+
+    fn read(&self, addr: u16) -> u8 {
+        (self as &dyn EmulatorCore<V>).read(addr)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ExprParseErr {
+    #[error("Unexpected character '{0}' at position {1}")]
+    UnexpectedChar(char, usize),
+
+    #[error("Number does not fit in 16 bits at position {0}")]
+    NumberRange(usize),
+
+    #[error("Unknown register or function \"{0}\"")]
+    UnknownName(String),
+
+    #[error("Expected {0} at position {1}")]
+    Expected(&'static str, usize),
+
+    #[error("Unexpected end of expression")]
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnaryOp {
+    Not,
+    Neg,
+    BitNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinaryOp {
+    Or,
+    And,
+    BitOr,
+    Xor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+}
+
+impl BinaryOp {
+    /// The operator and its precedence, higher binds tighter
+    fn from_symbol(symbol: &str) -> Option<(Self, u8)> {
+        let op = match symbol {
+            "||" => (BinaryOp::Or, 1),
+            "&&" => (BinaryOp::And, 2),
+            "|" => (BinaryOp::BitOr, 3),
+            "^" => (BinaryOp::Xor, 4),
+            "&" => (BinaryOp::BitAnd, 5),
+            "==" => (BinaryOp::Eq, 6),
+            "!=" => (BinaryOp::Ne, 6),
+            "<" => (BinaryOp::Lt, 7),
+            "<=" => (BinaryOp::Le, 7),
+            ">" => (BinaryOp::Gt, 7),
+            ">=" => (BinaryOp::Ge, 7),
+            "+" => (BinaryOp::Add, 8),
+            "-" => (BinaryOp::Sub, 8),
+            _ => return None,
+        };
+
+        Some(op)
+    }
+
+    fn apply(self, lhs: u16, rhs: u16) -> u16 {
+        match self {
+            BinaryOp::Or => (lhs != 0 || rhs != 0) as u16,
+            BinaryOp::And => (lhs != 0 && rhs != 0) as u16,
+            BinaryOp::BitOr => lhs | rhs,
+            BinaryOp::Xor => lhs ^ rhs,
+            BinaryOp::BitAnd => lhs & rhs,
+            BinaryOp::Eq => (lhs == rhs) as u16,
+            BinaryOp::Ne => (lhs != rhs) as u16,
+            BinaryOp::Lt => (lhs < rhs) as u16,
+            BinaryOp::Le => (lhs <= rhs) as u16,
+            BinaryOp::Gt => (lhs > rhs) as u16,
+            BinaryOp::Ge => (lhs >= rhs) as u16,
+            BinaryOp::Add => lhs.wrapping_add(rhs),
+            BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Num(u16),
+    Reg(Register),
+    Mem(Box<Node>),
+    Unary(UnaryOp, Box<Node>),
+    Binary(BinaryOp, Box<Node>, Box<Node>),
+
+    /// With the value at the previous evaluation
+    Changed(Box<Node>, Cell<Option<u16>>),
+}
+
+impl Node {
+    /// Both sides of `&&` and `||` are always evaluated, so every
+    /// `changed` sees every evaluation
+    fn eval(&self, ctx: &(impl ExprContext + ?Sized)) -> u16 {
+        match self {
+            Node::Num(n) => *n,
+            Node::Reg(reg) => ctx.register(*reg),
+            Node::Mem(addr) => ctx.read(addr.eval(ctx)) as u16,
+            Node::Unary(op, operand) => {
+                let value = operand.eval(ctx);
+
+                match op {
+                    UnaryOp::Not => (value == 0) as u16,
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::BitNot => !value,
+                }
+            }
+            Node::Binary(op, lhs, rhs) => op.apply(lhs.eval(ctx), rhs.eval(ctx)),
+            Node::Changed(operand, previous) => {
+                let value = operand.eval(ctx);
+                let changed = previous
+                    .replace(Some(value))
+                    .is_some_and(|prev| prev != value);
+
+                changed as u16
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Num(u16),
+    Name(String),
+    Symbol(&'static str),
+}
+
+const SYMBOLS: [&str; 19] = [
+    "==", "!=", "<=", ">=", "&&", "||", "<", ">", "&", "|", "^", "+", "-", "!", "~", "(", ")", "[",
+    "]",
+];
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ExprParseErr> {
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while let Some(c) = source[pos..].chars().next() {
+        let rest = &source[pos..];
+
+        if c.is_whitespace() {
+            pos += c.len_utf8();
+        } else if c.is_ascii_digit() || c == '$' {
+            let (digits, radix, skip) = if let Some(hex) = rest.strip_prefix('$') {
+                (hex, 16, 1)
+            } else if let Some(hex) = rest.strip_prefix("0x").or(rest.strip_prefix("0X")) {
+                (hex, 16, 2)
+            } else {
+                (rest, 10, 0)
+            };
+
+            let len = digits
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(digits.len());
+
+            let num = u16::from_str_radix(&digits[..len], radix).map_err(|_| {
+                if digits[..len].chars().all(|c| c.is_digit(radix)) && len != 0 {
+                    ExprParseErr::NumberRange(pos)
+                } else {
+                    ExprParseErr::Expected("a number", pos)
+                }
+            })?;
+
+            tokens.push((Token::Num(num), pos));
+            pos += skip + len;
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+
+            tokens.push((Token::Name(rest[..len].to_string()), pos));
+            pos += len;
+        } else {
+            let symbol = SYMBOLS
+                .iter()
+                .find(|symbol| rest.starts_with(**symbol))
+                .ok_or(ExprParseErr::UnexpectedChar(c, pos))?;
+
+            tokens.push((Token::Symbol(symbol), pos));
+            pos += symbol.len();
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn advance(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.next).map(|(token, _)| token.clone());
+        self.next += 1;
+        token
+    }
+
+    /// Position of the next token, for errors
+    fn pos(&self) -> usize {
+        self.tokens.get(self.next).map(|(_, pos)| *pos).unwrap_or(0)
+    }
+
+    fn expect(&mut self, symbol: &'static str) -> Result<(), ExprParseErr> {
+        match self.peek() {
+            Some(Token::Symbol(s)) if *s == symbol => {
+                self.next += 1;
+                Ok(())
+            }
+            Some(_) => Err(ExprParseErr::Expected(symbol, self.pos())),
+            None => Err(ExprParseErr::End),
+        }
+    }
+
+    /// Parses operators binding at least as tight as `min_precedence`
+    fn binary(&mut self, min_precedence: u8) -> Result<Node, ExprParseErr> {
+        let mut lhs = self.unary()?;
+
+        while let Some(Token::Symbol(symbol)) = self.peek() {
+            let Some((op, precedence)) = BinaryOp::from_symbol(symbol) else {
+                break;
+            };
+
+            if precedence < min_precedence {
+                break;
+            }
+
+            self.next += 1;
+            let rhs = self.binary(precedence + 1)?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, ExprParseErr> {
+        let op = match self.peek() {
+            Some(Token::Symbol("!")) => UnaryOp::Not,
+            Some(Token::Symbol("-")) => UnaryOp::Neg,
+            Some(Token::Symbol("~")) => UnaryOp::BitNot,
+            _ => return self.primary(),
+        };
+
+        self.next += 1;
+        Ok(Node::Unary(op, Box::new(self.unary()?)))
+    }
+
+    fn primary(&mut self) -> Result<Node, ExprParseErr> {
+        let pos = self.pos();
+
+        match self.advance().ok_or(ExprParseErr::End)? {
+            Token::Num(n) => Ok(Node::Num(n)),
+            Token::Name(name) if name.eq_ignore_ascii_case("changed") => {
+                self.expect("(")?;
+                let operand = self.binary(0)?;
+                self.expect(")")?;
+
+                Ok(Node::Changed(Box::new(operand), Cell::new(None)))
+            }
+            Token::Name(name) => Register::from_name(&name)
+                .map(Node::Reg)
+                .ok_or(ExprParseErr::UnknownName(name)),
+            Token::Symbol("(") => {
+                let inner = self.binary(0)?;
+                self.expect(")")?;
+
+                Ok(inner)
+            }
+            Token::Symbol("[") => {
+                let addr = self.binary(0)?;
+                self.expect("]")?;
+
+                Ok(Node::Mem(Box::new(addr)))
+            }
+            Token::Symbol(_) => Err(ExprParseErr::Expected("a value", pos)),
+        }
+    }
+}
+
+/// A parsed expression, see the [module docs](self) for the syntax
+#[derive(Debug, Clone)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, ExprParseErr> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            next: 0,
+        };
+
+        let root = parser.binary(0)?;
+
+        if parser.peek().is_some() {
+            return Err(ExprParseErr::Expected("an operator", parser.pos()));
+        }
+
+        Ok(Self {
+            source: source.trim().to_string(),
+            root,
+        })
+    }
+
+    /// The expression as it was written
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn eval(&self, ctx: &(impl ExprContext + ?Sized)) -> u16 {
+        self.root.eval(ctx)
+    }
+
+    /// Whether the expression evaluates to anything but 0
+    pub fn is_true(&self, ctx: &(impl ExprContext + ?Sized)) -> bool {
+        self.eval(ctx) != 0
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ExprParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ctx {
+        a: u8,
+        hl: u16,
+        mem: [u8; 0x10000],
+    }
+
+    impl Ctx {
+        fn new() -> Self {
+            Self {
+                a: 0,
+                hl: 0,
+                mem: [0; 0x10000],
+            }
+        }
+    }
+
+    impl ExprContext for Ctx {
+        fn register(&self, reg: Register) -> u16 {
+            match reg {
+                Register::A => self.a as u16,
+                Register::H => self.hl >> 8,
+                Register::L => self.hl & 0xFF,
+                Register::HL => self.hl,
+                _ => 0,
+            }
+        }
+
+        fn read(&self, addr: u16) -> u8 {
+            self.mem[addr as usize]
+        }
+    }
+
+    fn eval(source: &str, ctx: &Ctx) -> u16 {
+        Expr::parse(source).unwrap().eval(ctx)
+    }
+
+    #[test]
+    fn numbers_registers_and_memory() {
+        let mut ctx = Ctx::new();
+        ctx.a = 0x3C;
+        ctx.hl = 0xC000;
+        ctx.mem[0xC001] = 0x42;
+
+        assert_eq!(1, eval("A == 0x3C", &ctx));
+        assert_eq!(1, eval("a == $3c && a == 60", &ctx));
+        assert_eq!(0x42, eval("[hl + 1]", &ctx));
+        assert_eq!(0xC0, eval("h", &ctx));
+    }
+
+    #[test]
+    fn precedence_follows_c() {
+        let ctx = Ctx::new();
+
+        assert_eq!(1, eval("1 + 1 == 2", &ctx));
+        assert_eq!(1, eval("0 && 1 || 1", &ctx));
+        assert_eq!(0, eval("0 && (1 || 1)", &ctx));
+        assert_eq!(6, eval("2 | 4 & 6", &ctx));
+        assert_eq!(0xFFFF, eval("-1", &ctx));
+        assert_eq!(1, eval("!0", &ctx));
+        assert_eq!(3, eval("5 - 1 - 1", &ctx));
+    }
+
+    #[test]
+    fn changed_compares_with_previous_evaluation() {
+        let mut ctx = Ctx::new();
+        ctx.hl = 0xC000;
+
+        let expr = Expr::parse("changed([hl])").unwrap();
+        assert!(!expr.is_true(&ctx));
+        assert!(!expr.is_true(&ctx));
+
+        ctx.mem[0xC000] = 1;
+        assert!(expr.is_true(&ctx));
+        assert!(!expr.is_true(&ctx));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            ExprParseErr::UnknownName("q".to_string()),
+            Expr::parse("q == 1").unwrap_err()
+        );
+        assert_eq!(ExprParseErr::End, Expr::parse("a ==").unwrap_err());
+        assert_eq!(
+            ExprParseErr::Expected("]", 4),
+            Expr::parse("[hl 1").unwrap_err()
+        );
+        assert_eq!(
+            ExprParseErr::NumberRange(0),
+            Expr::parse("0x10000").unwrap_err()
+        );
+        assert_eq!(
+            ExprParseErr::UnexpectedChar('#', 2),
+            Expr::parse("a #").unwrap_err()
+        );
+        assert_eq!(
+            ExprParseErr::Expected("an operator", 2),
+            Expr::parse("a b").unwrap_err()
+        );
+    }
+}
@@ -0,0 +1,120 @@
+/// When an injected cartridge fault becomes active
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultTrigger {
+    /// Active right away
+    Immediately,
+
+    /// Active after the given amount of T-cycles
+    AfterCycles(u64),
+
+    /// Active as soon as the CPU writes to the given address
+    OnWrite(u16),
+}
+
+/// What cartridge reads do while a fault is active
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultReads {
+    /// Reads still reach the cartridge
+    Unaffected,
+
+    /// Reads return 0xFF, like on a real Gameboy with a disconnected cartridge
+    OpenBus,
+
+    /// Reads fail with an error, which is handed back from [crate::Ruboy::step]
+    Error,
+}
+
+/// A fault to inject into the cartridge bus. Used to test how games (and the
+/// emulator) handle flaky or removed cartridges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CartridgeFault {
+    pub trigger: FaultTrigger,
+    pub reads: FaultReads,
+
+    /// If true, writes to cartridge RAM are dropped while the fault is active
+    pub drop_ram_writes: bool,
+}
+
+impl CartridgeFault {
+    /// Emulates pulling the cartridge out of the slot
+    pub const fn removal(trigger: FaultTrigger) -> Self {
+        Self {
+            trigger,
+            reads: FaultReads::OpenBus,
+            drop_ram_writes: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct FaultState {
+    fault: Option<CartridgeFault>,
+    cycles: u64,
+    triggered: bool,
+}
+
+impl FaultState {
+    pub fn inject(&mut self, fault: CartridgeFault) {
+        log::info!("Injecting cartridge fault: {:?}", fault);
+
+        *self = Self {
+            fault: Some(fault),
+            cycles: 0,
+            triggered: fault.trigger == FaultTrigger::Immediately,
+        };
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub const fn triggered(&self) -> bool {
+        self.triggered
+    }
+
+    #[inline]
+    pub fn cycle(&mut self) {
+        if let Some(CartridgeFault {
+            trigger: FaultTrigger::AfterCycles(trigger_cycles),
+            ..
+        }) = self.fault
+        {
+            self.cycles += 1;
+
+            if !self.triggered && self.cycles >= trigger_cycles {
+                log::info!("Cartridge fault triggered after {} cycles", self.cycles);
+                self.triggered = true;
+            }
+        }
+    }
+
+    #[inline]
+    pub fn on_write(&mut self, addr: u16) {
+        if let Some(CartridgeFault {
+            trigger: FaultTrigger::OnWrite(trigger_addr),
+            ..
+        }) = self.fault
+        {
+            if !self.triggered && addr == trigger_addr {
+                log::info!("Cartridge fault triggered by write to 0x{:x}", addr);
+                self.triggered = true;
+            }
+        }
+    }
+
+    #[inline]
+    pub fn reads(&self) -> FaultReads {
+        match self.fault {
+            Some(fault) if self.triggered => fault.reads,
+            _ => FaultReads::Unaffected,
+        }
+    }
+
+    #[inline]
+    pub fn drops_ram_writes(&self) -> bool {
+        match self.fault {
+            Some(fault) if self.triggered => fault.drop_ram_writes,
+            _ => false,
+        }
+    }
+}
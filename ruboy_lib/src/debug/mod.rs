@@ -0,0 +1,191 @@
+//! Tools for debugging games, and the emulator itself
+
+#[cfg(feature = "cached_interpreter")]
+pub use crate::cpu::blockcache::BlockCacheStats;
+pub use crate::cpu::trace::{TraceDiff, TraceEntry, Tracer};
+pub use crate::memcontroller::MemRegion;
+pub use debugger::{Access, ConditionalBreakpoint, CpuRegisters, Debugger, StopReason, Watchpoint};
+pub use expr::{Expr, ExprContext, ExprParseErr, Register};
+pub use fault::{CartridgeFault, FaultReads, FaultTrigger};
+pub use profiler::{CallFrame, CallKind, CallStack, CodeLocation, Profiler, MAX_CALL_DEPTH};
+pub use stats::{Opcode, OpcodeStats};
+
+pub(crate) use fault::FaultState;
+
+use crate::GbMonoColor;
+use alloc::vec;
+use alloc::vec::Vec;
+
+mod debugger;
+pub mod expr;
+mod fault;
+mod profiler;
+mod stats;
+
+/// Which of the two background maps in VRAM to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TileMapArea {
+    /// 0x9800 - 0x9BFF
+    #[default]
+    Low,
+
+    /// 0x9C00 - 0x9FFF
+    High,
+}
+
+impl TileMapArea {
+    pub const fn base_addr(self) -> u16 {
+        match self {
+            TileMapArea::Low => 0x9800,
+            TileMapArea::High => 0x9C00,
+        }
+    }
+
+    pub(crate) const fn from_lcdc_bit(bit: bool) -> Self {
+        if bit {
+            TileMapArea::High
+        } else {
+            TileMapArea::Low
+        }
+    }
+}
+
+/// A single object in OAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OamEntry {
+    /// Index of the object in OAM, 0 - 39
+    pub index: u8,
+
+    /// Screen position plus 8, as stored in OAM
+    pub x: u8,
+
+    /// Screen position plus 16, as stored in OAM
+    pub y: u8,
+    pub tile: u8,
+
+    /// Object palette 0 or 1
+    pub palette: u8,
+    pub x_flip: bool,
+    pub y_flip: bool,
+
+    /// Drawn behind background colors 1 - 3
+    pub behind_bg: bool,
+}
+
+/// The parts of the PPU configuration needed to make sense of VRAM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VramLayout {
+    pub scx: u8,
+    pub scy: u8,
+    pub win_x: u8,
+    pub win_y: u8,
+    pub bg_tilemap: TileMapArea,
+    pub window_tilemap: TileMapArea,
+    pub window_enabled: bool,
+
+    /// Background and window tiles are addressed from 0x8000 (unsigned)
+    /// instead of 0x9000 (signed)
+    pub unsigned_tile_addressing: bool,
+
+    /// Objects are 8x16 instead of 8x8
+    pub tall_objects: bool,
+}
+
+/// What a sound channel is currently doing, see
+/// [crate::Ruboy::audio_channel]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelState {
+    /// Triggered, and not stopped by its length counter or sweep since
+    pub enabled: bool,
+
+    /// Without its DAC, the channel is silent even while enabled
+    pub dac_enabled: bool,
+
+    /// The 11 bit frequency of NRx3 and NRx4. Zero for the noise channel
+    pub freq: u16,
+
+    /// The pitch the channel plays at. For the noise channel, how often its
+    /// shift register is clocked.
+    pub hz: f32,
+
+    /// The fraction of the time the wave is high. Only on the square
+    /// channels
+    pub duty: Option<f32>,
+
+    /// The current volume, 0 - 15
+    pub volume: u8,
+
+    /// Not on the wave channel
+    pub envelope: Option<EnvelopeState>,
+
+    /// The amount of 256Hz ticks until the channel stops, if its length
+    /// counter is enabled
+    pub length: Option<u16>,
+
+    /// The current digital output, 0 - 15
+    pub output: u8,
+
+    /// Sent to the left and right speaker (NR51)
+    pub left: bool,
+    pub right: bool,
+
+    /// Left out of the mixed output by the frontend, see
+    /// [crate::Ruboy::set_audio_channel_muted]
+    pub muted: bool,
+}
+
+/// The volume envelope of a channel (NRx2)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EnvelopeState {
+    pub initial: u8,
+    pub increase: bool,
+
+    /// The volume changes once every `period` 64Hz ticks. Zero when the
+    /// envelope is stopped.
+    pub period: u8,
+}
+
+/// An image of arbitrary size, used for views into video memory
+#[derive(Debug, Clone)]
+pub struct DebugImage {
+    width: usize,
+    height: usize,
+    pixels: Vec<GbMonoColor>,
+}
+
+impl DebugImage {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![GbMonoColor::White; width * height],
+        }
+    }
+
+    pub const fn width(&self) -> usize {
+        self.width
+    }
+
+    pub const fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The pixels, row by row
+    pub fn get_raw(&self) -> &[GbMonoColor] {
+        &self.pixels
+    }
+
+    pub fn get_pix(&self, x: usize, y: usize) -> Option<GbMonoColor> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+
+        Some(self.pixels[(y * self.width) + x])
+    }
+
+    pub fn set_pix(&mut self, x: usize, y: usize, val: GbMonoColor) {
+        debug_assert!(x < self.width && y < self.height);
+
+        self.pixels[(y * self.width) + x] = val;
+    }
+}
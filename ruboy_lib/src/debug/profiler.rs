@@ -0,0 +1,298 @@
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Write};
+
+/// Calls nested deeper than this drop their outermost frames
+pub const MAX_CALL_DEPTH: usize = 256;
+
+/// An address, together with the ROM bank mapped there if it is in the
+/// cartridge ROM
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CodeLocation {
+    pub bank: Option<u16>,
+    pub addr: u16,
+}
+
+impl Display for CodeLocation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.addr),
+            None => write!(f, "{:04X}", self.addr),
+        }
+    }
+}
+
+/// How a [CallFrame] was entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallKind {
+    Call,
+    Rst,
+    Interrupt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    pub kind: CallKind,
+
+    /// Where the call went to
+    pub target: CodeLocation,
+    pub return_addr: u16,
+
+    /// Where the return address was pushed
+    pub sp: u16,
+}
+
+/// The calls the CPU is in right now, as seen from CALL, RST, interrupts
+/// and returns. Games are free to mess with the stack, so frames are popped
+/// by stack pointer instead of one per return: returning from a frame also
+/// drops every frame that was pushed after it.
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    frames: Vec<CallFrame>,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The frames, outermost first
+    pub fn frames(&self) -> &[CallFrame] {
+        &self.frames
+    }
+
+    pub fn depth(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    pub(crate) fn push(&mut self, frame: CallFrame) {
+        if self.frames.len() == MAX_CALL_DEPTH {
+            self.frames.remove(0);
+        }
+
+        self.frames.push(frame);
+    }
+
+    /// A return, popping the return address from `sp`
+    pub(crate) fn ret(&mut self, sp: u16) {
+        while self.frames.last().is_some_and(|frame| frame.sp <= sp) {
+            self.frames.pop();
+        }
+    }
+}
+
+/// Samples where the CPU spends its cycles. Every `interval` cycles, the
+/// instruction being executed and the calls leading up to it are charged
+/// with those cycles.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    interval: u32,
+    countdown: u32,
+
+    /// The instruction being executed
+    current: CodeLocation,
+
+    /// The targets of the calls leading up to the instruction being
+    /// executed, followed by the instruction
+    stack: Vec<CodeLocation>,
+
+    /// Cycles per call stack, as the targets of the calls followed by the
+    /// executed instruction
+    stacks: BTreeMap<Vec<CodeLocation>, u64>,
+    addresses: BTreeMap<CodeLocation, u64>,
+    total: u64,
+}
+
+impl Profiler {
+    /// Often enough to see short routines, without slowing down emulation
+    /// too much
+    pub const DEFAULT_INTERVAL: u32 = 64;
+
+    /// Samples every `interval` cycles, at least every cycle
+    pub fn new(interval: u32) -> Self {
+        let interval = interval.max(1);
+
+        let current = CodeLocation {
+            bank: None,
+            addr: 0,
+        };
+
+        Self {
+            interval,
+            countdown: interval,
+            current,
+            stack: vec![current],
+            stacks: BTreeMap::new(),
+            addresses: BTreeMap::new(),
+            total: 0,
+        }
+    }
+
+    pub const fn interval(&self) -> u32 {
+        self.interval
+    }
+
+    /// All cycles charged so far
+    pub const fn total_cycles(&self) -> u64 {
+        self.total
+    }
+
+    /// Cycles charged to each instruction, most first
+    pub fn by_address(&self) -> Vec<(CodeLocation, u64)> {
+        let mut hottest: Vec<_> = self.addresses.iter().map(|(l, c)| (*l, *c)).collect();
+        hottest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        hottest
+    }
+
+    /// Cycles charged to each call stack, as the targets of the calls
+    /// followed by the executed instruction
+    pub fn stacks(&self) -> impl Iterator<Item = (&[CodeLocation], u64)> + '_ {
+        self.stacks
+            .iter()
+            .map(|(stack, cycles)| (stack.as_slice(), *cycles))
+    }
+
+    /// Writes the samples in the folded stack format read by flamegraph.pl
+    /// and inferno, one `02:4100;01:4A20;01:4A27 1280` line per stack
+    pub fn write_folded(&self, out: &mut impl Write) -> core::fmt::Result {
+        for (stack, cycles) in self.stacks() {
+            for (i, location) in stack.iter().enumerate() {
+                if i != 0 {
+                    out.write_char(';')?;
+                }
+
+                write!(out, "{}", location)?;
+            }
+
+            writeln!(out, " {}", cycles)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.stacks.clear();
+        self.addresses.clear();
+        self.total = 0;
+        self.countdown = self.interval;
+    }
+
+    /// Called when the CPU starts executing an instruction, with the calls
+    /// made before it. All of its cycles are charged to those, even when it
+    /// is a call or return itself.
+    #[inline]
+    pub(crate) fn enter(&mut self, location: CodeLocation, calls: Option<&CallStack>) {
+        let frames = calls.map(CallStack::frames).unwrap_or_default();
+
+        self.current = location;
+        self.stack.clear();
+        self.stack.extend(frames.iter().map(|frame| frame.target));
+        self.stack.push(location);
+    }
+
+    /// Called every cycle
+    #[inline]
+    pub(crate) fn tick(&mut self) {
+        self.countdown -= 1;
+
+        if self.countdown == 0 {
+            self.countdown = self.interval;
+            self.sample();
+        }
+    }
+
+    fn sample(&mut self) {
+        let cycles = self.interval as u64;
+
+        match self.stacks.get_mut(self.stack.as_slice()) {
+            Some(total) => *total += cycles,
+            None => {
+                self.stacks.insert(self.stack.clone(), cycles);
+            }
+        }
+
+        *self.addresses.entry(self.current).or_default() += cycles;
+        self.total += cycles;
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_INTERVAL)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::*;
+
+    fn loc(bank: u16, addr: u16) -> CodeLocation {
+        CodeLocation {
+            bank: Some(bank),
+            addr,
+        }
+    }
+
+    fn call(target: CodeLocation, sp: u16) -> CallFrame {
+        CallFrame {
+            kind: CallKind::Call,
+            target,
+            return_addr: 0,
+            sp,
+        }
+    }
+
+    #[test]
+    fn return_pops_abandoned_frames() {
+        let mut calls = CallStack::new();
+        calls.push(call(loc(0, 0x200), 0xFFFC));
+        calls.push(call(loc(1, 0x4000), 0xFFFA));
+        calls.push(call(loc(1, 0x4100), 0xFFF8));
+
+        // The innermost frame dropped its return address and returns to the
+        // outermost caller directly
+        calls.ret(0xFFFA);
+        assert_eq!(1, calls.depth());
+        assert_eq!(loc(0, 0x200), calls.frames()[0].target);
+
+        calls.ret(0xFFFC);
+        assert_eq!(0, calls.depth());
+    }
+
+    #[test]
+    fn samples_are_charged_to_the_call_stack() {
+        let mut calls = CallStack::new();
+        calls.push(call(loc(1, 0x4000), 0xFFFC));
+
+        let mut profiler = Profiler::new(4);
+        profiler.enter(loc(1, 0x4002), Some(&calls));
+
+        for _ in 0..8 {
+            profiler.tick();
+        }
+
+        let ram = CodeLocation {
+            bank: None,
+            addr: 0xC000,
+        };
+        profiler.enter(ram, None);
+
+        for _ in 0..4 {
+            profiler.tick();
+        }
+
+        assert_eq!(12, profiler.total_cycles());
+        assert_eq!(vec![(loc(1, 0x4002), 8), (ram, 4)], profiler.by_address());
+
+        let mut folded = String::new();
+        profiler.write_folded(&mut folded).unwrap();
+        assert_eq!("C000 4\n01:4000;01:4002 8\n", folded);
+    }
+}
@@ -0,0 +1,112 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+/// An opcode, either from the base instruction set or from the 0xCB
+/// prefixed one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Opcode {
+    Base(u8),
+    Prefixed(u8),
+}
+
+impl Opcode {
+    /// The bytes making up the opcode
+    pub fn bytes(self) -> Vec<u8> {
+        match self {
+            Opcode::Base(op) => vec![op],
+            Opcode::Prefixed(op) => vec![0xCB, op],
+        }
+    }
+}
+
+impl Display for Opcode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Opcode::Base(op) => write!(f, "0x{:02X}", op),
+            Opcode::Prefixed(op) => write!(f, "0xCB 0x{:02X}", op),
+        }
+    }
+}
+
+/// Per-opcode execution counts
+#[derive(Debug, Clone)]
+pub struct OpcodeStats {
+    base: [u64; 256],
+    prefixed: [u64; 256],
+}
+
+impl Default for OpcodeStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpcodeStats {
+    pub const fn new() -> Self {
+        Self {
+            base: [0; 256],
+            prefixed: [0; 256],
+        }
+    }
+
+    #[inline]
+    pub fn record(&mut self, opcode: Opcode) {
+        match opcode {
+            Opcode::Base(op) => self.base[op as usize] += 1,
+            Opcode::Prefixed(op) => self.prefixed[op as usize] += 1,
+        }
+    }
+
+    pub fn count(&self, opcode: Opcode) -> u64 {
+        match opcode {
+            Opcode::Base(op) => self.base[op as usize],
+            Opcode::Prefixed(op) => self.prefixed[op as usize],
+        }
+    }
+
+    /// Total amount of executed instructions
+    pub fn total(&self) -> u64 {
+        self.base.iter().chain(self.prefixed.iter()).sum()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// All opcodes that were executed at least once, most executed first
+    pub fn sorted(&self) -> Vec<(Opcode, u64)> {
+        let base = (0..=255u8).map(|op| (Opcode::Base(op), self.base[op as usize]));
+        let prefixed = (0..=255u8).map(|op| (Opcode::Prefixed(op), self.prefixed[op as usize]));
+
+        let mut all: Vec<(Opcode, u64)> = base.chain(prefixed).filter(|(_, n)| *n > 0).collect();
+
+        all.sort_by(|(op_a, n_a), (op_b, n_b)| n_b.cmp(n_a).then(op_a.cmp(op_b)));
+        all
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorted_by_count() {
+        let mut stats = OpcodeStats::new();
+
+        stats.record(Opcode::Base(0x00));
+        stats.record(Opcode::Prefixed(0x37));
+        stats.record(Opcode::Prefixed(0x37));
+        stats.record(Opcode::Base(0x3E));
+
+        assert_eq!(4, stats.total());
+        assert_eq!(
+            vec![
+                (Opcode::Prefixed(0x37), 2),
+                (Opcode::Base(0x00), 1),
+                (Opcode::Base(0x3E), 1)
+            ],
+            stats.sorted()
+        );
+    }
+}
@@ -0,0 +1,741 @@
+//! [EmulatorCore], the parts of a [Ruboy] a frontend drives, without the
+//! allocator and ROM reader in its type. With [boxed], both are chosen at
+//! runtime instead.
+//!
+//! ```ignore
+//! let mut core = emucore::boxed(
+//!     CoreRom::Buffer(rom),
+//!     CoreAllocator::Box,
+//!     output,
+//!     input,
+//!     BootRom::default(),
+//! )?;
+//!
+//! core.step(dt)?;
+//! ```
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+#[cfg(feature = "std")]
+use std::fs::File;
+#[cfg(feature = "std")]
+use std::io::BufReader;
+
+use crate::color::DmgPalette;
+use crate::debug::{
+    CallStack, ChannelState, CpuRegisters, DebugImage, Debugger, MemRegion, OamEntry, Profiler,
+    TileMapArea, Tracer, VramLayout,
+};
+use crate::memcontroller::{ReadError, WriteError};
+use crate::model::BootRom;
+use crate::rom::meta::{CartridgeMapper, RomMeta};
+use crate::rom::rtc::Rtc;
+#[cfg(feature = "mmap")]
+use crate::rom::MmapRom;
+use crate::storage::StorageProvider;
+use crate::{
+    rtc_key, sav_key, state_key, AudioChannel, BoxAllocator, ErrorPolicy, Fault, FrameEvent,
+    FrameObserver, Frameskip, GBAllocator, GBGraphicsDrawer, GbInputs, InlineAllocator,
+    InputHandler, LinkDevice, LoadPersistentErr, RomBuffer, RomReader, Ruboy, RuboyErr,
+    RuboyStartErr, RumbleHandler, StateLoadErr,
+};
+
+/// What a frontend needs to run an emulator instance, see the [Ruboy]
+/// method of the same name for each. Only generic over the video output,
+/// which errors are reported with.
+pub trait EmulatorCore<V: GBGraphicsDrawer> {
+    fn step(&mut self, dt: f64) -> Result<usize, RuboyErr<V>>;
+    fn step_frame(&mut self) -> Result<FrameEvent<'_>, RuboyErr<V>>;
+    fn step_instruction(&mut self) -> Result<usize, RuboyErr<V>>;
+    fn run_cycles(&mut self, cycles: u64) -> Result<u64, RuboyErr<V>>;
+    fn reset(&mut self) -> Result<(), RuboyErr<V>>;
+    fn step_until_audio(&mut self, samples: usize) -> Result<usize, RuboyErr<V>>;
+    fn linked_cycle(
+        &mut self,
+        exchange: &mut dyn FnMut(bool) -> Option<bool>,
+    ) -> Result<bool, RuboyErr<V>>;
+    fn serial_external_clock(&mut self, bit_in: bool) -> Option<bool>;
+
+    fn set_paused(&mut self, paused: bool);
+    fn paused(&self) -> bool;
+    fn set_speed(&mut self, speed: f64);
+    fn speed(&self) -> f64;
+    fn set_error_policy(&mut self, policy: ErrorPolicy);
+    fn take_fault(&mut self) -> Option<Fault>;
+    fn frame_count(&self) -> u64;
+    fn cycles_elapsed(&self) -> u64;
+
+    fn set_palette(&mut self, palette: DmgPalette);
+    fn palette(&self) -> &DmgPalette;
+    fn set_frameskip(&mut self, frameskip: Frameskip);
+    fn present_frame(&mut self) -> Result<(), V::Err>;
+
+    fn set_inputs(&mut self, inputs: GbInputs);
+    fn inputs(&self) -> GbInputs;
+
+    fn enable_audio(&mut self, sample_rate: u32);
+    fn disable_audio(&mut self);
+    fn audio_sample_rate(&self) -> Option<u32>;
+    fn set_audio_sample_rate(&mut self, sample_rate: u32);
+    fn set_audio_channel_muted(&mut self, channel: AudioChannel, muted: bool);
+    fn audio_channel_muted(&self, channel: AudioChannel) -> bool;
+    fn drain_audio(&mut self) -> Box<dyn Iterator<Item = [f32; 2]> + '_>;
+    fn audio_channel(&self, channel: AudioChannel) -> ChannelState;
+    fn enable_audio_scope(&mut self, len: usize);
+    fn disable_audio_scope(&mut self);
+    fn audio_scope(&self) -> Option<Box<dyn Iterator<Item = [f32; 2]> + '_>>;
+
+    fn attach_frame_observer(&mut self, observer: Box<dyn FrameObserver>);
+    fn attach_rumble_handler(&mut self, handler: Box<dyn RumbleHandler>);
+    fn attach_link_device(&mut self, device: Box<dyn LinkDevice>);
+    fn detach_link_device(&mut self) -> Option<Box<dyn LinkDevice>>;
+
+    fn save_state(&self) -> Vec<u8>;
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateLoadErr>;
+    fn state_digest(&self) -> u64;
+
+    fn cartridge_ram(&self) -> &[u8];
+    fn load_cartridge_ram(&mut self, data: &[u8]) -> bool;
+    fn save_rtc(&mut self) -> Option<Rtc>;
+    fn load_rtc(&mut self, saved: Rtc) -> bool;
+
+    fn rom_meta(&self) -> &RomMeta;
+    fn registers(&self) -> CpuRegisters;
+    fn peek(&self, addr: u16) -> Result<u8, ReadError>;
+    fn read_memory(&self, range: RangeInclusive<u16>) -> Result<Vec<u8>, ReadError>;
+    fn write_memory(&mut self, addr: u16, bytes: &[u8]) -> Result<(), WriteError>;
+    fn memory_region(&self, addr: u16) -> MemRegion;
+    fn current_bank(&self) -> u16;
+    fn bank_at(&self, addr: u16) -> Option<u16>;
+
+    fn vram_layout(&self) -> VramLayout;
+    fn render_tiles(&self) -> Result<DebugImage, ReadError>;
+    fn render_tilemap(&self, area: TileMapArea) -> Result<DebugImage, ReadError>;
+    fn render_oam(&self) -> Result<DebugImage, ReadError>;
+    fn oam_entries(&self) -> Result<Vec<OamEntry>, ReadError>;
+
+    fn attach_debugger(&mut self, debugger: Debugger);
+    fn detach_debugger(&mut self) -> Option<Debugger>;
+    fn debugger(&self) -> Option<&Debugger>;
+    fn debugger_mut(&mut self) -> Option<&mut Debugger>;
+    fn enable_trace(&mut self, capacity: usize);
+    fn disable_trace(&mut self);
+    fn trace(&self) -> Option<&Tracer>;
+    fn enable_call_stack(&mut self);
+    fn disable_call_stack(&mut self);
+    fn call_stack(&self) -> Option<&CallStack>;
+    fn enable_profiler(&mut self, interval: u32);
+    fn disable_profiler(&mut self) -> Option<Profiler>;
+    fn profiler(&self) -> Option<&Profiler>;
+}
+
+impl<A, R, V, I> EmulatorCore<V> for Ruboy<A, R, V, I>
+where
+    A: GBAllocator,
+    R: RomReader,
+    V: GBGraphicsDrawer,
+    I: InputHandler,
+{
+    fn step(&mut self, dt: f64) -> Result<usize, RuboyErr<V>> {
+        Ruboy::step(self, dt)
+    }
+
+    fn step_frame(&mut self) -> Result<FrameEvent<'_>, RuboyErr<V>> {
+        Ruboy::step_frame(self)
+    }
+
+    fn step_instruction(&mut self) -> Result<usize, RuboyErr<V>> {
+        Ruboy::step_instruction(self)
+    }
+
+    fn run_cycles(&mut self, cycles: u64) -> Result<u64, RuboyErr<V>> {
+        Ruboy::run_cycles(self, cycles)
+    }
+
+    fn reset(&mut self) -> Result<(), RuboyErr<V>> {
+        Ruboy::reset(self)
+    }
+
+    fn step_until_audio(&mut self, samples: usize) -> Result<usize, RuboyErr<V>> {
+        Ruboy::step_until_audio(self, samples)
+    }
+
+    fn linked_cycle(
+        &mut self,
+        exchange: &mut dyn FnMut(bool) -> Option<bool>,
+    ) -> Result<bool, RuboyErr<V>> {
+        Ruboy::linked_cycle(self, exchange)
+    }
+
+    fn serial_external_clock(&mut self, bit_in: bool) -> Option<bool> {
+        Ruboy::serial_external_clock(self, bit_in)
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        Ruboy::set_paused(self, paused)
+    }
+
+    fn paused(&self) -> bool {
+        Ruboy::paused(self)
+    }
+
+    fn set_speed(&mut self, speed: f64) {
+        Ruboy::set_speed(self, speed)
+    }
+
+    fn speed(&self) -> f64 {
+        Ruboy::speed(self)
+    }
+
+    fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        Ruboy::set_error_policy(self, policy)
+    }
+
+    fn take_fault(&mut self) -> Option<Fault> {
+        Ruboy::take_fault(self)
+    }
+
+    fn frame_count(&self) -> u64 {
+        Ruboy::frame_count(self)
+    }
+
+    fn cycles_elapsed(&self) -> u64 {
+        Ruboy::cycles_elapsed(self)
+    }
+
+    fn set_palette(&mut self, palette: DmgPalette) {
+        Ruboy::set_palette(self, palette)
+    }
+
+    fn palette(&self) -> &DmgPalette {
+        Ruboy::palette(self)
+    }
+
+    fn set_frameskip(&mut self, frameskip: Frameskip) {
+        Ruboy::set_frameskip(self, frameskip)
+    }
+
+    fn present_frame(&mut self) -> Result<(), V::Err> {
+        Ruboy::present_frame(self)
+    }
+
+    fn set_inputs(&mut self, inputs: GbInputs) {
+        Ruboy::set_inputs(self, inputs)
+    }
+
+    fn inputs(&self) -> GbInputs {
+        Ruboy::inputs(self)
+    }
+
+    fn enable_audio(&mut self, sample_rate: u32) {
+        Ruboy::enable_audio(self, sample_rate)
+    }
+
+    fn disable_audio(&mut self) {
+        Ruboy::disable_audio(self)
+    }
+
+    fn audio_sample_rate(&self) -> Option<u32> {
+        Ruboy::audio_sample_rate(self)
+    }
+
+    fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        Ruboy::set_audio_sample_rate(self, sample_rate)
+    }
+
+    fn set_audio_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        Ruboy::set_audio_channel_muted(self, channel, muted)
+    }
+
+    fn audio_channel_muted(&self, channel: AudioChannel) -> bool {
+        Ruboy::audio_channel_muted(self, channel)
+    }
+
+    fn drain_audio(&mut self) -> Box<dyn Iterator<Item = [f32; 2]> + '_> {
+        Box::new(Ruboy::drain_audio(self))
+    }
+
+    fn audio_channel(&self, channel: AudioChannel) -> ChannelState {
+        Ruboy::audio_channel(self, channel)
+    }
+
+    fn enable_audio_scope(&mut self, len: usize) {
+        Ruboy::enable_audio_scope(self, len)
+    }
+
+    fn disable_audio_scope(&mut self) {
+        Ruboy::disable_audio_scope(self)
+    }
+
+    fn audio_scope(&self) -> Option<Box<dyn Iterator<Item = [f32; 2]> + '_>> {
+        Ruboy::audio_scope(self).map(|scope| Box::new(scope) as Box<dyn Iterator<Item = _>>)
+    }
+
+    fn attach_frame_observer(&mut self, observer: Box<dyn FrameObserver>) {
+        self.frame_observer = Some(observer);
+    }
+
+    fn attach_rumble_handler(&mut self, handler: Box<dyn RumbleHandler>) {
+        self.rumble_handler = Some(handler);
+    }
+
+    fn attach_link_device(&mut self, device: Box<dyn LinkDevice>) {
+        self.link_device = Some(device);
+    }
+
+    fn detach_link_device(&mut self) -> Option<Box<dyn LinkDevice>> {
+        Ruboy::detach_link_device(self)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        Ruboy::save_state(self)
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), StateLoadErr> {
+        Ruboy::load_state(self, state)
+    }
+
+    fn state_digest(&self) -> u64 {
+        Ruboy::state_digest(self)
+    }
+
+    fn cartridge_ram(&self) -> &[u8] {
+        Ruboy::cartridge_ram(self)
+    }
+
+    fn load_cartridge_ram(&mut self, data: &[u8]) -> bool {
+        Ruboy::load_cartridge_ram(self, data)
+    }
+
+    fn save_rtc(&mut self) -> Option<Rtc> {
+        Ruboy::save_rtc(self)
+    }
+
+    fn load_rtc(&mut self, saved: Rtc) -> bool {
+        Ruboy::load_rtc(self, saved)
+    }
+
+    fn rom_meta(&self) -> &RomMeta {
+        Ruboy::rom_meta(self)
+    }
+
+    fn registers(&self) -> CpuRegisters {
+        Ruboy::registers(self)
+    }
+
+    fn peek(&self, addr: u16) -> Result<u8, ReadError> {
+        Ruboy::peek(self, addr)
+    }
+
+    fn read_memory(&self, range: RangeInclusive<u16>) -> Result<Vec<u8>, ReadError> {
+        Ruboy::read_memory(self, range)
+    }
+
+    fn write_memory(&mut self, addr: u16, bytes: &[u8]) -> Result<(), WriteError> {
+        Ruboy::write_memory(self, addr, bytes)
+    }
+
+    fn memory_region(&self, addr: u16) -> MemRegion {
+        Ruboy::memory_region(self, addr)
+    }
+
+    fn current_bank(&self) -> u16 {
+        Ruboy::current_bank(self)
+    }
+
+    fn bank_at(&self, addr: u16) -> Option<u16> {
+        Ruboy::bank_at(self, addr)
+    }
+
+    fn vram_layout(&self) -> VramLayout {
+        Ruboy::vram_layout(self)
+    }
+
+    fn render_tiles(&self) -> Result<DebugImage, ReadError> {
+        Ruboy::render_tiles(self)
+    }
+
+    fn render_tilemap(&self, area: TileMapArea) -> Result<DebugImage, ReadError> {
+        Ruboy::render_tilemap(self, area)
+    }
+
+    fn render_oam(&self) -> Result<DebugImage, ReadError> {
+        Ruboy::render_oam(self)
+    }
+
+    fn oam_entries(&self) -> Result<Vec<OamEntry>, ReadError> {
+        Ruboy::oam_entries(self)
+    }
+
+    fn attach_debugger(&mut self, debugger: Debugger) {
+        Ruboy::attach_debugger(self, debugger)
+    }
+
+    fn detach_debugger(&mut self) -> Option<Debugger> {
+        Ruboy::detach_debugger(self)
+    }
+
+    fn debugger(&self) -> Option<&Debugger> {
+        Ruboy::debugger(self)
+    }
+
+    fn debugger_mut(&mut self) -> Option<&mut Debugger> {
+        Ruboy::debugger_mut(self)
+    }
+
+    fn enable_trace(&mut self, capacity: usize) {
+        Ruboy::enable_trace(self, capacity)
+    }
+
+    fn disable_trace(&mut self) {
+        Ruboy::disable_trace(self)
+    }
+
+    fn trace(&self) -> Option<&Tracer> {
+        Ruboy::trace(self)
+    }
+
+    fn enable_call_stack(&mut self) {
+        Ruboy::enable_call_stack(self)
+    }
+
+    fn disable_call_stack(&mut self) {
+        Ruboy::disable_call_stack(self)
+    }
+
+    fn call_stack(&self) -> Option<&CallStack> {
+        Ruboy::call_stack(self)
+    }
+
+    fn enable_profiler(&mut self, interval: u32) {
+        Ruboy::enable_profiler(self, interval)
+    }
+
+    fn disable_profiler(&mut self) -> Option<Profiler> {
+        Ruboy::disable_profiler(self)
+    }
+
+    fn profiler(&self) -> Option<&Profiler> {
+        Ruboy::profiler(self)
+    }
+}
+
+/// Implements the methods storing data through a [StorageProvider], which
+/// can't be part of [EmulatorCore] itself as they are generic, for both a
+/// [boxed] and a [boxed_send] core
+macro_rules! storage_methods {
+    ($($core:ty),*) => {$(
+        impl<V: GBGraphicsDrawer> $core {
+            /// See [Ruboy::save_persistent]
+            pub fn save_persistent<S: StorageProvider>(
+                &mut self,
+                storage: &mut S,
+                name: &str,
+            ) -> Result<(), S::Err> {
+                let ram = self.cartridge_ram();
+
+                if self.rom_meta().cartridge_hardware().has_battery() && !ram.is_empty() {
+                    storage.store(&sav_key(name), ram)?;
+                }
+
+                if let Some(rtc) = self.save_rtc() {
+                    storage.store(&rtc_key(name), &rtc.to_save_bytes())?;
+                }
+
+                Ok(())
+            }
+
+            /// See [Ruboy::load_persistent]
+            pub fn load_persistent<S: StorageProvider>(
+                &mut self,
+                storage: &S,
+                name: &str,
+            ) -> Result<(), LoadPersistentErr<S::Err>> {
+                if self.rom_meta().cartridge_hardware().has_battery() {
+                    let saved = storage
+                        .load(&sav_key(name))
+                        .map_err(LoadPersistentErr::Storage)?;
+
+                    if let Some(bytes) = saved {
+                        if !self.load_cartridge_ram(&bytes) {
+                            return Err(LoadPersistentErr::CartRamLength {
+                                expected: self.cartridge_ram().len(),
+                                found: bytes.len(),
+                            });
+                        }
+                    }
+                }
+
+                if self.save_rtc().is_some() {
+                    let saved = storage
+                        .load(&rtc_key(name))
+                        .map_err(LoadPersistentErr::Storage)?;
+
+                    if let Some(bytes) = saved {
+                        self.load_rtc(Rtc::from_save_bytes(&bytes)?);
+                    }
+                }
+
+                Ok(())
+            }
+
+            /// See [Ruboy::save_state_to]
+            pub fn save_state_to<S: StorageProvider>(
+                &self,
+                storage: &mut S,
+                name: &str,
+            ) -> Result<(), S::Err> {
+                storage.store(&state_key(name), &self.save_state())
+            }
+
+            /// See [Ruboy::load_state_from]
+            pub fn load_state_from<S: StorageProvider>(
+                &mut self,
+                storage: &S,
+                name: &str,
+            ) -> Result<bool, LoadPersistentErr<S::Err>> {
+                match storage
+                    .load(&state_key(name))
+                    .map_err(LoadPersistentErr::Storage)?
+                {
+                    Some(state) => {
+                        self.load_state(&state)?;
+                        Ok(true)
+                    }
+                    None => Ok(false),
+                }
+            }
+        }
+    )*};
+}
+
+storage_methods!(dyn EmulatorCore<V> + '_, dyn EmulatorCore<V> + Send + '_);
+
+/// Which [GBAllocator] a [boxed] core uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CoreAllocator {
+    /// [InlineAllocator], keeping all memory inside the instance
+    #[default]
+    Inline,
+
+    /// [BoxAllocator], for when the instance would not fit on the stack
+    Box,
+}
+
+/// Where a [boxed] core reads the ROM from
+#[derive(Debug)]
+pub enum CoreRom {
+    /// The whole ROM, read through a [RomBuffer]
+    Buffer(Vec<u8>),
+
+    /// A ROM file, read as banks are needed
+    #[cfg(feature = "std")]
+    File(File),
+
+    /// A ROM file mapped into memory
+    #[cfg(feature = "mmap")]
+    Mmap(MmapRom),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CoreStartErr {
+    #[error(transparent)]
+    Buffer(RuboyStartErr<RomBuffer<Vec<u8>>>),
+
+    #[cfg(feature = "std")]
+    #[error(transparent)]
+    File(RuboyStartErr<BufReader<File>>),
+
+    #[cfg(feature = "mmap")]
+    #[error(transparent)]
+    Mmap(RuboyStartErr<MmapRom>),
+}
+
+impl CoreStartErr {
+    /// See [RuboyStartErr::unsupported_mapper]
+    pub fn unsupported_mapper(&self) -> Option<CartridgeMapper> {
+        match self {
+            CoreStartErr::Buffer(e) => e.unsupported_mapper(),
+            #[cfg(feature = "std")]
+            CoreStartErr::File(e) => e.unsupported_mapper(),
+            #[cfg(feature = "mmap")]
+            CoreStartErr::Mmap(e) => e.unsupported_mapper(),
+        }
+    }
+}
+
+/// Starts an instance of every allocator and reader combination, boxed as
+/// `$core`
+macro_rules! start_core {
+    ($core:ty, $rom:expr, $allocator:expr, $output:expr, $input:expr, $boot_rom:expr) => {{
+        let core: $core = match ($rom, $allocator) {
+            (CoreRom::Buffer(rom), CoreAllocator::Inline) => Box::new(
+                Ruboy::<InlineAllocator, _, _, _>::with_boot_rom(
+                    RomBuffer(rom),
+                    $output,
+                    $input,
+                    $boot_rom,
+                )
+                .map_err(CoreStartErr::Buffer)?,
+            ),
+            (CoreRom::Buffer(rom), CoreAllocator::Box) => Box::new(
+                Ruboy::<BoxAllocator, _, _, _>::with_boot_rom(
+                    RomBuffer(rom),
+                    $output,
+                    $input,
+                    $boot_rom,
+                )
+                .map_err(CoreStartErr::Buffer)?,
+            ),
+            #[cfg(feature = "std")]
+            (CoreRom::File(file), CoreAllocator::Inline) => Box::new(
+                Ruboy::<InlineAllocator, _, _, _>::with_boot_rom(
+                    BufReader::new(file),
+                    $output,
+                    $input,
+                    $boot_rom,
+                )
+                .map_err(CoreStartErr::File)?,
+            ),
+            #[cfg(feature = "std")]
+            (CoreRom::File(file), CoreAllocator::Box) => Box::new(
+                Ruboy::<BoxAllocator, _, _, _>::with_boot_rom(
+                    BufReader::new(file),
+                    $output,
+                    $input,
+                    $boot_rom,
+                )
+                .map_err(CoreStartErr::File)?,
+            ),
+            #[cfg(feature = "mmap")]
+            (CoreRom::Mmap(rom), CoreAllocator::Inline) => Box::new(
+                Ruboy::<InlineAllocator, _, _, _>::with_boot_rom(rom, $output, $input, $boot_rom)
+                    .map_err(CoreStartErr::Mmap)?,
+            ),
+            #[cfg(feature = "mmap")]
+            (CoreRom::Mmap(rom), CoreAllocator::Box) => Box::new(
+                Ruboy::<BoxAllocator, _, _, _>::with_boot_rom(rom, $output, $input, $boot_rom)
+                    .map_err(CoreStartErr::Mmap)?,
+            ),
+        };
+
+        Ok(core)
+    }};
+}
+
+/// Creates a new emulator instance like [Ruboy::with_boot_rom], with the
+/// allocator and ROM reader chosen at runtime
+pub fn boxed<V, I>(
+    rom: CoreRom,
+    allocator: CoreAllocator,
+    output: V,
+    input: I,
+    boot_rom: BootRom,
+) -> Result<Box<dyn EmulatorCore<V>>, CoreStartErr>
+where
+    V: GBGraphicsDrawer + 'static,
+    I: InputHandler + 'static,
+{
+    start_core!(
+        Box<dyn EmulatorCore<V>>,
+        rom,
+        allocator,
+        output,
+        input,
+        boot_rom
+    )
+}
+
+/// Same as [boxed], for instances that have to move to another thread
+pub fn boxed_send<V, I>(
+    rom: CoreRom,
+    allocator: CoreAllocator,
+    output: V,
+    input: I,
+    boot_rom: BootRom,
+) -> Result<Box<dyn EmulatorCore<V> + Send>, CoreStartErr>
+where
+    V: GBGraphicsDrawer + Send + 'static,
+    I: InputHandler + Send + 'static,
+{
+    start_core!(
+        Box<dyn EmulatorCore<V> + Send>,
+        rom,
+        allocator,
+        output,
+        input,
+        boot_rom
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::GbModel;
+    use crate::testutils::{blank_rom, fix_header_checksum, NullInput, NullOutput, TestRuboy};
+
+    #[test]
+    fn boxed_runs_like_ruboy() {
+        let rom = blank_rom(0x3C).into_inner();
+        let boot_rom = BootRom::Skip(GbModel::Dmg);
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_boot_rom(blank_rom(0x3C), NullOutput, NullInput, boot_rom.clone()).unwrap();
+
+        for allocator in [CoreAllocator::Inline, CoreAllocator::Box] {
+            let mut core = boxed(
+                CoreRom::Buffer(rom.clone()),
+                allocator,
+                NullOutput,
+                NullInput,
+                boot_rom.clone(),
+            )
+            .unwrap();
+
+            core.run_cycles(10_000).unwrap();
+            assert_eq!(10_000, core.cycles_elapsed());
+
+            let state = core.save_state();
+            assert!(ruboy.load_state(&state).is_ok());
+            assert_eq!(ruboy.state_digest(), core.state_digest());
+        }
+    }
+
+    #[test]
+    fn boxed_edits_memory() {
+        let mut core = boxed_send(
+            CoreRom::Buffer(blank_rom(0x3C).into_inner()),
+            CoreAllocator::Box,
+            NullOutput,
+            NullInput,
+            BootRom::Skip(GbModel::Dmg),
+        )
+        .unwrap();
+
+        core.write_memory(0xC000, &[0x12, 0x34]).unwrap();
+
+        assert_eq!(0x34, core.peek(0xC001).unwrap());
+        assert_eq!(vec![0x12, 0x34], core.read_memory(0xC000..=0xC001).unwrap());
+        assert_eq!(MemRegion::WorkRam, core.memory_region(0xC000));
+    }
+
+    #[test]
+    fn boxed_reports_unsupported_mappers() {
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x147] = 0x05; // MBC2
+        fix_header_checksum(&mut rom);
+
+        let err = boxed_send(
+            CoreRom::Buffer(rom),
+            CoreAllocator::Inline,
+            NullOutput,
+            NullInput,
+            BootRom::Skip(GbModel::Dmg),
+        )
+        .err()
+        .unwrap();
+
+        assert_eq!(Some(CartridgeMapper::MBC2), err.unsupported_mapper());
+    }
+}
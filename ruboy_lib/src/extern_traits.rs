@@ -1,11 +1,16 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::error::Error;
 use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::io::{Read, Seek};
 
-use std::{
-    error::Error,
-    io::{Read, Seek},
-};
-
+use crate::color::{DmgPalette, Rgb};
+use crate::flatmem::FlatMemory;
+use crate::model::StateDigest;
 use crate::ppu::palette::Palette;
+use crate::ppu::{LcdMode, LcdRegisters};
+use crate::sgb::{SgbBorder, SgbScreen};
 
 /// Trait representing something that can read a ROM.
 /// Used internally by the Ruboy ROM memory-bank-controllers to read the data
@@ -36,8 +41,16 @@ pub trait RomReader: Debug {
 
         Ok(buf)
     }
+
+    /// The whole ROM, if the reader keeps it in memory. The ROM controllers
+    /// then read banks straight from it, instead of copying every bank they
+    /// switch to.
+    fn as_slice(&self) -> Option<&[u8]> {
+        None
+    }
 }
 
+#[cfg(feature = "std")]
 impl<T> RomReader for T
 where
     T: Read + Seek + Debug,
@@ -58,14 +71,54 @@ where
     }
 }
 
+/// A ROM image that is completely in memory, like a byte slice or a [Vec].
+/// Unlike readers based on [std::io], this is also available without std.
+#[derive(Debug, Clone)]
+pub struct RomBuffer<B: AsRef<[u8]> + Debug>(pub B);
+
+#[derive(Debug, thiserror::Error, Clone, Copy, PartialEq, Eq)]
+#[error("Read of {len} bytes at 0x{addr:x} is past the end of the ROM")]
+pub struct RomBufferErr {
+    pub addr: usize,
+    pub len: usize,
+}
+
+impl<B: AsRef<[u8]> + Debug> RomReader for RomBuffer<B> {
+    type Err = RomBufferErr;
+
+    fn read_into(&mut self, buf: &mut [u8], addr: usize) -> Result<(), Self::Err> {
+        read_from_slice(self.0.as_ref(), buf, addr)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(self.0.as_ref())
+    }
+}
+
+/// [RomReader::read_into] for readers that have the whole ROM in memory
+pub(crate) fn read_from_slice(rom: &[u8], buf: &mut [u8], addr: usize) -> Result<(), RomBufferErr> {
+    let src = addr
+        .checked_add(buf.len())
+        .and_then(|end| rom.get(addr..end))
+        .ok_or(RomBufferErr {
+            addr,
+            len: buf.len(),
+        })?;
+
+    buf.copy_from_slice(src);
+
+    Ok(())
+}
+
 /// Trait representing something that can allocate memory for [crate::Ruboy]
 /// Usually not required to implement directly, but can be useful if a custom memory
 /// allocator is used.
 ///
 /// See the two provided implementations: [InlineAllocator] and [BoxAllocator]
 pub trait GBAllocator: Debug {
-    /// The type of the memory created by this allocator. For example [T; N] or Box<[T; N]>
-    type Mem<T: Copy + Debug, const N: usize>: GBRam<T> + Debug;
+    /// The type of the memory created by this allocator. For example [T; N] or Box<[T; N]>.
+    /// Has to be [Send], so that [crate::Ruboy] can be moved to other threads.
+    type Mem<T: Copy + Debug + Send, const N: usize>: GBRam<T> + Debug + Send;
 
     /// Return an initialized buffer of size N, filled with clones of "orig"
     ///
@@ -74,7 +127,7 @@ pub trait GBAllocator: Debug {
     /// * `T` The type of the buffer elements
     /// * `N` The size of the buffer, in amount of elements
     /// * `orig` The object to clone for initializing each element
-    fn clone_from<T: Copy + Debug, const N: usize>(orig: &T) -> Self::Mem<T, N>;
+    fn clone_from<T: Copy + Debug + Send, const N: usize>(orig: &T) -> Self::Mem<T, N>;
 
     /// Return a buffer of size N with each element initialized to its [Default]
     ///
@@ -82,7 +135,7 @@ pub trait GBAllocator: Debug {
     ///
     /// * `T` The type of the buffer elements
     /// * `N` The siz of the buffer, in amount of elements
-    fn empty<T: Default + Copy + Debug, const N: usize>() -> Self::Mem<T, N>;
+    fn empty<T: Default + Copy + Debug + Send, const N: usize>() -> Self::Mem<T, N>;
 }
 
 pub trait GBRam<T: Copy + Debug> {
@@ -142,13 +195,13 @@ impl<T: Copy + Debug, R: GBRam<T>> GBRam<T> for Box<R> {
 pub struct InlineAllocator;
 
 impl GBAllocator for InlineAllocator {
-    type Mem<T: Copy + Debug, const N: usize> = [T; N];
+    type Mem<T: Copy + Debug + Send, const N: usize> = [T; N];
 
-    fn clone_from<T: Copy + Debug, const N: usize>(orig: &T) -> Self::Mem<T, N> {
-        std::array::from_fn(|_| *orig)
+    fn clone_from<T: Copy + Debug + Send, const N: usize>(orig: &T) -> Self::Mem<T, N> {
+        core::array::from_fn(|_| *orig)
     }
 
-    fn empty<T: Default + Copy + Debug, const N: usize>() -> Self::Mem<T, N> {
+    fn empty<T: Default + Copy + Debug + Send, const N: usize>() -> Self::Mem<T, N> {
         [T::default(); N]
     }
 }
@@ -157,13 +210,13 @@ impl GBAllocator for InlineAllocator {
 pub struct BoxAllocator;
 
 impl GBAllocator for BoxAllocator {
-    type Mem<T: Copy + Debug, const N: usize> = Box<[T; N]>;
+    type Mem<T: Copy + Debug + Send, const N: usize> = Box<[T; N]>;
 
-    fn clone_from<T: Copy + Debug, const N: usize>(orig: &T) -> Self::Mem<T, N> {
-        Box::new(std::array::from_fn(|_| *orig))
+    fn clone_from<T: Copy + Debug + Send, const N: usize>(orig: &T) -> Self::Mem<T, N> {
+        Box::new(core::array::from_fn(|_| *orig))
     }
 
-    fn empty<T: Default + Copy + Debug, const N: usize>() -> Self::Mem<T, N> {
+    fn empty<T: Default + Copy + Debug + Send, const N: usize>() -> Self::Mem<T, N> {
         Box::new([T::default(); N])
     }
 }
@@ -171,7 +224,7 @@ impl GBAllocator for BoxAllocator {
 pub const FRAME_X: usize = 160;
 pub const FRAME_Y: usize = 144;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum GbMonoColor {
     White = 0,
@@ -180,8 +233,9 @@ pub enum GbMonoColor {
     Black = 3,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum GbColorID {
+    #[default]
     ID0,
     ID1,
     ID2,
@@ -207,6 +261,12 @@ impl TryFrom<u8> for GbColorID {
 #[derive(Debug, Clone)]
 pub struct Frame {
     pixels: [GbMonoColor; FRAME_X * FRAME_Y],
+
+    /// The colors to show the shades in. Set with [crate::Ruboy::set_palette]
+    palette: DmgPalette,
+
+    /// The colors of the Super Game Boy, used instead of the palette
+    sgb: Option<SgbScreen>,
 }
 
 impl Frame {
@@ -218,6 +278,44 @@ impl Frame {
         &mut self.pixels
     }
 
+    pub const fn palette(&self) -> &DmgPalette {
+        &self.palette
+    }
+
+    pub fn set_palette(&mut self, palette: DmgPalette) {
+        self.palette = palette;
+    }
+
+    /// How the Super Game Boy colors the frame, when running a game with
+    /// SGB support. See [crate::sgb].
+    pub const fn sgb(&self) -> Option<&SgbScreen> {
+        self.sgb.as_ref()
+    }
+
+    pub(crate) fn set_sgb(&mut self, sgb: Option<SgbScreen>) {
+        self.sgb = sgb;
+    }
+
+    /// The pixels row by row, in the colors of the palette, or of the Super
+    /// Game Boy if it colors the frame
+    pub fn rgb_pixels(&self) -> impl Iterator<Item = Rgb> + '_ {
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(|(i, shade)| match &self.sgb {
+                Some(sgb) => sgb.color(i % FRAME_X, i / FRAME_X, *shade),
+                None => self.palette.color(*shade),
+            })
+    }
+
+    /// The pixels row by row as 8-bit RGBA, in the colors of
+    /// [Frame::rgb_pixels]. Ready to be written to an image file.
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        self.rgb_pixels()
+            .flat_map(|rgb| [rgb.r, rgb.g, rgb.b, u8::MAX])
+            .collect()
+    }
+
     pub fn get_pix(&self, x: u8, y: u8) -> Option<GbMonoColor> {
         if x as usize >= FRAME_X || y as usize >= FRAME_Y {
             return None;
@@ -238,12 +336,27 @@ impl Frame {
 
         self.pixels[(y as usize * FRAME_X) + x as usize] = val;
     }
+
+    /// Hash of the pixel contents. Stable between runs and machines, so it
+    /// can be used to compare frames of different emulator instances. The
+    /// palette is not included.
+    pub fn digest(&self) -> u64 {
+        let mut digest = StateDigest::new();
+
+        for pixel in self.pixels {
+            digest.write_u8(pixel as u8);
+        }
+
+        digest.finish()
+    }
 }
 
 impl Default for Frame {
     fn default() -> Self {
         Self {
             pixels: [GbMonoColor::White; FRAME_X * FRAME_Y],
+            palette: DmgPalette::default(),
+            sgb: None,
         }
     }
 }
@@ -251,9 +364,16 @@ impl Default for Frame {
 pub trait GBGraphicsDrawer: Debug {
     type Err: Error + 'static;
     fn output(&mut self, frame: &Frame) -> Result<(), Self::Err>;
+
+    /// Called with the border of the Super Game Boy whenever the game
+    /// changes it, to be drawn around the frames. Does nothing by default.
+    fn border(&mut self, border: SgbBorder<'_>) -> Result<(), Self::Err> {
+        let _ = border;
+        Ok(())
+    }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub struct GbInputs {
     pub start: bool,
     pub select: bool,
@@ -265,6 +385,138 @@ pub struct GbInputs {
     pub right: bool,
 }
 
+/// Provides the buttons held by the player. The emulator polls it once per
+/// frame by default, see [crate::model::InputPolling].
 pub trait InputHandler {
-    fn get_new_inputs(&mut self) -> GbInputs;
+    /// Returns the buttons held down, or None if nothing changed since the
+    /// last poll, which keeps the previous inputs. Handlers fed by input
+    /// events can return None until the next event comes in.
+    fn poll_inputs(&mut self) -> Option<GbInputs>;
+
+    /// Called whenever the PPU finished a frame and the next one starts.
+    /// Lets handlers that work per frame, like movie playback, keep count.
+    fn frame_started(&mut self) {}
+}
+
+/// Something sitting on the CPU memory bus, like an accessory on the
+/// cartridge bus or a cheat device. It sees every byte the CPU reads
+/// (including instruction fetches) and writes, and can change or drop them.
+/// Accesses by the PPU and DMA are not seen.
+pub trait BusSnooper: Send {
+    /// Called for every CPU read, with the value read from memory. The
+    /// returned value is what the CPU gets.
+    fn read(&mut self, addr: u16, value: u8) -> u8 {
+        let _ = addr;
+        value
+    }
+
+    /// Called for every CPU write, before it reaches memory. Returns the
+    /// value to write, or None to drop the write.
+    fn write(&mut self, addr: u16, value: u8) -> Option<u8> {
+        let _ = addr;
+        Some(value)
+    }
+}
+
+/// Sees every frame the moment the PPU finishes drawing it. Unlike the
+/// [GBGraphicsDrawer], it is not called again when a frame is re-sent with
+/// [crate::Ruboy::present_frame], so it gets each frame exactly once, e.g.
+/// for recording.
+pub trait FrameObserver: Send {
+    /// Called with the finished frame, its number (see
+    /// [crate::Ruboy::frame_count]) and the cycle it finished at (see
+    /// [crate::Ruboy::cycles_elapsed])
+    fn frame(&mut self, frame: &Frame, number: u64, cycle: u64);
+}
+
+/// Looks at memory once every frame, e.g. an achievements runtime like
+/// rcheevos, which checks its conditions once per frame
+pub trait MemoryInspector: Send {
+    /// Called after the [FrameObserver] with the memory in the layout of
+    /// [crate::flatmem], and the number of the frame that just finished
+    fn frame(&mut self, memory: &dyn FlatMemory, number: u64);
+}
+
+/// Drives a rumble motor, e.g. the force feedback of a gamepad, for
+/// cartridges that have one
+pub trait RumbleHandler: Send {
+    /// Called whenever the game turns the motor on or off. Games vary the
+    /// strength of the rumble by switching it many times per frame, so
+    /// handlers may want to average over some time.
+    fn rumble(&mut self, on: bool);
+}
+
+/// A device on the other end of the link cable that this Gameboy clocks,
+/// like the Game Boy Printer. Devices that provide the clock themselves,
+/// like another Gameboy, use [crate::Ruboy::serial_external_clock] instead.
+pub trait LinkDevice: Send {
+    /// Called when a transfer finishes, with the byte the game sent and the
+    /// cycle it finished at (see [crate::Ruboy::cycles_elapsed]). Returns the
+    /// byte the device sent at the same time.
+    fn exchange(&mut self, sent: u8, cycle: u64) -> u8;
+}
+
+/// Whatever faces the infrared port of a Game Boy Color, like another
+/// Game Boy Color. Models without the port never use it.
+pub trait IrTransceiver: Send {
+    /// Called whenever the game turns its LED on or off
+    fn set_led(&mut self, on: bool);
+
+    /// Whether light reaches the sensor of this Gameboy right now
+    fn receiving(&self) -> bool;
+}
+
+/// Sees what the PPU does while it draws, e.g. for debuggers or visualizers
+/// that follow the rendering of a frame. Each event gets the LCD registers
+/// as they are at that moment. All events do nothing by default.
+pub trait PpuHooks: Send {
+    /// Called when the PPU starts line `regs.ly`, including the lines of
+    /// VBlank
+    fn scanline_start(&mut self, regs: &LcdRegisters) {
+        let _ = regs;
+    }
+
+    /// Called when the PPU switches to `mode`
+    fn mode_changed(&mut self, mode: LcdMode, regs: &LcdRegisters) {
+        let _ = (mode, regs);
+    }
+
+    /// Called with each finished frame and its number, see
+    /// [crate::Ruboy::frame_count]
+    fn frame_completed(&mut self, frame: &Frame, number: u64, regs: &LcdRegisters) {
+        let _ = (frame, number, regs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgba_bytes_use_palette() {
+        let mut frame = Frame::default();
+        frame.set_palette(DmgPalette::GRAYSCALE);
+        frame.set_pix(1, 0, GbMonoColor::Black);
+
+        let bytes = frame.to_rgba_bytes();
+
+        assert_eq!(FRAME_X * FRAME_Y * 4, bytes.len());
+        assert_eq!([255, 255, 255, 255], bytes[0..4]);
+        assert_eq!([0, 0, 0, 255], bytes[4..8]);
+    }
+
+    #[test]
+    fn rom_buffer_bounds() {
+        let mut rom = RomBuffer(vec![1, 2, 3, 4]);
+        let mut buf = [0; 2];
+
+        rom.read_into(&mut buf, 2).unwrap();
+        assert_eq!([3, 4], buf);
+
+        assert_eq!(
+            Err(RomBufferErr { addr: 3, len: 2 }),
+            rom.read_into(&mut buf, 3)
+        );
+        assert!(rom.read_into(&mut buf, usize::MAX).is_err());
+    }
 }
@@ -0,0 +1,212 @@
+//! One flat address map over all memory a game keeps its state in, for
+//! tools that watch memory from the outside, like an achievements runtime.
+//!
+//! Addresses 0x0000 to 0xFFFF are the address space as the CPU sees it,
+//! read like [Ruboy::peek]. All of the cartridge RAM follows at
+//! [CARTRIDGE_RAM_START], every bank in order, so values in banks that aren't
+//! mapped can be watched too. The space in between is where the Game Boy
+//! Color keeps its extra work RAM banks, and is never readable here. This is
+//! the layout rcheevos uses for the Game Boy, so its addresses can be passed
+//! on as they are. The layout is stable, addresses won't move in later
+//! versions.
+
+use crate::{GBAllocator, GBGraphicsDrawer, InputHandler, RomReader, Ruboy};
+
+/// Where the banks of the cartridge RAM start in the flat address map
+pub const CARTRIDGE_RAM_START: u32 = 0x16000;
+
+/// The largest cartridge RAM, 16 banks of 8KiB
+const CARTRIDGE_RAM_MAX: u32 = 0x20000;
+
+/// What a region of the flat address map holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Rom,
+    VideoRam,
+    CartridgeRam,
+    WorkRam,
+
+    /// Mirrors another region
+    Mirror,
+    Io,
+    Unused,
+}
+
+/// A range of addresses in the flat address map, see [REGIONS]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub start: u32,
+
+    /// Inclusive
+    pub end: u32,
+    pub kind: RegionKind,
+    pub description: &'static str,
+}
+
+const fn region(start: u32, end: u32, kind: RegionKind, description: &'static str) -> Region {
+    Region {
+        start,
+        end,
+        kind,
+        description,
+    }
+}
+
+/// Every region of the flat address map, in order. The cartridge RAM region
+/// has room for the largest cartridge RAM, only the part up to the size of
+/// [Ruboy::cartridge_ram] is readable.
+pub const REGIONS: &[Region] = &[
+    region(0x0000, 0x3FFF, RegionKind::Rom, "Cartridge ROM (fixed)"),
+    region(0x4000, 0x7FFF, RegionKind::Rom, "Cartridge ROM (banked)"),
+    region(0x8000, 0x9FFF, RegionKind::VideoRam, "Video RAM"),
+    region(
+        0xA000,
+        0xBFFF,
+        RegionKind::CartridgeRam,
+        "Cartridge RAM (mapped bank)",
+    ),
+    region(0xC000, 0xDFFF, RegionKind::WorkRam, "Work RAM"),
+    region(0xE000, 0xFDFF, RegionKind::Mirror, "Echo RAM"),
+    region(
+        0xFE00,
+        0xFE9F,
+        RegionKind::VideoRam,
+        "Object attribute memory",
+    ),
+    region(0xFEA0, 0xFEFF, RegionKind::Unused, "Unused"),
+    region(0xFF00, 0xFF7F, RegionKind::Io, "I/O registers"),
+    region(0xFF80, 0xFFFE, RegionKind::WorkRam, "High RAM"),
+    region(0xFFFF, 0xFFFF, RegionKind::Io, "Interrupt enable"),
+    region(
+        CARTRIDGE_RAM_START,
+        CARTRIDGE_RAM_START + CARTRIDGE_RAM_MAX - 1,
+        RegionKind::CartridgeRam,
+        "Cartridge RAM (all banks)",
+    ),
+];
+
+/// The region the address belongs to, if any
+pub fn region_of(addr: u32) -> Option<&'static Region> {
+    REGIONS.iter().find(|r| (r.start..=r.end).contains(&addr))
+}
+
+/// Memory that can be read through the flat address map
+pub trait FlatMemory {
+    /// The byte at the address, or [None] if nothing can be read there.
+    /// Reading never has side effects.
+    fn peek_flat(&self, addr: u32) -> Option<u8>;
+
+    /// Fills the buffer with the bytes starting at the address, stopping at
+    /// the first one that can't be read. Returns the amount of bytes read,
+    /// the same as the memory read callback of rcheevos.
+    fn read_flat(&self, addr: u32, buf: &mut [u8]) -> usize {
+        let mut read = 0;
+
+        for (addr, byte) in (addr..).zip(buf.iter_mut()) {
+            match self.peek_flat(addr) {
+                Some(value) => *byte = value,
+                None => break,
+            }
+
+            read += 1;
+        }
+
+        read
+    }
+}
+
+impl<A, R, V, I> FlatMemory for Ruboy<A, R, V, I>
+where
+    A: GBAllocator,
+    R: RomReader,
+    V: GBGraphicsDrawer,
+    I: InputHandler,
+{
+    fn peek_flat(&self, addr: u32) -> Option<u8> {
+        match u16::try_from(addr) {
+            Ok(addr) => self.peek(addr).ok(),
+            Err(_) => {
+                let offset = addr.checked_sub(CARTRIDGE_RAM_START)?;
+                self.cartridge_ram().get(offset as usize).copied()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::model::GbModel;
+    use crate::testutils::{blank_rom, fix_header_checksum, NullInput, NullOutput, TestRuboy};
+
+    fn new_ruboy() -> TestRuboy {
+        Ruboy::with_model(blank_rom(0x3C), NullOutput, NullInput, GbModel::Dmg).unwrap()
+    }
+
+    /// An MBC1 cartridge with 4 banks of RAM
+    fn with_ram() -> TestRuboy {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x03;
+        fix_header_checksum(&mut rom);
+
+        Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap()
+    }
+
+    #[test]
+    fn address_space_is_peeked() {
+        let mut ruboy = new_ruboy();
+        ruboy.write_memory(0xC010, &[0x12, 0x34]).unwrap();
+
+        assert_eq!(Some(0x12), ruboy.peek_flat(0xC010));
+        assert_eq!(Some(0x12), ruboy.peek_flat(0xE010));
+
+        let mut buf = [0; 2];
+        assert_eq!(2, ruboy.read_flat(0xC010, &mut buf));
+        assert_eq!([0x12, 0x34], buf);
+    }
+
+    #[test]
+    fn nothing_past_the_address_space_without_cartridge_ram() {
+        let ruboy = new_ruboy();
+
+        assert_eq!(None, ruboy.peek_flat(0x10000));
+        assert_eq!(None, ruboy.peek_flat(CARTRIDGE_RAM_START));
+
+        let mut buf = [0; 4];
+        assert_eq!(2, ruboy.read_flat(0xFFFE, &mut buf));
+    }
+
+    #[test]
+    fn every_cartridge_ram_bank_is_mapped() {
+        let mut ruboy = with_ram();
+
+        // Enable RAM, switch to RAM banking mode and write to bank 2
+        ruboy.write_memory(0x0000, &[0x0A]).unwrap();
+        ruboy.write_memory(0x6000, &[0x01]).unwrap();
+        ruboy.write_memory(0x4000, &[0x02]).unwrap();
+        ruboy.write_memory(0xA005, &[0x42]).unwrap();
+
+        ruboy.write_memory(0x4000, &[0x00]).unwrap();
+        assert_eq!(Some(0x00), ruboy.peek_flat(0xA005));
+        assert_eq!(Some(0x42), ruboy.peek_flat(CARTRIDGE_RAM_START + 0x4005));
+
+        assert_eq!(
+            None,
+            ruboy.peek_flat(CARTRIDGE_RAM_START + 4 * 0x2000),
+            "reads past the cartridge RAM"
+        );
+    }
+
+    #[test]
+    fn regions_are_ordered() {
+        for pair in REGIONS.windows(2) {
+            assert!(pair[0].end < pair[1].start);
+        }
+
+        assert_eq!(Some(RegionKind::Mirror), region_of(0xE000).map(|r| r.kind));
+        assert_eq!(None, region_of(0x10000));
+    }
+}
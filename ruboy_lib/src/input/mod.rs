@@ -1,28 +1,8 @@
 use crate::GbInputs;
 
-pub fn apply_input_to(cur_joypad_register: u8, cur_inputs: GbInputs) -> (u8, bool) {
-    let select_buttons = cur_joypad_register & 0b00100000 == 0;
-    let select_dpad = cur_joypad_register & 0b00010000 == 0;
-    let selector_nibble = cur_joypad_register & 0b11110000;
-
-    if select_buttons {
-        let new_joypad_reg_value = selector_nibble | get_input_nibble_for_buttons(cur_inputs);
-        let can_raise_interrupt =
-            cur_joypad_register & 0x0F == 0x0F && new_joypad_reg_value & 0x0F != 0x0F;
-
-        (new_joypad_reg_value, can_raise_interrupt)
-    } else if select_dpad {
-        let new_joypad_reg_value = selector_nibble | get_input_nibble_for_dpad(cur_inputs);
-        let can_raise_interrupt =
-            cur_joypad_register & 0x0F == 0x0F && new_joypad_reg_value & 0x0F != 0x0F;
-
-        (new_joypad_reg_value, can_raise_interrupt)
-    } else {
-        (selector_nibble | 0b00001111, false)
-    }
-}
-
-fn get_input_nibble_for_buttons(inputs: GbInputs) -> u8 {
+/// The lower nibble of P1 with the buttons selected. Pressed buttons read
+/// as 0.
+pub fn get_input_nibble_for_buttons(inputs: GbInputs) -> u8 {
     let start = if !inputs.start { 0b00001000 } else { 0 };
 
     let select = if !inputs.select { 0b00000100 } else { 0 };
@@ -38,7 +18,8 @@ fn get_input_nibble_for_buttons(inputs: GbInputs) -> u8 {
     res
 }
 
-fn get_input_nibble_for_dpad(inputs: GbInputs) -> u8 {
+/// The lower nibble of P1 with the d-pad selected
+pub fn get_input_nibble_for_dpad(inputs: GbInputs) -> u8 {
     let down = if !inputs.down { 0b00001000 } else { 0 };
 
     let up = if !inputs.up { 0b00000100 } else { 0 };
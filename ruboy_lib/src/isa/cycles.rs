@@ -2,7 +2,7 @@ use super::{
     ArithSrc, IncDecTarget, Instruction, Ld16Dst, Ld16Src, Ld8Dst, Ld8Src, MemLoc, PrefArithTarget,
 };
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TCycles {
     Static(u8),
     Branching { taken: u8, non_taken: u8 },
@@ -1,3 +1,4 @@
+use arrayvec::ArrayVec;
 use thiserror::Error;
 
 use crate::isa::{
@@ -5,14 +6,14 @@ use crate::isa::{
     Reg16, Reg8,
 };
 
-use super::{Bit, PrefArithTarget, RsVec};
+use super::{encoder::MAX_INSTRUCTION_LEN, Bit, PrefArithTarget, RsVec, PREFIX};
 
-#[derive(Error, Debug, Clone, Copy)]
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DecodeError {
-    /// Not enough bytes in the input
-    /// slice to properly decode the error
-    #[error("Could not read enough bytes to decode the instruction")]
-    NotEnoughBytes,
+    /// The instruction continues past the end of the input slice, at the
+    /// given address
+    #[error("Could not read enough bytes to decode the instruction: 0x{0:04X} is out of bounds")]
+    NotEnoughBytes(u16),
 }
 
 macro_rules! illegal {
@@ -347,44 +348,65 @@ const fn decode_prefixed(instr: u8) -> Instruction {
     }
 }
 
+/// The opcodes that don't map to any instruction, and lock up the CPU when
+/// executed
+pub const ILLEGAL_OPCODES: [u8; 11] = [
+    0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+];
+
+/// Memory the decoder can read instructions from. Addresses are those of the
+/// Gameboy address space, so operands of an instruction at the end of it
+/// wrap around to 0x0000, like the PC does.
 pub trait DecoderReadable {
     type Err;
-    fn read_at(&self, idx: usize) -> Result<u8, Self::Err>;
+    fn read_at(&self, addr: u16) -> Result<u8, Self::Err>;
 }
 
+/// A slice starting at address 0x0000
 impl DecoderReadable for &[u8] {
     type Err = DecodeError;
-    fn read_at(&self, idx: usize) -> Result<u8, Self::Err> {
-        self.get(idx).cloned().ok_or(DecodeError::NotEnoughBytes)
+    fn read_at(&self, addr: u16) -> Result<u8, Self::Err> {
+        self.get(addr as usize)
+            .copied()
+            .ok_or(DecodeError::NotEnoughBytes(addr))
     }
 }
 
-fn read8<T: DecoderReadable>(mem: &T, idx: u16) -> Result<u8, T::Err> {
-    mem.read_at(idx as usize)
+fn read8<T: DecoderReadable>(mem: &T, addr: u16) -> Result<u8, T::Err> {
+    mem.read_at(addr)
 }
 
-fn read16<T: DecoderReadable>(mem: &T, idx: u16) -> Result<u16, T::Err> {
-    let b1 = mem.read_at(idx as usize)?;
-    let b2 = mem.read_at((idx + 1) as usize)?;
+fn read16<T: DecoderReadable>(mem: &T, addr: u16) -> Result<u16, T::Err> {
+    let b1 = mem.read_at(addr)?;
+    let b2 = mem.read_at(addr.wrapping_add(1))?;
 
     Ok(u16::from_le_bytes([b1, b2]))
 }
 
+/// Decodes the instruction at `pc`. Never panics, whatever the memory
+/// contains: opcodes without an instruction decode as
+/// [Instruction::IllegalInstruction], and failing reads are returned.
 pub fn decode<T: DecoderReadable>(mem: &T, pc: u16) -> Result<Instruction, T::Err> {
     let opcode = read8(mem, pc)?;
 
     let instr = match opcode {
         // 0x0_
         0x00 => Instruction::Nop,
-        0x01 => Instruction::Load16(Ld16Dst::Reg(Reg16::BC), Ld16Src::Imm(read16(mem, pc + 1)?)),
+        0x01 => Instruction::Load16(
+            Ld16Dst::Reg(Reg16::BC),
+            Ld16Src::Imm(read16(mem, pc.wrapping_add(1))?),
+        ),
         0x02 => Instruction::Load8(Ld8Dst::Mem(MemLoc::Reg(Reg16::BC)), Ld8Src::Reg(Reg8::A)),
         0x03 => Instruction::Inc(IncDecTarget::Reg16(Reg16::BC)),
         0x04 => Instruction::Inc(IncDecTarget::Reg8(Reg8::B)),
         0x05 => Instruction::Dec(IncDecTarget::Reg8(Reg8::B)),
-        0x06 => Instruction::Load8(Ld8Dst::Reg(Reg8::B), Ld8Src::Imm(read8(mem, pc + 1)?)),
+        0x06 => Instruction::Load8(
+            Ld8Dst::Reg(Reg8::B),
+            Ld8Src::Imm(read8(mem, pc.wrapping_add(1))?),
+        ),
         0x07 => Instruction::RotLeftCircularA,
         0x08 => Instruction::Load16(
-            Ld16Dst::Mem(MemLoc::Imm(read16(mem, pc + 1)?)),
+            Ld16Dst::Mem(MemLoc::Imm(read16(mem, pc.wrapping_add(1))?)),
             Ld16Src::Reg(Reg16::SP),
         ),
         0x09 => Instruction::AddHL(Reg16::BC),
@@ -392,64 +414,91 @@ pub fn decode<T: DecoderReadable>(mem: &T, pc: u16) -> Result<Instruction, T::Er
         0x0B => Instruction::Dec(IncDecTarget::Reg16(Reg16::BC)),
         0x0C => Instruction::Inc(IncDecTarget::Reg8(Reg8::C)),
         0x0D => Instruction::Dec(IncDecTarget::Reg8(Reg8::C)),
-        0x0E => Instruction::Load8(Ld8Dst::Reg(Reg8::C), Ld8Src::Imm(read8(mem, pc + 1)?)),
+        0x0E => Instruction::Load8(
+            Ld8Dst::Reg(Reg8::C),
+            Ld8Src::Imm(read8(mem, pc.wrapping_add(1))?),
+        ),
         0x0F => Instruction::RotRightCircularA,
 
         // 0x1_
-        0x10 => Instruction::Stop(read8(mem, pc + 1)?),
-        0x11 => Instruction::Load16(Ld16Dst::Reg(Reg16::DE), Ld16Src::Imm(read16(mem, pc + 1)?)),
+        0x10 => Instruction::Stop(read8(mem, pc.wrapping_add(1))?),
+        0x11 => Instruction::Load16(
+            Ld16Dst::Reg(Reg16::DE),
+            Ld16Src::Imm(read16(mem, pc.wrapping_add(1))?),
+        ),
         0x12 => Instruction::Load8(Ld8Dst::Mem(MemLoc::Reg(Reg16::DE)), Ld8Src::Reg(Reg8::A)),
         0x13 => Instruction::Inc(IncDecTarget::Reg16(Reg16::DE)),
         0x14 => Instruction::Inc(IncDecTarget::Reg8(Reg8::D)),
         0x15 => Instruction::Dec(IncDecTarget::Reg8(Reg8::D)),
-        0x16 => Instruction::Load8(Ld8Dst::Reg(Reg8::D), Ld8Src::Imm(read8(mem, pc + 1)?)),
+        0x16 => Instruction::Load8(
+            Ld8Dst::Reg(Reg8::D),
+            Ld8Src::Imm(read8(mem, pc.wrapping_add(1))?),
+        ),
         0x17 => Instruction::RotLeftA,
-        0x18 => Instruction::JumpRel(read8(mem, pc + 1)? as i8),
+        0x18 => Instruction::JumpRel(read8(mem, pc.wrapping_add(1))? as i8),
         0x19 => Instruction::AddHL(Reg16::DE),
         0x1A => Instruction::Load8(Ld8Dst::Reg(Reg8::A), Ld8Src::Mem(MemLoc::Reg(Reg16::DE))),
         0x1B => Instruction::Dec(IncDecTarget::Reg16(Reg16::DE)),
         0x1C => Instruction::Inc(IncDecTarget::Reg8(Reg8::E)),
         0x1D => Instruction::Dec(IncDecTarget::Reg8(Reg8::E)),
-        0x1E => Instruction::Load8(Ld8Dst::Reg(Reg8::E), Ld8Src::Imm(read8(mem, pc + 1)?)),
+        0x1E => Instruction::Load8(
+            Ld8Dst::Reg(Reg8::E),
+            Ld8Src::Imm(read8(mem, pc.wrapping_add(1))?),
+        ),
         0x1F => Instruction::RotRightA,
 
         // 0x2_
-        0x20 => Instruction::JumpRelIf(read8(mem, pc + 1)? as i8, Condition::NotZero),
-        0x21 => Instruction::Load16(Ld16Dst::Reg(Reg16::HL), Ld16Src::Imm(read16(mem, pc + 1)?)),
+        0x20 => Instruction::JumpRelIf(read8(mem, pc.wrapping_add(1))? as i8, Condition::NotZero),
+        0x21 => Instruction::Load16(
+            Ld16Dst::Reg(Reg16::HL),
+            Ld16Src::Imm(read16(mem, pc.wrapping_add(1))?),
+        ),
         0x22 => Instruction::LoadAtoHLI,
         0x23 => Instruction::Inc(IncDecTarget::Reg16(Reg16::HL)),
         0x24 => Instruction::Inc(IncDecTarget::Reg8(Reg8::H)),
         0x25 => Instruction::Dec(IncDecTarget::Reg8(Reg8::H)),
-        0x26 => Instruction::Load8(Ld8Dst::Reg(Reg8::H), Ld8Src::Imm(read8(mem, pc + 1)?)),
+        0x26 => Instruction::Load8(
+            Ld8Dst::Reg(Reg8::H),
+            Ld8Src::Imm(read8(mem, pc.wrapping_add(1))?),
+        ),
         0x27 => Instruction::DecimalAdjust,
-        0x28 => Instruction::JumpRelIf(read8(mem, pc + 1)? as i8, Condition::Zero),
+        0x28 => Instruction::JumpRelIf(read8(mem, pc.wrapping_add(1))? as i8, Condition::Zero),
         0x29 => Instruction::AddHL(Reg16::HL),
         0x2A => Instruction::LoadHLItoA,
         0x2B => Instruction::Dec(IncDecTarget::Reg16(Reg16::HL)),
         0x2C => Instruction::Inc(IncDecTarget::Reg8(Reg8::L)),
         0x2D => Instruction::Dec(IncDecTarget::Reg8(Reg8::L)),
-        0x2E => Instruction::Load8(Ld8Dst::Reg(Reg8::L), Ld8Src::Imm(read8(mem, pc + 1)?)),
+        0x2E => Instruction::Load8(
+            Ld8Dst::Reg(Reg8::L),
+            Ld8Src::Imm(read8(mem, pc.wrapping_add(1))?),
+        ),
         0x2F => Instruction::ComplementAccumulator,
 
         // 0x3_
-        0x30 => Instruction::JumpRelIf(read8(mem, pc + 1)? as i8, Condition::NotCarry),
-        0x31 => Instruction::Load16(Ld16Dst::Reg(Reg16::SP), Ld16Src::Imm(read16(mem, pc + 1)?)),
+        0x30 => Instruction::JumpRelIf(read8(mem, pc.wrapping_add(1))? as i8, Condition::NotCarry),
+        0x31 => Instruction::Load16(
+            Ld16Dst::Reg(Reg16::SP),
+            Ld16Src::Imm(read16(mem, pc.wrapping_add(1))?),
+        ),
         0x32 => Instruction::LoadAtoHLD,
         0x33 => Instruction::Inc(IncDecTarget::Reg16(Reg16::SP)),
         0x34 => Instruction::Inc(IncDecTarget::MemHL),
         0x35 => Instruction::Dec(IncDecTarget::MemHL),
         0x36 => Instruction::Load8(
             Ld8Dst::Mem(MemLoc::Reg(Reg16::HL)),
-            Ld8Src::Imm(read8(mem, pc + 1)?),
+            Ld8Src::Imm(read8(mem, pc.wrapping_add(1))?),
         ),
         0x37 => Instruction::SetCarryFlag,
-        0x38 => Instruction::JumpRelIf(read8(mem, pc + 1)? as i8, Condition::Carry),
+        0x38 => Instruction::JumpRelIf(read8(mem, pc.wrapping_add(1))? as i8, Condition::Carry),
         0x39 => Instruction::AddHL(Reg16::SP),
         0x3A => Instruction::LoadHLDtoA,
         0x3B => Instruction::Dec(IncDecTarget::Reg16(Reg16::SP)),
         0x3C => Instruction::Inc(IncDecTarget::Reg8(Reg8::A)),
         0x3D => Instruction::Dec(IncDecTarget::Reg8(Reg8::A)),
-        0x3E => Instruction::Load8(Ld8Dst::Reg(Reg8::A), Ld8Src::Imm(read8(mem, pc + 1)?)),
+        0x3E => Instruction::Load8(
+            Ld8Dst::Reg(Reg8::A),
+            Ld8Src::Imm(read8(mem, pc.wrapping_add(1))?),
+        ),
         0x3F => Instruction::ComplementCarry,
 
         // 0x4_
@@ -599,42 +648,42 @@ pub fn decode<T: DecoderReadable>(mem: &T, pc: u16) -> Result<Instruction, T::Er
         // 0xC_
         0xC0 => Instruction::RetIf(Condition::NotZero),
         0xC1 => Instruction::Pop(Reg16::BC),
-        0xC2 => Instruction::JumpIf(read16(mem, pc + 1)?, Condition::NotZero),
-        0xC3 => Instruction::Jump(read16(mem, pc + 1)?),
-        0xC4 => Instruction::CallIf(read16(mem, pc + 1)?, Condition::NotZero),
+        0xC2 => Instruction::JumpIf(read16(mem, pc.wrapping_add(1))?, Condition::NotZero),
+        0xC3 => Instruction::Jump(read16(mem, pc.wrapping_add(1))?),
+        0xC4 => Instruction::CallIf(read16(mem, pc.wrapping_add(1))?, Condition::NotZero),
         0xC5 => Instruction::Push(Reg16::BC),
-        0xC6 => Instruction::Add(ArithSrc::Imm(read8(mem, pc + 1)?)),
+        0xC6 => Instruction::Add(ArithSrc::Imm(read8(mem, pc.wrapping_add(1))?)),
         0xC7 => Instruction::Rst(RsVec::Rst0),
         0xC8 => Instruction::RetIf(Condition::Zero),
         0xC9 => Instruction::Ret,
-        0xCA => Instruction::JumpIf(read16(mem, pc + 1)?, Condition::Zero),
-        0xCB => decode_prefixed(read8(mem, pc + 1)?), // Special instruction, maps to another instruction set
-        0xCC => Instruction::CallIf(read16(mem, pc + 1)?, Condition::Zero),
-        0xCD => Instruction::Call(read16(mem, pc + 1)?),
-        0xCE => Instruction::AddCarry(ArithSrc::Imm(read8(mem, pc + 1)?)),
+        0xCA => Instruction::JumpIf(read16(mem, pc.wrapping_add(1))?, Condition::Zero),
+        0xCB => decode_prefixed(read8(mem, pc.wrapping_add(1))?), // Special instruction, maps to another instruction set
+        0xCC => Instruction::CallIf(read16(mem, pc.wrapping_add(1))?, Condition::Zero),
+        0xCD => Instruction::Call(read16(mem, pc.wrapping_add(1))?),
+        0xCE => Instruction::AddCarry(ArithSrc::Imm(read8(mem, pc.wrapping_add(1))?)),
         0xCF => Instruction::Rst(RsVec::Rst1),
 
         // 0xD_
         0xD0 => Instruction::RetIf(Condition::NotCarry),
         0xD1 => Instruction::Pop(Reg16::DE),
-        0xD2 => Instruction::JumpIf(read16(mem, pc + 1)?, Condition::NotCarry),
+        0xD2 => Instruction::JumpIf(read16(mem, pc.wrapping_add(1))?, Condition::NotCarry),
         0xD3 => illegal!(0xD3),
-        0xD4 => Instruction::CallIf(read16(mem, pc + 1)?, Condition::NotCarry),
+        0xD4 => Instruction::CallIf(read16(mem, pc.wrapping_add(1))?, Condition::NotCarry),
         0xD5 => Instruction::Push(Reg16::DE),
-        0xD6 => Instruction::Sub(ArithSrc::Imm(read8(mem, pc + 1)?)),
+        0xD6 => Instruction::Sub(ArithSrc::Imm(read8(mem, pc.wrapping_add(1))?)),
         0xD7 => Instruction::Rst(RsVec::Rst2),
         0xD8 => Instruction::RetIf(Condition::Carry),
         0xD9 => Instruction::Reti,
-        0xDA => Instruction::JumpIf(read16(mem, pc + 1)?, Condition::Carry),
+        0xDA => Instruction::JumpIf(read16(mem, pc.wrapping_add(1))?, Condition::Carry),
         0xDB => illegal!(0xDB),
-        0xDC => Instruction::CallIf(read16(mem, pc + 1)?, Condition::Carry),
+        0xDC => Instruction::CallIf(read16(mem, pc.wrapping_add(1))?, Condition::Carry),
         0xDD => illegal!(0xDD),
-        0xDE => Instruction::SubCarry(ArithSrc::Imm(read8(mem, pc + 1)?)),
+        0xDE => Instruction::SubCarry(ArithSrc::Imm(read8(mem, pc.wrapping_add(1))?)),
         0xDF => Instruction::Rst(RsVec::Rst3),
 
         // 0xE_
         0xE0 => Instruction::Load8(
-            Ld8Dst::Mem(MemLoc::HighMemImm(read8(mem, pc + 1)?)),
+            Ld8Dst::Mem(MemLoc::HighMemImm(read8(mem, pc.wrapping_add(1))?)),
             Ld8Src::Reg(Reg8::A),
         ),
         0xE1 => Instruction::Pop(Reg16::HL),
@@ -645,24 +694,24 @@ pub fn decode<T: DecoderReadable>(mem: &T, pc: u16) -> Result<Instruction, T::Er
         0xE3 => illegal!(0xE3),
         0xE4 => illegal!(0xE4),
         0xE5 => Instruction::Push(Reg16::HL),
-        0xE6 => Instruction::And(ArithSrc::Imm(read8(mem, pc + 1)?)),
+        0xE6 => Instruction::And(ArithSrc::Imm(read8(mem, pc.wrapping_add(1))?)),
         0xE7 => Instruction::Rst(RsVec::Rst4),
-        0xE8 => Instruction::AddSP(read8(mem, pc + 1)? as i8),
+        0xE8 => Instruction::AddSP(read8(mem, pc.wrapping_add(1))? as i8),
         0xE9 => Instruction::JumpHL,
         0xEA => Instruction::Load8(
-            Ld8Dst::Mem(MemLoc::Imm(read16(mem, pc + 1)?)),
+            Ld8Dst::Mem(MemLoc::Imm(read16(mem, pc.wrapping_add(1))?)),
             Ld8Src::Reg(Reg8::A),
         ),
         0xEB => illegal!(0xEB),
         0xEC => illegal!(0xEC),
         0xED => illegal!(0xED),
-        0xEE => Instruction::Xor(ArithSrc::Imm(read8(mem, pc + 1)?)),
+        0xEE => Instruction::Xor(ArithSrc::Imm(read8(mem, pc.wrapping_add(1))?)),
         0xEF => Instruction::Rst(RsVec::Rst5),
 
         // 0xF_
         0xF0 => Instruction::Load8(
             Ld8Dst::Reg(Reg8::A),
-            Ld8Src::Mem(MemLoc::HighMemImm(read8(mem, pc + 1)?)),
+            Ld8Src::Mem(MemLoc::HighMemImm(read8(mem, pc.wrapping_add(1))?)),
         ),
         0xF1 => Instruction::Pop(Reg16::AF),
         0xF2 => Instruction::Load8(
@@ -672,24 +721,82 @@ pub fn decode<T: DecoderReadable>(mem: &T, pc: u16) -> Result<Instruction, T::Er
         0xF3 => Instruction::DI,
         0xF4 => illegal!(0xF4),
         0xF5 => Instruction::Push(Reg16::AF),
-        0xF6 => Instruction::Or(ArithSrc::Imm(read8(mem, pc + 1)?)),
+        0xF6 => Instruction::Or(ArithSrc::Imm(read8(mem, pc.wrapping_add(1))?)),
         0xF7 => Instruction::Rst(RsVec::Rst6),
-        0xF8 => Instruction::LoadSPi8toHL(read8(mem, pc + 1)? as i8),
+        0xF8 => Instruction::LoadSPi8toHL(read8(mem, pc.wrapping_add(1))? as i8),
         0xF9 => Instruction::Load16(Ld16Dst::Reg(Reg16::SP), Ld16Src::Reg(Reg16::HL)),
         0xFA => Instruction::Load8(
             Ld8Dst::Reg(Reg8::A),
-            Ld8Src::Mem(MemLoc::Imm(read16(mem, pc + 1)?)),
+            Ld8Src::Mem(MemLoc::Imm(read16(mem, pc.wrapping_add(1))?)),
         ),
         0xFB => Instruction::EI,
         0xFC => illegal!(0xFC),
         0xFD => illegal!(0xFD),
-        0xFE => Instruction::Cmp(ArithSrc::Imm(read8(mem, pc + 1)?)),
+        0xFE => Instruction::Cmp(ArithSrc::Imm(read8(mem, pc.wrapping_add(1))?)),
         0xFF => Instruction::Rst(RsVec::Rst7),
     };
 
     Ok(instr)
 }
 
+/// Where the immediate operand is in the bytes of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OperandSpan {
+    /// Bytes from the start of the instruction
+    pub offset: u8,
+    pub len: u8,
+}
+
+impl OperandSpan {
+    /// The operand, as a range of the instruction bytes
+    pub const fn range(&self) -> core::ops::Range<usize> {
+        self.offset as usize..(self.offset + self.len) as usize
+    }
+}
+
+/// An instruction, together with the bytes it was decoded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpannedInstruction {
+    pub instr: Instruction,
+
+    /// Every byte of the instruction, starting with the opcode
+    pub bytes: ArrayVec<u8, MAX_INSTRUCTION_LEN>,
+
+    /// The immediate operand, if the instruction has one. No instruction has
+    /// more than one.
+    pub operand: Option<OperandSpan>,
+}
+
+/// Like [decode], but also returns the bytes of the instruction and where
+/// its immediate operand is in them. The bytes are read again after
+/// decoding, so `mem` should not change when read.
+pub fn decode_with_span<T: DecoderReadable>(
+    mem: &T,
+    pc: u16,
+) -> Result<SpannedInstruction, T::Err> {
+    let instr = decode(mem, pc)?;
+    let mut bytes = ArrayVec::new();
+
+    for offset in 0..instr.len() {
+        bytes.push(read8(mem, pc.wrapping_add(offset as u16))?);
+    }
+
+    // Prefixed instructions never have an immediate, but the prefix still
+    // comes before the opcode
+    let opcode_len = if bytes[0] == PREFIX { 2 } else { 1 };
+
+    let operand = (instr.len() > opcode_len).then(|| OperandSpan {
+        offset: opcode_len,
+        len: instr.len() - opcode_len,
+    });
+
+    Ok(SpannedInstruction {
+        instr,
+        bytes,
+        operand,
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use crate::isa::testutils;
@@ -710,6 +817,42 @@ mod tests {
         }
     }
 
+    #[test]
+    fn operands_wrap_around() {
+        let mut mem = vec![0u8; 0x10000];
+        mem[0xFFFF] = 0xC3; // JP a16
+        mem[0x0000] = 0x34;
+        mem[0x0001] = 0x12;
+
+        assert_eq!(
+            Instruction::Jump(0x1234),
+            decode(&mem.as_slice(), 0xFFFF).unwrap()
+        );
+
+        mem[0xFFFE] = 0xCB;
+        mem[0xFFFF] = 0x37;
+        assert_eq!(
+            Instruction::Swap(PrefArithTarget::Reg(Reg8::A)),
+            decode(&mem.as_slice(), 0xFFFE).unwrap()
+        );
+    }
+
+    #[test]
+    fn truncated_instructions() {
+        assert_eq!(
+            Err(DecodeError::NotEnoughBytes(0)),
+            decode(&[].as_slice(), 0)
+        );
+        assert_eq!(
+            Err(DecodeError::NotEnoughBytes(3)),
+            decode(&[0x00, 0x01, 0x34].as_slice(), 1)
+        );
+        assert_eq!(
+            Err(DecodeError::NotEnoughBytes(0xFFFF)),
+            decode(&[0xCB].as_slice(), 0xFFFF)
+        );
+    }
+
     #[test]
     fn decode_illegals() {
         for opcode in testutils::illegal_opcodes() {
@@ -725,4 +868,60 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn spans() {
+        let jump = decode_with_span(&[0xC3, 0x34, 0x12].as_slice(), 0).unwrap();
+        assert_eq!(Instruction::Jump(0x1234), jump.instr);
+        assert_eq!([0xC3, 0x34, 0x12], jump.bytes.as_slice());
+        assert_eq!(Some(OperandSpan { offset: 1, len: 2 }), jump.operand);
+
+        let swap = decode_with_span(&[0xCB, 0x37, 0xFF].as_slice(), 0).unwrap();
+        assert_eq!([0xCB, 0x37], swap.bytes.as_slice());
+        assert_eq!(None, swap.operand);
+
+        let illegal = decode_with_span(&[0xD3, 0xFF].as_slice(), 0).unwrap();
+        assert_eq!([0xD3], illegal.bytes.as_slice());
+        assert_eq!(None, illegal.operand);
+    }
+
+    #[test]
+    fn spans_wrap_around() {
+        let mut mem = vec![0u8; 0x10000];
+        mem[0xFFFF] = 0x3E; // LD A, n8
+        mem[0x0000] = 0x42;
+
+        let load = decode_with_span(&mem.as_slice(), 0xFFFF).unwrap();
+        assert_eq!([0x3E, 0x42], load.bytes.as_slice());
+        assert_eq!(Some(OperandSpan { offset: 1, len: 1 }), load.operand);
+    }
+
+    #[test]
+    fn operand_spans_cover_immediates() {
+        for opcode in testutils::legal_instrs().filter(|opcode| opcode[2] == 0xFF) {
+            let spanned = decode_with_span(&opcode.as_slice(), 0x0).unwrap();
+            let len = spanned.bytes.len();
+
+            assert_eq!(spanned.instr.len() as usize, len);
+
+            // Patching the operand bytes must patch the immediate value
+            let Some(operand) = spanned.operand else {
+                continue;
+            };
+
+            assert_eq!(len, operand.range().end, "{:?}", spanned.instr);
+
+            let mut patched = spanned.bytes.clone();
+            patched[operand.range()]
+                .iter_mut()
+                .for_each(|byte| *byte = !*byte);
+
+            assert_ne!(
+                spanned.instr,
+                decode(&patched.as_slice(), 0x0).unwrap(),
+                "{:?}",
+                spanned.instr
+            );
+        }
+    }
 }
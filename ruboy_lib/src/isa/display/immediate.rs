@@ -1,3 +1,5 @@
+use alloc::format;
+use alloc::string::String;
 use core::fmt::{Display, LowerHex, UpperHex};
 
 use super::ImmediateFormat;
@@ -25,7 +27,7 @@ impl DisplayableImmediate {
                 let abs = (*x as i16).abs(); // Upcast to prevent overflow
                 let abs_fmt = format_immediate(fmt, abs);
 
-                if abs.is_negative() {
+                if x.is_negative() {
                     format!("-{}", abs_fmt)
                 } else {
                     abs_fmt
@@ -1,3 +1,6 @@
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
 use immediate::DisplayableImmediate;
 use operand::{optype::DisplayableOperandType, DisplayableOperand, MemType};
 
@@ -6,6 +9,9 @@ use super::{Bit, Condition, Instruction, PrefArithTarget};
 mod immediate;
 mod operand;
 mod reg;
+mod symbols;
+
+pub use symbols::{HardwareRegisters, SymbolResolver, Symbols};
 
 #[derive(Debug, Clone)]
 enum DisplayableOperands {
@@ -43,6 +49,10 @@ pub struct FormatOpts {
     pub hlid_as_signs: bool,
     pub imm_format: ImmediateFormat,
     pub operand_order: OperandOrder,
+
+    /// Names for addresses in jumps, calls and memory operands. Addresses
+    /// without a name are printed as numbers.
+    pub symbols: Option<Symbols>,
 }
 
 impl FormatOpts {
@@ -55,8 +65,18 @@ impl FormatOpts {
                 prefix: "$".to_owned(),
             },
             operand_order: OperandOrder::DstFirst,
+            symbols: None,
         }
     }
+
+    pub fn with_symbols(mut self, resolver: impl SymbolResolver + 'static) -> Self {
+        self.symbols = Some(Symbols::new(resolver));
+        self
+    }
+
+    fn symbol(&self, addr: u16) -> Option<String> {
+        self.symbols.as_ref()?.resolve(addr)
+    }
 }
 
 impl Default for FormatOpts {
@@ -98,9 +118,21 @@ impl DisplayableInstruction {
     }
 
     pub fn with_format(&self, fmt: &FormatOpts) -> String {
+        // A named high RAM address no longer shows the 0xFF00 base, which
+        // rgbds wants spelled out as ldh instead
+        let mnemonic = match self.operands {
+            DisplayableOperands::Dual { src, dst }
+                if self.mnemonic == "ld"
+                    && (src.named_high_mem(fmt) || dst.named_high_mem(fmt)) =>
+            {
+                "ldh"
+            }
+            _ => self.mnemonic,
+        };
+
         let fmt_mnemonic = match fmt.mnemonic_case {
-            Case::Upper => self.mnemonic.to_uppercase(),
-            Case::Lower => self.mnemonic.to_lowercase(),
+            Case::Upper => mnemonic.to_uppercase(),
+            Case::Lower => mnemonic.to_lowercase(),
         };
 
         match self.operands {
@@ -324,27 +356,33 @@ impl From<Instruction> for DisplayableInstruction {
             ),
             Instruction::LoadSPi8toHL(offset) => DisplayableInstruction::from_dual(
                 "ld",
-                DisplayableOperand::from("hl"),
                 DisplayableOperand {
                     memory: MemType::None,
                     operand: DisplayableOperandType::SpOffset(DisplayableImmediate::I8(offset)),
                 },
+                DisplayableOperand::from("hl"),
             ),
             Instruction::Jump(tgt) => {
-                DisplayableInstruction::from_single("jp", DisplayableOperand::from(tgt))
+                DisplayableInstruction::from_single("jp", DisplayableOperand::addr(tgt))
             }
-            Instruction::JumpRel(tgt) => {
-                DisplayableInstruction::from_single("jr", DisplayableOperand::from(tgt))
+            Instruction::JumpRel(offset) => {
+                DisplayableInstruction::from_single("jr", DisplayableOperand::jump_offset(offset))
             }
             Instruction::JumpHL => {
                 DisplayableInstruction::from_single("jp", DisplayableOperand::from("hl"))
             }
-            Instruction::JumpIf(tgt, cond) => to_display_cond_with_tgt(cond, "jp", tgt),
-            Instruction::JumpRelIf(tgt, cond) => to_display_cond_with_tgt(cond, "jr", tgt),
+            Instruction::JumpIf(tgt, cond) => {
+                to_display_cond_with_tgt(cond, "jp", DisplayableOperand::addr(tgt))
+            }
+            Instruction::JumpRelIf(offset, cond) => {
+                to_display_cond_with_tgt(cond, "jr", DisplayableOperand::jump_offset(offset))
+            }
             Instruction::Call(tgt) => {
-                DisplayableInstruction::from_single("call", DisplayableOperand::from(tgt))
+                DisplayableInstruction::from_single("call", DisplayableOperand::addr(tgt))
+            }
+            Instruction::CallIf(tgt, cond) => {
+                to_display_cond_with_tgt(cond, "call", DisplayableOperand::addr(tgt))
             }
-            Instruction::CallIf(tgt, cond) => to_display_cond_with_tgt(cond, "call", tgt),
             Instruction::Ret => DisplayableInstruction::from_none("ret"),
             Instruction::Reti => DisplayableInstruction::from_none("reti"),
             Instruction::RetIf(cond) => to_display_cond(cond, "ret"),
@@ -1,3 +1,6 @@
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
 use optype::DisplayableOperandType;
 
 use crate::isa::{
@@ -22,14 +25,59 @@ pub struct DisplayableOperand {
 }
 
 impl DisplayableOperand {
+    pub const fn addr(addr: u16) -> Self {
+        Self {
+            memory: MemType::None,
+            operand: DisplayableOperandType::Addr(addr),
+        }
+    }
+
+    pub const fn jump_offset(offset: i8) -> Self {
+        Self {
+            memory: MemType::None,
+            operand: DisplayableOperandType::JumpOffset(offset),
+        }
+    }
+
+    /// Whether this is a high RAM address that the symbols have a name for
+    pub fn named_high_mem(&self, fmt: &FormatOpts) -> bool {
+        match (self.memory, self.operand) {
+            (MemType::HighMem, DisplayableOperandType::Addr(addr)) => fmt.symbol(addr).is_some(),
+            _ => false,
+        }
+    }
+
     pub fn with_format(&self, fmt: &FormatOpts) -> String {
+        if let DisplayableOperandType::Addr(addr) = self.operand {
+            if let Some(name) = fmt.symbol(addr) {
+                return match self.memory {
+                    MemType::None => name,
+                    MemType::Normal | MemType::HighMem => format!("[{}]", name),
+                };
+            }
+        }
+
         let op_fmt = match self.operand {
             DisplayableOperandType::Reg(reg) => reg.with_format(fmt).to_owned(),
             DisplayableOperandType::Imm(imm) => imm.with_format(&fmt.imm_format),
+            DisplayableOperandType::Addr(addr) => match self.memory {
+                MemType::HighMem => DisplayableImmediate::U8(addr as u8),
+                _ => DisplayableImmediate::U16(addr),
+            }
+            .with_format(&fmt.imm_format),
             DisplayableOperandType::SpOffset(imm) => {
                 let sp = DisplayableReg::SP.with_format(fmt);
                 format!("{} + {}", sp, imm.with_format(&fmt.imm_format))
             }
+            DisplayableOperandType::JumpOffset(offset) => {
+                // The offset counts from the end of the two byte jump, `@`
+                // is its start, as assemblers read it
+                let from_start = offset as i16 + 2;
+                let sign = if from_start < 0 { '-' } else { '+' };
+                let abs = DisplayableImmediate::U8(from_start.unsigned_abs() as u8);
+
+                format!("@ {} {}", sign, abs.with_format(&fmt.imm_format))
+            }
             DisplayableOperandType::Extension(prefmt) => match fmt.mnemonic_case {
                 Case::Upper => prefmt.to_uppercase(),
                 Case::Lower => prefmt.to_lowercase(),
@@ -79,11 +127,11 @@ impl From<MemLoc> for DisplayableOperand {
             },
             MemLoc::HighMemImm(imm) => DisplayableOperand {
                 memory: MemType::HighMem,
-                operand: imm.into(),
+                operand: DisplayableOperandType::Addr(0xFF00 | imm as u16),
             },
             MemLoc::Imm(imm) => DisplayableOperand {
                 memory: MemType::Normal,
-                operand: imm.into(),
+                operand: DisplayableOperandType::Addr(imm),
             },
         }
     }
@@ -7,7 +7,11 @@ use crate::isa::{
 pub enum DisplayableOperandType {
     Reg(DisplayableReg),
     Imm(DisplayableImmediate),
+    /// An absolute address, which can be printed as a symbol
+    Addr(u16),
     SpOffset(DisplayableImmediate),
+    /// The offset of a relative jump
+    JumpOffset(i8),
     Extension(&'static str),
 }
 
@@ -0,0 +1,162 @@
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use core::fmt::Debug;
+
+/// Looks up a name for an address, to print instead of the number
+pub trait SymbolResolver {
+    fn resolve(&self, addr: u16) -> Option<String>;
+}
+
+impl<F> SymbolResolver for F
+where
+    F: Fn(u16) -> Option<String>,
+{
+    fn resolve(&self, addr: u16) -> Option<String> {
+        self(addr)
+    }
+}
+
+/// A symbol resolver that can be shared between format options
+#[derive(Clone)]
+pub struct Symbols(Rc<dyn SymbolResolver>);
+
+impl Symbols {
+    pub fn new(resolver: impl SymbolResolver + 'static) -> Self {
+        Self(Rc::new(resolver))
+    }
+
+    pub fn resolve(&self, addr: u16) -> Option<String> {
+        self.0.resolve(addr)
+    }
+}
+
+impl Debug for Symbols {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("Symbols(..)")
+    }
+}
+
+/// Resolves the I/O registers to the names `hardware.inc` gives them
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HardwareRegisters;
+
+impl HardwareRegisters {
+    pub fn name(addr: u16) -> Option<&'static str> {
+        let name = match addr {
+            0xFF00 => "rP1",
+            0xFF01 => "rSB",
+            0xFF02 => "rSC",
+            0xFF04 => "rDIV",
+            0xFF05 => "rTIMA",
+            0xFF06 => "rTMA",
+            0xFF07 => "rTAC",
+            0xFF0F => "rIF",
+            0xFF10 => "rNR10",
+            0xFF11 => "rNR11",
+            0xFF12 => "rNR12",
+            0xFF13 => "rNR13",
+            0xFF14 => "rNR14",
+            0xFF16 => "rNR21",
+            0xFF17 => "rNR22",
+            0xFF18 => "rNR23",
+            0xFF19 => "rNR24",
+            0xFF1A => "rNR30",
+            0xFF1B => "rNR31",
+            0xFF1C => "rNR32",
+            0xFF1D => "rNR33",
+            0xFF1E => "rNR34",
+            0xFF20 => "rNR41",
+            0xFF21 => "rNR42",
+            0xFF22 => "rNR43",
+            0xFF23 => "rNR44",
+            0xFF24 => "rNR50",
+            0xFF25 => "rNR51",
+            0xFF26 => "rNR52",
+            0xFF40 => "rLCDC",
+            0xFF41 => "rSTAT",
+            0xFF42 => "rSCY",
+            0xFF43 => "rSCX",
+            0xFF44 => "rLY",
+            0xFF45 => "rLYC",
+            0xFF46 => "rDMA",
+            0xFF47 => "rBGP",
+            0xFF48 => "rOBP0",
+            0xFF49 => "rOBP1",
+            0xFF4A => "rWY",
+            0xFF4B => "rWX",
+            0xFF4D => "rKEY1",
+            0xFF4F => "rVBK",
+            0xFF51 => "rHDMA1",
+            0xFF52 => "rHDMA2",
+            0xFF53 => "rHDMA3",
+            0xFF54 => "rHDMA4",
+            0xFF55 => "rHDMA5",
+            0xFF56 => "rRP",
+            0xFF68 => "rBCPS",
+            0xFF69 => "rBCPD",
+            0xFF6A => "rOCPS",
+            0xFF6B => "rOCPD",
+            0xFF70 => "rSVBK",
+            0xFFFF => "rIE",
+            _ => return None,
+        };
+
+        Some(name)
+    }
+
+    /// The address of a register name, the reverse of [`HardwareRegisters::name`]
+    pub fn addr(name: &str) -> Option<u16> {
+        (0xFF00..=0xFFFF).find(|addr| Self::name(*addr) == Some(name))
+    }
+}
+
+impl SymbolResolver for HardwareRegisters {
+    fn resolve(&self, addr: u16) -> Option<String> {
+        Self::name(addr).map(ToString::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::{String, ToString};
+
+    use crate::isa::{
+        display::{DisplayableInstruction, FormatOpts},
+        Instruction, Ld8Dst, Ld8Src, MemLoc, Reg8,
+    };
+
+    use super::{HardwareRegisters, SymbolResolver};
+
+    fn format(instr: Instruction) -> String {
+        let opts = FormatOpts::rgdbs().with_symbols(|addr| match addr {
+            0x0150 => Some("InitSound".to_string()),
+            _ => HardwareRegisters.resolve(addr),
+        });
+
+        DisplayableInstruction::from(instr).with_format(&opts)
+    }
+
+    #[test]
+    fn resolves_targets() {
+        assert_eq!(format(Instruction::Call(0x0150)), "call InitSound");
+        assert_eq!(format(Instruction::Jump(0x0151)), "jp $151");
+    }
+
+    #[test]
+    fn resolves_high_mem() {
+        let store = Instruction::Load8(Ld8Dst::Mem(MemLoc::HighMemImm(0x40)), Ld8Src::Reg(Reg8::A));
+        let load = Instruction::Load8(Ld8Dst::Reg(Reg8::A), Ld8Src::Mem(MemLoc::Imm(0xFF44)));
+        let unnamed =
+            Instruction::Load8(Ld8Dst::Mem(MemLoc::HighMemImm(0x80)), Ld8Src::Reg(Reg8::A));
+
+        assert_eq!(format(store), "ldh [rLCDC], a");
+        assert_eq!(format(load), "ld a, [rLY]");
+        assert_eq!(format(unnamed), "ld [$FF00 + $80], a");
+    }
+
+    #[test]
+    fn register_names_round_trip() {
+        assert_eq!(HardwareRegisters::addr("rIE"), Some(0xFFFF));
+        assert_eq!(HardwareRegisters::addr("rFOO"), None);
+    }
+}
@@ -0,0 +1,312 @@
+//! Turns instructions back into their bytes, the inverse of
+//! [super::decoder::decode]
+
+use arrayvec::ArrayVec;
+use thiserror::Error;
+
+use super::{
+    decoder::ILLEGAL_OPCODES, ArithSrc, Condition, IncDecTarget, Instruction, Ld16Dst, Ld16Src,
+    Ld8Dst, Ld8Src, MemLoc, PrefArithTarget, Reg16, Reg8,
+};
+
+/// The longest instruction, in bytes
+pub const MAX_INSTRUCTION_LEN: usize = 3;
+
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The instruction can be represented, but no opcode exists for it,
+    /// like a load into F
+    #[error("No encoding exists for instruction {0:?}")]
+    NoEncoding(Instruction),
+
+    #[error("Buffer of {len} bytes is too small for an instruction of {needed} bytes")]
+    BufferTooSmall { needed: usize, len: usize },
+}
+
+/// Index of an 8 bit operand in the opcode, with 6 being (HL)
+const fn reg8(reg: Reg8) -> Option<u8> {
+    match reg {
+        Reg8::B => Some(0),
+        Reg8::C => Some(1),
+        Reg8::D => Some(2),
+        Reg8::E => Some(3),
+        Reg8::H => Some(4),
+        Reg8::L => Some(5),
+        Reg8::A => Some(7),
+        Reg8::F => None,
+    }
+}
+
+const fn pref_target(target: PrefArithTarget) -> Option<u8> {
+    match target {
+        PrefArithTarget::Reg(reg) => reg8(reg),
+        PrefArithTarget::MemHL => Some(6),
+    }
+}
+
+/// Index of a 16 bit register operand of loads and arithmetic
+const fn reg16(reg: Reg16) -> Option<u8> {
+    match reg {
+        Reg16::BC => Some(0),
+        Reg16::DE => Some(1),
+        Reg16::HL => Some(2),
+        Reg16::SP => Some(3),
+        Reg16::AF => None,
+    }
+}
+
+/// Index of a 16 bit register operand of PUSH and POP
+const fn reg16_stack(reg: Reg16) -> Option<u8> {
+    match reg {
+        Reg16::BC => Some(0),
+        Reg16::DE => Some(1),
+        Reg16::HL => Some(2),
+        Reg16::AF => Some(3),
+        Reg16::SP => None,
+    }
+}
+
+const fn condition(cond: Condition) -> u8 {
+    match cond {
+        Condition::NotZero => 0,
+        Condition::Zero => 1,
+        Condition::NotCarry => 2,
+        Condition::Carry => 3,
+    }
+}
+
+type Bytes = ArrayVec<u8, MAX_INSTRUCTION_LEN>;
+
+fn op(opcode: u8) -> Bytes {
+    let mut bytes = Bytes::new();
+    bytes.push(opcode);
+    bytes
+}
+
+fn op_u8(opcode: u8, imm: u8) -> Bytes {
+    let mut bytes = op(opcode);
+    bytes.push(imm);
+    bytes
+}
+
+fn op_u16(opcode: u8, imm: u16) -> Bytes {
+    let [lo, hi] = imm.to_le_bytes();
+
+    let mut bytes = op_u8(opcode, lo);
+    bytes.push(hi);
+    bytes
+}
+
+/// The 8 bit arithmetic group. `base` is the opcode with register B, the
+/// immediate version is always 0x46 higher.
+fn arith(base: u8, src: ArithSrc) -> Option<Bytes> {
+    Some(match src {
+        ArithSrc::Reg(reg) => op(base | reg8(reg)?),
+        ArithSrc::Mem(MemLoc::Reg(Reg16::HL)) => op(base | 6),
+        ArithSrc::Mem(_) => return None,
+        ArithSrc::Imm(imm) => op_u8(base + 0x46, imm),
+    })
+}
+
+fn prefixed(base: u8, target: PrefArithTarget) -> Option<Bytes> {
+    Some(op_u8(0xCB, base | pref_target(target)?))
+}
+
+fn load8(dst: Ld8Dst, src: Ld8Src) -> Option<Bytes> {
+    const HL: MemLoc = MemLoc::Reg(Reg16::HL);
+
+    Some(match (dst, src) {
+        (Ld8Dst::Reg(dst), Ld8Src::Reg(src)) => op(0x40 | reg8(dst)? << 3 | reg8(src)?),
+        (Ld8Dst::Reg(dst), Ld8Src::Mem(HL)) => op(0x46 | reg8(dst)? << 3),
+        (Ld8Dst::Mem(HL), Ld8Src::Reg(src)) => op(0x70 | reg8(src)?),
+        (Ld8Dst::Reg(dst), Ld8Src::Imm(imm)) => op_u8(0x06 | reg8(dst)? << 3, imm),
+        (Ld8Dst::Mem(HL), Ld8Src::Imm(imm)) => op_u8(0x36, imm),
+
+        (Ld8Dst::Mem(MemLoc::Reg(Reg16::BC)), Ld8Src::Reg(Reg8::A)) => op(0x02),
+        (Ld8Dst::Mem(MemLoc::Reg(Reg16::DE)), Ld8Src::Reg(Reg8::A)) => op(0x12),
+        (Ld8Dst::Reg(Reg8::A), Ld8Src::Mem(MemLoc::Reg(Reg16::BC))) => op(0x0A),
+        (Ld8Dst::Reg(Reg8::A), Ld8Src::Mem(MemLoc::Reg(Reg16::DE))) => op(0x1A),
+
+        (Ld8Dst::Mem(MemLoc::HighMemImm(addr)), Ld8Src::Reg(Reg8::A)) => op_u8(0xE0, addr),
+        (Ld8Dst::Reg(Reg8::A), Ld8Src::Mem(MemLoc::HighMemImm(addr))) => op_u8(0xF0, addr),
+        (Ld8Dst::Mem(MemLoc::HighMemReg(Reg8::C)), Ld8Src::Reg(Reg8::A)) => op(0xE2),
+        (Ld8Dst::Reg(Reg8::A), Ld8Src::Mem(MemLoc::HighMemReg(Reg8::C))) => op(0xF2),
+        (Ld8Dst::Mem(MemLoc::Imm(addr)), Ld8Src::Reg(Reg8::A)) => op_u16(0xEA, addr),
+        (Ld8Dst::Reg(Reg8::A), Ld8Src::Mem(MemLoc::Imm(addr))) => op_u16(0xFA, addr),
+
+        _ => return None,
+    })
+}
+
+fn load16(dst: Ld16Dst, src: Ld16Src) -> Option<Bytes> {
+    Some(match (dst, src) {
+        (Ld16Dst::Reg(dst), Ld16Src::Imm(imm)) => op_u16(0x01 | reg16(dst)? << 4, imm),
+        (Ld16Dst::Mem(MemLoc::Imm(addr)), Ld16Src::Reg(Reg16::SP)) => op_u16(0x08, addr),
+        (Ld16Dst::Reg(Reg16::SP), Ld16Src::Reg(Reg16::HL)) => op(0xF9),
+        _ => return None,
+    })
+}
+
+fn inc_dec(reg8_base: u8, reg16_base: u8, target: IncDecTarget) -> Option<Bytes> {
+    Some(match target {
+        IncDecTarget::Reg8(reg) => op(reg8_base | reg8(reg)? << 3),
+        IncDecTarget::MemHL => op(reg8_base | 6 << 3),
+        IncDecTarget::Reg16(reg) => op(reg16_base | reg16(reg)? << 4),
+    })
+}
+
+impl Instruction {
+    fn try_encode(self) -> Option<Bytes> {
+        Some(match self {
+            Instruction::Nop => op(0x00),
+            Instruction::Stop(val) => op_u8(0x10, val),
+            Instruction::Halt => op(0x76),
+            Instruction::EI => op(0xFB),
+            Instruction::DI => op(0xF3),
+            Instruction::Add(src) => arith(0x80, src)?,
+            Instruction::AddCarry(src) => arith(0x88, src)?,
+            Instruction::AddHL(reg) => op(0x09 | reg16(reg)? << 4),
+            Instruction::AddSP(offset) => op_u8(0xE8, offset as u8),
+            Instruction::Sub(src) => arith(0x90, src)?,
+            Instruction::SubCarry(src) => arith(0x98, src)?,
+            Instruction::And(src) => arith(0xA0, src)?,
+            Instruction::Xor(src) => arith(0xA8, src)?,
+            Instruction::Or(src) => arith(0xB0, src)?,
+            Instruction::Cmp(src) => arith(0xB8, src)?,
+            Instruction::Inc(target) => inc_dec(0x04, 0x03, target)?,
+            Instruction::Dec(target) => inc_dec(0x05, 0x0B, target)?,
+            Instruction::RotLeftCircularA => op(0x07),
+            Instruction::RotRightCircularA => op(0x0F),
+            Instruction::RotLeftA => op(0x17),
+            Instruction::RotRightA => op(0x1F),
+            Instruction::RotLeftCircular(target) => prefixed(0x00, target)?,
+            Instruction::RotRightCircular(target) => prefixed(0x08, target)?,
+            Instruction::RotLeft(target) => prefixed(0x10, target)?,
+            Instruction::RotRight(target) => prefixed(0x18, target)?,
+            Instruction::ShiftLeftArith(target) => prefixed(0x20, target)?,
+            Instruction::ShiftRightArith(target) => prefixed(0x28, target)?,
+            Instruction::Swap(target) => prefixed(0x30, target)?,
+            Instruction::ShiftRightLogic(target) => prefixed(0x38, target)?,
+            Instruction::Bit(bit, target) => prefixed(0x40 | (bit as u8) << 3, target)?,
+            Instruction::Res(bit, target) => prefixed(0x80 | (bit as u8) << 3, target)?,
+            Instruction::Set(bit, target) => prefixed(0xC0 | (bit as u8) << 3, target)?,
+            Instruction::Load8(dst, src) => load8(dst, src)?,
+            Instruction::Load16(dst, src) => load16(dst, src)?,
+            Instruction::LoadAtoHLI => op(0x22),
+            Instruction::LoadAtoHLD => op(0x32),
+            Instruction::LoadHLItoA => op(0x2A),
+            Instruction::LoadHLDtoA => op(0x3A),
+            Instruction::LoadSPi8toHL(offset) => op_u8(0xF8, offset as u8),
+            Instruction::Jump(addr) => op_u16(0xC3, addr),
+            Instruction::JumpRel(offset) => op_u8(0x18, offset as u8),
+            Instruction::JumpHL => op(0xE9),
+            Instruction::JumpIf(addr, cond) => op_u16(0xC2 | condition(cond) << 3, addr),
+            Instruction::JumpRelIf(offset, cond) => {
+                op_u8(0x20 | condition(cond) << 3, offset as u8)
+            }
+            Instruction::Call(addr) => op_u16(0xCD, addr),
+            Instruction::CallIf(addr, cond) => op_u16(0xC4 | condition(cond) << 3, addr),
+            Instruction::Ret => op(0xC9),
+            Instruction::Reti => op(0xD9),
+            Instruction::RetIf(cond) => op(0xC0 | condition(cond) << 3),
+            Instruction::Pop(reg) => op(0xC1 | reg16_stack(reg)? << 4),
+            Instruction::Push(reg) => op(0xC5 | reg16_stack(reg)? << 4),
+            Instruction::DecimalAdjust => op(0x27),
+            Instruction::ComplementAccumulator => op(0x2F),
+            Instruction::SetCarryFlag => op(0x37),
+            Instruction::ComplementCarry => op(0x3F),
+            Instruction::Rst(vec) => op(0xC7 | vec as u8),
+            Instruction::IllegalInstruction(opcode) => {
+                if !ILLEGAL_OPCODES.contains(&opcode) {
+                    return None;
+                }
+
+                op(opcode)
+            }
+        })
+    }
+
+    /// The bytes of this instruction, as [super::decoder::decode] reads
+    /// them
+    pub fn encode(&self) -> Result<ArrayVec<u8, MAX_INSTRUCTION_LEN>, EncodeError> {
+        self.try_encode().ok_or(EncodeError::NoEncoding(*self))
+    }
+
+    /// Writes the bytes of this instruction to the start of `buf`,
+    /// returning how many were written
+    pub fn encode_into(&self, buf: &mut [u8]) -> Result<usize, EncodeError> {
+        let bytes = self.encode()?;
+
+        let Some(dst) = buf.get_mut(..bytes.len()) else {
+            return Err(EncodeError::BufferTooSmall {
+                needed: bytes.len(),
+                len: buf.len(),
+            });
+        };
+
+        dst.copy_from_slice(&bytes);
+
+        Ok(bytes.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::isa::{decoder::decode, testutils};
+
+    use super::*;
+
+    #[test]
+    fn legal_instructions_round_trip() {
+        for opcode in testutils::legal_instrs() {
+            let instr = decode(&opcode.as_slice(), 0x0).unwrap();
+            let bytes = instr.encode().unwrap();
+
+            assert_eq!(instr.len() as usize, bytes.len(), "{:?}", instr);
+            assert_eq!(opcode[..bytes.len()], bytes[..], "{:?}", instr);
+            assert_eq!(instr, decode(&bytes.as_slice(), 0x0).unwrap());
+        }
+    }
+
+    #[test]
+    fn illegal_opcodes_round_trip() {
+        for opcode in testutils::illegal_opcodes() {
+            let instr = Instruction::IllegalInstruction(opcode);
+
+            assert_eq!(&[opcode], instr.encode().unwrap().as_slice());
+        }
+
+        let not_illegal = Instruction::IllegalInstruction(0x00);
+        assert_eq!(
+            Err(EncodeError::NoEncoding(not_illegal)),
+            not_illegal.encode()
+        );
+    }
+
+    #[test]
+    fn unencodable_instructions() {
+        for instr in [
+            Instruction::Load8(Ld8Dst::Reg(Reg8::F), Ld8Src::Imm(0)),
+            Instruction::Load8(Ld8Dst::Reg(Reg8::B), Ld8Src::Mem(MemLoc::Imm(0xC000))),
+            Instruction::Push(Reg16::SP),
+            Instruction::Inc(IncDecTarget::Reg16(Reg16::AF)),
+            Instruction::Add(ArithSrc::Mem(MemLoc::Reg(Reg16::BC))),
+        ] {
+            assert_eq!(Err(EncodeError::NoEncoding(instr)), instr.encode());
+        }
+    }
+
+    #[test]
+    fn encode_into_buffer() {
+        let instr = Instruction::Call(0x1234);
+        let mut buf = [0u8; 4];
+
+        assert_eq!(Ok(3), instr.encode_into(&mut buf));
+        assert_eq!([0xCD, 0x34, 0x12, 0x00], buf);
+
+        assert_eq!(
+            Err(EncodeError::BufferTooSmall { needed: 3, len: 2 }),
+            instr.encode_into(&mut buf[..2])
+        );
+    }
+}
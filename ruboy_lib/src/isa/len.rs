@@ -1,7 +1,12 @@
 use super::{ArithSrc, IncDecTarget, Instruction, Ld16Dst, Ld8Dst, Ld8Src, MemLoc};
 
 impl Instruction {
-    /// Returns the length of this [`Instruction`] in bytes.
+    /// Returns the length of this [`Instruction`] in bytes, which is how far
+    /// the program counter moves past it: the opcode, the 0xCB prefix if any,
+    /// and the immediate operands. Always between 1 and 3.
+    ///
+    /// An [`Instruction::IllegalInstruction`] is a single byte, the opcode
+    /// that could not be decoded.
     #[allow(clippy::len_without_is_empty)]
     pub const fn len(self) -> u8 {
         match self {
@@ -9,7 +14,7 @@ impl Instruction {
             Instruction::Stop(_) => 2,
             Instruction::Halt => 1,
             Instruction::EI => 1,
-            Instruction::DI => 2,
+            Instruction::DI => 1,
             Instruction::Add(src) => 1 + src.op_size(),
             Instruction::AddCarry(src) => 1 + src.op_size(),
             Instruction::AddHL(_) => 1,
@@ -61,7 +66,7 @@ impl Instruction {
             Instruction::RotRightCircularA => 1,
             Instruction::RotLeftA => 1,
             Instruction::RotRightA => 1,
-            Instruction::IllegalInstruction(_) => panic!("Illegal instruction has no length"),
+            Instruction::IllegalInstruction(_) => 1,
         }
     }
 }
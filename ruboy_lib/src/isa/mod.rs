@@ -1,18 +1,21 @@
-use std::fmt::Display;
+use core::fmt::Display;
 
 mod cycles;
 pub mod decoder;
+pub mod encoder;
 mod len;
+mod table;
 
 #[cfg(test)]
 mod testutils;
 
 pub use cycles::*;
+pub use table::{opcode_table, OpcodeInfo, PREFIX};
 
 #[cfg(feature = "isa_display")]
 pub mod display;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Reg8 {
     A,
     B,
@@ -24,7 +27,7 @@ pub enum Reg8 {
     L,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Reg16 {
     AF,
     BC,
@@ -33,7 +36,7 @@ pub enum Reg16 {
     SP,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum MemLoc {
     /// 0xFF00 + u8
     HighMemReg(Reg8),
@@ -43,27 +46,27 @@ pub enum MemLoc {
     Imm(u16),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ArithSrc {
     Reg(Reg8),
     Imm(u8),
     Mem(MemLoc),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Ld8Src {
     Reg(Reg8),
     Mem(MemLoc),
     Imm(u8),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Ld8Dst {
     Mem(MemLoc),
     Reg(Reg8),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Ld16Src {
     Reg(Reg16),
     Imm(u16),
@@ -78,20 +81,20 @@ impl Ld16Src {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Ld16Dst {
     Mem(MemLoc),
     Reg(Reg16),
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum IncDecTarget {
     Reg8(Reg8),
     Reg16(Reg16),
     MemHL,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum PrefArithTarget {
     Reg(Reg8),
 
@@ -99,7 +102,7 @@ pub enum PrefArithTarget {
     MemHL,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Bit {
     B0 = 0,
     B1 = 1,
@@ -111,7 +114,7 @@ pub enum Bit {
     B7 = 7,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Condition {
     Zero,
     NotZero,
@@ -119,7 +122,7 @@ pub enum Condition {
     NotCarry,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum RsVec {
     Rst0 = 0x00,
     Rst1 = 0x08,
@@ -131,7 +134,7 @@ pub enum RsVec {
     Rst7 = 0x38,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Instruction {
     /// No operation
     Nop,
@@ -307,9 +310,33 @@ pub enum Instruction {
     IllegalInstruction(u8),
 }
 
+impl Instruction {
+    /// True if this instruction (possibly) continues execution somewhere
+    /// other than the next instruction, or stops the CPU
+    pub const fn is_control_flow(self) -> bool {
+        matches!(
+            self,
+            Instruction::Jump(_)
+                | Instruction::JumpRel(_)
+                | Instruction::JumpHL
+                | Instruction::JumpIf(_, _)
+                | Instruction::JumpRelIf(_, _)
+                | Instruction::Call(_)
+                | Instruction::CallIf(_, _)
+                | Instruction::Ret
+                | Instruction::Reti
+                | Instruction::RetIf(_)
+                | Instruction::Rst(_)
+                | Instruction::Halt
+                | Instruction::Stop(_)
+                | Instruction::IllegalInstruction(_)
+        )
+    }
+}
+
 impl Display for Instruction {
     #[cfg(feature = "isa_display")]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let as_displayable = display::DisplayableInstruction::from(*self);
 
         write!(
@@ -320,7 +347,7 @@ impl Display for Instruction {
     }
 
     #[cfg(not(feature = "isa_display"))]
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{:?}", self)
     }
 }
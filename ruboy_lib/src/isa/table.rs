@@ -0,0 +1,193 @@
+use alloc::vec::Vec;
+
+use super::{
+    decoder::{decode, ILLEGAL_OPCODES},
+    Instruction, TCycles,
+};
+
+/// The opcode that selects an instruction from the prefixed table with the
+/// byte after it
+pub const PREFIX: u8 = 0xCB;
+
+/// What every instruction with an opcode has in common, whatever its
+/// immediate operands are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpcodeInfo {
+    /// Whether the opcode follows the 0xCB prefix
+    pub prefixed: bool,
+    pub opcode: u8,
+    pub mnemonic: &'static str,
+
+    /// Length in bytes, including the prefix and the immediate operands
+    pub len: u8,
+    pub cycles: TCycles,
+}
+
+impl OpcodeInfo {
+    fn new(prefixed: bool, opcode: u8) -> Self {
+        let bytes = if prefixed {
+            [PREFIX, opcode, 0]
+        } else {
+            [opcode, 0, 0]
+        };
+
+        // Three bytes are enough for any instruction
+        let instr = decode(&bytes.as_slice(), 0).expect("Opcode with operands not decoded");
+
+        Self {
+            prefixed,
+            opcode,
+            mnemonic: instr.mnemonic(),
+            len: instr.len(),
+            cycles: instr.cycles(),
+        }
+    }
+}
+
+/// Every legal opcode in order, followed by every prefixed opcode in order.
+/// The prefix itself and the illegal opcodes are left out.
+pub fn opcode_table() -> Vec<OpcodeInfo> {
+    let unprefixed = (u8::MIN..=u8::MAX)
+        .filter(|opcode| *opcode != PREFIX && !ILLEGAL_OPCODES.contains(opcode))
+        .map(|opcode| OpcodeInfo::new(false, opcode));
+
+    let prefixed = (u8::MIN..=u8::MAX).map(|opcode| OpcodeInfo::new(true, opcode));
+
+    unprefixed.chain(prefixed).collect()
+}
+
+impl Instruction {
+    /// The mnemonic of this [`Instruction`], as the disassembly shows it
+    pub const fn mnemonic(self) -> &'static str {
+        match self {
+            Instruction::Nop => "nop",
+            Instruction::Stop(_) => "stop",
+            Instruction::Halt => "halt",
+            Instruction::EI => "ei",
+            Instruction::DI => "di",
+            Instruction::Add(_) | Instruction::AddHL(_) | Instruction::AddSP(_) => "add",
+            Instruction::AddCarry(_) => "adc",
+            Instruction::Sub(_) => "sub",
+            Instruction::SubCarry(_) => "sbc",
+            Instruction::And(_) => "and",
+            Instruction::Or(_) => "or",
+            Instruction::Xor(_) => "xor",
+            Instruction::Cmp(_) => "cmp",
+            Instruction::Inc(_) => "inc",
+            Instruction::Dec(_) => "dec",
+            Instruction::RotLeftCircular(_) => "rlc",
+            Instruction::RotLeftCircularA => "rlca",
+            Instruction::RotRightCircular(_) => "rrc",
+            Instruction::RotRightCircularA => "rrca",
+            Instruction::RotLeft(_) => "rl",
+            Instruction::RotLeftA => "rla",
+            Instruction::RotRight(_) => "rr",
+            Instruction::RotRightA => "rra",
+            Instruction::ShiftLeftArith(_) => "sla",
+            Instruction::ShiftRightArith(_) => "sra",
+            Instruction::Swap(_) => "swap",
+            Instruction::ShiftRightLogic(_) => "srl",
+            Instruction::Bit(_, _) => "bit",
+            Instruction::Res(_, _) => "res",
+            Instruction::Set(_, _) => "set",
+            Instruction::Load8(_, _)
+            | Instruction::Load16(_, _)
+            | Instruction::LoadAtoHLI
+            | Instruction::LoadAtoHLD
+            | Instruction::LoadHLItoA
+            | Instruction::LoadHLDtoA
+            | Instruction::LoadSPi8toHL(_) => "ld",
+            Instruction::Jump(_) | Instruction::JumpHL | Instruction::JumpIf(_, _) => "jp",
+            Instruction::JumpRel(_) | Instruction::JumpRelIf(_, _) => "jr",
+            Instruction::Call(_) | Instruction::CallIf(_, _) => "call",
+            Instruction::Ret | Instruction::RetIf(_) => "ret",
+            Instruction::Reti => "reti",
+            Instruction::Pop(_) => "pop",
+            Instruction::Push(_) => "push",
+            Instruction::DecimalAdjust => "daa",
+            Instruction::ComplementAccumulator => "cpl",
+            Instruction::SetCarryFlag => "scf",
+            Instruction::ComplementCarry => "ccf",
+            Instruction::Rst(_) => "rst",
+            Instruction::IllegalInstruction(_) => "???",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::isa::{decoder::decode, testutils};
+
+    use super::*;
+
+    /// Every legal opcode, with the byte after it and an immediate
+    fn opcodes() -> impl Iterator<Item = [u8; 3]> {
+        testutils::legal_instrs().filter(|opcode| opcode[2] == 0xFF)
+    }
+
+    fn find(table: &[OpcodeInfo], opcode: [u8; 3]) -> Option<&OpcodeInfo> {
+        match opcode {
+            [PREFIX, opcode, _] => table
+                .iter()
+                .find(|info| info.prefixed && info.opcode == opcode),
+            [opcode, _, _] => table
+                .iter()
+                .find(|info| !info.prefixed && info.opcode == opcode),
+        }
+    }
+
+    #[test]
+    fn covers_every_legal_opcode() {
+        let table = opcode_table();
+
+        assert_eq!(table.len(), 0x100 - 1 - ILLEGAL_OPCODES.len() + 0x100);
+
+        for opcode in opcodes() {
+            assert!(
+                find(&table, opcode).is_some(),
+                "Opcode {:02X?} missing",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn matches_decoded_instructions() {
+        let table = opcode_table();
+
+        for opcode in opcodes() {
+            let instr = decode(&opcode.as_slice(), 0x0).unwrap();
+            let info = find(&table, opcode).unwrap();
+
+            assert_eq!(info.len, instr.len(), "{:?}", instr);
+            assert_eq!(info.cycles, instr.cycles(), "{:?}", instr);
+            assert_eq!(info.mnemonic, instr.mnemonic(), "{:?}", instr);
+        }
+    }
+
+    #[test]
+    fn illegal_instruction_is_one_byte() {
+        for opcode in testutils::illegal_opcodes() {
+            let instr = decode(&[opcode].as_slice(), 0x0).unwrap();
+
+            assert_eq!(instr.len(), 1);
+        }
+    }
+
+    #[cfg(feature = "isa_display")]
+    #[test]
+    fn mnemonics_match_display() {
+        use alloc::string::ToString;
+
+        for opcode in opcodes() {
+            let instr = decode(&opcode.as_slice(), 0x0).unwrap();
+
+            assert_eq!(
+                instr.to_string().split(' ').next(),
+                Some(instr.mnemonic()),
+                "{:?}",
+                instr
+            );
+        }
+    }
+}
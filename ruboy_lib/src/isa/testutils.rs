@@ -1,7 +1,7 @@
+use super::decoder::ILLEGAL_OPCODES;
+
 pub(super) fn illegal_opcodes() -> Vec<u8> {
-    vec![
-        0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
-    ]
+    ILLEGAL_OPCODES.to_vec()
 }
 
 pub(super) fn legal_instrs() -> impl Iterator<Item = [u8; 3]> {
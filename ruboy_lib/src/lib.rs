@@ -1,27 +1,106 @@
-use std::fmt::Display;
-use std::time::Instant;
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt::Display;
+use core::ops::RangeInclusive;
+#[cfg(not(feature = "std"))]
+use num::traits::Float;
+#[cfg(feature = "std")]
+use std::io::Write;
+
+use color::DmgPalette;
 use cpu::Cpu;
 use cpu::CpuErr;
-use input::apply_input_to;
+use debug::CallStack;
+use debug::CartridgeFault;
+use debug::ChannelState;
+use debug::CpuRegisters;
+use debug::DebugImage;
+use debug::Debugger;
+use debug::MemRegion;
+use debug::OamEntry;
+use debug::OpcodeStats;
+use debug::Profiler;
+use debug::StopReason;
+use debug::TileMapArea;
+use debug::Tracer;
+use debug::VramLayout;
+use emucore::EmulatorCore;
+use logging::{debug, trace, Subsystem};
 use memcontroller::MemController;
 
 use memcontroller::MemControllerInitErr;
+use memcontroller::ReadError;
 use memcontroller::WriteError;
-use ppu::PpuErr;
+use model::BootRom;
+use model::GbModel;
+use model::InputPolling;
+use model::RamInit;
+use model::RuboyConfig;
+use model::StateDigest;
+use model::BOOT_ROM_SIZE;
 use ppu::{Ppu, FRAME_CYCLES};
+use ppu::{PpuErr, VBlankErr};
+use quirks::Quirk;
+use rom::controller::RomControllerInitErr;
+use rom::meta::{CartridgeMapper, RomMeta};
+use rom::rtc::{Rtc, RtcClock, RtcLoadErr};
+use rom::BankCaching;
+use savestate::{StateData, StateReader, StateWriter, STATE_MAGIC, STATE_VERSION};
+use sgb::Sgb;
+use storage::StorageProvider;
 use thiserror::Error;
 
+mod apu;
 mod boot;
+mod builder;
+pub mod cheatsearch;
+pub mod color;
 mod cpu;
+pub mod debug;
+pub mod emucore;
 mod extern_traits;
+pub mod flatmem;
+#[cfg(target_has_atomic = "ptr")]
+pub mod infrared;
 mod input;
 pub mod isa;
+pub mod link;
+pub mod logging;
 mod memcontroller;
+pub mod model;
+pub mod movie;
+#[cfg(feature = "std")]
+pub mod netlink;
 mod ppu;
+pub mod printer;
+pub mod quirks;
+pub mod rewind;
 pub mod rom;
+mod savestate;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod selftest;
+pub mod sgb;
+#[cfg(feature = "std")]
+pub mod shared;
+pub mod storage;
 
+#[cfg(test)]
+mod testutils;
+
+pub use apu::AudioChannel;
+pub use builder::RuboyBuilder;
 pub use extern_traits::*;
+pub use memcontroller::Strictness;
+pub use ppu::{Frameskip, LcdMode, LcdRegisters, RenderMode};
+pub use savestate::StateLoadErr;
 
 pub const CLOCK_SPEED_HZ: usize = 1 << 22;
 pub const CLOCK_SPEED_HZ_F64: f64 = CLOCK_SPEED_HZ as f64;
@@ -35,16 +114,101 @@ where
     I: InputHandler,
 {
     cycle_accumulator: f64,
+    speed: f64,
     cpu: Cpu,
     ppu: Ppu<V>,
     mem: MemController<A, R>,
     input: I,
+
+    /// Sees every finished frame
+    frame_observer: Option<Box<dyn FrameObserver>>,
+
+    /// Looks at memory after every finished frame
+    memory_inspector: Option<Box<dyn MemoryInspector>>,
+
+    /// Sees the lines, modes and frames of the PPU
+    ppu_hooks: Option<Box<dyn PpuHooks>>,
+
+    /// Told when the rumble motor switches, which it last was in `rumble_on`
+    rumble_handler: Option<Box<dyn RumbleHandler>>,
+    rumble_on: bool,
+
+    /// On the other end of the link cable
+    link_device: Option<Box<dyn LinkDevice>>,
+
+    /// While set, [Ruboy::step] runs nothing
+    paused: bool,
+
+    error_policy: ErrorPolicy,
+
+    /// The last failure, until taken with [Ruboy::take_fault]
+    fault: Option<Fault>,
+
+    /// The cycles the PPU is behind on the rest of the system. It only
+    /// catches up when something could notice, see [Ruboy::catch_up_ppu]
+    ppu_behind: usize,
+
+    /// Cycles ran since the instance was created, see [Ruboy::cycles_elapsed]
+    cycles: u64,
+
+    /// The cycle the newest audio sample was produced at
+    last_sample_cycle: Option<u64>,
+
+    /// The span of the frame the PPU is drawing, and its number
+    #[cfg(feature = "tracing")]
+    frame_span: (u64, tracing::Span),
+
+    /// Which frames are drawn. `skipped` counts the frames skipped in a
+    /// row, and `auto_skip` is how many [Frameskip::Auto] skips after every
+    /// drawn frame, as decided by the last [Ruboy::step].
+    frameskip: Frameskip,
+    skipped: u8,
+    auto_skip: u8,
+
+    /// How the instance was created, which [Ruboy::reset] starts over with.
+    /// If `boot_rom` is set, the boot ROM runs again instead of applying
+    /// the boot state of the model.
+    config: RuboyConfig,
+    boot_rom: bool,
 }
 
+// Ruboy is Send whenever its parameters are, so it can run on another thread
+// than the one handling input and video. Fails to compile otherwise.
+const _: () = {
+    const fn assert_send<T: Send>() {}
+
+    #[allow(dead_code)]
+    const fn ruboy_is_send<A, R, V, I>()
+    where
+        A: GBAllocator + Send,
+        R: RomReader + Send,
+        V: GBGraphicsDrawer + Send,
+        I: InputHandler + Send,
+    {
+        assert_send::<Ruboy<A, R, V, I>>();
+    }
+};
+
 #[derive(Debug, Error)]
 pub enum RuboyStartErr<R: RomReader> {
     #[error("Could not initialize memory controller: {0}")]
     MemController(#[from] MemControllerInitErr<R>),
+
+    #[error("Boot ROM image is {0} bytes instead of {expected}", expected = BOOT_ROM_SIZE)]
+    BootRomSize(usize),
+}
+
+impl<R: RomReader> RuboyStartErr<R> {
+    /// The mapper of the cartridge, if it could not be started because
+    /// Ruboy doesn't emulate that mapper yet
+    pub fn unsupported_mapper(&self) -> Option<CartridgeMapper> {
+        match self {
+            RuboyStartErr::MemController(MemControllerInitErr::Rom(
+                RomControllerInitErr::UnsupportedMapper(mapper),
+            )) => Some(*mapper),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -57,55 +221,2919 @@ pub enum RuboyErr<V: GBGraphicsDrawer> {
 
     #[error("Error during DMA cycle")]
     Dma(#[source] WriteError),
+
+    #[error("Error while resetting the cartridge")]
+    Reset(#[source] WriteError),
+}
+
+/// What happens when emulation fails, for example because the game writes
+/// to cartridge RAM the cartridge doesn't have. However it is handled, the
+/// failure is kept as a [Fault], see [Ruboy::take_fault].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    /// The error is returned. The instance can be inspected afterwards, but
+    /// should not be ran anymore.
+    #[default]
+    Stop,
+
+    /// The instance pauses itself, and the run that failed returns the
+    /// cycles ran until then. With a debugger attached, it stops with
+    /// [StopReason::Fault]. Resuming retries the failed instruction.
+    Pause,
+
+    /// Failed memory accesses are let through: reads return 0xFF and writes
+    /// are dropped. Everything else that fails, like the frontend not being
+    /// able to draw, is handled as with [ErrorPolicy::Pause].
+    Ignore,
+}
+
+/// Where and why emulation failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    /// The program counter when it failed
+    pub pc: u16,
+
+    /// The ROM bank mapped at `pc`, if it is in the cartridge
+    pub bank: Option<u16>,
+
+    /// The error, followed by everything that caused it
+    pub error: String,
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "Emulation stopped at PC={:02X}:{:04X}", bank, self.pc)?,
+            None => write!(f, "Emulation stopped at PC={:04X}", self.pc)?,
+        }
+
+        write!(f, " due to: {}", self.error)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum LoadPersistentErr<E: core::error::Error + 'static> {
+    #[error("Could not read from storage: {0}")]
+    Storage(#[source] E),
+
+    #[error("Could not load RTC: {0}")]
+    Rtc(#[from] RtcLoadErr),
+
+    #[error("Cartridge RAM save has wrong length: {found}, wanted {expected}")]
+    CartRamLength { expected: usize, found: usize },
+
+    #[error("Could not load save state: {0}")]
+    State(#[from] StateLoadErr),
+}
+
+/// How a [Ruboy::run_until] call ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunUntil {
+    /// The condition was met after the given amount of cycles
+    Reached(u64),
+
+    /// The condition was not met within the cycle limit
+    Limit,
+
+    /// The debugger stopped execution first
+    Stopped(StopReason),
+}
+
+/// How [Ruboy::step_frame] ended
+#[derive(Debug, Clone, Copy)]
+pub enum FrameEvent<'a> {
+    /// A new frame was drawn
+    Completed(&'a Frame),
+
+    /// The LCD is off, so a frame worth of cycles ran without drawing
+    /// anything
+    LcdOff,
+
+    /// The debugger stopped execution before the frame was finished
+    Stopped(StopReason),
+}
+
+/// The storage key the cartridge clock is stored under, see
+/// [Ruboy::save_persistent]
+fn rtc_key(name: &str) -> String {
+    format!("{}.rtc", name)
+}
+
+/// The storage key battery backed cartridge RAM is stored under, see
+/// [Ruboy::save_persistent]
+fn sav_key(name: &str) -> String {
+    format!("{}.sav", name)
+}
+
+/// The storage key of a save state, see [Ruboy::save_state_to]
+fn state_key(name: &str) -> String {
+    format!("{}.state", name)
 }
 
 impl<A: GBAllocator, R: RomReader, V: GBGraphicsDrawer, I: InputHandler> Ruboy<A, R, V, I> {
+    /// Creates a new emulator instance. If a boot ROM image was compiled in,
+    /// emulation starts by running that boot ROM. Otherwise, the boot state of
+    /// [GbModel::default] is applied and emulation starts at the cartridge entrypoint.
     pub fn new(rom: R, output: V, input: I) -> Result<Self, RuboyStartErr<R>> {
-        Ok(Self {
+        Self::with_boot_rom(rom, output, input, BootRom::BuiltIn)
+    }
+
+    /// Starts building a new emulator instance, for more options than the
+    /// other constructors offer
+    pub fn builder(rom: R, output: V, input: I) -> RuboyBuilder<A, R, V, I> {
+        RuboyBuilder::new(rom, output, input)
+    }
+
+    /// Creates a new emulator instance that starts with the given boot ROM,
+    /// or without one
+    pub fn with_boot_rom(
+        rom: R,
+        output: V,
+        input: I,
+        boot_rom: BootRom,
+    ) -> Result<Self, RuboyStartErr<R>> {
+        let image = match boot_rom {
+            BootRom::BuiltIn if !cfg!(feature = "boot_img_enabled") => {
+                return Self::with_model(rom, output, input, GbModel::default());
+            }
+            BootRom::BuiltIn => None,
+            BootRom::Skip(model) => return Self::with_model(rom, output, input, model),
+            BootRom::External(image) if image.len() != BOOT_ROM_SIZE => {
+                return Err(RuboyStartErr::BootRomSize(image.len()));
+            }
+            BootRom::External(image) => Some(image),
+        };
+
+        let mut mem = MemController::new(rom, BankCaching::default())?;
+
+        if let Some(image) = image {
+            mem.set_boot_image(image);
+        }
+
+        Ok(Self::from_parts(
+            Cpu::new(),
+            Ppu::new(output),
+            mem,
+            input,
+            RuboyConfig::default(),
+            true,
+        ))
+    }
+
+    /// Creates a new emulator instance that skips the boot ROM, and instead
+    /// starts in the exact state the given model is in after its boot ROM
+    /// finishes. See [GbModel::boot_state] for the values used.
+    pub fn with_model(
+        rom: R,
+        output: V,
+        input: I,
+        model: GbModel,
+    ) -> Result<Self, RuboyStartErr<R>> {
+        let config = RuboyConfig {
+            model,
+            ..Default::default()
+        };
+
+        Self::with_config(rom, output, input, config)
+    }
+
+    /// Same as [Ruboy::with_model], with further control over the initial
+    /// state
+    pub fn with_config(
+        rom: R,
+        output: V,
+        input: I,
+        config: RuboyConfig,
+    ) -> Result<Self, RuboyStartErr<R>> {
+        let mut cpu = Cpu::new();
+        let mut mem = MemController::new(rom, config.bank_caching)?;
+
+        let boot_state = config.model.boot_state(mem.header_checksum());
+
+        cpu.apply_boot_registers(&boot_state.registers);
+        mem.apply_boot_state(&boot_state);
+
+        match config.deterministic {
+            Some(seed) => mem.make_deterministic(seed, config.model),
+            None => mem.init_ram(config.ram_init, config.model),
+        }
+
+        if !config.access_blocking {
+            mem.disable_access_blocking();
+        }
+
+        mem.io_registers.joypad.set_polling(config.input_polling);
+
+        if config.model.is_sgb() && sgb::supported(mem.rom_meta()) {
+            log::info!("Game supports the Super Game Boy, listening to its packets");
+            mem.io_registers.sgb = Some(Box::default());
+        }
+
+        log::info!("Starting emulation with {} boot state", config.model);
+
+        let mut ppu = Ppu::new(output);
+        ppu.set_render_mode(config.render_mode);
+
+        Ok(Self::from_parts(cpu, ppu, mem, input, config, false))
+    }
+
+    /// Puts a new instance together from its set up parts, with nothing
+    /// attached and every runtime setting at its default
+    fn from_parts(
+        cpu: Cpu,
+        ppu: Ppu<V>,
+        mem: MemController<A, R>,
+        input: I,
+        config: RuboyConfig,
+        boot_rom: bool,
+    ) -> Self {
+        Self {
             cycle_accumulator: 0.0,
-            cpu: Cpu::new(),
-            ppu: Ppu::new(output),
-            mem: MemController::new(rom)?,
+            speed: 1.0,
+            cpu,
+            ppu,
+            mem,
             input,
-        })
+            frame_observer: None,
+            memory_inspector: None,
+            ppu_hooks: None,
+            rumble_handler: None,
+            rumble_on: false,
+            link_device: None,
+            paused: false,
+            error_policy: ErrorPolicy::default(),
+            fault: None,
+            ppu_behind: 0,
+            cycles: 0,
+            last_sample_cycle: None,
+            #[cfg(feature = "tracing")]
+            frame_span: (u64::MAX, tracing::Span::none()),
+            frameskip: Frameskip::Off,
+            skipped: 0,
+            auto_skip: 0,
+            config,
+            boot_rom,
+        }
     }
 
-    pub fn step(&mut self, dt: f64) -> Result<usize, RuboyErr<V>> {
-        log::debug!("Stepping emulator {} seconds", dt);
+    /// Turns the Gameboy off and on again. The CPU, PPU, memory and I/O
+    /// registers start over as they did when the instance was created,
+    /// running the boot ROM again if it did. The cartridge keeps the
+    /// contents of its RAM and its clock, so saved games survive, and
+    /// everything attached stays attached.
+    pub fn reset(&mut self) -> Result<(), RuboyErr<V>> {
+        log::info!("Resetting emulator");
 
-        let cycles_dt = dt * CLOCK_SPEED_HZ_F64;
-        let (mut cycles_to_run, accumulated) = split_f64(cycles_dt);
+        self.mem.reset_mapper().map_err(RuboyErr::Reset)?;
+        self.power_cycle();
 
-        self.cycle_accumulator += accumulated;
-        let (extra_cycles, new_accumulator) = split_f64(self.cycle_accumulator);
+        Ok(())
+    }
 
-        cycles_to_run += extra_cycles;
-        self.cycle_accumulator = new_accumulator;
+    /// Swaps in another game, without recreating the instance. The new
+    /// game starts like [Ruboy::reset] does, keeping the output, input and
+    /// everything attached. Save the data of the current game with
+    /// [Ruboy::save_persistent] first, as its cartridge is dropped.
+    ///
+    /// If the new ROM can't be used, the current game keeps its place and
+    /// can continue running.
+    pub fn load_rom(&mut self, rom: R) -> Result<(), RuboyStartErr<R>> {
+        self.mem.insert_rom(rom, self.config.bank_caching)?;
 
-        debug_assert!(cycles_to_run >= 0);
+        log::info!("Loaded {}", self.mem.rom_meta().title());
+
+        if !self.config.access_blocking {
+            self.mem.disable_access_blocking();
+        }
+
+        if let Some(seed) = self.config.deterministic {
+            self.mem.make_deterministic(seed, self.config.model);
+        }
+
+        if !self.boot_rom {
+            let sgb = self.config.model.is_sgb() && sgb::supported(self.mem.rom_meta());
+            self.mem.io_registers.sgb = sgb.then(Box::default);
+        }
+
+        self.power_cycle();
+
+        Ok(())
+    }
+
+    /// Starts the CPU, PPU, memory and I/O registers over, as they were
+    /// when the instance was created
+    fn power_cycle(&mut self) {
+        self.cpu.reset();
+        self.ppu.reset();
+        self.mem.reset();
+        self.cycle_accumulator = 0.0;
+
+        let ram_init = match self.config.deterministic {
+            Some(seed) => RamInit::Pattern { seed },
+            None => self.config.ram_init,
+        };
+
+        self.mem.init_ram(ram_init, self.config.model);
+
+        if !self.boot_rom {
+            let boot_state = self.config.model.boot_state(self.mem.header_checksum());
+
+            self.cpu.apply_boot_registers(&boot_state.registers);
+            self.mem.apply_boot_state(&boot_state);
+        }
+    }
+
+    /// Sets what happens when emulation fails. [ErrorPolicy::Stop] by
+    /// default.
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.error_policy = policy;
+        self.mem.ignore_errors = policy == ErrorPolicy::Ignore;
+    }
+
+    pub const fn error_policy(&self) -> ErrorPolicy {
+        self.error_policy
+    }
+
+    /// The last time emulation failed, if that wasn't taken yet. Meant for
+    /// telling the user, which is why it can only be taken once.
+    pub fn take_fault(&mut self) -> Option<Fault> {
+        self.fault.take()
+    }
+
+    /// Pauses or resumes emulation. While paused, [Ruboy::step] runs
+    /// nothing, while explicit calls like [Ruboy::run_cycles] and
+    /// [Ruboy::step_frame] still do.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub const fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// The game specific workarounds applied to the inserted cartridge
+    pub fn quirks(&self) -> &'static [Quirk] {
+        quirks::for_rom(self.mem.rom_meta())
+    }
+
+    /// When enabled, every CPU access to VRAM or OAM that is blocked because
+    /// the PPU is using that memory is logged. Useful for finding timing
+    /// problems in games.
+    pub fn set_log_blocked_access(&mut self, enabled: bool) {
+        self.mem.log_blocked_access = enabled;
+    }
+
+    /// Sets whether accessing an I/O address without a register stops
+    /// emulation with an error, or behaves like hardware. Permissive by
+    /// default.
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.mem.set_strictness(strictness);
+    }
+
+    pub const fn strictness(&self) -> Strictness {
+        self.mem.strictness()
+    }
+
+    /// When enabled, accesses to I/O addresses without a register are logged
+    /// in [Strictness::Permissive] mode
+    pub fn set_log_unmapped_io(&mut self, enabled: bool) {
+        self.mem.log_unmapped_io = enabled;
+    }
+
+    /// Starts recording the last `capacity` executed instructions. Any
+    /// previously recorded trace is discarded.
+    pub fn enable_trace(&mut self, capacity: usize) {
+        self.cpu.tracer = Some(Tracer::new(capacity));
+    }
+
+    pub fn disable_trace(&mut self) {
+        self.cpu.tracer = None;
+    }
+
+    /// The recorded instruction trace, if tracing is enabled. See
+    /// [Tracer::dump_doctor] for writing it out in a format that can be
+    /// diffed against other emulators, and [Tracer::dump_diff] for a
+    /// shorter format showing only what each instruction changed.
+    pub fn trace(&self) -> Option<&Tracer> {
+        self.cpu.tracer.as_ref()
+    }
+
+    /// Delivers a clock pulse from a linked device, carrying `bit_in`.
+    ///
+    /// Only has an effect while the game is waiting on a serial transfer
+    /// using the external clock. Returns the bit this Gameboy shifted out in
+    /// that case, and None otherwise.
+    pub fn serial_external_clock(&mut self, bit_in: bool) -> Option<bool> {
+        self.mem.serial_external_clock(bit_in)
+    }
+
+    /// Runs a single cycle, as one side of [link::run_linked]. If that
+    /// clocked a bit out over the cable, `exchange` gets it and returns the
+    /// bit the other side shifted out in return, or None if it wasn't
+    /// listening. Returns false if the debugger stopped the emulator.
+    pub fn linked_cycle(
+        &mut self,
+        exchange: impl FnOnce(bool) -> Option<bool>,
+    ) -> Result<bool, RuboyErr<V>> {
+        let serial = &self.mem.io_registers.serial;
+        let clocking = serial.transferring() && serial.internal_clock();
+        let bits_left = serial.bits_left();
+        let bit_out = serial.data() & 0b10000000 != 0;
+
+        if self.run_cycles(1)? == 0 {
+            return Ok(false);
+        }
+
+        let serial = &mut self.mem.io_registers.serial;
+
+        if clocking && serial.bits_left() + 1 == bits_left {
+            // The line is pulled high when the other side isn't listening
+            let bit_in = exchange(bit_out).unwrap_or(true);
+            serial.set_received_bit(bit_in);
+        }
+
+        Ok(true)
+    }
+
+    /// Makes [Ruboy::step] run `speed` times faster than real time, e.g.
+    /// `2.0` for double speed or `0.5` for slow motion. Produced audio is
+    /// resampled to keep the output rate the same.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` is not a positive, finite number
+    pub fn set_speed(&mut self, speed: f64) {
+        assert!(
+            speed.is_finite() && speed > 0.0,
+            "Invalid emulation speed {}",
+            speed
+        );
+
+        self.speed = speed;
+        self.mem.io_registers.apu.set_speed(speed);
+    }
+
+    pub const fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Starts producing stereo audio samples at `sample_rate` Hz. Any
+    /// previously buffered samples are discarded.
+    ///
+    /// Samples are buffered until drained with [Ruboy::drain_audio]. At most
+    /// one second of audio is kept around.
+    pub fn enable_audio(&mut self, sample_rate: u32) {
+        self.mem.io_registers.apu.enable_output(sample_rate);
+    }
+
+    pub fn disable_audio(&mut self) {
+        self.mem.io_registers.apu.disable_output();
+    }
+
+    /// Changes the output rate without dropping buffered samples. Frontends
+    /// can nudge this around the real rate of the audio device to keep its
+    /// buffer from running dry or overflowing.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: u32) {
+        self.mem.io_registers.apu.set_sample_rate(sample_rate);
+    }
+
+    /// The current output rate, if audio is enabled
+    pub fn audio_sample_rate(&self) -> Option<u32> {
+        self.mem.io_registers.apu.sample_rate()
+    }
+
+    /// The cycle the newest buffered sample was produced at, see
+    /// [Ruboy::cycles_elapsed]. At normal speed, the samples before it were
+    /// produced one every `CLOCK_SPEED_HZ / rate` cycles, which is enough to
+    /// line audio up with frames.
+    pub const fn last_sample_cycle(&self) -> Option<u64> {
+        self.last_sample_cycle
+    }
+
+    /// Takes the buffered `[left, right]` samples, oldest first. Empty if
+    /// audio is disabled.
+    pub fn drain_audio(&mut self) -> impl Iterator<Item = [f32; 2]> + '_ {
+        self.mem.io_registers.apu.drain_samples()
+    }
+
+    /// Leaves a channel out of the mixed output. Only affects the produced
+    /// samples, the channel itself keeps running.
+    pub fn set_audio_channel_muted(&mut self, channel: AudioChannel, muted: bool) {
+        self.mem.io_registers.apu.set_muted(channel, muted);
+    }
+
+    pub fn audio_channel_muted(&self, channel: AudioChannel) -> bool {
+        self.mem.io_registers.apu.muted(channel)
+    }
+
+    /// The frequency, duty, envelope, length and output of a sound channel
+    pub fn audio_channel(&self, channel: AudioChannel) -> ChannelState {
+        self.mem.io_registers.apu.channel_state(channel)
+    }
+
+    /// Starts keeping the last `len` mixed `[left, right]` samples, for
+    /// drawing an oscilloscope. Samples are only mixed while audio is
+    /// enabled, see [Ruboy::enable_audio].
+    pub fn enable_audio_scope(&mut self, len: usize) {
+        self.mem.io_registers.apu.enable_scope(len);
+    }
+
+    pub fn disable_audio_scope(&mut self) {
+        self.mem.io_registers.apu.disable_scope();
+    }
+
+    /// The samples kept for the oscilloscope, oldest first, if it is
+    /// enabled. Unlike [Ruboy::drain_audio], this doesn't take them.
+    pub fn audio_scope(&self) -> Option<impl Iterator<Item = [f32; 2]> + '_> {
+        self.mem.io_registers.apu.scope()
+    }
+
+    /// Starts counting how often each opcode is executed, from zero
+    pub fn enable_opcode_stats(&mut self) {
+        self.cpu.opcode_stats = Some(Box::default());
+    }
+
+    /// Stops counting opcodes, handing back the counts so far
+    pub fn disable_opcode_stats(&mut self) -> Option<OpcodeStats> {
+        self.cpu.opcode_stats.take().map(|stats| *stats)
+    }
+
+    /// The opcode execution counts, if enabled
+    pub fn opcode_stats(&self) -> Option<&OpcodeStats> {
+        self.cpu.opcode_stats.as_deref()
+    }
+
+    /// Starts following calls, RSTs, interrupts and returns, from an empty
+    /// call stack
+    pub fn enable_call_stack(&mut self) {
+        self.cpu.call_stack = Some(Box::default());
+    }
+
+    /// Stops following calls. The profiler needs the call stack, so this
+    /// also stops the profiler.
+    pub fn disable_call_stack(&mut self) {
+        self.cpu.call_stack = None;
+        self.cpu.profiler = None;
+    }
+
+    /// The calls the CPU is in right now, if following calls is enabled.
+    /// Starts out empty wherever it was enabled, so it only knows about the
+    /// calls made since then.
+    pub fn call_stack(&self) -> Option<&CallStack> {
+        self.cpu.call_stack.as_deref()
+    }
 
-        log::trace!("Running {} cycles", cycles_to_run as usize);
+    /// Starts sampling where cycles are spent every `interval` cycles, see
+    /// [Profiler]. Also starts following calls if it wasn't yet, so the
+    /// samples know the calls that lead to each instruction.
+    pub fn enable_profiler(&mut self, interval: u32) {
+        if self.cpu.call_stack.is_none() {
+            self.enable_call_stack();
+        }
+
+        self.cpu.profiler = Some(Box::new(Profiler::new(interval)));
+    }
+
+    /// Stops sampling, handing back the samples so far
+    pub fn disable_profiler(&mut self) -> Option<Profiler> {
+        self.cpu.profiler.take().map(|profiler| *profiler)
+    }
+
+    /// The samples so far, if the profiler is enabled
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.cpu.profiler.as_deref()
+    }
+
+    /// Hit/miss statistics of the block cache
+    #[cfg(feature = "cached_interpreter")]
+    pub fn block_cache_stats(&self) -> debug::BlockCacheStats {
+        self.mem.block_cache.stats()
+    }
+
+    /// Writes a line in the
+    /// [gameboy-doctor](https://github.com/robert/gameboy-doctor) log format
+    /// to `sink` before every executed instruction, so CPU behaviour can be
+    /// compared against known-good logs. As gameboy-doctor expects, the CPU
+    /// reads LY as 0x90 while the log is enabled.
+    ///
+    /// Write errors abort [Ruboy::step] with a [RuboyErr::Cpu] error.
+    #[cfg(feature = "std")]
+    pub fn enable_doctor_log(&mut self, sink: impl Write + Send + 'static) {
+        self.cpu.doctor_log = Some(Box::new(sink));
+        self.mem.doctor_ly = true;
+    }
+
+    /// Stops the gameboy-doctor log, handing back the sink
+    #[cfg(feature = "std")]
+    pub fn disable_doctor_log(&mut self) -> Option<Box<dyn Write + Send>> {
+        self.mem.doctor_ly = false;
+        self.cpu.doctor_log.take()
+    }
+
+    /// Renders the full 256x256 background map in the given area, as it
+    /// currently is in VRAM
+    pub fn render_tilemap(&self, area: TileMapArea) -> Result<DebugImage, ReadError> {
+        ppu::view::render_tilemap(area, &self.mem)
+    }
+
+    /// Renders all objects in OAM, as they currently are in memory. The
+    /// objects are laid out in a grid of 8 by 5 cells of 8x16 pixels
+    pub fn render_oam(&self) -> Result<DebugImage, ReadError> {
+        ppu::view::render_oam(&self.mem)
+    }
+
+    /// Renders all 384 tiles in VRAM, 16 tiles wide, in the order they are
+    /// stored in memory
+    pub fn render_tiles(&self) -> Result<DebugImage, ReadError> {
+        ppu::view::render_tiles(&self.mem)
+    }
+
+    pub fn oam_entries(&self) -> Result<Vec<OamEntry>, ReadError> {
+        ppu::view::oam_entries(&self.mem)
+    }
+
+    /// The current scroll positions, tilemap selections and addressing
+    /// modes of the PPU
+    pub fn vram_layout(&self) -> VramLayout {
+        ppu::view::vram_layout(&self.mem)
+    }
+
+    /// Video RAM, 0x8000 to 0x9FFF, as one slice. Reading it is free of side
+    /// effects and access restrictions, like [Ruboy::peek].
+    pub fn vram(&self) -> &[u8] {
+        self.mem.vram()
+    }
+
+    /// Work RAM, 0xC000 to 0xDFFF
+    pub fn wram(&self) -> &[u8] {
+        self.mem.wram()
+    }
+
+    /// Object attribute memory, 0xFE00 to 0xFE9F
+    pub fn oam(&self) -> &[u8] {
+        self.mem.oam()
+    }
+
+    /// High RAM, 0xFF80 to 0xFFFE
+    pub fn hram(&self) -> &[u8] {
+        self.mem.hram()
+    }
+
+    /// All of the cartridge RAM, every bank in order, no matter which bank
+    /// is mapped to 0xA000 or whether the game enabled the RAM. Empty for
+    /// cartridges without RAM.
+    pub fn cartridge_ram(&self) -> &[u8] {
+        self.mem.cartridge_ram()
+    }
+
+    /// Mutable [Ruboy::vram]. Writes through it skip everything a
+    /// [Ruboy::write_memory] would see, like watchpoints.
+    #[cfg(feature = "debug_tools")]
+    pub fn vram_mut(&mut self) -> &mut [u8] {
+        self.mem.vram_mut()
+    }
+
+    /// Mutable [Ruboy::wram], see [Ruboy::vram_mut]
+    #[cfg(feature = "debug_tools")]
+    pub fn wram_mut(&mut self) -> &mut [u8] {
+        self.mem.wram_mut()
+    }
+
+    /// Mutable [Ruboy::oam], see [Ruboy::vram_mut]
+    #[cfg(feature = "debug_tools")]
+    pub fn oam_mut(&mut self) -> &mut [u8] {
+        self.mem.oam_mut()
+    }
+
+    /// Mutable [Ruboy::hram], see [Ruboy::vram_mut]
+    #[cfg(feature = "debug_tools")]
+    pub fn hram_mut(&mut self) -> &mut [u8] {
+        self.mem.hram_mut()
+    }
+
+    /// Injects a fault into the cartridge bus, replacing any previously
+    /// injected fault. See [CartridgeFault].
+    pub fn inject_cartridge_fault(&mut self, fault: CartridgeFault) {
+        self.mem.cart_fault.inject(fault);
+    }
+
+    /// Removes an injected cartridge fault, reconnecting the cartridge
+    pub fn clear_cartridge_fault(&mut self) {
+        self.mem.cart_fault.clear();
+    }
+
+    /// Whether an injected cartridge fault is currently active
+    pub fn cartridge_fault_active(&self) -> bool {
+        self.mem.cart_fault.triggered()
+    }
+
+    /// The header of the inserted cartridge. See [RomMeta::capabilities]
+    /// for the features it needs.
+    pub fn rom_meta(&self) -> &RomMeta {
+        self.mem.rom_meta()
+    }
+
+    /// Returns the cartridge real time clock, brought up to date with the host
+    /// clock, or [None] if the cartridge has no clock. Store it alongside the
+    /// save file (see [Rtc::to_save_bytes]) to keep the clock running while
+    /// the emulator is closed.
+    pub fn save_rtc(&mut self) -> Option<Rtc> {
+        self.mem.rtc_mut().map(|rtc| {
+            rtc.sync();
+            *rtc
+        })
+    }
 
-        for _ in 0..(cycles_to_run as usize) {
-            let (new_joypad_reg_value, can_raise_joypad_interrupt) =
-                apply_input_to(self.mem.io_registers.joypad, self.input.get_new_inputs());
+    /// Replaces the cartridge real time clock with a previously saved one.
+    /// Time that passed since the clock was saved is applied on the next
+    /// access, unless the clock was halted by the game. A clock running on
+    /// emulated time keeps doing so, without applying the time that passed.
+    /// Returns false if the cartridge has no clock.
+    pub fn load_rtc(&mut self, saved: Rtc) -> bool {
+        match self.mem.rtc_mut() {
+            Some(rtc) => {
+                let clock = rtc.clock();
+                *rtc = saved;
 
-            self.mem.io_registers.joypad = new_joypad_reg_value;
-            if can_raise_joypad_interrupt {
-                self.mem.io_registers.interrupts_requested.set_joypad(true);
+                if clock != RtcClock::Host {
+                    rtc.set_clock(clock);
+                }
+
+                true
             }
+            None => false,
+        }
+    }
+
+    /// Replaces all of the cartridge RAM, e.g. with a `.sav` file made by
+    /// another emulator. Returns false, leaving the RAM unchanged, if `data`
+    /// is not exactly as large as the RAM of the cartridge.
+    pub fn load_cartridge_ram(&mut self, data: &[u8]) -> bool {
+        let ram = self.mem.cartridge_ram_mut();
 
-            self.cpu.run_cycle(&mut self.mem)?;
-            self.ppu.run_cycle(&mut self.mem)?;
-            self.mem.dma_cycle().map_err(|e| RuboyErr::Dma(e))?;
+        if ram.len() != data.len() {
+            return false;
         }
 
-        Ok(cycles_to_run as usize)
+        ram.copy_from_slice(data);
+        true
     }
-}
 
-fn split_f64(f: f64) -> (i64, f64) {
-    let truncated = f.trunc();
+    /// Stores the cartridge data that has to survive between sessions: the
+    /// cartridge RAM if it is battery backed, and the real time clock. Every
+    /// kind of data is stored under `name` with its own extension, so
+    /// `name.sav` and `name.rtc`.
+    pub fn save_persistent<S: StorageProvider>(
+        &mut self,
+        storage: &mut S,
+        name: &str,
+    ) -> Result<(), S::Err> {
+        (self as &mut dyn EmulatorCore<V>).save_persistent(storage, name)
+    }
 
-    (truncated as i64, f - truncated)
+    /// Loads the data stored by [Ruboy::save_persistent]. Data that was
+    /// never stored is left at its power-on state.
+    pub fn load_persistent<S: StorageProvider>(
+        &mut self,
+        storage: &S,
+        name: &str,
+    ) -> Result<(), LoadPersistentErr<S::Err>> {
+        (self as &mut dyn EmulatorCore<V>).load_persistent(storage, name)
+    }
+
+    /// Returns a digest of the current emulator state: the CPU registers,
+    /// I/O registers and all internal RAM. The digest is stable across
+    /// platforms and Ruboy versions with the same state layout, so it can be
+    /// used to verify that two runs start from (or arrive at) the same state.
+    pub fn state_digest(&self) -> u64 {
+        let mut digest = StateDigest::new();
+
+        self.cpu.digest_into(&mut digest);
+        self.mem.digest_into(&mut digest);
+
+        digest.finish()
+    }
+
+    /// Serializes the complete emulator state. Host settings like the speed,
+    /// audio output and attached debuggers are not part of the state.
+    ///
+    /// The state can only be loaded into an instance running the same ROM,
+    /// by the same version of Ruboy.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+
+        w.write_bytes(&STATE_MAGIC);
+        STATE_VERSION.save_state(&mut w);
+        self.mem.rom_meta().header_hash().save_state(&mut w);
+
+        self.cpu.save_state(&mut w);
+        self.ppu.save_state(&mut w);
+        self.mem.save_state(&mut w);
+
+        w.finish()
+    }
+
+    /// Restores a state made with [Ruboy::save_state]. On error, the
+    /// emulator is left unchanged.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateLoadErr> {
+        let mut r = StateReader::new(state);
+
+        if r.read_array()? != STATE_MAGIC {
+            return Err(StateLoadErr::BadMagic);
+        }
+
+        let mut version = 0u16;
+        version.load_state(&mut r)?;
+
+        if version != STATE_VERSION {
+            return Err(StateLoadErr::Version(version));
+        }
+
+        let mut header_hash = 0u64;
+        header_hash.load_state(&mut r)?;
+
+        if header_hash != self.mem.rom_meta().header_hash() {
+            return Err(StateLoadErr::WrongRom);
+        }
+
+        // Components are overwritten one by one, so keep a copy to roll back
+        // to if the state turns out to be invalid halfway through
+        let backup = self.save_state();
+
+        let res = self
+            .cpu
+            .load_state(&mut r)
+            .and_then(|_| self.ppu.load_state(&mut r))
+            .and_then(|_| self.mem.load_state(&mut r))
+            .and_then(|_| r.finish());
+
+        match res {
+            Ok(()) => {
+                self.cycle_accumulator = 0.0;
+
+                // The calls in the state aren't known
+                if let Some(calls) = &mut self.cpu.call_stack {
+                    calls.clear();
+                }
+
+                if let Some(sgb) = &mut self.mem.io_registers.sgb {
+                    sgb.mark_border_changed();
+                    self.ppu.frame_mut().set_sgb(Some(sgb.screen()));
+                }
+
+                Ok(())
+            }
+            Err(e) => {
+                self.load_state(&backup)
+                    .expect("Backup of the current state is always valid");
+                Err(e)
+            }
+        }
+    }
+
+    /// Stores a [Ruboy::save_state] under `name`, as `name.state`
+    pub fn save_state_to<S: StorageProvider>(
+        &self,
+        storage: &mut S,
+        name: &str,
+    ) -> Result<(), S::Err> {
+        (self as &dyn EmulatorCore<V>).save_state_to(storage, name)
+    }
+
+    /// Restores the state stored by [Ruboy::save_state_to]. Returns false if
+    /// no state is stored under `name`.
+    pub fn load_state_from<S: StorageProvider>(
+        &mut self,
+        storage: &S,
+        name: &str,
+    ) -> Result<bool, LoadPersistentErr<S::Err>> {
+        (self as &mut dyn EmulatorCore<V>).load_state_from(storage, name)
+    }
+
+    /// The amount of frames drawn since power on
+    pub fn frame_count(&self) -> u64 {
+        self.ppu.frame_count()
+    }
+
+    /// The cycles ran since the instance was created, at [CLOCK_SPEED_HZ].
+    /// Only ever goes up: resetting or loading a state doesn't change it.
+    /// Frames, audio samples and serial transfers are timestamped with it.
+    pub const fn cycles_elapsed(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Sets the colors frames are shown in. Every frame sent to the output
+    /// from now on carries this palette, see [Frame::palette].
+    pub fn set_palette(&mut self, palette: DmgPalette) {
+        self.ppu.set_palette(palette);
+    }
+
+    pub fn palette(&self) -> &DmgPalette {
+        self.ppu.frame().palette()
+    }
+
+    /// Switches between drawing pixel by pixel and drawing whole lines at
+    /// once, from the next line on. See [RenderMode].
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.ppu.set_render_mode(mode);
+    }
+
+    pub fn render_mode(&self) -> RenderMode {
+        self.ppu.render_mode()
+    }
+
+    /// Skips drawing frames to save work, from the frame after the next on.
+    /// Emulation keeps the same timing, only the frames aren't drawn or
+    /// sent to the output. Frame observers see the last drawn frame again
+    /// for skipped frames. See [Frameskip].
+    pub fn set_frameskip(&mut self, frameskip: Frameskip) {
+        self.frameskip = frameskip;
+    }
+
+    pub fn frameskip(&self) -> Frameskip {
+        self.frameskip
+    }
+
+    /// Whether the frame being drawn, or the one just completed during
+    /// VBlank, is skipped. The frame buffer then still holds the last drawn
+    /// frame.
+    pub fn frame_skipped(&self) -> bool {
+        self.ppu.skipping()
+    }
+
+    /// Changes which ROM banks are kept in memory. Going to
+    /// [BankCaching::All] reads the whole ROM right away.
+    pub fn set_bank_caching(&mut self, caching: BankCaching) -> Result<(), R::Err> {
+        self.mem.set_bank_caching(caching)
+    }
+
+    /// Sends the last drawn frame to the output again, e.g. after loading a
+    /// state while emulation is not running
+    pub fn present_frame(&mut self) -> Result<(), V::Err> {
+        self.ppu.present()
+    }
+
+    /// Attaches a debugger. From now on, [Ruboy::step] stops at the
+    /// debugger's breakpoints and watchpoints, and does nothing while it is
+    /// paused.
+    pub fn attach_debugger(&mut self, debugger: Debugger) {
+        self.mem.debugger = Some(debugger);
+    }
+
+    pub fn detach_debugger(&mut self) -> Option<Debugger> {
+        self.mem.debugger.take()
+    }
+
+    pub fn debugger(&self) -> Option<&Debugger> {
+        self.mem.debugger.as_ref()
+    }
+
+    pub fn debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.mem.debugger.as_mut()
+    }
+
+    /// Attaches a bus snooper, which from now on sees (and may change) every
+    /// memory access of the CPU. Replaces any previously attached snooper.
+    pub fn attach_snooper(&mut self, snooper: impl BusSnooper + 'static) {
+        self.mem.snooper = Some(RefCell::new(Box::new(snooper)));
+    }
+
+    /// Detaches the bus snooper, handing it back
+    pub fn detach_snooper(&mut self) -> Option<Box<dyn BusSnooper>> {
+        self.mem.snooper.take().map(RefCell::into_inner)
+    }
+
+    /// Attaches a frame observer, which from now on gets every finished
+    /// frame. Replaces any previously attached observer.
+    pub fn attach_frame_observer(&mut self, observer: impl FrameObserver + 'static) {
+        self.frame_observer = Some(Box::new(observer));
+    }
+
+    /// Detaches the frame observer, handing it back
+    pub fn detach_frame_observer(&mut self) -> Option<Box<dyn FrameObserver>> {
+        self.frame_observer.take()
+    }
+
+    /// Attaches a memory inspector, which from now on gets to look at memory
+    /// after every finished frame. Replaces any previously attached inspector.
+    pub fn attach_memory_inspector(&mut self, inspector: impl MemoryInspector + 'static) {
+        self.memory_inspector = Some(Box::new(inspector));
+    }
+
+    /// Detaches the memory inspector, handing it back
+    pub fn detach_memory_inspector(&mut self) -> Option<Box<dyn MemoryInspector>> {
+        self.memory_inspector.take()
+    }
+
+    /// Sets the hooks that from now on see every line the PPU starts, every
+    /// mode it switches to and every frame it finishes. Replaces any
+    /// previously set hooks.
+    pub fn set_ppu_hooks(&mut self, hooks: impl PpuHooks + 'static) {
+        self.ppu_hooks = Some(Box::new(hooks));
+    }
+
+    /// Removes the PPU hooks, handing them back
+    pub fn clear_ppu_hooks(&mut self) -> Option<Box<dyn PpuHooks>> {
+        self.ppu_hooks.take()
+    }
+
+    /// Attaches a rumble handler, which from now on is told whenever the
+    /// rumble motor of the cartridge turns on or off. Replaces any previously
+    /// attached handler.
+    pub fn attach_rumble_handler(&mut self, handler: impl RumbleHandler + 'static) {
+        self.rumble_handler = Some(Box::new(handler));
+    }
+
+    /// Detaches the rumble handler, handing it back
+    pub fn detach_rumble_handler(&mut self) -> Option<Box<dyn RumbleHandler>> {
+        self.rumble_handler.take()
+    }
+
+    /// Plugs a device into the link port, which from now on exchanges a byte
+    /// with every serial transfer the game clocks. Replaces any previously
+    /// attached device.
+    pub fn attach_link_device(&mut self, device: impl LinkDevice + 'static) {
+        self.link_device = Some(Box::new(device));
+    }
+
+    /// Unplugs the link device, handing it back
+    pub fn detach_link_device(&mut self) -> Option<Box<dyn LinkDevice>> {
+        self.link_device.take()
+    }
+
+    /// Points the infrared port at `transceiver`, replacing whatever it
+    /// faced before. Only the Game Boy Color has the port, so on the models
+    /// emulated so far the game never sees it.
+    pub fn attach_ir_transceiver(&mut self, transceiver: impl IrTransceiver + 'static) {
+        self.mem.io_registers.infrared.attach(Box::new(transceiver));
+    }
+
+    /// Takes away what faces the infrared port, handing it back
+    pub fn detach_ir_transceiver(&mut self) -> Option<Box<dyn IrTransceiver>> {
+        self.mem.io_registers.infrared.detach()
+    }
+
+    /// Changes when the [InputHandler] is asked for new inputs
+    pub fn set_input_polling(&mut self, polling: InputPolling) {
+        self.mem.io_registers.joypad.set_polling(polling);
+    }
+
+    pub fn input_polling(&self) -> InputPolling {
+        self.mem.io_registers.joypad.polling()
+    }
+
+    /// Presses and releases buttons right away, without waiting for the
+    /// next poll of the [InputHandler]. Lets frontends forward input events
+    /// as they happen. These changes are not seen by the handler, so they
+    /// are not recorded in a [movie::Movie].
+    pub fn set_inputs(&mut self, inputs: GbInputs) {
+        self.mem.set_inputs(inputs);
+    }
+
+    /// The buttons currently held down
+    pub fn inputs(&self) -> GbInputs {
+        self.mem.io_registers.joypad.inputs()
+    }
+
+    /// Sets the buttons of another player, for Super Game Boy games that
+    /// asked for more joypads, see [Ruboy::players]. Player 0 is the one
+    /// the [InputHandler] plays, players 1 to 3 only have the inputs set
+    /// here.
+    pub fn set_player_inputs(&mut self, player: usize, inputs: GbInputs) {
+        if self
+            .mem
+            .io_registers
+            .joypad
+            .set_player_inputs(player, inputs)
+        {
+            self.mem.io_registers.interrupts_requested.set_joypad(true);
+        }
+    }
+
+    /// The amount of joypads the game asked for, 1 unless a Super Game Boy
+    /// game asks for 2 or 4
+    pub fn players(&self) -> u8 {
+        self.mem.io_registers.joypad.players()
+    }
+
+    /// The Super Game Boy, when running one of its models with a game that
+    /// supports it. See [sgb].
+    pub fn sgb(&self) -> Option<&Sgb> {
+        self.mem.io_registers.sgb.as_deref()
+    }
+
+    pub fn input(&self) -> &I {
+        &self.input
+    }
+
+    pub fn input_mut(&mut self) -> &mut I {
+        &mut self.input
+    }
+
+    pub fn registers(&self) -> CpuRegisters {
+        self.cpu.registers_snapshot()
+    }
+
+    /// Reads memory without any side effects, ignoring the VRAM/OAM access
+    /// restrictions and watchpoints
+    pub fn peek(&self, addr: u16) -> Result<u8, ReadError> {
+        self.mem.read8_direct(addr)
+    }
+
+    /// Reads a range of memory, the same way as [Ruboy::peek]
+    pub fn read_memory(&self, range: RangeInclusive<u16>) -> Result<Vec<u8>, ReadError> {
+        range.map(|addr| self.mem.read8_direct(addr)).collect()
+    }
+
+    /// Writes the bytes to consecutive addresses starting at `addr`,
+    /// ignoring the VRAM/OAM access restrictions and watchpoints. Writes
+    /// behave like they would from the CPU otherwise, so writing to the
+    /// cartridge ROM area talks to the memory bank controller, and writing to
+    /// I/O registers has their usual side effects. Stops at the end of the
+    /// address space.
+    pub fn write_memory(&mut self, addr: u16, bytes: &[u8]) -> Result<(), WriteError> {
+        for (addr, byte) in (addr..=u16::MAX).zip(bytes) {
+            self.mem.write8_direct(addr, *byte)?;
+        }
+
+        Ok(())
+    }
+
+    /// The ROM bank mapped to 0x4000 to 0x7FFF right now
+    pub fn current_bank(&self) -> u16 {
+        // Never the boot ROM, so always mapped
+        self.bank_at(0x4000).unwrap_or(1)
+    }
+
+    /// The ROM bank the address is mapped to right now, as the mapper
+    /// resolves it. [None] for addresses outside of the cartridge ROM,
+    /// including the boot ROM while it is mapped.
+    pub fn bank_at(&self, addr: u16) -> Option<u16> {
+        self.mem.rom_bank(addr).map(|bank| bank as u16)
+    }
+
+    /// The region of the memory map the address currently belongs to
+    pub fn memory_region(&self, addr: u16) -> MemRegion {
+        self.mem.map_to_region(addr)
+    }
+
+    /// Runs until the current instruction is finished, even if the
+    /// debugger is paused. Breakpoints are ignored. Returns the amount of
+    /// cycles ran.
+    pub fn step_instruction(&mut self) -> Result<usize, RuboyErr<V>> {
+        let mut cycles = 0;
+
+        loop {
+            if let Err(e) = self.run_cycle() {
+                self.fail(e)?;
+                return Ok(cycles);
+            }
+
+            cycles += 1;
+
+            if self.cpu.at_instruction_boundary() {
+                break;
+            }
+        }
+
+        if let Some(debugger) = &mut self.mem.debugger {
+            let reason = debugger.take_watch_hit().unwrap_or(StopReason::Step);
+            debugger.stop(reason);
+        }
+
+        Ok(cycles)
+    }
+
+    /// Runs until the PPU finished drawing the next frame (the start of
+    /// VBlank), even if the debugger is paused. Stops early at breakpoints
+    /// and watchpoints. While the LCD is off no frames are drawn, so at most
+    /// a single frame worth of cycles is ran.
+    pub fn step_frame(&mut self) -> Result<FrameEvent<'_>, RuboyErr<V>> {
+        if let Some(debugger) = &mut self.mem.debugger {
+            debugger.resume();
+        }
+
+        let event = match self.run_until_vblank()? {
+            RunUntil::Stopped(reason) => return Ok(FrameEvent::Stopped(reason)),
+            RunUntil::Reached(_) => FrameEvent::Completed(self.ppu.frame()),
+            RunUntil::Limit => FrameEvent::LcdOff,
+        };
+
+        if let Some(debugger) = &mut self.mem.debugger {
+            debugger.stop(StopReason::Frame);
+        }
+
+        Ok(event)
+    }
+
+    /// Runs one cycle at a time until `condition` holds after a cycle, or
+    /// `max_cycles` were ran. Stops early if the debugger stops execution,
+    /// and does nothing while the debugger is paused.
+    pub fn run_until(
+        &mut self,
+        max_cycles: u64,
+        condition: impl FnMut(&Self) -> bool,
+    ) -> Result<RunUntil, RuboyErr<V>> {
+        self.run_until_reading(max_cycles, true, condition)
+    }
+
+    /// Same as [Ruboy::run_until]. Unless `condition` reads the PPU, it is
+    /// only caught up once done instead of after every cycle.
+    fn run_until_reading(
+        &mut self,
+        max_cycles: u64,
+        reads_ppu: bool,
+        condition: impl FnMut(&Self) -> bool,
+    ) -> Result<RunUntil, RuboyErr<V>> {
+        let until = self.run_until_behind(max_cycles, reads_ppu, condition)?;
+
+        // Whoever looks at the instance next sees the PPU caught up
+        if let Err(e) = self.catch_up_ppu() {
+            self.fail(e)?;
+        }
+
+        Ok(until)
+    }
+
+    fn run_until_behind(
+        &mut self,
+        max_cycles: u64,
+        reads_ppu: bool,
+        mut condition: impl FnMut(&Self) -> bool,
+    ) -> Result<RunUntil, RuboyErr<V>> {
+        let stop_reason = |ruboy: &Self| ruboy.mem.debugger.as_ref().and_then(|d| d.stop_reason());
+
+        if let Some(reason) = stop_reason(self) {
+            return Ok(RunUntil::Stopped(reason));
+        }
+
+        for ran in 1..=max_cycles {
+            let mut failed = self.run_cycles_behind(1)? == 0;
+
+            if reads_ppu {
+                if let Err(e) = self.catch_up_ppu() {
+                    self.fail(e)?;
+                    failed = true;
+                }
+            }
+
+            if let Some(reason) = stop_reason(self) {
+                return Ok(RunUntil::Stopped(reason));
+            }
+
+            // Only a failure stops a cycle without the debugger stopping
+            if let Some(fault) = self.fault.as_ref().filter(|_| failed) {
+                return Ok(RunUntil::Stopped(StopReason::Fault(fault.pc)));
+            }
+
+            if condition(self) {
+                return Ok(RunUntil::Reached(ran));
+            }
+        }
+
+        Ok(RunUntil::Limit)
+    }
+
+    /// Runs until the next frame is completed (the start of VBlank). Gives up
+    /// after a frame worth of cycles, which only happens when the LCD is off.
+    pub fn run_until_vblank(&mut self) -> Result<RunUntil, RuboyErr<V>> {
+        let frames = self.ppu.frame_count();
+
+        self.run_until_reading(FRAME_CYCLES as u64, true, |ruboy| {
+            ruboy.ppu.frame_count() != frames
+        })
+    }
+
+    /// Runs until the CPU is about to execute the instruction at `addr`. At
+    /// least one cycle is ran, so this also works when already at `addr`.
+    pub fn run_until_pc(&mut self, addr: u16, max_cycles: u64) -> Result<RunUntil, RuboyErr<V>> {
+        self.run_until_reading(max_cycles, false, |ruboy| {
+            ruboy.cpu.at_instruction_boundary() && ruboy.cpu.pc() == addr
+        })
+    }
+
+    /// Runs the emulator for `dt` seconds of real time, scaled by the speed
+    /// set with [Ruboy::set_speed]. Returns the amount of cycles ran.
+    pub fn step(&mut self, dt: f64) -> Result<usize, RuboyErr<V>> {
+        debug!(Subsystem::Emulator, "Stepping emulator {} seconds", dt);
+
+        if self.paused || self.mem.debugger.as_ref().is_some_and(|d| d.paused()) {
+            return Ok(0);
+        }
+
+        let cycles_dt = dt * self.speed * CLOCK_SPEED_HZ_F64;
+        let (mut cycles_to_run, accumulated) = split_f64(cycles_dt);
+
+        self.cycle_accumulator += accumulated;
+        let (extra_cycles, new_accumulator) = split_f64(self.cycle_accumulator);
+
+        cycles_to_run += extra_cycles;
+        self.cycle_accumulator = new_accumulator;
+
+        debug_assert!(cycles_to_run >= 0);
+
+        // Of the frames ran by this step, only the last is shown
+        if let Frameskip::Auto { max } = self.frameskip {
+            let frames = cycles_to_run as u64 / FRAME_CYCLES as u64;
+            self.auto_skip = frames.saturating_sub(1).min(max.into()) as u8;
+        }
+
+        trace!(
+            Subsystem::Emulator,
+            "Running {} cycles",
+            cycles_to_run as usize
+        );
+
+        self.run_cycles(cycles_to_run as u64)
+            .map(|ran| ran as usize)
+    }
+
+    /// Runs until the APU produced `samples` more audio samples, so that
+    /// frontends can clock emulation off the audio device: asking for as many
+    /// samples as the device is about to play never drifts from it. Like
+    /// [Ruboy::step], runs nothing while paused, and also when audio is
+    /// disabled. Stops early if the debugger stops execution. Returns the
+    /// amount of cycles ran.
+    pub fn step_until_audio(&mut self, samples: usize) -> Result<usize, RuboyErr<V>> {
+        if self.paused
+            || self.mem.debugger.as_ref().is_some_and(|d| d.paused())
+            || self.audio_sample_rate().is_none()
+        {
+            return Ok(0);
+        }
+
+        let mut produced = 0;
+        let mut ran = 0;
+
+        while produced < samples {
+            let last = self.last_sample_cycle;
+
+            if self.run_cycles_behind(1)? == 0 {
+                break;
+            }
+
+            ran += 1;
+
+            if self.last_sample_cycle != last {
+                produced += 1;
+            }
+        }
+
+        if let Err(e) = self.catch_up_ppu() {
+            self.fail(e)?;
+        }
+
+        Ok(ran)
+    }
+
+    /// Runs exactly the given amount of cycles, stopping early if the
+    /// debugger hits a breakpoint or watchpoint. Unlike [Ruboy::step], no
+    /// fractional cycles are carried over between calls, so runs are exactly
+    /// reproducible. Returns the amount of cycles ran.
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<u64, RuboyErr<V>> {
+        let ran = self.run_cycles_behind(cycles)?;
+
+        // Whoever looks at the instance next sees the PPU caught up
+        if let Err(e) = self.catch_up_ppu() {
+            self.fail(e)?;
+        }
+
+        Ok(ran)
+    }
+
+    /// Same as [Ruboy::run_cycles], but may leave the PPU behind
+    fn run_cycles_behind(&mut self, cycles: u64) -> Result<u64, RuboyErr<V>> {
+        #[cfg(feature = "tracing")]
+        let mut frame_span = self.enter_frame();
+
+        for ran in 0..cycles {
+            if self.mem.debugger.is_some() && self.cpu.at_instruction_boundary() {
+                // Conditions can look at the LCD registers
+                if let Err(e) = self.catch_up_ppu() {
+                    self.fail(e)?;
+                    return Ok(ran);
+                }
+
+                let pc = self.cpu.pc();
+                let bank = self.bank_at(pc);
+
+                let condition = match &self.mem.debugger {
+                    Some(debugger) if debugger.armed() => debugger.check_conditions(pc, self),
+                    _ => None,
+                };
+
+                if let Some(debugger) = &mut self.mem.debugger {
+                    if let Some(reason) = debugger.check_breakpoint(pc, bank, condition) {
+                        debugger.stop(reason);
+                        return Ok(ran);
+                    }
+                }
+            }
+
+            if let Err(e) = self.run_cycle() {
+                self.fail(e)?;
+                return Ok(ran);
+            }
+
+            #[cfg(feature = "tracing")]
+            if self.frame_span.0 != self.ppu.frame_count() {
+                drop(frame_span);
+                frame_span = self.enter_frame();
+            }
+
+            if let Some(debugger) = &mut self.mem.debugger {
+                if let Some(reason) = debugger.take_watch_hit() {
+                    debugger.stop(reason);
+                    return Ok(ran + 1);
+                }
+            }
+        }
+
+        Ok(cycles)
+    }
+
+    /// Enters the span of the frame the PPU is drawing, starting a new one
+    /// if it moved on to the next frame
+    #[cfg(feature = "tracing")]
+    fn enter_frame(&mut self) -> tracing::span::EnteredSpan {
+        let number = self.ppu.frame_count();
+
+        if self.frame_span.0 != number {
+            let span = if logging::enabled(Subsystem::Emulator, log::Level::Debug) {
+                tracing::debug_span!(target: Subsystem::Emulator.target(), "frame", number)
+            } else {
+                tracing::Span::none()
+            };
+
+            self.frame_span = (number, span);
+        }
+
+        self.frame_span.1.clone().entered()
+    }
+
+    /// Keeps the failure as a [Fault], and handles it as the [ErrorPolicy]
+    /// says. Returns the error if emulation should stop.
+    fn fail(&mut self, err: RuboyErr<V>) -> Result<(), RuboyErr<V>> {
+        let pc = self.cpu.pc();
+
+        let mut error = err.to_string();
+
+        // The output can't be borrowed as a 'static error, so that one is
+        // just displayed
+        let mut source: Option<&(dyn core::error::Error + 'static)> = match &err {
+            RuboyErr::Cpu(e) => Some(e),
+            RuboyErr::Dma(e) | RuboyErr::Reset(e) => Some(e),
+            RuboyErr::Ppu(e) => {
+                error = format!("{}: {}", error, e);
+                None
+            }
+        };
+
+        while let Some(cause) = source {
+            error = format!("{}: {}", error, cause);
+            source = cause.source();
+        }
+
+        let fault = Fault {
+            pc,
+            bank: self.bank_at(pc),
+            error,
+        };
+
+        log::error!("{}", fault);
+        self.fault = Some(fault);
+
+        if self.error_policy == ErrorPolicy::Stop {
+            return Err(err);
+        }
+
+        self.paused = true;
+
+        if let Some(debugger) = &mut self.mem.debugger {
+            debugger.stop(StopReason::Fault(pc));
+        }
+
+        Ok(())
+    }
+
+    /// Applies the inputs of the handler, if they changed
+    fn poll_inputs(&mut self) {
+        if let Some(inputs) = self.input.poll_inputs() {
+            self.mem.set_inputs(inputs);
+        }
+    }
+
+    fn run_cycle(&mut self) -> Result<(), RuboyErr<V>> {
+        if self.mem.joypad_cycle() {
+            self.poll_inputs();
+        }
+
+        self.mem.cart_fault.cycle();
+
+        // STOP halts the system clock, which DIV counts
+        if !self.cpu.stopped() {
+            self.mem.timer_cycle();
+        }
+
+        self.mem.rtc_cycle();
+        if self.mem.serial_cycle() {
+            if let Some(device) = &mut self.link_device {
+                let serial = &mut self.mem.io_registers.serial;
+                serial.receive(device.exchange(serial.sent(), self.cycles + 1));
+            }
+        }
+        if self.mem.io_registers.apu.cycle() {
+            self.last_sample_cycle = Some(self.cycles + 1);
+        }
+
+        // The CPU only touches memory when it starts an instruction or
+        // interrupt, so the PPU only has to catch up right before that
+        if self.cpu.at_instruction_boundary() {
+            self.catch_up_ppu()?;
+        }
+
+        self.cpu.run_cycle(&mut self.mem)?;
+
+        if let Some(handler) = &mut self.rumble_handler {
+            let on = self.mem.rumble();
+
+            if on != self.rumble_on {
+                self.rumble_on = on;
+                handler.rumble(on);
+            }
+        }
+
+        self.ppu_behind += 1;
+        self.cycles += 1;
+
+        // Hooks see every cycle, and DMA writes OAM when it finishes
+        if self.ppu_hooks.is_some() || self.mem.dma_active() {
+            self.catch_up_ppu()?;
+        }
+
+        self.mem.dma_cycle().map_err(|e| RuboyErr::Dma(e))?;
+
+        Ok(())
+    }
+
+    /// Runs the cycles the PPU is behind on, handling every frame completed
+    /// on the way
+    fn catch_up_ppu(&mut self) -> Result<(), RuboyErr<V>> {
+        while self.ppu_behind > 0 {
+            let frames = self.ppu.frame_count();
+            let before = self
+                .ppu_hooks
+                .is_some()
+                .then(|| (self.ppu.lcd_mode(), self.mem.io_registers.lcd_y));
+
+            let ran = self.ppu.run_cycles(&mut self.mem, self.ppu_behind)?;
+            self.ppu_behind -= ran;
+
+            if let (Some(hooks), Some((mode, ly))) = (&mut self.ppu_hooks, before) {
+                let regs = LcdRegisters::from_io(&self.mem.io_registers);
+                let new_mode = self.ppu.lcd_mode();
+
+                // Turning the LCD off resets LY, but starts no line
+                if new_mode != LcdMode::Off && (regs.ly != ly || mode == LcdMode::Off) {
+                    hooks.scanline_start(&regs);
+                }
+
+                if new_mode != mode {
+                    hooks.mode_changed(new_mode, &regs);
+                }
+            }
+
+            if self.ppu.frame_count() != frames {
+                self.frame_completed()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn frame_completed(&mut self) -> Result<(), RuboyErr<V>> {
+        if let Some(sgb) = &mut self.mem.io_registers.sgb {
+            sgb.frame_completed(self.ppu.frame_mut());
+
+            if sgb.take_border_changed() {
+                self.ppu
+                    .output_border(sgb.border())
+                    .map_err(|e| RuboyErr::Ppu(PpuErr::VBlank(VBlankErr::OutputErr(e))))?;
+            }
+        }
+
+        if let Some(hooks) = &mut self.ppu_hooks {
+            let regs = LcdRegisters::from_io(&self.mem.io_registers);
+            hooks.frame_completed(self.ppu.frame(), self.ppu.frame_count(), &regs);
+        }
+
+        if let Some(observer) = &mut self.frame_observer {
+            // The PPU stops right after finishing the frame
+            let cycle = self.cycles - self.ppu_behind as u64;
+            observer.frame(self.ppu.frame(), self.ppu.frame_count(), cycle);
+        }
+
+        if let Some(mut inspector) = self.memory_inspector.take() {
+            inspector.frame(self, self.ppu.frame_count());
+            self.memory_inspector = Some(inspector);
+        }
+
+        if self.input_polling() == InputPolling::VBlank {
+            self.poll_inputs();
+        }
+
+        self.input.frame_started();
+        self.plan_next_frame();
+
+        Ok(())
+    }
+
+    /// Decides whether the frame after the VBlank that just started is drawn
+    fn plan_next_frame(&mut self) {
+        let skip = match self.frameskip {
+            Frameskip::Off => 0,
+            Frameskip::Fixed(skip) => skip,
+            Frameskip::Auto { .. } => self.auto_skip,
+        };
+
+        let skip_next = self.skipped < skip;
+
+        self.skipped = if skip_next { self.skipped + 1 } else { 0 };
+        self.ppu.set_skip_next(skip_next);
+    }
+}
+
+fn split_f64(f: f64) -> (i64, f64) {
+    let truncated = f.trunc();
+
+    (truncated as i64, f - truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debug::{Access, Opcode, Watchpoint};
+    use crate::testutils::{
+        blank_rom, fix_header_checksum, NullInput, NullOutput, Recorder, TestRuboy,
+    };
+    use std::io::Cursor;
+
+    fn new_with_model(model: GbModel) -> TestRuboy {
+        Ruboy::with_model(blank_rom(0x3C), NullOutput, NullInput, model).unwrap()
+    }
+
+    /// Records the byte at 0xC000 after every frame
+    impl MemoryInspector for Recorder<Option<u8>> {
+        fn frame(&mut self, memory: &dyn flatmem::FlatMemory, _number: u64) {
+            self.record(memory.peek_flat(0xC000));
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum PpuEvent {
+        Line(u8),
+        Mode(LcdMode),
+        Frame(u64),
+    }
+
+    impl PpuHooks for Recorder<PpuEvent> {
+        fn scanline_start(&mut self, regs: &LcdRegisters) {
+            self.record(PpuEvent::Line(regs.ly));
+        }
+
+        fn mode_changed(&mut self, mode: LcdMode, _regs: &LcdRegisters) {
+            self.record(PpuEvent::Mode(mode));
+        }
+
+        fn frame_completed(&mut self, _frame: &Frame, number: u64, regs: &LcdRegisters) {
+            assert_eq!(FRAME_Y as u8, regs.ly);
+            self.record(PpuEvent::Frame(number));
+        }
+    }
+
+    /// Holds Start, recording every poll
+    impl InputHandler for Recorder<GbInputs> {
+        fn poll_inputs(&mut self) -> Option<GbInputs> {
+            let inputs = GbInputs {
+                start: true,
+                ..Default::default()
+            };
+
+            self.record(inputs);
+            Some(inputs)
+        }
+    }
+
+    #[test]
+    fn power_on_digest_is_deterministic() {
+        for model in GbModel::ALL {
+            assert_eq!(
+                new_with_model(model).state_digest(),
+                new_with_model(model).state_digest(),
+                "Digest for {} differs between instances",
+                model
+            );
+        }
+    }
+
+    #[test]
+    fn reset_starts_over_but_keeps_cartridge_ram() {
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x147] = 0x03; // MBC1 with RAM and battery
+        rom[0x149] = 0x02; // 8KB
+        rom[0x100..0x10F].copy_from_slice(&[
+            0x3E, 0x0A, // LD A, 0x0A
+            0xEA, 0x00, 0x00, // LD (0x0000), A
+            0x3E, 0x42, // LD A, 0x42
+            0xEA, 0x00, 0xA0, // LD (0xA000), A
+            0xEA, 0x00, 0xC0, // LD (0xC000), A
+            0x18, 0xFE, // JR -2
+        ]);
+        fix_header_checksum(&mut rom);
+
+        let start = || -> TestRuboy {
+            Ruboy::with_model(
+                Cursor::new(rom.clone()),
+                NullOutput,
+                NullInput,
+                GbModel::Dmg,
+            )
+            .unwrap()
+        };
+
+        let fresh = start();
+        let mut ruboy = start();
+        ruboy.run_cycles(10_000).unwrap();
+        assert_eq!(0x42, ruboy.wram()[0]);
+
+        ruboy.reset().unwrap();
+
+        assert_eq!(0x100, ruboy.cpu.pc());
+        assert_eq!(fresh.wram(), ruboy.wram());
+        assert_eq!(0x42, ruboy.cartridge_ram()[0]);
+    }
+
+    #[test]
+    fn load_rom_swaps_the_game() {
+        let titled = |title: &[u8]| {
+            let mut rom = blank_rom(0x00).into_inner();
+            rom[0x134..0x134 + title.len()].copy_from_slice(title);
+            fix_header_checksum(&mut rom);
+            Cursor::new(rom)
+        };
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(titled(b"FIRST"), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        ruboy.run_cycles(10_000).unwrap();
+
+        ruboy.load_rom(titled(b"SECOND")).unwrap();
+        assert_eq!("SECOND", ruboy.rom_meta().title());
+        assert_eq!(0x100, ruboy.cpu.pc());
+
+        let mut unsupported = titled(b"THIRD").into_inner();
+        unsupported[0x147] = 0x05; // MBC2
+        fix_header_checksum(&mut unsupported);
+
+        let err = ruboy.load_rom(Cursor::new(unsupported)).unwrap_err();
+        assert_eq!(Some(CartridgeMapper::MBC2), err.unsupported_mapper());
+        assert_eq!("SECOND", ruboy.rom_meta().title());
+        assert_eq!(100, ruboy.run_cycles(100).unwrap());
+    }
+
+    #[test]
+    fn fixed_frameskip_skips_between_drawn_frames() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.set_frameskip(Frameskip::Fixed(2));
+
+        let skipped: Vec<bool> = (0..9)
+            .map(|_| {
+                ruboy.step_frame().unwrap();
+                ruboy.frame_skipped()
+            })
+            .collect();
+
+        assert_eq!(
+            [false, true, true, false, true, true],
+            skipped[skipped.len() - 6..]
+        );
+    }
+
+    #[test]
+    fn auto_frameskip_shows_the_last_frame_of_a_step() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.set_frameskip(Frameskip::Auto { max: 8 });
+
+        let frame_time = FRAME_CYCLES as f64 / CLOCK_SPEED_HZ_F64;
+        ruboy.step(frame_time * 3.0).unwrap();
+        assert_eq!(2, ruboy.auto_skip);
+
+        ruboy.step(frame_time).unwrap();
+        assert_eq!(0, ruboy.auto_skip);
+
+        ruboy.set_frameskip(Frameskip::Auto { max: 1 });
+        ruboy.step(frame_time * 10.0).unwrap();
+        assert_eq!(1, ruboy.auto_skip);
+    }
+
+    #[test]
+    fn frameskip_keeps_scanline_timing() {
+        let run = |frameskip| {
+            let mut ruboy = new_with_model(GbModel::Dmg);
+            ruboy.set_render_mode(RenderMode::Scanline);
+            ruboy.set_frameskip(frameskip);
+            ruboy.run_cycles(FRAME_CYCLES as u64 * 10).unwrap();
+            ruboy.state_digest()
+        };
+
+        assert_eq!(run(Frameskip::Off), run(Frameskip::Fixed(3)));
+    }
+
+    #[test]
+    fn batched_ppu_matches_single_cycles() {
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x100..0x10E].copy_from_slice(&[
+            0xF0, 0x44, // LDH A, (LY)
+            0xE0, 0x43, // LDH (SCX), A
+            0xF0, 0x41, // LDH A, (STAT)
+            0xE0, 0x42, // LDH (SCY), A
+            0x3E, 0xC0, // LD A, 0xC0
+            0xE0, 0x46, // LDH (DMA), A
+            0x18, 0xF2, // JR -14
+        ]);
+
+        for mode in [RenderMode::Fifo, RenderMode::Scanline] {
+            let start = || -> TestRuboy {
+                let mut ruboy = Ruboy::with_model(
+                    Cursor::new(rom.clone()),
+                    NullOutput,
+                    NullInput,
+                    GbModel::Dmg,
+                )
+                .unwrap();
+                ruboy.set_render_mode(mode);
+                ruboy
+            };
+
+            let mut batched = start();
+            batched.run_cycles(FRAME_CYCLES as u64 * 3 + 1234).unwrap();
+
+            let mut single = start();
+            for _ in 0..FRAME_CYCLES * 3 + 1234 {
+                single.run_cycles(1).unwrap();
+            }
+
+            assert_eq!(3, batched.ppu.frame_count());
+            assert!(batched.save_state() == single.save_state(), "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn step_runs_nothing_while_paused() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.set_paused(true);
+
+        assert_eq!(0, ruboy.step(0.01).unwrap());
+        assert_eq!(100, ruboy.run_cycles(100).unwrap());
+
+        ruboy.set_paused(false);
+        assert!(ruboy.step(0.01).unwrap() > 0);
+    }
+
+    /// Writes to cartridge RAM, which the blank cartridge doesn't have
+    fn new_failing(policy: ErrorPolicy) -> TestRuboy {
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x100..0x108].copy_from_slice(&[
+            0x3E, 0x12, // LD A, 0x12
+            0xEA, 0x00, 0xA0, // LD (0xA000), A
+            0xC3, 0x00, 0x01, // JP 0x100
+        ]);
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        ruboy.set_error_policy(policy);
+
+        ruboy
+    }
+
+    #[test]
+    fn stop_policy_returns_error() {
+        let mut ruboy = new_failing(ErrorPolicy::Stop);
+
+        assert!(matches!(ruboy.run_cycles(100), Err(RuboyErr::Cpu(_))));
+        assert!(!ruboy.paused());
+
+        let fault = ruboy.take_fault().unwrap();
+        assert_eq!(0x102, fault.pc);
+        assert_eq!(Some(0), fault.bank);
+        assert!(fault
+            .to_string()
+            .starts_with("Emulation stopped at PC=00:0102 due to: "));
+
+        assert!(ruboy.take_fault().is_none());
+    }
+
+    #[test]
+    fn pause_policy_pauses() {
+        let mut ruboy = new_failing(ErrorPolicy::Pause);
+
+        let ran = ruboy.run_cycles(100).unwrap();
+        assert!(ran < 100);
+        assert!(ruboy.paused());
+        assert_eq!(0, ruboy.step(0.01).unwrap());
+
+        // Retrying fails again
+        assert!(matches!(
+            ruboy.run_until_vblank().unwrap(),
+            RunUntil::Stopped(StopReason::Fault(0x102))
+        ));
+        assert_eq!(0x102, ruboy.take_fault().unwrap().pc);
+    }
+
+    #[test]
+    fn ignore_policy_continues() {
+        let mut ruboy = new_failing(ErrorPolicy::Ignore);
+
+        assert_eq!(1000, ruboy.run_cycles(1000).unwrap());
+        assert!(!ruboy.paused());
+        assert!(ruboy.take_fault().is_none());
+        assert_eq!(0x12, ruboy.registers().a);
+    }
+
+    #[test]
+    fn super_game_boy_only_for_games_that_support_it() {
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x146] = 0x03;
+        rom[0x14B] = 0x33;
+        fix_header_checksum(&mut rom);
+
+        let start = |model| -> TestRuboy {
+            Ruboy::with_model(Cursor::new(rom.clone()), NullOutput, NullInput, model).unwrap()
+        };
+
+        assert!(start(GbModel::Dmg).sgb().is_none());
+        assert!(new_with_model(GbModel::Sgb).sgb().is_none());
+
+        let mut ruboy = start(GbModel::Sgb2);
+        assert!(ruboy.sgb().is_some());
+
+        match ruboy.step_frame().unwrap() {
+            FrameEvent::Completed(frame) => assert!(frame.sgb().is_some()),
+            _ => panic!("No frame drawn"),
+        }
+    }
+
+    #[test]
+    fn trace_records_executed_instructions() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.enable_trace(4);
+
+        // A blank ROM is all NOPs, which take 4 cycles each
+        ruboy.step(40.0 / CLOCK_SPEED_HZ_F64).unwrap();
+
+        let trace = ruboy.trace().unwrap();
+        let pcs: Vec<u16> = trace.entries().map(|e| e.pc).collect();
+
+        assert_eq!(vec![0x106, 0x107, 0x108, 0x109], pcs);
+    }
+
+    #[test]
+    fn trace_does_not_trigger_watchpoints() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.enable_trace(4);
+
+        // Only read for the trace, as part of the bytes after the last NOP
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint::at(0x10C, Access::Read));
+        ruboy.attach_debugger(debugger);
+
+        ruboy.step(40.0 / CLOCK_SPEED_HZ_F64).unwrap();
+
+        assert_eq!(0x109, ruboy.trace().unwrap().entries().last().unwrap().pc);
+        assert_eq!(None, ruboy.debugger().unwrap().stop_reason());
+    }
+
+    #[test]
+    fn runs_external_boot_rom() {
+        // LD A, 0x42 and LDH (0x50), A, which unmaps the boot ROM
+        let mut image = vec![0x00; BOOT_ROM_SIZE];
+        image[..4].copy_from_slice(&[0x3E, 0x42, 0xE0, 0x50]);
+
+        let mut ruboy: TestRuboy = Ruboy::with_boot_rom(
+            blank_rom(0),
+            NullOutput,
+            NullInput,
+            BootRom::External(image),
+        )
+        .unwrap();
+
+        assert_eq!(0x3E, ruboy.peek(0x0000).unwrap());
+
+        ruboy.run_cycles(20).unwrap();
+
+        assert_eq!(0x42, ruboy.registers().a);
+        assert_eq!(0x00, ruboy.peek(0x0000).unwrap());
+    }
+
+    #[test]
+    fn boot_rom_selection() {
+        let skipped: TestRuboy = Ruboy::with_boot_rom(
+            blank_rom(0x3C),
+            NullOutput,
+            NullInput,
+            BootRom::Skip(GbModel::Mgb),
+        )
+        .unwrap();
+
+        assert_eq!(
+            new_with_model(GbModel::Mgb).state_digest(),
+            skipped.state_digest()
+        );
+
+        let cgb_sized = Ruboy::<InlineAllocator, _, _, _>::with_boot_rom(
+            blank_rom(0),
+            NullOutput,
+            NullInput,
+            BootRom::External(vec![0x00; 0x900]),
+        );
+
+        assert!(matches!(cgb_sized, Err(RuboyStartErr::BootRomSize(0x900))));
+    }
+
+    #[test]
+    fn speed_scales_stepped_cycles() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.set_speed(2.0);
+
+        assert_eq!(80, ruboy.step(40.0 / CLOCK_SPEED_HZ_F64).unwrap());
+
+        ruboy.set_speed(0.5);
+
+        assert_eq!(20, ruboy.step(40.0 / CLOCK_SPEED_HZ_F64).unwrap());
+    }
+
+    #[test]
+    fn save_state_round_trips() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.step(0.1).unwrap();
+
+        let state = ruboy.save_state();
+        let digest = ruboy.state_digest();
+        let frames = ruboy.frame_count();
+
+        ruboy.step(0.1).unwrap();
+        assert_ne!(frames, ruboy.frame_count());
+
+        ruboy.load_state(&state).unwrap();
+        assert_eq!(digest, ruboy.state_digest());
+        assert_eq!(frames, ruboy.frame_count());
+        assert_eq!(state, ruboy.save_state());
+
+        // Truncated states are rejected without touching the emulator
+        assert!(matches!(
+            ruboy.load_state(&state[..state.len() - 1]),
+            Err(StateLoadErr::Truncated)
+        ));
+        assert_eq!(state, ruboy.save_state());
+    }
+
+    #[test]
+    fn save_state_rejects_other_rom() {
+        let state = new_with_model(GbModel::Dmg).save_state();
+
+        let mut other: TestRuboy =
+            Ruboy::with_model(blank_rom(0x3D), NullOutput, NullInput, GbModel::Dmg).unwrap();
+
+        assert!(matches!(
+            other.load_state(&state),
+            Err(StateLoadErr::WrongRom)
+        ));
+        assert!(matches!(
+            other.load_state(b"not a state"),
+            Err(StateLoadErr::BadMagic)
+        ));
+    }
+
+    #[test]
+    fn save_state_through_storage() {
+        use crate::storage::{MemoryStorage, StorageProvider};
+
+        let mut storage = MemoryStorage::new();
+        let mut ruboy = new_with_model(GbModel::Dmg);
+
+        assert!(!ruboy.load_state_from(&storage, "game-1").unwrap());
+
+        ruboy.step(0.1).unwrap();
+        ruboy.save_state_to(&mut storage, "game-1").unwrap();
+        assert_eq!(
+            Some(ruboy.save_state()),
+            storage.load("game-1.state").unwrap()
+        );
+
+        let digest = ruboy.state_digest();
+        ruboy.step(0.1).unwrap();
+
+        assert!(ruboy.load_state_from(&storage, "game-1").unwrap());
+        assert_eq!(digest, ruboy.state_digest());
+
+        storage.store("game-1.state", b"not a state").unwrap();
+        assert!(matches!(
+            ruboy.load_state_from(&storage, "game-1"),
+            Err(LoadPersistentErr::State(StateLoadErr::BadMagic))
+        ));
+    }
+
+    #[test]
+    fn persistent_data_round_trips() {
+        use crate::rom::capabilities::Capability;
+        use crate::storage::{MemoryStorage, StorageProvider};
+
+        // MBC3 with clock, 8 KiB of RAM and a battery
+        let mbc3_timer = || {
+            let mut rom = blank_rom(0x00).into_inner();
+            rom[0x147] = 0x10;
+            rom[0x149] = 0x02;
+            fix_header_checksum(&mut rom);
+            Cursor::new(rom)
+        };
+
+        let mut storage = MemoryStorage::new();
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(mbc3_timer(), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        assert_eq!(
+            vec![Capability::Battery, Capability::Rtc],
+            ruboy.rom_meta().capabilities()
+        );
+
+        let ram: Vec<u8> = (0..0x2000).map(|idx| (idx * 7) as u8).collect();
+        assert!(ruboy.load_cartridge_ram(&ram));
+        assert!(!ruboy.load_cartridge_ram(&ram[..0x100]));
+
+        // A halted clock, so no time passes between saving and comparing
+        let mut rtc = Rtc::new();
+        rtc.write(0x0C, 0x41);
+        rtc.write(0x08, 12);
+        rtc.write(0x09, 34);
+        rtc.write(0x0A, 5);
+        rtc.write(0x0B, 0x78);
+        rtc.latch();
+        assert!(ruboy.load_rtc(rtc));
+
+        ruboy.save_persistent(&mut storage, "game").unwrap();
+
+        assert_eq!(
+            vec!["game.rtc", "game.sav"],
+            storage.keys().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Rtc::SAVE_LENGTH,
+            storage.load("game.rtc").unwrap().unwrap().len()
+        );
+        assert_eq!(ram, storage.load("game.sav").unwrap().unwrap());
+
+        let mut restored: TestRuboy =
+            Ruboy::with_model(mbc3_timer(), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        restored.load_persistent(&storage, "game").unwrap();
+
+        let restored_rtc = restored.save_rtc().unwrap();
+        assert_eq!(rtc.current(), restored_rtc.current());
+        assert_eq!(rtc.latched(), restored_rtc.latched());
+        assert_eq!(ram, restored.cartridge_ram());
+
+        // A save of another cartridge is refused
+        storage.store("game.sav", &[0; 0x100]).unwrap();
+        assert!(matches!(
+            restored.load_persistent(&storage, "game"),
+            Err(LoadPersistentErr::CartRamLength {
+                expected: 0x2000,
+                found: 0x100
+            })
+        ));
+        assert_eq!(ram, restored.cartridge_ram());
+
+        // Carts without a clock have nothing to persist
+        let mut plain = new_with_model(GbModel::Dmg);
+        let mut empty = MemoryStorage::new();
+        plain.save_persistent(&mut empty, "game").unwrap();
+        assert!(plain.rom_meta().capabilities().is_empty());
+
+        assert_eq!(0, empty.keys().count());
+    }
+
+    #[test]
+    fn deterministic_runs_are_identical() {
+        let run = |seed: u64| {
+            let mut rom = blank_rom(0x00).into_inner();
+            rom[0x147] = 0x0F;
+            fix_header_checksum(&mut rom);
+
+            let mut ruboy: TestRuboy = Ruboy::builder(Cursor::new(rom), NullOutput, NullInput)
+                .model(GbModel::Dmg)
+                .deterministic(seed)
+                .build()
+                .unwrap();
+
+            let mut frames = Vec::new();
+            for _ in 0..60 {
+                ruboy.step_frame().unwrap();
+                frames.push(ruboy.ppu.frame().digest());
+            }
+
+            (ruboy, frames)
+        };
+
+        let (mut first, first_frames) = run(7);
+        let (second, second_frames) = run(7);
+        let (other, _) = run(8);
+
+        assert_eq!(first.state_digest(), second.state_digest());
+        assert_eq!(first_frames, second_frames);
+        assert_ne!(first.state_digest(), other.state_digest());
+
+        // 60 frames are just over a second of emulated time
+        let rtc = first.save_rtc().unwrap();
+        assert!(matches!(rtc.clock(), RtcClock::Emulated { now: 1, .. }));
+        assert_eq!(1, rtc.current().seconds);
+
+        let state = first.save_state();
+        first.load_state(&state).unwrap();
+        assert_eq!(rtc.clock(), first.save_rtc().unwrap().clock());
+    }
+
+    #[test]
+    fn doctor_log_writes_line_per_instruction() {
+        let buf = Recorder::default();
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.enable_doctor_log(buf.clone());
+
+        // Two NOPs
+        ruboy.step(8.0 / CLOCK_SPEED_HZ_F64).unwrap();
+        assert!(ruboy.disable_doctor_log().is_some());
+
+        let log = String::from_utf8(buf.recorded().clone()).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+
+        assert_eq!(
+            vec![
+                "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:00,00,00,00",
+                "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0101 PCMEM:00,00,00,00",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn doctor_log_stubs_ly() {
+        // LDH A, (0x44) twice
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x100..0x104].copy_from_slice(&[0xF0, 0x44, 0xF0, 0x44]);
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+
+        ruboy.enable_doctor_log(std::io::sink());
+        ruboy.step(4.0 / CLOCK_SPEED_HZ_F64).unwrap();
+        assert_eq!(0x90, ruboy.registers().a);
+
+        ruboy.disable_doctor_log();
+        ruboy.step(16.0 / CLOCK_SPEED_HZ_F64).unwrap();
+        assert_eq!(ruboy.peek(0xFF44).unwrap(), ruboy.registers().a);
+        assert_ne!(0x90, ruboy.registers().a);
+    }
+
+    #[test]
+    fn debug_view_sizes() {
+        let ruboy = new_with_model(GbModel::Dmg);
+
+        let tilemap = ruboy.render_tilemap(TileMapArea::High).unwrap();
+        assert_eq!((256, 256), (tilemap.width(), tilemap.height()));
+        assert_eq!(256 * 256, tilemap.get_raw().len());
+
+        let oam = ruboy.render_oam().unwrap();
+        assert_eq!((64, 80), (oam.width(), oam.height()));
+
+        let tiles = ruboy.render_tiles().unwrap();
+        assert_eq!((128, 192), (tiles.width(), tiles.height()));
+    }
+
+    #[test]
+    fn debugger_stops_at_breakpoint_and_steps() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+
+        let mut debugger = Debugger::new();
+        debugger.add_breakpoint(0x104);
+        ruboy.attach_debugger(debugger);
+
+        // Four NOPs before reaching the breakpoint
+        let ran = ruboy.step(1000.0 / CLOCK_SPEED_HZ_F64).unwrap();
+        assert_eq!(16, ran);
+        assert_eq!(0x104, ruboy.registers().pc);
+        assert_eq!(
+            Some(StopReason::Breakpoint(0x104)),
+            ruboy.debugger().unwrap().stop_reason()
+        );
+
+        // Paused, so nothing happens
+        assert_eq!(0, ruboy.step(1000.0 / CLOCK_SPEED_HZ_F64).unwrap());
+
+        assert_eq!(4, ruboy.step_instruction().unwrap());
+        assert_eq!(0x105, ruboy.registers().pc);
+        assert_eq!(
+            Some(StopReason::Step),
+            ruboy.debugger().unwrap().stop_reason()
+        );
+
+        ruboy.debugger_mut().unwrap().resume();
+        assert_eq!(40, ruboy.step(40.0 / CLOCK_SPEED_HZ_F64).unwrap());
+    }
+
+    #[test]
+    fn banked_breakpoint_resolves_through_the_mapper() {
+        // MBC1 with 4 banks. Bank 0 switches to bank 2 and jumps into it.
+        let mut rom = vec![0u8; 4 * 0x4000];
+        rom[0x100..0x108].copy_from_slice(&[0x3E, 0x02, 0xEA, 0x00, 0x20, 0xC3, 0x00, 0x40]);
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x01;
+        fix_header_checksum(&mut rom);
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        assert_eq!(1, ruboy.current_bank());
+        assert_eq!(Some(0), ruboy.bank_at(0x0100));
+        assert_eq!(None, ruboy.bank_at(0xC000));
+
+        let mut debugger = Debugger::new();
+        debugger.add_banked_breakpoint(1, 0x4000);
+        debugger.add_banked_breakpoint(2, 0x4001);
+        ruboy.attach_debugger(debugger);
+
+        ruboy.step(1000.0 / CLOCK_SPEED_HZ_F64).unwrap();
+        assert_eq!(2, ruboy.current_bank());
+        assert_eq!(
+            Some(StopReason::BankedBreakpoint {
+                bank: 2,
+                addr: 0x4001
+            }),
+            ruboy.debugger().unwrap().stop_reason()
+        );
+    }
+
+    #[test]
+    fn call_stack_follows_calls_and_returns() {
+        // CALL 0x0200, then spin. 0x0200 calls 0x0300, both return.
+        let mut rom = vec![0u8; 0x8000];
+        rom[0x100..0x105].copy_from_slice(&[0xCD, 0x00, 0x02, 0x18, 0xFE]);
+        rom[0x200..0x204].copy_from_slice(&[0xCD, 0x00, 0x03, 0xC9]);
+        rom[0x300..0x302].copy_from_slice(&[0x00, 0xC9]);
+        fix_header_checksum(&mut rom);
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        ruboy.enable_profiler(1);
+
+        // Both calls take 24 cycles
+        ruboy.run_cycles(48).unwrap();
+
+        let frames: Vec<_> = ruboy
+            .call_stack()
+            .unwrap()
+            .frames()
+            .iter()
+            .map(|frame| (frame.target.addr, frame.return_addr))
+            .collect();
+        assert_eq!(vec![(0x0200, 0x0103), (0x0300, 0x0203)], frames);
+
+        ruboy.run_cycles(200).unwrap();
+        assert_eq!(0, ruboy.call_stack().unwrap().depth());
+
+        let profiler = ruboy.disable_profiler().unwrap();
+        assert_eq!(248, profiler.total_cycles());
+
+        let hottest = profiler.by_address()[0].0;
+        assert_eq!(Some(0), hottest.bank);
+        assert_eq!(0x0103, hottest.addr);
+
+        let mut folded = String::new();
+        profiler.write_folded(&mut folded).unwrap();
+        assert!(folded.contains("00:0200;00:0300;00:0301 "));
+    }
+
+    #[test]
+    fn debugger_stops_on_conditions() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+
+        let mut debugger = Debugger::new();
+        debugger.add_watch_expression("pc == 0x104".parse().unwrap());
+        debugger.add_conditional_breakpoint(0x104, "[0xC000] == 7".parse().unwrap());
+        debugger.add_conditional_breakpoint(0x106, "[0xC000] == 7".parse().unwrap());
+        ruboy.attach_debugger(debugger);
+
+        ruboy.step(1000.0 / CLOCK_SPEED_HZ_F64).unwrap();
+        assert_eq!(
+            Some(StopReason::WatchExpression {
+                index: 0,
+                pc: 0x104
+            }),
+            ruboy.debugger().unwrap().stop_reason()
+        );
+
+        // Resuming skips what stopped at 0x104, the condition now holds at
+        // the next conditional breakpoint
+        ruboy.write_memory(0xC000, &[7]).unwrap();
+
+        let debugger = ruboy.debugger_mut().unwrap();
+        assert!(debugger.remove_watch_expression(0).is_some());
+        debugger.resume();
+
+        ruboy.step(1000.0 / CLOCK_SPEED_HZ_F64).unwrap();
+        assert_eq!(
+            Some(StopReason::Breakpoint(0x106)),
+            ruboy.debugger().unwrap().stop_reason()
+        );
+    }
+
+    #[test]
+    fn debugger_stops_on_watched_write() {
+        // LD A, 0x42; LD (0xC000), A
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x100..0x105].copy_from_slice(&[0x3E, 0x42, 0xEA, 0x00, 0xC0]);
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+
+        let mut debugger = Debugger::new();
+        debugger.add_watchpoint(Watchpoint::at(0xC000, Access::Write));
+        ruboy.attach_debugger(debugger);
+
+        ruboy.step(1000.0 / CLOCK_SPEED_HZ_F64).unwrap();
+
+        assert_eq!(
+            Some(StopReason::Watchpoint {
+                addr: 0xC000,
+                access: Access::Write,
+                value: 0x42
+            }),
+            ruboy.debugger().unwrap().stop_reason()
+        );
+        assert_eq!(0x42, ruboy.peek(0xC000).unwrap());
+    }
+
+    #[test]
+    fn opcode_stats_count_executed_instructions() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        assert!(ruboy.opcode_stats().is_none());
+
+        ruboy.enable_opcode_stats();
+        ruboy.step(40.0 / CLOCK_SPEED_HZ_F64).unwrap();
+
+        let stats = ruboy.disable_opcode_stats().unwrap();
+        assert_eq!(10, stats.count(Opcode::Base(0x00)));
+        assert_eq!(10, stats.total());
+    }
+
+    #[cfg(feature = "cached_interpreter")]
+    #[test]
+    fn block_cache_sees_self_modifying_code() {
+        // Copies "INC A; JR -3" to WRAM and jumps there. The INC A is then
+        // patched into a DEC A, which must be picked up by the cache
+        let program: &[u8] = &[
+            0x21, 0x00, 0xC0, // LD HL, 0xC000
+            0x36, 0x3C, // LD (HL), INC A
+            0x23, // INC HL
+            0x36, 0x18, // LD (HL), JR
+            0x23, // INC HL
+            0x36, 0xFD, // LD (HL), -3
+            0xC3, 0x00, 0xC0, // JP 0xC000
+        ];
+
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x100..0x100 + program.len()].copy_from_slice(program);
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+
+        ruboy.step(2000.0 / CLOCK_SPEED_HZ_F64).unwrap();
+        let a_before = ruboy.registers().a;
+
+        assert!(ruboy.block_cache_stats().hits > 0);
+
+        // Patch INC A into DEC A
+        ruboy.mem.write8(0xC000, 0x3D).unwrap();
+        ruboy.step(800.0 / CLOCK_SPEED_HZ_F64).unwrap();
+
+        assert!(ruboy.registers().a < a_before);
+        assert!(ruboy.block_cache_stats().invalidations > 0);
+    }
+
+    #[test]
+    fn write_memory_reads_back() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+
+        ruboy.write_memory(0xDFFE, &[0x12, 0x34, 0x56]).unwrap();
+
+        assert_eq!(
+            vec![0x12, 0x34, 0x56],
+            ruboy.read_memory(0xDFFE..=0xE000).unwrap()
+        );
+        assert_eq!(MemRegion::EchoRam, ruboy.memory_region(0xE000));
+        assert_eq!(vec![0x00; 4], ruboy.read_memory(0xFEA0..=0xFEA3).unwrap());
+    }
+
+    #[test]
+    fn snooper_patches_reads_and_drops_writes() {
+        /// Replaces the first instruction with LD A, 0x42 and makes work RAM
+        /// at 0xC000 read-only
+        struct Patcher;
+
+        impl BusSnooper for Patcher {
+            fn read(&mut self, addr: u16, value: u8) -> u8 {
+                match addr {
+                    0x100 => 0x3E,
+                    0x101 => 0x42,
+                    _ => value,
+                }
+            }
+
+            fn write(&mut self, addr: u16, value: u8) -> Option<u8> {
+                (addr != 0xC000).then_some(value)
+            }
+        }
+
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.attach_snooper(Patcher);
+
+        // LD A, n followed by a NOP
+        ruboy.step(12.0 / CLOCK_SPEED_HZ_F64).unwrap();
+        assert_eq!(0x42, ruboy.registers().a);
+
+        ruboy.mem.write8(0xC000, 0x12).unwrap();
+        ruboy.mem.write8(0xC001, 0x34).unwrap();
+        assert_eq!(
+            vec![0x00, 0x34],
+            ruboy.read_memory(0xC000..=0xC001).unwrap()
+        );
+
+        // Inspection reads are not snooped
+        assert_eq!(0x00, ruboy.peek(0x100).unwrap());
+        assert!(ruboy.detach_snooper().is_some());
+    }
+
+    #[test]
+    fn step_until_audio_produces_exactly_enough() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        assert_eq!(0, ruboy.step_until_audio(100).unwrap());
+
+        ruboy.set_audio_sample_rate(48000);
+        ruboy.step_until_audio(1).unwrap();
+        ruboy.drain_audio().for_each(drop);
+
+        let ran = ruboy.step_until_audio(480).unwrap();
+        assert_eq!(480, ruboy.drain_audio().count());
+        assert_eq!(Some(ruboy.cycles_elapsed()), ruboy.last_sample_cycle());
+
+        // 10ms worth of samples
+        assert!(ran.abs_diff(CLOCK_SPEED_HZ / 100) < CLOCK_SPEED_HZ / 48000);
+
+        ruboy.set_paused(true);
+        assert_eq!(0, ruboy.step_until_audio(480).unwrap());
+    }
+
+    #[test]
+    fn events_are_timestamped() {
+        let seen = Recorder::default();
+
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.attach_frame_observer(seen.clone());
+        ruboy.set_audio_sample_rate(48000);
+
+        assert_eq!(100, ruboy.run_cycles(100).unwrap());
+        assert_eq!(100, ruboy.cycles_elapsed());
+
+        ruboy.step_frame().unwrap();
+        ruboy.step_frame().unwrap();
+
+        let frames: Vec<u64> = seen.recorded().iter().map(|(_, cycle)| *cycle).collect();
+        assert_eq!(Some(&ruboy.cycles_elapsed()), frames.last());
+        assert_eq!(FRAME_CYCLES as u64, frames[1] - frames[0]);
+
+        let sample = ruboy.last_sample_cycle().unwrap();
+        assert!(ruboy.cycles_elapsed() - sample < (CLOCK_SPEED_HZ / 48000) as u64 + 1);
+
+        // Doesn't start over
+        let before = ruboy.cycles_elapsed();
+        ruboy.reset().unwrap();
+        ruboy.run_cycles(10).unwrap();
+        assert_eq!(before + 10, ruboy.cycles_elapsed());
+    }
+
+    #[test]
+    fn frame_observer_sees_each_frame_once() {
+        let seen = Recorder::default();
+
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.attach_frame_observer(seen.clone());
+
+        for _ in 0..3 {
+            assert!(matches!(ruboy.step_frame(), Ok(FrameEvent::Completed(_))));
+        }
+
+        ruboy.present_frame().unwrap();
+
+        let numbers: Vec<u64> = seen.recorded().iter().map(|(number, _)| *number).collect();
+        let first = numbers[0];
+        assert_eq!(vec![first, first + 1, first + 2], numbers);
+        assert_eq!(first + 2, ruboy.frame_count());
+
+        assert!(ruboy.detach_frame_observer().is_some());
+    }
+
+    #[test]
+    fn memory_inspector_sees_memory_after_each_frame() {
+        let seen = Recorder::default();
+
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.attach_memory_inspector(seen.clone());
+        ruboy.write_memory(0xC000, &[0x5A]).unwrap();
+
+        for _ in 0..2 {
+            assert!(matches!(ruboy.step_frame(), Ok(FrameEvent::Completed(_))));
+        }
+
+        assert_eq!(vec![Some(0x5A); 2], *seen.recorded());
+        assert!(ruboy.detach_memory_inspector().is_some());
+    }
+
+    #[test]
+    fn ram_slices_match_memory() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+
+        ruboy.write_memory(0xC010, &[0x12, 0x34]).unwrap();
+        ruboy.write_memory(0xFF80, &[0x56]).unwrap();
+
+        assert_eq!(0x2000, ruboy.wram().len());
+        assert_eq!([0x12, 0x34], ruboy.wram()[0x10..0x12]);
+        assert_eq!(0x56, ruboy.hram()[0]);
+        assert_eq!(ruboy.read_memory(0x8000..=0x9FFF).unwrap(), ruboy.vram());
+        assert_eq!(ruboy.read_memory(0xFE00..=0xFE9F).unwrap(), ruboy.oam());
+    }
+
+    #[cfg(feature = "debug_tools")]
+    #[test]
+    fn ram_slices_write_through() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+
+        ruboy.wram_mut()[0x20] = 0x99;
+        ruboy.hram_mut()[0x7E] = 0x98;
+
+        assert_eq!(0x99, ruboy.peek(0xC020).unwrap());
+        assert_eq!(0x98, ruboy.peek(0xFFFE).unwrap());
+    }
+
+    #[test]
+    fn rumble_handler_sees_motor_switches() {
+        let mut rom = blank_rom(0x00).into_inner();
+        rom[0x147] = 0x1C; // MBC5 with rumble
+
+        // Motor on, motor off, and then loop forever
+        rom[0x100..0x10B].copy_from_slice(&[
+            0x3E, 0x08, 0xEA, 0x00, 0x40, 0xAF, 0xEA, 0x00, 0x40, 0x18, 0xFE,
+        ]);
+
+        let seen = Recorder::default();
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        ruboy.attach_rumble_handler(seen.clone());
+        ruboy.step_frame().unwrap();
+
+        assert_eq!(vec![true, false], *seen.recorded());
+        assert!(ruboy.detach_rumble_handler().is_some());
+    }
+
+    #[test]
+    fn unsupported_mapper_is_an_error() {
+        let mut rom = blank_rom(0x00).into_inner();
+        rom[0x147] = 0x05; // MBC2
+
+        let result: Result<TestRuboy, _> =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg);
+        let err = result
+            .err()
+            .expect("Started a ROM with an unsupported mapper");
+
+        assert_eq!(Some(CartridgeMapper::MBC2), err.unsupported_mapper());
+        assert_eq!(
+            "Could not initialize memory controller: Could not initialize ROM controller: \
+             Cartridges with the MBC2 mapper are not supported yet",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn link_device_exchanges_bytes() {
+        let mut rom = blank_rom(0x00).into_inner();
+
+        // Send 0x42 with the internal clock, and then loop forever
+        rom[0x100..0x10A]
+            .copy_from_slice(&[0x3E, 0x42, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE]);
+
+        let sent = Recorder::default();
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        ruboy.attach_link_device(sent.clone());
+        ruboy.step_frame().unwrap();
+
+        assert_eq!(vec![0x42], *sent.recorded());
+        assert_eq!(0xBD, ruboy.peek(0xFF01).unwrap());
+        assert!(ruboy.detach_link_device().is_some());
+    }
+
+    #[test]
+    fn ppu_hooks_see_lines_modes_and_frames() {
+        let events = Recorder::default();
+
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy.step_frame().unwrap();
+        ruboy.set_ppu_hooks(events.clone());
+        ruboy.step_frame().unwrap();
+
+        let events = events.recorded();
+
+        let lines: Vec<u8> = events
+            .iter()
+            .filter_map(|event| match event {
+                PpuEvent::Line(ly) => Some(*ly),
+                _ => None,
+            })
+            .collect();
+        let modes: Vec<LcdMode> = events
+            .iter()
+            .filter_map(|event| match event {
+                PpuEvent::Mode(mode) => Some(*mode),
+                _ => None,
+            })
+            .collect();
+        let frames: Vec<u64> = events
+            .iter()
+            .filter_map(|event| match event {
+                PpuEvent::Frame(number) => Some(*number),
+                _ => None,
+            })
+            .collect();
+
+        let expected: Vec<u8> = (FRAME_Y as u8 + 1..154).chain(0..=FRAME_Y as u8).collect();
+
+        assert_eq!(expected, lines);
+        assert_eq!(vec![ruboy.frame_count()], frames);
+
+        let draws = modes.iter().filter(|m| **m == LcdMode::Draw).count();
+        assert_eq!(FRAME_Y, draws);
+        assert_eq!(Some(&LcdMode::VBlank), modes.last());
+
+        assert!(ruboy.clear_ppu_hooks().is_some());
+    }
+
+    #[test]
+    fn input_polling_modes() {
+        let polls = Recorder::default();
+        let mut ruboy = Ruboy::<InlineAllocator, _, _, _>::with_model(
+            blank_rom(0x00),
+            NullOutput,
+            polls.clone(),
+            GbModel::Dmg,
+        )
+        .unwrap();
+
+        ruboy.step_frame().unwrap();
+        ruboy.step_frame().unwrap();
+        assert_eq!(2, polls.recorded().len());
+        assert!(ruboy.inputs().start);
+
+        ruboy.set_input_polling(InputPolling::Interval(1000.try_into().unwrap()));
+        ruboy.run_cycles(10_000).unwrap();
+        assert_eq!(12, polls.recorded().len());
+
+        // Events bypass the handler
+        ruboy.set_inputs(GbInputs::default());
+        assert!(!ruboy.inputs().start);
+        assert_eq!(12, polls.recorded().len());
+    }
+
+    #[test]
+    fn oam_entries_decode_attributes() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+        ruboy
+            .write_memory(0xFE04, &[0x20, 0x18, 0x7F, 0b1011_0000])
+            .unwrap();
+
+        let entries = ruboy.oam_entries().unwrap();
+        assert_eq!(40, entries.len());
+        assert_eq!(
+            OamEntry {
+                index: 1,
+                x: 0x18,
+                y: 0x20,
+                tile: 0x7F,
+                palette: 1,
+                x_flip: true,
+                y_flip: false,
+                behind_bg: true,
+            },
+            entries[1]
+        );
+    }
+
+    #[test]
+    fn step_frame_stops_at_vblank() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+
+        assert!(matches!(
+            ruboy.step_frame().unwrap(),
+            FrameEvent::Completed(_)
+        ));
+        assert_eq!(FRAME_Y as u8, ruboy.peek(0xFF44).unwrap());
+
+        ruboy.attach_debugger(Debugger::new());
+        assert!(matches!(
+            ruboy.step_frame().unwrap(),
+            FrameEvent::Completed(_)
+        ));
+        assert_eq!(
+            Some(StopReason::Frame),
+            ruboy.debugger().unwrap().stop_reason()
+        );
+
+        let pc = ruboy.registers().pc;
+        ruboy.debugger_mut().unwrap().add_breakpoint(pc + 2);
+        assert!(matches!(
+            ruboy.step_frame().unwrap(),
+            FrameEvent::Stopped(StopReason::Breakpoint(_))
+        ));
+
+        ruboy.detach_debugger();
+        ruboy.write_memory(0xFF40, &[0x00]).unwrap();
+        assert!(matches!(ruboy.step_frame().unwrap(), FrameEvent::LcdOff));
+    }
+
+    #[test]
+    fn ram_init_from_config() {
+        let config = RuboyConfig {
+            model: GbModel::Dmg,
+            ram_init: RamInit::Fill(0xA5),
+            ..Default::default()
+        };
+
+        let ruboy: TestRuboy =
+            Ruboy::with_config(blank_rom(0x3C), NullOutput, NullInput, config).unwrap();
+
+        assert_eq!(vec![0xA5; 2], ruboy.read_memory(0xC000..=0xC001).unwrap());
+        assert_eq!(0xA5, ruboy.peek(0xFF80).unwrap());
+    }
+
+    #[test]
+    fn exact_cycle_stepping() {
+        let mut ruboy = new_with_model(GbModel::Dmg);
+
+        // A blank ROM is all NOPs, which take 4 cycles each
+        assert_eq!(40, ruboy.run_cycles(40).unwrap());
+        assert_eq!(0x10A, ruboy.registers().pc);
+
+        assert_eq!(
+            RunUntil::Reached(8),
+            ruboy.run_until_pc(0x10C, 100).unwrap()
+        );
+        assert_eq!(RunUntil::Limit, ruboy.run_until_pc(0x100, 100).unwrap());
+
+        assert!(matches!(
+            ruboy.run_until_vblank().unwrap(),
+            RunUntil::Reached(_)
+        ));
+        assert_eq!(
+            RunUntil::Reached(FRAME_CYCLES as u64),
+            ruboy.run_until_vblank().unwrap()
+        );
+    }
+
+    #[test]
+    fn run_until_pc_catches_the_ppu_up_once_done() {
+        let mut until = new_with_model(GbModel::Dmg);
+        let mut stepped = new_with_model(GbModel::Dmg);
+
+        let RunUntil::Reached(ran) = until.run_until_pc(0x180, 1000).unwrap() else {
+            panic!("Never reached 0x180");
+        };
+        stepped.run_cycles(ran).unwrap();
+
+        assert_eq!(0, until.ppu_behind);
+        assert_eq!(stepped.state_digest(), until.state_digest());
+    }
+
+    #[test]
+    fn power_on_digest_differs_per_model() {
+        assert_ne!(
+            new_with_model(GbModel::Dmg).state_digest(),
+            new_with_model(GbModel::Mgb).state_digest()
+        );
+    }
 }
@@ -0,0 +1,179 @@
+//! Two Gameboys connected by a link cable, both emulated in-process.
+//!
+//! Whichever side starts a transfer with the internal clock is the master:
+//! every bit it shifts out is a clock pulse for the other side, which
+//! shifts out its own bit in return, see [Ruboy::serial_external_clock].
+//! The slave only moves when the master clocks it, so a game waiting on
+//! the external clock waits until the other game starts sending, like on
+//! real hardware. If both sides use the internal clock, neither listens,
+//! and both receive `0xFF`.
+
+use thiserror::Error;
+
+use crate::emucore::EmulatorCore;
+use crate::{
+    split_f64, GBAllocator, GBGraphicsDrawer, InputHandler, RomReader, Ruboy, RuboyErr,
+    CLOCK_SPEED_HZ_F64,
+};
+
+#[derive(Debug, Error)]
+pub enum LinkErr<V: GBGraphicsDrawer> {
+    #[error("Error in the first Gameboy")]
+    First(#[source] RuboyErr<V>),
+
+    #[error("Error in the second Gameboy")]
+    Second(#[source] RuboyErr<V>),
+}
+
+/// Two emulator instances with a link cable between them, ran in lockstep
+pub struct LinkedPair<A, R, V, I>
+where
+    A: GBAllocator,
+    R: RomReader,
+    V: GBGraphicsDrawer,
+    I: InputHandler,
+{
+    first: Ruboy<A, R, V, I>,
+    second: Ruboy<A, R, V, I>,
+    cycle_accumulator: f64,
+}
+
+impl<A, R, V, I> LinkedPair<A, R, V, I>
+where
+    A: GBAllocator,
+    R: RomReader,
+    V: GBGraphicsDrawer,
+    I: InputHandler,
+{
+    /// Plugs the cable into both instances. Any link device attached to
+    /// either is unplugged first.
+    pub fn new(mut first: Ruboy<A, R, V, I>, mut second: Ruboy<A, R, V, I>) -> Self {
+        first.detach_link_device();
+        second.detach_link_device();
+
+        Self {
+            first,
+            second,
+            cycle_accumulator: 0.0,
+        }
+    }
+
+    pub fn first(&self) -> &Ruboy<A, R, V, I> {
+        &self.first
+    }
+
+    pub fn first_mut(&mut self) -> &mut Ruboy<A, R, V, I> {
+        &mut self.first
+    }
+
+    pub fn second(&self) -> &Ruboy<A, R, V, I> {
+        &self.second
+    }
+
+    pub fn second_mut(&mut self) -> &mut Ruboy<A, R, V, I> {
+        &mut self.second
+    }
+
+    /// Unplugs the cable, handing back both instances
+    pub fn into_inner(self) -> [Ruboy<A, R, V, I>; 2] {
+        [self.first, self.second]
+    }
+
+    /// Runs both instances for `dt` seconds, at the speed of the first. See
+    /// [Ruboy::step].
+    pub fn step(&mut self, dt: f64) -> Result<u64, LinkErr<V>> {
+        let cycles_dt = dt * self.first.speed() * CLOCK_SPEED_HZ_F64;
+        let (mut cycles_to_run, accumulated) = split_f64(cycles_dt);
+
+        self.cycle_accumulator += accumulated;
+        let (extra_cycles, new_accumulator) = split_f64(self.cycle_accumulator);
+
+        cycles_to_run += extra_cycles;
+        self.cycle_accumulator = new_accumulator;
+
+        debug_assert!(cycles_to_run >= 0);
+
+        self.run_cycles(cycles_to_run as u64)
+    }
+
+    /// Runs both instances for exactly the given amount of cycles. See
+    /// [run_linked].
+    pub fn run_cycles(&mut self, cycles: u64) -> Result<u64, LinkErr<V>> {
+        run_linked(&mut self.first, &mut self.second, cycles)
+    }
+}
+
+/// Runs two instances in lockstep for the given amount of cycles, as if a
+/// link cable connects them, for when they can't be moved into a
+/// [LinkedPair]. Link devices attached to either would answer transfers
+/// in place of the other side, so detach them first.
+///
+/// Stops early if the debugger of either side stops it. Returns the amount
+/// of cycles both sides ran.
+pub fn run_linked<V, C>(first: &mut C, second: &mut C, cycles: u64) -> Result<u64, LinkErr<V>>
+where
+    V: GBGraphicsDrawer,
+    C: EmulatorCore<V> + ?Sized,
+{
+    for ran in 0..cycles {
+        if !first
+            .linked_cycle(&mut |bit| second.serial_external_clock(bit))
+            .map_err(LinkErr::First)?
+            || !second
+                .linked_cycle(&mut |bit| first.serial_external_clock(bit))
+                .map_err(LinkErr::Second)?
+        {
+            return Ok(ran);
+        }
+    }
+
+    Ok(cycles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::GbModel;
+    use crate::testutils::{blank_rom, NullInput, NullOutput, TestRuboy};
+    use std::io::Cursor;
+
+    /// Sends `byte` with the given serial control value, then spins
+    fn sender(byte: u8, control: u8) -> TestRuboy {
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x100..0x10A].copy_from_slice(&[
+            0x3E, byte, // LD A, byte
+            0xE0, 0x01, // LDH (SB), A
+            0x3E, control, // LD A, control
+            0xE0, 0x02, // LDH (SC), A
+            0x18, 0xFE, // JR -2
+        ]);
+
+        Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap()
+    }
+
+    fn sb(ruboy: &TestRuboy) -> u8 {
+        ruboy.mem.io_registers.serial.data()
+    }
+
+    #[test]
+    fn master_and_slave_swap_bytes() {
+        let master = sender(0x42, 0x81);
+        let slave = sender(0x99, 0x80);
+
+        let mut pair = LinkedPair::new(slave, master);
+        assert_eq!(10_000, pair.run_cycles(10_000).unwrap());
+
+        assert_eq!(0x42, sb(pair.first()));
+        assert_eq!(0x99, sb(pair.second()));
+        assert!(!pair.first().mem.io_registers.serial.transferring());
+    }
+
+    #[test]
+    fn nobody_listening_receives_ones() {
+        let mut pair = LinkedPair::new(sender(0x42, 0x81), sender(0x99, 0x81));
+        pair.run_cycles(10_000).unwrap();
+
+        assert_eq!(0xFF, sb(pair.first()));
+        assert_eq!(0xFF, sb(pair.second()));
+    }
+}
@@ -0,0 +1,165 @@
+//! Per subsystem control over what Ruboy logs. The debug and trace messages
+//! of each subsystem are logged with its own target, like `ruboy_lib::ppu`,
+//! and can be limited at runtime with [set_level], e.g. to only see the
+//! traces of the PPU without drowning in those of the CPU. This comes on top
+//! of the filtering the logger itself does.
+//!
+//! Messages go to the `log` crate, or with the `tracing` feature to
+//! `tracing` instead. Every frame and every instruction then also is a span,
+//! at the debug and trace level of [Subsystem::Emulator] and [Subsystem::Cpu].
+
+use core::fmt::Display;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use log::{Level, LevelFilter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    Cpu,
+    Ppu,
+    Apu,
+
+    /// The memory bus, DMA and the cartridge
+    Memory,
+    Serial,
+
+    /// Running the system as a whole, like stepping and frames
+    Emulator,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 6] = [
+        Subsystem::Cpu,
+        Subsystem::Ppu,
+        Subsystem::Apu,
+        Subsystem::Memory,
+        Subsystem::Serial,
+        Subsystem::Emulator,
+    ];
+
+    /// The target messages of the subsystem are logged with
+    pub const fn target(self) -> &'static str {
+        match self {
+            Subsystem::Cpu => "ruboy_lib::cpu",
+            Subsystem::Ppu => "ruboy_lib::ppu",
+            Subsystem::Apu => "ruboy_lib::apu",
+            Subsystem::Memory => "ruboy_lib::memory",
+            Subsystem::Serial => "ruboy_lib::serial",
+            Subsystem::Emulator => "ruboy_lib::emulator",
+        }
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Subsystem::Cpu => "cpu",
+            Subsystem::Ppu => "ppu",
+            Subsystem::Apu => "apu",
+            Subsystem::Memory => "memory",
+            Subsystem::Serial => "serial",
+            Subsystem::Emulator => "emulator",
+        }
+    }
+}
+
+impl Display for Subsystem {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("Unknown subsystem, expected one of cpu, ppu, apu, memory, serial or emulator")]
+pub struct SubsystemParseErr;
+
+impl FromStr for Subsystem {
+    type Err = SubsystemParseErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Subsystem::ALL
+            .into_iter()
+            .find(|subsystem| subsystem.name().eq_ignore_ascii_case(s))
+            .ok_or(SubsystemParseErr)
+    }
+}
+
+/// The most verbose level of every subsystem, as a [LevelFilter]. Everything
+/// is let through by default.
+static LEVELS: [AtomicU8; Subsystem::ALL.len()] =
+    [const { AtomicU8::new(LevelFilter::Trace as u8) }; Subsystem::ALL.len()];
+
+/// Limits the messages of `subsystem` to `level` and below. Applies to all
+/// instances.
+pub fn set_level(subsystem: Subsystem, level: LevelFilter) {
+    LEVELS[subsystem as usize].store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level(subsystem: Subsystem) -> LevelFilter {
+    match LEVELS[subsystem as usize].load(Ordering::Relaxed) {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Whether messages of `subsystem` at `level` are let through
+#[inline]
+pub(crate) fn enabled(subsystem: Subsystem, level: Level) -> bool {
+    level as u8 <= LEVELS[subsystem as usize].load(Ordering::Relaxed)
+}
+
+/// Logs a message of a subsystem, if [set_level] lets it through
+macro_rules! event {
+    ($subsystem:expr, $log:ident, $tracing:ident, $($arg:tt)+) => {
+        if $crate::logging::enabled($subsystem, log::Level::$log) {
+            #[cfg(feature = "tracing")]
+            tracing::event!(target: $subsystem.target(), tracing::Level::$tracing, $($arg)+);
+
+            #[cfg(not(feature = "tracing"))]
+            log::log!(target: $subsystem.target(), log::Level::$log, $($arg)+);
+        }
+    };
+}
+
+macro_rules! debug {
+    ($subsystem:expr, $($arg:tt)+) => {
+        $crate::logging::event!($subsystem, Debug, DEBUG, $($arg)+)
+    };
+}
+
+macro_rules! trace {
+    ($subsystem:expr, $($arg:tt)+) => {
+        $crate::logging::event!($subsystem, Trace, TRACE, $($arg)+)
+    };
+}
+
+pub(crate) use {debug, event, trace};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_per_subsystem() {
+        assert!(enabled(Subsystem::Apu, Level::Trace));
+
+        set_level(Subsystem::Apu, LevelFilter::Info);
+
+        assert_eq!(LevelFilter::Info, level(Subsystem::Apu));
+        assert!(enabled(Subsystem::Apu, Level::Info));
+        assert!(!enabled(Subsystem::Apu, Level::Debug));
+        assert!(enabled(Subsystem::Ppu, Level::Trace));
+
+        set_level(Subsystem::Apu, LevelFilter::Trace);
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(Ok(Subsystem::Ppu), "PPU".parse());
+        assert_eq!(Ok(Subsystem::Memory), "memory".parse());
+        assert_eq!(Err(SubsystemParseErr), "gpu".parse::<Subsystem>());
+    }
+}
@@ -1,4 +1,5 @@
-use crate::{GBAllocator, RomReader};
+use crate::{savestate::state_data, GBAllocator, RomReader};
+use alloc::vec::Vec;
 
 use super::{MemController, ReadError, WriteErrType};
 
@@ -7,13 +8,21 @@ pub struct DMAController {
     oam: Option<DMACommand>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct DMACommand {
     pub cycles: usize,
     pub target_address: u16,
     pub data: Vec<u8>,
 }
 
+state_data!(DMAController { oam });
+
+state_data!(DMACommand {
+    cycles,
+    target_address,
+    data,
+});
+
 impl DMAController {
     pub fn new() -> Self {
         Self { oam: None }
@@ -25,6 +34,10 @@ impl DMAController {
         }
     }
 
+    pub const fn active(&self) -> bool {
+        self.oam.is_some()
+    }
+
     pub fn run_cycle(&mut self) -> Vec<DMACommand> {
         let mut ret = Vec::new();
 
@@ -57,7 +70,7 @@ pub fn oam_dma_command(
     let mut source_data: Vec<u8> = Vec::with_capacity(0x100);
 
     for addr in source_addr..(source_addr + 0x100) {
-        source_data.push(mem.read8(addr)?);
+        source_data.push(mem.read8_direct(addr)?);
     }
 
     Ok(DMACommand {
@@ -0,0 +1,138 @@
+use alloc::boxed::Box;
+use core::fmt::Debug;
+
+use crate::extern_traits::IrTransceiver;
+use crate::model::StateDigest;
+use crate::savestate::state_data;
+
+const RP_LED: u8 = 0b1;
+const RP_NOT_RECEIVING: u8 = 0b10;
+const RP_READ_ENABLE: u8 = 0b11000000;
+const RP_UNUSED: u8 = 0b00111100;
+
+/// RP (0xFF56), the infrared port of the CGB. The game turns its LED on and
+/// off, and while reading is enabled, sees whether light reaches the sensor.
+/// What is on the other side is up to the attached [IrTransceiver].
+///
+/// Other models don't have the port: the register reads 0xFF, and writes
+/// are ignored.
+pub struct Infrared {
+    /// The LED and read enable bits
+    control: u8,
+
+    cgb: bool,
+    transceiver: Option<Box<dyn IrTransceiver>>,
+}
+
+// The transceiver is plugged in by the host, not part of the state
+state_data!(Infrared { control });
+
+impl Infrared {
+    pub const fn new(cgb: bool) -> Self {
+        Self {
+            control: 0,
+            cgb,
+            transceiver: None,
+        }
+    }
+
+    const fn led_on(&self) -> bool {
+        self.control & RP_LED != 0
+    }
+
+    pub fn read(&self) -> u8 {
+        if !self.cgb {
+            return 0xFF;
+        }
+
+        let receiving = self.control & RP_READ_ENABLE == RP_READ_ENABLE
+            && self.transceiver.as_ref().is_some_and(|t| t.receiving());
+
+        let sensor = if receiving { 0 } else { RP_NOT_RECEIVING };
+
+        self.control | RP_UNUSED | sensor
+    }
+
+    pub fn write(&mut self, val: u8) {
+        if !self.cgb {
+            return;
+        }
+
+        let was_on = self.led_on();
+        self.control = val & (RP_READ_ENABLE | RP_LED);
+        let on = self.led_on();
+
+        if was_on != on {
+            if let Some(transceiver) = &mut self.transceiver {
+                transceiver.set_led(on);
+            }
+        }
+    }
+
+    pub fn attach(&mut self, transceiver: Box<dyn IrTransceiver>) {
+        self.transceiver = Some(transceiver);
+    }
+
+    pub fn detach(&mut self) -> Option<Box<dyn IrTransceiver>> {
+        self.transceiver.take()
+    }
+
+    /// Turns the LED off and disables reading, keeping the transceiver
+    pub fn reset(&mut self) {
+        self.write(0);
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_u8(self.control);
+    }
+}
+
+impl Debug for Infrared {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Infrared")
+            .field("control", &self.control)
+            .field("cgb", &self.cgb)
+            .field("transceiver", &self.transceiver.is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrared::IrLoopback;
+
+    #[test]
+    fn sees_light_of_other_side_while_reading() {
+        let (a, b) = IrLoopback::pair();
+
+        let mut first = Infrared::new(true);
+        let mut second = Infrared::new(true);
+        first.attach(Box::new(a));
+        second.attach(Box::new(b));
+
+        second.write(RP_READ_ENABLE);
+        assert_eq!(0xFE, second.read());
+
+        first.write(RP_LED);
+        assert_eq!(0x3F, first.read());
+        assert_eq!(0xFC, second.read());
+
+        // Nothing is seen with reading disabled
+        second.write(0);
+        assert_eq!(0x3E, second.read());
+
+        first.write(0);
+        second.write(RP_READ_ENABLE);
+        assert_eq!(0xFE, second.read());
+    }
+
+    #[test]
+    fn no_port_without_cgb() {
+        let mut dmg = Infrared::new(false);
+        dmg.attach(Box::new(IrLoopback::mirror()));
+
+        dmg.write(RP_READ_ENABLE | RP_LED);
+        assert_eq!(0xFF, dmg.read());
+    }
+}
@@ -1,9 +1,13 @@
+use crate::savestate::state_data;
+
 #[derive(Debug, Clone, Copy, Default)]
 #[repr(transparent)]
 pub struct Interrupts {
     raw: u8,
 }
 
+state_data!(Interrupts { raw });
+
 impl Interrupts {
     #[inline]
     const fn get(self, mask: u8) -> bool {
@@ -1,10 +1,18 @@
-use core::num::Wrapping;
-
+use alloc::boxed::Box;
 use thiserror::Error;
 
-use crate::ppu::palette::Palette;
+use crate::{
+    apu::Apu,
+    input::{get_input_nibble_for_buttons, get_input_nibble_for_dpad},
+    model::{BootIo, InputPolling, StateDigest},
+    movie,
+    ppu::{palette::Palette, LcdMode},
+    savestate::state_data,
+    sgb::Sgb,
+    GbInputs,
+};
 
-use super::interrupts::Interrupts;
+use super::{infrared::Infrared, interrupts::Interrupts, serial::Serial, timer::Timer};
 
 #[derive(Debug, Copy, Clone, Default)]
 pub struct LcdControl(u8);
@@ -21,6 +29,8 @@ impl From<LcdControl> for u8 {
     }
 }
 
+state_data!(LcdControl { 0 });
+
 impl LcdControl {
     #[inline]
     const fn get(self, mask: u8) -> bool {
@@ -117,31 +127,296 @@ impl LcdControl {
     }
 }
 
+const STAT_MODE: u8 = 0b11;
+const STAT_LYC_EQUAL: u8 = 0b100;
+const STAT_SELECT: u8 = 0b01111000;
+
+/// The LCD status register (STAT). The PPU keeps the mode in the lower two
+/// bits up to date, the LY=LYC flag is derived on every read, and only the
+/// interrupt selects in bits 3 to 6 are writable. Bit 7 is unused and reads
+/// as set.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct LcdStat(u8);
+
+impl From<u8> for LcdStat {
+    fn from(value: u8) -> Self {
+        Self(value & (STAT_SELECT | STAT_MODE))
+    }
+}
+
+impl From<LcdStat> for u8 {
+    fn from(value: LcdStat) -> Self {
+        value.0
+    }
+}
+
+state_data!(LcdStat { 0 });
+
+impl LcdStat {
+    /// Sets the mode bits. While the LCD is off, they read as HBlank.
+    #[inline]
+    pub fn set_mode(&mut self, mode: LcdMode) {
+        let bits = match mode {
+            LcdMode::Off | LcdMode::HBlank => 0,
+            LcdMode::VBlank => 1,
+            LcdMode::OamScan => 2,
+            LcdMode::Draw => 3,
+        };
+
+        self.0 = (self.0 & !STAT_MODE) | bits;
+    }
+
+    pub fn write(&mut self, val: u8) {
+        self.0 = (self.0 & STAT_MODE) | (val & STAT_SELECT);
+    }
+
+    pub const fn read(self, lyc_equal: bool) -> u8 {
+        let lyc_equal = if lyc_equal { STAT_LYC_EQUAL } else { 0 };
+
+        0b10000000 | lyc_equal | self.0
+    }
+}
+
+const P1_SELECT_BUTTONS: u8 = 0b00100000;
+const P1_SELECT_DPAD: u8 = 0b00010000;
+
+/// The joypad register (P1). The CPU selects the buttons and/or the d-pad
+/// by pulling bit 5 and/or 4 low, after which the lower nibble reads the
+/// selected inputs, 0 meaning pressed. With both selected, the nibbles are
+/// ANDed together.
+///
+/// The lower nibble is derived from the stored inputs on every read, so
+/// changing the selection mid-frame reads the right group. Whenever one of
+/// the lines goes from high to low, by a press or by a selection change,
+/// the joypad interrupt should be requested.
+///
+/// The Super Game Boy can ask for up to four joypads, see
+/// [crate::sgb]. With more than one, P1 reads the number of the current
+/// joypad while nothing is selected, and releasing P15 moves on to the next.
+#[derive(Debug, Clone)]
+pub struct Joypad {
+    /// Bits 4 and 5 of P1
+    select: u8,
+
+    inputs: GbInputs,
+
+    /// The inputs of the second to fourth player
+    others: [GbInputs; 3],
+    players: u8,
+
+    /// The joypad being read, 0 for the first
+    player: u8,
+
+    /// T-cycles left until the inputs are polled again, with
+    /// [InputPolling::Interval]
+    poll_in: u16,
+
+    /// Configuration, so not part of the state
+    polling: InputPolling,
+}
+
+state_data!(Joypad {
+    select,
+    inputs,
+    others,
+    players,
+    player,
+    poll_in,
+});
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Joypad {
+    pub const fn new() -> Self {
+        Self {
+            select: P1_SELECT_BUTTONS | P1_SELECT_DPAD,
+            inputs: GbInputs {
+                start: false,
+                select: false,
+                b: false,
+                a: false,
+                down: false,
+                up: false,
+                left: false,
+                right: false,
+            },
+            others: [GbInputs {
+                start: false,
+                select: false,
+                b: false,
+                a: false,
+                down: false,
+                up: false,
+                left: false,
+                right: false,
+            }; 3],
+            players: 1,
+            player: 0,
+            poll_in: 0,
+            polling: InputPolling::VBlank,
+        }
+    }
+
+    /// The lower nibble of P1
+    fn lines(&self) -> u8 {
+        let deselected = P1_SELECT_BUTTONS | P1_SELECT_DPAD;
+
+        if self.players > 1 && self.select == deselected {
+            return 0x0F - self.player;
+        }
+
+        let inputs = match self.player {
+            0 => self.inputs,
+            player => self.others[player as usize - 1],
+        };
+
+        let mut lines = 0x0F;
+
+        if self.select & P1_SELECT_BUTTONS == 0 {
+            lines &= get_input_nibble_for_buttons(inputs);
+        }
+
+        if self.select & P1_SELECT_DPAD == 0 {
+            lines &= get_input_nibble_for_dpad(inputs);
+        }
+
+        lines
+    }
+
+    /// Applies a change, returning true if any line went from high to low
+    fn update(&mut self, change: impl FnOnce(&mut Self)) -> bool {
+        let before = self.lines();
+
+        change(self);
+
+        before & !self.lines() != 0
+    }
+
+    pub fn read(&self) -> u8 {
+        // The unused upper bits always read as set
+        0b11000000 | self.select | self.lines()
+    }
+
+    /// Only the select bits are writable. Returns true if the joypad
+    /// interrupt should be requested.
+    pub fn write(&mut self, val: u8) -> bool {
+        self.update(|joypad| {
+            let released = joypad.select & P1_SELECT_BUTTONS == 0 && val & P1_SELECT_BUTTONS != 0;
+
+            if released {
+                joypad.player = (joypad.player + 1) % joypad.players;
+            }
+
+            joypad.select = val & (P1_SELECT_BUTTONS | P1_SELECT_DPAD);
+        })
+    }
+
+    pub const fn inputs(&self) -> GbInputs {
+        self.inputs
+    }
+
+    /// Returns true if the joypad interrupt should be requested
+    pub fn set_inputs(&mut self, inputs: GbInputs) -> bool {
+        self.update(|joypad| joypad.inputs = inputs)
+    }
+
+    /// The amount of joypads the game asked for, 1, 2 or 4
+    pub const fn players(&self) -> u8 {
+        self.players
+    }
+
+    /// Starts over at the first of the given amount of joypads
+    pub fn set_players(&mut self, players: u8) {
+        self.players = players.clamp(1, 4);
+        self.player = 0;
+    }
+
+    /// Sets the inputs of the second (1) to fourth (3) player. Returns true
+    /// if the joypad interrupt should be requested
+    pub fn set_player_inputs(&mut self, player: usize, inputs: GbInputs) -> bool {
+        match player {
+            0 => self.set_inputs(inputs),
+            1..=3 => self.update(|joypad| joypad.others[player - 1] = inputs),
+            _ => false,
+        }
+    }
+
+    pub const fn polling(&self) -> InputPolling {
+        self.polling
+    }
+
+    pub fn set_polling(&mut self, polling: InputPolling) {
+        self.polling = polling;
+        self.poll_in = 0;
+    }
+
+    /// Runs a single T-cycle. Returns true if the inputs should be polled
+    /// because the interval passed. Polling at VBlank is up to the PPU.
+    pub fn cycle(&mut self) -> bool {
+        let InputPolling::Interval(interval) = self.polling else {
+            return false;
+        };
+
+        if self.poll_in == 0 {
+            self.poll_in = interval.get() - 1;
+            true
+        } else {
+            self.poll_in -= 1;
+            false
+        }
+    }
+
+    pub fn apply_boot_state(&mut self, p1: u8) {
+        *self = Self {
+            select: p1 & (P1_SELECT_BUTTONS | P1_SELECT_DPAD),
+            polling: self.polling,
+            ..Self::new()
+        };
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_u8(self.select);
+        digest.write_u8(movie::pack(self.inputs));
+
+        for inputs in self.others {
+            digest.write_u8(movie::pack(inputs));
+        }
+
+        digest.write_u8(self.players);
+        digest.write_u8(self.player);
+        digest.write_u16(self.poll_in);
+    }
+}
+
 #[derive(Debug)]
 pub struct IoRegs {
     /// 0xFF00
-    pub joypad: u8,
-
-    /// 0xFF04
-    pub timer_div: Wrapping<u8>,
+    pub joypad: Joypad,
 
-    /// 0xFF05
-    pub timer_counter: u8,
+    /// 0xFF01 - 0xFF02
+    pub serial: Serial,
 
-    /// 0xFF06
-    pub timer_modulo: u8,
+    /// Listens to P1 when running a Super Game Boy game
+    pub sgb: Option<Box<Sgb>>,
 
-    /// 0xFF07
-    pub timer_control: u8,
+    /// 0xFF04 - 0xFF07
+    pub timer: Timer,
 
     /// 0xFF0F
     pub interrupts_requested: Interrupts,
 
+    /// 0xFF10 - 0xFF3F
+    pub apu: Apu,
+
     /// 0xFF40
     pub lcd_control: LcdControl,
 
     /// 0xFF41
-    pub lcd_stat: u8,
+    pub lcd_stat: LcdStat,
 
     /// 0xFF42
     pub scy: u8,
@@ -175,13 +450,22 @@ pub struct IoRegs {
 
     /// 0xFF50
     pub boot_rom_enabled: bool,
+
+    /// 0xFF56
+    pub infrared: Infrared,
 }
 
-#[derive(Debug, Error)]
-pub enum IoWriteErr {}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum IoWriteErr {
+    #[error("No I/O register at 0x{0:04x}")]
+    Unmapped(u16),
+}
 
-#[derive(Debug, Error)]
-pub enum IoReadErr {}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum IoReadErr {
+    #[error("No I/O register at 0x{0:04x}")]
+    Unmapped(u16),
+}
 
 impl Default for IoRegs {
     fn default() -> Self {
@@ -189,17 +473,40 @@ impl Default for IoRegs {
     }
 }
 
+state_data!(IoRegs {
+    joypad,
+    serial,
+    sgb,
+    timer,
+    interrupts_requested,
+    apu,
+    lcd_control,
+    lcd_stat,
+    scy,
+    scx,
+    lcd_y,
+    lcd_y_comp,
+    oam_dma,
+    bg_palette,
+    obj0_palette,
+    obj1_palette,
+    win_y,
+    win_x,
+    boot_rom_enabled,
+    infrared,
+});
+
 impl IoRegs {
     pub fn new() -> Self {
         Self {
-            joypad: 0,
-            timer_div: Wrapping(0),
-            timer_counter: 0,
-            timer_modulo: 0,
-            timer_control: 0,
+            joypad: Joypad::new(),
+            serial: Serial::new(false),
+            sgb: None,
+            timer: Timer::new(),
             interrupts_requested: Interrupts::default(),
+            apu: Apu::new(),
             lcd_control: LcdControl::default(),
-            lcd_stat: 0,
+            lcd_stat: LcdStat::default(),
             scy: 0,
             scx: 0,
             lcd_y: 0,
@@ -211,22 +518,124 @@ impl IoRegs {
             win_y: 0,
             win_x: 0,
             boot_rom_enabled: cfg!(feature = "boot_img_enabled"),
+            infrared: Infrared::new(false),
+        }
+    }
+
+    /// Loads the given preset into the registers and unmaps the boot ROM
+    pub fn apply_boot_state(&mut self, io: &BootIo) {
+        self.joypad.apply_boot_state(io.joypad);
+        self.serial.reset();
+        self.timer.apply_boot_state(
+            io.timer_div,
+            io.timer_counter,
+            io.timer_modulo,
+            io.timer_control,
+        );
+        self.interrupts_requested = io.interrupts_requested.into();
+        self.apu
+            .apply_boot_state(io.audio_volume, io.audio_panning, io.audio_enable);
+        self.lcd_control = io.lcd_control.into();
+        self.lcd_stat = io.lcd_stat.into();
+        self.scy = io.scy;
+        self.scx = io.scx;
+        self.lcd_y = io.lcd_y;
+        self.lcd_y_comp = io.lcd_y_comp;
+        self.oam_dma = io.oam_dma;
+        self.bg_palette = io.bg_palette.into();
+        self.obj0_palette = io.obj0_palette.into();
+        self.obj1_palette = io.obj1_palette.into();
+        self.win_y = io.win_y;
+        self.win_x = io.win_x;
+        self.boot_rom_enabled = false;
+        self.infrared.reset();
+    }
+
+    /// Puts the registers back in their power on state and maps the boot
+    /// ROM, like [IoRegs::apply_boot_state] does for the state after it.
+    /// The input polling, audio output and attached handlers are kept.
+    pub fn power_on(&mut self) {
+        self.joypad.apply_boot_state(0);
+        self.serial.reset();
+
+        if let Some(sgb) = &mut self.sgb {
+            **sgb = Sgb::default();
         }
+
+        self.timer = Timer::new();
+        self.interrupts_requested = Interrupts::default();
+        self.apu.apply_boot_state(0, 0, 0);
+        self.lcd_control = LcdControl::default();
+        self.lcd_stat = LcdStat::default();
+        self.scy = 0;
+        self.scx = 0;
+        self.lcd_y = 0;
+        self.lcd_y_comp = 0;
+        self.oam_dma = 0;
+        self.bg_palette = Palette::new();
+        self.obj0_palette = Palette::new();
+        self.obj1_palette = Palette::new();
+        self.win_y = 0;
+        self.win_x = 0;
+        self.boot_rom_enabled = true;
+        self.infrared.reset();
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        self.joypad.digest_into(digest);
+        self.serial.digest_into(digest);
+        self.timer.digest_into(digest);
+        digest.write_u8(self.interrupts_requested.into());
+        self.apu.digest_into(digest);
+        digest.write_u8(self.lcd_control.into());
+        digest.write_u8(self.lcd_stat.into());
+        digest.write_u8(self.scy);
+        digest.write_u8(self.scx);
+        digest.write_u8(self.lcd_y);
+        digest.write_u8(self.lcd_y_comp);
+        digest.write_u8(self.oam_dma);
+        digest.write_u8(self.bg_palette.into());
+        digest.write_u8(self.obj0_palette.into());
+        digest.write_u8(self.obj1_palette.into());
+        digest.write_u8(self.win_y);
+        digest.write_u8(self.win_x);
+        digest.write_bool(self.boot_rom_enabled);
+        self.infrared.digest_into(digest);
+    }
+
+    /// STAT as the CPU reads it
+    pub const fn stat(&self) -> u8 {
+        self.lcd_stat.read(self.lcd_y == self.lcd_y_comp)
     }
 
+    /// Fails for addresses without a register, what happens then is up to
+    /// the [super::Strictness] of the memory controller
     pub fn write(&mut self, addr: u16, val: u8) -> Result<(), IoWriteErr> {
         match addr {
             ..=0xFEFF => panic!("Too low for I/O range"),
-            0xFF00 => self.joypad = (self.joypad & 0x0F) | (val & 0xF0),
-            0xFF04 => self.timer_div.0 = 0, // Writing to div register always resets it
-            0xFF05 => self.timer_counter = val,
-            0xFF06 => self.timer_modulo = val,
-            0xFF07 => self.timer_control = val,
+            0xFF00 => {
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_p1(val, &mut self.joypad);
+                }
+
+                if self.joypad.write(val) {
+                    self.interrupts_requested.set_joypad(true);
+                }
+            }
+            0xFF01 => self.serial.write_data(val),
+            0xFF02 => self.serial.write_control(val),
+            0xFF04 => self.timer.write_div(),
+            0xFF05 => self.timer.write_tima(val),
+            0xFF06 => self.timer.write_tma(val),
+            0xFF07 => self.timer.write_tac(val),
+            0xFF0F => self.interrupts_requested = (val & 0x1F).into(),
+            0xFF10..=0xFF3F => self.apu.write(addr, val),
             0xFF40 => self.lcd_control = val.into(),
-            0xFF41 => self.lcd_stat = val,
+            0xFF41 => self.lcd_stat.write(val),
             0xFF42 => self.scy = val,
             0xFF43 => self.scx = val,
-            // 0xFF44 => self.lcd_y = val,
+            // LY is read only
+            0xFF44 => {}
             0xFF45 => self.lcd_y_comp = val,
             0xFF46 => self.oam_dma = val,
             0xFF47 => self.bg_palette = val.into(),
@@ -241,25 +650,30 @@ impl IoRegs {
 
                 self.boot_rom_enabled = self.boot_rom_enabled && val == 0; // Disable boot-rom if non-zero is written
             }
+            0xFF56 => self.infrared.write(val),
             0xFF80.. => panic!("Too high for I/O range"),
-            _ => {
-                log::debug!("I/O register not implemented for writing: 0x{:x}", addr);
-            }
+            _ => return Err(IoWriteErr::Unmapped(addr)),
         };
 
         Ok(())
     }
 
+    /// Fails for addresses without a register, like [IoRegs::write]
     pub fn read(&self, addr: u16) -> Result<u8, IoReadErr> {
         match addr {
             ..=0xFEFF => panic!("Too low for I/O range"),
-            0xFF00 => Ok(self.joypad),
-            0xFF04 => Ok(self.timer_div.0),
-            0xFF05 => Ok(self.timer_counter),
-            0xFF06 => Ok(self.timer_modulo),
-            0xFF07 => Ok(self.timer_control),
+            0xFF00 => Ok(self.joypad.read()),
+            0xFF01 => Ok(self.serial.data()),
+            0xFF02 => Ok(self.serial.control()),
+            0xFF04 => Ok(self.timer.div()),
+            0xFF05 => Ok(self.timer.tima()),
+            0xFF06 => Ok(self.timer.tma()),
+            0xFF07 => Ok(self.timer.tac()),
+            // The unused upper bits always read as set
+            0xFF0F => Ok(u8::from(self.interrupts_requested) | 0xE0),
+            0xFF10..=0xFF3F => Ok(self.apu.read(addr)),
             0xFF40 => Ok(self.lcd_control.into()),
-            0xFF41 => Ok(self.lcd_stat),
+            0xFF41 => Ok(self.stat()),
             0xFF42 => Ok(self.scy),
             0xFF43 => Ok(self.scx),
             0xFF44 => Ok(self.lcd_y),
@@ -270,14 +684,122 @@ impl IoRegs {
             0xFF49 => Ok(self.obj1_palette.into()),
             0xFF4A => Ok(self.win_y),
             0xFF4B => Ok(self.win_x),
+            // Write only
+            0xFF50 => Ok(0xFF),
+            0xFF56 => Ok(self.infrared.read()),
             0xFF80.. => panic!("Too high for I/O range"),
-            _ => {
-                log::debug!(
-                    "I/O register not implemented for reading: 0x{:x}, returning 0x00",
-                    addr
-                );
-                Ok(0x00)
-            }
+            _ => Err(IoReadErr::Unmapped(addr)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUTTONS: u8 = 0b00010000;
+    const DPAD: u8 = 0b00100000;
+
+    #[test]
+    fn p1_reads_selected_group() {
+        let mut joypad = Joypad::new();
+        joypad.set_inputs(GbInputs {
+            a: true,
+            up: true,
+            ..Default::default()
+        });
+
+        assert_eq!(0xFF, joypad.read());
+
+        joypad.write(BUTTONS);
+        assert_eq!(0b11011110, joypad.read());
+
+        // Changing the selection recomputes the lower nibble
+        joypad.write(DPAD);
+        assert_eq!(0b11101011, joypad.read());
+
+        // Both groups selected
+        joypad.write(0x00);
+        assert_eq!(0b11001010, joypad.read());
+    }
+
+    #[test]
+    fn interrupt_on_falling_line() {
+        let mut joypad = Joypad::new();
+        let a = GbInputs {
+            a: true,
+            ..Default::default()
+        };
+
+        // Nothing selected, so no line falls
+        assert!(!joypad.set_inputs(a));
+        assert!(!joypad.set_inputs(GbInputs::default()));
+
+        assert!(!joypad.write(BUTTONS));
+        assert!(joypad.set_inputs(a));
+
+        // Held, and released
+        assert!(!joypad.set_inputs(a));
+        assert!(!joypad.set_inputs(GbInputs::default()));
+
+        // Selecting a group with a held button
+        joypad.write(DPAD);
+        joypad.set_inputs(a);
+        assert!(joypad.write(BUTTONS));
+    }
+
+    #[test]
+    fn p1_write_raises_interrupt() {
+        let mut io = IoRegs::new();
+        io.joypad.set_inputs(GbInputs {
+            start: true,
+            ..Default::default()
+        });
+
+        io.write(0xFF00, DPAD).unwrap();
+        assert!(!io.interrupts_requested.joypad());
+
+        io.write(0xFF00, BUTTONS).unwrap();
+        assert!(io.interrupts_requested.joypad());
+        assert_eq!(0b11010111, io.read(0xFF00).unwrap());
+    }
+
+    #[test]
+    fn stat_reads_mode_and_lyc_flag() {
+        let mut io = IoRegs::new();
+        io.lcd_stat.set_mode(LcdMode::Draw);
+        io.lcd_y = 5;
+        io.lcd_y_comp = 5;
+
+        // The mode and flag are read-only
+        io.write(0xFF41, 0x00).unwrap();
+        assert_eq!(0b10000111, io.read(0xFF41).unwrap());
+
+        io.write(0xFF41, 0xFF).unwrap();
+        io.lcd_y_comp = 6;
+        io.lcd_stat.set_mode(LcdMode::VBlank);
+        assert_eq!(0b11111001, io.read(0xFF41).unwrap());
+
+        io.lcd_stat.set_mode(LcdMode::Off);
+        assert_eq!(0b11111000, io.read(0xFF41).unwrap());
+    }
+
+    #[test]
+    fn polls_at_interval() {
+        let mut joypad = Joypad::new();
+
+        for _ in 0..1000 {
+            assert!(!joypad.cycle());
         }
+
+        joypad.set_polling(InputPolling::Interval(100.try_into().unwrap()));
+
+        assert!(joypad.cycle());
+
+        for _ in 1..100 {
+            assert!(!joypad.cycle());
+        }
+
+        assert!(joypad.cycle());
     }
 }
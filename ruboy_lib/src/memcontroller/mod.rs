@@ -1,4 +1,7 @@
-use std::{error::Error, fmt::Display};
+use alloc::borrow::Cow;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::{cell::RefCell, error::Error, fmt::Display};
 
 use dma::{DMACommand, DMAController};
 use interrupts::Interrupts;
@@ -7,17 +10,33 @@ use thiserror::Error;
 
 use crate::{
     boot,
-    extern_traits::{GBAllocator, GBRam, RomReader},
+    debug::{Access, Debugger, FaultReads, FaultState},
+    extern_traits::{BusSnooper, GBAllocator, GBRam, RomReader},
     isa::decoder::DecoderReadable,
+    logging::{debug, trace, Subsystem},
+    model::{BootState, GbModel, RamInit, StateDigest, BOOT_ROM_SIZE},
+    quirks::{self, Quirk},
     rom::{
         self,
-        controller::{RomController, RomControllerInitErr},
+        controller::{BankCaching, RomController, RomControllerInitErr},
+        meta::RomMeta,
+        rtc::Rtc,
     },
+    savestate::{StateData, StateLoadErr, StateReader, StateWriter},
+    GbInputs,
+};
+#[cfg(feature = "cached_interpreter")]
+use crate::{
+    cpu::blockcache::{self, BlockCache, CodeSource},
+    isa::{decoder, Instruction},
 };
 
 mod dma;
+pub mod infrared;
 pub mod interrupts;
 pub mod io;
+pub mod serial;
+pub mod timer;
 
 pub const VRAM_START: u16 = 0x8000;
 pub const VRAM_END: u16 = 0xA000;
@@ -46,6 +65,10 @@ pub struct MemController<A: GBAllocator, R: RomReader> {
     oam: A::Mem<u8, { OAM_SIZE as usize }>,
     hram: A::Mem<u8, { HRAM_SIZE as usize }>,
 
+    /// Mapped over the start of the cartridge until the boot ROM disables
+    /// itself
+    boot_image: Cow<'static, [u8]>,
+
     dma_controller: DMAController,
 
     pub interrupts_enabled: Interrupts,
@@ -56,11 +79,57 @@ pub struct MemController<A: GBAllocator, R: RomReader> {
     /// If true, CPU can access OAM mem
     pub oam_open: bool,
 
+    /// If true, CPU accesses to VRAM/OAM that are blocked because the PPU
+    /// currently owns that memory are logged
+    pub log_blocked_access: bool,
+
+    /// If false, the CPU can always access VRAM/OAM. Only disabled by
+    /// [Quirk::NoAccessBlocking]
+    access_blocking: bool,
+
+    strictness: Strictness,
+
+    /// If true, CPU accesses that fail are let through instead, see
+    /// [crate::ErrorPolicy::Ignore]
+    pub(crate) ignore_errors: bool,
+
+    /// If true, accesses to I/O addresses without a register are logged
+    /// when they are let through, see [Strictness::Permissive]
+    pub log_unmapped_io: bool,
+
+    /// If true, the CPU reads LY as 0x90, as gameboy-doctor logs expect
+    pub(crate) doctor_ly: bool,
+
+    /// Injected cartridge faults, for robustness testing
+    pub(crate) cart_fault: FaultState,
+
+    /// The attached debugger, which watches CPU memory accesses
+    pub(crate) debugger: Option<Debugger>,
+
+    /// The attached bus snooper. Reads take &self, hence the RefCell
+    pub(crate) snooper: Option<RefCell<Box<dyn BusSnooper>>>,
+
+    #[cfg(feature = "cached_interpreter")]
+    pub(crate) block_cache: BlockCache,
+
     pub io_registers: IoRegs,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum MemRegion {
+/// What happens when the CPU accesses an I/O address without a register
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Strictness {
+    /// The access fails, which stops emulation. Useful for finding
+    /// registers that games use but aren't emulated yet.
+    Strict,
+
+    /// Reads return 0xFF and writes are ignored, like on hardware
+    #[default]
+    Permissive,
+}
+
+/// The part of the memory map an address belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemRegion {
     BootRom,
     Cartridge,
     VRam,
@@ -73,8 +142,31 @@ enum MemRegion {
     InterruptEnableReg,
 }
 
+/// The region of every 256 byte page of the address space. The boot ROM
+/// shares its page with the cartridge, OAM with the prohibited area, and the
+/// I/O registers with high RAM and IE, see [MemController::map_to_region].
+const REGION_PAGES: [MemRegion; 256] = {
+    let mut pages = [MemRegion::Cartridge; 256];
+    let mut page = 0;
+
+    while page < pages.len() {
+        pages[page] = match page {
+            0x80..=0x9F => MemRegion::VRam,
+            0xC0..=0xDF => MemRegion::WorkRam,
+            0xE0..=0xFD => MemRegion::EchoRam,
+            0xFE => MemRegion::ObjectAttrMem,
+            0xFF => MemRegion::IORegs,
+            _ => MemRegion::Cartridge,
+        };
+
+        page += 1;
+    }
+
+    pages
+};
+
 impl Display for MemRegion {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let name = match self {
             MemRegion::BootRom => "Boot ROM",
             MemRegion::Cartridge => "Cartridge",
@@ -92,7 +184,7 @@ impl Display for MemRegion {
     }
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
 pub enum ReadErrType {
     #[error("Error during ROM reading: {0}")]
     Rom(#[from] rom::controller::ReadError),
@@ -101,13 +193,25 @@ pub enum ReadErrType {
     IORegs(#[from] IoReadErr),
 }
 
-#[derive(Debug)]
+/// Any memory access can fail, so errors are cheap to make: small, Copy and
+/// never allocating. The region is only for the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ReadError {
     addr: u16,
     region: MemRegion,
     err: ReadErrType,
 }
 
+impl ReadError {
+    fn new(addr: u16, region: MemRegion, err: impl Into<ReadErrType>) -> Self {
+        Self {
+            addr,
+            region,
+            err: err.into(),
+        }
+    }
+}
+
 impl Error for ReadError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         Some(&self.err)
@@ -115,7 +219,7 @@ impl Error for ReadError {
 }
 
 impl Display for ReadError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "Error during memory read at 0x{:x} in region {}: {}",
@@ -124,13 +228,24 @@ impl Display for ReadError {
     }
 }
 
-#[derive(Debug)]
+/// See [ReadError]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct WriteError {
     addr: u16,
     region: MemRegion,
     err: WriteErrType,
 }
 
+impl WriteError {
+    fn new(addr: u16, region: MemRegion, err: impl Into<WriteErrType>) -> Self {
+        Self {
+            addr,
+            region,
+            err: err.into(),
+        }
+    }
+}
+
 impl Error for WriteError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         Some(&self.err)
@@ -138,7 +253,7 @@ impl Error for WriteError {
 }
 
 impl Display for WriteError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "Error during memory write at 0x{:x} in region {}",
@@ -147,7 +262,10 @@ impl Display for WriteError {
     }
 }
 
-#[derive(Debug, Error)]
+// Returned by value from every memory access
+static_assertions::const_assert!(size_of::<WriteError>() <= 16);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
 pub enum WriteErrType {
     #[error("Write to read-only memory")]
     ReadOnly,
@@ -162,12 +280,6 @@ pub enum WriteErrType {
     DMA(#[source] ReadError),
 }
 
-macro_rules! unimplemented_read {
-    ($region:expr) => {
-        todo!("Attempted read at unimplemented region {}", $region)
-    };
-}
-
 macro_rules! unimplemented_write {
     ($region:expr) => {
         todo!("Attempted write at unimplemented region {}", $region)
@@ -181,60 +293,227 @@ pub enum MemControllerInitErr<R: RomReader> {
 }
 
 impl<A: GBAllocator, R: RomReader> MemController<A, R> {
-    pub fn new(rom: R) -> Result<Self, MemControllerInitErr<R>> {
-        log::debug!("Initializing memory controller");
+    pub fn new(rom: R, caching: BankCaching) -> Result<Self, MemControllerInitErr<R>> {
+        debug!(Subsystem::Memory, "Initializing memory controller");
+
+        let rom = RomController::new(rom, caching)?;
+        let access_blocking = !quirks::applies(rom.meta(), Quirk::NoAccessBlocking);
 
         Ok(MemController {
-            rom: RomController::new(rom)?,
+            rom,
             vram: A::empty(),
             ram: A::empty(),
             oam: A::empty(),
             hram: A::empty(),
+            boot_image: Cow::Borrowed(boot::IMAGE),
             dma_controller: DMAController::new(),
             io_registers: IoRegs::new(),
             interrupts_enabled: Interrupts::default(),
             vram_open: true,
             oam_open: true,
+            log_blocked_access: false,
+            access_blocking,
+            strictness: Strictness::default(),
+            ignore_errors: false,
+            log_unmapped_io: false,
+            doctor_ly: false,
+            cart_fault: FaultState::default(),
+            debugger: None,
+            snooper: None,
+            #[cfg(feature = "cached_interpreter")]
+            block_cache: BlockCache::new(),
         })
     }
 
-    #[inline]
-    fn r_err(&self, addr: u16, err: impl Into<ReadErrType>) -> ReadError {
-        ReadError {
-            addr,
-            region: self.map_to_region(addr),
-            err: err.into(),
+    /// Runs the given image instead of the compiled in boot ROM. Must be
+    /// [BOOT_ROM_SIZE] bytes.
+    pub fn set_boot_image(&mut self, image: Vec<u8>) {
+        debug_assert_eq!(BOOT_ROM_SIZE, image.len());
+        self.boot_image = Cow::Owned(image);
+        self.io_registers.boot_rom_enabled = true;
+
+        #[cfg(feature = "cached_interpreter")]
+        self.block_cache.clear();
+    }
+
+    /// Applies the I/O and RAM part of a boot state preset
+    pub fn apply_boot_state(&mut self, state: &BootState) {
+        self.io_registers.apply_boot_state(&state.io);
+        self.interrupts_enabled = state.io.interrupts_enabled.into();
+    }
+
+    pub fn init_ram(&mut self, ram_init: RamInit, model: GbModel) {
+        ram_init.fill(model, self.ram.raw_mut(), self.hram.raw_mut());
+    }
+
+    /// Puts the mapper registers of the cartridge back in their power on
+    /// state. The RAM and clock keep their contents.
+    pub fn reset_mapper(&mut self) -> Result<(), WriteError> {
+        // The values the registers start with: RAM off, ROM bank 1, RAM bank
+        // 0 and mode 0. The high ROM bank bit of the MBC5 lives at 0x3000.
+        for (addr, value) in [
+            (0x0000, 0x00),
+            (0x2000, 0x01),
+            (0x3000, 0x00),
+            (0x4000, 0x00),
+            (0x6000, 0x00),
+        ] {
+            self.rom
+                .write(addr, value)
+                .map_err(|e| WriteError::new(addr, MemRegion::Cartridge, e))?;
         }
+
+        Ok(())
     }
 
-    #[inline]
-    fn w_err(&self, addr: u16, err: impl Into<WriteErrType>) -> WriteError {
-        WriteError {
-            addr,
-            region: self.map_to_region(addr),
-            err: err.into(),
+    /// Clears the memory and registers like a power cycle, mapping the boot
+    /// ROM again. Leaves the cartridge alone, see [MemController::reset_mapper],
+    /// and everything attached stays attached.
+    pub fn reset(&mut self) {
+        self.vram = A::empty();
+        self.ram = A::empty();
+        self.oam = A::empty();
+        self.hram = A::empty();
+        self.dma_controller = DMAController::new();
+        self.io_registers.power_on();
+        self.interrupts_enabled = Interrupts::default();
+        self.vram_open = true;
+        self.oam_open = true;
+
+        #[cfg(feature = "cached_interpreter")]
+        self.block_cache.clear_ram();
+    }
+
+    /// Swaps in another cartridge. The previous one is dropped, so save its
+    /// RAM first.
+    pub fn insert_rom(
+        &mut self,
+        rom: R,
+        caching: BankCaching,
+    ) -> Result<(), MemControllerInitErr<R>> {
+        self.rom = RomController::new(rom, caching)?;
+        self.access_blocking = !quirks::applies(self.rom.meta(), Quirk::NoAccessBlocking);
+
+        #[cfg(feature = "cached_interpreter")]
+        self.block_cache.clear();
+
+        Ok(())
+    }
+
+    pub fn disable_access_blocking(&mut self) {
+        self.access_blocking = false;
+    }
+
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.strictness = strictness;
+    }
+
+    pub const fn strictness(&self) -> Strictness {
+        self.strictness
+    }
+
+    /// Derives the power on state that would otherwise depend on the host
+    /// from the seed, see [crate::model::RuboyConfig::deterministic]
+    pub fn make_deterministic(&mut self, seed: u64, model: GbModel) {
+        self.init_ram(RamInit::Pattern { seed }, model);
+
+        if let Some(rtc) = self.rtc_mut() {
+            *rtc = Rtc::emulated();
         }
     }
 
-    fn map_to_region(&self, addr: u16) -> MemRegion {
-        match addr {
-            0x0..=0xFF => {
-                if self.io_registers.boot_rom_enabled {
-                    MemRegion::BootRom
-                } else {
-                    MemRegion::Cartridge
-                }
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rom.rtc_mut()
+    }
+
+    pub fn set_bank_caching(&mut self, caching: BankCaching) -> Result<(), R::Err> {
+        self.rom.set_bank_caching(caching)
+    }
+
+    /// Whether the rumble motor of the cartridge is on
+    pub fn rumble(&self) -> bool {
+        self.rom.rumble()
+    }
+
+    /// The header checksum of the inserted cartridge
+    pub fn header_checksum(&self) -> u8 {
+        self.rom.meta().header_checksum()
+    }
+
+    pub fn rom_meta(&self) -> &RomMeta {
+        self.rom.meta()
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_bytes(self.vram.raw());
+        digest.write_bytes(self.ram.raw());
+        digest.write_bytes(self.oam.raw());
+        digest.write_bytes(self.hram.raw());
+        digest.write_u8(self.interrupts_enabled.into());
+        digest.write_bool(self.vram_open);
+        digest.write_bool(self.oam_open);
+        self.io_registers.digest_into(digest);
+    }
+
+    pub(crate) fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(self.vram.raw());
+        w.write_bytes(self.ram.raw());
+        w.write_bytes(self.oam.raw());
+        w.write_bytes(self.hram.raw());
+        self.dma_controller.save_state(w);
+        self.interrupts_enabled.save_state(w);
+        self.vram_open.save_state(w);
+        self.oam_open.save_state(w);
+        self.io_registers.save_state(w);
+        self.rom.save_state(w);
+    }
+
+    /// Restores a state written by [MemController::save_state]. Attached
+    /// debuggers, snoopers and injected faults are left as they are.
+    pub(crate) fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        r.read_bytes_into(self.vram.raw_mut())?;
+        r.read_bytes_into(self.ram.raw_mut())?;
+        r.read_bytes_into(self.oam.raw_mut())?;
+        r.read_bytes_into(self.hram.raw_mut())?;
+        self.dma_controller.load_state(r)?;
+        self.interrupts_enabled.load_state(r)?;
+        self.vram_open.load_state(r)?;
+        self.oam_open.load_state(r)?;
+        self.io_registers.load_state(r)?;
+        self.rom.load_state(r)?;
+
+        #[cfg(feature = "cached_interpreter")]
+        self.block_cache.clear_ram();
+
+        Ok(())
+    }
+
+    /// Looks up the region in [REGION_PAGES], and then only has to tell
+    /// apart the regions sharing a page
+    #[inline]
+    pub fn map_to_region(&self, addr: u16) -> MemRegion {
+        match REGION_PAGES[(addr >> 8) as usize] {
+            MemRegion::Cartridge if addr <= 0xFF && self.io_registers.boot_rom_enabled => {
+                MemRegion::BootRom
             }
-            0x100..=0x7FFF => MemRegion::Cartridge,
-            0x8000..=0x9FFF => MemRegion::VRam,
-            0xA000..=0xBFFF => MemRegion::Cartridge,
-            0xC000..=0xDFFF => MemRegion::WorkRam,
-            0xE000..=0xFDFF => MemRegion::EchoRam,
-            0xFE00..=0xFE9F => MemRegion::ObjectAttrMem,
-            0xFEA0..=0xFEFF => MemRegion::Prohibited,
-            0xFF00..=0xFF7F => MemRegion::IORegs,
-            0xFF80..=0xFFFE => MemRegion::HighRam,
-            0xFFFF => MemRegion::InterruptEnableReg,
+            MemRegion::ObjectAttrMem if addr >= OAM_END => MemRegion::Prohibited,
+            MemRegion::IORegs if addr == 0xFFFF => MemRegion::InterruptEnableReg,
+            MemRegion::IORegs if addr >= HRAM_START => MemRegion::HighRam,
+            region => region,
+        }
+    }
+
+    /// Returns true if the CPU is currently not allowed to access the given
+    /// region, because the PPU is using it
+    fn is_blocked(&self, region: MemRegion) -> bool {
+        if !self.access_blocking {
+            return false;
+        }
+
+        match region {
+            MemRegion::VRam => !self.vram_open,
+            MemRegion::ObjectAttrMem => !self.oam_open,
+            _ => false,
         }
     }
 
@@ -248,10 +527,80 @@ impl<A: GBAllocator, R: RomReader> MemController<A, R> {
         Ok(buf)
     }
 
+    /// Same as [MemController::read_range], but ignores the VRAM/OAM
+    /// restrictions. Used by the PPU and DMA
+    pub fn read_range_direct<const N: usize>(&self, addr: u16) -> Result<[u8; N], ReadError> {
+        let mut buf = [0u8; N];
+
+        for i in 0u16..(N as u16) {
+            buf[i as usize] = self.read8_direct(addr + i)?;
+        }
+
+        Ok(buf)
+    }
+
+    /// Reads a byte as seen by the CPU. Reads from VRAM or OAM while the
+    /// PPU owns them return 0xFF
     pub fn read8(&self, addr: u16) -> Result<u8, ReadError> {
-        match self.map_to_region(addr) {
-            MemRegion::BootRom => Ok(boot::IMAGE[addr as usize]),
-            MemRegion::Cartridge => self.rom.read(addr).map_err(|e| self.r_err(addr, e)),
+        let value = self.read8_bus(addr)?;
+
+        if let Some(debugger) = &self.debugger {
+            debugger.check_access(addr, Access::Read, value);
+        }
+
+        Ok(value)
+    }
+
+    /// Same as [MemController::read8], but not seen by watchpoints. Used
+    /// for instruction fetches
+    fn read8_bus(&self, addr: u16) -> Result<u8, ReadError> {
+        let region = self.map_to_region(addr);
+
+        let value = if self.doctor_ly && addr == 0xFF44 {
+            0x90
+        } else if self.is_blocked(region) {
+            if self.log_blocked_access {
+                log::info!("Blocked read from {} at 0x{:x}", region, addr);
+            }
+
+            0xFF
+        } else {
+            match self.read8_region(addr, region) {
+                Err(e) if self.ignore_errors => {
+                    log::warn!("Ignoring failed read: {}", e);
+                    0xFF
+                }
+                res => res?,
+            }
+        };
+
+        match &self.snooper {
+            Some(snooper) => Ok(snooper.borrow_mut().read(addr, value)),
+            None => Ok(value),
+        }
+    }
+
+    /// Same as [MemController::read8], but ignores the VRAM/OAM
+    /// restrictions. Used by the PPU and DMA
+    pub fn read8_direct(&self, addr: u16) -> Result<u8, ReadError> {
+        self.read8_region(addr, self.map_to_region(addr))
+    }
+
+    fn read8_region(&self, addr: u16, region: MemRegion) -> Result<u8, ReadError> {
+        match region {
+            MemRegion::BootRom => Ok(self.boot_image[addr as usize]),
+            MemRegion::Cartridge => match self.cart_fault.reads() {
+                FaultReads::Unaffected => self
+                    .rom
+                    .read(addr)
+                    .map_err(|e| ReadError::new(addr, region, e)),
+                FaultReads::OpenBus => Ok(0xFF),
+                FaultReads::Error => Err(ReadError::new(
+                    addr,
+                    region,
+                    rom::controller::ReadError::Fault,
+                )),
+            },
             MemRegion::VRam => {
                 let res = self.vram.read(addr - VRAM_START);
                 // log::info!("Reading from VRAM @ 0x{:x}: 0x{:x}", addr, res);
@@ -260,11 +609,18 @@ impl<A: GBAllocator, R: RomReader> MemController<A, R> {
             MemRegion::WorkRam => Ok(self.ram.read(addr - WORKRAM_START)),
             MemRegion::EchoRam => Ok(self.ram.read(addr - ECHORAM_START)),
             MemRegion::ObjectAttrMem => Ok(self.oam.read(addr - OAM_START)),
-            MemRegion::Prohibited => unimplemented_read!(MemRegion::Prohibited),
-            MemRegion::IORegs => self
-                .io_registers
-                .read(addr)
-                .map_err(|e| self.r_err(addr, e)),
+            // Matches DMG behaviour while OAM is accessible
+            MemRegion::Prohibited => Ok(0x00),
+            MemRegion::IORegs => match self.io_registers.read(addr) {
+                Err(IoReadErr::Unmapped(_)) if self.strictness == Strictness::Permissive => {
+                    if self.log_unmapped_io {
+                        log::info!("Read from unmapped I/O address 0x{:x}", addr);
+                    }
+
+                    Ok(0xFF)
+                }
+                res => res.map_err(|e| ReadError::new(addr, region, e)),
+            },
             MemRegion::HighRam => Ok(self.hram.read(addr - HRAM_START)),
             MemRegion::InterruptEnableReg => Ok(self.interrupts_enabled.into()),
         }
@@ -277,18 +633,153 @@ impl<A: GBAllocator, R: RomReader> MemController<A, R> {
         ]))
     }
 
+    /// Writes a byte as the CPU. Writes to VRAM or OAM while the PPU owns
+    /// them are dropped
     pub fn write8(&mut self, addr: u16, value: u8) -> Result<(), WriteError> {
+        let value = match &mut self.snooper {
+            Some(snooper) => match snooper.get_mut().write(addr, value) {
+                Some(value) => value,
+                None => {
+                    trace!(
+                        Subsystem::Memory,
+                        "Bus snooper dropped write of 0x{:x} to 0x{:x}",
+                        value,
+                        addr
+                    );
+                    return Ok(());
+                }
+            },
+            None => value,
+        };
+
+        self.cart_fault.on_write(addr);
+
+        if let Some(debugger) = &self.debugger {
+            debugger.check_access(addr, Access::Write, value);
+        }
+
+        let region = self.map_to_region(addr);
+
+        if self.is_blocked(region) {
+            if self.log_blocked_access {
+                log::info!(
+                    "Blocked write of 0x{:x} to {} at 0x{:x}",
+                    value,
+                    region,
+                    addr
+                );
+            }
+
+            return Ok(());
+        }
+
+        match self.write8_region(addr, region, value) {
+            Err(e) if self.ignore_errors => {
+                log::warn!("Ignoring failed write: {}", e);
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    pub fn vram(&self) -> &[u8] {
+        self.vram.raw()
+    }
+
+    pub fn wram(&self) -> &[u8] {
+        self.ram.raw()
+    }
+
+    pub fn oam(&self) -> &[u8] {
+        self.oam.raw()
+    }
+
+    pub fn hram(&self) -> &[u8] {
+        self.hram.raw()
+    }
+
+    /// See [RomController::rom_bank]. The boot ROM is not in any bank.
+    pub fn rom_bank(&self, addr: u16) -> Option<usize> {
+        match self.map_to_region(addr) {
+            MemRegion::Cartridge => self.rom.rom_bank(addr),
+            _ => None,
+        }
+    }
+
+    pub fn cartridge_ram(&self) -> &[u8] {
+        self.rom.ram()
+    }
+
+    /// Cartridge RAM can hold code, so cached blocks are dropped
+    pub fn cartridge_ram_mut(&mut self) -> &mut [u8] {
+        #[cfg(feature = "cached_interpreter")]
+        self.block_cache.clear_ram();
+
+        self.rom.ram_mut()
+    }
+
+    #[cfg(feature = "debug_tools")]
+    pub fn vram_mut(&mut self) -> &mut [u8] {
+        self.vram.raw_mut()
+    }
+
+    /// Work RAM can hold code, so cached blocks are dropped
+    #[cfg(feature = "debug_tools")]
+    pub fn wram_mut(&mut self) -> &mut [u8] {
+        #[cfg(feature = "cached_interpreter")]
+        self.block_cache.clear_ram();
+
+        self.ram.raw_mut()
+    }
+
+    #[cfg(feature = "debug_tools")]
+    pub fn oam_mut(&mut self) -> &mut [u8] {
+        self.oam.raw_mut()
+    }
+
+    /// High RAM can hold code, so cached blocks are dropped
+    #[cfg(feature = "debug_tools")]
+    pub fn hram_mut(&mut self) -> &mut [u8] {
+        #[cfg(feature = "cached_interpreter")]
+        self.block_cache.clear_ram();
+
+        self.hram.raw_mut()
+    }
+
+    /// Same as [MemController::write8], but ignores the VRAM/OAM
+    /// restrictions, cartridge faults and watchpoints. Used for editing
+    /// memory from outside the emulated system
+    pub fn write8_direct(&mut self, addr: u16, value: u8) -> Result<(), WriteError> {
+        self.write8_region(addr, self.map_to_region(addr), value)
+    }
+
+    fn write8_region(&mut self, addr: u16, region: MemRegion, value: u8) -> Result<(), WriteError> {
+        #[cfg(feature = "cached_interpreter")]
+        self.block_cache.on_write(addr);
+
         if addr == 0xFF46 {
             let command = dma::oam_dma_command(value, self)
-                .map_err(|e| self.w_err(addr, WriteErrType::DMA(e)))?;
+                .map_err(|e| WriteError::new(addr, region, WriteErrType::DMA(e)))?;
 
             // Special case that starts DMA transfer
             self.dma_controller.push_oam(command);
         }
 
-        match self.map_to_region(addr) {
-            MemRegion::BootRom => Err(self.w_err(addr, WriteErrType::ReadOnly)),
-            MemRegion::Cartridge => self.rom.write(addr, value).map_err(|e| self.w_err(addr, e)),
+        match region {
+            MemRegion::BootRom => Err(WriteError::new(addr, region, WriteErrType::ReadOnly)),
+            MemRegion::Cartridge => {
+                if (0xA000..=0xBFFF).contains(&addr) && self.cart_fault.drops_ram_writes() {
+                    debug!(
+                        Subsystem::Memory,
+                        "Dropping cartridge RAM write to 0x{:x} due to fault", addr
+                    );
+                    return Ok(());
+                }
+
+                self.rom
+                    .write(addr, value)
+                    .map_err(|e| WriteError::new(addr, region, e))
+            }
             MemRegion::VRam => {
                 // log::info!("Writing into VRAM @ 0x{:x}: 0x{:x}", addr, value);
                 self.vram.write(addr - VRAM_START, value);
@@ -307,17 +798,28 @@ impl<A: GBAllocator, R: RomReader> MemController<A, R> {
                 Ok(())
             }
             MemRegion::Prohibited => {
-                log::trace!(
+                trace!(
+                    Subsystem::Memory,
                     "Ignoring write of 0x{:x} to addr 0x{:x} in prohibited area",
                     value,
                     addr
                 );
                 Ok(())
             }
-            MemRegion::IORegs => self
-                .io_registers
-                .write(addr, value)
-                .map_err(|e| self.w_err(addr, e)),
+            MemRegion::IORegs => match self.io_registers.write(addr, value) {
+                Err(IoWriteErr::Unmapped(_)) if self.strictness == Strictness::Permissive => {
+                    if self.log_unmapped_io {
+                        log::info!(
+                            "Ignoring write of 0x{:x} to unmapped I/O address 0x{:x}",
+                            value,
+                            addr
+                        );
+                    }
+
+                    Ok(())
+                }
+                res => res.map_err(|e| WriteError::new(addr, region, e)),
+            },
             MemRegion::HighRam => {
                 self.hram.write(addr - HRAM_START, value);
                 Ok(())
@@ -336,6 +838,119 @@ impl<A: GBAllocator, R: RomReader> MemController<A, R> {
         self.write8(addr + 1, bytes[1])
     }
 
+    /// Decodes the instruction at `pc` through the block cache. On a miss,
+    /// the whole basic block starting at `pc` is decoded and cached.
+    #[cfg(feature = "cached_interpreter")]
+    pub fn decode_cached(&mut self, pc: u16) -> Result<Instruction, MemControllerDecoderErr> {
+        let source = match self.code_source(pc) {
+            // Cartridge faults and bus snoopers change what reads return, so
+            // bypass the cache
+            Some(source) if !self.cart_fault.triggered() && self.snooper.is_none() => source,
+            _ => return decoder::decode(self, pc),
+        };
+
+        if let Some(instr) = self.block_cache.get(source, pc) {
+            return Ok(instr);
+        }
+
+        let end = source.end(pc);
+        let mut block = Vec::new();
+        let mut addr = pc as u32;
+
+        while block.len() < blockcache::MAX_BLOCK_LEN {
+            // Data may follow the code, which only has to decode once it runs
+            let Ok(instr) = decoder::decode(self, addr as u16) else {
+                break;
+            };
+
+            let next = addr + instr.len() as u32;
+
+            if next > end {
+                break;
+            }
+
+            block.push((addr as u16, instr));
+            addr = next;
+
+            if instr.is_control_flow() || addr == end {
+                break;
+            }
+        }
+
+        match block.first() {
+            Some(&(_, first)) => {
+                self.block_cache.insert(source, &block);
+                Ok(first)
+            }
+            None => decoder::decode(self, pc),
+        }
+    }
+
+    /// Where code at `pc` is decoded from, or None if it cannot be cached
+    #[cfg(feature = "cached_interpreter")]
+    fn code_source(&self, pc: u16) -> Option<CodeSource> {
+        match self.map_to_region(pc) {
+            MemRegion::BootRom => Some(CodeSource::BootRom),
+            MemRegion::Cartridge => self.rom.rom_bank(pc).map(CodeSource::RomBank),
+            MemRegion::WorkRam | MemRegion::HighRam => Some(CodeSource::Ram),
+            _ => None,
+        }
+    }
+
+    pub fn timer_cycle(&mut self) {
+        if self.io_registers.timer.cycle() {
+            self.io_registers.interrupts_requested.set_timer(true);
+        }
+    }
+
+    /// Runs a T-cycle of the cartridge clock, if there is one
+    pub fn rtc_cycle(&mut self) {
+        if let Some(rtc) = self.rtc_mut() {
+            rtc.cycle();
+        }
+    }
+
+    /// Runs a joypad T-cycle. Returns true if new inputs should be given
+    /// through [MemController::set_inputs].
+    pub fn joypad_cycle(&mut self) -> bool {
+        self.io_registers.joypad.cycle()
+    }
+
+    pub fn set_inputs(&mut self, inputs: GbInputs) {
+        if self.io_registers.joypad.set_inputs(inputs) {
+            self.io_registers.interrupts_requested.set_joypad(true);
+        }
+    }
+
+    /// Returns true if a transfer with the internal clock finished, which a
+    /// linked device can answer through [serial::Serial::receive]
+    pub fn serial_cycle(&mut self) -> bool {
+        let finished = self.io_registers.serial.cycle();
+
+        if finished {
+            self.io_registers.interrupts_requested.set_serial(true);
+        }
+
+        finished
+    }
+
+    /// A clock pulse from the other side of the serial link. See
+    /// [serial::Serial::external_clock]
+    pub fn serial_external_clock(&mut self, bit_in: bool) -> Option<bool> {
+        let (bit_out, finished) = self.io_registers.serial.external_clock(bit_in)?;
+
+        if finished {
+            self.io_registers.interrupts_requested.set_serial(true);
+        }
+
+        Some(bit_out)
+    }
+
+    /// Whether a DMA transfer is in progress, which writes OAM when it ends
+    pub const fn dma_active(&self) -> bool {
+        self.dma_controller.active()
+    }
+
     pub fn dma_cycle(&mut self) -> Result<(), WriteError> {
         for finished_transfer in self.dma_controller.run_cycle() {
             log::info!(
@@ -343,7 +958,11 @@ impl<A: GBAllocator, R: RomReader> MemController<A, R> {
                 finished_transfer.target_address
             );
             for (idx, byte) in finished_transfer.data.into_iter().enumerate() {
-                self.write8(finished_transfer.target_address + (idx as u16), byte)?;
+                let addr = finished_transfer.target_address + (idx as u16);
+
+                // DMA has its own path into OAM, so it is not subject to the
+                // CPU bus restrictions
+                self.write8_region(addr, self.map_to_region(addr), byte)?;
             }
         }
 
@@ -353,21 +972,151 @@ impl<A: GBAllocator, R: RomReader> MemController<A, R> {
 
 #[derive(Debug, Error)]
 pub enum MemControllerDecoderErr {
-    #[error("Address out of 16 bit range: {0}")]
-    Addr(usize),
-
     #[error("Read error: {0}")]
     Read(#[from] ReadError),
 }
 
 impl<A: GBAllocator, R: RomReader> DecoderReadable for MemController<A, R> {
     type Err = MemControllerDecoderErr;
-    fn read_at(&self, idx: usize) -> Result<u8, Self::Err> {
-        let result = match u16::try_from(idx) {
-            Ok(addr) => self.read8(addr)?,
-            Err(_) => return Err(MemControllerDecoderErr::Addr(idx)),
-        };
+    fn read_at(&self, addr: u16) -> Result<u8, Self::Err> {
+        Ok(self.read8_bus(addr)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::{
+        debug::{CartridgeFault, FaultTrigger},
+        testutils::blank_rom,
+        InlineAllocator,
+    };
+
+    fn new_mem() -> MemController<InlineAllocator, Cursor<Vec<u8>>> {
+        MemController::new(blank_rom(0), BankCaching::default()).unwrap()
+    }
+
+    #[test]
+    fn blocked_vram_reads_open_bus() {
+        let mut mem = new_mem();
+
+        mem.write8(VRAM_START, 0x12).unwrap();
+        mem.vram_open = false;
+
+        assert_eq!(0xFF, mem.read8(VRAM_START).unwrap());
+        assert_eq!(0x12, mem.read8_direct(VRAM_START).unwrap());
+    }
+
+    #[test]
+    fn blocked_oam_writes_dropped() {
+        let mut mem = new_mem();
+
+        mem.write8(OAM_START, 0x34).unwrap();
+        mem.oam_open = false;
+        mem.write8(OAM_START, 0x56).unwrap();
+        mem.oam_open = true;
+
+        assert_eq!(0x34, mem.read8(OAM_START).unwrap());
+    }
+
+    /// The memory map as documented, one range at a time
+    fn documented_region(addr: u16, boot_rom: bool) -> MemRegion {
+        match addr {
+            0x0000..=0x00FF if boot_rom => MemRegion::BootRom,
+            0x0000..=0x7FFF => MemRegion::Cartridge,
+            0x8000..=0x9FFF => MemRegion::VRam,
+            0xA000..=0xBFFF => MemRegion::Cartridge,
+            0xC000..=0xDFFF => MemRegion::WorkRam,
+            0xE000..=0xFDFF => MemRegion::EchoRam,
+            0xFE00..=0xFE9F => MemRegion::ObjectAttrMem,
+            0xFEA0..=0xFEFF => MemRegion::Prohibited,
+            0xFF00..=0xFF7F => MemRegion::IORegs,
+            0xFF80..=0xFFFE => MemRegion::HighRam,
+            0xFFFF => MemRegion::InterruptEnableReg,
+        }
+    }
+
+    #[test]
+    fn every_address_maps_to_its_region() {
+        let mut mem = new_mem();
+
+        for boot_rom in [true, false] {
+            mem.io_registers.boot_rom_enabled = boot_rom;
+
+            for addr in 0..=u16::MAX {
+                assert_eq!(
+                    documented_region(addr, boot_rom),
+                    mem.map_to_region(addr),
+                    "0x{:04x} with the boot ROM {}",
+                    addr,
+                    if boot_rom { "mapped" } else { "unmapped" }
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn errors_name_the_region() {
+        let mut mem = new_mem();
+
+        let err = mem.write8(0x0010, 0x00).unwrap_err();
+        assert_eq!(MemRegion::BootRom, err.region);
+        assert_eq!(WriteErrType::ReadOnly, err.err);
+
+        // No RAM on this cartridge
+        let err = mem.write8(0xA000, 0x00).unwrap_err();
+        assert_eq!(MemRegion::Cartridge, err.region);
+        assert_eq!(0xA000, err.addr);
+    }
+
+    #[test]
+    fn unmapped_io_by_strictness() {
+        let mut mem = new_mem();
+
+        mem.write8(0xFF03, 0x12).unwrap();
+        assert_eq!(0xFF, mem.read8(0xFF03).unwrap());
+
+        mem.set_strictness(Strictness::Strict);
+
+        let err = mem.read8(0xFF4C).unwrap_err();
+        assert_eq!(ReadErrType::IORegs(IoReadErr::Unmapped(0xFF4C)), err.err);
+
+        let err = mem.write8(0xFF03, 0x12).unwrap_err();
+        assert_eq!(MemRegion::IORegs, err.region);
+
+        // Registers that are read or write only are not unmapped
+        assert_eq!(0xFF, mem.read8(0xFF50).unwrap());
+        mem.write8(0xFF44, 0x12).unwrap();
+    }
+
+    #[test]
+    fn cartridge_removal_after_cycles() {
+        let mut mem = new_mem();
+        mem.io_registers.boot_rom_enabled = false;
+        mem.cart_fault
+            .inject(CartridgeFault::removal(FaultTrigger::AfterCycles(2)));
+
+        mem.cart_fault.cycle();
+        assert_eq!(0x00, mem.read8(0x100).unwrap());
+
+        mem.cart_fault.cycle();
+        assert_eq!(0xFF, mem.read8(0x100).unwrap());
+    }
+
+    #[test]
+    fn cartridge_read_errors_on_write_trigger() {
+        let mut mem = new_mem();
+        mem.io_registers.boot_rom_enabled = false;
+        mem.cart_fault.inject(CartridgeFault {
+            trigger: FaultTrigger::OnWrite(0xC000),
+            reads: FaultReads::Error,
+            drop_ram_writes: false,
+        });
 
-        Ok(result)
+        assert!(mem.read8(0x100).is_ok());
+        mem.write8(0xC000, 0x01).unwrap();
+        assert!(mem.read8(0x100).is_err());
     }
 }
@@ -0,0 +1,292 @@
+use crate::logging::{debug, Subsystem};
+use crate::model::StateDigest;
+use crate::savestate::state_data;
+
+const SC_TRANSFER_ENABLE: u8 = 0b10000000;
+const SC_HIGH_SPEED: u8 = 0b10;
+const SC_INTERNAL_CLOCK: u8 = 0b1;
+
+/// T-cycles per bit on the internal 8192Hz clock
+const NORMAL_SPEED_BIT_CYCLES: u16 = 512;
+
+/// T-cycles per bit on the internal 262144Hz clock (CGB only)
+const HIGH_SPEED_BIT_CYCLES: u16 = 16;
+
+/// The serial port (SB/SC). Bytes are shifted out one bit at a time, MSB
+/// first, while the bits of the other side are shifted in at the bottom.
+///
+/// With the internal clock, this Gameboy drives the transfer. With the
+/// external clock, a bit is only shifted when the other side provides a
+/// clock pulse through [Serial::external_clock]. If nothing is connected,
+/// that never happens, and the transfer never completes, just like on
+/// real hardware.
+///
+/// A device clocked by this Gameboy, like the printer, exchanges whole
+/// bytes: see [Serial::sent] and [Serial::receive].
+#[derive(Debug, Clone)]
+pub struct Serial {
+    /// 0xFF01
+    data: u8,
+
+    /// 0xFF02
+    control: u8,
+
+    /// The byte being shifted out by the current or last transfer
+    sent: u8,
+
+    bits_left: u8,
+    bit_cycles: u16,
+    cgb: bool,
+}
+
+// Whether this is a CGB is part of the configuration, not the state
+state_data!(Serial {
+    data,
+    control,
+    sent,
+    bits_left,
+    bit_cycles,
+});
+
+impl Serial {
+    pub const fn new(cgb: bool) -> Self {
+        Self {
+            data: 0,
+            control: 0,
+            sent: 0,
+            bits_left: 0,
+            bit_cycles: 0,
+            cgb,
+        }
+    }
+
+    pub const fn data(&self) -> u8 {
+        self.data
+    }
+
+    pub fn write_data(&mut self, val: u8) {
+        self.data = val;
+    }
+
+    pub const fn control(&self) -> u8 {
+        let unused = if self.cgb { 0b01111100 } else { 0b01111110 };
+
+        self.control | unused
+    }
+
+    pub fn write_control(&mut self, val: u8) {
+        let used = if self.cgb {
+            SC_TRANSFER_ENABLE | SC_HIGH_SPEED | SC_INTERNAL_CLOCK
+        } else {
+            SC_TRANSFER_ENABLE | SC_INTERNAL_CLOCK
+        };
+
+        self.control = val & used;
+
+        if self.transferring() {
+            debug!(
+                Subsystem::Serial,
+                "Starting serial transfer of 0x{:x} ({} clock)",
+                self.data,
+                if self.internal_clock() {
+                    "internal"
+                } else {
+                    "external"
+                }
+            );
+
+            self.sent = self.data;
+            self.bits_left = 8;
+            self.bit_cycles = 0;
+        } else {
+            self.bits_left = 0;
+        }
+    }
+
+    pub const fn transferring(&self) -> bool {
+        self.control & SC_TRANSFER_ENABLE != 0
+    }
+
+    pub const fn internal_clock(&self) -> bool {
+        self.control & SC_INTERNAL_CLOCK != 0
+    }
+
+    const fn cycles_per_bit(&self) -> u16 {
+        if self.cgb && self.control & SC_HIGH_SPEED != 0 {
+            HIGH_SPEED_BIT_CYCLES
+        } else {
+            NORMAL_SPEED_BIT_CYCLES
+        }
+    }
+
+    /// Shifts one bit out and `bit_in` in. Returns the shifted out bit,
+    /// and whether that finished the transfer.
+    fn shift(&mut self, bit_in: bool) -> (bool, bool) {
+        let bit_out = self.data & 0b10000000 != 0;
+
+        self.data = (self.data << 1) | bit_in as u8;
+        self.bits_left -= 1;
+
+        let finished = self.bits_left == 0;
+
+        if finished {
+            debug!(
+                Subsystem::Serial,
+                "Serial transfer finished, received 0x{:x}", self.data
+            );
+            self.control &= !SC_TRANSFER_ENABLE;
+        }
+
+        (bit_out, finished)
+    }
+
+    /// Runs a single T-cycle. Returns true if a transfer finished, which
+    /// should raise the serial interrupt.
+    pub fn cycle(&mut self) -> bool {
+        if !self.transferring() || !self.internal_clock() {
+            return false;
+        }
+
+        self.bit_cycles += 1;
+
+        if self.bit_cycles < self.cycles_per_bit() {
+            return false;
+        }
+
+        self.bit_cycles = 0;
+
+        // Nothing is connected to the other side, so the line is pulled high
+        let (_, finished) = self.shift(true);
+        finished
+    }
+
+    /// A clock pulse from the other side of the link, carrying `bit_in`.
+    /// Only has an effect while a transfer with the external clock is
+    /// running. Returns the bit shifted out, and whether that finished the
+    /// transfer.
+    pub fn external_clock(&mut self, bit_in: bool) -> Option<(bool, bool)> {
+        if !self.transferring() || self.internal_clock() {
+            return None;
+        }
+
+        Some(self.shift(bit_in))
+    }
+
+    /// Bits still to be shifted by the current transfer
+    pub const fn bits_left(&self) -> u8 {
+        self.bits_left
+    }
+
+    /// Replaces the bit shifted in by the last internal clock pulse with
+    /// the one another Gameboy shifted out on that pulse
+    pub fn set_received_bit(&mut self, bit: bool) {
+        self.data = (self.data & !1) | bit as u8;
+    }
+
+    /// The byte shifted out by the last transfer
+    pub const fn sent(&self) -> u8 {
+        self.sent
+    }
+
+    /// Replaces the byte received by a finished transfer with the one a
+    /// linked device sent back. With the internal clock, the bits come in
+    /// as 1s until then, as if nothing was connected.
+    pub fn receive(&mut self, byte: u8) {
+        self.data = byte;
+    }
+
+    /// Cancels any running transfer and clears the registers
+    pub fn reset(&mut self) {
+        *self = Self::new(self.cgb);
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_u8(self.data);
+        digest.write_u8(self.control);
+        digest.write_u8(self.sent);
+        digest.write_u8(self.bits_left);
+        digest.write_u16(self.bit_cycles);
+    }
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Self::new(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_until_done(serial: &mut Serial) -> usize {
+        let mut cycles = 0;
+
+        loop {
+            cycles += 1;
+
+            if serial.cycle() {
+                return cycles;
+            }
+
+            assert!(cycles < 10_000, "Transfer did not finish");
+        }
+    }
+
+    #[test]
+    fn internal_clock_takes_eight_bits_at_8192hz() {
+        let mut serial = Serial::new(false);
+        serial.write_data(0x42);
+        serial.write_control(SC_TRANSFER_ENABLE | SC_INTERNAL_CLOCK);
+
+        assert_eq!(8 * 512, run_until_done(&mut serial));
+        assert!(!serial.transferring());
+        assert_eq!(0xFF, serial.data());
+        assert_eq!(0x42, serial.sent());
+    }
+
+    #[test]
+    fn high_speed_only_on_cgb() {
+        let start = SC_TRANSFER_ENABLE | SC_HIGH_SPEED | SC_INTERNAL_CLOCK;
+
+        let mut dmg = Serial::new(false);
+        dmg.write_control(start);
+        assert_eq!(8 * 512, run_until_done(&mut dmg));
+
+        let mut cgb = Serial::new(true);
+        cgb.write_control(start);
+        assert_eq!(8 * 16, run_until_done(&mut cgb));
+    }
+
+    #[test]
+    fn external_clock_waits_for_other_side() {
+        let mut serial = Serial::new(false);
+        serial.write_data(0b10100000);
+        serial.write_control(SC_TRANSFER_ENABLE);
+
+        for _ in 0..10_000 {
+            assert!(!serial.cycle());
+        }
+
+        let mut out = 0u8;
+        let mut finished = false;
+
+        for bit in [false, true, false, true, false, true, false, true] {
+            assert!(!finished);
+            let (bit_out, done) = serial.external_clock(bit).unwrap();
+            out = (out << 1) | bit_out as u8;
+            finished = done;
+        }
+
+        assert!(finished);
+        assert_eq!(0b10100000, out);
+        assert_eq!(0b01010101, serial.data());
+        assert_eq!(None, serial.external_clock(true));
+    }
+
+    #[test]
+    fn unused_control_bits_read_high() {
+        assert_eq!(0x7E, Serial::new(false).control());
+        assert_eq!(0x7C, Serial::new(true).control());
+    }
+}
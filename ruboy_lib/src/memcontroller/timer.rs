@@ -0,0 +1,373 @@
+use crate::model::StateDigest;
+use crate::savestate::state_data;
+
+const TAC_ENABLE: u8 = 0b100;
+const TAC_CLOCK_SELECT: u8 = 0b11;
+
+/// T-cycles between TIMA overflowing and being reloaded from TMA
+const RELOAD_DELAY_CYCLES: u8 = 4;
+
+/// The timer (DIV/TIMA/TMA/TAC).
+///
+/// Everything is driven by a single 16-bit counter that increments every
+/// T-cycle, of which DIV is the upper byte. TIMA increments on the falling
+/// edge of the counter bit selected by TAC, ANDed with the enable bit. That
+/// makes writes to DIV and TAC increment TIMA when they make the selected
+/// bit fall, just like on real hardware.
+///
+/// When TIMA overflows it reads as 0x00 for one M-cycle, after which it is
+/// reloaded from TMA and the timer interrupt is requested. Writing TIMA in
+/// that M-cycle cancels the reload. During the M-cycle of the reload
+/// itself, TIMA writes are ignored and TMA writes go through to TIMA too.
+#[derive(Debug, Clone, Default)]
+pub struct Timer {
+    counter: u16,
+
+    /// 0xFF05
+    tima: u8,
+
+    /// 0xFF06
+    tma: u8,
+
+    /// 0xFF07
+    tac: u8,
+
+    /// T-cycles left until TIMA is reloaded, 0 if it did not overflow
+    reload_in: u8,
+
+    /// T-cycles left of the M-cycle TIMA was reloaded in
+    reloading: u8,
+}
+
+state_data!(Timer {
+    counter,
+    tima,
+    tma,
+    tac,
+    reload_in,
+    reloading,
+});
+
+impl Timer {
+    pub const fn new() -> Self {
+        Self {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+            reload_in: 0,
+            reloading: 0,
+        }
+    }
+
+    /// Sets the registers as the boot ROM leaves them. Only the upper byte
+    /// of the counter is visible, so the lower byte starts at zero.
+    pub fn apply_boot_state(&mut self, div: u8, tima: u8, tma: u8, tac: u8) {
+        *self = Self {
+            counter: (div as u16) << 8,
+            tima,
+            tma,
+            tac: tac & (TAC_ENABLE | TAC_CLOCK_SELECT),
+            reload_in: 0,
+            reloading: 0,
+        };
+    }
+
+    pub const fn div(&self) -> u8 {
+        (self.counter >> 8) as u8
+    }
+
+    pub const fn tima(&self) -> u8 {
+        self.tima
+    }
+
+    pub const fn tma(&self) -> u8 {
+        self.tma
+    }
+
+    pub const fn tac(&self) -> u8 {
+        // The unused bits always read as set
+        self.tac | 0b11111000
+    }
+
+    /// The counter bit whose falling edge increments TIMA, ANDed with the
+    /// enable bit
+    const fn tima_signal(&self) -> bool {
+        let bit = match self.tac & TAC_CLOCK_SELECT {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        };
+
+        self.tac & TAC_ENABLE != 0 && self.counter & (1 << bit) != 0
+    }
+
+    fn increment_tima(&mut self) {
+        let (incremented, overflown) = self.tima.overflowing_add(1);
+
+        self.tima = incremented;
+
+        if overflown {
+            self.reload_in = RELOAD_DELAY_CYCLES;
+        }
+    }
+
+    /// Changes the counter, incrementing TIMA if that made the selected
+    /// bit fall
+    fn set_counter(&mut self, counter: u16) {
+        let before = self.tima_signal();
+
+        self.counter = counter;
+
+        if before && !self.tima_signal() {
+            self.increment_tima();
+        }
+    }
+
+    /// Writing any value to DIV resets the whole counter
+    pub fn write_div(&mut self) {
+        self.set_counter(0);
+    }
+
+    pub fn write_tima(&mut self, val: u8) {
+        if self.reloading != 0 {
+            return;
+        }
+
+        self.tima = val;
+        self.reload_in = 0;
+    }
+
+    pub fn write_tma(&mut self, val: u8) {
+        self.tma = val;
+
+        if self.reloading != 0 {
+            self.tima = val;
+        }
+    }
+
+    pub fn write_tac(&mut self, val: u8) {
+        let before = self.tima_signal();
+
+        self.tac = val & (TAC_ENABLE | TAC_CLOCK_SELECT);
+
+        if before && !self.tima_signal() {
+            self.increment_tima();
+        }
+    }
+
+    /// Runs a single T-cycle. Returns true if TIMA was reloaded, which
+    /// should raise the timer interrupt.
+    pub fn cycle(&mut self) -> bool {
+        self.reloading = self.reloading.saturating_sub(1);
+
+        let mut reloaded = false;
+
+        if self.reload_in != 0 {
+            self.reload_in -= 1;
+
+            if self.reload_in == 0 {
+                self.tima = self.tma;
+                self.reloading = RELOAD_DELAY_CYCLES;
+                reloaded = true;
+            }
+        }
+
+        self.set_counter(self.counter.wrapping_add(1));
+
+        reloaded
+    }
+
+    pub(crate) fn digest_into(&self, digest: &mut StateDigest) {
+        digest.write_u16(self.counter);
+        digest.write_u8(self.tima);
+        digest.write_u8(self.tma);
+        digest.write_u8(self.tac);
+        digest.write_u8(self.reload_in);
+        digest.write_u8(self.reloading);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs cycles until the timer interrupt is requested. Returns how many
+    /// that took.
+    fn run_until_interrupt(timer: &mut Timer) -> usize {
+        let mut cycles = 0;
+
+        loop {
+            cycles += 1;
+
+            if timer.cycle() {
+                return cycles;
+            }
+
+            assert!(cycles < 1_000_000, "Timer never overflowed");
+        }
+    }
+
+    #[test]
+    fn div_is_upper_byte_of_counter() {
+        let mut timer = Timer::new();
+
+        for _ in 0..255 {
+            timer.cycle();
+        }
+
+        assert_eq!(0, timer.div());
+
+        timer.cycle();
+        assert_eq!(1, timer.div());
+
+        timer.write_div();
+        assert_eq!(0, timer.div());
+    }
+
+    #[test]
+    fn tima_frequencies() {
+        for (tac, period) in [(0b100, 1024), (0b101, 16), (0b110, 64), (0b111, 256)] {
+            let mut timer = Timer::new();
+            timer.write_tac(tac);
+            timer.write_tima(0xFF);
+
+            // The overflow itself, then the reload delay
+            assert_eq!(
+                period + 4,
+                run_until_interrupt(&mut timer),
+                "TAC {:03b}",
+                tac
+            );
+        }
+    }
+
+    #[test]
+    fn overflow_reloads_after_delay() {
+        let mut timer = Timer::new();
+        timer.write_tma(0xAB);
+        timer.write_tac(0b101);
+        timer.write_tima(0xFF);
+
+        for _ in 0..16 {
+            assert!(!timer.cycle());
+        }
+
+        // Reads as zero until the reload
+        assert_eq!(0x00, timer.tima());
+
+        for _ in 0..3 {
+            assert!(!timer.cycle());
+            assert_eq!(0x00, timer.tima());
+        }
+
+        assert!(timer.cycle());
+        assert_eq!(0xAB, timer.tima());
+    }
+
+    #[test]
+    fn tima_write_during_delay_cancels_reload() {
+        let mut timer = Timer::new();
+        timer.write_tma(0xAB);
+        timer.write_tac(0b101);
+        timer.write_tima(0xFF);
+
+        for _ in 0..16 {
+            timer.cycle();
+        }
+
+        timer.write_tima(0x42);
+
+        for _ in 0..4 {
+            assert!(!timer.cycle());
+        }
+
+        assert_eq!(0x42, timer.tima());
+    }
+
+    #[test]
+    fn writes_during_reload_cycle() {
+        let mut timer = Timer::new();
+        timer.write_tma(0xAB);
+        timer.write_tac(0b101);
+        timer.write_tima(0xFF);
+
+        run_until_interrupt(&mut timer);
+
+        // TIMA writes are ignored, TMA writes land in TIMA as well
+        timer.write_tima(0x42);
+        assert_eq!(0xAB, timer.tima());
+
+        timer.write_tma(0xCD);
+        assert_eq!(0xCD, timer.tima());
+
+        for _ in 0..4 {
+            timer.cycle();
+        }
+
+        timer.write_tima(0x42);
+        assert_eq!(0x42, timer.tima());
+    }
+
+    #[test]
+    fn div_write_on_high_bit_increments_tima() {
+        let mut timer = Timer::new();
+        timer.write_tac(0b101);
+
+        // Bit 3 of the counter is set
+        for _ in 0..8 {
+            timer.cycle();
+        }
+
+        assert_eq!(0, timer.tima());
+
+        timer.write_div();
+        assert_eq!(1, timer.tima());
+
+        // Bit 3 is clear again, so no increment
+        timer.write_div();
+        assert_eq!(1, timer.tima());
+    }
+
+    #[test]
+    fn tac_write_dropping_signal_increments_tima() {
+        let mut timer = Timer::new();
+        timer.write_tac(0b101);
+
+        for _ in 0..8 {
+            timer.cycle();
+        }
+
+        // Disabling the timer while the selected bit is set
+        timer.write_tac(0b001);
+        assert_eq!(1, timer.tima());
+
+        // Selecting a bit that is clear while enabled
+        timer.write_tac(0b101);
+        timer.write_tac(0b100);
+        assert_eq!(2, timer.tima());
+    }
+
+    /// Runs the timer tests of the Mooneye test suite, in the directory set
+    /// by `RUBOY_MOONEYE_TIMER` (`acceptance/timer` of the suite's builds)
+    #[test]
+    #[ignore = "needs the Mooneye test ROMs"]
+    fn mooneye_timer() {
+        const ROMS: [&str; 11] = [
+            "tima_reload.gb",
+            "div_write.gb",
+            "rapid_toggle.gb",
+            "tim00.gb",
+            "tim00_div_trigger.gb",
+            "tim01.gb",
+            "tim01_div_trigger.gb",
+            "tim10.gb",
+            "tim10_div_trigger.gb",
+            "tim11.gb",
+            "tim11_div_trigger.gb",
+        ];
+
+        crate::testutils::assert_mooneye_passes("RUBOY_MOONEYE_TIMER", |name| ROMS.contains(&name));
+    }
+}
@@ -0,0 +1,490 @@
+use alloc::vec::Vec;
+use core::fmt::Display;
+use core::num::NonZeroU16;
+
+use crate::rom::BankCaching;
+use crate::RenderMode;
+
+/// The Gameboy hardware models that Ruboy knows the power-on state of.
+///
+/// Games can tell these apart by the registers the boot ROM leaves behind,
+/// see [GbModel::boot_state]. Pick one with [crate::RuboyBuilder::model],
+/// which also fills RAM with the [RamInit::Pattern] the model powers on
+/// with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GbModel {
+    /// Original (very early) DMG revision
+    Dmg0,
+
+    /// Regular DMG
+    #[default]
+    Dmg,
+
+    /// Gameboy Pocket
+    Mgb,
+
+    /// Super Gameboy
+    Sgb,
+
+    /// Super Gameboy 2
+    Sgb2,
+}
+
+impl Display for GbModel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = match self {
+            GbModel::Dmg0 => "DMG0",
+            GbModel::Dmg => "DMG",
+            GbModel::Mgb => "MGB",
+            GbModel::Sgb => "SGB",
+            GbModel::Sgb2 => "SGB2",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl GbModel {
+    /// Whether this is one of the Super Game Boy models, see [crate::sgb]
+    pub const fn is_sgb(self) -> bool {
+        matches!(self, GbModel::Sgb | GbModel::Sgb2)
+    }
+
+    pub const ALL: [GbModel; 5] = [
+        GbModel::Dmg0,
+        GbModel::Dmg,
+        GbModel::Mgb,
+        GbModel::Sgb,
+        GbModel::Sgb2,
+    ];
+
+    /// Returns the state the given model is in right after the boot ROM
+    /// hands control to the cartridge at 0x100.
+    ///
+    /// # Arguments
+    ///
+    /// * `header_checksum` The header checksum of the inserted cartridge. On
+    ///   DMG and MGB models this influences the initial flags register
+    pub const fn boot_state(self, header_checksum: u8) -> BootState {
+        BootState {
+            registers: BootRegisters::for_model(self, header_checksum),
+            io: BootIo::for_model(self),
+        }
+    }
+}
+
+/// The complete initial state applied when starting emulation without a
+/// boot ROM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootState {
+    pub registers: BootRegisters,
+    pub io: BootIo,
+}
+
+/// The size of the DMG, MGB and SGB boot ROMs
+pub const BOOT_ROM_SIZE: usize = 0x100;
+
+/// What runs before the cartridge takes over, see
+/// [crate::Ruboy::with_boot_rom]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum BootRom {
+    /// The image compiled in through one of the `boot_*` features. Without
+    /// one, the same as skipping the boot ROM of [GbModel::default]
+    #[default]
+    BuiltIn,
+
+    /// No boot ROM. Emulation starts at the cartridge entrypoint, in the
+    /// state the given model is in after its boot ROM finishes
+    Skip(GbModel),
+
+    /// Runs the given image, e.g. a dump of an original boot ROM. Must be
+    /// [BOOT_ROM_SIZE] bytes, the larger CGB boot ROMs are not supported.
+    External(Vec<u8>),
+}
+
+/// Settings for creating a new emulator instance, see
+/// [crate::Ruboy::with_config]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuboyConfig {
+    pub model: GbModel,
+    pub ram_init: RamInit,
+
+    /// Block CPU accesses to VRAM and OAM while the PPU is using them, like
+    /// real hardware does. Games with the
+    /// [crate::quirks::Quirk::NoAccessBlocking] quirk never block
+    pub access_blocking: bool,
+
+    /// When the [crate::InputHandler] is asked for new inputs
+    pub input_polling: InputPolling,
+
+    /// How the PPU draws the screen
+    pub render_mode: RenderMode,
+
+    /// Which ROM banks are kept in memory, for ROM readers that don't keep
+    /// the whole ROM in memory themselves
+    pub bank_caching: BankCaching,
+
+    /// Makes runs reproducible by deriving everything that would otherwise
+    /// come from the host from this seed: work RAM and high RAM start as
+    /// [RamInit::Pattern] with the seed, overriding `ram_init`, and the
+    /// cartridge clock runs on emulated time (see
+    /// [crate::rom::rtc::RtcClock::Emulated]).
+    ///
+    /// Two instances started with the same ROM, config and seed, that run
+    /// the same amount of cycles with the same inputs, are guaranteed to
+    /// arrive at the same [crate::Ruboy::state_digest] and draw frames with
+    /// the same [crate::Frame::digest]. Inputs have to be given at the same
+    /// cycles, e.g. with [crate::Ruboy::step_frame] or
+    /// [crate::Ruboy::run_cycles] and [InputPolling::VBlank], and not with
+    /// [crate::Ruboy::step], which runs as many cycles as fit in real time.
+    /// A link device has to behave the same in both runs as well.
+    pub deterministic: Option<u64>,
+}
+
+impl Default for RuboyConfig {
+    fn default() -> Self {
+        Self {
+            model: GbModel::default(),
+            ram_init: RamInit::default(),
+            access_blocking: true,
+            input_polling: InputPolling::default(),
+            render_mode: RenderMode::default(),
+            bank_caching: BankCaching::default(),
+            deterministic: None,
+        }
+    }
+}
+
+/// When the emulator asks the [crate::InputHandler] for new inputs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InputPolling {
+    /// Once per frame, when VBlank starts
+    #[default]
+    VBlank,
+
+    /// Every given amount of T-cycles, for games that read the joypad more
+    /// than once per frame and should see changes in between
+    Interval(NonZeroU16),
+}
+
+/// What work RAM and high RAM contain at power on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RamInit {
+    /// Every byte zeroed
+    #[default]
+    Zeroed,
+
+    /// Every byte set to the given value
+    Fill(u8),
+
+    /// Approximates the noise the RAM of the model powers on with, which
+    /// some games use as a source of randomness. The noise is generated from
+    /// the seed, so runs stay reproducible
+    Pattern { seed: u64 },
+}
+
+impl RamInit {
+    /// Fills work RAM and high RAM with the initial contents for the model
+    pub fn fill(self, model: GbModel, wram: &mut [u8], hram: &mut [u8]) {
+        match self {
+            RamInit::Zeroed => {
+                wram.fill(0x00);
+                hram.fill(0x00);
+            }
+            RamInit::Fill(val) => {
+                wram.fill(val);
+                hram.fill(val);
+            }
+            RamInit::Pattern { seed } => {
+                let mut rng = XorShift64::new(seed);
+
+                match model {
+                    GbModel::Dmg0 | GbModel::Dmg | GbModel::Mgb | GbModel::Sgb | GbModel::Sgb2 => {
+                        dmg_wram_pattern(&mut rng, wram);
+                        hram.fill_with(|| rng.next_u8());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// DMG work RAM powers on in blocks of 256 bytes that alternate between
+/// mostly set and mostly cleared bits
+fn dmg_wram_pattern(rng: &mut XorShift64, wram: &mut [u8]) {
+    for (addr, byte) in wram.iter_mut().enumerate() {
+        let noise = rng.next_u8();
+
+        *byte = if addr & 0x100 == 0 {
+            noise | rng.next_u8()
+        } else {
+            noise & rng.next_u8()
+        };
+    }
+}
+
+/// Small, platform independent random number generator, so that RAM
+/// patterns are the same on every machine
+#[derive(Debug, Clone, Copy)]
+struct XorShift64(u64);
+
+impl XorShift64 {
+    fn new(seed: u64) -> Self {
+        // Spread out small seeds. The state may never be zero
+        Self((seed ^ 0x9E3779B97F4A7C15).max(1))
+    }
+
+    fn next_u8(&mut self) -> u8 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+
+        (self.0 >> 56) as u8
+    }
+}
+
+/// Initial CPU register values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootRegisters {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+}
+
+impl BootRegisters {
+    pub const fn for_model(model: GbModel, header_checksum: u8) -> Self {
+        // The DMG/MGB boot ROMs leave the half-carry and carry flags set
+        // unless the header checksum happens to be zero
+        let dmg_flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+
+        let (a, f, b, c, d, e, h, l) = match model {
+            GbModel::Dmg0 => (0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03),
+            GbModel::Dmg => (0x01, dmg_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+            GbModel::Mgb => (0xFF, dmg_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D),
+            GbModel::Sgb => (0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+            GbModel::Sgb2 => (0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60),
+        };
+
+        Self {
+            a,
+            f,
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+}
+
+/// Initial I/O register values. Registers that are not emulated
+/// (yet) are not part of this preset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BootIo {
+    /// 0xFF00
+    pub joypad: u8,
+
+    /// 0xFF04
+    pub timer_div: u8,
+
+    /// 0xFF05
+    pub timer_counter: u8,
+
+    /// 0xFF06
+    pub timer_modulo: u8,
+
+    /// 0xFF07
+    pub timer_control: u8,
+
+    /// 0xFF0F
+    pub interrupts_requested: u8,
+
+    /// 0xFF24
+    pub audio_volume: u8,
+
+    /// 0xFF25
+    pub audio_panning: u8,
+
+    /// 0xFF26
+    pub audio_enable: u8,
+
+    /// 0xFF40
+    pub lcd_control: u8,
+
+    /// 0xFF41
+    pub lcd_stat: u8,
+
+    /// 0xFF42
+    pub scy: u8,
+
+    /// 0xFF43
+    pub scx: u8,
+
+    /// 0xFF44
+    pub lcd_y: u8,
+
+    /// 0xFF45
+    pub lcd_y_comp: u8,
+
+    /// 0xFF46
+    pub oam_dma: u8,
+
+    /// 0xFF47
+    pub bg_palette: u8,
+
+    /// 0xFF48
+    pub obj0_palette: u8,
+
+    /// 0xFF49
+    pub obj1_palette: u8,
+
+    /// 0xFF4A
+    pub win_y: u8,
+
+    /// 0xFF4B
+    pub win_x: u8,
+
+    /// 0xFFFF
+    pub interrupts_enabled: u8,
+}
+
+impl BootIo {
+    pub const fn for_model(model: GbModel) -> Self {
+        // On the SGB models, DIV depends on how long the SNES took to
+        // hand over control. We pick a fixed value so that runs stay
+        // reproducible.
+        let (timer_div, lcd_stat, audio_enable) = match model {
+            GbModel::Dmg0 => (0x18, 0x81, 0xF1),
+            GbModel::Dmg | GbModel::Mgb => (0xAB, 0x85, 0xF1),
+            GbModel::Sgb | GbModel::Sgb2 => (0x00, 0x85, 0xF0),
+        };
+
+        Self {
+            joypad: 0xCF,
+            timer_div,
+            timer_counter: 0x00,
+            timer_modulo: 0x00,
+            timer_control: 0xF8,
+            interrupts_requested: 0xE1,
+            audio_volume: 0x77,
+            audio_panning: 0xF3,
+            audio_enable,
+            lcd_control: 0x91,
+            lcd_stat,
+            scy: 0x00,
+            scx: 0x00,
+            lcd_y: 0x00,
+            lcd_y_comp: 0x00,
+            oam_dma: 0xFF,
+            bg_palette: 0xFC,
+            obj0_palette: 0xFF,
+            obj1_palette: 0xFF,
+            win_y: 0x00,
+            win_x: 0x00,
+            interrupts_enabled: 0x00,
+        }
+    }
+}
+
+/// Small, platform independent FNV-1a hasher used for state digests.
+/// [core::hash::DefaultHasher] is not guaranteed to be stable between
+/// Rust releases, so it cannot be used for digests that are compared
+/// between machines.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StateDigest(u64);
+
+impl StateDigest {
+    const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+
+    pub fn new() -> Self {
+        Self(Self::OFFSET_BASIS)
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.0 ^= val as u64;
+        self.0 = self.0.wrapping_mul(Self::PRIME);
+    }
+
+    pub fn write_bool(&mut self, val: bool) {
+        self.write_u8(val as u8);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.write_bytes(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.write_bytes(&val.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.write_u8(*byte);
+        }
+    }
+
+    pub const fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digest_known_value() {
+        // Reference value for FNV-1a 64 of "a"
+        let mut digest = StateDigest::new();
+        digest.write_u8(b'a');
+
+        assert_eq!(0xaf63dc4c8601ec8c, digest.finish());
+    }
+
+    #[test]
+    fn dmg_flags_follow_checksum() {
+        assert_eq!(0x80, BootRegisters::for_model(GbModel::Dmg, 0x00).f);
+        assert_eq!(0xB0, BootRegisters::for_model(GbModel::Dmg, 0x3C).f);
+        assert_eq!(0x00, BootRegisters::for_model(GbModel::Sgb, 0x3C).f);
+    }
+
+    #[test]
+    fn dmg_pattern_alternates_per_block() {
+        let mut wram = [0u8; 0x2000];
+        let mut hram = [0u8; 0x7F];
+
+        RamInit::Pattern { seed: 1 }.fill(GbModel::Dmg, &mut wram, &mut hram);
+
+        let ones = |block: &[u8]| block.iter().map(|b| b.count_ones()).sum::<u32>();
+
+        // Each 256 byte block holds 2048 bits, of which about 3/4 are set
+        // in the first and 1/4 in the second block
+        assert!(ones(&wram[..0x100]) > 1300);
+        assert!(ones(&wram[0x100..0x200]) < 750);
+        assert!(hram.iter().any(|b| *b != 0));
+
+        let mut again = [0u8; 0x2000];
+        RamInit::Pattern { seed: 1 }.fill(GbModel::Dmg, &mut again, &mut hram);
+        assert_eq!(wram, again);
+    }
+
+    #[test]
+    fn models_have_distinct_states() {
+        for (i, left) in GbModel::ALL.iter().enumerate() {
+            for right in &GbModel::ALL[i + 1..] {
+                assert_ne!(left.boot_state(0x3C), right.boot_state(0x3C));
+            }
+        }
+    }
+}
@@ -0,0 +1,422 @@
+//! Recording the inputs of a play session, and replaying them exactly.
+//!
+//! A [Movie] holds the save state the recording started at, and the
+//! inputs of every frame since. Inputs are only sampled once per frame
+//! while recording or replaying, so replaying a movie from its start state
+//! gives the exact same emulation, which makes movies usable for regression
+//! runs and tool-assisted play.
+//!
+//! Record by giving the emulator a [MovieRecorder] as its input handler,
+//! and replay with a [ReplayInputHandler]:
+//!
+//! ```ignore
+//! let movie = Movie::begin(&ruboy);
+//! ruboy.input_mut().start(movie);
+//! // ...play...
+//! let movie = ruboy.input_mut().stop().unwrap();
+//!
+//! let mut replay = Ruboy::new(rom, output, ReplayInputHandler::new(&movie))?;
+//! movie.restore_start(&mut replay)?;
+//! ```
+
+use alloc::vec::Vec;
+use thiserror::Error;
+
+use crate::savestate::{StateData, StateLoadErr, StateReader, StateWriter};
+use crate::{GBAllocator, GBGraphicsDrawer, GbInputs, InputHandler, RomReader, Ruboy};
+
+/// Identifies a Ruboy movie file
+const MOVIE_MAGIC: [u8; 4] = *b"RBMV";
+
+/// Bumped on every change to the movie layout
+const MOVIE_VERSION: u16 = 1;
+
+#[derive(Debug, Error)]
+pub enum MovieLoadErr {
+    #[error("Not a Ruboy movie")]
+    BadMagic,
+
+    #[error("Unsupported movie version {0}, expected {MOVIE_VERSION}")]
+    Version(u16),
+
+    #[error("Invalid movie data: {0}")]
+    Data(#[from] StateLoadErr),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    /// [crate::rom::meta::RomMeta::header_hash] of the recorded game
+    rom_hash: u64,
+
+    /// Made with [Ruboy::save_state]
+    start_state: Vec<u8>,
+
+    /// The inputs of every frame, in order
+    inputs: Vec<GbInputs>,
+}
+
+impl Movie {
+    /// Starts an empty movie at the current state of the emulator
+    pub fn begin<A, R, V, I>(ruboy: &Ruboy<A, R, V, I>) -> Self
+    where
+        A: GBAllocator,
+        R: RomReader,
+        V: GBGraphicsDrawer,
+        I: InputHandler,
+    {
+        Self {
+            rom_hash: ruboy.rom_meta().header_hash(),
+            start_state: ruboy.save_state(),
+            inputs: Vec::new(),
+        }
+    }
+
+    pub const fn rom_hash(&self) -> u64 {
+        self.rom_hash
+    }
+
+    pub fn start_state(&self) -> &[u8] {
+        &self.start_state
+    }
+
+    pub fn inputs(&self) -> &[GbInputs] {
+        &self.inputs
+    }
+
+    /// The length of the movie in frames
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// Appends a frame. Useful to write a movie by hand, e.g. for
+    /// tool-assisted play.
+    pub fn push(&mut self, inputs: GbInputs) {
+        self.inputs.push(inputs);
+    }
+
+    /// Puts the emulator in the state the movie starts at. Fails if the
+    /// emulator runs another game.
+    pub fn restore_start<A, R, V, I>(
+        &self,
+        ruboy: &mut Ruboy<A, R, V, I>,
+    ) -> Result<(), StateLoadErr>
+    where
+        A: GBAllocator,
+        R: RomReader,
+        V: GBGraphicsDrawer,
+        I: InputHandler,
+    {
+        ruboy.load_state(&self.start_state)
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+
+        w.write_bytes(&MOVIE_MAGIC);
+        MOVIE_VERSION.save_state(&mut w);
+        self.rom_hash.save_state(&mut w);
+        self.start_state.save_state(&mut w);
+
+        let packed: Vec<u8> = self.inputs.iter().map(|inputs| pack(*inputs)).collect();
+        packed.save_state(&mut w);
+
+        w.finish()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MovieLoadErr> {
+        let mut r = StateReader::new(bytes);
+
+        if r.read_array()? != MOVIE_MAGIC {
+            return Err(MovieLoadErr::BadMagic);
+        }
+
+        let mut version = 0u16;
+        version.load_state(&mut r)?;
+
+        if version != MOVIE_VERSION {
+            return Err(MovieLoadErr::Version(version));
+        }
+
+        let mut movie = Self {
+            rom_hash: 0,
+            start_state: Vec::new(),
+            inputs: Vec::new(),
+        };
+
+        movie.rom_hash.load_state(&mut r)?;
+        movie.start_state.load_state(&mut r)?;
+
+        let mut packed: Vec<u8> = Vec::new();
+        packed.load_state(&mut r)?;
+        r.finish()?;
+
+        movie.inputs = packed.into_iter().map(unpack).collect();
+
+        Ok(movie)
+    }
+}
+
+/// One bit per button, in the order of the joypad register
+pub(crate) fn pack(inputs: GbInputs) -> u8 {
+    [
+        inputs.a,
+        inputs.b,
+        inputs.select,
+        inputs.start,
+        inputs.right,
+        inputs.left,
+        inputs.up,
+        inputs.down,
+    ]
+    .into_iter()
+    .enumerate()
+    .fold(0, |packed, (bit, pressed)| {
+        packed | (u8::from(pressed) << bit)
+    })
+}
+
+pub(crate) fn unpack(packed: u8) -> GbInputs {
+    let pressed = |bit: u8| packed & (1 << bit) != 0;
+
+    GbInputs {
+        a: pressed(0),
+        b: pressed(1),
+        select: pressed(2),
+        start: pressed(3),
+        right: pressed(4),
+        left: pressed(5),
+        up: pressed(6),
+        down: pressed(7),
+    }
+}
+
+/// Passes the inputs of another handler through, and records them into a
+/// [Movie] while recording
+#[derive(Debug)]
+pub struct MovieRecorder<I: InputHandler> {
+    inner: I,
+    movie: Option<Movie>,
+
+    /// The buttons held down, as of the last change of the inner handler
+    held: GbInputs,
+
+    /// The inputs of the current frame, once sampled
+    latched: Option<GbInputs>,
+}
+
+impl<I: InputHandler> MovieRecorder<I> {
+    pub const fn new(inner: I) -> Self {
+        Self {
+            inner,
+            movie: None,
+            held: GbInputs {
+                start: false,
+                select: false,
+                b: false,
+                a: false,
+                down: false,
+                up: false,
+                left: false,
+                right: false,
+            },
+            latched: None,
+        }
+    }
+
+    /// Starts recording into the given movie, usually made with
+    /// [Movie::begin] right before. Inputs are appended to it.
+    pub fn start(&mut self, movie: Movie) {
+        self.movie = Some(movie);
+        self.latched = None;
+    }
+
+    /// Stops recording, handing back the movie
+    pub fn stop(&mut self) -> Option<Movie> {
+        self.movie.take()
+    }
+
+    pub const fn is_recording(&self) -> bool {
+        self.movie.is_some()
+    }
+
+    pub const fn inner(&self) -> &I {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut I {
+        &mut self.inner
+    }
+}
+
+impl<I: InputHandler> InputHandler for MovieRecorder<I> {
+    fn poll_inputs(&mut self) -> Option<GbInputs> {
+        let changed = self.inner.poll_inputs();
+
+        if let Some(inputs) = changed {
+            self.held = inputs;
+        }
+
+        let Some(movie) = &mut self.movie else {
+            return changed;
+        };
+
+        let held = self.held;
+
+        Some(*self.latched.get_or_insert_with(|| {
+            movie.push(held);
+            held
+        }))
+    }
+
+    fn frame_started(&mut self) {
+        self.latched = None;
+        self.inner.frame_started();
+    }
+}
+
+/// Plays back the inputs of a [Movie]. Once the movie ended, no buttons
+/// are pressed, so the default one never presses any.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayInputHandler {
+    inputs: Vec<GbInputs>,
+
+    /// Index of the current frame in `inputs`
+    frame: usize,
+}
+
+impl ReplayInputHandler {
+    pub fn new(movie: &Movie) -> Self {
+        Self {
+            inputs: movie.inputs.clone(),
+            frame: 0,
+        }
+    }
+
+    /// The number of frames played so far
+    pub const fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn finished(&self) -> bool {
+        self.frame >= self.inputs.len()
+    }
+}
+
+impl InputHandler for ReplayInputHandler {
+    fn poll_inputs(&mut self) -> Option<GbInputs> {
+        Some(self.inputs.get(self.frame).copied().unwrap_or_default())
+    }
+
+    fn frame_started(&mut self) {
+        self.frame += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::model::GbModel;
+    use crate::testutils::NullOutput;
+    use crate::InlineAllocator;
+
+    /// Changes its inputs every few polls, so every few frames
+    struct Masher {
+        polls: u64,
+    }
+
+    impl InputHandler for Masher {
+        fn poll_inputs(&mut self) -> Option<GbInputs> {
+            self.polls += 1;
+
+            Some(GbInputs {
+                a: (self.polls / 4).is_multiple_of(2),
+                start: (self.polls / 3).is_multiple_of(3),
+                ..Default::default()
+            })
+        }
+    }
+
+    /// Keeps adding the button state to register B
+    fn joypad_rom() -> Cursor<Vec<u8>> {
+        let mut rom = vec![0u8; 0x8000];
+
+        // JP 0x150
+        rom[0x100..0x103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+
+        rom[0x150..0x15A].copy_from_slice(&[
+            0x3E, 0x10, // LD A, 0x10 (select the action buttons)
+            0xE0, 0x00, // LDH (0x00), A
+            0xF0, 0x00, // LDH A, (0x00)
+            0x80, // ADD A, B
+            0x47, // LD B, A
+            0x18, 0xF6, // JR 0x150
+        ]);
+
+        rom[0x14D] = 0xE7;
+
+        Cursor::new(rom)
+    }
+
+    fn new_with<I: InputHandler>(
+        input: I,
+    ) -> Ruboy<InlineAllocator, Cursor<Vec<u8>>, NullOutput, I> {
+        Ruboy::with_model(joypad_rom(), NullOutput, input, GbModel::Dmg).unwrap()
+    }
+
+    #[test]
+    fn inputs_pack_losslessly() {
+        for packed in 0..=u8::MAX {
+            assert_eq!(packed, pack(unpack(packed)));
+        }
+    }
+
+    #[test]
+    fn replay_is_deterministic() {
+        let mut ruboy = new_with(MovieRecorder::new(Masher { polls: 0 }));
+        ruboy.step(0.05).unwrap();
+
+        let movie = Movie::begin(&ruboy);
+        ruboy.input_mut().start(movie);
+        let start_frame = ruboy.frame_count();
+
+        ruboy.step(0.5).unwrap();
+
+        let digest = ruboy.state_digest();
+        let frames = ruboy.frame_count() - start_frame;
+        let movie = ruboy.input_mut().stop().unwrap();
+
+        // Sampled at the end of every frame
+        assert_eq!(frames as usize, movie.len());
+        assert!(movie.inputs().iter().any(|inputs| inputs.a));
+        assert!(movie.inputs().iter().any(|inputs| !inputs.a));
+
+        let movie = Movie::from_bytes(&movie.to_bytes()).unwrap();
+
+        let mut replay = new_with(ReplayInputHandler::new(&movie));
+        movie.restore_start(&mut replay).unwrap();
+        replay.step(0.5).unwrap();
+
+        assert_eq!(digest, replay.state_digest());
+
+        // The inputs really matter
+        let mut idle = new_with(ReplayInputHandler::new(&Movie::begin(&replay)));
+        movie.restore_start(&mut idle).unwrap();
+        idle.step(0.5).unwrap();
+
+        assert_ne!(digest, idle.state_digest());
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(
+            Movie::from_bytes(b"RBST\x01\x00"),
+            Err(MovieLoadErr::BadMagic)
+        ));
+    }
+}
@@ -0,0 +1,625 @@
+//! Link cable play over the network, between two hosts that each run one of
+//! the games.
+//!
+//! A transfer started by this game is sent to the other side, which clocks
+//! its game with the bits and sends back the byte that game shifted out,
+//! see [crate::link] for how that works over a real cable. Transfers of
+//! the other side come in the same way, and are answered by
+//! [NetLink::poll].
+//!
+//! Packets are 3 bytes: the kind, a sequence number and the byte. Over a
+//! transport that can lose packets, like UDP, transfers are sent again
+//! until they are answered, and answers to repeated transfers are repeated
+//! too.
+
+use core::fmt::{self, Debug};
+use std::boxed::Box;
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use std::vec::Vec;
+
+use crate::emucore::EmulatorCore;
+use crate::{GBGraphicsDrawer, LinkDevice};
+
+/// How long a transfer waits for an answer by default, before giving up as
+/// if the cable was pulled
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long an unanswered transfer waits before it is sent again, over a
+/// transport that can lose packets
+const RESEND_INTERVAL: Duration = Duration::from_millis(50);
+
+const PACKET_SIZE: usize = 3;
+
+const KIND_TRANSFER: u8 = 1;
+const KIND_ANSWER: u8 = 2;
+
+/// What the other side reads while nothing answers
+const LINE_HIGH: u8 = 0xFF;
+
+/// How a transfer started by this side deals with the time the other side
+/// takes to answer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkSync {
+    /// Every transfer waits for the answer of the other side, so games see
+    /// exactly the bytes they would over a cable. Emulation stops for a
+    /// round trip on each transfer, which slows down games that send a lot.
+    #[default]
+    PerTransfer,
+
+    /// Transfers don't wait, and assume the other side answers the same as
+    /// it did last time. Its actual answer is used for the next transfer.
+    /// Hides the latency, but games only work if they repeat bytes until
+    /// both sides agree, like most handshakes do.
+    Speculative,
+}
+
+/// A single message between the two sides
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Packet {
+    kind: u8,
+    seq: u8,
+    byte: u8,
+}
+
+impl Packet {
+    pub const fn to_bytes(self) -> [u8; PACKET_SIZE] {
+        [self.kind, self.seq, self.byte]
+    }
+
+    /// Parses a packet, returning None if it is not one
+    pub const fn from_bytes(bytes: [u8; PACKET_SIZE]) -> Option<Self> {
+        match bytes[0] {
+            KIND_TRANSFER | KIND_ANSWER => Some(Self {
+                kind: bytes[0],
+                seq: bytes[1],
+                byte: bytes[2],
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Carries packets to the other side. Implemented for TCP and UDP, but
+/// anything that gets packets across will do.
+pub trait LinkTransport: Send {
+    fn send(&mut self, packet: Packet) -> io::Result<()>;
+
+    /// Waits up to `timeout` for a packet from the other side. Returns None
+    /// if none arrived in time. A zero timeout only takes what already
+    /// arrived.
+    fn recv(&mut self, timeout: Duration) -> io::Result<Option<Packet>>;
+
+    /// Whether packets can get lost, so transfers have to be sent again
+    fn lossy(&self) -> bool;
+}
+
+/// A TCP connection to the other side
+#[derive(Debug)]
+pub struct TcpTransport {
+    stream: TcpStream,
+
+    /// Bytes of a packet that has only partially arrived
+    buf: Vec<u8>,
+}
+
+impl TcpTransport {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        // Packets are tiny, and waiting to fill them up only adds latency
+        stream.set_nodelay(true)?;
+
+        Ok(Self {
+            stream,
+            buf: Vec::new(),
+        })
+    }
+
+    pub fn connect(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Self::new(TcpStream::connect(addr)?)
+    }
+
+    /// Waits for the other side to connect to `addr`
+    pub fn listen(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let (stream, _) = TcpListener::bind(addr)?.accept()?;
+        Self::new(stream)
+    }
+}
+
+impl LinkTransport for TcpTransport {
+    fn send(&mut self, packet: Packet) -> io::Result<()> {
+        self.stream.write_all(&packet.to_bytes())
+    }
+
+    fn recv(&mut self, timeout: Duration) -> io::Result<Option<Packet>> {
+        loop {
+            if self.buf.len() >= PACKET_SIZE {
+                let bytes = [self.buf[0], self.buf[1], self.buf[2]];
+                self.buf.drain(..PACKET_SIZE);
+
+                match Packet::from_bytes(bytes) {
+                    Some(packet) => return Ok(Some(packet)),
+                    None => continue,
+                }
+            }
+
+            if timeout.is_zero() {
+                self.stream.set_nonblocking(true)?;
+            } else {
+                self.stream.set_nonblocking(false)?;
+                self.stream.set_read_timeout(Some(timeout))?;
+            }
+
+            let mut chunk = [0; 64];
+
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.buf.extend_from_slice(&chunk[..read]),
+                Err(e) if timed_out(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn lossy(&self) -> bool {
+        false
+    }
+}
+
+/// UDP packets to and from the other side. Both sides bind their own
+/// address, and send to the address of the other.
+#[derive(Debug)]
+pub struct UdpTransport {
+    socket: UdpSocket,
+}
+
+impl UdpTransport {
+    pub fn new(local: impl ToSocketAddrs, remote: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(remote)?;
+
+        Ok(Self { socket })
+    }
+}
+
+impl LinkTransport for UdpTransport {
+    fn send(&mut self, packet: Packet) -> io::Result<()> {
+        self.socket.send(&packet.to_bytes()).map(drop)
+    }
+
+    fn recv(&mut self, timeout: Duration) -> io::Result<Option<Packet>> {
+        if timeout.is_zero() {
+            self.socket.set_nonblocking(true)?;
+        } else {
+            self.socket.set_nonblocking(false)?;
+            self.socket.set_read_timeout(Some(timeout))?;
+        }
+
+        let mut bytes = [0; PACKET_SIZE];
+
+        loop {
+            match self.socket.recv(&mut bytes) {
+                Ok(PACKET_SIZE) => match Packet::from_bytes(bytes) {
+                    Some(packet) => return Ok(Some(packet)),
+                    None => continue,
+                },
+                // Not one of ours
+                Ok(_) => continue,
+                Err(e) if timed_out(&e) => return Ok(None),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn lossy(&self) -> bool {
+        true
+    }
+}
+
+fn timed_out(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+struct Connection {
+    transport: Box<dyn LinkTransport>,
+    sync: LinkSync,
+    timeout: Duration,
+
+    /// Set once sending or receiving failed. No packets go out after that.
+    broken: bool,
+
+    /// Sequence number of the next transfer this side starts
+    next_seq: u8,
+
+    /// The last answer of the other side, assumed by speculative transfers
+    last_answer: u8,
+
+    /// The last transfer of the other side that was answered, and the
+    /// answer, to send it again if the transfer is repeated
+    answered: Option<(u8, u8)>,
+
+    /// Transfers of the other side that came in while waiting for an
+    /// answer, to be answered by the next poll
+    pending: VecDeque<Packet>,
+}
+
+impl Connection {
+    fn send(&mut self, packet: Packet) {
+        if self.broken {
+            return;
+        }
+
+        if let Err(e) = self.transport.send(packet) {
+            log::error!("Network link failed: {}", e);
+            self.broken = true;
+        }
+    }
+
+    fn recv(&mut self, timeout: Duration) -> io::Result<Option<Packet>> {
+        if self.broken {
+            return Err(ErrorKind::NotConnected.into());
+        }
+
+        self.transport.recv(timeout).inspect_err(|e| {
+            log::error!("Network link failed: {}", e);
+            self.broken = true;
+        })
+    }
+
+    /// Sends the byte of a transfer this side clocks, and returns the
+    /// answer of the other side
+    fn transfer(&mut self, sent: u8) -> u8 {
+        let seq = self.next_seq;
+        self.next_seq = seq.wrapping_add(1);
+
+        let packet = Packet {
+            kind: KIND_TRANSFER,
+            seq,
+            byte: sent,
+        };
+
+        self.send(packet);
+
+        match self.sync {
+            LinkSync::PerTransfer => self.wait_for_answer(packet),
+            LinkSync::Speculative => {
+                // Pick up answers that arrived since the last transfer
+                while let Ok(Some(packet)) = self.recv(Duration::ZERO) {
+                    self.received(packet);
+                }
+
+                self.last_answer
+            }
+        }
+    }
+
+    fn wait_for_answer(&mut self, transfer: Packet) -> u8 {
+        let deadline = Instant::now() + self.timeout;
+        let mut resend = Instant::now() + RESEND_INTERVAL;
+
+        loop {
+            let now = Instant::now();
+
+            if now >= deadline {
+                log::warn!("Network link timed out, the other side did not answer");
+                return LINE_HIGH;
+            }
+
+            let wait = if self.transport.lossy() {
+                if now >= resend {
+                    self.send(transfer);
+                    resend = now + RESEND_INTERVAL;
+                }
+
+                deadline.min(resend) - now
+            } else {
+                deadline - now
+            };
+
+            match self.recv(wait) {
+                Ok(Some(packet)) if packet.kind == KIND_ANSWER && packet.seq == transfer.seq => {
+                    self.last_answer = packet.byte;
+                    return packet.byte;
+                }
+                Ok(Some(packet)) => self.received(packet),
+                Ok(None) => {}
+                Err(_) => return LINE_HIGH,
+            }
+        }
+    }
+
+    /// Handles a packet that is not the answer being waited for
+    fn received(&mut self, packet: Packet) {
+        match packet.kind {
+            // A late answer, only useful as the next guess
+            KIND_ANSWER => self.last_answer = packet.byte,
+            _ => self.pending.push_back(packet),
+        }
+    }
+
+    /// Answers a transfer of the other side, clocking `ruboy` with its bits
+    fn answer<V, C>(&mut self, transfer: Packet, ruboy: &mut C)
+    where
+        V: GBGraphicsDrawer,
+        C: EmulatorCore<V> + ?Sized,
+    {
+        let byte = match self.answered {
+            Some((seq, byte)) if seq == transfer.seq => byte,
+            _ => {
+                let byte = clock_in(ruboy, transfer.byte);
+                self.answered = Some((transfer.seq, byte));
+                byte
+            }
+        };
+
+        self.send(Packet {
+            kind: KIND_ANSWER,
+            seq: transfer.seq,
+            byte,
+        });
+    }
+}
+
+/// Clocks `byte` into the game bit by bit, MSB first, returning the byte
+/// it shifted out. A game that isn't waiting on the external clock doesn't
+/// answer, leaving the line high.
+fn clock_in<V, C>(ruboy: &mut C, byte: u8) -> u8
+where
+    V: GBGraphicsDrawer,
+    C: EmulatorCore<V> + ?Sized,
+{
+    (0..8).rev().fold(0, |out, bit| {
+        let bit_out = ruboy
+            .serial_external_clock(byte & (1 << bit) != 0)
+            .unwrap_or(true);
+
+        (out << 1) | bit_out as u8
+    })
+}
+
+fn lock(conn: &Mutex<Connection>) -> MutexGuard<'_, Connection> {
+    conn.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// One end of a link cable over the network. Attach [NetLink::device] to
+/// the emulator so the transfers of the game go out, and call
+/// [NetLink::poll] regularly, e.g. after every step, to answer the
+/// transfers of the other side.
+#[derive(Clone)]
+pub struct NetLink {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl NetLink {
+    pub fn new(transport: impl LinkTransport + 'static, sync: LinkSync) -> Self {
+        Self {
+            conn: Arc::new(Mutex::new(Connection {
+                transport: Box::new(transport),
+                sync,
+                timeout: DEFAULT_TIMEOUT,
+                broken: false,
+                next_seq: 0,
+                last_answer: LINE_HIGH,
+                answered: None,
+                pending: VecDeque::new(),
+            })),
+        }
+    }
+
+    /// Changes how long a [LinkSync::PerTransfer] transfer waits for an
+    /// answer. See [DEFAULT_TIMEOUT].
+    pub fn set_timeout(&self, timeout: Duration) {
+        lock(&self.conn).timeout = timeout;
+    }
+
+    pub fn sync(&self) -> LinkSync {
+        lock(&self.conn).sync
+    }
+
+    /// False once the connection failed. Transfers of the game then get no
+    /// answer, as if the cable was pulled.
+    pub fn connected(&self) -> bool {
+        !lock(&self.conn).broken
+    }
+
+    /// The end of the cable that plugs into the emulator
+    pub fn device(&self) -> NetLinkDevice {
+        NetLinkDevice {
+            conn: self.conn.clone(),
+        }
+    }
+
+    /// Answers the transfers the other side started since the last call
+    pub fn poll<V, C>(&self, ruboy: &mut C) -> io::Result<()>
+    where
+        V: GBGraphicsDrawer,
+        C: EmulatorCore<V> + ?Sized,
+    {
+        let mut conn = lock(&self.conn);
+
+        while let Some(transfer) = conn.pending.pop_front() {
+            conn.answer(transfer, ruboy);
+        }
+
+        while let Some(packet) = conn.recv(Duration::ZERO)? {
+            match packet.kind {
+                KIND_TRANSFER => conn.answer(packet, ruboy),
+                _ => conn.received(packet),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for NetLink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let conn = lock(&self.conn);
+
+        f.debug_struct("NetLink")
+            .field("sync", &conn.sync)
+            .field("connected", &!conn.broken)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Plugs a [NetLink] into the emulator, see
+/// [crate::Ruboy::attach_link_device]
+pub struct NetLinkDevice {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl LinkDevice for NetLinkDevice {
+    fn exchange(&mut self, sent: u8, _cycle: u64) -> u8 {
+        lock(&self.conn).transfer(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::GbModel;
+    use crate::testutils::{blank_rom, NullInput, NullOutput, TestRuboy};
+    use crate::Ruboy;
+    use std::io::Cursor;
+    use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+    use std::thread;
+
+    struct ChannelTransport {
+        tx: Sender<Packet>,
+        rx: Receiver<Packet>,
+
+        /// Drops every other packet sent
+        lossy: bool,
+        dropped: bool,
+    }
+
+    fn channel_pair(lossy: bool) -> (ChannelTransport, ChannelTransport) {
+        let (a_tx, b_rx) = mpsc::channel();
+        let (b_tx, a_rx) = mpsc::channel();
+
+        let a = ChannelTransport {
+            tx: a_tx,
+            rx: a_rx,
+            lossy,
+            dropped: false,
+        };
+        let b = ChannelTransport {
+            tx: b_tx,
+            rx: b_rx,
+            lossy,
+            dropped: false,
+        };
+
+        (a, b)
+    }
+
+    impl LinkTransport for ChannelTransport {
+        fn send(&mut self, packet: Packet) -> io::Result<()> {
+            if self.lossy {
+                self.dropped = !self.dropped;
+
+                if self.dropped {
+                    return Ok(());
+                }
+            }
+
+            self.tx
+                .send(packet)
+                .map_err(|_| ErrorKind::BrokenPipe.into())
+        }
+
+        fn recv(&mut self, timeout: Duration) -> io::Result<Option<Packet>> {
+            match self.rx.recv_timeout(timeout) {
+                Ok(packet) => Ok(Some(packet)),
+                Err(RecvTimeoutError::Timeout) => Ok(None),
+                Err(RecvTimeoutError::Disconnected) => Err(ErrorKind::BrokenPipe.into()),
+            }
+        }
+
+        fn lossy(&self) -> bool {
+            self.lossy
+        }
+    }
+
+    /// Waits on the external clock with `byte` in SB
+    fn slave(byte: u8) -> TestRuboy {
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x100..0x10A].copy_from_slice(&[
+            0x3E, byte, // LD A, byte
+            0xE0, 0x01, // LDH (SB), A
+            0x3E, 0x80, // LD A, 0x80
+            0xE0, 0x02, // LDH (SC), A
+            0x18, 0xFE, // JR -2
+        ]);
+
+        let mut ruboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        ruboy.run_cycles(100).unwrap();
+        ruboy
+    }
+
+    fn exchange_with_slave(lossy: bool) {
+        let (a, b) = channel_pair(lossy);
+        let master = NetLink::new(a, LinkSync::PerTransfer);
+        let remote = NetLink::new(b, LinkSync::PerTransfer);
+
+        let answering = thread::spawn(move || {
+            let mut ruboy = slave(0x99);
+
+            while ruboy.mem.io_registers.serial.transferring() {
+                remote.poll(&mut ruboy).unwrap();
+            }
+
+            // Keep answering repeated transfers for a bit
+            for _ in 0..10 {
+                remote.poll(&mut ruboy).unwrap();
+                thread::sleep(RESEND_INTERVAL);
+            }
+
+            ruboy.mem.io_registers.serial.data()
+        });
+
+        assert_eq!(0x99, master.device().exchange(0x42, 0));
+        assert_eq!(0x42, answering.join().unwrap());
+    }
+
+    #[test]
+    fn transfer_waits_for_answer() {
+        exchange_with_slave(false);
+    }
+
+    #[test]
+    fn lost_packets_are_sent_again() {
+        exchange_with_slave(true);
+    }
+
+    #[test]
+    fn speculative_transfer_assumes_last_answer() {
+        let (a, b) = channel_pair(false);
+        let master = NetLink::new(a, LinkSync::Speculative);
+        let remote = NetLink::new(b, LinkSync::PerTransfer);
+        let mut ruboy = slave(0x99);
+        let mut device = master.device();
+
+        // Nothing answered yet
+        assert_eq!(0xFF, device.exchange(0x42, 0));
+
+        remote.poll(&mut ruboy).unwrap();
+        assert_eq!(0x42, ruboy.mem.io_registers.serial.data());
+
+        assert_eq!(0x99, device.exchange(0x43, 0));
+    }
+
+    #[test]
+    fn timeout_reads_line_high() {
+        let (a, _b) = channel_pair(false);
+        let master = NetLink::new(a, LinkSync::PerTransfer);
+        master.set_timeout(Duration::from_millis(10));
+
+        assert_eq!(0xFF, master.device().exchange(0x42, 0));
+        assert!(master.connected());
+    }
+}
@@ -5,6 +5,7 @@ use thiserror::Error;
 use crate::{
     memcontroller::{MemController, ReadError},
     ppu::tilemap,
+    savestate::{state_data, StateData, StateLoadErr, StateReader, StateWriter},
     GBAllocator, GbColorID, RomReader,
 };
 
@@ -23,17 +24,33 @@ pub struct PixelFetcher {
     phase: Phase,
 }
 
-#[derive(Debug, Clone, Copy)]
+state_data!(PixelFetcher {
+    cycles_left,
+    bg_win_x_pos,
+    window_lines_drawn,
+    first_tile_fetched,
+    win_x_reached,
+    object_to_fetch,
+    bg_fifo,
+    obj_fifo,
+    phase
+});
+
+#[derive(Debug, Clone, Copy, Default)]
 struct FetchDataLowData {
     pub tile_idx: u8,
 }
 
-#[derive(Debug, Clone, Copy)]
+state_data!(FetchDataLowData { tile_idx });
+
+#[derive(Debug, Clone, Copy, Default)]
 struct FetchDataHighData {
     pub tile_idx: u8,
     pub lower: u8,
 }
 
+state_data!(FetchDataHighData { tile_idx, lower });
+
 #[derive(Debug, Clone, Copy)]
 enum Phase {
     FetchTile,
@@ -43,13 +60,72 @@ enum Phase {
     Push([GbColorID; 8]),
 }
 
-#[derive(Debug, Clone, Copy)]
+impl StateData for Phase {
+    fn save_state(&self, w: &mut StateWriter) {
+        match self {
+            Phase::FetchTile => w.write_u8(0),
+            Phase::FetchDataLow(data) => {
+                w.write_u8(1);
+                data.save_state(w);
+            }
+            Phase::FetchDataHigh(data) => {
+                w.write_u8(2);
+                data.save_state(w);
+            }
+            Phase::Sleep(pixels) => {
+                w.write_u8(3);
+                pixels.save_state(w);
+            }
+            Phase::Push(pixels) => {
+                w.write_u8(4);
+                pixels.save_state(w);
+            }
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        *self = match r.read_u8()? {
+            0 => Phase::FetchTile,
+            1 => {
+                let mut data = FetchDataLowData::default();
+                data.load_state(r)?;
+                Phase::FetchDataLow(data)
+            }
+            2 => {
+                let mut data = FetchDataHighData::default();
+                data.load_state(r)?;
+                Phase::FetchDataHigh(data)
+            }
+            3 => {
+                let mut pixels = [GbColorID::default(); 8];
+                pixels.load_state(r)?;
+                Phase::Sleep(pixels)
+            }
+            4 => {
+                let mut pixels = [GbColorID::default(); 8];
+                pixels.load_state(r)?;
+                Phase::Push(pixels)
+            }
+            _ => return Err(StateLoadErr::Invalid("fetcher phase")),
+        };
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
 pub struct FetchedPixel {
     pub color: GbColorID,
     pub palette_id: PaletteID,
     pub bg_win_prio: bool,
 }
 
+state_data!(FetchedPixel {
+    color,
+    palette_id,
+    bg_win_prio
+});
+
 #[derive(Debug, Error)]
 pub enum FetcherErr {
     #[error("Error during tile number fetching: {0}")]
@@ -102,24 +178,52 @@ impl PixelFetcher {
         self.phase = Phase::FetchTile;
     }
 
+    /// Whether the rest of the line shows the window
+    pub fn in_window(&self) -> bool {
+        self.win_x_reached
+    }
+
+    /// The line of the window drawn next
+    pub fn window_line(&self) -> u8 {
+        self.window_lines_drawn
+    }
+
+    /// Switches from the background to the window for the rest of the line
+    pub fn start_window(&mut self) {
+        self.win_x_reached = true;
+        self.bg_win_x_pos = 0;
+        self.first_tile_fetched = true;
+        self.bg_fifo.clear();
+        self.cycles_left = 0;
+        self.phase = Phase::FetchTile;
+    }
+
     pub fn vblank_reset(&mut self) {
         self.window_lines_drawn = 0;
         self.hblank_reset();
     }
 
     pub fn hblank_reset(&mut self) {
+        // The window only counts the lines it was actually drawn on
+        if self.win_x_reached {
+            self.window_lines_drawn += 1;
+            self.win_x_reached = false;
+        }
+
         self.bg_win_x_pos = 0;
         self.first_tile_fetched = false;
         self.bg_fifo.clear();
         self.obj_fifo.clear();
     }
 
-    fn fetch_obj_tile(&mut self) -> Result<(), FetchTileErr> {
+    fn fetch_obj_tile(
+        &mut self,
+        mem: &MemController<impl GBAllocator, impl RomReader>,
+    ) -> Result<(), FetchTileErr> {
         let obj = self.object_to_fetch.unwrap();
+        let (tile_idx, _) = obj_tile_row(obj, mem);
 
-        self.phase = Phase::FetchDataLow(FetchDataLowData {
-            tile_idx: obj.tilenum(),
-        });
+        self.phase = Phase::FetchDataLow(FetchDataLowData { tile_idx });
 
         Ok(())
     }
@@ -134,14 +238,12 @@ impl PixelFetcher {
             0x9800
         };
 
-        let (x, y) = todo!();
-
-        debug_assert!(x <= 31, "tile X wrong size: {}", x);
+        let (x, y) = (self.bg_win_x_pos & 0x1F, self.window_lines_drawn / 8);
 
         let tile_offset = tilemap::calc_offset(x, y) & 0x3FF; // AND mask make sure that it stays within the bounds of the tilemap
 
         let tile_addr = tilemap_base + tile_offset;
-        let tile_idx = mem.read8(tile_addr)?;
+        let tile_idx = mem.read8_direct(tile_addr)?;
 
         self.phase = Phase::FetchDataLow(FetchDataLowData { tile_idx });
         Ok(())
@@ -159,7 +261,7 @@ impl PixelFetcher {
 
         let (x, y) = (
             ((mem.io_registers.scx / 8) + self.bg_win_x_pos) & 0x1F,
-            ((mem.io_registers.scy + mem.io_registers.lcd_y) / 8),
+            (mem.io_registers.scy.wrapping_add(mem.io_registers.lcd_y) / 8),
         );
 
         debug_assert!(x <= 31, "tile X wrong size: {}", x);
@@ -167,7 +269,7 @@ impl PixelFetcher {
         let tile_offset = tilemap::calc_offset(x, y) & 0x3FF; // AND mask make sure that it stays within the bounds of the tilemap
 
         let tile_addr = tilemap_base + tile_offset;
-        let tile_idx = mem.read8(tile_addr)?;
+        let tile_idx = mem.read8_direct(tile_addr)?;
 
         self.phase = Phase::FetchDataLow(FetchDataLowData { tile_idx });
         Ok(())
@@ -176,29 +278,39 @@ impl PixelFetcher {
     fn fetch_tile(
         &mut self,
         mem: &mut MemController<impl GBAllocator, impl RomReader>,
-        fetching_window: bool,
     ) -> Result<(), FetchTileErr> {
         if self.is_fetching_obj() {
-            self.fetch_obj_tile()
-        } else if fetching_window {
+            self.fetch_obj_tile(mem)
+        } else if self.win_x_reached {
             self.fetch_win_tile(mem)
         } else {
             self.fetch_bg_tile(mem)
         }
     }
 
+    /// The row of the fetched tile on the current line
+    fn tile_line(&self, mem: &MemController<impl GBAllocator, impl RomReader>) -> u8 {
+        match self.object_to_fetch {
+            Some(obj) => obj_tile_row(obj, mem).1,
+            None if self.win_x_reached => self.window_lines_drawn % (Tile::Y_SIZE as u8),
+            None => {
+                mem.io_registers.lcd_y.wrapping_add(mem.io_registers.scy) % (Tile::Y_SIZE as u8)
+            }
+        }
+    }
+
     fn fetch_data_low(
         &mut self,
         mem: &mut MemController<impl GBAllocator, impl RomReader>,
     ) -> Result<(), FetchDataErr> {
         let is_obj = self.is_fetching_obj();
+        let tile_line = self.tile_line(mem);
         let data = match &mut self.phase {
             Phase::FetchDataLow(data) => data,
             _ => panic!("Invalid mode for fetch_data_low!"),
         };
 
         let tile = get_tile_by_idx(is_obj, data.tile_idx, mem)?;
-        let tile_line = (mem.io_registers.lcd_y + mem.io_registers.scy) % (Tile::Y_SIZE as u8);
 
         let pix_lower = tile.get_lower_for_row(tile_line);
 
@@ -215,19 +327,19 @@ impl PixelFetcher {
         mem: &mut MemController<impl GBAllocator, impl RomReader>,
     ) -> Result<(), FetchDataErr> {
         let is_obj = self.is_fetching_obj();
+        let tile_line = self.tile_line(mem);
         let data = match &mut self.phase {
             Phase::FetchDataHigh(data) => data,
             _ => panic!("Invalid mode for fetch_data_high!"),
         };
 
         let tile = get_tile_by_idx(is_obj, data.tile_idx, mem)?;
-        let tile_line = (mem.io_registers.lcd_y + mem.io_registers.scy) % (Tile::Y_SIZE as u8);
 
         let pix_lower = data.lower;
         let pix_upper = tile.get_upper_for_row(tile_line);
 
         let pix_ids: [GbColorID; 8] =
-            std::array::from_fn(|i| combine_pixdata(pix_lower, pix_upper, i));
+            core::array::from_fn(|i| combine_pixdata(pix_lower, pix_upper, i));
 
         self.phase = Phase::Sleep(pix_ids);
 
@@ -235,7 +347,9 @@ impl PixelFetcher {
     }
 
     fn push(&mut self) -> Result<(), PushErr> {
-        if self.bg_fifo.space_remaining() < 8 {
+        // Drawing pauses while an object is fetched, so the background FIFO
+        // would never make room for it
+        if !self.is_fetching_obj() && self.bg_fifo.space_remaining() < 8 {
             return Ok(());
         }
 
@@ -248,25 +362,26 @@ impl PixelFetcher {
 
         if self.is_fetching_obj() {
             let obj = self.object_to_fetch.take().unwrap();
-            let occupied_slots = self.obj_fifo.len();
-            let obj_pix_on_screen = u8::min(obj.x_pos(), 8);
-            let pix_to_skip = usize::max(occupied_slots, obj_pix_on_screen as usize);
+
+            // Objects partially left of the screen are fetched at the first
+            // pixel, so their pixels left of it are dropped
+            let pix_off_screen = 8 - u8::min(obj.x_pos(), 8);
 
             if obj.flags().x_flip() {
                 pixels.reverse();
             }
 
-            // TODO: Obj y-flip
-
-            pixels.into_iter().skip(pix_to_skip).for_each(|pix| {
-                self.obj_fifo
-                    .push(FetchedPixel {
+            merge_obj_pixels(
+                &mut self.obj_fifo,
+                pixels
+                    .into_iter()
+                    .skip(pix_off_screen as usize)
+                    .map(|pix| FetchedPixel {
                         color: pix,
                         palette_id: obj.flags().palette(),
                         bg_win_prio: obj.flags().bg_win_prio(),
-                    })
-                    .unwrap()
-            });
+                    }),
+            );
 
             debug_assert!(!self.is_fetching_obj());
         } else {
@@ -308,7 +423,6 @@ impl PixelFetcher {
     pub fn run_cycle(
         &mut self,
         mem: &mut MemController<impl GBAllocator, impl RomReader>,
-        fetching_window: bool,
     ) -> Result<(), FetcherErr> {
         if self.cycles_left != 0 {
             self.cycles_left -= 1;
@@ -316,7 +430,7 @@ impl PixelFetcher {
         }
 
         match self.phase {
-            Phase::FetchTile => self.fetch_tile(mem, fetching_window)?,
+            Phase::FetchTile => self.fetch_tile(mem)?,
             Phase::FetchDataLow(_) => self.fetch_data_low(mem)?,
             Phase::FetchDataHigh(_) => self.fetch_data_high(mem)?,
             Phase::Sleep(colors) => self.phase = Phase::Push(colors),
@@ -332,7 +446,48 @@ impl PixelFetcher {
     }
 }
 
-fn addr_from_tile_idx(tile_idx: u8, addressing_mode: bool) -> u16 {
+/// The tile of an object to draw on the current line, and the row in it.
+/// Objects of 8x16 pixels are two tiles, the top one with an even index.
+pub(super) fn obj_tile_row(
+    obj: ObjectData,
+    mem: &MemController<impl GBAllocator, impl RomReader>,
+) -> (u8, u8) {
+    let height: u8 = if mem.io_registers.lcd_control.obj_size() {
+        16
+    } else {
+        8
+    };
+
+    // The OAM scan only selects objects on the current line
+    let mut row = (mem.io_registers.lcd_y as i16 - obj.offset_ypos()) as u8 % height;
+
+    if obj.flags().y_flip() {
+        row = height - 1 - row;
+    }
+
+    match height {
+        16 => ((obj.tilenum() & 0xFE) | (row / 8), row % 8),
+        _ => (obj.tilenum(), row),
+    }
+}
+
+/// Mixes the pixels of a newly fetched object into the object FIFO. The
+/// pixels already in it belong to objects with a higher priority, so they only
+/// make way where they are transparent.
+fn merge_obj_pixels(
+    fifo: &mut InlineQueue<FetchedPixel, 8>,
+    pixels: impl Iterator<Item = FetchedPixel>,
+) {
+    for (idx, pix) in pixels.enumerate() {
+        match fifo.get_mut(idx) {
+            Some(existing) if existing.color == GbColorID::ID0 => *existing = pix,
+            Some(_) => {}
+            None => fifo.push(pix).unwrap(),
+        }
+    }
+}
+
+pub(super) fn addr_from_tile_idx(tile_idx: u8, addressing_mode: bool) -> u16 {
     let tile_addr_usize = match addressing_mode {
         true => 0x8000 + ((tile_idx as usize) * size_of::<Tile>()),
         false => {
@@ -343,7 +498,7 @@ fn addr_from_tile_idx(tile_idx: u8, addressing_mode: bool) -> u16 {
     u16::try_from(tile_addr_usize).unwrap()
 }
 
-fn get_tile_by_idx(
+pub(super) fn get_tile_by_idx(
     is_obj: bool,
     tile_idx: u8,
     mem: &MemController<impl GBAllocator, impl RomReader>,
@@ -353,12 +508,12 @@ fn get_tile_by_idx(
 
     // log::info!("Getting tile {} at 0x{:x}", tile_idx, tile_addr);
 
-    let tile_bytes: [u8; size_of::<Tile>()] = mem.read_range(tile_addr)?;
+    let tile_bytes: [u8; size_of::<Tile>()] = mem.read_range_direct(tile_addr)?;
 
     Ok(tile_bytes.into())
 }
 
-fn combine_pixdata(lower: u8, upper: u8, idx: usize) -> GbColorID {
+pub(super) fn combine_pixdata(lower: u8, upper: u8, idx: usize) -> GbColorID {
     debug_assert!(idx < 8);
 
     let id_mask: u8 = 0b1 << idx;
@@ -390,6 +545,50 @@ mod tests {
         assert_eq!(GbColorID::ID3, combine_pixdata(lower, upper, 7));
     }
 
+    fn obj_pixels(colors: [GbColorID; 8], palette_id: PaletteID) -> [FetchedPixel; 8] {
+        colors.map(|color| FetchedPixel {
+            color,
+            palette_id,
+            bg_win_prio: false,
+        })
+    }
+
+    #[test]
+    fn obj_pixels_keep_priority() {
+        use GbColorID::*;
+
+        let mut fifo = InlineQueue::new();
+
+        merge_obj_pixels(
+            &mut fifo,
+            obj_pixels([ID1, ID0, ID1, ID0, ID1, ID0, ID1, ID0], PaletteID::Zero)
+                .into_iter()
+                .skip(4),
+        );
+
+        merge_obj_pixels(&mut fifo, obj_pixels([ID2; 8], PaletteID::One).into_iter());
+
+        let merged: [FetchedPixel; 8] = fifo.pop_n().unwrap();
+
+        assert_eq!(
+            [ID1, ID2, ID1, ID2, ID2, ID2, ID2, ID2],
+            merged.map(|pix| pix.color)
+        );
+        assert_eq!(
+            [
+                PaletteID::Zero,
+                PaletteID::One,
+                PaletteID::Zero,
+                PaletteID::One,
+                PaletteID::One,
+                PaletteID::One,
+                PaletteID::One,
+                PaletteID::One
+            ],
+            merged.map(|pix| pix.palette_id)
+        );
+    }
+
     #[test]
     fn test_addressing_mode_zero() {
         assert_eq!(0x9000, addr_from_tile_idx(0, false));
@@ -1,4 +1,6 @@
-use std::fmt::Debug;
+use core::fmt::Debug;
+
+use crate::savestate::{StateData, StateLoadErr, StateReader, StateWriter};
 
 pub struct InlineQueue<T, const N: usize> {
     data: [Option<T>; N],
@@ -9,7 +11,7 @@ impl<T, const N: usize> Debug for InlineQueue<T, N>
 where
     T: Debug,
 {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Fifo")
             .field("data", &self.data)
             .field("cur_elements", &self.cur_elements)
@@ -31,10 +33,28 @@ where
 
 impl<T, const N: usize> Copy for InlineQueue<T, N> where T: Copy {}
 
+impl<T: StateData + Default, const N: usize> StateData for InlineQueue<T, N> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.data.save_state(w);
+        self.cur_elements.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        self.data.load_state(r)?;
+        self.cur_elements.load_state(r)?;
+
+        if self.cur_elements > N {
+            return Err(StateLoadErr::Invalid("queue length"));
+        }
+
+        Ok(())
+    }
+}
+
 impl<T, const N: usize> InlineQueue<T, N> {
     pub fn new() -> Self {
         Self {
-            data: std::array::from_fn(|_| None),
+            data: core::array::from_fn(|_| None),
             cur_elements: 0,
         }
     }
@@ -59,6 +79,15 @@ impl<T, const N: usize> InlineQueue<T, N> {
         self.len() == 0
     }
 
+    /// The element at `idx`, counting from the front
+    pub fn get_mut(&mut self, idx: usize) -> Option<&mut T> {
+        if idx < self.len() {
+            self.data[idx].as_mut()
+        } else {
+            None
+        }
+    }
+
     #[inline]
     pub fn push(&mut self, elem: T) -> Result<(), ()> {
         self.push_n([elem])
@@ -92,7 +121,7 @@ impl<T, const N: usize> InlineQueue<T, N> {
             return Err(());
         }
 
-        let buf: [T; I] = std::array::from_fn(|i| self.data[i].take().unwrap());
+        let buf: [T; I] = core::array::from_fn(|i| self.data[i].take().unwrap());
 
         for i in I..self.len() {
             self.data[i - I] = self.data[i].take();
@@ -193,6 +222,19 @@ mod tests {
         });
     }
 
+    #[test]
+    fn get_mut() {
+        let mut x = InlineQueue::<u8, 16>::new();
+
+        x.push_n([1, 2, 3]).unwrap();
+        x.pop().unwrap();
+
+        *x.get_mut(1).unwrap() = 4;
+
+        assert!(x.get_mut(2).is_none());
+        assert_eq!([2, 4], x.pop_n::<2>().unwrap());
+    }
+
     #[test]
     fn space_remaining_ok() {
         let mut x = InlineQueue::<u8, 16>::new();
@@ -6,21 +6,28 @@ use palette::Palette;
 use thiserror::Error;
 
 use crate::{
+    color::DmgPalette,
     extern_traits::{Frame, GBAllocator, GBGraphicsDrawer, RomReader, FRAME_X, FRAME_Y},
-    memcontroller::{MemController, ReadError, OAM_START},
-    GbColorID,
+    logging::{trace, Subsystem},
+    memcontroller::{io::IoRegs, MemController, ReadError, OAM_START},
+    savestate::{state_data, StateData, StateLoadErr, StateReader, StateWriter},
+    sgb::SgbBorder,
+    GbColorID, GbMonoColor,
 };
 
 mod fetcher;
 mod inlinequeue;
 mod objectdata;
 pub mod palette;
+mod scanline;
 mod tile;
 mod tilemap;
+pub mod view;
 
 const OAM_CYCLES: usize = 80;
 const SCANLINE_CYCLES: usize = 456;
-pub const FRAME_CYCLES: usize = SCANLINE_CYCLES * (FRAME_Y + 8);
+/// 144 visible lines plus 10 lines of VBlank
+pub const FRAME_CYCLES: usize = SCANLINE_CYCLES * (FRAME_Y + 10);
 
 #[derive(Debug, Clone)]
 enum PpuMode {
@@ -38,6 +45,60 @@ enum PpuMode {
     Draw(DrawData),
 }
 
+impl StateData for PpuMode {
+    fn save_state(&self, w: &mut StateWriter) {
+        match self {
+            PpuMode::Inactive => w.write_u8(0),
+            PpuMode::HBlank => w.write_u8(1),
+            PpuMode::VBlank => w.write_u8(2),
+            PpuMode::OAMScan(data) => {
+                w.write_u8(3);
+                data.save_state(w);
+            }
+            PpuMode::Draw(data) => {
+                w.write_u8(4);
+                data.save_state(w);
+            }
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        *self = match r.read_u8()? {
+            0 => PpuMode::Inactive,
+            1 => PpuMode::HBlank,
+            2 => PpuMode::VBlank,
+            3 => {
+                let mut data = OAMScanData::new();
+                data.load_state(r)?;
+
+                if data.num_in_buf > 10 {
+                    return Err(StateLoadErr::Invalid("OAM scan buffer"));
+                }
+
+                PpuMode::OAMScan(data)
+            }
+            4 => {
+                let mut data = DrawData {
+                    pix_to_discard: 0,
+                    pushed_pixels: 0,
+                    num_in_buf: 0,
+                    buffer: [ObjectData::default(); 10],
+                };
+                data.load_state(r)?;
+
+                if data.num_in_buf > 10 {
+                    return Err(StateLoadErr::Invalid("object buffer"));
+                }
+
+                PpuMode::Draw(data)
+            }
+            _ => return Err(StateLoadErr::Invalid("PPU mode")),
+        };
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
 struct OAMScanData {
     buffer: [ObjectData; 10],
@@ -47,6 +108,14 @@ struct OAMScanData {
     window_check_done: bool,
 }
 
+state_data!(OAMScanData {
+    buffer,
+    num_in_buf,
+    cur_obj_index,
+    cycles_left,
+    window_check_done
+});
+
 impl OAMScanData {
     pub fn new() -> Self {
         Self {
@@ -67,9 +136,20 @@ struct DrawData {
     buffer: [ObjectData; 10],
 }
 
+state_data!(DrawData {
+    pix_to_discard,
+    pushed_pixels,
+    num_in_buf,
+    buffer
+});
+
 impl DrawData {
     pub fn new(obj_buffer: [ObjectData; 10], num_in_buf: u8, to_discard: u8) -> Self {
-        log::trace!("Starting line draw, discarding {} pixels", to_discard);
+        trace!(
+            Subsystem::Ppu,
+            "Starting line draw, discarding {} pixels",
+            to_discard
+        );
         Self {
             pix_to_discard: to_discard,
             pushed_pixels: 0,
@@ -81,6 +161,92 @@ impl DrawData {
 
 const NUM_OAM_OBJECTS: u8 = 40;
 
+/// What the PPU is doing, as seen from outside
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LcdMode {
+    /// The LCD is turned off
+    Off,
+
+    /// Mode 0
+    HBlank,
+
+    /// Mode 1
+    VBlank,
+
+    /// Mode 2
+    OamScan,
+
+    /// Mode 3
+    Draw,
+}
+
+/// The values of the LCD registers, 0xFF40 to 0xFF4B, at some moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LcdRegisters {
+    pub lcdc: u8,
+    pub stat: u8,
+    pub scy: u8,
+    pub scx: u8,
+    pub ly: u8,
+    pub lyc: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    pub wy: u8,
+    pub wx: u8,
+}
+
+impl LcdRegisters {
+    pub(crate) fn from_io(io: &IoRegs) -> Self {
+        Self {
+            lcdc: io.lcd_control.into(),
+            stat: io.stat(),
+            scy: io.scy,
+            scx: io.scx,
+            ly: io.lcd_y,
+            lyc: io.lcd_y_comp,
+            bgp: io.bg_palette.into(),
+            obp0: io.obj0_palette.into(),
+            obp1: io.obj1_palette.into(),
+            wy: io.win_y,
+            wx: io.win_x,
+        }
+    }
+}
+
+/// How the PPU draws the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    /// Pixel by pixel through the pixel FIFOs, like the hardware does. Shows
+    /// changes to the registers in the middle of a line.
+    #[default]
+    Fifo,
+
+    /// Each line at once, when drawing it ends. Much faster, but changes in
+    /// the middle of a line only show on the next one, and mode 3 always
+    /// takes as long.
+    Scanline,
+}
+
+/// Which frames the PPU skips drawing, to save work when the host can't keep
+/// up. Skipped frames run with the timing of [RenderMode::Scanline] and
+/// raise the same interrupts, but nothing is drawn and the previous frame
+/// is shown for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Frameskip {
+    /// Every frame is drawn
+    #[default]
+    Off,
+
+    /// After every drawn frame, the given amount is skipped
+    Fixed(u8),
+
+    /// Skips the frames that [crate::Ruboy::step] runs besides the last,
+    /// which are never shown when the host can't call it once per frame. At
+    /// most `max` frames are skipped in a row.
+    Auto { max: u8 },
+}
+
 #[derive(Debug, Error)]
 pub enum PpuErr<V: GBGraphicsDrawer> {
     #[error("Error during HBlank: {0}")]
@@ -115,6 +281,9 @@ pub enum OAMScanErr {
 pub enum DrawErr {
     #[error("Error during pixel fetcher cycle: {0}")]
     Fetcher(#[from] FetcherErr),
+
+    #[error("Error during memory read: {0}")]
+    MemRead(#[from] ReadError),
 }
 
 #[derive(Debug)]
@@ -125,6 +294,16 @@ pub struct Ppu<V: GBGraphicsDrawer> {
     line_data: LineData,
     frame_data: FrameData,
     pix_fetcher: PixelFetcher,
+    render_mode: RenderMode,
+
+    /// Whether the frame being drawn is skipped, and whether the next one
+    /// is. See [Frameskip].
+    skipping: bool,
+    skip_next: bool,
+
+    /// Amount of frames completed. Incremented when VBlank starts, at which
+    /// point `framebuf` holds the complete frame
+    frames: u64,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -133,6 +312,8 @@ struct LineData {
     cur_cycle: usize,
 }
 
+state_data!(LineData { cur_cycle });
+
 impl LineData {
     pub fn new() -> Self {
         LineData { cur_cycle: 0 }
@@ -150,6 +331,11 @@ struct FrameData {
     win_y_reached: bool,
 }
 
+state_data!(FrameData {
+    cur_cycle,
+    win_y_reached
+});
+
 impl FrameData {
     pub fn new() -> Self {
         FrameData {
@@ -159,6 +345,60 @@ impl FrameData {
     }
 }
 
+impl StateData for GbColorID {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(*self as u8);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        *self = GbColorID::try_from(r.read_u8()?).map_err(|_| StateLoadErr::Invalid("color"))?;
+        Ok(())
+    }
+}
+
+impl StateData for Frame {
+    fn save_state(&self, w: &mut StateWriter) {
+        for pix in self.get_raw() {
+            w.write_u8(*pix as u8);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        for pix in self.get_raw_mut() {
+            *pix = match r.read_u8()? {
+                0 => GbMonoColor::White,
+                1 => GbMonoColor::LightGray,
+                2 => GbMonoColor::DarkGray,
+                3 => GbMonoColor::Black,
+                _ => return Err(StateLoadErr::Invalid("pixel")),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+// The output is owned by the frontend, and not part of the state
+impl<V: GBGraphicsDrawer> StateData for Ppu<V> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.mode.save_state(w);
+        self.framebuf.save_state(w);
+        self.line_data.save_state(w);
+        self.frame_data.save_state(w);
+        self.pix_fetcher.save_state(w);
+        self.frames.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        self.mode.load_state(r)?;
+        self.framebuf.load_state(r)?;
+        self.line_data.load_state(r)?;
+        self.frame_data.load_state(r)?;
+        self.pix_fetcher.load_state(r)?;
+        self.frames.load_state(r)
+    }
+}
+
 impl<V: GBGraphicsDrawer> Ppu<V> {
     pub fn new(output: V) -> Self {
         Self {
@@ -168,9 +408,85 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
             line_data: LineData::new(),
             frame_data: FrameData::new(),
             pix_fetcher: PixelFetcher::new(),
+            render_mode: RenderMode::default(),
+            skipping: false,
+            skip_next: false,
+            frames: 0,
+        }
+    }
+
+    /// Stops drawing and blanks the frame, as at power on. The output,
+    /// palette, render mode, frameskip and frame count are kept.
+    pub fn reset(&mut self) {
+        let palette = *self.framebuf.palette();
+
+        self.mode = PpuMode::Inactive;
+        self.framebuf = Frame::default();
+        self.framebuf.set_palette(palette);
+        self.line_data = LineData::new();
+        self.frame_data = FrameData::new();
+        self.pix_fetcher = PixelFetcher::new();
+        self.skipping = false;
+    }
+
+    pub const fn lcd_mode(&self) -> LcdMode {
+        match self.mode {
+            PpuMode::Inactive => LcdMode::Off,
+            PpuMode::HBlank => LcdMode::HBlank,
+            PpuMode::VBlank => LcdMode::VBlank,
+            PpuMode::OAMScan(_) => LcdMode::OamScan,
+            PpuMode::Draw(_) => LcdMode::Draw,
         }
     }
 
+    pub const fn render_mode(&self) -> RenderMode {
+        self.render_mode
+    }
+
+    /// Switches how lines are drawn, from the next line on
+    pub fn set_render_mode(&mut self, mode: RenderMode) {
+        self.render_mode = mode;
+    }
+
+    /// Skips drawing the next frame, or not. Applies to the frame after the
+    /// one being drawn, so call it once a frame is completed.
+    pub fn set_skip_next(&mut self, skip: bool) {
+        self.skip_next = skip;
+    }
+
+    /// Whether drawing the current frame is skipped
+    pub const fn skipping(&self) -> bool {
+        self.skipping
+    }
+
+    /// The last drawn frame. Only complete right after
+    /// [Ppu::frame_count] increased
+    pub const fn frame(&self) -> &Frame {
+        &self.framebuf
+    }
+
+    /// The amount of frames completed since power on
+    pub const fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    pub fn set_palette(&mut self, palette: DmgPalette) {
+        self.framebuf.set_palette(palette);
+    }
+
+    pub(crate) fn frame_mut(&mut self) -> &mut Frame {
+        &mut self.framebuf
+    }
+
+    pub fn output_border(&mut self, border: SgbBorder<'_>) -> Result<(), V::Err> {
+        self.output.border(border)
+    }
+
+    /// Sends the current frame buffer to the output again
+    pub fn present(&mut self) -> Result<(), V::Err> {
+        self.output.output(&self.framebuf)
+    }
+
     fn sync_active_state(&mut self, mem: &mut MemController<impl GBAllocator, impl RomReader>) {
         let should_be_active = mem.io_registers.lcd_control.lcd_ppu_enable();
         let is_active = !matches!(self.mode, PpuMode::Inactive);
@@ -178,7 +494,9 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
         if should_be_active && !is_active {
             log::info!("Turning PPU on");
 
-            self.mode = PpuMode::OAMScan(OAMScanData::new())
+            mem.oam_open = false;
+            self.mode = PpuMode::OAMScan(OAMScanData::new());
+            mem.io_registers.lcd_stat.set_mode(LcdMode::OamScan);
         } else if !should_be_active && is_active {
             log::info!("Turning PPU off");
 
@@ -187,6 +505,9 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
             self.frame_data = FrameData::new();
             self.pix_fetcher = PixelFetcher::new();
             mem.io_registers.lcd_y = 0;
+            mem.io_registers.lcd_stat.set_mode(LcdMode::Off);
+            mem.vram_open = true;
+            mem.oam_open = true;
         }
     }
 
@@ -215,7 +536,8 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
             debug_assert_eq!(OAM_CYCLES + 1, self.line_data.cur_cycle);
             // Operation complete. If no more objects need to be scanned, go to next
             // phase
-            log::trace!(
+            trace!(
+                Subsystem::Ppu,
                 "OAM scan done, entering Draw mode. Found {} objects",
                 data.num_in_buf
             );
@@ -230,9 +552,9 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
         }
 
         if data.num_in_buf < 10 {
-            log::trace!("OAM Scanning object {}", data.cur_obj_index);
+            trace!(Subsystem::Ppu, "OAM Scanning object {}", data.cur_obj_index);
 
-            let obj_data_raw: [u8; 4] = mem.read_range(
+            let obj_data_raw: [u8; 4] = mem.read_range_direct(
                 OAM_START + (size_of::<ObjectData>() as u16 * data.cur_obj_index as u16),
             )?;
 
@@ -248,17 +570,24 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
                 && (ly as i16) < obj_data.offset_ypos() + obj_height; // bottom below current line
 
             if ypos_ok {
-                log::trace!("Adding object {} to buffer", data.cur_obj_index);
+                trace!(
+                    Subsystem::Ppu,
+                    "Adding object {} to buffer",
+                    data.cur_obj_index
+                );
 
                 data.buffer[data.num_in_buf as usize] = obj_data;
                 data.num_in_buf += 1;
 
                 if data.num_in_buf == 10 {
-                    log::trace!("Object buffer full, not adding any more");
+                    trace!(Subsystem::Ppu, "Object buffer full, not adding any more");
                 }
             }
         } else {
-            log::trace!("Cannot add more object to buffer in OAM scan, continuing");
+            trace!(
+                Subsystem::Ppu,
+                "Cannot add more object to buffer in OAM scan, continuing"
+            );
         }
 
         data.cur_obj_index += 1;
@@ -268,8 +597,17 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
         Ok(())
     }
 
+    /// Removes the object to fetch at pixel `x` from the buffer, if any. Of
+    /// the objects starting at or before `x`, the one with the lowest X
+    /// comes first, and then the one that comes first in OAM, like the buffer
+    /// is ordered. Objects fetched first win where they overlap later ones.
     fn get_obj_at_x(objs: &mut [ObjectData], x: u8) -> Option<ObjectData> {
-        let idx = objs.iter().position(|obj| obj.offset_xpos() <= x as i16);
+        let idx = objs
+            .iter()
+            .enumerate()
+            .filter(|(_, obj)| obj.offset_xpos() <= x as i16)
+            .min_by_key(|(_, obj)| obj.x_pos())
+            .map(|(idx, _)| idx);
 
         match idx {
             Some(idx) => {
@@ -292,15 +630,34 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
         &mut self,
         mem: &mut MemController<impl GBAllocator, impl RomReader>,
     ) -> Result<(), DrawErr> {
+        if self.render_mode == RenderMode::Scanline || self.skipping {
+            return self.draw_scanline(mem);
+        }
+
         let data = match &mut self.mode {
             PpuMode::Draw(data) => data,
             _ => panic!("Invalid mode for drawing!"),
         };
 
-        self.pix_fetcher.run_cycle(mem, false)?;
+        let lcd_control = mem.io_registers.lcd_control;
+
+        // The window takes over once its left edge is reached, and skips its
+        // pixels left of the screen
+        if !self.pix_fetcher.in_window()
+            && !self.pix_fetcher.is_fetching_obj()
+            && lcd_control.window_enable()
+            && lcd_control.bg_win_enable()
+            && self.frame_data.win_y_reached
+            && data.pushed_pixels as u16 + 7 >= mem.io_registers.win_x as u16
+        {
+            self.pix_fetcher.start_window();
+            data.pix_to_discard = 7_u8.saturating_sub(mem.io_registers.win_x);
+        }
+
+        self.pix_fetcher.run_cycle(mem)?;
 
         // Find out if there's an object at the current x we need to fetch
-        if !self.pix_fetcher.is_fetching_obj() {
+        if !self.pix_fetcher.is_fetching_obj() && lcd_control.obj_enable() {
             let found_obj = Self::get_obj_at_x(
                 &mut data.buffer[..data.num_in_buf as usize],
                 data.pushed_pixels,
@@ -327,8 +684,16 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
                 data.pix_to_discard -= 1;
             } else {
                 let bg_palette = Palette::load_bg(mem);
-                let bg_pix = self.pix_fetcher.get_bg_fifo_mut().pop().unwrap();
-                let bg_color = bg_palette.make_color(bg_pix);
+                let mut bg_pix = self.pix_fetcher.get_bg_fifo_mut().pop().unwrap();
+                let mut bg_color = bg_palette.make_color(bg_pix);
+
+                // With the background and window off, they are blank and
+                // objects always show over them
+                if !lcd_control.bg_win_enable() {
+                    bg_pix = GbColorID::ID0;
+                    bg_color = GbMonoColor::White;
+                }
+
                 let obj_pix = self.pix_fetcher.get_obj_fifo_mut().pop();
 
                 let color = if let Ok(obj_pix) = obj_pix {
@@ -359,6 +724,47 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
         Ok(())
     }
 
+    fn draw_scanline(
+        &mut self,
+        mem: &mut MemController<impl GBAllocator, impl RomReader>,
+    ) -> Result<(), DrawErr> {
+        let data = match &self.mode {
+            PpuMode::Draw(data) => data,
+            _ => panic!("Invalid mode for drawing!"),
+        };
+
+        if self.line_data.cur_cycle <= OAM_CYCLES + scanline::DRAW_CYCLES {
+            return Ok(());
+        }
+
+        let lcd_control = mem.io_registers.lcd_control;
+        let window = lcd_control.window_enable()
+            && lcd_control.bg_win_enable()
+            && self.frame_data.win_y_reached
+            && (mem.io_registers.win_x as usize) < FRAME_X + 7;
+
+        if !self.skipping {
+            scanline::render_line(
+                &mut self.framebuf,
+                mem,
+                &data.buffer[..data.num_in_buf as usize],
+                window.then(|| self.pix_fetcher.window_line()),
+            )?;
+        }
+
+        // The fetcher keeps the window line count, and moves on to the next
+        // line of the window at HBlank
+        if window {
+            self.pix_fetcher.start_window();
+        }
+
+        mem.vram_open = true;
+        mem.oam_open = true;
+        self.mode = PpuMode::HBlank;
+
+        Ok(())
+    }
+
     fn hblank(
         &mut self,
         mem: &mut MemController<impl GBAllocator, impl RomReader>,
@@ -371,6 +777,7 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
 
             if mem.io_registers.lcd_y as usize == FRAME_Y {
                 self.mode = PpuMode::VBlank;
+                self.frames += 1;
                 mem.io_registers.interrupts_requested.set_vblank(true);
             } else {
                 mem.oam_open = false;
@@ -392,9 +799,13 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
             if mem.io_registers.lcd_y as usize == (FRAME_Y + 10) {
                 mem.io_registers.lcd_y = 0;
 
-                self.output
-                    .output(&self.framebuf)
-                    .map_err(|e| VBlankErr::<V>::OutputErr(e))?;
+                if !self.skipping {
+                    self.output
+                        .output(&self.framebuf)
+                        .map_err(|e| VBlankErr::<V>::OutputErr(e))?;
+                }
+
+                self.skipping = self.skip_next;
 
                 self.frame_data = FrameData::new();
 
@@ -408,6 +819,55 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
         Ok(())
     }
 
+    /// Runs up to `cycles` cycles, the same as calling [Ppu::run_cycle] that
+    /// many times without anything else touching memory in between. Spans in
+    /// which the PPU only waits, like the rest of HBlank, are skipped over at
+    /// once. Stops right after a frame is completed, and returns the amount
+    /// of cycles ran.
+    pub fn run_cycles(
+        &mut self,
+        mem: &mut MemController<impl GBAllocator, impl RomReader>,
+        cycles: usize,
+    ) -> Result<usize, PpuErr<V>> {
+        // Nothing else changes the LCD control while this runs
+        self.sync_active_state(mem);
+
+        if matches!(self.mode, PpuMode::Inactive) {
+            return Ok(cycles);
+        }
+
+        let frames = self.frames;
+        let mut ran = 0;
+
+        while ran < cycles && self.frames == frames {
+            let idle = self.idle_cycles().min(cycles - ran);
+
+            if idle > 0 {
+                self.line_data.cur_cycle += idle;
+                self.frame_data.cur_cycle += idle;
+                ran += idle;
+            } else {
+                self.run_cycle(mem)?;
+                ran += 1;
+            }
+        }
+
+        Ok(ran)
+    }
+
+    /// The amount of upcoming cycles in which the PPU does nothing but count
+    fn idle_cycles(&self) -> usize {
+        let until = |end: usize| end.saturating_sub(self.line_data.cur_cycle + 1);
+
+        match self.mode {
+            PpuMode::HBlank | PpuMode::VBlank => until(SCANLINE_CYCLES),
+            PpuMode::Draw(_) if self.render_mode == RenderMode::Scanline || self.skipping => {
+                until(OAM_CYCLES + scanline::DRAW_CYCLES + 1)
+            }
+            _ => 0,
+        }
+    }
+
     pub fn run_cycle(
         &mut self,
         mem: &mut MemController<impl GBAllocator, impl RomReader>,
@@ -427,6 +887,8 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
             PpuMode::Draw(_) => self.draw(mem)?,
         }
 
+        mem.io_registers.lcd_stat.set_mode(self.lcd_mode());
+
         debug_assert!((mem.io_registers.lcd_y as usize) < (FRAME_Y + 10));
 
         if mem.io_registers.lcd_y as usize >= FRAME_Y {
@@ -436,3 +898,315 @@ impl<V: GBGraphicsDrawer> Ppu<V> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::testutils::{blank_rom, NullInput, NullOutput, TestRuboy};
+    use crate::{FrameEvent, GbModel, Ruboy};
+
+    fn obj(x: u8, tile: u8) -> ObjectData {
+        ObjectData::from([16, x, tile, 0])
+    }
+
+    #[test]
+    fn lowest_x_fetched_first() {
+        let mut objs = [obj(30, 0), obj(10, 1), obj(20, 2)];
+
+        let found = Ppu::<NullOutput>::get_obj_at_x(&mut objs, 20).unwrap();
+
+        assert_eq!(1, found.tilenum());
+        assert_eq!([0, 2], [objs[0].tilenum(), objs[1].tilenum()]);
+    }
+
+    #[test]
+    fn same_x_fetched_in_oam_order() {
+        let mut objs = [obj(9, 0), obj(9, 1)];
+
+        let found = Ppu::<NullOutput>::get_obj_at_x(&mut objs, 1).unwrap();
+
+        assert_eq!(0, found.tilenum());
+    }
+
+    #[test]
+    fn no_obj_before_x() {
+        let mut objs = [obj(30, 0)];
+
+        assert!(Ppu::<NullOutput>::get_obj_at_x(&mut objs, 21).is_none());
+    }
+
+    /// Runs a DMG with a ROM that loops forever, with tile 1 in color 1 and
+    /// tile 2 in color 2, and returns the shades of the second frame, from 0
+    /// for white to 3 for black. `setup` runs while the LCD is off.
+    fn draw_frame(lcdc: u8, setup: impl FnOnce(&mut TestRuboy)) -> Vec<Vec<u8>> {
+        draw_frame_in(RenderMode::Fifo, lcdc, setup)
+    }
+
+    fn draw_frame_in(
+        mode: RenderMode,
+        lcdc: u8,
+        setup: impl FnOnce(&mut TestRuboy),
+    ) -> Vec<Vec<u8>> {
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // jr -2
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+
+        ruboy.set_render_mode(mode);
+        ruboy.write_memory(0xFF40, &[0x00]).unwrap();
+        ruboy.write_memory(0x8010, &[0xFF, 0x00].repeat(8)).unwrap();
+        ruboy.write_memory(0x8020, &[0x00, 0xFF].repeat(8)).unwrap();
+
+        setup(&mut ruboy);
+
+        // Identity palettes, then the LCD on
+        ruboy.write_memory(0xFF47, &[0xE4, 0xE4, 0xE4]).unwrap();
+        ruboy.write_memory(0xFF40, &[lcdc]).unwrap();
+
+        ruboy.step_frame().unwrap();
+
+        match ruboy.step_frame().unwrap() {
+            FrameEvent::Completed(frame) => (0..FRAME_Y as u8)
+                .map(|y| {
+                    (0..FRAME_X as u8)
+                        .map(|x| frame.get_pix(x, y).unwrap() as u8)
+                        .collect()
+                })
+                .collect(),
+            _ => panic!("No frame drawn"),
+        }
+    }
+
+    fn write_oam(ruboy: &mut TestRuboy, objs: &[[u8; 4]]) {
+        for (idx, obj) in objs.iter().enumerate() {
+            ruboy.write_memory(OAM_START + idx as u16 * 4, obj).unwrap();
+        }
+    }
+
+    /// The top line of a frame with the given objects and 8x8 objects on
+    fn draw_objects(objs: &[ObjectData]) -> Vec<u8> {
+        let objs: Vec<[u8; 4]> = objs
+            .iter()
+            .map(|obj| [obj.y_pos(), obj.x_pos(), obj.tilenum(), 0])
+            .collect();
+
+        draw_frame(0x83, |ruboy| write_oam(ruboy, &objs)).swap_remove(0)
+    }
+
+    #[test]
+    fn overlapping_objects() {
+        // Lower X wins over lower OAM index, and then lower OAM index wins.
+        // Both objects at the left edge are due at the first pixel.
+        let line = draw_objects(&[
+            obj(4, 2),
+            obj(2, 1),
+            obj(20, 2),
+            obj(16, 1),
+            obj(40, 1),
+            obj(40, 2),
+        ]);
+
+        assert_eq!([1, 1, 2, 2], line[..4]);
+        assert_eq!([0; 4], line[4..8]);
+        assert_eq!([1; 8], line[8..16]);
+        assert_eq!([2; 4], line[16..20]);
+        assert_eq!([1; 8], line[32..40]);
+        assert_eq!(0, line[40]);
+    }
+
+    #[test]
+    fn ten_objects_per_line() {
+        let objs: Vec<ObjectData> = (0..11).map(|i| obj(8 + i * 8, 2)).collect();
+        let line = draw_objects(&objs);
+
+        assert_eq!(2, line[79]);
+        assert_eq!(0, line[80]);
+    }
+
+    #[test]
+    fn window() {
+        // Window at (10, 2) from the map at 0x9C00, filled with tile 1
+        let frame = draw_frame(0xF1, |ruboy| {
+            ruboy.write_memory(0x9C00, &[0x01; 0x400]).unwrap();
+            ruboy.write_memory(0xFF4A, &[2, 17]).unwrap();
+        });
+
+        assert_eq!([0; FRAME_X], frame[1][..]);
+        assert_eq!([0; 10], frame[2][..10]);
+        assert_eq!([1; FRAME_X - 10], frame[2][10..]);
+        assert_eq!([1; FRAME_X - 10], frame[FRAME_Y - 1][10..]);
+    }
+
+    #[test]
+    fn tall_objects() {
+        // Tile 3 selects tiles 2 and 3. The second object is flipped.
+        let frame = draw_frame(0x87, |ruboy| {
+            ruboy.write_memory(0x8030, &[0xFF, 0x00].repeat(8)).unwrap();
+            write_oam(ruboy, &[[16, 8, 3, 0x00], [16, 16, 3, 0x40]]);
+        });
+
+        assert_eq!([2; 8], frame[0][..8]);
+        assert_eq!([1; 8], frame[15][..8]);
+        assert_eq!([1; 8], frame[0][8..16]);
+        assert_eq!([2; 8], frame[15][8..16]);
+        assert_eq!(0, frame[16][0]);
+    }
+
+    #[test]
+    fn flipped_objects() {
+        // Tile 3 is color 1 on the top half and color 2 on the bottom
+        let setup = |ruboy: &mut TestRuboy| {
+            let tile = [[0xFF, 0x00].repeat(4), [0x00, 0xFF].repeat(4)].concat();
+            ruboy.write_memory(0x8030, &tile).unwrap();
+            write_oam(ruboy, &[[16, 8, 3, 0x00], [16, 16, 3, 0x40]]);
+        };
+
+        for mode in [RenderMode::Fifo, RenderMode::Scanline] {
+            let frame = draw_frame_in(mode, 0x83, setup);
+
+            assert_eq!([1; 8], frame[0][..8], "{:?}", mode);
+            assert_eq!([2; 8], frame[7][..8], "{:?}", mode);
+            assert_eq!([2; 8], frame[0][8..16], "{:?}", mode);
+            assert_eq!([1; 8], frame[7][8..16], "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn objects_disabled() {
+        let frame = draw_frame(0x81, |ruboy| write_oam(ruboy, &[[16, 8, 1, 0]]));
+
+        assert_eq!([0; 8], frame[0][..8]);
+    }
+
+    #[test]
+    fn stat_follows_the_mode() {
+        let mut rom = blank_rom(0x3C).into_inner();
+        rom[0x100..0x102].copy_from_slice(&[0x18, 0xFE]); // jr -2
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+
+        let mut seen = [false; 4];
+
+        for _ in 0..FRAME_CYCLES {
+            ruboy.run_cycles(1).unwrap();
+
+            let expected = match ruboy.ppu.lcd_mode() {
+                LcdMode::Off | LcdMode::HBlank => 0,
+                LcdMode::VBlank => 1,
+                LcdMode::OamScan => 2,
+                LcdMode::Draw => 3,
+            };
+
+            let stat = ruboy.mem.read8(0xFF41).unwrap();
+            assert_eq!(expected, stat & 0b11);
+            seen[expected as usize] = true;
+        }
+
+        assert_eq!([true; 4], seen);
+
+        ruboy.write_memory(0xFF40, &[0x00]).unwrap();
+        ruboy.run_cycles(1).unwrap();
+        assert_eq!(0, ruboy.mem.read8(0xFF41).unwrap() & 0b11);
+    }
+
+    #[test]
+    fn scanline_matches_fifo() {
+        let setup = |ruboy: &mut TestRuboy| {
+            // Tile 3 is color 1 on the left half and color 2 on the right
+            ruboy.write_memory(0x8030, &[0xF0, 0x0F].repeat(8)).unwrap();
+
+            let bg: Vec<u8> = (0..0x400).map(|i| (i % 7 % 4) as u8).collect();
+            ruboy.write_memory(0x9800, &bg).unwrap();
+            ruboy.write_memory(0x9C00, &[0x02; 0x400]).unwrap();
+
+            // Scrolled background, and the window at (43, 40)
+            ruboy.write_memory(0xFF42, &[5, 3]).unwrap();
+            ruboy.write_memory(0xFF4A, &[40, 50]).unwrap();
+
+            write_oam(
+                ruboy,
+                &[
+                    [16, 4, 3, 0x00],
+                    [20, 6, 1, 0x00],
+                    [30, 30, 3, 0x20],
+                    [34, 26, 3, 0x40],
+                    [60, 80, 1, 0x80],
+                    [70, 120, 3, 0x10],
+                ],
+            );
+        };
+
+        let fifo = draw_frame_in(RenderMode::Fifo, 0xF3, setup);
+
+        assert_eq!([2; 8], fifo[FRAME_Y - 1][FRAME_X - 8..]);
+        assert_eq!(fifo, draw_frame_in(RenderMode::Scanline, 0xF3, setup));
+    }
+
+    /// Runs dmg-acid2 and compares its output with the reference image. Set
+    /// `RUBOY_DMG_ACID2` to a directory with `dmg-acid2.gb` and
+    /// `dmg-acid2-dmg.png`, from https://github.com/mattcurrie/dmg-acid2
+    #[test]
+    #[ignore = "needs the dmg-acid2 ROM and reference image"]
+    fn dmg_acid2() {
+        use std::path::PathBuf;
+
+        let dir = PathBuf::from(std::env::var("RUBOY_DMG_ACID2").expect("RUBOY_DMG_ACID2 not set"));
+        let rom = std::fs::read(dir.join("dmg-acid2.gb")).unwrap();
+
+        let mut decoder =
+            png::Decoder::new(std::fs::File::open(dir.join("dmg-acid2-dmg.png")).unwrap());
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder.read_info().unwrap();
+        let mut image = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut image).unwrap();
+        let channels = info.color_type.samples();
+
+        let mut expected = Frame::default();
+
+        for y in 0..FRAME_Y {
+            for x in 0..FRAME_X {
+                let luma = image[(y * FRAME_X + x) * channels];
+                let shade = match luma {
+                    0xC0..=0xFF => GbMonoColor::White,
+                    0x80..=0xBF => GbMonoColor::LightGray,
+                    0x40..=0x7F => GbMonoColor::DarkGray,
+                    _ => GbMonoColor::Black,
+                };
+
+                expected.set_pix(x as u8, y as u8, shade);
+            }
+        }
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+
+        // The test draws its final image within a few frames, and then halts
+        let mut last = None;
+
+        for _ in 0..60 {
+            if let FrameEvent::Completed(frame) = ruboy.step_frame().unwrap() {
+                last = Some(frame.clone());
+            }
+        }
+
+        let actual = last.expect("No frame drawn");
+
+        let mismatches = (0..FRAME_Y as u8)
+            .flat_map(|y| (0..FRAME_X as u8).map(move |x| (x, y)))
+            .filter(|(x, y)| {
+                actual.get_pix(*x, *y).map(|c| c as u8) != expected.get_pix(*x, *y).map(|c| c as u8)
+            })
+            .count();
+
+        assert_eq!(
+            expected.digest(),
+            actual.digest(),
+            "{} pixels differ from the reference",
+            mismatches
+        );
+    }
+}
@@ -1,11 +1,15 @@
 use core::mem::size_of;
 
+use crate::savestate::state_data;
+
 use super::palette::PaletteID;
 
 #[derive(Debug, Clone, Copy, Default)]
 #[repr(transparent)]
 pub struct ObjectData([u8; 4]);
 
+state_data!(ObjectData { 0 });
+
 impl ObjectData {
     pub const fn y_pos(self) -> u8 {
         self.0[0]
@@ -82,7 +86,7 @@ impl From<ObjDataFlags> for u8 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::mem::size_of;
+    use core::mem::size_of;
 
     #[test]
     fn object_data_size() {
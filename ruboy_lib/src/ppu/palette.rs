@@ -1,17 +1,38 @@
-use crate::{memcontroller::MemController, GBAllocator, GbColorID, GbMonoColor, RomReader};
+use crate::{
+    memcontroller::MemController,
+    savestate::{state_data, StateData, StateLoadErr, StateReader, StateWriter},
+    GBAllocator, GbColorID, GbMonoColor, RomReader,
+};
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum PaletteID {
+    #[default]
     Zero,
     One,
 }
 
+impl StateData for PaletteID {
+    fn save_state(&self, w: &mut StateWriter) {
+        matches!(self, PaletteID::One).save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        let mut one = false;
+        one.load_state(r)?;
+
+        *self = if one { PaletteID::One } else { PaletteID::Zero };
+        Ok(())
+    }
+}
+
 #[repr(transparent)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Palette {
     val: u8,
 }
 
+state_data!(Palette { val });
+
 impl Palette {
     pub fn new() -> Self {
         Self::default()
@@ -32,7 +32,7 @@ impl From<[u8; size_of::<Tile>()]> for Tile {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::mem::size_of;
+    use core::mem::size_of;
 
     #[test]
     fn tile_size() {
@@ -20,7 +20,7 @@ pub fn calc_offset(x: u8, y: u8) -> u16 {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::mem::size_of;
+    use core::mem::size_of;
 
     #[test]
     fn tilemap_size() {
@@ -0,0 +1,222 @@
+//! Renders PPU memory into images for debugging views, independent of what
+//! is currently being drawn to the screen
+
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+use crate::{
+    debug::{DebugImage, OamEntry, TileMapArea, VramLayout},
+    extern_traits::{GBAllocator, RomReader},
+    memcontroller::{MemController, ReadError, OAM_START, VRAM_START},
+};
+
+use super::{
+    fetcher::{addr_from_tile_idx, combine_pixdata},
+    objectdata::ObjectData,
+    palette::{Palette, PaletteID},
+    tile::Tile,
+    tilemap,
+};
+
+const TILEMAP_TILES: usize = 32;
+const OAM_VIEW_COLUMNS: usize = 8;
+const OAM_VIEW_ROWS: usize = super::NUM_OAM_OBJECTS as usize / OAM_VIEW_COLUMNS;
+
+/// Tile data holds 3 blocks of 128 tiles
+const TILE_DATA_TILES: usize = 384;
+const TILE_DATA_COLUMNS: usize = 16;
+
+fn read_tile(
+    tile_idx: u8,
+    addressing_mode: bool,
+    mem: &MemController<impl GBAllocator, impl RomReader>,
+) -> Result<Tile, ReadError> {
+    let tile_bytes: [u8; size_of::<Tile>()] =
+        mem.read_range_direct(addr_from_tile_idx(tile_idx, addressing_mode))?;
+
+    Ok(tile_bytes.into())
+}
+
+fn read_object(
+    obj_idx: usize,
+    mem: &MemController<impl GBAllocator, impl RomReader>,
+) -> Result<ObjectData, ReadError> {
+    let obj_raw: [u8; size_of::<ObjectData>()] =
+        mem.read_range_direct(OAM_START + (obj_idx * size_of::<ObjectData>()) as u16)?;
+
+    Ok(obj_raw.into())
+}
+
+fn draw_tile(
+    img: &mut DebugImage,
+    tile: Tile,
+    palette: Palette,
+    x_base: usize,
+    y_base: usize,
+    flip: (bool, bool),
+) {
+    for row in 0..Tile::Y_SIZE {
+        let lower = tile.get_lower_for_row(row as u8);
+        let upper = tile.get_upper_for_row(row as u8);
+
+        for col in 0..Tile::X_SIZE {
+            let color = palette.make_color(combine_pixdata(lower, upper, 7 - col));
+
+            let x = if flip.0 { Tile::X_SIZE - 1 - col } else { col };
+            let y = if flip.1 { Tile::Y_SIZE - 1 - row } else { row };
+
+            img.set_pix(x_base + x, y_base + y, color);
+        }
+    }
+}
+
+/// The full 256x256 background map in the given area, using the current
+/// background palette and tile addressing mode
+pub fn render_tilemap(
+    area: TileMapArea,
+    mem: &MemController<impl GBAllocator, impl RomReader>,
+) -> Result<DebugImage, ReadError> {
+    let base = area.base_addr();
+
+    let addressing_mode = mem.io_registers.lcd_control.bg_window_tile_area();
+    let palette = Palette::load_bg(mem);
+
+    let mut img = DebugImage::new(TILEMAP_TILES * Tile::X_SIZE, TILEMAP_TILES * Tile::Y_SIZE);
+
+    for y in 0..TILEMAP_TILES {
+        for x in 0..TILEMAP_TILES {
+            let tile_idx = mem.read8_direct(base + tilemap::calc_offset(x as u8, y as u8))?;
+            let tile = read_tile(tile_idx, addressing_mode, mem)?;
+
+            draw_tile(
+                &mut img,
+                tile,
+                palette,
+                x * Tile::X_SIZE,
+                y * Tile::Y_SIZE,
+                (false, false),
+            );
+        }
+    }
+
+    Ok(img)
+}
+
+/// All 40 objects in OAM, in a grid of 8 by 5 cells of 8x16 pixels. Each
+/// object is drawn with its own palette and flip flags, in the top of its
+/// cell when using 8x8 objects
+pub fn render_oam(
+    mem: &MemController<impl GBAllocator, impl RomReader>,
+) -> Result<DebugImage, ReadError> {
+    let tall = mem.io_registers.lcd_control.obj_size();
+    let cell_y = Tile::Y_SIZE * 2;
+
+    let mut img = DebugImage::new(OAM_VIEW_COLUMNS * Tile::X_SIZE, OAM_VIEW_ROWS * cell_y);
+
+    for obj_idx in 0..(super::NUM_OAM_OBJECTS as usize) {
+        let obj = read_object(obj_idx, mem)?;
+        let flags = obj.flags();
+        let palette = Palette::load_obj(flags.palette(), mem);
+        let flip = (flags.x_flip(), flags.y_flip());
+
+        let x_base = (obj_idx % OAM_VIEW_COLUMNS) * Tile::X_SIZE;
+        let y_base = (obj_idx / OAM_VIEW_COLUMNS) * cell_y;
+
+        // In 8x16 mode, the lowest bit of the tile number is ignored
+        let tiles = if tall {
+            [Some(obj.tilenum() & !1), Some(obj.tilenum() | 1)]
+        } else {
+            [Some(obj.tilenum()), None]
+        };
+
+        for (half, tile_idx) in tiles.into_iter().enumerate() {
+            let Some(tile_idx) = tile_idx else {
+                continue;
+            };
+
+            // Y-flipping an 8x16 object also swaps its two tiles
+            let half = if tall && flip.1 { 1 - half } else { half };
+
+            draw_tile(
+                &mut img,
+                read_tile(tile_idx, true, mem)?,
+                palette,
+                x_base,
+                y_base + (half * Tile::Y_SIZE),
+                flip,
+            );
+        }
+    }
+
+    Ok(img)
+}
+
+/// All 384 tiles in VRAM, in a grid of 16 tiles wide, in the order they are
+/// stored. Uses the current background palette
+pub fn render_tiles(
+    mem: &MemController<impl GBAllocator, impl RomReader>,
+) -> Result<DebugImage, ReadError> {
+    let palette = Palette::load_bg(mem);
+    let rows = TILE_DATA_TILES / TILE_DATA_COLUMNS;
+
+    let mut img = DebugImage::new(TILE_DATA_COLUMNS * Tile::X_SIZE, rows * Tile::Y_SIZE);
+
+    for tile_idx in 0..TILE_DATA_TILES {
+        let tile_bytes: [u8; size_of::<Tile>()] =
+            mem.read_range_direct(VRAM_START + (tile_idx * size_of::<Tile>()) as u16)?;
+
+        draw_tile(
+            &mut img,
+            tile_bytes.into(),
+            palette,
+            (tile_idx % TILE_DATA_COLUMNS) * Tile::X_SIZE,
+            (tile_idx / TILE_DATA_COLUMNS) * Tile::Y_SIZE,
+            (false, false),
+        );
+    }
+
+    Ok(img)
+}
+
+/// The attributes of all 40 objects in OAM
+pub fn oam_entries(
+    mem: &MemController<impl GBAllocator, impl RomReader>,
+) -> Result<Vec<OamEntry>, ReadError> {
+    (0..(super::NUM_OAM_OBJECTS as usize))
+        .map(|obj_idx| {
+            let obj = read_object(obj_idx, mem)?;
+            let flags = obj.flags();
+
+            Ok(OamEntry {
+                index: obj_idx as u8,
+                x: obj.x_pos(),
+                y: obj.y_pos(),
+                tile: obj.tilenum(),
+                palette: match flags.palette() {
+                    PaletteID::Zero => 0,
+                    PaletteID::One => 1,
+                },
+                x_flip: flags.x_flip(),
+                y_flip: flags.y_flip(),
+                behind_bg: flags.bg_win_prio(),
+            })
+        })
+        .collect()
+}
+
+pub fn vram_layout(mem: &MemController<impl GBAllocator, impl RomReader>) -> VramLayout {
+    let io = &mem.io_registers;
+    let lcdc = io.lcd_control;
+
+    VramLayout {
+        scx: io.scx,
+        scy: io.scy,
+        win_x: io.win_x,
+        win_y: io.win_y,
+        bg_tilemap: TileMapArea::from_lcdc_bit(lcdc.bg_tilemap_area()),
+        window_tilemap: TileMapArea::from_lcdc_bit(lcdc.window_tilemap_area()),
+        window_enabled: lcdc.window_enable(),
+        unsigned_tile_addressing: lcdc.bg_window_tile_area(),
+        tall_objects: lcdc.obj_size(),
+    }
+}
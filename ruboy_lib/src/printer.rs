@@ -0,0 +1,467 @@
+//! The Game Boy Printer, a thermal printer on the link port.
+//!
+//! Games talk to it in packets: the magic bytes `0x88 0x33`, a command, a
+//! compression flag, a little endian data length, the data, and a little
+//! endian checksum over everything from the command on. While the game
+//! sends two more (zero) bytes, the printer answers that it is alive
+//! (`0x81`) and its status.
+//!
+//! Image data comes in as tiles, 20 tiles to a row like the screen, and is
+//! kept until a print command hands it to the [PrintHandler] as a
+//! [Printout].
+
+use alloc::vec::Vec;
+
+use crate::color::DmgPalette;
+use crate::extern_traits::{GbMonoColor, LinkDevice};
+
+/// Width of the paper in pixels, the same as the screen
+pub const PRINTOUT_X: usize = 160;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const ALIVE: u8 = 0x81;
+
+const CMD_INIT: u8 = 0x01;
+const CMD_PRINT: u8 = 0x02;
+const CMD_DATA: u8 = 0x04;
+const CMD_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERR: u8 = 0b1;
+const STATUS_PRINTING: u8 = 0b10;
+const STATUS_DATA_FULL: u8 = 0b100;
+const STATUS_UNPROCESSED: u8 = 0b1000;
+const STATUS_PACKET_ERR: u8 = 0b10000;
+
+const TILE_BYTES: usize = 16;
+const TILES_PER_ROW: usize = PRINTOUT_X / 8;
+
+/// The printer holds at most 9 data packets of 2 tile rows, as many lines
+/// as the screen
+const BUFFER_SIZE: usize = 9 * 2 * TILES_PER_ROW * TILE_BYTES;
+
+/// How many status requests report that the printer is still printing. Games
+/// wait for printing to start and stop, but not for any amount of time.
+const PRINT_POLLS: u8 = 4;
+
+/// A printed image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Printout {
+    /// Row by row, [PRINTOUT_X] pixels wide
+    pixels: Vec<GbMonoColor>,
+}
+
+impl Printout {
+    pub fn height(&self) -> usize {
+        self.pixels.len() / PRINTOUT_X
+    }
+
+    pub fn get_raw(&self) -> &[GbMonoColor] {
+        &self.pixels
+    }
+
+    pub fn get_pix(&self, x: usize, y: usize) -> Option<GbMonoColor> {
+        if x >= PRINTOUT_X {
+            return None;
+        }
+
+        self.pixels.get((y * PRINTOUT_X) + x).copied()
+    }
+
+    /// The pixels row by row as 8-bit RGBA, in the colors of the palette.
+    /// Ready to be written to an image file.
+    pub fn to_rgba_bytes(&self, palette: &DmgPalette) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .map(|shade| palette.color(*shade))
+            .flat_map(|rgb| [rgb.r, rgb.g, rgb.b, u8::MAX])
+            .collect()
+    }
+}
+
+/// Gets everything the [Printer] prints
+pub trait PrintHandler: Send {
+    fn print(&mut self, printout: Printout);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Magic(usize),
+    Command,
+    Compression,
+    Length(usize),
+    Data,
+    Checksum(usize),
+    Alive,
+    Status,
+}
+
+/// A Game Boy Printer, to attach with [crate::Ruboy::attach_link_device]
+#[derive(Debug)]
+pub struct Printer<H: PrintHandler> {
+    handler: H,
+    stage: Stage,
+
+    command: u8,
+    compressed: bool,
+    length: u16,
+    data: Vec<u8>,
+    checksum: u16,
+
+    /// Decompressed tile data of the image to print next
+    buffer: Vec<u8>,
+    status: u8,
+    print_polls: u8,
+}
+
+impl<H: PrintHandler> Printer<H> {
+    pub fn new(handler: H) -> Self {
+        Self {
+            handler,
+            stage: Stage::Magic(0),
+            command: 0,
+            compressed: false,
+            length: 0,
+            data: Vec::new(),
+            checksum: 0,
+            buffer: Vec::new(),
+            status: 0,
+            print_polls: 0,
+        }
+    }
+
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// The checksum the packet should have
+    fn packet_sum(&self) -> u16 {
+        [
+            self.command,
+            self.compressed as u8,
+            self.length as u8,
+            (self.length >> 8) as u8,
+        ]
+        .iter()
+        .chain(&self.data)
+        .fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16))
+    }
+
+    /// Handles a complete packet, updating the status to answer with
+    fn run_packet(&mut self) {
+        if self.packet_sum() != self.checksum {
+            log::warn!("Printer packet with wrong checksum");
+            self.status |= STATUS_CHECKSUM_ERR;
+            return;
+        }
+
+        self.status &= !(STATUS_CHECKSUM_ERR | STATUS_PACKET_ERR);
+
+        match self.command {
+            CMD_INIT => {
+                self.buffer.clear();
+                self.status = 0;
+                self.print_polls = 0;
+            }
+            CMD_DATA => {
+                // An empty data packet ends the image
+                if self.data.is_empty() {
+                    self.status |= STATUS_DATA_FULL;
+                    return;
+                }
+
+                let data = core::mem::take(&mut self.data);
+
+                if self.compressed {
+                    decompress(&data, &mut self.buffer);
+                } else {
+                    self.buffer.extend_from_slice(&data);
+                }
+
+                self.buffer.truncate(BUFFER_SIZE);
+                self.status |= STATUS_UNPROCESSED;
+
+                if self.buffer.len() == BUFFER_SIZE {
+                    self.status |= STATUS_DATA_FULL;
+                }
+            }
+            CMD_PRINT => {
+                // Sheets, margins, palette and exposure. Only the palette
+                // changes the image.
+                let palette = self.data.get(2).copied().unwrap_or(0);
+
+                let printout = render(&self.buffer, palette);
+                log::info!("Printing an image of {} lines", printout.height());
+
+                self.handler.print(printout);
+                self.buffer.clear();
+
+                self.status &= !(STATUS_UNPROCESSED | STATUS_DATA_FULL);
+                self.print_polls = PRINT_POLLS;
+            }
+            CMD_STATUS => {}
+            cmd => {
+                log::warn!("Unknown printer command 0x{:x}", cmd);
+                self.status |= STATUS_PACKET_ERR;
+            }
+        }
+    }
+
+    fn status(&mut self) -> u8 {
+        if self.print_polls == 0 {
+            return self.status;
+        }
+
+        self.print_polls -= 1;
+        self.status | STATUS_PRINTING
+    }
+}
+
+impl<H: PrintHandler> LinkDevice for Printer<H> {
+    fn exchange(&mut self, sent: u8, _cycle: u64) -> u8 {
+        let mut reply = 0;
+
+        self.stage = match self.stage {
+            Stage::Magic(idx) if sent == MAGIC[idx] => match idx {
+                0 => Stage::Magic(1),
+                _ => Stage::Command,
+            },
+            // Out of sync, so wait for the start of the next packet
+            Stage::Magic(_) if sent == MAGIC[0] => Stage::Magic(1),
+            Stage::Magic(_) => Stage::Magic(0),
+            Stage::Command => {
+                self.command = sent;
+                Stage::Compression
+            }
+            Stage::Compression => {
+                self.compressed = sent & 0b1 != 0;
+                Stage::Length(0)
+            }
+            Stage::Length(0) => {
+                self.length = sent as u16;
+                Stage::Length(1)
+            }
+            Stage::Length(_) => {
+                self.length |= (sent as u16) << 8;
+                self.data.clear();
+
+                match self.length {
+                    0 => Stage::Checksum(0),
+                    _ => Stage::Data,
+                }
+            }
+            Stage::Data => {
+                self.data.push(sent);
+
+                match self.data.len() == self.length as usize {
+                    true => Stage::Checksum(0),
+                    false => Stage::Data,
+                }
+            }
+            Stage::Checksum(0) => {
+                self.checksum = sent as u16;
+                Stage::Checksum(1)
+            }
+            Stage::Checksum(_) => {
+                self.checksum |= (sent as u16) << 8;
+                Stage::Alive
+            }
+            Stage::Alive => {
+                reply = ALIVE;
+                Stage::Status
+            }
+            Stage::Status => {
+                self.run_packet();
+                reply = self.status();
+                Stage::Magic(0)
+            }
+        };
+
+        reply
+    }
+}
+
+/// Run-length decoding. A control byte with the top bit set repeats the next
+/// byte `(ctrl & 0x7F) + 2` times, otherwise the next `ctrl + 1` bytes are
+/// copied as they are.
+fn decompress(data: &[u8], out: &mut Vec<u8>) {
+    let mut bytes = data.iter().copied();
+
+    while let Some(ctrl) = bytes.next() {
+        if ctrl & 0x80 != 0 {
+            let Some(byte) = bytes.next() else {
+                break;
+            };
+
+            out.extend(core::iter::repeat_n(byte, (ctrl & 0x7F) as usize + 2));
+        } else {
+            out.extend(bytes.by_ref().take(ctrl as usize + 1));
+        }
+    }
+}
+
+/// Turns the tile data into pixels with a BGP-style palette
+fn render(buffer: &[u8], palette: u8) -> Printout {
+    // Games that don't care send 0, which prints like the default palette
+    let palette = match palette {
+        0 => 0b11100100,
+        palette => palette,
+    };
+
+    let tile_rows = buffer.len() / (TILES_PER_ROW * TILE_BYTES);
+    let height = tile_rows * 8;
+
+    let pixels = (0..height)
+        .flat_map(|y| (0..PRINTOUT_X).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let tile = ((y / 8) * TILES_PER_ROW) + (x / 8);
+            let row = (tile * TILE_BYTES) + ((y % 8) * 2);
+            let bit = 7 - (x % 8);
+
+            let low = (buffer[row] >> bit) & 0b1;
+            let high = (buffer[row + 1] >> bit) & 0b1;
+            let id = (high << 1) | low;
+
+            match (palette >> (id * 2)) & 0b11 {
+                0 => GbMonoColor::White,
+                1 => GbMonoColor::LightGray,
+                2 => GbMonoColor::DarkGray,
+                _ => GbMonoColor::Black,
+            }
+        })
+        .collect();
+
+    Printout { pixels }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::*;
+    use crate::testutils::Recorder;
+
+    type Prints = Recorder<Printout>;
+
+    /// Sends a packet, returning the alive and status bytes the printer
+    /// answered with
+    fn send(printer: &mut Printer<Prints>, command: u8, compressed: bool, data: &[u8]) -> [u8; 2] {
+        let len = data.len() as u16;
+        let header = [command, compressed as u8, len as u8, (len >> 8) as u8];
+        let sum = header
+            .iter()
+            .chain(data)
+            .fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16));
+
+        let bytes: Vec<u8> = MAGIC
+            .iter()
+            .chain(&header)
+            .chain(data)
+            .chain(&sum.to_le_bytes())
+            .copied()
+            .collect();
+
+        for byte in bytes {
+            assert_eq!(0, printer.exchange(byte, 0));
+        }
+
+        [printer.exchange(0, 0), printer.exchange(0, 0)]
+    }
+
+    /// Two rows of tiles, with every pixel of the first row color 3 and of
+    /// the second row color 1
+    fn two_rows() -> Vec<u8> {
+        let mut data = vec![0xFF; TILES_PER_ROW * TILE_BYTES];
+        data.extend([0xFF, 0x00].repeat(TILES_PER_ROW * 8));
+        data
+    }
+
+    #[test]
+    fn prints_image_data() {
+        let prints = Prints::default();
+        let mut printer = Printer::new(prints.clone());
+
+        assert_eq!([ALIVE, 0], send(&mut printer, CMD_INIT, false, &[]));
+        assert_eq!(
+            [ALIVE, STATUS_UNPROCESSED],
+            send(&mut printer, CMD_DATA, false, &two_rows())
+        );
+        assert_eq!(
+            [ALIVE, STATUS_UNPROCESSED | STATUS_DATA_FULL],
+            send(&mut printer, CMD_DATA, false, &[])
+        );
+
+        // Inverted palette
+        let [_, status] = send(&mut printer, CMD_PRINT, false, &[1, 0x13, 0b00011011, 0x40]);
+        assert_eq!(STATUS_PRINTING, status);
+
+        let prints = prints.recorded();
+        assert_eq!(1, prints.len());
+        assert_eq!(16, prints[0].height());
+        assert_eq!(Some(GbMonoColor::White), prints[0].get_pix(0, 0));
+        assert_eq!(Some(GbMonoColor::DarkGray), prints[0].get_pix(159, 15));
+        assert_eq!(None, prints[0].get_pix(0, 16));
+
+        // Printing finishes after a few status requests
+        for _ in 1..PRINT_POLLS {
+            assert_eq!(
+                [ALIVE, STATUS_PRINTING],
+                send(&mut printer, CMD_STATUS, false, &[])
+            );
+        }
+
+        assert_eq!([ALIVE, 0], send(&mut printer, CMD_STATUS, false, &[]));
+    }
+
+    #[test]
+    fn compressed_data() {
+        let mut out = Vec::new();
+        decompress(&[0x81, 0xAB, 0x01, 0x12, 0x34], &mut out);
+        assert_eq!(vec![0xAB, 0xAB, 0xAB, 0x12, 0x34], out);
+
+        // One row of solid tiles in three runs, then the start of a row that
+        // is not printed
+        let compressed = [0xFE, 0xFF, 0xFE, 0xFF, 0xBE, 0xFF, 0x01, 0xFF, 0x00];
+
+        let prints = Prints::default();
+        let mut printer = Printer::new(prints.clone());
+
+        send(&mut printer, CMD_DATA, true, &compressed);
+        send(&mut printer, CMD_PRINT, false, &[1, 0, 0, 0x40]);
+
+        let prints = prints.recorded();
+        assert_eq!(8, prints[0].height());
+        assert_eq!(Some(GbMonoColor::Black), prints[0].get_pix(80, 7));
+    }
+
+    #[test]
+    fn bad_checksum_and_resync() {
+        let mut printer = Printer::new(Prints::default());
+
+        // Noise before the packet is skipped
+        printer.exchange(0x88, 0);
+        printer.exchange(0x00, 0);
+
+        let mut packet = vec![0x88, 0x33, CMD_STATUS, 0, 0, 0, 0x00, 0x00];
+
+        for byte in &packet {
+            printer.exchange(*byte, 0);
+        }
+
+        assert_eq!(
+            [ALIVE, STATUS_CHECKSUM_ERR],
+            [printer.exchange(0, 0), printer.exchange(0, 0)]
+        );
+
+        packet[6] = CMD_STATUS;
+
+        for byte in &packet {
+            printer.exchange(*byte, 0);
+        }
+
+        assert_eq!([ALIVE, 0], [printer.exchange(0, 0), printer.exchange(0, 0)]);
+    }
+}
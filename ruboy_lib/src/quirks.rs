@@ -0,0 +1,112 @@
+//! Workarounds for specific games that do not run correctly otherwise.
+//! Quirks are looked up by [RomMeta::header_hash], so they only affect the
+//! games they were added for. Every applied quirk is logged.
+
+use core::fmt::Display;
+
+use crate::rom::meta::{CartridgeMapper, RomMeta};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quirk {
+    /// Use the given mapper, or none at all, instead of the one in the
+    /// header
+    ForceMapper(Option<CartridgeMapper>),
+
+    /// Let the CPU access VRAM and OAM while the PPU is using them
+    NoAccessBlocking,
+}
+
+impl Display for Quirk {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Quirk::ForceMapper(Some(mapper)) => write!(f, "Forcing {} mapper", mapper),
+            Quirk::ForceMapper(None) => write!(f, "Forcing no mapper"),
+            Quirk::NoAccessBlocking => write!(f, "Not blocking VRAM/OAM accesses"),
+        }
+    }
+}
+
+/// The quirks for a single game
+#[derive(Debug, Clone, Copy)]
+pub struct QuirkEntry {
+    pub header_hash: u64,
+
+    /// Only used for logging, the hash already covers the title
+    pub title: &'static str,
+    pub quirks: &'static [Quirk],
+}
+
+/// All games that need quirks. The header hash of a ROM is shown by
+/// `ruboy_rominfo`
+pub static KNOWN: &[QuirkEntry] = &[];
+
+/// Finds the quirks for the ROM in the given table
+pub fn find(table: &'static [QuirkEntry], meta: &RomMeta) -> &'static [Quirk] {
+    table
+        .iter()
+        .find(|entry| entry.header_hash == meta.header_hash())
+        .map(|entry| entry.quirks)
+        .unwrap_or_default()
+}
+
+/// The quirks from [KNOWN] for the ROM
+pub fn for_rom(meta: &RomMeta) -> &'static [Quirk] {
+    find(KNOWN, meta)
+}
+
+/// The mapper to use for the ROM, after applying quirks
+pub(crate) fn mapper(meta: &RomMeta) -> Option<CartridgeMapper> {
+    let forced = for_rom(meta).iter().find_map(|quirk| match quirk {
+        Quirk::ForceMapper(mapper) => Some(*mapper),
+        _ => None,
+    });
+
+    match forced {
+        Some(mapper) => {
+            log::info!("Quirk for {}: {}", meta.title(), Quirk::ForceMapper(mapper));
+            mapper
+        }
+        None => meta.cartridge_hardware().mapper(),
+    }
+}
+
+/// True if the ROM has the given quirk. Logs when it does
+pub(crate) fn applies(meta: &RomMeta, quirk: Quirk) -> bool {
+    let found = for_rom(meta).contains(&quirk);
+
+    if found {
+        log::info!("Quirk for {}: {}", meta.title(), quirk);
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta_with_title(title: &[u8]) -> RomMeta {
+        let mut header = [0u8; RomMeta::HEADER_LENGTH];
+        header[RomMeta::OFFSET_TITLE_START..RomMeta::OFFSET_TITLE_START + title.len()]
+            .copy_from_slice(title);
+
+        RomMeta::parse(&header).unwrap()
+    }
+
+    #[test]
+    fn quirks_found_by_header_hash() {
+        let target = meta_with_title(b"BROKEN");
+        let other = meta_with_title(b"FINE");
+
+        assert_ne!(target.header_hash(), other.header_hash());
+
+        let table: &'static [QuirkEntry] = Box::leak(Box::new([QuirkEntry {
+            header_hash: target.header_hash(),
+            title: "BROKEN",
+            quirks: &[Quirk::NoAccessBlocking],
+        }]));
+
+        assert_eq!(&[Quirk::NoAccessBlocking], find(table, &target));
+        assert!(find(table, &other).is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+//! Rewinding through recent emulator history.
+//!
+//! A [Rewinder] takes a save state every few frames. Only the newest state
+//! is kept whole; older ones are stored as the difference with their
+//! successor. Most of the state stays the same between two snapshots, so the
+//! XOR of two states is mostly zeroes, which are run-length encoded away.
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use crate::emucore::EmulatorCore;
+use crate::{GBGraphicsDrawer, StateLoadErr};
+
+/// Zero runs shorter than this are kept inside a literal run, as splitting
+/// them off costs more than it saves
+const MIN_ZERO_RUN: usize = 4;
+
+#[derive(Debug, Clone)]
+pub struct Rewinder {
+    interval: u64,
+    capacity: usize,
+
+    /// The newest snapshot, and the frame it was taken at
+    latest: Option<(u64, Vec<u8>)>,
+
+    /// Whether the emulator didn't run since `latest` was taken or loaded
+    at_latest: bool,
+
+    /// Turns a snapshot into the one before it. The newest delta is at the
+    /// back.
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl Rewinder {
+    /// Takes a snapshot every `interval_frames` frames, and keeps at most
+    /// `capacity` of them
+    pub fn new(interval_frames: u64, capacity: usize) -> Self {
+        assert!(
+            interval_frames > 0,
+            "Snapshot interval must be at least one frame"
+        );
+        assert!(capacity > 0, "Rewinder must be able to hold a snapshot");
+
+        Self {
+            interval: interval_frames,
+            capacity,
+            latest: None,
+            at_latest: false,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    /// The amount of snapshots that can be rewound to
+    pub fn len(&self) -> usize {
+        match self.latest {
+            Some(_) => self.deltas.len() + 1,
+            None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    /// Drops all snapshots. Must be called when a different ROM is loaded.
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.at_latest = false;
+        self.deltas.clear();
+    }
+
+    /// Takes a snapshot if at least the interval passed since the last
+    /// one. Call this after every step of the emulator.
+    pub fn capture<V, C>(&mut self, ruboy: &C)
+    where
+        V: GBGraphicsDrawer,
+        C: EmulatorCore<V> + ?Sized,
+    {
+        let frame = ruboy.frame_count();
+
+        if let Some((taken_at, _)) = &self.latest {
+            if frame.abs_diff(*taken_at) < self.interval {
+                self.at_latest = false;
+                return;
+            }
+        }
+
+        let state = ruboy.save_state();
+
+        match self.latest.take() {
+            Some((_, prev)) if prev.len() == state.len() => {
+                self.deltas.push_back(encode_delta(&state, &prev));
+
+                if self.deltas.len() >= self.capacity {
+                    self.deltas.pop_front();
+                }
+            }
+            _ => self.deltas.clear(),
+        }
+
+        self.latest = Some((frame, state));
+        self.at_latest = true;
+    }
+
+    /// Goes back to the newest snapshot. If the emulator is already there,
+    /// that snapshot is dropped and the one before it is loaded instead. The
+    /// oldest snapshot is never dropped. Returns false if there was nothing
+    /// left to go back to.
+    pub fn rewind<V, C>(&mut self, ruboy: &mut C) -> Result<bool, StateLoadErr>
+    where
+        V: GBGraphicsDrawer,
+        C: EmulatorCore<V> + ?Sized,
+    {
+        let Some((_, state)) = &mut self.latest else {
+            return Ok(false);
+        };
+
+        if self.at_latest {
+            let Some(delta) = self.deltas.pop_back() else {
+                return Ok(false);
+            };
+
+            apply_delta(&delta, state);
+        }
+
+        if let Err(e) = ruboy.load_state(state) {
+            self.clear();
+            return Err(e);
+        }
+
+        // The next snapshot is only taken a full interval after this one
+        self.latest = self
+            .latest
+            .take()
+            .map(|(_, state)| (ruboy.frame_count(), state));
+        self.at_latest = true;
+
+        Ok(true)
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push((val as u8) | 0x80);
+        val >>= 7;
+    }
+
+    out.push(val as u8);
+}
+
+fn read_varint(data: &mut &[u8]) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+
+    while let Some((&byte, rest)) = data.split_first() {
+        *data = rest;
+        val |= ((byte & 0x7F) as usize) << shift;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+
+    val
+}
+
+/// Encodes `to XOR from` as alternating runs: the length of a run of
+/// zeroes, followed by the length and bytes of a literal run
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    debug_assert_eq!(from.len(), to.len());
+
+    let xor: Vec<u8> = from.iter().zip(to).map(|(a, b)| a ^ b).collect();
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < xor.len() {
+        let zeroes = xor[pos..].iter().take_while(|b| **b == 0).count();
+        pos += zeroes;
+
+        if pos == xor.len() {
+            break;
+        }
+
+        let literal_start = pos;
+
+        while pos < xor.len() {
+            let zero_run = xor[pos..]
+                .iter()
+                .take(MIN_ZERO_RUN)
+                .take_while(|b| **b == 0)
+                .count();
+
+            if zero_run == MIN_ZERO_RUN || pos + zero_run == xor.len() {
+                break;
+            }
+
+            pos += zero_run.max(1);
+        }
+
+        write_varint(&mut out, zeroes);
+        write_varint(&mut out, pos - literal_start);
+        out.extend_from_slice(&xor[literal_start..pos]);
+    }
+
+    out
+}
+
+/// Turns `from` into `to` in place, given a delta from [encode_delta]
+fn apply_delta(mut delta: &[u8], state: &mut [u8]) {
+    let mut pos = 0;
+
+    while !delta.is_empty() {
+        pos += read_varint(&mut delta);
+
+        let literal_len = read_varint(&mut delta);
+        let (literal, rest) = delta.split_at(literal_len);
+
+        for (byte, xor) in state[pos..pos + literal_len].iter_mut().zip(literal) {
+            *byte ^= xor;
+        }
+
+        pos += literal_len;
+        delta = rest;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::GbModel;
+    use crate::testutils::{blank_rom, NullInput, NullOutput, TestRuboy};
+    use crate::Ruboy;
+    use crate::DESIRED_FRAMERATE;
+
+    #[test]
+    fn delta_round_trips() {
+        let from: Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        let mut to = from.clone();
+
+        to[0] ^= 1;
+        to[2] = 0;
+        to[500..510].fill(0xAA);
+        to[999] = 3;
+
+        let delta = encode_delta(&from, &to);
+        assert!(delta.len() < 40, "Delta is {} bytes", delta.len());
+
+        let mut state = from.clone();
+        apply_delta(&delta, &mut state);
+        assert_eq!(to, state);
+
+        assert!(encode_delta(&from, &from).is_empty());
+    }
+
+    #[test]
+    fn rewinds_to_earlier_frames() {
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(blank_rom(0x3C), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        let mut rewinder = Rewinder::new(1, 3);
+
+        let mut frames = Vec::new();
+
+        for _ in 0..5 {
+            ruboy.step(1.0 / DESIRED_FRAMERATE).unwrap();
+            rewinder.capture(&ruboy);
+            frames.push((ruboy.frame_count(), ruboy.state_digest()));
+        }
+
+        assert_eq!(3, rewinder.len());
+
+        // Already at the newest snapshot, and only the last three are kept
+        for (frame, digest) in frames[2..4].iter().rev() {
+            assert!(rewinder.rewind(&mut ruboy).unwrap());
+
+            assert_eq!(*frame, ruboy.frame_count());
+            assert_eq!(*digest, ruboy.state_digest());
+        }
+
+        assert!(!rewinder.rewind(&mut ruboy).unwrap());
+        assert_eq!(1, rewinder.len());
+        assert_eq!(frames[2].0, ruboy.frame_count());
+
+        // Running on from a rewound state loads that state again
+        ruboy.step(0.5 / DESIRED_FRAMERATE).unwrap();
+        rewinder.capture(&ruboy);
+        assert!(rewinder.rewind(&mut ruboy).unwrap());
+        assert_eq!(frames[2].1, ruboy.state_digest());
+    }
+}
@@ -0,0 +1,95 @@
+//! Cartridge features a frontend may want to react to, e.g. by showing a
+//! rumble indicator, or by warning about games that won't run properly.
+
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use super::meta::{CgbFlag, RomMeta};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Cartridge RAM is kept alive by a battery, so the game can save
+    Battery,
+
+    /// Real time clock, which keeps running while the game is off
+    Rtc,
+
+    /// Rumble motor
+    Rumble,
+
+    /// Accelerometer, as used by MBC7 games
+    Sensor,
+
+    /// Game Boy Camera
+    Camera,
+
+    /// The game only runs on a Game Boy Color
+    CgbOnly,
+}
+
+impl Capability {
+    pub const ALL: [Capability; 6] = [
+        Capability::Battery,
+        Capability::Rtc,
+        Capability::Rumble,
+        Capability::Sensor,
+        Capability::Camera,
+        Capability::CgbOnly,
+    ];
+
+    /// Whether Ruboy emulates this feature. Games using unsupported features
+    /// may not work, or lose data.
+    pub const fn supported(self) -> bool {
+        matches!(
+            self,
+            Capability::Battery | Capability::Rtc | Capability::Rumble
+        )
+    }
+
+    /// What happens to a game using this feature when it is not supported
+    pub const fn limitation(self) -> Option<&'static str> {
+        match self {
+            Capability::Battery | Capability::Rtc | Capability::Rumble => None,
+            Capability::Sensor => Some("The accelerometer is not emulated"),
+            Capability::Camera => Some("The camera is not emulated"),
+            Capability::CgbOnly => Some(
+                "Game Boy Color mode is not emulated, so the game will probably refuse to start",
+            ),
+        }
+    }
+}
+
+impl Display for Capability {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let name = match self {
+            Capability::Battery => "Battery",
+            Capability::Rtc => "Real time clock",
+            Capability::Rumble => "Rumble",
+            Capability::Sensor => "Accelerometer",
+            Capability::Camera => "Camera",
+            Capability::CgbOnly => "Game Boy Color only",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl RomMeta {
+    /// The notable features of this cartridge, in the order of
+    /// [Capability::ALL]
+    pub fn capabilities(&self) -> Vec<Capability> {
+        let hw = self.cartridge_hardware();
+
+        Capability::ALL
+            .into_iter()
+            .filter(|cap| match cap {
+                Capability::Battery => hw.has_battery(),
+                Capability::Rtc => hw.has_timer(),
+                Capability::Rumble => hw.has_rumble(),
+                Capability::Sensor => hw.has_sensor(),
+                Capability::Camera => hw.has_camera(),
+                Capability::CgbOnly => matches!(self.cgb_support(), CgbFlag::CgbOnly),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,376 @@
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::num::NonZeroUsize;
+
+use crate::{GBAllocator, GBRam, RomReader};
+
+use super::bank_num_to_addr;
+
+pub const BANK_SIZE: usize = 0x4000;
+
+/// Which ROM banks are kept in memory. Only applies to readers that don't
+/// keep the whole ROM in memory themselves (see [RomReader::as_slice]),
+/// those are always read from directly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BankCaching {
+    /// Only the two mapped banks. Every bank switch reads the new bank.
+    #[default]
+    OnDemand,
+
+    /// The given amount of most recently mapped banks, but at least two.
+    /// Switching back to one of those doesn't read anything.
+    Lru(NonZeroUsize),
+
+    /// The whole ROM, read once when the cartridge is loaded
+    All,
+}
+
+/// The two halves of the ROM area
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    /// 0x0000-0x3FFF
+    Low,
+
+    /// 0x4000-0x7FFF
+    High,
+}
+
+impl Slot {
+    const fn other(self) -> Self {
+        match self {
+            Slot::Low => Slot::High,
+            Slot::High => Slot::Low,
+        }
+    }
+}
+
+/// The ROM of a cartridge, with one bank mapped into each [Slot]. Every
+/// caching policy finds the data of a mapped bank in constant time, they
+/// only differ in how often a bank switch has to go to the reader.
+#[derive(Debug)]
+pub struct BankCache<A: GBAllocator, R: RomReader> {
+    reader: R,
+    num_banks: usize,
+
+    /// The bank mapped in each slot
+    mapped: [usize; 2],
+
+    storage: Storage<A>,
+}
+
+#[derive(Debug)]
+enum Storage<A: GBAllocator> {
+    /// The reader keeps the ROM in memory already
+    Borrowed,
+
+    /// A copy of the bank mapped in each slot
+    OnDemand([A::Mem<u8, BANK_SIZE>; 2]),
+
+    Cached(Lru),
+}
+
+#[derive(Debug)]
+struct Lru {
+    /// The most banks kept at once
+    capacity: usize,
+
+    /// For each bank of the ROM, where it is in `banks` if it is cached
+    index: Vec<Option<usize>>,
+
+    banks: Vec<CachedBank>,
+
+    /// Where the bank mapped in each slot is in `banks`
+    slots: [usize; 2],
+
+    /// Counts bank switches, to find the least recently used bank
+    clock: u64,
+}
+
+#[derive(Debug)]
+struct CachedBank {
+    bank: usize,
+    data: Box<[u8]>,
+    last_used: u64,
+}
+
+impl<A: GBAllocator, R: RomReader> BankCache<A, R> {
+    /// Maps bank 0 in [Slot::Low] and bank 1 in [Slot::High]
+    pub fn new(reader: R, num_banks: usize, caching: BankCaching) -> Result<Self, R::Err> {
+        let mut new = Self {
+            reader,
+            // Even the smallest ROMs have two banks to map
+            num_banks: num_banks.max(2),
+            mapped: [0, 1],
+            storage: Storage::Borrowed,
+        };
+
+        new.set_caching(caching)?;
+
+        Ok(new)
+    }
+
+    /// Switches to another caching policy, keeping the same banks mapped
+    pub fn set_caching(&mut self, caching: BankCaching) -> Result<(), R::Err> {
+        let [low, high] = self.mapped;
+
+        self.storage = match caching {
+            _ if self.reader.as_slice().is_some() => Storage::Borrowed,
+            BankCaching::OnDemand => Storage::OnDemand([A::empty(), A::empty()]),
+            BankCaching::Lru(capacity) => Storage::Cached(Lru::new(self.num_banks, capacity.get())),
+            BankCaching::All => Storage::Cached(Lru::new(self.num_banks, self.num_banks)),
+        };
+
+        if caching == BankCaching::All && matches!(self.storage, Storage::Cached(_)) {
+            // Fits every bank, so nothing is evicted
+            for bank in 0..self.num_banks {
+                self.map(Slot::High, bank)?;
+            }
+        }
+
+        self.map(Slot::Low, low)?;
+        self.map(Slot::High, high)
+    }
+
+    /// Maps `bank` in `slot`. Banks past the end of the ROM wrap around.
+    pub fn map(&mut self, slot: Slot, bank: usize) -> Result<(), R::Err> {
+        let bank = bank % self.num_banks;
+        self.mapped[slot as usize] = bank;
+
+        match &mut self.storage {
+            Storage::Borrowed => Ok(()),
+            Storage::OnDemand(copies) => self
+                .reader
+                .read_into(copies[slot as usize].raw_mut(), bank_num_to_addr(bank)),
+            Storage::Cached(lru) => lru.map(&mut self.reader, slot, bank),
+        }
+    }
+
+    /// The bank mapped in `slot`
+    pub fn mapped(&self, slot: Slot) -> usize {
+        self.mapped[slot as usize]
+    }
+
+    /// Reads `addr`, relative to the start of the bank mapped in `slot`
+    pub fn read(&self, slot: Slot, addr: u16) -> u8 {
+        match &self.storage {
+            // Nothing drives the bus past the end of a short ROM
+            Storage::Borrowed => self
+                .reader
+                .as_slice()
+                .and_then(|rom| {
+                    rom.get(bank_num_to_addr(self.mapped[slot as usize]) + addr as usize)
+                })
+                .copied()
+                .unwrap_or(0xFF),
+            Storage::OnDemand(copies) => copies[slot as usize].read(addr),
+            Storage::Cached(lru) => lru.banks[lru.slots[slot as usize]].data[addr as usize],
+        }
+    }
+}
+
+impl Lru {
+    fn new(num_banks: usize, capacity: usize) -> Self {
+        Self {
+            // Both mapped banks have to fit
+            capacity: capacity.clamp(2, num_banks),
+            index: vec![None; num_banks],
+            banks: Vec::new(),
+            slots: [usize::MAX; 2],
+            clock: 0,
+        }
+    }
+
+    fn map<R: RomReader>(&mut self, reader: &mut R, slot: Slot, bank: usize) -> Result<(), R::Err> {
+        let entry = match self.index[bank] {
+            Some(entry) => entry,
+            None => self.load(reader, slot, bank)?,
+        };
+
+        self.clock += 1;
+        self.banks[entry].last_used = self.clock;
+        self.slots[slot as usize] = entry;
+
+        Ok(())
+    }
+
+    /// Reads `bank` into the cache, evicting the least recently used bank
+    /// if it is full. The bank mapped in the other slot stays.
+    fn load<R: RomReader>(
+        &mut self,
+        reader: &mut R,
+        slot: Slot,
+        bank: usize,
+    ) -> Result<usize, R::Err> {
+        let entry = if self.banks.len() < self.capacity {
+            self.banks.push(CachedBank {
+                bank,
+                data: vec![0; BANK_SIZE].into_boxed_slice(),
+                last_used: 0,
+            });
+
+            self.banks.len() - 1
+        } else {
+            let keep = self.slots[slot.other() as usize];
+            let entry = (0..self.banks.len())
+                .filter(|&entry| entry != keep)
+                .min_by_key(|&entry| self.banks[entry].last_used)
+                .expect("Bank cache holds at least two banks");
+
+            self.index[self.banks[entry].bank] = None;
+            self.banks[entry].bank = bank;
+
+            entry
+        };
+
+        reader.read_into(&mut self.banks[entry].data, bank_num_to_addr(bank))?;
+        self.index[bank] = Some(entry);
+
+        Ok(entry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Cursor};
+    use std::vec;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::rom::InMemoryRom;
+    use crate::InlineAllocator;
+
+    /// A streamed ROM that counts how often it is read from
+    #[derive(Debug)]
+    struct CountingRom {
+        rom: Cursor<Vec<u8>>,
+        reads: usize,
+    }
+
+    impl RomReader for CountingRom {
+        type Err = io::Error;
+
+        fn read_into(&mut self, buf: &mut [u8], addr: usize) -> Result<(), Self::Err> {
+            self.reads += 1;
+            self.rom.read_into(buf, addr)
+        }
+    }
+
+    fn banks(num: usize) -> Vec<u8> {
+        (0..num)
+            .flat_map(|bank| vec![bank as u8; BANK_SIZE])
+            .collect()
+    }
+
+    fn counting(num: usize, caching: BankCaching) -> BankCache<InlineAllocator, CountingRom> {
+        let rom = CountingRom {
+            rom: Cursor::new(banks(num)),
+            reads: 0,
+        };
+
+        BankCache::new(rom, num, caching).unwrap()
+    }
+
+    fn lru(capacity: usize) -> BankCaching {
+        BankCaching::Lru(NonZeroUsize::new(capacity).unwrap())
+    }
+
+    #[test]
+    fn in_memory_banks_are_not_copied() {
+        let rom = InMemoryRom::from(banks(4));
+        let mut cache = BankCache::<InlineAllocator, _>::new(rom, 4, BankCaching::All).unwrap();
+
+        assert!(matches!(cache.storage, Storage::Borrowed));
+        assert_eq!(1, cache.read(Slot::High, 0x0000));
+
+        cache.map(Slot::High, 3).unwrap();
+        assert_eq!(3, cache.read(Slot::High, 0x3FFF));
+    }
+
+    #[test]
+    fn on_demand_reads_every_switch() {
+        let mut cache = counting(4, BankCaching::OnDemand);
+        assert_eq!(2, cache.reader.reads);
+
+        for bank in [2, 3, 2] {
+            cache.map(Slot::High, bank).unwrap();
+        }
+
+        assert_eq!(5, cache.reader.reads);
+        assert_eq!(2, cache.read(Slot::High, 0x1234));
+    }
+
+    #[test]
+    fn lru_keeps_recent_banks() {
+        let mut cache = counting(8, lru(3));
+
+        // Bank 1 is evicted for 3, bank 0 stays as it is mapped in the
+        // low slot
+        for bank in [2, 3, 2, 3] {
+            cache.map(Slot::High, bank).unwrap();
+        }
+
+        assert_eq!(4, cache.reader.reads);
+        assert_eq!(3, cache.read(Slot::High, 0x0000));
+        assert_eq!(0, cache.read(Slot::Low, 0x0000));
+
+        cache.map(Slot::High, 1).unwrap();
+        assert_eq!(5, cache.reader.reads);
+        assert_eq!(1, cache.read(Slot::High, 0x0000));
+    }
+
+    #[test]
+    fn lru_holds_both_mapped_banks() {
+        let mut cache = counting(8, lru(1));
+
+        cache.map(Slot::High, 5).unwrap();
+        cache.map(Slot::Low, 6).unwrap();
+
+        assert_eq!(6, cache.read(Slot::Low, 0x0000));
+        assert_eq!(5, cache.read(Slot::High, 0x0000));
+    }
+
+    #[test]
+    fn all_reads_the_rom_once() {
+        let mut cache = counting(8, BankCaching::All);
+        assert_eq!(8, cache.reader.reads);
+
+        for bank in [7, 2, 5, 1, 0] {
+            cache.map(Slot::High, bank).unwrap();
+            assert_eq!(bank as u8, cache.read(Slot::High, 0x2000));
+        }
+
+        assert_eq!(8, cache.reader.reads);
+    }
+
+    #[test]
+    fn switching_policy_keeps_mapped_banks() {
+        let mut cache = counting(8, BankCaching::OnDemand);
+        cache.map(Slot::Low, 4).unwrap();
+        cache.map(Slot::High, 6).unwrap();
+
+        for caching in [BankCaching::All, lru(2), BankCaching::OnDemand] {
+            cache.set_caching(caching).unwrap();
+
+            assert_eq!(4, cache.read(Slot::Low, 0x0000));
+            assert_eq!(6, cache.read(Slot::High, 0x0000));
+        }
+    }
+
+    #[test]
+    fn banks_wrap_around() {
+        let mut cache = counting(4, lru(2));
+
+        cache.map(Slot::High, 6).unwrap();
+        assert_eq!(2, cache.read(Slot::High, 0x0000));
+    }
+
+    #[test]
+    fn past_the_end_reads_open_bus() {
+        let rom = InMemoryRom::from(vec![0x12; BANK_SIZE + 0x100]);
+        let cache = BankCache::<InlineAllocator, _>::new(rom, 2, BankCaching::OnDemand).unwrap();
+
+        assert_eq!(0x12, cache.read(Slot::High, 0x00FF));
+        assert_eq!(0xFF, cache.read(Slot::High, 0x0100));
+    }
+}
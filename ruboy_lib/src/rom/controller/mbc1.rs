@@ -1,107 +1,222 @@
 use crate::rom::controller::bank_num_to_addr;
 use crate::rom::meta::RomMeta;
-use crate::{GBAllocator, GBRam, RomReader};
+use crate::savestate::{StateData, StateLoadErr, StateReader, StateWriter};
+use crate::{GBAllocator, RomReader};
+use alloc::boxed::Box;
+use alloc::vec;
 
-use super::{Mbc, ReadError, WriteError};
+use super::cache::{BankCache, BankCaching, Slot};
+use super::{reader_err, Mbc, ReadError, WriteError};
+
+const RAM_BANK_SIZE: usize = 0x2000;
+
+/// MBC1M multicarts are always 8 Mbit
+const MULTICART_ROM_SIZE: usize = 1024 * 1024;
+
+/// The bank the second game of a multicart starts at, and with that its
+/// header and Nintendo logo
+const MULTICART_GAME_BANKS: usize = 0x10;
 
 #[derive(Debug)]
 pub struct Mbc1<A: GBAllocator, R: RomReader> {
     meta: RomMeta,
-    reader: R,
-
-    /// All banks ending with 0 (00, 10, 20, etc.)
-    rom_bank_x0: A::Mem<u8, 0x4000>,
 
-    /// All other banks
-    rom_bank_1x: A::Mem<u8, 0x4000>,
+    /// The low slot has bank 0, unless mode 1 selects another one with the
+    /// secondary bank
+    rom: BankCache<A, R>,
 
-    ram_bank_x: A::Mem<u8, 0x2000>,
+    /// All RAM banks. Too large to keep inline, so always on the heap.
+    ram: Box<[u8]>,
 
     ram_enabled: bool,
 
     addressing_mode: AddrMode,
 
+    /// BANK1, the lower 5 bits of the ROM bank. Writing 0 selects 1, so this
+    /// is never 0.
     selected_bank: u8,
 
+    /// BANK2, 2 bits that select the upper ROM bits or, in mode 1, the RAM
+    /// bank
     secondary_bank: u8,
+
+    /// MBC1M multicarts only connect 4 bits of BANK1, so the secondary bank
+    /// selects one of four 256KB games
+    multicart: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
 enum AddrMode {
+    /// The secondary bank only applies to 0x4000-0x7FFF
     Mode0,
+
+    /// The secondary bank also applies to 0x0000-0x3FFF and the RAM
     Mode1,
 }
 
 impl<A: GBAllocator, R: RomReader> Mbc1<A, R> {
-    pub fn new(meta: RomMeta, mut reader: R) -> Result<Self, R::Err> {
-        log::info!("Initializing MBC1 ROM mapper");
+    pub fn new(meta: RomMeta, mut reader: R, caching: BankCaching) -> Result<Self, R::Err> {
+        let multicart = Self::detect_multicart(&meta, &mut reader)?;
 
-        let mut bank_0 = A::empty();
-        let mut bank_1 = A::empty();
+        if multicart {
+            log::info!("Initializing MBC1M multicart ROM mapper");
+        } else {
+            log::info!("Initializing MBC1 ROM mapper");
+        }
 
-        reader.read_into(bank_0.raw_mut(), bank_num_to_addr(0))?;
-        reader.read_into(bank_1.raw_mut(), bank_num_to_addr(1))?;
+        let rom = BankCache::new(reader, meta.rom_size().num_banks(), caching)?;
+        let ram = vec![0; meta.ram_size().in_bytes()].into_boxed_slice();
 
         let new = Self {
             meta,
-            reader,
-            rom_bank_x0: bank_0,
-            rom_bank_1x: bank_1,
-            ram_bank_x: A::empty(),
+            rom,
+            ram,
             ram_enabled: false,
             addressing_mode: AddrMode::Mode0,
-            selected_bank: 0,
+            selected_bank: 1,
             secondary_bank: 0,
+            multicart,
         };
 
         Ok(new)
     }
 
-    fn switch_rom_bank(&mut self, bank: usize) -> Result<(), R::Err> {
-        self.reader
-            .read_into(self.rom_bank_1x.raw_mut(), bank_num_to_addr(bank))?;
+    pub fn set_bank_caching(&mut self, caching: BankCaching) -> Result<(), R::Err> {
+        self.rom.set_caching(caching)
+    }
+
+    /// Multicarts have the same header type as regular MBC1 cartridges, but
+    /// contain more than one game. Like other emulators, they are told apart
+    /// by the Nintendo logo of the second game.
+    fn detect_multicart(meta: &RomMeta, reader: &mut R) -> Result<bool, R::Err> {
+        if meta.rom_size().in_bytes() != MULTICART_ROM_SIZE {
+            return Ok(false);
+        }
+
+        let mut logo = [0; RomMeta::LOGO.len()];
+        reader.read_into(
+            &mut logo,
+            bank_num_to_addr(MULTICART_GAME_BANKS) + RomMeta::OFFSET_LOGO,
+        )?;
+
+        Ok(logo == RomMeta::LOGO)
+    }
+
+    /// How far up the secondary bank goes in the ROM bank number
+    const fn secondary_shift(&self) -> u32 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
 
-        Ok(())
+    /// The bank mapped at 0x0000-0x3FFF
+    fn low_rom_bank(&self) -> usize {
+        let bank = match self.addressing_mode {
+            AddrMode::Mode0 => 0,
+            AddrMode::Mode1 => (self.secondary_bank as usize) << self.secondary_shift(),
+        };
+
+        bank % self.meta.rom_size().num_banks()
     }
 
-    fn switch_ram_bank(&mut self, bank: usize) {
-        //TODO: Save previous bank somewhere?
+    /// The bank mapped at 0x4000-0x7FFF
+    fn high_rom_bank(&self) -> usize {
+        let selected = match self.multicart {
+            true => self.selected_bank & 0b1111,
+            false => self.selected_bank,
+        };
+
+        let bank = ((self.secondary_bank as usize) << self.secondary_shift()) | selected as usize;
+
+        bank % self.meta.rom_size().num_banks()
+    }
+
+    fn switch_low_rom_bank(&mut self) -> Result<(), R::Err> {
+        let bank = self.low_rom_bank();
+        self.rom.map(Slot::Low, bank)
     }
 
-    fn calc_rom_bank(&self) -> usize {
-        assert!(self.selected_bank <= 0b11111, "ROM bank too high, invalid!");
-        assert!(
-            self.secondary_bank <= 0b11,
-            "ROM secondary bank too high, invalid!"
-        );
+    fn switch_high_rom_bank(&mut self) -> Result<(), R::Err> {
+        let bank = self.high_rom_bank();
+        self.rom.map(Slot::High, bank)
+    }
 
-        let actual_bank = self.selected_bank + (self.secondary_bank << 5);
+    /// Where `addr` ends up in the RAM. Carts with a single bank ignore the
+    /// bank, and larger ones only see the secondary bank in mode 1.
+    fn ram_index(&self, addr: u16) -> usize {
+        let bank = match self.addressing_mode {
+            AddrMode::Mode0 => 0,
+            AddrMode::Mode1 => self.secondary_bank as usize,
+        };
 
-        (actual_bank as usize) % self.meta.rom_size().num_banks()
+        ((bank * RAM_BANK_SIZE) + (addr - 0xA000) as usize) % self.ram.len()
+    }
+}
+
+impl<A: GBAllocator, R: RomReader> StateData for Mbc1<A, R> {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.ram);
+        self.ram_enabled.save_state(w);
+        matches!(self.addressing_mode, AddrMode::Mode1).save_state(w);
+        self.selected_bank.save_state(w);
+        self.secondary_bank.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        r.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled.load_state(r)?;
+
+        let mut mode1 = false;
+        mode1.load_state(r)?;
+        self.addressing_mode = if mode1 {
+            AddrMode::Mode1
+        } else {
+            AddrMode::Mode0
+        };
+
+        self.selected_bank.load_state(r)?;
+        self.secondary_bank.load_state(r)?;
+
+        if !(1..=0b11111).contains(&self.selected_bank) || self.secondary_bank > 0b11 {
+            return Err(StateLoadErr::Invalid("MBC1 bank"));
+        }
+
+        self.switch_low_rom_bank()
+            .and_then(|_| self.switch_high_rom_bank())
+            .map_err(|e| StateLoadErr::Rom(Box::new(e)))
     }
 }
 
 impl<A: GBAllocator, R: RomReader> Mbc for Mbc1<A, R> {
+    fn meta(&self) -> &RomMeta {
+        &self.meta
+    }
+
+    fn rom_bank(&self, slot: Slot) -> usize {
+        self.rom.mapped(slot)
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
     fn read(&self, addr: u16) -> Result<u8, super::ReadError> {
         match addr {
-            0x0000..=0x3FFF => match self.addressing_mode {
-                AddrMode::Mode0 => Ok(self.rom_bank_x0.read(addr)),
-                AddrMode::Mode1 => todo!(),
-            },
-            0x4000..=0x7FFF => Ok(self.rom_bank_1x.read(addr - 0x4000)),
+            0x0000..=0x3FFF => Ok(self.rom.read(Slot::Low, addr)),
+            0x4000..=0x7FFF => Ok(self.rom.read(Slot::High, addr - 0x4000)),
             0xA000..=0xBFFF => {
-                let ram_size = self.meta.ram_size().in_bytes();
-                if ram_size == 0 {
+                if self.ram.is_empty() {
                     return Err(ReadError::NotEnoughRam { addr, max: 0 });
                 }
 
                 if self.ram_enabled {
-                    let ram_addr = match self.addressing_mode {
-                        AddrMode::Mode0 => addr - 0xA000,
-                        AddrMode::Mode1 => todo!(),
-                    };
-
-                    Ok(self.ram_bank_x.read(ram_addr))
+                    Ok(self.ram[self.ram_index(addr)])
                 } else {
                     Ok(0xFF)
                 }
@@ -117,33 +232,24 @@ impl<A: GBAllocator, R: RomReader> Mbc for Mbc1<A, R> {
                 Ok(())
             }
             0x2000..=0x3FFF => {
-                // 5-bit register
+                // 5-bit register. The check for 0 looks at all 5 bits, even
+                // on multicarts that only use 4 of them.
                 let mut bank_num = val & 0b11111;
                 if bank_num == 0 {
                     bank_num = 1;
                 }
 
                 self.selected_bank = bank_num;
-                self.switch_rom_bank(self.calc_rom_bank())
-                    .map_err(|e| WriteError::Reader(Box::new(e)))?;
-
-                Ok(())
+                self.switch_high_rom_bank()
+                    .map_err(|e| reader_err(self.high_rom_bank(), e))
             }
             0x4000..=0x5FFF => {
-                const MB: usize = 1024 * 1024;
-                let rom_size = self.meta.rom_size().in_bytes();
-                let ram_size = self.meta.ram_size().in_bytes();
-
-                if rom_size >= MB {
-                    self.secondary_bank = val & 0b11;
-                    self.switch_rom_bank(self.calc_rom_bank())
-                        .map_err(|e| WriteError::Reader(Box::new(e)))?;
-                } else if ram_size > 0 {
-                    self.switch_ram_bank((val & 0b11) as usize);
-                }
-                // else do nothing
+                self.secondary_bank = val & 0b11;
 
-                Ok(())
+                self.switch_low_rom_bank()
+                    .map_err(|e| reader_err(self.low_rom_bank(), e))?;
+                self.switch_high_rom_bank()
+                    .map_err(|e| reader_err(self.high_rom_bank(), e))
             }
             0x6000..=0x7FFF => {
                 if val & 0b1 == 0b1 {
@@ -152,9 +258,166 @@ impl<A: GBAllocator, R: RomReader> Mbc for Mbc1<A, R> {
                     self.addressing_mode = AddrMode::Mode0;
                 }
 
+                self.switch_low_rom_bank()
+                    .map_err(|e| reader_err(self.low_rom_bank(), e))
+            }
+            0xA000..=0xBFFF => {
+                if self.ram.is_empty() {
+                    return Err(WriteError::NotEnoughRam { addr, max: 0 });
+                }
+
+                if self.ram_enabled {
+                    let index = self.ram_index(addr);
+                    self.ram[index] = val;
+                }
+
                 Ok(())
             }
             _ => panic!("Address not a ROM address"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::vec;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::InlineAllocator;
+
+    type TestMbc1 = Mbc1<InlineAllocator, Cursor<Vec<u8>>>;
+
+    /// A ROM of `banks` banks with each bank filled with its number, and
+    /// the given RAM size code. Multicarts get the logo of a second game.
+    fn rom(banks: usize, ram_size: u8, multicart: bool) -> TestMbc1 {
+        let mut rom: Vec<u8> = (0..banks)
+            .flat_map(|bank| vec![bank as u8; 0x4000])
+            .collect();
+
+        rom[0x147] = 0x03;
+        rom[0x148] = (banks / 2).trailing_zeros() as u8;
+        rom[0x149] = ram_size;
+
+        if multicart {
+            let logo = bank_num_to_addr(MULTICART_GAME_BANKS) + RomMeta::OFFSET_LOGO;
+            rom[logo..][..RomMeta::LOGO.len()].copy_from_slice(&RomMeta::LOGO);
+        }
+
+        let header = &rom[RomMeta::OFFSET_HEADER_START..][..RomMeta::HEADER_LENGTH];
+        let meta = RomMeta::parse(header).unwrap();
+
+        Mbc1::new(meta, Cursor::new(rom), BankCaching::default()).unwrap()
+    }
+
+    /// The banks mapped at 0x0000 and 0x4000
+    fn banks(mbc: &TestMbc1) -> (u8, u8) {
+        (mbc.read(0x0000).unwrap(), mbc.read(0x4000).unwrap())
+    }
+
+    #[test]
+    fn zero_selects_bank_one() {
+        let mut mbc = rom(32, 0x00, false);
+        assert_eq!((0, 1), banks(&mbc));
+
+        mbc.write(0x2000, 0x04).unwrap();
+        assert_eq!((0, 4), banks(&mbc));
+
+        // Only the 5 bits of the register count for the check
+        mbc.write(0x2000, 0x20).unwrap();
+        assert_eq!((0, 1), banks(&mbc));
+
+        mbc.write(0x2000, 0xE3).unwrap();
+        assert_eq!((0, 3), banks(&mbc));
+    }
+
+    #[test]
+    fn secondary_bank_in_both_modes() {
+        let mut mbc = rom(128, 0x00, false);
+
+        mbc.write(0x2000, 0x05).unwrap();
+        mbc.write(0x4000, 0x02).unwrap();
+        assert_eq!((0, 0x45), banks(&mbc));
+
+        mbc.write(0x6000, 0x01).unwrap();
+        assert_eq!((0x40, 0x45), banks(&mbc));
+
+        // Banks 0x20, 0x40 and 0x60 can't be mapped at 0x4000
+        mbc.write(0x2000, 0x00).unwrap();
+        assert_eq!((0x40, 0x41), banks(&mbc));
+
+        mbc.write(0x6000, 0x00).unwrap();
+        assert_eq!((0, 0x41), banks(&mbc));
+    }
+
+    #[test]
+    fn small_rom_wraps() {
+        let mut mbc = rom(32, 0x00, false);
+
+        mbc.write(0x2000, 0x12).unwrap();
+        mbc.write(0x4000, 0x03).unwrap();
+        mbc.write(0x6000, 0x01).unwrap();
+
+        assert_eq!((0, 0x12), banks(&mbc));
+    }
+
+    #[test]
+    fn ram_banks_only_in_mode_1() {
+        let mut mbc = rom(4, 0x03, false);
+        mbc.write(0x0000, 0x0A).unwrap();
+
+        for bank in 0..4 {
+            mbc.write(0x6000, 0x01).unwrap();
+            mbc.write(0x4000, bank).unwrap();
+            mbc.write(0xA000, 0x10 + bank).unwrap();
+        }
+
+        mbc.write(0x4000, 0x02).unwrap();
+        assert_eq!(0x12, mbc.read(0xA000).unwrap());
+
+        mbc.write(0x6000, 0x00).unwrap();
+        assert_eq!(0x10, mbc.read(0xA000).unwrap());
+    }
+
+    #[test]
+    fn ram_enable_looks_at_lower_bits() {
+        let mut mbc = rom(4, 0x02, false);
+
+        mbc.write(0x0000, 0x1A).unwrap();
+        mbc.write(0xA000, 0x42).unwrap();
+        assert_eq!(0x42, mbc.read(0xA000).unwrap());
+
+        mbc.write(0x0000, 0x0B).unwrap();
+        assert_eq!(0xFF, mbc.read(0xA000).unwrap());
+    }
+
+    #[test]
+    fn multicart_banks() {
+        assert!(!rom(64, 0x00, false).multicart);
+
+        let mut mbc = rom(64, 0x00, true);
+        assert!(mbc.multicart);
+
+        mbc.write(0x4000, 0x01).unwrap();
+        mbc.write(0x2000, 0x03).unwrap();
+        assert_eq!((0, 0x13), banks(&mbc));
+
+        // The top bit of the bank is not connected, but still counts for
+        // the check for 0
+        mbc.write(0x2000, 0x10).unwrap();
+        assert_eq!((0, 0x10), banks(&mbc));
+
+        mbc.write(0x6000, 0x01).unwrap();
+        mbc.write(0x4000, 0x03).unwrap();
+        assert_eq!((0x30, 0x30), banks(&mbc));
+    }
+
+    /// Runs the MBC1 tests of the Mooneye test suite, in the directory set
+    /// by `RUBOY_MOONEYE_MBC1` (`emulator-only/mbc1` of the suite's builds)
+    #[test]
+    #[ignore = "needs the Mooneye test ROMs"]
+    fn mooneye_mbc1() {
+        crate::testutils::assert_mooneye_passes("RUBOY_MOONEYE_MBC1", |_| true);
+    }
+}
@@ -0,0 +1,211 @@
+use crate::rom::meta::RomMeta;
+use crate::rom::rtc::Rtc;
+use crate::savestate::{StateData, StateLoadErr, StateReader, StateWriter};
+use crate::{GBAllocator, GBRam, RomReader};
+use alloc::boxed::Box;
+
+use super::cache::{BankCache, BankCaching, Slot};
+use super::{reader_err, Mbc, ReadError, WriteError};
+
+const RAM_BANK_SIZE: usize = 0x2000;
+
+#[derive(Debug)]
+pub struct Mbc3<A: GBAllocator, R: RomReader> {
+    meta: RomMeta,
+    rom: BankCache<A, R>,
+
+    /// The bank mapped in the high slot
+    rom_bank: u8,
+
+    /// All four RAM banks
+    ram: A::Mem<u8, { RAM_BANK_SIZE * 4 }>,
+
+    /// Only present if the cartridge has a timer
+    rtc: Option<Rtc>,
+
+    ram_rtc_enabled: bool,
+
+    /// Either a RAM bank (0x00-0x03) or RTC register (0x08-0x0C)
+    ram_rtc_select: u8,
+
+    /// Last value written to the latch register
+    latch_prev: u8,
+}
+
+impl<A: GBAllocator, R: RomReader> Mbc3<A, R> {
+    pub fn new(meta: RomMeta, reader: R, caching: BankCaching) -> Result<Self, R::Err> {
+        log::info!("Initializing MBC3 ROM mapper");
+
+        let rom = BankCache::new(reader, meta.rom_size().num_banks(), caching)?;
+
+        let rtc = if meta.cartridge_hardware().has_timer() {
+            Some(Rtc::new())
+        } else {
+            None
+        };
+
+        Ok(Self {
+            meta,
+            rom,
+            rom_bank: 1,
+            ram: A::empty(),
+            rtc,
+            ram_rtc_enabled: false,
+            ram_rtc_select: 0,
+            latch_prev: 0xFF,
+        })
+    }
+
+    pub fn set_bank_caching(&mut self, caching: BankCaching) -> Result<(), R::Err> {
+        self.rom.set_caching(caching)
+    }
+
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+
+    fn switch_rom_bank(&mut self, bank: usize) -> Result<(), R::Err> {
+        self.rom.map(Slot::High, bank)
+    }
+
+    fn ram_index(&self, addr: u16) -> Option<u16> {
+        let index = (self.ram_rtc_select as usize * RAM_BANK_SIZE) + (addr - 0xA000) as usize;
+
+        if index < self.meta.ram_size().in_bytes() {
+            Some(index as u16)
+        } else {
+            None
+        }
+    }
+
+    fn selects_rtc(&self) -> bool {
+        (0x08..=0x0C).contains(&self.ram_rtc_select)
+    }
+}
+
+impl<A: GBAllocator, R: RomReader> StateData for Mbc3<A, R> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.rom_bank.save_state(w);
+        w.write_bytes(self.ram.raw());
+        self.rtc.save_state(w);
+        self.ram_rtc_enabled.save_state(w);
+        self.ram_rtc_select.save_state(w);
+        self.latch_prev.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        self.rom_bank.load_state(r)?;
+        r.read_bytes_into(self.ram.raw_mut())?;
+        self.rtc.load_state(r)?;
+        self.ram_rtc_enabled.load_state(r)?;
+        self.ram_rtc_select.load_state(r)?;
+        self.latch_prev.load_state(r)?;
+
+        self.switch_rom_bank(self.rom_bank as usize)
+            .map_err(|e| StateLoadErr::Rom(Box::new(e)))
+    }
+}
+
+impl<A: GBAllocator, R: RomReader> Mbc for Mbc3<A, R> {
+    fn meta(&self) -> &RomMeta {
+        &self.meta
+    }
+
+    fn rom_bank(&self, slot: Slot) -> usize {
+        self.rom.mapped(slot)
+    }
+
+    fn ram(&self) -> &[u8] {
+        let size = self.meta.ram_size().in_bytes().min(RAM_BANK_SIZE * 4);
+        &self.ram.raw()[..size]
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        let size = self.meta.ram_size().in_bytes().min(RAM_BANK_SIZE * 4);
+        &mut self.ram.raw_mut()[..size]
+    }
+
+    fn read(&self, addr: u16) -> Result<u8, ReadError> {
+        match addr {
+            0x0000..=0x3FFF => Ok(self.rom.read(Slot::Low, addr)),
+            0x4000..=0x7FFF => Ok(self.rom.read(Slot::High, addr - 0x4000)),
+            0xA000..=0xBFFF => {
+                if !self.ram_rtc_enabled {
+                    return Ok(0xFF);
+                }
+
+                if self.selects_rtc() {
+                    return Ok(self
+                        .rtc
+                        .as_ref()
+                        .map(|rtc| rtc.read(self.ram_rtc_select))
+                        .unwrap_or(0xFF));
+                }
+
+                match self.ram_index(addr) {
+                    Some(index) => Ok(self.ram.read(index)),
+                    None => Err(ReadError::NotEnoughRam {
+                        addr,
+                        max: self.meta.ram_size().in_bytes() as u16,
+                    }),
+                }
+            }
+            _ => panic!("Address not a ROM address"),
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) -> Result<(), WriteError> {
+        match addr {
+            0x0000..=0x1FFF => {
+                self.ram_rtc_enabled = val & 0x0F == 0xA;
+                Ok(())
+            }
+            0x2000..=0x3FFF => {
+                // 7-bit register, bank 0 maps to bank 1
+                self.rom_bank = u8::max(1, val & 0b1111111);
+
+                self.switch_rom_bank(self.rom_bank as usize)
+                    .map_err(|e| reader_err(self.rom_bank as usize, e))
+            }
+            0x4000..=0x5FFF => {
+                self.ram_rtc_select = val;
+                Ok(())
+            }
+            0x6000..=0x7FFF => {
+                if self.latch_prev == 0x00 && val == 0x01 {
+                    if let Some(rtc) = self.rtc.as_mut() {
+                        rtc.latch();
+                    }
+                }
+
+                self.latch_prev = val;
+                Ok(())
+            }
+            0xA000..=0xBFFF => {
+                if !self.ram_rtc_enabled {
+                    return Ok(());
+                }
+
+                if self.selects_rtc() {
+                    if let Some(rtc) = self.rtc.as_mut() {
+                        rtc.write(self.ram_rtc_select, val);
+                    }
+
+                    return Ok(());
+                }
+
+                match self.ram_index(addr) {
+                    Some(index) => {
+                        self.ram.write(index, val);
+                        Ok(())
+                    }
+                    None => Err(WriteError::NotEnoughRam {
+                        addr,
+                        max: self.meta.ram_size().in_bytes() as u16,
+                    }),
+                }
+            }
+            _ => panic!("Address not a ROM address"),
+        }
+    }
+}
@@ -0,0 +1,234 @@
+use crate::rom::meta::RomMeta;
+use crate::savestate::{StateData, StateLoadErr, StateReader, StateWriter};
+use crate::{GBAllocator, RomReader};
+use alloc::boxed::Box;
+use alloc::vec;
+
+use super::cache::{BankCache, BankCaching, Slot};
+use super::{reader_err, Mbc, ReadError, WriteError};
+
+const RAM_BANK_SIZE: usize = 0x2000;
+
+/// On rumble cartridges, this bit of the RAM bank register drives the motor
+const RUMBLE_MOTOR: u8 = 0b1000;
+
+#[derive(Debug)]
+pub struct Mbc5<A: GBAllocator, R: RomReader> {
+    meta: RomMeta,
+    rom: BankCache<A, R>,
+
+    /// The bank mapped in the high slot. 9 bits, and unlike the older
+    /// mappers bank 0 can be selected.
+    rom_bank: u16,
+
+    /// As many RAM banks as the header says, up to sixteen. Too large to
+    /// keep inline, so always on the heap.
+    ram: Box<[u8]>,
+    ram_enabled: bool,
+    ram_bank: u8,
+
+    /// Whether the rumble motor is on. Always off for cartridges without one.
+    rumble: bool,
+}
+
+impl<A: GBAllocator, R: RomReader> Mbc5<A, R> {
+    pub fn new(meta: RomMeta, reader: R, caching: BankCaching) -> Result<Self, R::Err> {
+        log::info!("Initializing MBC5 ROM mapper");
+
+        let rom = BankCache::new(reader, meta.rom_size().num_banks(), caching)?;
+
+        let ram = vec![0; meta.ram_size().in_bytes()].into_boxed_slice();
+
+        Ok(Self {
+            meta,
+            rom,
+            rom_bank: 1,
+            ram,
+            ram_enabled: false,
+            ram_bank: 0,
+            rumble: false,
+        })
+    }
+
+    pub fn set_bank_caching(&mut self, caching: BankCaching) -> Result<(), R::Err> {
+        self.rom.set_caching(caching)
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.rumble
+    }
+
+    fn switch_rom_bank(&mut self, bank: usize) -> Result<(), R::Err> {
+        self.rom.map(Slot::High, bank)
+    }
+
+    fn ram_index(&self, addr: u16) -> Option<usize> {
+        let index = (self.ram_bank as usize * RAM_BANK_SIZE) + (addr - 0xA000) as usize;
+
+        (index < self.ram.len()).then_some(index)
+    }
+}
+
+impl<A: GBAllocator, R: RomReader> StateData for Mbc5<A, R> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.rom_bank.save_state(w);
+        w.write_bytes(&self.ram);
+        self.ram_enabled.save_state(w);
+        self.ram_bank.save_state(w);
+        self.rumble.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        self.rom_bank.load_state(r)?;
+        r.read_bytes_into(&mut self.ram)?;
+        self.ram_enabled.load_state(r)?;
+        self.ram_bank.load_state(r)?;
+        self.rumble.load_state(r)?;
+
+        self.switch_rom_bank(self.rom_bank as usize)
+            .map_err(|e| StateLoadErr::Rom(Box::new(e)))
+    }
+}
+
+impl<A: GBAllocator, R: RomReader> Mbc for Mbc5<A, R> {
+    fn meta(&self) -> &RomMeta {
+        &self.meta
+    }
+
+    fn rom_bank(&self, slot: Slot) -> usize {
+        self.rom.mapped(slot)
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        &mut self.ram
+    }
+
+    fn read(&self, addr: u16) -> Result<u8, ReadError> {
+        match addr {
+            0x0000..=0x3FFF => Ok(self.rom.read(Slot::Low, addr)),
+            0x4000..=0x7FFF => Ok(self.rom.read(Slot::High, addr - 0x4000)),
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return Ok(0xFF);
+                }
+
+                match self.ram_index(addr) {
+                    Some(index) => Ok(self.ram[index]),
+                    None => Err(ReadError::NotEnoughRam {
+                        addr,
+                        max: self.meta.ram_size().in_bytes() as u16,
+                    }),
+                }
+            }
+            _ => panic!("Address not a ROM address"),
+        }
+    }
+
+    fn write(&mut self, addr: u16, val: u8) -> Result<(), WriteError> {
+        match addr {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = val & 0x0F == 0xA;
+                Ok(())
+            }
+            0x2000..=0x3FFF => {
+                // Lower 8 bits, then the 9th bit
+                self.rom_bank = match addr {
+                    0x2000..=0x2FFF => (self.rom_bank & 0x100) | val as u16,
+                    _ => (self.rom_bank & 0xFF) | ((val as u16 & 0b1) << 8),
+                };
+
+                self.switch_rom_bank(self.rom_bank as usize)
+                    .map_err(|e| reader_err(self.rom_bank as usize, e))
+            }
+            0x4000..=0x5FFF => {
+                if self.meta.cartridge_hardware().has_rumble() {
+                    self.rumble = val & RUMBLE_MOTOR != 0;
+                    self.ram_bank = val & 0b0111;
+                } else {
+                    self.ram_bank = val & 0b1111;
+                }
+
+                Ok(())
+            }
+            0x6000..=0x7FFF => Ok(()),
+            0xA000..=0xBFFF => {
+                if !self.ram_enabled {
+                    return Ok(());
+                }
+
+                match self.ram_index(addr) {
+                    Some(index) => {
+                        self.ram[index] = val;
+                        Ok(())
+                    }
+                    None => Err(WriteError::NotEnoughRam {
+                        addr,
+                        max: self.meta.ram_size().in_bytes() as u16,
+                    }),
+                }
+            }
+            _ => panic!("Address not a ROM address"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::vec;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::InlineAllocator;
+
+    /// A ROM of `banks` banks with each bank filled with its number, and
+    /// the given cartridge type
+    fn rom(cartridge_type: u8, banks: usize) -> Mbc5<InlineAllocator, Cursor<Vec<u8>>> {
+        let mut rom: Vec<u8> = (0..banks)
+            .flat_map(|bank| vec![bank as u8; 0x4000])
+            .collect();
+
+        rom[0x147] = cartridge_type;
+        rom[0x148] = (banks / 2).trailing_zeros() as u8;
+        rom[0x149] = 0x03;
+
+        let header = &rom[RomMeta::OFFSET_HEADER_START..][..RomMeta::HEADER_LENGTH];
+        let meta = RomMeta::parse(header).unwrap();
+
+        Mbc5::new(meta, Cursor::new(rom), BankCaching::default()).unwrap()
+    }
+
+    #[test]
+    fn nine_bit_rom_bank() {
+        let mut mbc = rom(0x19, 512);
+
+        mbc.write(0x2000, 0x05).unwrap();
+        assert_eq!(5, mbc.read(0x4000).unwrap());
+
+        mbc.write(0x3000, 0x01).unwrap();
+        assert_eq!(0x105, mbc.rom_bank);
+
+        mbc.write(0x2000, 0x00).unwrap();
+        mbc.write(0x3000, 0x00).unwrap();
+        assert_eq!(0, mbc.read(0x4000).unwrap());
+    }
+
+    #[test]
+    fn rumble_bit() {
+        let mut mbc = rom(0x1D, 4);
+
+        mbc.write(0x0000, 0x0A).unwrap();
+        mbc.write(0x4000, 0x09).unwrap();
+        assert!(mbc.rumble());
+
+        // The motor bit does not select a RAM bank
+        mbc.write(0xA000, 0x42).unwrap();
+        mbc.write(0x4000, 0x01).unwrap();
+        assert!(!mbc.rumble());
+        assert_eq!(0x42, mbc.read(0xA000).unwrap());
+    }
+}
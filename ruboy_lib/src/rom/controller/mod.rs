@@ -1,31 +1,56 @@
 use mbc1::Mbc1;
+use mbc3::Mbc3;
+use mbc5::Mbc5;
 use nonbanking::NonBankingController;
 use thiserror::Error;
 
 use crate::extern_traits::GBAllocator;
+use crate::logging::{debug, Subsystem};
+use crate::quirks;
 use crate::rom::meta::CartridgeMapper;
+use crate::rom::rtc::Rtc;
+use crate::savestate::{StateData, StateLoadErr, StateReader, StateWriter};
 
 use super::meta::{RomMeta, RomMetaParseError};
 use crate::extern_traits::RomReader;
 
+use cache::Slot;
+
+pub use cache::BankCaching;
+
+mod cache;
 mod mbc1;
+mod mbc3;
+mod mbc5;
 mod nonbanking;
 
 trait Mbc {
+    fn meta(&self) -> &RomMeta;
     fn read(&self, addr: u16) -> Result<u8, ReadError>;
     fn write(&mut self, addr: u16, val: u8) -> Result<(), WriteError>;
+
+    /// All of the cartridge RAM, every bank in order
+    fn ram(&self) -> &[u8];
+
+    /// Mutable [Mbc::ram]
+    fn ram_mut(&mut self) -> &mut [u8];
+
+    /// The ROM bank mapped in the slot
+    fn rom_bank(&self, slot: Slot) -> usize;
 }
 
 #[derive(Debug)]
 #[allow(unused_associated_type_bounds)]
 pub enum RomController<A: GBAllocator, R: RomReader> {
-    None(NonBankingController<A>),
+    None(NonBankingController<A, R>),
     Mbc1(Mbc1<A, R>),
+    Mbc3(Mbc3<A, R>),
+    Mbc5(Mbc5<A, R>),
 }
 
 impl<A: GBAllocator, R: RomReader> RomController<A, R> {
-    pub fn new(mut rom: R) -> Result<Self, RomControllerInitErr<R>> {
-        log::debug!("Initializing ROM controller");
+    pub fn new(mut rom: R, caching: BankCaching) -> Result<Self, RomControllerInitErr<R>> {
+        debug!(Subsystem::Memory, "Initializing ROM controller");
 
         let header_bytes: [u8; RomMeta::HEADER_LENGTH] = rom
             .read(RomMeta::OFFSET_HEADER_START)
@@ -33,42 +58,148 @@ impl<A: GBAllocator, R: RomReader> RomController<A, R> {
 
         let meta = RomMeta::parse(&header_bytes)?;
 
-        log::debug!("Resolving ROM mapper type");
+        debug!(Subsystem::Memory, "Resolving ROM mapper type");
 
-        let controller = match meta.cartridge_hardware().mapper() {
+        let controller = match quirks::mapper(&meta) {
             Some(mapper) => match mapper {
                 CartridgeMapper::MBC1 => RomController::Mbc1(
-                    Mbc1::new(meta, rom).map_err(|e| RomControllerInitErr::Read(e))?,
+                    Mbc1::new(meta, rom, caching).map_err(|e| RomControllerInitErr::Read(e))?,
+                ),
+                CartridgeMapper::MBC3 => RomController::Mbc3(
+                    Mbc3::new(meta, rom, caching).map_err(|e| RomControllerInitErr::Read(e))?,
                 ),
-                _ => todo!("ROM controller not yet implemented: {}", mapper),
+                CartridgeMapper::MBC5 => RomController::Mbc5(
+                    Mbc5::new(meta, rom, caching).map_err(|e| RomControllerInitErr::Read(e))?,
+                ),
+                mapper => return Err(RomControllerInitErr::UnsupportedMapper(mapper)),
             },
             None => RomController::None(
-                NonBankingController::new(meta, rom).map_err(|e| RomControllerInitErr::Read(e))?,
+                NonBankingController::new(meta, rom, caching)
+                    .map_err(|e| RomControllerInitErr::Read(e))?,
             ),
         };
 
         Ok(controller)
     }
 
+    pub fn meta(&self) -> &RomMeta {
+        match self {
+            RomController::None(c) => c.meta(),
+            RomController::Mbc1(mbc) => mbc.meta(),
+            RomController::Mbc3(mbc) => mbc.meta(),
+            RomController::Mbc5(mbc) => mbc.meta(),
+        }
+    }
+
+    /// The real time clock of the cartridge, if it has one
+    pub fn rtc_mut(&mut self) -> Option<&mut Rtc> {
+        match self {
+            RomController::Mbc3(mbc) => mbc.rtc_mut(),
+            _ => None,
+        }
+    }
+
+    /// See [BankCaching]
+    pub fn set_bank_caching(&mut self, caching: BankCaching) -> Result<(), R::Err> {
+        match self {
+            RomController::None(c) => c.set_bank_caching(caching),
+            RomController::Mbc1(mbc) => mbc.set_bank_caching(caching),
+            RomController::Mbc3(mbc) => mbc.set_bank_caching(caching),
+            RomController::Mbc5(mbc) => mbc.set_bank_caching(caching),
+        }
+    }
+
+    /// Whether the rumble motor of the cartridge is on
+    pub fn rumble(&self) -> bool {
+        match self {
+            RomController::Mbc5(mbc) => mbc.rumble(),
+            _ => false,
+        }
+    }
+
     pub fn read(&self, addr: u16) -> Result<u8, ReadError> {
         let result = match self {
             RomController::None(c) => c.read(addr)?,
             RomController::Mbc1(mbc) => mbc.read(addr)?,
+            RomController::Mbc3(mbc) => mbc.read(addr)?,
+            RomController::Mbc5(mbc) => mbc.read(addr)?,
         };
 
         Ok(result)
     }
 
+    /// The ROM bank the address is mapped to right now, or [None] for
+    /// addresses outside of the cartridge ROM
+    pub fn rom_bank(&self, addr: u16) -> Option<usize> {
+        let slot = match addr {
+            0x0000..=0x3FFF => Slot::Low,
+            0x4000..=0x7FFF => Slot::High,
+            _ => return None,
+        };
+
+        let bank = match self {
+            RomController::None(c) => c.rom_bank(slot),
+            RomController::Mbc1(mbc) => mbc.rom_bank(slot),
+            RomController::Mbc3(mbc) => mbc.rom_bank(slot),
+            RomController::Mbc5(mbc) => mbc.rom_bank(slot),
+        };
+
+        Some(bank)
+    }
+
+    /// All of the cartridge RAM, every bank in order, no matter which bank
+    /// is mapped or whether the RAM is enabled
+    pub fn ram(&self) -> &[u8] {
+        match self {
+            RomController::None(c) => c.ram(),
+            RomController::Mbc1(mbc) => mbc.ram(),
+            RomController::Mbc3(mbc) => mbc.ram(),
+            RomController::Mbc5(mbc) => mbc.ram(),
+        }
+    }
+
+    /// Mutable [RomController::ram]
+    pub fn ram_mut(&mut self) -> &mut [u8] {
+        match self {
+            RomController::None(c) => c.ram_mut(),
+            RomController::Mbc1(mbc) => mbc.ram_mut(),
+            RomController::Mbc3(mbc) => mbc.ram_mut(),
+            RomController::Mbc5(mbc) => mbc.ram_mut(),
+        }
+    }
+
     pub fn write(&mut self, addr: u16, val: u8) -> Result<(), WriteError> {
         match self {
             RomController::None(c) => c.write(addr, val)?,
             RomController::Mbc1(mbc) => mbc.write(addr, val)?,
+            RomController::Mbc3(mbc) => mbc.write(addr, val)?,
+            RomController::Mbc5(mbc) => mbc.write(addr, val)?,
         };
 
         Ok(())
     }
 }
 
+impl<A: GBAllocator, R: RomReader> StateData for RomController<A, R> {
+    fn save_state(&self, w: &mut StateWriter) {
+        match self {
+            RomController::None(c) => c.save_state(w),
+            RomController::Mbc1(mbc) => mbc.save_state(w),
+            RomController::Mbc3(mbc) => mbc.save_state(w),
+            RomController::Mbc5(mbc) => mbc.save_state(w),
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        match self {
+            RomController::None(c) => c.load_state(r),
+            RomController::Mbc1(mbc) => mbc.load_state(r),
+            RomController::Mbc3(mbc) => mbc.load_state(r),
+            RomController::Mbc5(mbc) => mbc.load_state(r),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Error)]
 pub enum RomControllerInitErr<R: RomReader> {
     #[error("Error reading ROM file: {0}")]
@@ -76,18 +207,24 @@ pub enum RomControllerInitErr<R: RomReader> {
 
     #[error("Error parsing ROM file: {0}")]
     Parse(#[from] RomMetaParseError),
+
+    #[error("Cartridges with the {0} mapper are not supported yet")]
+    UnsupportedMapper(CartridgeMapper),
 }
 
-#[derive(Debug, Error)]
+// Every memory access can fail with these, so they are small and never
+// allocate. What went wrong in the reader is logged instead of kept.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
 pub enum ReadError {
     #[error("RAM address {addr} out of reach for this cartridge (max {max})")]
     NotEnoughRam { addr: u16, max: u16 },
 
-    #[error("Error with RomReader: {}", 0)]
-    Reader(Box<dyn std::error::Error>),
+    #[error("Injected cartridge fault")]
+    Fault,
 }
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
 pub enum WriteError {
     #[error("RAM address {addr} out of reach for this cartridge (max {max})")]
     NotEnoughRam { addr: u16, max: u16 },
@@ -95,8 +232,14 @@ pub enum WriteError {
     #[error("Address is read only: 0x{:x}", .0)]
     ReadOnly(u16),
 
-    #[error("Error with RomReader: {}", 0)]
-    Reader(Box<dyn std::error::Error>),
+    #[error("Could not load ROM bank {0}, see the log for the error of the reader")]
+    Reader(u16),
+}
+
+/// For bank switches that failed to load `bank`
+fn reader_err(bank: usize, err: impl core::error::Error) -> WriteError {
+    log::error!("Error with RomReader while loading bank {}: {}", bank, err);
+    WriteError::Reader(bank as u16)
 }
 
 /// Converts a bank index to an address within the ROM
@@ -1,38 +1,70 @@
-use crate::rom::controller::bank_num_to_addr;
+use crate::savestate::{StateData, StateLoadErr, StateReader, StateWriter};
 use crate::{
     extern_traits::{GBAllocator, GBRam, RomReader},
     rom::meta::RomMeta,
 };
 
+use super::cache::{BankCache, BankCaching, Slot};
 use super::{Mbc, ReadError, WriteError};
 
 #[derive(Debug)]
-pub struct NonBankingController<A: GBAllocator> {
+pub struct NonBankingController<A: GBAllocator, R: RomReader> {
     meta: RomMeta,
-    rom_content: A::Mem<u8, 0x8000>,
+
+    /// Always banks 0 and 1
+    rom: BankCache<A, R>,
     ram_content: A::Mem<u8, 0x2000>,
 }
 
-impl<A: GBAllocator> NonBankingController<A> {
-    pub fn new<R: RomReader>(meta: RomMeta, mut reader: R) -> Result<Self, R::Err> {
+impl<A: GBAllocator, R: RomReader> NonBankingController<A, R> {
+    pub fn new(meta: RomMeta, reader: R, caching: BankCaching) -> Result<Self, R::Err> {
         log::info!("Initializing non-banking (MBC0) ROM mapper");
 
-        let mut new = Self {
+        Ok(Self {
+            rom: BankCache::new(reader, 2, caching)?,
             meta,
-            rom_content: A::empty(),
             ram_content: A::empty(),
-        };
+        })
+    }
 
-        reader.read_into(new.rom_content.raw_mut(), bank_num_to_addr(0))?;
+    pub fn set_bank_caching(&mut self, caching: BankCaching) -> Result<(), R::Err> {
+        self.rom.set_caching(caching)
+    }
+}
 
-        Ok(new)
+impl<A: GBAllocator, R: RomReader> StateData for NonBankingController<A, R> {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(self.ram_content.raw());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        r.read_bytes_into(self.ram_content.raw_mut())
     }
 }
 
-impl<A: GBAllocator> Mbc for NonBankingController<A> {
+impl<A: GBAllocator, R: RomReader> Mbc for NonBankingController<A, R> {
+    fn meta(&self) -> &RomMeta {
+        &self.meta
+    }
+
+    fn rom_bank(&self, slot: Slot) -> usize {
+        self.rom.mapped(slot)
+    }
+
+    fn ram(&self) -> &[u8] {
+        let size = self.meta.ram_size().in_bytes().min(0x2000);
+        &self.ram_content.raw()[..size]
+    }
+
+    fn ram_mut(&mut self) -> &mut [u8] {
+        let size = self.meta.ram_size().in_bytes().min(0x2000);
+        &mut self.ram_content.raw_mut()[..size]
+    }
+
     fn read(&self, addr: u16) -> Result<u8, ReadError> {
         match addr {
-            0x0000..=0x7FFF => Ok(self.rom_content.read(addr)),
+            0x0000..=0x3FFF => Ok(self.rom.read(Slot::Low, addr)),
+            0x4000..=0x7FFF => Ok(self.rom.read(Slot::High, addr - 0x4000)),
             0xA000..=0xBFFF => {
                 let ram_index = addr - 0xA000;
                 let ram_size = self.meta.ram_size().in_bytes();
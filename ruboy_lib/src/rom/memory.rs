@@ -0,0 +1,137 @@
+//! [RomReader]s that keep the whole ROM in memory. The ROM controllers read
+//! banks straight from these, so switching banks never copies anything.
+
+#[cfg(target_has_atomic = "ptr")]
+use alloc::sync::Arc;
+#[cfg(target_has_atomic = "ptr")]
+use alloc::vec::Vec;
+
+#[cfg(target_has_atomic = "ptr")]
+use crate::extern_traits::{read_from_slice, RomBufferErr};
+#[cfg(target_has_atomic = "ptr")]
+use crate::RomReader;
+
+/// A ROM loaded into memory. Cloning it is cheap, as all clones share the
+/// same bytes.
+#[cfg(target_has_atomic = "ptr")]
+#[derive(Debug, Clone)]
+pub struct InMemoryRom(Arc<[u8]>);
+
+#[cfg(target_has_atomic = "ptr")]
+impl InMemoryRom {
+    pub fn new(rom: impl Into<Arc<[u8]>>) -> Self {
+        Self(rom.into())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl From<Vec<u8>> for InMemoryRom {
+    fn from(rom: Vec<u8>) -> Self {
+        Self(rom.into())
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl From<Arc<[u8]>> for InMemoryRom {
+    fn from(rom: Arc<[u8]>) -> Self {
+        Self(rom)
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl RomReader for InMemoryRom {
+    type Err = RomBufferErr;
+
+    fn read_into(&mut self, buf: &mut [u8], addr: usize) -> Result<(), Self::Err> {
+        read_from_slice(&self.0, buf, addr)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(&self.0)
+    }
+}
+
+/// A ROM file mapped into memory. Pages are only read from disk once they
+/// are first accessed, which suits large ROMs of which little is used.
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct MmapRom(memmap2::Mmap);
+
+#[cfg(feature = "mmap")]
+impl MmapRom {
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+
+        // SAFETY: The map is read-only. Like any other memory map, it is
+        // undefined behaviour if the file is changed while mapped, which ROM
+        // files only are when the user goes out of their way to do so.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+
+        Ok(Self(map))
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(feature = "mmap")]
+impl RomReader for MmapRom {
+    type Err = RomBufferErr;
+
+    fn read_into(&mut self, buf: &mut [u8], addr: usize) -> Result<(), Self::Err> {
+        read_from_slice(&self.0, buf, addr)
+    }
+
+    fn as_slice(&self) -> Option<&[u8]> {
+        Some(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::vec;
+
+    use super::*;
+
+    #[test]
+    fn in_memory_reads() {
+        let mut rom = InMemoryRom::from(vec![1, 2, 3, 4]);
+
+        assert_eq!([2, 3], rom.read::<2>(1).unwrap());
+        assert_eq!(Some(&[1, 2, 3, 4][..]), rom.as_slice());
+    }
+
+    #[test]
+    fn in_memory_reads_past_the_end() {
+        let mut rom = InMemoryRom::from(vec![1, 2, 3, 4]);
+
+        assert_eq!(Err(RomBufferErr { addr: 3, len: 2 }), rom.read::<2>(3));
+        assert!(rom.read::<1>(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn in_memory_clones_share_the_rom() {
+        let rom = InMemoryRom::from(vec![0; 0x8000]);
+        let clone = rom.clone();
+
+        assert!(core::ptr::eq(rom.as_bytes(), clone.as_bytes()));
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn mmap_reads() {
+        let path = std::env::temp_dir().join(format!("ruboy-mmap-{}.gb", std::process::id()));
+        std::fs::write(&path, [5, 6, 7, 8]).unwrap();
+
+        let mut rom = MmapRom::open(&path).unwrap();
+        assert_eq!([7, 8], rom.read::<2>(2).unwrap());
+        assert!(rom.read::<2>(3).is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
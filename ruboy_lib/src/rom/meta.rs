@@ -1,8 +1,9 @@
-use std::{fmt::Display, io::Read, num::Wrapping};
+use alloc::string::{String, ToString};
+use core::fmt::Display;
 
 use thiserror::Error;
 
-use crate::rom::licensee;
+use crate::{model::StateDigest, rom::licensee};
 
 #[derive(Debug, Clone)]
 pub struct RomMeta {
@@ -19,6 +20,7 @@ pub struct RomMeta {
     header_checksum: u8,
     header_checksum_valid: bool,
     global_checksum: u16,
+    header_hash: u64,
 }
 
 impl RomMeta {
@@ -73,6 +75,18 @@ impl RomMeta {
     pub fn global_checksum(&self) -> u16 {
         self.global_checksum
     }
+
+    /// Stable hash of the title up to and including the global checksum.
+    /// Identifies a specific release of a game, see [crate::quirks]
+    pub fn header_hash(&self) -> u64 {
+        self.header_hash
+    }
+}
+
+fn header_hash(bytes: &[u8]) -> u64 {
+    let mut digest = StateDigest::new();
+    digest.write_bytes(bytes);
+    digest.finish()
 }
 
 fn get_last_nonnull_idx(bytes: &[u8]) -> usize {
@@ -91,6 +105,14 @@ impl RomMeta {
     pub const OFFSET_LOGO: usize = 0x104;
     pub const OFFSET_LOGO_START: usize = 0x104 - Self::OFFSET_HEADER_START;
 
+    /// The Nintendo logo every ROM has to contain, or the boot ROM locks up
+    pub const LOGO: [u8; 48] = [
+        0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00,
+        0x0D, 0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD,
+        0xD9, 0x99, 0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB,
+        0xB9, 0x33, 0x3E,
+    ];
+
     pub const OFFSET_TITLE: usize = 0x134;
     pub const OFFSET_TITLE_START: usize = 0x134 - Self::OFFSET_HEADER_START;
 
@@ -214,6 +236,7 @@ impl RomMeta {
                 header_checksum,
             ),
             global_checksum,
+            header_hash: header_hash(&header_bytes[Self::OFFSET_TITLE_START..Self::HEADER_LENGTH]),
         };
 
         Ok(meta)
@@ -222,13 +245,30 @@ impl RomMeta {
     pub fn verify_header_checksum(header_bytes: &[u8], header_checksum: u8) -> bool {
         debug_assert_eq!(Self::HEADER_LENGTH, header_bytes.len());
 
+        Self::compute_header_checksum(header_bytes) == header_checksum
+    }
+
+    /// The header checksum the boot ROM expects, computed over the header
+    /// bytes from 0x100
+    pub fn compute_header_checksum(header_bytes: &[u8]) -> u8 {
         let mut computed_checksum = 0u8;
 
         for byte in &header_bytes[Self::OFFSET_TITLE_START..Self::OFFSET_HEADER_CHECKSUM_START] {
             computed_checksum = computed_checksum.wrapping_add(!byte);
         }
 
-        computed_checksum == header_checksum
+        computed_checksum
+    }
+
+    /// The global checksum of a whole ROM: the sum of all its bytes, except
+    /// those of the checksum itself. Real hardware never checks it.
+    pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+        rom.iter()
+            .enumerate()
+            .filter(|(idx, _)| {
+                !(Self::OFFSET_GLOBAL_CHECKSUM..Self::OFFSET_HEADER_END).contains(idx)
+            })
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16))
     }
 }
 
@@ -244,7 +284,7 @@ pub struct Manufacturer {
 }
 
 impl Display for Manufacturer {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(f, "{}", u32::from_be_bytes(self.raw))
     }
 }
@@ -268,7 +308,7 @@ pub enum Licensee {
 }
 
 impl Display for Licensee {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             Licensee::Old { raw } => match licensee::find_old(*raw) {
                 Some(licensee) => write!(f, "{}", licensee.names.join("/")),
@@ -300,7 +340,7 @@ pub enum CgbFlag {
 }
 
 impl Display for CgbFlag {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             CgbFlag::NoCgb => write!(f, "No CGB support"),
             CgbFlag::CgbBackwards => write!(f, "CGB support (backwards compatible)"),
@@ -319,7 +359,7 @@ impl From<u8> for CgbFlag {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum CartridgeMapper {
     MBC1,
     MBC2,
@@ -334,7 +374,7 @@ pub enum CartridgeMapper {
 }
 
 impl Display for CartridgeMapper {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
             CartridgeMapper::MBC1 => write!(f, "MBC1"),
             CartridgeMapper::MBC2 => write!(f, "MBC2"),
@@ -531,7 +571,7 @@ pub struct RomSize {
 }
 
 impl Display for RomSize {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "{} KiB ({} banks)",
@@ -562,7 +602,7 @@ impl RomSize {
 
     pub const fn num_banks(&self) -> usize {
         const KB: usize = 1024;
-        const BANK_SIZE: usize = 32 * KB;
+        const BANK_SIZE: usize = 16 * KB;
 
         self.in_bytes() / BANK_SIZE
     }
@@ -574,7 +614,7 @@ pub struct RamSize {
 }
 
 impl Display for RamSize {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         write!(
             f,
             "{} KiB ({} banks)",
@@ -625,7 +665,7 @@ pub enum Destination {
 }
 
 impl Display for Destination {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let dest = match self {
             Destination::Japan => "Japan",
             Destination::Elsewhere => "Elsewhere",
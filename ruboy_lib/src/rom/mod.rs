@@ -1,8 +1,9 @@
-use std::{
-    error::Error,
-    io::{Read, Seek},
-};
-
+pub mod capabilities;
 pub(crate) mod controller;
 pub mod licensee;
+mod memory;
 pub mod meta;
+pub mod rtc;
+
+pub use controller::BankCaching;
+pub use memory::*;
@@ -0,0 +1,418 @@
+use cfg_if::cfg_if;
+
+use thiserror::Error;
+
+use crate::savestate::{StateData, StateLoadErr, StateReader, StateWriter};
+use crate::CLOCK_SPEED_HZ;
+
+const SECS_PER_MINUTE: u64 = 60;
+const SECS_PER_HOUR: u64 = 60 * SECS_PER_MINUTE;
+const SECS_PER_DAY: u64 = 24 * SECS_PER_HOUR;
+const MAX_DAYS: u64 = 512;
+
+const DH_DAY_HIGH: u8 = 0b1;
+const DH_HALT: u8 = 0b1000000;
+const DH_DAY_CARRY: u8 = 0b10000000;
+
+/// The five clock registers of the MBC3 real time clock
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RtcRegisters {
+    /// 0x08
+    pub seconds: u8,
+
+    /// 0x09
+    pub minutes: u8,
+
+    /// 0x0A
+    pub hours: u8,
+
+    /// 0x0B, lower 8 bits of the day counter
+    pub days_low: u8,
+
+    /// 0x0C, upper bit of the day counter, halt flag and day carry flag
+    pub days_high: u8,
+}
+
+impl RtcRegisters {
+    pub const fn halted(&self) -> bool {
+        self.days_high & DH_HALT != 0
+    }
+
+    pub const fn days(&self) -> u16 {
+        (((self.days_high & DH_DAY_HIGH) as u16) << 8) | self.days_low as u16
+    }
+
+    pub fn read(&self, reg: u8) -> u8 {
+        match reg {
+            0x08 => self.seconds,
+            0x09 => self.minutes,
+            0x0A => self.hours,
+            0x0B => self.days_low,
+            0x0C => self.days_high,
+            _ => panic!("Not an RTC register: 0x{:x}", reg),
+        }
+    }
+
+    pub fn write(&mut self, reg: u8, val: u8) {
+        match reg {
+            0x08 => self.seconds = val & 0b111111,
+            0x09 => self.minutes = val & 0b111111,
+            0x0A => self.hours = val & 0b11111,
+            0x0B => self.days_low = val,
+            0x0C => self.days_high = val & (DH_DAY_HIGH | DH_HALT | DH_DAY_CARRY),
+            _ => panic!("Not an RTC register: 0x{:x}", reg),
+        }
+    }
+
+    /// Advances the clock by the given amount of seconds, setting the day
+    /// carry flag if the day counter overflows
+    fn advance(&mut self, secs: u64) {
+        if secs == 0 {
+            return;
+        }
+
+        let cur = self.seconds as u64
+            + (self.minutes as u64 * SECS_PER_MINUTE)
+            + (self.hours as u64 * SECS_PER_HOUR)
+            + (self.days() as u64 * SECS_PER_DAY);
+
+        let total = cur + secs;
+        let mut days = total / SECS_PER_DAY;
+
+        if days >= MAX_DAYS {
+            self.days_high |= DH_DAY_CARRY;
+            days %= MAX_DAYS;
+        }
+
+        self.seconds = (total % SECS_PER_MINUTE) as u8;
+        self.minutes = ((total % SECS_PER_HOUR) / SECS_PER_MINUTE) as u8;
+        self.hours = ((total % SECS_PER_DAY) / SECS_PER_HOUR) as u8;
+        self.days_low = (days & 0xFF) as u8;
+        self.days_high = (self.days_high & !DH_DAY_HIGH) | ((days >> 8) as u8 & DH_DAY_HIGH);
+    }
+}
+
+/// Where a [Rtc] gets the current time from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RtcClock {
+    /// The clock of the host machine
+    #[default]
+    Host,
+
+    /// A clock that only runs along with the emulated CPU, advancing one
+    /// second every [CLOCK_SPEED_HZ] cycles. Used for reproducible runs,
+    /// see [crate::model::RuboyConfig::deterministic].
+    Emulated {
+        /// Seconds since the clock started
+        now: u64,
+
+        /// Cycles since `now` last advanced
+        cycles: u32,
+    },
+}
+
+/// The MBC3 real time clock, backed by the host clock by default.
+///
+/// The clock remembers the time it was last brought up to date at. When
+/// a saved clock is loaded again later, the time that passed while the
+/// emulator was closed is added on the next access, unless the game halted
+/// the clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rtc {
+    current: RtcRegisters,
+    latched: RtcRegisters,
+
+    /// Time (UNIX seconds for the host clock) the current registers
+    /// correspond to
+    last_update: u64,
+    clock: RtcClock,
+}
+
+#[derive(Debug, Error, Clone, Copy)]
+pub enum RtcLoadErr {
+    #[error("RTC save data has wrong length: {0}, wanted {1}")]
+    WrongLength(usize, usize),
+}
+
+impl Rtc {
+    /// Length of the serialized clock. Matches the RTC footer that
+    /// BGB and VBA-M append to save files
+    pub const SAVE_LENGTH: usize = 48;
+
+    pub fn new() -> Self {
+        Self::new_at(host_now())
+    }
+
+    pub const fn new_at(now: u64) -> Self {
+        Self {
+            current: RtcRegisters {
+                seconds: 0,
+                minutes: 0,
+                hours: 0,
+                days_low: 0,
+                days_high: 0,
+            },
+            latched: RtcRegisters {
+                seconds: 0,
+                minutes: 0,
+                hours: 0,
+                days_low: 0,
+                days_high: 0,
+            },
+            last_update: now,
+            clock: RtcClock::Host,
+        }
+    }
+
+    /// A clock that runs on emulated time instead of the host clock, see
+    /// [RtcClock::Emulated]
+    pub const fn emulated() -> Self {
+        let mut rtc = Self::new_at(0);
+        rtc.clock = RtcClock::Emulated { now: 0, cycles: 0 };
+        rtc
+    }
+
+    pub const fn clock(&self) -> RtcClock {
+        self.clock
+    }
+
+    /// Switches to another clock. The registers keep their value, time
+    /// that passed since the last update is dropped.
+    pub fn set_clock(&mut self, clock: RtcClock) {
+        self.clock = clock;
+        self.last_update = self.now();
+    }
+
+    fn now(&self) -> u64 {
+        match self.clock {
+            RtcClock::Host => host_now(),
+            RtcClock::Emulated { now, .. } => now,
+        }
+    }
+
+    /// Runs a T-cycle, which only matters for an emulated clock
+    #[inline]
+    pub(crate) fn cycle(&mut self) {
+        if let RtcClock::Emulated { now, cycles } = &mut self.clock {
+            *cycles += 1;
+
+            if *cycles as usize == CLOCK_SPEED_HZ {
+                *cycles = 0;
+                *now += 1;
+            }
+        }
+    }
+
+    pub const fn current(&self) -> RtcRegisters {
+        self.current
+    }
+
+    pub const fn latched(&self) -> RtcRegisters {
+        self.latched
+    }
+
+    pub const fn last_update(&self) -> u64 {
+        self.last_update
+    }
+
+    /// Brings the clock up to date with the given host time. While halted,
+    /// time does not advance.
+    pub fn sync_to(&mut self, now: u64) {
+        if !self.current.halted() {
+            self.current.advance(now.saturating_sub(self.last_update));
+        }
+
+        self.last_update = now;
+    }
+
+    pub fn sync(&mut self) {
+        self.sync_to(self.now());
+    }
+
+    pub fn latch(&mut self) {
+        self.sync();
+        self.latched = self.current;
+    }
+
+    /// Reads a latched RTC register
+    pub fn read(&self, reg: u8) -> u8 {
+        self.latched.read(reg)
+    }
+
+    /// Writes an RTC register. The clock is brought up to date first, so that
+    /// the time passed before the write is not lost or applied twice
+    pub fn write(&mut self, reg: u8, val: u8) {
+        self.sync();
+        self.current.write(reg, val);
+    }
+
+    pub fn to_save_bytes(&self) -> [u8; Self::SAVE_LENGTH] {
+        let mut buf = [0u8; Self::SAVE_LENGTH];
+
+        for (idx, reg) in (0x08..=0x0C).enumerate() {
+            let current = self.current.read(reg) as u32;
+            let latched = self.latched.read(reg) as u32;
+
+            buf[idx * 4..(idx + 1) * 4].copy_from_slice(&current.to_le_bytes());
+            buf[20 + (idx * 4)..20 + ((idx + 1) * 4)].copy_from_slice(&latched.to_le_bytes());
+        }
+
+        buf[40..].copy_from_slice(&self.last_update.to_le_bytes());
+
+        buf
+    }
+
+    pub fn from_save_bytes(bytes: &[u8]) -> Result<Self, RtcLoadErr> {
+        if bytes.len() != Self::SAVE_LENGTH {
+            return Err(RtcLoadErr::WrongLength(bytes.len(), Self::SAVE_LENGTH));
+        }
+
+        let mut rtc = Self::new_at(u64::from_le_bytes(bytes[40..].try_into().unwrap()));
+
+        for (idx, reg) in (0x08..=0x0C).enumerate() {
+            rtc.current.write(reg, bytes[idx * 4]);
+            rtc.latched.write(reg, bytes[20 + (idx * 4)]);
+        }
+
+        Ok(rtc)
+    }
+}
+
+impl StateData for Rtc {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.to_save_bytes());
+
+        match self.clock {
+            RtcClock::Host => w.write_u8(0),
+            RtcClock::Emulated { now, cycles } => {
+                w.write_u8(1);
+                now.save_state(w);
+                cycles.save_state(w);
+            }
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        let bytes: [u8; Self::SAVE_LENGTH] = r.read_array()?;
+
+        let mut rtc = Self::from_save_bytes(&bytes).map_err(|_| StateLoadErr::Invalid("RTC"))?;
+
+        rtc.clock = match r.read_u8()? {
+            0 => RtcClock::Host,
+            1 => {
+                let (mut now, mut cycles) = (0u64, 0u32);
+                now.load_state(r)?;
+                cycles.load_state(r)?;
+
+                RtcClock::Emulated { now, cycles }
+            }
+            _ => return Err(StateLoadErr::Invalid("RTC clock")),
+        };
+
+        *self = rtc;
+        Ok(())
+    }
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+cfg_if! {
+    if #[cfg(not(feature = "std"))] {
+        use core::sync::atomic::{AtomicU32, Ordering};
+
+        static HOST_TIME: AtomicU32 = AtomicU32::new(0);
+
+        /// Sets the current Unix time in seconds, for the cartridge clock.
+        /// Without std there is no system clock, so embedders have to keep
+        /// this up to date themselves.
+        pub fn set_host_time(unix_secs: u32) {
+            HOST_TIME.store(unix_secs, Ordering::Relaxed);
+        }
+
+        fn host_now() -> u64 {
+            HOST_TIME.load(Ordering::Relaxed).into()
+        }
+    } else if #[cfg(all(target_arch = "wasm32", target_os = "unknown"))] {
+        /// There is no system clock in the browser, so ask JavaScript
+        fn host_now() -> u64 {
+            (js_sys::Date::now() / 1000.0) as u64
+        }
+    } else {
+        fn host_now() -> u64 {
+            use std::time::{SystemTime, UNIX_EPOCH};
+
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catches_up_after_closing() {
+        let mut rtc = Rtc::new_at(1000);
+        let saved = rtc.to_save_bytes();
+
+        // Two days, three hours and five seconds later
+        let mut loaded = Rtc::from_save_bytes(&saved).unwrap();
+        loaded.sync_to(1000 + (2 * SECS_PER_DAY) + (3 * SECS_PER_HOUR) + 5);
+
+        let regs = loaded.current();
+        assert_eq!(5, regs.seconds);
+        assert_eq!(0, regs.minutes);
+        assert_eq!(3, regs.hours);
+        assert_eq!(2, regs.days());
+
+        rtc.sync_to(1000);
+        assert_eq!(RtcRegisters::default(), rtc.current());
+    }
+
+    #[test]
+    fn halted_clock_does_not_advance() {
+        let mut rtc = Rtc::new_at(1000);
+        rtc.current.write(0x0C, DH_HALT);
+
+        let mut loaded = Rtc::from_save_bytes(&rtc.to_save_bytes()).unwrap();
+        loaded.sync_to(5000);
+
+        assert_eq!(0, loaded.current().seconds);
+        assert_eq!(5000, loaded.last_update());
+    }
+
+    #[test]
+    fn day_counter_overflow_sets_carry() {
+        let mut rtc = Rtc::new_at(0);
+        rtc.sync_to(MAX_DAYS * SECS_PER_DAY + 1);
+
+        let regs = rtc.current();
+        assert_eq!(0, regs.days());
+        assert_eq!(1, regs.seconds);
+        assert!(regs.days_high & DH_DAY_CARRY != 0);
+    }
+
+    #[test]
+    fn emulated_clock_follows_cycles() {
+        let mut rtc = Rtc::emulated();
+
+        for _ in 0..(CLOCK_SPEED_HZ * 3) - 1 {
+            rtc.cycle();
+        }
+
+        rtc.latch();
+        assert_eq!(2, rtc.read(0x08));
+
+        rtc.cycle();
+        rtc.latch();
+        assert_eq!(3, rtc.read(0x08));
+        assert_eq!(RtcClock::Emulated { now: 3, cycles: 0 }, rtc.clock());
+    }
+}
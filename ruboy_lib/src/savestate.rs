@@ -0,0 +1,322 @@
+//! Serialization of the complete emulator state, for save states and rewind.
+//!
+//! The format is a flat list of fields in a fixed order, without any
+//! self-description. Every component writes its fields through
+//! [StateData], usually generated with [state_data]. Any change to a
+//! component's fields changes the format, so [STATE_VERSION] must be bumped
+//! along with it.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::error::Error;
+use core::num::Wrapping;
+
+use thiserror::Error;
+
+use crate::{movie, GbInputs};
+
+/// Identifies a Ruboy save state
+pub(crate) const STATE_MAGIC: [u8; 4] = *b"RBST";
+
+/// Bumped on every change to the state layout. States of other versions are
+/// rejected.
+pub(crate) const STATE_VERSION: u16 = 10;
+
+#[derive(Debug, Error)]
+pub enum StateLoadErr {
+    #[error("Not a Ruboy save state")]
+    BadMagic,
+
+    #[error("Unsupported save state version {0}, expected {STATE_VERSION}")]
+    Version(u16),
+
+    #[error("Save state was made with a different ROM")]
+    WrongRom,
+
+    #[error("Save state is truncated")]
+    Truncated,
+
+    #[error("Save state has {0} unexpected trailing bytes")]
+    TrailingData(usize),
+
+    #[error("Invalid {0} in save state")]
+    Invalid(&'static str),
+
+    #[error("Could not reload ROM bank: {0}")]
+    Rom(#[source] Box<dyn Error>),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct StateReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub const fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, StateLoadErr> {
+        let [val] = self.read_array()?;
+        Ok(val)
+    }
+
+    pub fn read_array<const N: usize>(&mut self) -> Result<[u8; N], StateLoadErr> {
+        let mut out = [0; N];
+        self.read_bytes_into(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn read_bytes_into(&mut self, out: &mut [u8]) -> Result<(), StateLoadErr> {
+        if self.buf.len() < out.len() {
+            return Err(StateLoadErr::Truncated);
+        }
+
+        let (bytes, rest) = self.buf.split_at(out.len());
+        out.copy_from_slice(bytes);
+        self.buf = rest;
+
+        Ok(())
+    }
+
+    /// Fails if anything is left unread
+    pub const fn finish(self) -> Result<(), StateLoadErr> {
+        match self.buf.len() {
+            0 => Ok(()),
+            left => Err(StateLoadErr::TrailingData(left)),
+        }
+    }
+}
+
+/// Something that is part of the emulator state
+pub(crate) trait StateData {
+    fn save_state(&self, w: &mut StateWriter);
+
+    /// Overwrites `self` with the state read from `r`
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr>;
+}
+
+/// Implements [StateData] for a struct by saving the given fields in order
+macro_rules! state_data {
+    ($ty:ty { $($field:tt),* $(,)? }) => {
+        impl $crate::savestate::StateData for $ty {
+            fn save_state(&self, w: &mut $crate::savestate::StateWriter) {
+                $($crate::savestate::StateData::save_state(&self.$field, w);)*
+            }
+
+            fn load_state(
+                &mut self,
+                r: &mut $crate::savestate::StateReader,
+            ) -> Result<(), $crate::savestate::StateLoadErr> {
+                $($crate::savestate::StateData::load_state(&mut self.$field, r)?;)*
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use state_data;
+
+macro_rules! state_data_int {
+    ($($ty:ty),*) => {
+        $(
+            impl StateData for $ty {
+                fn save_state(&self, w: &mut StateWriter) {
+                    w.write_bytes(&self.to_le_bytes());
+                }
+
+                fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+                    *self = <$ty>::from_le_bytes(r.read_array()?);
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+state_data_int!(u8, u16, u32, u64);
+
+impl StateData for usize {
+    fn save_state(&self, w: &mut StateWriter) {
+        (*self as u64).save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        let mut val = 0u64;
+        val.load_state(r)?;
+
+        *self = usize::try_from(val).map_err(|_| StateLoadErr::Invalid("size"))?;
+        Ok(())
+    }
+}
+
+impl StateData for bool {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(*self as u8);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        *self = match r.read_u8()? {
+            0 => false,
+            1 => true,
+            _ => return Err(StateLoadErr::Invalid("boolean")),
+        };
+
+        Ok(())
+    }
+}
+
+impl StateData for GbInputs {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_u8(movie::pack(*self));
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        *self = movie::unpack(r.read_u8()?);
+        Ok(())
+    }
+}
+
+impl<T: StateData> StateData for Wrapping<T> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.0.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        self.0.load_state(r)
+    }
+}
+
+impl<T: StateData, const N: usize> StateData for [T; N] {
+    fn save_state(&self, w: &mut StateWriter) {
+        for elem in self {
+            elem.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        for elem in self {
+            elem.load_state(r)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: StateData> StateData for Box<T> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.as_ref().save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        self.as_mut().load_state(r)
+    }
+}
+
+impl<T: StateData + Default> StateData for Option<T> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.is_some().save_state(w);
+
+        if let Some(val) = self {
+            val.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        let mut present = false;
+        present.load_state(r)?;
+
+        if present {
+            self.get_or_insert_with(T::default).load_state(r)
+        } else {
+            *self = None;
+            Ok(())
+        }
+    }
+}
+
+impl StateData for Vec<u8> {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.len().save_state(w);
+        w.write_bytes(self);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), StateLoadErr> {
+        let mut len = 0usize;
+        len.load_state(r)?;
+
+        if len > r.buf.len() {
+            return Err(StateLoadErr::Truncated);
+        }
+
+        self.resize(len, 0);
+        r.read_bytes_into(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, PartialEq)]
+    struct Fields {
+        a: u8,
+        b: u16,
+        c: Option<u64>,
+        d: [bool; 2],
+        e: Vec<u8>,
+    }
+
+    state_data!(Fields { a, b, c, d, e });
+
+    #[test]
+    fn fields_round_trip() {
+        let fields = Fields {
+            a: 1,
+            b: 0x1234,
+            c: Some(u64::MAX),
+            d: [true, false],
+            e: vec![4, 5, 6],
+        };
+
+        let mut w = StateWriter::new();
+        fields.save_state(&mut w);
+        let bytes = w.finish();
+
+        let mut loaded = Fields::default();
+        let mut r = StateReader::new(&bytes);
+        loaded.load_state(&mut r).unwrap();
+        r.finish().unwrap();
+
+        assert_eq!(fields, loaded);
+
+        let mut r = StateReader::new(&bytes[..bytes.len() - 1]);
+        assert!(matches!(
+            loaded.load_state(&mut r),
+            Err(StateLoadErr::Truncated)
+        ));
+    }
+}
@@ -0,0 +1,453 @@
+//! User scripts that inspect and drive the emulator, for bots,
+//! auto-splitters and research tooling. Scripts are written in
+//! [Rhai](https://rhai.rs). Their top level runs once when loaded, and an
+//! `on_frame()` function, if they define one, after every frame the host
+//! passes to [Script::frame].
+//!
+//! Scripts can call these functions on top of the Rhai standard library:
+//!
+//! | Function                     | Does                                                       |
+//! |------------------------------|------------------------------------------------------------|
+//! | `read8(addr)`, `read16(addr)`| Reads memory, without side effects                         |
+//! | `write8(addr, value)`        | Writes memory, like [crate::Ruboy::write_memory]           |
+//! | `reg(name)`                  | A CPU register: `a` to `l`, `af`, `bc`, `de`, `hl`, `sp` or `pc` |
+//! | `frame()`                    | The amount of frames emulated so far                       |
+//! | `held(button)`               | Whether a button is held: `a`, `b`, `start`, `select`, `up`, `down`, `left` or `right` |
+//! | `press(button)`, `release(button)` | Changes the buttons, like [crate::Ruboy::set_inputs] |
+//!
+//! Functions can't see the global variables of the script. Instead, `this`
+//! in `on_frame()` is an object map that is kept between frames, e.g.
+//! `this.count = (this.count ?? 0) + 1`.
+//!
+//! Scripts see the emulator as it was when the call started, plus their
+//! own changes, which are only applied once the call returns. `print` and
+//! `debug` go to the log.
+
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, Map, ParseError, Scope, AST};
+use thiserror::Error;
+
+use crate::debug::CpuRegisters;
+use crate::emucore::EmulatorCore;
+use crate::memcontroller::WriteError;
+use crate::{GBGraphicsDrawer, GbInputs};
+
+/// The most operations a single call into a script may take. Stops scripts
+/// stuck in a loop before they hang the emulator.
+pub const MAX_OPERATIONS: u64 = 1_000_000;
+
+const FRAME_CALLBACK: &str = "on_frame";
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+#[derive(Debug, Error)]
+pub enum ScriptErr {
+    #[error("Could not compile script: {0}")]
+    Compile(#[from] ParseError),
+
+    #[error("Script failed: {0}")]
+    Runtime(#[from] Box<EvalAltResult>),
+
+    #[error("Script could not write to memory: {0}")]
+    Write(#[from] WriteError),
+}
+
+/// A loaded script. See the [module documentation](self) for what scripts
+/// can do.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+
+    /// The global variables of the script, kept between calls
+    scope: Scope<'static>,
+
+    /// Bound to `this` in the frame callback
+    this: Dynamic,
+
+    state: Arc<Mutex<State>>,
+    has_frame_callback: bool,
+}
+
+/// What the script sees of the emulator during a call
+#[derive(Debug)]
+struct State {
+    /// The whole address space
+    memory: Vec<u8>,
+    registers: CpuRegisters,
+    frame: u64,
+    inputs: GbInputs,
+
+    /// Changes made by the script, applied when the call returns
+    writes: Vec<(u16, u8)>,
+    inputs_changed: bool,
+}
+
+impl Script {
+    /// Compiles the script and runs its top level
+    pub fn load<V, C>(source: &str, ruboy: &mut C) -> Result<Self, ScriptErr>
+    where
+        V: GBGraphicsDrawer,
+        C: EmulatorCore<V> + ?Sized,
+    {
+        let state = Arc::new(Mutex::new(State {
+            memory: vec![0; 0x10000],
+            registers: CpuRegisters::default(),
+            frame: 0,
+            inputs: GbInputs::default(),
+            writes: Vec::new(),
+            inputs_changed: false,
+        }));
+
+        let engine = new_engine(&state);
+        let ast = engine.compile(source)?;
+
+        let has_frame_callback = ast
+            .iter_functions()
+            .any(|f| f.name == FRAME_CALLBACK && f.params.is_empty());
+
+        let mut script = Self {
+            engine,
+            ast,
+            scope: Scope::new(),
+            this: Map::new().into(),
+            state,
+            has_frame_callback,
+        };
+
+        script.call(ruboy, |engine, scope, ast, _| {
+            engine.run_ast_with_scope(scope, ast)
+        })?;
+
+        Ok(script)
+    }
+
+    /// Whether the script defines `on_frame()`
+    pub fn has_frame_callback(&self) -> bool {
+        self.has_frame_callback
+    }
+
+    /// Calls `on_frame()` of the script, if it has one. Call after every
+    /// emulated frame.
+    pub fn frame<V, C>(&mut self, ruboy: &mut C) -> Result<(), ScriptErr>
+    where
+        V: GBGraphicsDrawer,
+        C: EmulatorCore<V> + ?Sized,
+    {
+        if !self.has_frame_callback {
+            return Ok(());
+        }
+
+        self.call(ruboy, |engine, scope, ast, this| {
+            // The top level already ran when loading
+            let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(this);
+
+            engine
+                .call_fn_with_options::<Dynamic>(options, scope, ast, FRAME_CALLBACK, ())
+                .map(drop)
+        })
+    }
+
+    fn call<V, C>(
+        &mut self,
+        ruboy: &mut C,
+        f: impl FnOnce(&Engine, &mut Scope<'static>, &AST, &mut Dynamic) -> ScriptResult<()>,
+    ) -> Result<(), ScriptErr>
+    where
+        V: GBGraphicsDrawer,
+        C: EmulatorCore<V> + ?Sized,
+    {
+        lock(&self.state).capture(ruboy);
+
+        let result = f(&self.engine, &mut self.scope, &self.ast, &mut self.this);
+
+        // Changes made before a failure still happened
+        lock(&self.state).apply(ruboy)?;
+
+        Ok(result?)
+    }
+}
+
+impl Debug for Script {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Script")
+            .field("has_frame_callback", &self.has_frame_callback)
+            .finish_non_exhaustive()
+    }
+}
+
+impl State {
+    fn capture<V, C>(&mut self, ruboy: &C)
+    where
+        V: GBGraphicsDrawer,
+        C: EmulatorCore<V> + ?Sized,
+    {
+        for (addr, byte) in self.memory.iter_mut().enumerate() {
+            // Like the bus, reads from nowhere give 0xFF
+            *byte = ruboy.peek(addr as u16).unwrap_or(0xFF);
+        }
+
+        self.registers = ruboy.registers();
+        self.frame = ruboy.frame_count();
+        self.inputs = ruboy.inputs();
+        self.inputs_changed = false;
+        self.writes.clear();
+    }
+
+    fn apply<V, C>(&mut self, ruboy: &mut C) -> Result<(), WriteError>
+    where
+        V: GBGraphicsDrawer,
+        C: EmulatorCore<V> + ?Sized,
+    {
+        if self.inputs_changed {
+            ruboy.set_inputs(self.inputs);
+        }
+
+        for (addr, value) in self.writes.drain(..) {
+            ruboy.write_memory(addr, &[value])?;
+        }
+
+        Ok(())
+    }
+
+    fn register(&self, name: &str) -> ScriptResult<i64> {
+        let regs = &self.registers;
+        let pair = |hi: u8, lo: u8| u16::from_be_bytes([hi, lo]);
+
+        let value = match name {
+            "a" => regs.a as u16,
+            "f" => regs.f as u16,
+            "b" => regs.b as u16,
+            "c" => regs.c as u16,
+            "d" => regs.d as u16,
+            "e" => regs.e as u16,
+            "h" => regs.h as u16,
+            "l" => regs.l as u16,
+            "af" => pair(regs.a, regs.f),
+            "bc" => pair(regs.b, regs.c),
+            "de" => pair(regs.d, regs.e),
+            "hl" => pair(regs.h, regs.l),
+            "sp" => regs.sp,
+            "pc" => regs.pc,
+            _ => return Err(format!("No register named \"{}\"", name).into()),
+        };
+
+        Ok(value as i64)
+    }
+
+    fn button(&mut self, name: &str) -> ScriptResult<&mut bool> {
+        let inputs = &mut self.inputs;
+
+        let button = match name {
+            "a" => &mut inputs.a,
+            "b" => &mut inputs.b,
+            "start" => &mut inputs.start,
+            "select" => &mut inputs.select,
+            "up" => &mut inputs.up,
+            "down" => &mut inputs.down,
+            "left" => &mut inputs.left,
+            "right" => &mut inputs.right,
+            _ => return Err(format!("No button named \"{}\"", name).into()),
+        };
+
+        Ok(button)
+    }
+
+    fn set_button(&mut self, name: &str, held: bool) -> ScriptResult<()> {
+        *self.button(name)? = held;
+        self.inputs_changed = true;
+
+        Ok(())
+    }
+}
+
+fn new_engine(state: &Arc<Mutex<State>>) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| log::info!("Script: {}", text));
+    engine.on_debug(|text, _, pos| log::debug!("Script at {}: {}", pos, text));
+
+    let s = state.clone();
+    engine.register_fn("read8", move |addr: i64| -> ScriptResult<i64> {
+        let addr = address(addr)?;
+        Ok(lock(&s).memory[addr as usize] as i64)
+    });
+
+    let s = state.clone();
+    engine.register_fn("read16", move |addr: i64| -> ScriptResult<i64> {
+        let addr = address(addr)?;
+        let state = lock(&s);
+        let lo = state.memory[addr as usize];
+        let hi = state.memory[addr.wrapping_add(1) as usize];
+
+        Ok(u16::from_le_bytes([lo, hi]) as i64)
+    });
+
+    let s = state.clone();
+    engine.register_fn("write8", move |addr: i64, value: i64| -> ScriptResult<()> {
+        let addr = address(addr)?;
+        let value =
+            u8::try_from(value).map_err(|_| format!("Value {} does not fit in a byte", value))?;
+
+        let mut state = lock(&s);
+        state.memory[addr as usize] = value;
+        state.writes.push((addr, value));
+
+        Ok(())
+    });
+
+    let s = state.clone();
+    engine.register_fn("reg", move |name: &str| lock(&s).register(name));
+
+    let s = state.clone();
+    engine.register_fn("frame", move || lock(&s).frame as i64);
+
+    let s = state.clone();
+    engine.register_fn("held", move |name: &str| -> ScriptResult<bool> {
+        lock(&s).button(name).map(|held| *held)
+    });
+
+    let s = state.clone();
+    engine.register_fn("press", move |name: &str| lock(&s).set_button(name, true));
+
+    let s = state.clone();
+    engine.register_fn("release", move |name: &str| {
+        lock(&s).set_button(name, false)
+    });
+
+    engine
+}
+
+fn address(addr: i64) -> ScriptResult<u16> {
+    u16::try_from(addr).map_err(|_| {
+        let msg: String = format!("Address {} is outside of the memory map", addr);
+        msg.into()
+    })
+}
+
+/// Every call captures the state anew, so a panic while it was locked
+/// leaves nothing behind that matters
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::GbModel;
+    use crate::testutils::{blank_rom, NullInput, NullOutput, TestRuboy};
+    use crate::Ruboy;
+
+    fn ruboy() -> TestRuboy {
+        Ruboy::with_model(blank_rom(0x3C), NullOutput, NullInput, GbModel::Dmg).unwrap()
+    }
+
+    #[test]
+    fn reads_registers_and_writes_memory() {
+        let mut ruboy = ruboy();
+
+        Script::load("write8(0xC000, reg(\"pc\") >> 8);", &mut ruboy).unwrap();
+
+        assert_eq!(0x01, ruboy.peek(0xC000).unwrap());
+
+        ruboy.write_memory(0xC001, &[0x34, 0x12]).unwrap();
+        Script::load("write8(0xC003, read16(0xC001) >> 8);", &mut ruboy).unwrap();
+
+        assert_eq!(0x12, ruboy.peek(0xC003).unwrap());
+    }
+
+    #[test]
+    fn reads_see_own_writes() {
+        let mut ruboy = ruboy();
+
+        Script::load(
+            "write8(0xC000, 7); write8(0xC001, read8(0xC000) + 1);",
+            &mut ruboy,
+        )
+        .unwrap();
+
+        assert_eq!(8, ruboy.peek(0xC001).unwrap());
+    }
+
+    #[test]
+    fn frame_callback_keeps_this() {
+        let mut ruboy = ruboy();
+        let source = "
+            write8(0xC001, read8(0xC001) + 1);
+
+            fn on_frame() {
+                this.frames = (this.frames ?? 0) + 1;
+                write8(0xC000, this.frames);
+            }
+        ";
+
+        let mut script = Script::load(source, &mut ruboy).unwrap();
+        assert!(script.has_frame_callback());
+
+        for _ in 0..3 {
+            ruboy.step_frame().unwrap();
+            script.frame(&mut ruboy).unwrap();
+        }
+
+        assert_eq!(3, ruboy.peek(0xC000).unwrap());
+
+        // The top level only ran once
+        assert_eq!(1, ruboy.peek(0xC001).unwrap());
+    }
+
+    #[test]
+    fn presses_buttons() {
+        let mut ruboy = ruboy();
+
+        Script::load(
+            "press(\"start\"); press(\"a\"); release(\"a\");",
+            &mut ruboy,
+        )
+        .unwrap();
+
+        let inputs = ruboy.inputs();
+        assert!(inputs.start);
+        assert!(!inputs.a);
+    }
+
+    #[test]
+    fn bad_arguments_are_errors() {
+        let mut ruboy = ruboy();
+
+        for source in [
+            "press(\"turbo\")",
+            "reg(\"x\")",
+            "read8(0x10000)",
+            "write8(0xC000, 256)",
+        ] {
+            assert!(
+                matches!(Script::load(source, &mut ruboy), Err(ScriptErr::Runtime(_))),
+                "{} did not fail",
+                source
+            );
+        }
+
+        assert!(matches!(
+            Script::load("let = 1;", &mut ruboy),
+            Err(ScriptErr::Compile(_))
+        ));
+    }
+
+    #[test]
+    fn endless_loops_are_stopped() {
+        let mut ruboy = ruboy();
+
+        assert!(matches!(
+            Script::load("loop {}", &mut ruboy),
+            Err(ScriptErr::Runtime(_))
+        ));
+    }
+}
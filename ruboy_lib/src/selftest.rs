@@ -0,0 +1,273 @@
+//! Consistency checks over the tables compiled into the emulator.
+//!
+//! These mirror the unit tests that guard the tables, but can be run on a
+//! finished build, so packagers and users can quickly verify it wasn't
+//! miscompiled or built from corrupted sources.
+
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use thiserror::Error;
+
+use crate::isa::decoder::{decode, ILLEGAL_OPCODES};
+use crate::isa::{Instruction, TCycles};
+use crate::memcontroller::io::IoRegs;
+use crate::rom::licensee::{NEW_LICENSEES, OLD_LICENSEES};
+
+/// The bits that read back as 1 for every APU register between 0xFF10
+/// and 0xFF2F, regardless of what was written
+const APU_READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10 - NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // Unused, NR21 - NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30 - NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // Unused, NR41 - NR44
+    0x00, 0x00, 0x70, // NR50 - NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, // Unused
+];
+
+const NR52: u16 = 0xFF26;
+
+#[derive(Debug, Error)]
+pub enum SelfTestErr {
+    #[error("Opcode {0:02X?} could not be decoded")]
+    NotDecoded([u8; 3]),
+
+    #[error("Opcode {0:02X?} was decoded as illegal")]
+    DecodedIllegal([u8; 3]),
+
+    #[error("Illegal opcode {0:02X} was decoded as {1:?}")]
+    IllegalDecoded(u8, Instruction),
+
+    #[error("Opcode {opcode:02X?} has an invalid length of {len}")]
+    InvalidLength { opcode: [u8; 3], len: u8 },
+
+    #[error("Opcode {opcode:02X?} has invalid cycle timings: {cycles:?}")]
+    InvalidCycles { opcode: [u8; 3], cycles: TCycles },
+
+    #[error("Old licensee code 0x{0:02X} is duplicate")]
+    DuplicateOldLicensee(u8),
+
+    #[error("New licensee code {0:02X?} is duplicate")]
+    DuplicateNewLicensee([u8; 2]),
+
+    #[error("Boot ROM {name} is {len} bytes instead of 256")]
+    BootRomSize { name: &'static str, len: usize },
+
+    #[error("Boot ROM {name} has checksum 0x{actual:016X}, expected 0x{expected:016X}")]
+    BootRomChecksum {
+        name: &'static str,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("Register 0x{addr:04X} read back 0x{actual:02X} instead of 0x{expected:02X}")]
+    IoMask { addr: u16, expected: u8, actual: u8 },
+}
+
+/// A single named consistency check
+#[derive(Debug, Clone, Copy)]
+pub struct Check {
+    pub name: &'static str,
+    check: fn() -> Result<(), SelfTestErr>,
+}
+
+impl Check {
+    pub fn run(&self) -> CheckResult {
+        CheckResult {
+            name: self.name,
+            result: (self.check)(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub result: Result<(), SelfTestErr>,
+}
+
+impl CheckResult {
+    pub const fn passed(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+impl Display for CheckResult {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.result {
+            Ok(()) => write!(f, "{}: ok", self.name),
+            Err(e) => write!(f, "{}: FAILED ({})", self.name, e),
+        }
+    }
+}
+
+pub const CHECKS: &[Check] = &[
+    Check {
+        name: "Opcode table",
+        check: check_opcodes,
+    },
+    Check {
+        name: "Licensee tables",
+        check: check_licensees,
+    },
+    Check {
+        name: "Boot ROM",
+        check: check_boot_rom,
+    },
+    Check {
+        name: "I/O register masks",
+        check: check_io_masks,
+    },
+];
+
+/// Runs every check, in order
+pub fn run_all() -> Vec<CheckResult> {
+    CHECKS.iter().map(Check::run).collect()
+}
+
+/// Checks that decoding, instruction lengths and cycle timings agree with
+/// each other for every opcode, including all prefixed ones
+fn check_opcodes() -> Result<(), SelfTestErr> {
+    for opcode in u8::MIN..=u8::MAX {
+        if ILLEGAL_OPCODES.contains(&opcode) {
+            match decode(&[opcode].as_slice(), 0) {
+                Ok(Instruction::IllegalInstruction(_)) => continue,
+                Ok(instr) => return Err(SelfTestErr::IllegalDecoded(opcode, instr)),
+                Err(_) => return Err(SelfTestErr::NotDecoded([opcode, 0, 0])),
+            }
+        }
+
+        // The second byte selects the prefixed instruction, or is an
+        // immediate that doesn't influence decoding
+        for suffix in u8::MIN..=u8::MAX {
+            check_opcode([opcode, suffix, 0])?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_opcode(opcode: [u8; 3]) -> Result<(), SelfTestErr> {
+    let instr = decode(&opcode.as_slice(), 0).map_err(|_| SelfTestErr::NotDecoded(opcode))?;
+
+    if matches!(instr, Instruction::IllegalInstruction(_)) {
+        return Err(SelfTestErr::DecodedIllegal(opcode));
+    }
+
+    let len = instr.len();
+
+    if !(1..=3).contains(&len) {
+        return Err(SelfTestErr::InvalidLength { opcode, len });
+    }
+
+    let cycles = instr.cycles();
+
+    let valid = |cycles: u8| cycles > 0 && cycles.is_multiple_of(4);
+
+    let cycles_valid = match cycles {
+        TCycles::Static(cycles) => valid(cycles),
+        TCycles::Branching { taken, non_taken } => valid(taken) && valid(non_taken),
+    };
+
+    if !cycles_valid {
+        return Err(SelfTestErr::InvalidCycles { opcode, cycles });
+    }
+
+    Ok(())
+}
+
+fn check_licensees() -> Result<(), SelfTestErr> {
+    for (i, licensee) in OLD_LICENSEES.iter().enumerate() {
+        if OLD_LICENSEES[..i].iter().any(|l| l.code == licensee.code) {
+            return Err(SelfTestErr::DuplicateOldLicensee(licensee.code));
+        }
+    }
+
+    for (i, licensee) in NEW_LICENSEES.iter().enumerate() {
+        if NEW_LICENSEES[..i].iter().any(|l| l.code == licensee.code) {
+            return Err(SelfTestErr::DuplicateNewLicensee(licensee.code));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "boot_img_enabled")]
+fn check_boot_rom() -> Result<(), SelfTestErr> {
+    use crate::boot;
+    use crate::model::StateDigest;
+
+    if boot::IMAGE.len() != 0x100 {
+        return Err(SelfTestErr::BootRomSize {
+            name: boot::IMAGE_NAME,
+            len: boot::IMAGE.len(),
+        });
+    }
+
+    let mut digest = StateDigest::new();
+    digest.write_bytes(boot::IMAGE);
+
+    let actual = digest.finish();
+
+    if actual != boot::DIGEST {
+        return Err(SelfTestErr::BootRomChecksum {
+            name: boot::IMAGE_NAME,
+            expected: boot::DIGEST,
+            actual,
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(not(feature = "boot_img_enabled"))]
+fn check_boot_rom() -> Result<(), SelfTestErr> {
+    // Nothing compiled in, so nothing to verify
+    Ok(())
+}
+
+/// Writes zeroes to every APU register and checks that the unused bits read
+/// back as set
+fn check_io_masks() -> Result<(), SelfTestErr> {
+    let mut io = IoRegs::new();
+
+    // Registers can only be written while the APU is on
+    io.write(NR52, 0x80)
+        .expect("APU registers are always writable");
+
+    for (addr, expected) in (0xFF10..).zip(APU_READ_MASKS) {
+        let expected = match addr {
+            // The power bit that was just set
+            NR52 => expected | 0x80,
+            addr => {
+                io.write(addr, 0x00)
+                    .expect("APU registers are always writable");
+                expected
+            }
+        };
+
+        let actual = io.read(addr).expect("APU registers are always readable");
+
+        if actual != expected {
+            return Err(SelfTestErr::IoMask {
+                addr,
+                expected,
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::run_all;
+
+    #[test]
+    fn all_checks_pass() {
+        for result in run_all() {
+            assert!(result.passed(), "{}", result);
+        }
+    }
+}
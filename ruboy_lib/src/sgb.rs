@@ -0,0 +1,790 @@
+//! The Super Game Boy, the cartridge adapter that runs Game Boy games on the
+//! SNES.
+//!
+//! Games talk to the SNES side through the joypad register, by pulsing P14
+//! and P15 to send 16 byte packets. With those packets, a game colors its
+//! screen with four palettes laid out over a grid of 8x8 cells, draws a
+//! border around the screen, and asks for the joypads of more players.
+//! Larger data, like the border tiles, is sent by showing it on the screen
+//! right after the packet that announces it.
+//!
+//! Only games that declare SGB support in their header are listened to,
+//! and only when emulating one of the SGB models. The colors end up in every
+//! [Frame] (see [Frame::sgb]), the border is sent to
+//! [crate::GBGraphicsDrawer::border] whenever it changes.
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+use crate::color::Rgb;
+use crate::logging::{debug, Subsystem};
+use crate::memcontroller::io::Joypad;
+use crate::rom::meta::{Licensee, RomMeta};
+use crate::savestate::state_data;
+use crate::{Frame, GbMonoColor, FRAME_X, FRAME_Y};
+
+/// Width of the border, which covers the whole SNES screen
+pub const BORDER_X: usize = 256;
+pub const BORDER_Y: usize = 224;
+
+/// Where the Game Boy screen sits in the border
+pub const SCREEN_X: usize = 48;
+pub const SCREEN_Y: usize = 40;
+
+/// The palette grid has a cell for every 8x8 pixels of the screen
+pub const CELLS_X: usize = FRAME_X / 8;
+pub const CELLS_Y: usize = FRAME_Y / 8;
+
+const PACKET_SIZE: usize = 16;
+
+/// Every transfer is 4KiB of screen data
+const TRANSFER_SIZE: usize = 0x1000;
+
+const BORDER_TILE_SIZE: usize = 32;
+const BORDER_TILES_SIZE: usize = 256 * BORDER_TILE_SIZE;
+const BORDER_MAP_X: usize = 32;
+
+/// The border map, followed by the four border palettes
+const BORDER_DATA_SIZE: usize = 0x880;
+const BORDER_PALETTES_START: usize = 0x800;
+
+const ATTRIBUTE_FILES: usize = 45;
+const ATTRIBUTE_FILE_SIZE: usize = CELLS_X * CELLS_Y / 4;
+
+/// Palette 1-A, which the SGB starts with
+const DEFAULT_PALETTE: [u16; 4] = [0x67BF, 0x265B, 0x10B5, 0x2866];
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const ATTR_TRN: u8 = 0x15;
+const ATTR_SET: u8 = 0x16;
+const MASK_EN: u8 = 0x17;
+
+/// Whether the SGB listens to the packets of the game. The SGB boot ROM
+/// only allows them for games that set the SGB flag and use the new
+/// licensee code.
+pub fn supported(meta: &RomMeta) -> bool {
+    meta.sgb_support() && matches!(meta.licensee(), Licensee::New { .. })
+}
+
+/// Converts a SNES color, 5 bits per channel with red in the lowest bits
+fn snes_color(color: u16) -> Rgb {
+    let channel = |shift: u16| {
+        let c = ((color >> shift) & 0x1F) as u8;
+        (c << 3) | (c >> 2)
+    };
+
+    Rgb::new(channel(0), channel(5), channel(10))
+}
+
+fn color_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]]) & 0x7FFF
+}
+
+/// What the SGB shows instead of the Game Boy screen, set with MASK_EN
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SgbMask {
+    /// The screen is shown
+    #[default]
+    None,
+
+    /// The last frame stays on screen, while the game draws the next ones
+    Freeze,
+    Black,
+
+    /// The screen is filled with color 0
+    Color0,
+}
+
+impl SgbMask {
+    const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0 => Self::None,
+            1 => Self::Freeze,
+            2 => Self::Black,
+            _ => Self::Color0,
+        }
+    }
+}
+
+/// How the SGB colors the Game Boy screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SgbScreen {
+    /// The four screen palettes. Color 0 of the first palette is used as
+    /// color 0 of every palette
+    pub palettes: [[Rgb; 4]; 4],
+
+    /// The palette of every cell, row by row
+    pub attributes: [u8; CELLS_X * CELLS_Y],
+    pub mask: SgbMask,
+}
+
+impl SgbScreen {
+    /// The color the pixel at the given position is shown in
+    pub fn color(&self, x: usize, y: usize, shade: GbMonoColor) -> Rgb {
+        match (self.mask, shade) {
+            (SgbMask::Black, _) => Rgb::new(0, 0, 0),
+            (SgbMask::Color0, _) | (_, GbMonoColor::White) => self.palettes[0][0],
+            _ => {
+                let palette = self.attributes[(y / 8) * CELLS_X + (x / 8)];
+                self.palettes[palette as usize & 0b11][shade as usize]
+            }
+        }
+    }
+}
+
+/// The border around the screen, [BORDER_X] by [BORDER_Y] pixels
+#[derive(Debug, Clone, Copy)]
+pub struct SgbBorder<'a> {
+    tiles: &'a [u8],
+
+    /// The tile map, followed by the palettes
+    data: &'a [u8],
+}
+
+impl SgbBorder<'_> {
+    /// The color of a pixel of the border, or [None] if it is transparent.
+    /// Borders leave the area of the Game Boy screen transparent.
+    pub fn pixel(&self, x: usize, y: usize) -> Option<Rgb> {
+        if x >= BORDER_X || y >= BORDER_Y {
+            return None;
+        }
+
+        let entry = u16::from_le_bytes([
+            self.data[((y / 8) * BORDER_MAP_X + (x / 8)) * 2],
+            self.data[((y / 8) * BORDER_MAP_X + (x / 8)) * 2 + 1],
+        ]);
+
+        let tile = (entry & 0xFF) as usize;
+        let palette = ((entry >> 10) & 0b11) as usize;
+
+        let col = if entry & 0x4000 != 0 {
+            x % 8
+        } else {
+            7 - (x % 8)
+        };
+        let row = if entry & 0x8000 != 0 {
+            7 - (y % 8)
+        } else {
+            y % 8
+        };
+
+        // SNES tiles store bitplanes 0 and 1 of every row, then 2 and 3
+        let tile = &self.tiles[tile * BORDER_TILE_SIZE..][..BORDER_TILE_SIZE];
+        let planes = [
+            tile[row * 2],
+            tile[row * 2 + 1],
+            tile[16 + row * 2],
+            tile[17 + row * 2],
+        ];
+
+        let color = planes.iter().enumerate().fold(0, |color, (plane, bits)| {
+            color | (((bits >> col) & 1) as usize) << plane
+        });
+
+        match color {
+            0 => None,
+            color => Some(snes_color(color_at(
+                self.data,
+                BORDER_PALETTES_START + (palette * 16 + color) * 2,
+            ))),
+        }
+    }
+
+    /// The border row by row as 8-bit RGBA, transparent pixels having an
+    /// alpha of 0
+    pub fn to_rgba_bytes(&self) -> Vec<u8> {
+        (0..BORDER_Y)
+            .flat_map(|y| (0..BORDER_X).map(move |x| (x, y)))
+            .flat_map(|(x, y)| match self.pixel(x, y) {
+                Some(rgb) => [rgb.r, rgb.g, rgb.b, u8::MAX],
+                None => [0; 4],
+            })
+            .collect()
+    }
+}
+
+/// The SNES side of the Super Game Boy
+#[derive(Debug, Clone)]
+pub struct Sgb {
+    /// A packet is being received
+    receiving: bool,
+
+    /// P14 and P15 went high since the last bit, so the next one can be sent
+    released: bool,
+
+    /// Bits of the packet received so far
+    bits: u8,
+    packet: [u8; PACKET_SIZE],
+
+    /// The packets of the command received so far
+    command: Vec<u8>,
+
+    /// The four screen palettes, as SNES colors
+    palettes: [u16; 16],
+    attributes: [u8; CELLS_X * CELLS_Y],
+    mask: u8,
+
+    /// Stored with PAL_TRN, 512 palettes of 4 colors
+    system_palettes: Vec<u8>,
+
+    /// Stored with ATTR_TRN
+    attribute_files: Vec<u8>,
+    border_tiles: Vec<u8>,
+    border_data: Vec<u8>,
+
+    /// The command and first parameter of the transfer that happens on the
+    /// next frame
+    transfer: Option<[u8; 2]>,
+
+    /// The shades of the frame shown while the screen is frozen
+    frozen: Vec<u8>,
+
+    /// Not part of the state, the border is sent again after loading one
+    border_changed: bool,
+}
+
+state_data!(Sgb {
+    receiving,
+    released,
+    bits,
+    packet,
+    command,
+    palettes,
+    attributes,
+    mask,
+    system_palettes,
+    attribute_files,
+    border_tiles,
+    border_data,
+    transfer,
+    frozen,
+});
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Sgb {
+    pub fn new() -> Self {
+        Self {
+            receiving: false,
+            released: false,
+            bits: 0,
+            packet: [0; PACKET_SIZE],
+            command: Vec::new(),
+            palettes: [DEFAULT_PALETTE; 4].concat().try_into().unwrap(),
+            attributes: [0; CELLS_X * CELLS_Y],
+            mask: 0,
+            system_palettes: vec![0; TRANSFER_SIZE],
+            attribute_files: vec![0; ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE],
+            border_tiles: vec![0; BORDER_TILES_SIZE],
+            border_data: vec![0; BORDER_DATA_SIZE],
+            transfer: None,
+            frozen: Vec::new(),
+            border_changed: false,
+        }
+    }
+
+    /// The colors of the screen as they are now
+    pub fn screen(&self) -> SgbScreen {
+        let mut palettes = [[Rgb::default(); 4]; 4];
+
+        for (palette, colors) in palettes.iter_mut().zip(self.palettes.chunks_exact(4)) {
+            for (rgb, color) in palette.iter_mut().zip(colors) {
+                *rgb = snes_color(*color);
+            }
+        }
+
+        SgbScreen {
+            palettes,
+            attributes: self.attributes,
+            mask: SgbMask::from_bits(self.mask),
+        }
+    }
+
+    pub fn border(&self) -> SgbBorder<'_> {
+        SgbBorder {
+            tiles: &self.border_tiles,
+            data: &self.border_data,
+        }
+    }
+
+    /// Returns true once after the border changed
+    pub(crate) fn take_border_changed(&mut self) -> bool {
+        core::mem::take(&mut self.border_changed)
+    }
+
+    pub(crate) fn mark_border_changed(&mut self) {
+        self.border_changed = true;
+    }
+
+    /// Sees a write to P1. Packets start with both lines pulled low, after
+    /// which every bit is sent by pulling P14 (a 0) or P15 (a 1) low and
+    /// releasing both again. A packet ends with a 0 after its 128 bits.
+    pub(crate) fn write_p1(&mut self, val: u8, joypad: &mut Joypad) {
+        match val & 0x30 {
+            0x00 => {
+                self.receiving = true;
+                self.released = false;
+                self.bits = 0;
+                self.packet = [0; PACKET_SIZE];
+            }
+            0x30 => self.released = true,
+            lines if self.receiving && self.released => {
+                self.released = false;
+
+                let bit = lines == 0x10;
+
+                if self.bits as usize == PACKET_SIZE * 8 {
+                    self.receiving = false;
+
+                    if !bit {
+                        self.packet_received(joypad);
+                    }
+                } else {
+                    if bit {
+                        self.packet[self.bits as usize / 8] |= 1 << (self.bits % 8);
+                    }
+
+                    self.bits += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn packet_received(&mut self, joypad: &mut Joypad) {
+        self.command.extend_from_slice(&self.packet);
+
+        // The first packet holds the amount of packets of the command
+        let packets = (self.command[0] & 0b111).max(1) as usize;
+
+        if self.command.len() >= packets * PACKET_SIZE {
+            let command = core::mem::take(&mut self.command);
+            self.run(&command, joypad);
+        }
+    }
+
+    fn run(&mut self, data: &[u8], joypad: &mut Joypad) {
+        debug!(Subsystem::Memory, "SGB command 0x{:02x}", data[0] >> 3);
+
+        match data[0] >> 3 {
+            PAL01 => self.set_palettes(0, 1, data),
+            PAL23 => self.set_palettes(2, 3, data),
+            PAL03 => self.set_palettes(0, 3, data),
+            PAL12 => self.set_palettes(1, 2, data),
+            ATTR_BLK => self.attr_blk(data),
+            ATTR_LIN => self.attr_lin(data),
+            ATTR_DIV => self.attr_div(data),
+            ATTR_CHR => self.attr_chr(data),
+            PAL_SET => self.pal_set(data),
+            ATTR_SET => {
+                self.apply_attribute_file(data[1] & 0x3F);
+
+                if data[1] & 0x40 != 0 {
+                    self.mask = 0;
+                }
+            }
+            PAL_TRN | CHR_TRN | PCT_TRN | ATTR_TRN => self.transfer = Some([data[0] >> 3, data[1]]),
+            MLT_REQ => joypad.set_players(match data[1] & 0b11 {
+                1 => 2,
+                3 => 4,
+                _ => 1,
+            }),
+            MASK_EN => self.mask = data[1] & 0b11,
+            command => debug!(
+                Subsystem::Memory,
+                "Ignoring unsupported SGB command 0x{:02x}", command
+            ),
+        }
+    }
+
+    fn set_palettes(&mut self, a: usize, b: usize, data: &[u8]) {
+        self.palettes[0] = color_at(data, 1);
+
+        for i in 0..3 {
+            self.palettes[a * 4 + 1 + i] = color_at(data, 3 + i * 2);
+            self.palettes[b * 4 + 1 + i] = color_at(data, 9 + i * 2);
+        }
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        if x < CELLS_X && y < CELLS_Y {
+            self.attributes[y * CELLS_X + x] = palette & 0b11;
+        }
+    }
+
+    fn attr_blk(&mut self, data: &[u8]) {
+        let sets = (data[1] as usize).min(18);
+
+        for set in data[2..].chunks_exact(6).take(sets) {
+            let mut control = set[0] & 0b111;
+            let inside = set[1] & 0b11;
+            let mut border = (set[1] >> 2) & 0b11;
+            let outside = (set[1] >> 4) & 0b11;
+
+            // Coloring only one side colors the border along with it
+            match control {
+                0b001 => border = inside,
+                0b100 => border = outside,
+                _ => {}
+            }
+
+            if control == 0b001 || control == 0b100 {
+                control |= 0b010;
+            }
+
+            let (x1, y1, x2, y2) = (
+                set[2] as usize,
+                set[3] as usize,
+                set[4] as usize,
+                set[5] as usize,
+            );
+
+            for y in 0..CELLS_Y {
+                for x in 0..CELLS_X {
+                    let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                    let edge = within && (x == x1 || x == x2 || y == y1 || y == y2);
+
+                    let (part, palette) = match (within, edge) {
+                        (true, true) => (0b010, border),
+                        (true, false) => (0b001, inside),
+                        _ => (0b100, outside),
+                    };
+
+                    if control & part != 0 {
+                        self.set_attribute(x, y, palette);
+                    }
+                }
+            }
+        }
+    }
+
+    fn attr_lin(&mut self, data: &[u8]) {
+        let lines = data[1] as usize;
+
+        for line in data[2..].iter().take(lines) {
+            let at = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0b11;
+
+            if line & 0x80 != 0 {
+                for x in 0..CELLS_X {
+                    self.set_attribute(x, at, palette);
+                }
+            } else {
+                for y in 0..CELLS_Y {
+                    self.set_attribute(at, y, palette);
+                }
+            }
+        }
+    }
+
+    fn attr_div(&mut self, data: &[u8]) {
+        let after = data[1] & 0b11;
+        let before = (data[1] >> 2) & 0b11;
+        let on = (data[1] >> 4) & 0b11;
+        let horizontal = data[1] & 0x40 != 0;
+        let at = data[2] as usize;
+
+        for y in 0..CELLS_Y {
+            for x in 0..CELLS_X {
+                let pos = if horizontal { y } else { x };
+
+                let palette = match pos.cmp(&at) {
+                    Ordering::Less => before,
+                    Ordering::Equal => on,
+                    Ordering::Greater => after,
+                };
+
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    fn attr_chr(&mut self, data: &[u8]) {
+        let (mut x, mut y) = (data[1] as usize, data[2] as usize);
+
+        if x >= CELLS_X || y >= CELLS_Y {
+            return;
+        }
+
+        let count = u16::from_le_bytes([data[3], data[4]]) as usize;
+        let vertical = data[5] & 1 != 0;
+
+        for i in 0..count.min(CELLS_X * CELLS_Y) {
+            let Some(byte) = data.get(6 + i / 4) else {
+                break;
+            };
+
+            self.set_attribute(x, y, byte >> (6 - (i % 4) * 2));
+
+            if vertical {
+                y += 1;
+
+                if y == CELLS_Y {
+                    y = 0;
+                    x = (x + 1) % CELLS_X;
+                }
+            } else {
+                x += 1;
+
+                if x == CELLS_X {
+                    x = 0;
+                    y = (y + 1) % CELLS_Y;
+                }
+            }
+        }
+    }
+
+    fn pal_set(&mut self, data: &[u8]) {
+        for palette in 0..4 {
+            let index = (color_at(data, 1 + palette * 2) & 0x1FF) as usize;
+
+            for color in 0..4 {
+                self.palettes[palette * 4 + color] =
+                    color_at(&self.system_palettes, (index * 4 + color) * 2);
+            }
+        }
+
+        if data[9] & 0x80 != 0 {
+            self.apply_attribute_file(data[9] & 0x3F);
+        }
+
+        if data[9] & 0x40 != 0 {
+            self.mask = 0;
+        }
+    }
+
+    fn apply_attribute_file(&mut self, file: u8) {
+        let file = file as usize;
+
+        if file >= ATTRIBUTE_FILES {
+            return;
+        }
+
+        let data = &self.attribute_files[file * ATTRIBUTE_FILE_SIZE..][..ATTRIBUTE_FILE_SIZE];
+
+        for (i, attribute) in self.attributes.iter_mut().enumerate() {
+            *attribute = (data[i / 4] >> (6 - (i % 4) * 2)) & 0b11;
+        }
+    }
+
+    /// Called when the PPU finished a frame. Runs a pending transfer with
+    /// what the frame shows, and applies the colors and mask to it.
+    pub(crate) fn frame_completed(&mut self, frame: &mut Frame) {
+        if let Some([command, param]) = self.transfer.take() {
+            let data = screen_data(frame);
+
+            match command {
+                PAL_TRN => self.system_palettes.copy_from_slice(&data),
+                CHR_TRN => {
+                    let half = (param & 1) as usize * TRANSFER_SIZE;
+                    self.border_tiles[half..half + TRANSFER_SIZE].copy_from_slice(&data);
+                    self.border_changed = true;
+                }
+                PCT_TRN => {
+                    self.border_data.copy_from_slice(&data[..BORDER_DATA_SIZE]);
+                    self.border_changed = true;
+                }
+                ATTR_TRN => {
+                    let len = self.attribute_files.len();
+                    self.attribute_files.copy_from_slice(&data[..len]);
+                }
+                _ => {}
+            }
+        }
+
+        let pixels = frame.get_raw_mut();
+
+        if SgbMask::from_bits(self.mask) != SgbMask::Freeze {
+            self.frozen.clear();
+        } else if self.frozen.is_empty() {
+            self.frozen.extend(pixels.iter().map(|shade| *shade as u8));
+        } else {
+            for (pixel, shade) in pixels.iter_mut().zip(&self.frozen) {
+                *pixel = match shade {
+                    0 => GbMonoColor::White,
+                    1 => GbMonoColor::LightGray,
+                    2 => GbMonoColor::DarkGray,
+                    _ => GbMonoColor::Black,
+                };
+            }
+        }
+
+        frame.set_sgb(Some(self.screen()));
+    }
+}
+
+/// Reads the screen the way the SGB does for transfers: as tiles of 2 bits
+/// per pixel, left to right and top to bottom
+fn screen_data(frame: &Frame) -> Vec<u8> {
+    let pixels = frame.get_raw();
+    let mut data = vec![0; TRANSFER_SIZE];
+
+    for (tile, bytes) in data.chunks_exact_mut(16).enumerate() {
+        let (tx, ty) = (tile % CELLS_X, tile / CELLS_X);
+
+        for row in 0..8 {
+            let line = &pixels[(ty * 8 + row) * FRAME_X + tx * 8..][..8];
+
+            for (col, shade) in line.iter().enumerate() {
+                let shade = *shade as u8;
+
+                bytes[row * 2] |= (shade & 1) << (7 - col);
+                bytes[row * 2 + 1] |= (shade >> 1) << (7 - col);
+            }
+        }
+    }
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sends a packet to the SGB the way a game does
+    fn send(sgb: &mut Sgb, joypad: &mut Joypad, packet: [u8; PACKET_SIZE]) {
+        sgb.write_p1(0x00, joypad);
+        sgb.write_p1(0x30, joypad);
+
+        for byte in packet {
+            for bit in 0..8 {
+                let lines = if byte & (1 << bit) != 0 { 0x10 } else { 0x20 };
+                sgb.write_p1(lines, joypad);
+                sgb.write_p1(0x30, joypad);
+            }
+        }
+
+        sgb.write_p1(0x20, joypad);
+        sgb.write_p1(0x30, joypad);
+    }
+
+    fn packet(command: u8, params: &[u8]) -> [u8; PACKET_SIZE] {
+        let mut packet = [0; PACKET_SIZE];
+        packet[0] = (command << 3) | 1;
+        packet[1..=params.len()].copy_from_slice(params);
+        packet
+    }
+
+    #[test]
+    fn palettes_and_attributes_color_the_screen() {
+        let mut sgb = Sgb::new();
+        let mut joypad = Joypad::new();
+
+        // Color 0 black, palette 1 pure red, blue and green
+        send(
+            &mut sgb,
+            &mut joypad,
+            packet(PAL12, &[0, 0, 0x1F, 0x00, 0x00, 0x7C, 0xE0, 0x03]),
+        );
+
+        // The right half uses palette 1
+        send(&mut sgb, &mut joypad, packet(ATTR_DIV, &[0b0000_0001, 10]));
+
+        let screen = sgb.screen();
+        assert_eq!(Rgb::new(0, 0, 0), screen.color(100, 0, GbMonoColor::White));
+        assert_eq!(
+            Rgb::new(255, 0, 0),
+            screen.color(100, 0, GbMonoColor::LightGray)
+        );
+        assert_eq!(
+            snes_color(DEFAULT_PALETTE[1]),
+            screen.color(0, 0, GbMonoColor::LightGray)
+        );
+        assert_eq!(1, screen.attributes[CELLS_X - 1]);
+    }
+
+    #[test]
+    fn packets_need_a_stop_bit() {
+        let mut sgb = Sgb::new();
+        let mut joypad = Joypad::new();
+
+        let mut bad = Sgb::new();
+        bad.write_p1(0x00, &mut joypad);
+        bad.write_p1(0x30, &mut joypad);
+
+        for _ in 0..=PACKET_SIZE * 8 {
+            bad.write_p1(0x10, &mut joypad);
+            bad.write_p1(0x30, &mut joypad);
+        }
+
+        assert!(bad.command.is_empty());
+
+        send(&mut sgb, &mut joypad, packet(MASK_EN, &[2]));
+        assert_eq!(SgbMask::Black, sgb.screen().mask);
+    }
+
+    #[test]
+    fn multiplayer_request_cycles_joypads() {
+        let mut sgb = Sgb::new();
+        let mut joypad = Joypad::new();
+
+        send(&mut sgb, &mut joypad, packet(MLT_REQ, &[1]));
+
+        joypad.write(0x30);
+        assert_eq!(0x0F, joypad.read() & 0x0F);
+
+        joypad.write(0x10);
+        joypad.write(0x30);
+        assert_eq!(0x0E, joypad.read() & 0x0F);
+
+        joypad.write(0x10);
+        joypad.write(0x30);
+        assert_eq!(0x0F, joypad.read() & 0x0F);
+    }
+
+    #[test]
+    fn border_is_transferred_from_the_screen() {
+        let mut sgb = Sgb::new();
+        let mut joypad = Joypad::new();
+        let mut frame = Frame::default();
+
+        // Border tile 0 is made of the first two screen tiles. Filling the
+        // first with shade 1 sets bitplane 0, so color 1 everywhere
+        for row in 0..8 {
+            for col in 0..8 {
+                frame.set_pix(col, row, GbMonoColor::LightGray);
+            }
+        }
+
+        send(&mut sgb, &mut joypad, packet(CHR_TRN, &[0]));
+        sgb.frame_completed(&mut frame);
+        assert!(sgb.take_border_changed());
+
+        // An empty map shows tile 0 in the first border palette, of which
+        // color 1 is set to white
+        for pix in frame.get_raw_mut() {
+            *pix = GbMonoColor::White;
+        }
+
+        // The second row of a screen tile is its bytes 2 and 3
+        let tile = (BORDER_PALETTES_START + 2) / 16;
+        let (tx, ty) = (tile % CELLS_X, tile / CELLS_X);
+        for col in 0..8 {
+            frame.set_pix((tx * 8 + col) as u8, (ty * 8 + 1) as u8, GbMonoColor::Black);
+        }
+
+        send(&mut sgb, &mut joypad, packet(PCT_TRN, &[]));
+        sgb.frame_completed(&mut frame);
+
+        assert_eq!(Some(Rgb::new(255, 255, 255)), sgb.border().pixel(0, 0));
+        assert_eq!(BORDER_X * BORDER_Y * 4, sgb.border().to_rgba_bytes().len());
+        assert!(frame.sgb().is_some());
+    }
+}
@@ -0,0 +1,181 @@
+//! An [InputHandler] and [GBGraphicsDrawer] that share their state through
+//! an `Arc<Mutex>`, for hosts that run the emulator on another thread than
+//! the one handling input and drawing. Clones share the same state: give
+//! one to [crate::Ruboy] and keep the other.
+
+use std::boxed::Box;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::{Frame, GBGraphicsDrawer, GbInputs, InputHandler};
+
+/// Buttons set by the host and polled by the emulator
+#[derive(Debug, Clone, Default)]
+pub struct SharedInput {
+    inputs: Arc<Mutex<GbInputs>>,
+
+    /// What this clone handed out on the last poll
+    polled: Option<GbInputs>,
+}
+
+impl SharedInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The buttons currently held
+    pub fn get(&self) -> GbInputs {
+        *lock(&self.inputs)
+    }
+
+    /// Replaces all held buttons
+    pub fn set(&self, inputs: GbInputs) {
+        *lock(&self.inputs) = inputs;
+    }
+
+    /// Changes some of the held buttons, e.g. on a single key event
+    pub fn update(&self, f: impl FnOnce(&mut GbInputs)) {
+        f(&mut lock(&self.inputs));
+    }
+}
+
+impl InputHandler for SharedInput {
+    fn poll_inputs(&mut self) -> Option<GbInputs> {
+        let inputs = self.get();
+
+        if self.polled == Some(inputs) {
+            return None;
+        }
+
+        self.polled = Some(inputs);
+        Some(inputs)
+    }
+}
+
+/// The last frame the emulator finished
+#[derive(Debug, Clone, Default)]
+pub struct SharedVideo {
+    latest: Arc<Mutex<Latest>>,
+}
+
+#[derive(Debug, Default)]
+struct Latest {
+    frame: Box<Frame>,
+
+    /// Counts finished frames
+    count: u64,
+
+    /// Whether `frame` was finished after the last [SharedVideo::new_frame]
+    unseen: bool,
+}
+
+impl SharedVideo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A copy of the last finished frame
+    pub fn frame(&self) -> Frame {
+        lock(&self.latest).frame.as_ref().clone()
+    }
+
+    /// A copy of the last finished frame, if it was finished since the last
+    /// call. Skipped frames are not kept, only the newest one.
+    pub fn new_frame(&self) -> Option<Frame> {
+        let mut latest = lock(&self.latest);
+
+        if !latest.unseen {
+            return None;
+        }
+
+        latest.unseen = false;
+        Some(latest.frame.as_ref().clone())
+    }
+
+    /// How many frames the emulator finished so far
+    pub fn frame_count(&self) -> u64 {
+        lock(&self.latest).count
+    }
+}
+
+impl GBGraphicsDrawer for SharedVideo {
+    type Err = Infallible;
+
+    fn output(&mut self, frame: &Frame) -> Result<(), Self::Err> {
+        let mut latest = lock(&self.latest);
+
+        latest.frame.as_mut().clone_from(frame);
+        latest.count += 1;
+        latest.unseen = true;
+
+        Ok(())
+    }
+}
+
+/// The shared state stays valid when the other side panics while holding
+/// the lock, so poisoning is ignored
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::model::GbModel;
+    use crate::testutils::blank_rom;
+    use crate::{GbMonoColor, InlineAllocator, Ruboy};
+
+    #[test]
+    fn input_polls_only_changes() {
+        let host = SharedInput::new();
+        let mut emulator = host.clone();
+
+        assert_eq!(Some(GbInputs::default()), emulator.poll_inputs());
+        assert_eq!(None, emulator.poll_inputs());
+
+        host.update(|inputs| inputs.start = true);
+        assert!(emulator.poll_inputs().unwrap().start);
+        assert_eq!(None, emulator.poll_inputs());
+    }
+
+    #[test]
+    fn video_hands_out_each_frame_once() {
+        let host = SharedVideo::new();
+        let mut emulator = host.clone();
+        assert!(host.new_frame().is_none());
+
+        let mut frame = Frame::default();
+        frame.get_raw_mut()[0] = GbMonoColor::Black;
+        emulator.output(&frame).unwrap();
+
+        assert_eq!(GbMonoColor::Black, host.new_frame().unwrap().get_raw()[0]);
+        assert!(host.new_frame().is_none());
+        assert_eq!(1, host.frame_count());
+    }
+
+    #[test]
+    fn ruboy_runs_on_another_thread() {
+        let video = SharedVideo::new();
+        let ruboy = Ruboy::<InlineAllocator, _, _, _>::with_model(
+            blank_rom(0x3C),
+            video.clone(),
+            SharedInput::new(),
+            GbModel::Dmg,
+        )
+        .unwrap();
+
+        thread::spawn(move || {
+            let mut ruboy = ruboy;
+
+            for _ in 0..3 {
+                ruboy.step_frame().unwrap();
+            }
+        })
+        .join()
+        .unwrap();
+
+        assert!(video.frame_count() > 0);
+    }
+}
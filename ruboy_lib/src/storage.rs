@@ -0,0 +1,170 @@
+//! Persistence of data that outlives an emulator instance, like save files.
+//!
+//! Everything that needs to be stored goes through a [StorageProvider], so
+//! frontends only have to decide _where_ data ends up, e.g. in files on disk
+//! or in the storage of a browser.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::convert::Infallible;
+use core::error::Error;
+use core::fmt::Debug;
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+use std::fs;
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+use std::io::ErrorKind;
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+use std::path::{Path, PathBuf};
+
+/// A key-value store for binary blobs.
+///
+/// Keys are `/` separated paths, like `saves/tetris.rtc`. Implementations
+/// are free to map these onto whatever they store data in.
+pub trait StorageProvider: Debug {
+    /// The error that can be returned by this provider
+    type Err: Error + 'static;
+
+    /// Returns the data stored under `key`, or [None] if nothing is stored
+    /// there
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Err>;
+
+    /// Stores `data` under `key`, replacing anything that was stored there
+    fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Err>;
+
+    /// Removes the data stored under `key`. Removing a key that has nothing
+    /// stored under it is not an error.
+    fn remove(&mut self, key: &str) -> Result<(), Self::Err>;
+}
+
+/// Stores every key as a file below a root directory. Directories are
+/// created as needed. Not available without std, or in the browser, which
+/// has no file system.
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+#[derive(Debug, Clone)]
+pub struct FsStorage {
+    root: PathBuf,
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+impl FsStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let mut path = self.root.clone();
+        path.extend(key.split('/').filter(|part| !part.is_empty()));
+        path
+    }
+}
+
+#[cfg(all(
+    feature = "std",
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+impl StorageProvider for FsStorage {
+    type Err = std::io::Error;
+
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Err> {
+        match fs::read(self.path(key)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Err> {
+        let path = self.path(key);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, data)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), Self::Err> {
+        match fs::remove_file(self.path(key)) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Keeps everything in memory. Useful for tests, and for frontends that
+/// handle persisting the data themselves.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStorage {
+    entries: BTreeMap<String, Vec<u8>>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All stored keys, in sorted order
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+}
+
+impl StorageProvider for MemoryStorage {
+    type Err = Infallible;
+
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Err> {
+        Ok(self.entries.get(key).cloned())
+    }
+
+    fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Err> {
+        self.entries.insert(key.to_string(), data.to_vec());
+        Ok(())
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), Self::Err> {
+        self.entries.remove(key);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fs_storage_round_trips() {
+        let root = std::env::temp_dir().join(format!("ruboy-storage-{}", std::process::id()));
+        let mut storage = FsStorage::new(&root);
+
+        assert_eq!(None, storage.load("saves/game.sav").unwrap());
+
+        storage.store("saves/game.sav", &[1, 2, 3]).unwrap();
+        assert_eq!(Some(vec![1, 2, 3]), storage.load("saves/game.sav").unwrap());
+
+        storage.remove("saves/game.sav").unwrap();
+        storage.remove("saves/game.sav").unwrap();
+        assert_eq!(None, storage.load("saves/game.sav").unwrap());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
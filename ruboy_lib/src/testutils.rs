@@ -0,0 +1,167 @@
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use crate::model::GbModel;
+use crate::printer::{PrintHandler, Printout};
+use crate::{
+    Frame, FrameObserver, GBGraphicsDrawer, GbInputs, InlineAllocator, InputHandler, LinkDevice,
+    Ruboy, RumbleHandler,
+};
+
+#[derive(Debug)]
+pub(crate) struct NullOutput;
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum NullOutputErr {}
+
+impl GBGraphicsDrawer for NullOutput {
+    type Err = NullOutputErr;
+
+    fn output(&mut self, _frame: &Frame) -> Result<(), Self::Err> {
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct NullInput;
+
+impl InputHandler for NullInput {
+    fn poll_inputs(&mut self) -> Option<GbInputs> {
+        None
+    }
+}
+
+/// Keeps whatever a handler handed to the emulator sees, for the test to
+/// look at afterwards. Clones share the same list, so keep one and hand
+/// over the other.
+#[derive(Debug)]
+pub(crate) struct Recorder<T>(Arc<Mutex<Vec<T>>>);
+
+impl<T> Recorder<T> {
+    pub(crate) fn record(&self, value: T) {
+        self.0.lock().unwrap().push(value);
+    }
+
+    /// Everything recorded so far
+    pub(crate) fn recorded(&self) -> MutexGuard<'_, Vec<T>> {
+        self.0.lock().unwrap()
+    }
+}
+
+impl<T> Default for Recorder<T> {
+    fn default() -> Self {
+        Self(Arc::default())
+    }
+}
+
+impl<T> Clone for Recorder<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl Write for Recorder<u8> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Records the sent bytes, and answers with their complement so the answer
+/// can be told apart from an unconnected port
+impl LinkDevice for Recorder<u8> {
+    fn exchange(&mut self, sent: u8, _cycle: u64) -> u8 {
+        self.record(sent);
+        !sent
+    }
+}
+
+/// Records the number and finishing cycle of every frame
+impl FrameObserver for Recorder<(u64, u64)> {
+    fn frame(&mut self, _frame: &Frame, number: u64, cycle: u64) {
+        self.record((number, cycle));
+    }
+}
+
+impl RumbleHandler for Recorder<bool> {
+    fn rumble(&mut self, on: bool) {
+        self.record(on);
+    }
+}
+
+impl PrintHandler for Recorder<Printout> {
+    fn print(&mut self, printout: Printout) {
+        self.record(printout);
+    }
+}
+
+pub(crate) type TestRuboy = Ruboy<InlineAllocator, Cursor<Vec<u8>>, NullOutput, NullInput>;
+
+/// A 32KiB ROM without a mapper, filled with NOPs, with the given
+/// header checksum
+pub(crate) fn blank_rom(header_checksum: u8) -> Cursor<Vec<u8>> {
+    let mut rom = vec![0u8; 0x8000];
+    rom[0x14D] = header_checksum;
+
+    Cursor::new(rom)
+}
+
+/// Sets the header checksum of the ROM to match the rest of its header
+pub(crate) fn fix_header_checksum(rom: &mut [u8]) {
+    rom[0x14D] = rom[0x134..0x14D]
+        .iter()
+        .fold(0u8, |sum, b| sum.wrapping_sub(*b).wrapping_sub(1));
+}
+
+/// Runs the ROMs of the Mooneye test suite in the directory set by the
+/// environment variable `var` whose file names `select` picks, and asserts
+/// they all pass. The tests send the Fibonacci numbers over serial when
+/// they pass.
+pub(crate) fn assert_mooneye_passes(var: &str, select: impl Fn(&str) -> bool) {
+    const PASSED: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+    let dir = std::env::var(var).unwrap_or_else(|_| panic!("{} not set", var));
+    let mut ran = 0;
+    let mut failed: Vec<PathBuf> = Vec::new();
+
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+
+        let selected = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.ends_with(".gb") && select(name));
+
+        if !selected {
+            continue;
+        }
+
+        let rom = std::fs::read(&path).unwrap();
+        let sent = Recorder::default();
+
+        let mut ruboy: TestRuboy =
+            Ruboy::with_model(Cursor::new(rom), NullOutput, NullInput, GbModel::Dmg).unwrap();
+        ruboy.attach_link_device(sent.clone());
+
+        for _ in 0..600 {
+            // An emulation error fails just this ROM
+            if sent.recorded().len() >= PASSED.len() || ruboy.step_frame().is_err() {
+                break;
+            }
+        }
+
+        if *sent.recorded() != PASSED {
+            failed.push(path);
+        }
+
+        ran += 1;
+    }
+
+    assert!(ran > 0, "No test ROMs found in {}", var);
+    assert!(failed.is_empty(), "Failed: {:?}", failed);
+}
@@ -0,0 +1,233 @@
+use std::convert::Infallible;
+
+use ruboy_lib::rom::rtc::Rtc;
+use ruboy_lib::rom::InMemoryRom;
+use ruboy_lib::{
+    Frame, FrameEvent, GBGraphicsDrawer, GbInputs, InlineAllocator, InputHandler, Ruboy, RuboyErr,
+    RuboyStartErr, StateLoadErr, FRAME_X, FRAME_Y,
+};
+
+/// The rate the audio is produced at
+pub const SAMPLE_RATE: u32 = 48000;
+
+type CoreRuboy = Ruboy<InlineAllocator, InMemoryRom, NoOutput, PadInput>;
+
+/// Frames are taken from [Ruboy::step_frame] instead
+#[derive(Debug, Default)]
+pub struct NoOutput;
+
+impl GBGraphicsDrawer for NoOutput {
+    type Err = Infallible;
+
+    fn output(&mut self, _frame: &Frame) -> Result<(), Self::Err> {
+        Ok(())
+    }
+}
+
+/// The buttons held by the player are polled by the frontend at the start
+/// of every frame, and given straight to the emulator
+#[derive(Debug, Default)]
+pub struct PadInput;
+
+impl InputHandler for PadInput {
+    fn poll_inputs(&mut self) -> Option<GbInputs> {
+        None
+    }
+}
+
+/// A loaded game, and the output of the last frame
+pub struct Core {
+    ruboy: CoreRuboy,
+
+    /// The battery backed cartridge RAM, handed to the frontend to save.
+    /// The frontend writes a loaded save straight into it, so it is
+    /// compared against the emulated RAM before every frame.
+    save_ram: Vec<u8>,
+
+    /// The cartridge clock as [Rtc::to_save_bytes], handed to the frontend
+    /// like [Core::save_ram]. Empty for carts without a clock.
+    rtc: Vec<u8>,
+
+    /// The last [Core::rtc] given to the frontend, to notice when it loaded
+    /// another one. The saved clock changes as time passes, so it can't be
+    /// compared against the emulated clock directly.
+    rtc_given: Vec<u8>,
+
+    /// XRGB8888, [FRAME_X] by [FRAME_Y]. Kept while the LCD is off, so
+    /// the last frame is shown again.
+    framebuffer: Vec<u32>,
+
+    /// Interleaved stereo samples of the last frame
+    audio: Vec<i16>,
+}
+
+impl Core {
+    pub fn load(rom: Vec<u8>) -> Result<Self, RuboyStartErr<InMemoryRom>> {
+        let mut ruboy = Ruboy::new(InMemoryRom::from(rom), NoOutput, PadInput)?;
+        ruboy.enable_audio(SAMPLE_RATE);
+
+        let save_ram = if ruboy.rom_meta().cartridge_hardware().has_battery() {
+            ruboy.cartridge_ram().to_vec()
+        } else {
+            Vec::new()
+        };
+
+        let mut core = Self {
+            ruboy,
+            save_ram,
+            rtc: Vec::new(),
+            rtc_given: Vec::new(),
+            framebuffer: vec![0; FRAME_X * FRAME_Y],
+            audio: Vec::new(),
+        };
+
+        core.store_saves();
+
+        Ok(core)
+    }
+
+    /// Power cycles the Gameboy. The cartridge RAM and clock are kept.
+    pub fn reset(&mut self) {
+        self.load_saves();
+
+        if let Err(e) = self.ruboy.reset() {
+            log::error!("Could not reset: {}", e);
+        }
+    }
+
+    /// The battery backed cartridge RAM, see [Core::save_ram]. Empty if
+    /// the cartridge has none.
+    pub fn save_ram(&mut self) -> &mut [u8] {
+        &mut self.save_ram
+    }
+
+    /// The saved cartridge clock, see [Core::rtc]. Empty if the cartridge
+    /// has none.
+    pub fn rtc(&mut self) -> &mut [u8] {
+        &mut self.rtc
+    }
+
+    /// Applies saves the frontend wrote into [Core::save_ram] and
+    /// [Core::rtc]
+    fn load_saves(&mut self) {
+        if self.save_ram != self.ruboy.cartridge_ram() && !self.save_ram.is_empty() {
+            self.ruboy.load_cartridge_ram(&self.save_ram);
+        }
+
+        if self.rtc != self.rtc_given {
+            match Rtc::from_save_bytes(&self.rtc) {
+                Ok(rtc) => {
+                    self.ruboy.load_rtc(rtc);
+                }
+                Err(e) => log::error!("Ignoring saved clock: {}", e),
+            }
+        }
+    }
+
+    /// Copies the cartridge RAM and clock out for the frontend to save
+    fn store_saves(&mut self) {
+        if !self.save_ram.is_empty() {
+            self.save_ram.copy_from_slice(self.ruboy.cartridge_ram());
+        }
+
+        if let Some(rtc) = self.ruboy.save_rtc() {
+            self.rtc = rtc.to_save_bytes().to_vec();
+            self.rtc_given.clone_from(&self.rtc);
+        }
+    }
+
+    pub fn framebuffer(&self) -> &[u32] {
+        &self.framebuffer
+    }
+
+    pub fn audio(&self) -> &[i16] {
+        &self.audio
+    }
+
+    /// Runs a single frame with the given buttons held
+    pub fn run_frame(&mut self, inputs: GbInputs) -> Result<(), RuboyErr<NoOutput>> {
+        self.load_saves();
+        self.ruboy.set_inputs(inputs);
+
+        if let FrameEvent::Completed(frame) = self.ruboy.step_frame()? {
+            for (pixel, rgba) in self
+                .framebuffer
+                .iter_mut()
+                .zip(frame.to_rgba_bytes().chunks_exact(4))
+            {
+                *pixel = u32::from_be_bytes([0, rgba[0], rgba[1], rgba[2]]);
+            }
+        }
+
+        self.audio.clear();
+        self.audio.extend(
+            self.ruboy
+                .drain_audio()
+                .flatten()
+                .map(|sample| (sample.clamp(-1.0, 1.0) * f32::from(i16::MAX)) as i16),
+        );
+
+        self.store_saves();
+
+        Ok(())
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.ruboy.save_state()
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateLoadErr> {
+        self.ruboy.load_state(state)?;
+        self.store_saves();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty MBC3 cartridge with a clock, 8 KiB of RAM and a battery
+    fn mbc3_rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x10;
+        rom[0x149] = 0x02;
+        fix_checksum(&mut rom);
+
+        rom
+    }
+
+    fn fix_checksum(rom: &mut [u8]) {
+        rom[0x14D] = rom[0x134..=0x14C]
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_sub(*b).wrapping_sub(1));
+    }
+
+    #[test]
+    fn saves_survive_reset() {
+        let mut core = Core::load(mbc3_rom()).unwrap();
+        assert_eq!(0x2000, core.save_ram().len());
+        assert_eq!(Rtc::SAVE_LENGTH, core.rtc().len());
+
+        // Like the frontend loading a save file
+        core.save_ram().fill(0x5A);
+        core.run_frame(GbInputs::default()).unwrap();
+        assert!(core.ruboy.cartridge_ram().iter().all(|&b| b == 0x5A));
+
+        core.reset();
+        assert!(core.ruboy.cartridge_ram().iter().all(|&b| b == 0x5A));
+        assert!(core.save_ram().iter().all(|&b| b == 0x5A));
+    }
+
+    #[test]
+    fn no_save_ram_without_battery() {
+        let mut rom = mbc3_rom();
+        rom[0x147] = 0x12; // MBC3 with RAM, no battery
+        fix_checksum(&mut rom);
+
+        let mut core = Core::load(rom).unwrap();
+        assert!(core.save_ram().is_empty());
+        assert!(core.rtc().is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+//! Ruboy as a libretro core, so it can be loaded into RetroArch and other
+//! libretro frontends.
+//!
+//! The frontend calls the exported `retro_*` functions from a single
+//! thread. The core keeps the loaded game and the frontend callbacks in
+//! statics, as the API has no way to pass state around.
+
+use std::ffi::{c_char, c_uint, c_void};
+use std::ptr;
+use std::slice;
+use std::sync::{Mutex, MutexGuard};
+
+use ruboy_lib::{GbInputs, DESIRED_FRAMERATE, FRAME_X, FRAME_Y};
+
+use crate::emulator::{Core, SAMPLE_RATE};
+use crate::sys::*;
+
+mod emulator;
+mod sys;
+
+/// The callbacks handed to the core by the frontend
+struct Callbacks {
+    environment: Option<RetroEnvironmentFn>,
+    video_refresh: Option<RetroVideoRefreshFn>,
+    audio_sample_batch: Option<RetroAudioSampleBatchFn>,
+    input_poll: Option<RetroInputPollFn>,
+    input_state: Option<RetroInputStateFn>,
+}
+
+static CALLBACKS: Mutex<Callbacks> = Mutex::new(Callbacks {
+    environment: None,
+    video_refresh: None,
+    audio_sample_batch: None,
+    input_poll: None,
+    input_state: None,
+});
+
+/// The loaded game, if any
+static CORE: Mutex<Option<Core>> = Mutex::new(None);
+
+/// How the joypad of the frontend maps onto the Gameboy buttons
+const BUTTONS: [(c_uint, &str); 8] = [
+    (RETRO_DEVICE_ID_JOYPAD_A, "A\0"),
+    (RETRO_DEVICE_ID_JOYPAD_B, "B\0"),
+    (RETRO_DEVICE_ID_JOYPAD_SELECT, "Select\0"),
+    (RETRO_DEVICE_ID_JOYPAD_START, "Start\0"),
+    (RETRO_DEVICE_ID_JOYPAD_UP, "Up\0"),
+    (RETRO_DEVICE_ID_JOYPAD_DOWN, "Down\0"),
+    (RETRO_DEVICE_ID_JOYPAD_LEFT, "Left\0"),
+    (RETRO_DEVICE_ID_JOYPAD_RIGHT, "Right\0"),
+];
+
+fn callbacks() -> MutexGuard<'static, Callbacks> {
+    CALLBACKS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn core() -> MutexGuard<'static, Option<Core>> {
+    CORE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Sends an environment command to the frontend. Returns whether the
+/// frontend handled it.
+fn environment(cmd: c_uint, data: *mut c_void) -> bool {
+    match callbacks().environment {
+        // SAFETY: the frontend gave us this callback, and `data` points to
+        // what `cmd` expects
+        Some(env) => unsafe { env(cmd, data) },
+        None => false,
+    }
+}
+
+fn poll_inputs() -> GbInputs {
+    let callbacks = callbacks();
+
+    let (Some(poll), Some(state)) = (callbacks.input_poll, callbacks.input_state) else {
+        return GbInputs::default();
+    };
+
+    // SAFETY: these callbacks were given to us by the frontend
+    unsafe { poll() };
+
+    let pressed = |id| unsafe { state(0, RETRO_DEVICE_JOYPAD, 0, id) != 0 };
+
+    GbInputs {
+        a: pressed(RETRO_DEVICE_ID_JOYPAD_A),
+        b: pressed(RETRO_DEVICE_ID_JOYPAD_B),
+        select: pressed(RETRO_DEVICE_ID_JOYPAD_SELECT),
+        start: pressed(RETRO_DEVICE_ID_JOYPAD_START),
+        up: pressed(RETRO_DEVICE_ID_JOYPAD_UP),
+        down: pressed(RETRO_DEVICE_ID_JOYPAD_DOWN),
+        left: pressed(RETRO_DEVICE_ID_JOYPAD_LEFT),
+        right: pressed(RETRO_DEVICE_ID_JOYPAD_RIGHT),
+    }
+}
+
+fn describe_inputs() {
+    let mut descriptors: Vec<RetroInputDescriptor> = BUTTONS
+        .iter()
+        .map(|(id, name)| RetroInputDescriptor {
+            port: 0,
+            device: RETRO_DEVICE_JOYPAD,
+            index: 0,
+            id: *id,
+            description: name.as_ptr().cast(),
+        })
+        .collect();
+
+    // The list ends with a zeroed descriptor
+    descriptors.push(RetroInputDescriptor {
+        port: 0,
+        device: 0,
+        index: 0,
+        id: 0,
+        description: ptr::null(),
+    });
+
+    environment(
+        RETRO_ENVIRONMENT_SET_INPUT_DESCRIPTORS,
+        descriptors.as_mut_ptr().cast(),
+    );
+}
+
+#[no_mangle]
+pub extern "C" fn retro_api_version() -> c_uint {
+    RETRO_API_VERSION
+}
+
+#[no_mangle]
+pub extern "C" fn retro_init() {}
+
+#[no_mangle]
+pub extern "C" fn retro_deinit() {
+    *core() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_environment(cb: RetroEnvironmentFn) {
+    callbacks().environment = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_video_refresh(cb: RetroVideoRefreshFn) {
+    callbacks().video_refresh = Some(cb);
+}
+
+/// Unused, audio is sent in batches
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample(_cb: RetroAudioSampleFn) {}
+
+#[no_mangle]
+pub extern "C" fn retro_set_audio_sample_batch(cb: RetroAudioSampleBatchFn) {
+    callbacks().audio_sample_batch = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_poll(cb: RetroInputPollFn) {
+    callbacks().input_poll = Some(cb);
+}
+
+#[no_mangle]
+pub extern "C" fn retro_set_input_state(cb: RetroInputStateFn) {
+    callbacks().input_state = Some(cb);
+}
+
+/// # Safety
+///
+/// `info` must point to a valid [RetroSystemInfo]
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_info(info: *mut RetroSystemInfo) {
+    *info = RetroSystemInfo {
+        library_name: c"Ruboy".as_ptr(),
+        library_version: concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr().cast(),
+        valid_extensions: c"gb|dmg".as_ptr(),
+        need_fullpath: false,
+        block_extract: false,
+    };
+}
+
+/// # Safety
+///
+/// `info` must point to a valid [RetroSystemAvInfo]
+#[no_mangle]
+pub unsafe extern "C" fn retro_get_system_av_info(info: *mut RetroSystemAvInfo) {
+    *info = RetroSystemAvInfo {
+        geometry: RetroGameGeometry {
+            base_width: FRAME_X as c_uint,
+            base_height: FRAME_Y as c_uint,
+            max_width: FRAME_X as c_uint,
+            max_height: FRAME_Y as c_uint,
+            aspect_ratio: FRAME_X as f32 / FRAME_Y as f32,
+        },
+        timing: RetroSystemTiming {
+            fps: DESIRED_FRAMERATE,
+            sample_rate: f64::from(SAMPLE_RATE),
+        },
+    };
+}
+
+/// Only the joypad is supported
+#[no_mangle]
+pub extern "C" fn retro_set_controller_port_device(_port: c_uint, _device: c_uint) {}
+
+#[no_mangle]
+pub extern "C" fn retro_reset() {
+    if let Some(core) = core().as_mut() {
+        core.reset();
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_run() {
+    let inputs = poll_inputs();
+
+    let mut core = core();
+
+    let Some(core) = core.as_mut() else {
+        return;
+    };
+
+    if let Err(e) = core.run_frame(inputs) {
+        log::error!("Emulation failed: {}", e);
+    }
+
+    let callbacks = callbacks();
+
+    if let Some(video_refresh) = callbacks.video_refresh {
+        // SAFETY: the framebuffer is exactly FRAME_X by FRAME_Y pixels
+        unsafe {
+            video_refresh(
+                core.framebuffer().as_ptr().cast(),
+                FRAME_X as c_uint,
+                FRAME_Y as c_uint,
+                FRAME_X * size_of::<u32>(),
+            )
+        };
+    }
+
+    if let Some(audio_sample_batch) = callbacks.audio_sample_batch {
+        let audio = core.audio();
+
+        // SAFETY: the samples are interleaved stereo, so there are half as
+        // many audio frames as samples
+        unsafe { audio_sample_batch(audio.as_ptr(), audio.len() / 2) };
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_serialize_size() -> usize {
+    core().as_ref().map_or(0, |core| core.save_state().len())
+}
+
+/// # Safety
+///
+/// `data` must point to `size` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_serialize(data: *mut c_void, size: usize) -> bool {
+    let Some(state) = core().as_ref().map(Core::save_state) else {
+        return false;
+    };
+
+    if state.len() > size {
+        return false;
+    }
+
+    slice::from_raw_parts_mut(data.cast::<u8>(), state.len()).copy_from_slice(&state);
+
+    true
+}
+
+/// # Safety
+///
+/// `data` must point to `size` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_unserialize(data: *const c_void, size: usize) -> bool {
+    let mut core = core();
+
+    let Some(core) = core.as_mut() else {
+        return false;
+    };
+
+    let state = slice::from_raw_parts(data.cast::<u8>(), size);
+
+    match core.load_state(state) {
+        Ok(()) => true,
+        Err(e) => {
+            log::error!("Could not load state: {}", e);
+            false
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_reset() {}
+
+#[no_mangle]
+pub extern "C" fn retro_cheat_set(_index: c_uint, _enabled: bool, _code: *const c_char) {}
+
+/// # Safety
+///
+/// `game` must be null or point to a valid [RetroGameInfo], with `data`
+/// pointing to `size` readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn retro_load_game(game: *const RetroGameInfo) -> bool {
+    let Some(game) = game.as_ref() else {
+        return false;
+    };
+
+    if game.data.is_null() {
+        return false;
+    }
+
+    let mut format = RETRO_PIXEL_FORMAT_XRGB8888;
+
+    if !environment(
+        RETRO_ENVIRONMENT_SET_PIXEL_FORMAT,
+        ptr::addr_of_mut!(format).cast(),
+    ) {
+        log::error!("The frontend does not support XRGB8888");
+        return false;
+    }
+
+    describe_inputs();
+
+    let rom = slice::from_raw_parts(game.data.cast::<u8>(), game.size).to_vec();
+
+    match Core::load(rom) {
+        Ok(loaded) => {
+            *core() = Some(loaded);
+            true
+        }
+        Err(e) => {
+            log::error!("Could not load game: {}", e);
+            false
+        }
+    }
+}
+
+/// No special game types are supported
+#[no_mangle]
+pub extern "C" fn retro_load_game_special(
+    _game_type: c_uint,
+    _info: *const RetroGameInfo,
+    _num_info: usize,
+) -> bool {
+    false
+}
+
+#[no_mangle]
+pub extern "C" fn retro_unload_game() {
+    *core() = None;
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_region() -> c_uint {
+    RETRO_REGION_NTSC
+}
+
+/// The battery backed cartridge RAM and the cartridge clock, which the
+/// frontend keeps in its save files. The buffers stay in place until the
+/// game is unloaded.
+#[no_mangle]
+pub extern "C" fn retro_get_memory_data(id: c_uint) -> *mut c_void {
+    match memory(id, |mem| mem.as_mut_ptr()) {
+        Some(data) => data.cast(),
+        None => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn retro_get_memory_size(id: c_uint) -> usize {
+    memory(id, |mem| mem.len()).unwrap_or(0)
+}
+
+/// Calls `f` with the memory the frontend asked for, if the game has it
+fn memory<T>(id: c_uint, f: impl FnOnce(&mut [u8]) -> T) -> Option<T> {
+    let mut core = core();
+    let core = core.as_mut()?;
+
+    let mem = match id {
+        RETRO_MEMORY_SAVE_RAM => core.save_ram(),
+        RETRO_MEMORY_RTC => core.rtc(),
+        _ => return None,
+    };
+
+    (!mem.is_empty()).then(|| f(mem))
+}
@@ -0,0 +1,205 @@
+//! JavaScript bindings, to run Ruboy in the browser.
+//!
+//! Build with `wasm-pack build --target web ruboy_wasm`, and see
+//! `www/index.html` for a page that uses the bindings.
+
+mod storage;
+
+use std::cell::RefCell;
+use std::convert::Infallible;
+use std::rc::Rc;
+
+use ruboy_lib::emucore::{self, CoreAllocator, CoreRom, EmulatorCore};
+use ruboy_lib::model::BootRom;
+use ruboy_lib::{Frame, GBGraphicsDrawer, GbInputs, InputHandler, FRAME_X, FRAME_Y};
+use storage::LocalStorage;
+use wasm_bindgen::prelude::*;
+
+/// The longest time a single [WasmRuboy::step] emulates. Browsers stop
+/// calling `requestAnimationFrame` for background tabs, so the first step
+/// after coming back would otherwise try to catch up on all of it.
+const MAX_STEP_SECS: f64 = 0.25;
+
+/// Keeps the last drawn frame as RGBA bytes, ready for an `ImageData`
+#[derive(Debug, Clone)]
+struct CanvasOutput(Rc<RefCell<Vec<u8>>>);
+
+impl GBGraphicsDrawer for CanvasOutput {
+    type Err = Infallible;
+
+    fn output(&mut self, frame: &Frame) -> Result<(), Self::Err> {
+        *self.0.borrow_mut() = frame.to_rgba_bytes();
+        Ok(())
+    }
+}
+
+/// Key events are given to the emulator as they come in, so there is
+/// nothing to poll
+#[derive(Debug, Default)]
+struct KeyInput;
+
+impl InputHandler for KeyInput {
+    fn poll_inputs(&mut self) -> Option<GbInputs> {
+        None
+    }
+}
+
+/// The button for a `KeyboardEvent.code`
+fn button_for<'a>(code: &str, inputs: &'a mut GbInputs) -> Option<&'a mut bool> {
+    Some(match code {
+        "KeyX" => &mut inputs.a,
+        "KeyZ" => &mut inputs.b,
+        "Enter" => &mut inputs.start,
+        "Backspace" | "ShiftRight" => &mut inputs.select,
+        "ArrowUp" => &mut inputs.up,
+        "ArrowDown" => &mut inputs.down,
+        "ArrowLeft" => &mut inputs.left,
+        "ArrowRight" => &mut inputs.right,
+        _ => return None,
+    })
+}
+
+#[wasm_bindgen]
+pub struct WasmRuboy {
+    ruboy: Box<dyn EmulatorCore<CanvasOutput>>,
+    framebuffer: Rc<RefCell<Vec<u8>>>,
+}
+
+#[wasm_bindgen]
+impl WasmRuboy {
+    /// Starts the game in the given ROM image
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8]) -> Result<WasmRuboy, JsError> {
+        let framebuffer = Rc::new(RefCell::new(vec![0xFF; FRAME_X * FRAME_Y * 4]));
+
+        let ruboy = emucore::boxed(
+            CoreRom::Buffer(rom.to_vec()),
+            CoreAllocator::default(),
+            CanvasOutput(framebuffer.clone()),
+            KeyInput,
+            BootRom::default(),
+        )
+        .map_err(|e| JsError::new(&e.to_string()))?;
+
+        Ok(Self { ruboy, framebuffer })
+    }
+
+    pub fn width() -> usize {
+        FRAME_X
+    }
+
+    pub fn height() -> usize {
+        FRAME_Y
+    }
+
+    /// The title in the cartridge header
+    pub fn title(&self) -> String {
+        self.ruboy.rom_meta().title().to_string()
+    }
+
+    /// Emulates `dt` seconds, usually the time since the last
+    /// `requestAnimationFrame` callback
+    pub fn step(&mut self, dt: f64) -> Result<(), JsError> {
+        self.ruboy
+            .step(dt.clamp(0.0, MAX_STEP_SECS))
+            .map_err(|e| JsError::new(&e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Runs until the next frame is drawn
+    pub fn step_frame(&mut self) -> Result<(), JsError> {
+        self.ruboy
+            .step_frame()
+            .map_err(|e| JsError::new(&e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// The last drawn frame as RGBA bytes, [WasmRuboy::width] by
+    /// [WasmRuboy::height] pixels. Wrap it in an `ImageData` to draw it on
+    /// a canvas.
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.framebuffer.borrow().clone()
+    }
+
+    /// Handles a `keydown` event. Returns whether the key is mapped to a
+    /// button, so the page can call `preventDefault`.
+    pub fn key_down(&mut self, code: &str) -> bool {
+        self.set_key(code, true)
+    }
+
+    /// Handles a `keyup` event, see [WasmRuboy::key_down]
+    pub fn key_up(&mut self, code: &str) -> bool {
+        self.set_key(code, false)
+    }
+
+    fn set_key(&mut self, code: &str, pressed: bool) -> bool {
+        let mut inputs = self.ruboy.inputs();
+
+        match button_for(code, &mut inputs) {
+            Some(button) => {
+                *button = pressed;
+                self.ruboy.set_inputs(inputs);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Starts producing audio, to be taken with [WasmRuboy::drain_audio]
+    pub fn enable_audio(&mut self, sample_rate: u32) {
+        self.ruboy.enable_audio(sample_rate);
+    }
+
+    /// The buffered samples, interleaved left and right
+    pub fn drain_audio(&mut self) -> Vec<f32> {
+        self.ruboy.drain_audio().flatten().collect()
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        self.ruboy.save_state()
+    }
+
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), JsError> {
+        self.ruboy
+            .load_state(state)
+            .map_err(|e| JsError::new(&e.to_string()))
+    }
+
+    /// Stores the battery backed cartridge RAM and the clock in
+    /// `localStorage` under `name`, see `Ruboy::save_persistent`
+    pub fn save_persistent(&mut self, name: &str) -> Result<(), JsError> {
+        let mut storage = LocalStorage::open()?;
+
+        self.ruboy.save_persistent(&mut storage, name)?;
+
+        Ok(())
+    }
+
+    /// Loads the data stored by [WasmRuboy::save_persistent]
+    pub fn load_persistent(&mut self, name: &str) -> Result<(), JsError> {
+        let storage = LocalStorage::open()?;
+
+        self.ruboy.load_persistent(&storage, name)?;
+
+        Ok(())
+    }
+
+    /// Stores a save state in `localStorage` under `name`
+    pub fn save_state_to(&self, name: &str) -> Result<(), JsError> {
+        let mut storage = LocalStorage::open()?;
+
+        self.ruboy.save_state_to(&mut storage, name)?;
+
+        Ok(())
+    }
+
+    /// Restores the state stored by [WasmRuboy::save_state_to]. Returns
+    /// false if no state is stored under `name`.
+    pub fn load_state_from(&mut self, name: &str) -> Result<bool, JsError> {
+        let storage = LocalStorage::open()?;
+
+        Ok(self.ruboy.load_state_from(&storage, name)?)
+    }
+}
@@ -0,0 +1,70 @@
+//! Save data in the browser, kept in `localStorage`
+
+use ruboy_lib::storage::StorageProvider;
+use thiserror::Error;
+use wasm_bindgen::JsValue;
+use web_sys::{Storage, Window};
+
+/// Prefixed to every key, so other data of the page is left alone
+const KEY_PREFIX: &str = "ruboy/";
+
+/// Keeps every key in the `localStorage` of the page. It only holds
+/// strings, so data is stored base64 encoded.
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    window: Window,
+    storage: Storage,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Browser storage error: {0}")]
+pub struct LocalStorageErr(String);
+
+impl From<JsValue> for LocalStorageErr {
+    fn from(value: JsValue) -> Self {
+        Self(value.as_string().unwrap_or_else(|| format!("{:?}", value)))
+    }
+}
+
+impl LocalStorage {
+    /// The `localStorage` of the current page, if the browser allows
+    /// using it
+    pub fn open() -> Result<Self, LocalStorageErr> {
+        let window = web_sys::window().ok_or_else(|| LocalStorageErr("No window".to_string()))?;
+        let storage = window
+            .local_storage()?
+            .ok_or_else(|| LocalStorageErr("localStorage is not available".to_string()))?;
+
+        Ok(Self { window, storage })
+    }
+}
+
+impl StorageProvider for LocalStorage {
+    type Err = LocalStorageErr;
+
+    fn load(&self, key: &str) -> Result<Option<Vec<u8>>, Self::Err> {
+        let Some(encoded) = self.storage.get_item(&format!("{}{}", KEY_PREFIX, key))? else {
+            return Ok(None);
+        };
+
+        // atob gives a "binary string", with one char per byte
+        let data = self.window.atob(&encoded)?;
+
+        Ok(Some(data.chars().map(|c| c as u8).collect()))
+    }
+
+    fn store(&mut self, key: &str, data: &[u8]) -> Result<(), Self::Err> {
+        let binary: String = data.iter().map(|&b| char::from(b)).collect();
+        let encoded = self.window.btoa(&binary)?;
+
+        Ok(self
+            .storage
+            .set_item(&format!("{}{}", KEY_PREFIX, key), &encoded)?)
+    }
+
+    fn remove(&mut self, key: &str) -> Result<(), Self::Err> {
+        Ok(self
+            .storage
+            .remove_item(&format!("{}{}", KEY_PREFIX, key))?)
+    }
+}